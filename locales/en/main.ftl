@@ -0,0 +1,79 @@
+language-name = English
+
+subscribe-form-title = Subscribe to the newsletter
+subscribe-form-name = Name
+subscribe-form-email = Email address
+subscribe-form-email-confirmation = Confirm your email address
+subscribe-form-locale = Language of the emails
+subscribe-form-submit = Subscribe
+subscribe-success = Thanks for subscribing! Check your inbox to confirm your email address.
+subscribe-invalid = Please check your name and email address.
+subscribe-invalid-name = Please enter your name, without special characters such as < > or /.
+subscribe-invalid-email = Please enter a valid email address.
+subscribe-invalid-email-confirmation = The two email addresses do not match.
+subscribe-invalid-locale = Please pick one of the languages we offer.
+subscribe-invalid-policy = Please accept the terms to subscribe.
+subscribe-pending-title = Check your inbox
+subscribe-pending-sent-to = We have sent a confirmation link to { $email }.
+subscribe-pending-sent = We have sent you a confirmation link.
+subscribe-pending-delayed = Our email provider is having trouble right now, so the link may take a while to arrive.
+subscribe-pending-next = Click it to confirm your subscription - the next issue will then land in your inbox.
+subscribe-pending-missing = Nothing there? Check your spam folder, or subscribe again.
+subscribe-delayed = Thanks for subscribing! The confirmation email may take a while to arrive.
+subscribe-waitlisted-title = The list is full
+subscribe-waitlisted = The newsletter has all the subscribers it can take for now. You have joined the waitlist: we will send you a confirmation link as soon as a spot opens up.
+
+confirmed-title = Subscription confirmed
+confirmed-body = Thanks for confirming your email address. The next issue will land in your inbox.
+confirmed-archive-link = Read the past issues
+link-used-title = Link already used
+link-used-body = This confirmation link was already used. If you confirmed your subscription, there is nothing left to do.
+
+kept-title = You are still subscribed
+kept-body = Thanks for letting us know. You will keep receiving the newsletter.
+
+unsubscribe-title = Unsubscribe
+unsubscribe-body = You will no longer receive the newsletter.
+unsubscribe-survey = Would you tell us why? (optional)
+unsubscribe-reason-too-frequent = The emails are too frequent
+unsubscribe-reason-not-relevant = The content is not relevant to me
+unsubscribe-reason-other = Something else
+unsubscribe-submit = Unsubscribe
+unsubscribe-preferences = Would you rather receive the newsletter at another time of day? Update your preferences
+
+preferences-title = Your preferences
+preferences-timezone = Your timezone
+preferences-timezone-unset = Not set
+preferences-timezone-help = Issues sent at a set local time reach you at that time of your day.
+preferences-submit = Save
+preferences-saved = Your preferences have been saved.
+
+unsubscribed-title = You have been unsubscribed
+unsubscribed-body = Thanks for reading. You will not receive any other issue.
+unsubscribed-resubscribe = Changed your mind? Subscribe again
+
+confirmation-email-subject = Welcome!
+confirmation-email-text =
+    Welcome to our newsletter!
+    Visit { $confirmation_link } to confirm your subscription
+confirmation-email-title = Confirm your email address
+confirmation-email-preheader = Confirm your email address to activate your account
+confirmation-email-heading = Welcome. You're almost there.
+confirmation-email-heading-named = Welcome, { $name }. You're almost there.
+confirmation-email-intro = Click the link below to confirm your email address and finish your account setup
+confirmation-email-button = Confirm Email Address
+confirmation-email-fallback = Button not working? Copy and paste the link below into your web browser
+confirmation-email-ignore = If you did not make this request, you can ignore this email
+
+error-403-title = You cannot see this page
+error-403-body = You are not allowed to access this page.
+error-404-title = Page not found
+error-404-body = The page you are looking for does not exist, or has moved.
+error-500-title = Something went wrong
+error-500-body = We could not serve this page. Please try again in a moment.
+error-other-title = This request could not be served
+error-other-body = Please check the address or the form you submitted.
+error-home-link = Back to the home page
+
+issue-view-in-browser = View this email in your browser
+issue-unsubscribe = Unsubscribe
@@ -0,0 +1,79 @@
+language-name = Français
+
+subscribe-form-title = S'abonner à la newsletter
+subscribe-form-name = Nom
+subscribe-form-email = Adresse e-mail
+subscribe-form-email-confirmation = Confirmez votre adresse e-mail
+subscribe-form-locale = Langue des e-mails
+subscribe-form-submit = S'abonner
+subscribe-success = Merci pour votre inscription ! Consultez votre boîte de réception pour confirmer votre adresse e-mail.
+subscribe-invalid = Veuillez vérifier votre nom et votre adresse e-mail.
+subscribe-invalid-name = Veuillez saisir votre nom, sans caractères spéciaux tels que < > ou /.
+subscribe-invalid-email = Veuillez saisir une adresse e-mail valide.
+subscribe-invalid-email-confirmation = Les deux adresses e-mail ne correspondent pas.
+subscribe-invalid-locale = Veuillez choisir l'une des langues proposées.
+subscribe-invalid-policy = Veuillez accepter les conditions pour vous abonner.
+subscribe-pending-title = Consultez votre boîte de réception
+subscribe-pending-sent-to = Nous avons envoyé un lien de confirmation à { $email }.
+subscribe-pending-sent = Nous vous avons envoyé un lien de confirmation.
+subscribe-pending-delayed = Notre fournisseur d'e-mails rencontre des difficultés : le lien peut mettre un moment à arriver.
+subscribe-pending-next = Cliquez dessus pour confirmer votre inscription - le prochain numéro arrivera alors dans votre boîte de réception.
+subscribe-pending-missing = Rien reçu ? Vérifiez vos courriers indésirables, ou inscrivez-vous à nouveau.
+subscribe-delayed = Merci pour votre inscription ! L'e-mail de confirmation peut mettre un moment à arriver.
+subscribe-waitlisted-title = La liste est complète
+subscribe-waitlisted = La newsletter a atteint le nombre maximum d'abonnés pour le moment. Vous êtes sur la liste d'attente : nous vous enverrons un lien de confirmation dès qu'une place se libère.
+
+confirmed-title = Inscription confirmée
+confirmed-body = Merci d'avoir confirmé votre adresse e-mail. Le prochain numéro arrivera dans votre boîte de réception.
+confirmed-archive-link = Lire les numéros précédents
+link-used-title = Lien déjà utilisé
+link-used-body = Ce lien de confirmation a déjà été utilisé. Si vous avez confirmé votre inscription, il n'y a plus rien à faire.
+
+kept-title = Vous êtes toujours inscrit
+kept-body = Merci de nous l'avoir dit. Vous continuerez à recevoir la newsletter.
+
+unsubscribe-title = Se désinscrire
+unsubscribe-body = Vous ne recevrez plus la newsletter.
+unsubscribe-survey = Pouvez-vous nous dire pourquoi ? (facultatif)
+unsubscribe-reason-too-frequent = Les e-mails sont trop fréquents
+unsubscribe-reason-not-relevant = Le contenu ne me concerne pas
+unsubscribe-reason-other = Autre chose
+unsubscribe-submit = Se désinscrire
+unsubscribe-preferences = Vous préférez recevoir la newsletter à un autre moment de la journée ? Modifiez vos préférences
+
+preferences-title = Vos préférences
+preferences-timezone = Votre fuseau horaire
+preferences-timezone-unset = Non renseigné
+preferences-timezone-help = Les numéros envoyés à une heure locale fixe vous parviennent à cette heure-là de votre journée.
+preferences-submit = Enregistrer
+preferences-saved = Vos préférences ont été enregistrées.
+
+unsubscribed-title = Vous êtes désinscrit
+unsubscribed-body = Merci de nous avoir lus. Vous ne recevrez plus aucun numéro.
+unsubscribed-resubscribe = Vous avez changé d'avis ? Inscrivez-vous à nouveau
+
+confirmation-email-subject = Bienvenue !
+confirmation-email-text =
+    Bienvenue dans notre newsletter !
+    Rendez-vous sur { $confirmation_link } pour confirmer votre inscription
+confirmation-email-title = Confirmez votre adresse e-mail
+confirmation-email-preheader = Confirmez votre adresse e-mail pour activer votre compte
+confirmation-email-heading = Bienvenue. Vous y êtes presque.
+confirmation-email-heading-named = Bienvenue, { $name }. Vous y êtes presque.
+confirmation-email-intro = Cliquez sur le lien ci-dessous pour confirmer votre adresse e-mail et terminer votre inscription
+confirmation-email-button = Confirmer mon adresse e-mail
+confirmation-email-fallback = Le bouton ne fonctionne pas ? Copiez et collez le lien ci-dessous dans votre navigateur
+confirmation-email-ignore = Si vous n'êtes pas à l'origine de cette demande, vous pouvez ignorer cet e-mail
+
+error-403-title = Vous ne pouvez pas voir cette page
+error-403-body = Vous n'avez pas accès à cette page.
+error-404-title = Page introuvable
+error-404-body = La page que vous cherchez n'existe pas, ou a été déplacée.
+error-500-title = Une erreur est survenue
+error-500-body = Nous n'avons pas pu afficher cette page. Veuillez réessayer dans un instant.
+error-other-title = Cette requête n'a pas pu aboutir
+error-other-body = Veuillez vérifier l'adresse ou le formulaire envoyé.
+error-home-link = Retour à l'accueil
+
+issue-view-in-browser = Voir cet e-mail dans votre navigateur
+issue-unsubscribe = Se désabonner
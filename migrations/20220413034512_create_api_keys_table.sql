@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE api_keys (
+    api_key_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users(user_id),
+    description TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL,
+    revoked_at timestamptz NULL
+);
@@ -0,0 +1,16 @@
+-- Add migration script here
+CREATE TABLE jobs (
+    job_id uuid NOT NULL,
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    status TEXT NOT NULL,
+    processed INTEGER NOT NULL DEFAULT 0,
+    total INTEGER NOT NULL,
+    result JSONB NULL,
+    error TEXT NULL,
+    created_at timestamptz NOT NULL,
+    started_at timestamptz NULL,
+    finished_at timestamptz NULL,
+    PRIMARY KEY(job_id)
+);
+CREATE INDEX jobs_queued_idx ON jobs (created_at) WHERE status = 'queued';
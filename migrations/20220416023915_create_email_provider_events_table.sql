@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE email_provider_events (
+    event_id TEXT NOT NULL,
+    record_type TEXT NOT NULL,
+    recipient TEXT NULL,
+    payload JSONB NOT NULL,
+    received_at timestamptz NOT NULL,
+    PRIMARY KEY(event_id)
+);
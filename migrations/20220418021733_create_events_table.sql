@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE events (
+    event_id uuid NOT NULL,
+    event_type TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    occurred_at timestamptz NOT NULL,
+    published_at timestamptz NULL,
+    PRIMARY KEY (event_id)
+);
+CREATE INDEX events_unpublished_idx ON events (occurred_at) WHERE published_at IS NULL;
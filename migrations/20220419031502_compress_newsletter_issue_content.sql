@@ -0,0 +1,16 @@
+-- Add migration script here
+-- New issues store their content zstd-compressed; issues published before keep the
+-- plain text columns.
+ALTER TABLE newsletter_issues
+    ADD COLUMN text_content_zstd BYTEA,
+    ADD COLUMN html_content_zstd BYTEA,
+    ALTER COLUMN text_content DROP NOT NULL,
+    ALTER COLUMN html_content DROP NOT NULL,
+    ADD CONSTRAINT newsletter_issues_text_content_stored
+        CHECK ((text_content IS NULL) <> (text_content_zstd IS NULL)),
+    ADD CONSTRAINT newsletter_issues_html_content_stored
+        CHECK ((html_content IS NULL) <> (html_content_zstd IS NULL));
+-- Already compressed: do not let TOAST try again.
+ALTER TABLE newsletter_issues
+    ALTER COLUMN text_content_zstd SET STORAGE EXTERNAL,
+    ALTER COLUMN html_content_zstd SET STORAGE EXTERNAL;
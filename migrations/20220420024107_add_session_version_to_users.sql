@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Sessions remember the version they were opened with; bumping it logs out every
+-- other session of the user.
+ALTER TABLE users ADD COLUMN session_version INTEGER NOT NULL DEFAULT 0;
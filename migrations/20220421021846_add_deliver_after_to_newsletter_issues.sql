@@ -0,0 +1,2 @@
+-- Deliveries of an issue wait until deliver_after, leaving a window to cancel it.
+ALTER TABLE newsletter_issues ADD COLUMN deliver_after timestamptz NOT NULL DEFAULT now();
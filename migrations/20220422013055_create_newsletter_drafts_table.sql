@@ -0,0 +1,11 @@
+-- The content of the compose form, autosaved while it is being written.
+-- draft_id is the idempotency key of the form.
+CREATE TABLE newsletter_drafts (
+    draft_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users(user_id),
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    saved_at timestamptz NOT NULL
+);
+CREATE INDEX newsletter_drafts_user_id_idx ON newsletter_drafts (user_id, saved_at);
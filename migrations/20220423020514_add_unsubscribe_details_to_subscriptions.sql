@@ -0,0 +1,4 @@
+-- Set when a subscriber unsubscribes, cleared if they confirm their subscription again.
+ALTER TABLE subscriptions ADD COLUMN unsubscribed_at timestamptz NULL;
+-- The answer to the optional survey of the unsubscribe page.
+ALTER TABLE subscriptions ADD COLUMN unsubscribe_reason TEXT NULL;
@@ -0,0 +1,2 @@
+-- Only confirmed subscribers with this tag receive the issue. Every confirmed subscriber does when NULL.
+ALTER TABLE newsletter_issues ADD COLUMN segment TEXT NULL;
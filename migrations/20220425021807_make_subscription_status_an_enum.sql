@@ -0,0 +1,4 @@
+-- A misspelt status is now rejected by Postgres instead of being stored.
+CREATE TYPE subscription_status AS ENUM ('pending_confirmation', 'confirmed', 'unsubscribed');
+ALTER TABLE subscriptions
+    ALTER COLUMN status TYPE subscription_status USING status::subscription_status;
@@ -0,0 +1,3 @@
+-- Deleted subscribers are kept for a while, so an accidental deletion can be undone,
+-- then purged by the scheduler.
+ALTER TABLE subscriptions ADD COLUMN deleted_at timestamptz NULL;
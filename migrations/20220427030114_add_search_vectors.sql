@@ -0,0 +1,12 @@
+-- Email addresses are split on `@` and `.`, so that any part of them can be searched for.
+ALTER TABLE subscriptions ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (
+        to_tsvector('simple', name || ' ' || translate(email, '@.', '  '))
+    ) STORED;
+CREATE INDEX subscriptions_search_idx ON subscriptions USING GIN (search_vector);
+
+-- The content of issues is stored compressed, so their vector is computed when they are
+-- published. Issues published before only have their title indexed.
+ALTER TABLE newsletter_issues ADD COLUMN search_vector tsvector NULL;
+UPDATE newsletter_issues SET search_vector = setweight(to_tsvector('english', title), 'A');
+CREATE INDEX newsletter_issues_search_idx ON newsletter_issues USING GIN (search_vector);
@@ -0,0 +1,31 @@
+-- Several independent newsletters can share a deployment, each served on its own hostname.
+-- The default tenant is the deployment's own newsletter: everything stored so far is its.
+CREATE TABLE tenants (
+    tenant_id uuid NOT NULL,
+    name TEXT NOT NULL,
+    hostname TEXT NULL UNIQUE,
+    sender_email TEXT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY(tenant_id)
+);
+INSERT INTO tenants (tenant_id, name)
+VALUES ('00000000-0000-0000-0000-000000000000', 'Default');
+
+ALTER TABLE users ADD COLUMN tenant_id uuid NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES tenants (tenant_id);
+
+ALTER TABLE subscriptions ADD COLUMN tenant_id uuid NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES tenants (tenant_id);
+-- The same person can subscribe to several tenants' newsletters.
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_tenant_id_email_key UNIQUE (tenant_id, email);
+
+ALTER TABLE newsletter_issues ADD COLUMN tenant_id uuid NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES tenants (tenant_id);
+CREATE INDEX newsletter_issues_tenant_id_idx ON newsletter_issues (tenant_id);
+
+ALTER TABLE issue_delivery_queue ADD COLUMN tenant_id uuid NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES tenants (tenant_id);
+
+ALTER TABLE jobs ADD COLUMN tenant_id uuid NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES tenants (tenant_id);
@@ -0,0 +1,5 @@
+-- Tenants can send subscribers links to their own domain rather than the admin panel's.
+ALTER TABLE tenants ADD COLUMN public_base_url TEXT NULL;
+-- Requests to the public domain are served for the tenant too.
+ALTER TABLE tenants ADD COLUMN public_hostname TEXT NULL UNIQUE
+    GENERATED ALWAYS AS (lower(substring(public_base_url from '^[a-z]+://([^/:]+)'))) STORED;
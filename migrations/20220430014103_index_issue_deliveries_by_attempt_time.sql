@@ -0,0 +1,2 @@
+-- Sending quotas count the deliveries attempted in the current hour and day.
+CREATE INDEX issue_deliveries_attempted_at_idx ON issue_deliveries (attempted_at);
@@ -0,0 +1,5 @@
+-- Issues can be sent at a set hour of each recipient's day, in their own timezone.
+ALTER TABLE subscriptions ADD COLUMN timezone TEXT NULL;
+ALTER TABLE newsletter_issues ADD COLUMN local_send_hour SMALLINT NULL;
+-- The delivery to a recipient waits for their local send time, if any.
+ALTER TABLE issue_delivery_queue ADD COLUMN deliver_after timestamptz NULL;
@@ -0,0 +1,5 @@
+-- Subscribers who stopped opening the issues are asked whether they still want them.
+-- Set when they were last asked, cleared once they answer.
+ALTER TABLE subscriptions ADD COLUMN re_engagement_sent_at timestamptz NULL;
+-- When they last answered: only the issues delivered since count towards their inactivity.
+ALTER TABLE subscriptions ADD COLUMN re_engaged_at timestamptz NULL;
@@ -0,0 +1,12 @@
+-- The database backups taken from the admin panel. Like the maintenance tasks, they
+-- cover the whole database: the tenant is the one whose admin started them.
+CREATE TABLE backups (
+    backup_id uuid PRIMARY KEY,
+    tenant_id uuid NOT NULL REFERENCES tenants (tenant_id),
+    file_name TEXT NOT NULL,
+    -- A path on the worker, or an `s3://` URL.
+    location TEXT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+CREATE INDEX backups_created_at_idx ON backups (created_at);
@@ -0,0 +1,19 @@
+-- Whether the code of an older release can still run against the schema, for rolling
+-- deploys where both releases serve traffic for a while.
+--
+-- Additive migrations need nothing. A migration which breaks the previous release -
+-- dropping or renaming something its code uses - ends with
+--     SELECT breaks_older_code(<its own version>);
+-- and the instances which do not know that migration refuse to start.
+CREATE TABLE schema_compatibility (
+    -- A single row.
+    singleton BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (singleton),
+    -- Only the code which knows this migration runs against the schema.
+    oldest_compatible_migration BIGINT NOT NULL
+);
+INSERT INTO schema_compatibility (oldest_compatible_migration) VALUES (0);
+
+CREATE FUNCTION breaks_older_code(migration BIGINT) RETURNS void AS $$
+    UPDATE schema_compatibility
+    SET oldest_compatible_migration = GREATEST(oldest_compatible_migration, migration);
+$$ LANGUAGE SQL;
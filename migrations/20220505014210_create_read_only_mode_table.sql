@@ -0,0 +1,7 @@
+-- Read-only mode, switched on from the admin panel: the row exists while it is on.
+-- It applies to the whole deployment, whichever tenant's admin switched it on.
+CREATE TABLE read_only_mode (
+    singleton BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (singleton),
+    enabled_at timestamptz NOT NULL,
+    enabled_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL
+);
@@ -0,0 +1,10 @@
+-- Paths which moved, e.g. archive URLs from a previous platform. They are only looked
+-- up for the paths which match no route.
+CREATE TABLE redirects (
+    tenant_id uuid NOT NULL REFERENCES tenants (tenant_id),
+    from_path TEXT NOT NULL,
+    -- A path of this site or an absolute URL.
+    to_location TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (tenant_id, from_path)
+);
@@ -0,0 +1,3 @@
+-- The language the subscriber signed up in, for the emails sent to them.
+-- NULL falls back to the locale of the request, or the default one.
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NULL;
@@ -0,0 +1,13 @@
+-- Subscribers may sign up without a name, see `subscribers.require_name`.
+ALTER TABLE subscriptions ALTER COLUMN name DROP NOT NULL;
+
+-- The search vector concatenated the name: it would be NULL without one.
+DROP INDEX subscriptions_search_idx;
+ALTER TABLE subscriptions DROP COLUMN search_vector;
+ALTER TABLE subscriptions ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (
+        to_tsvector('simple', coalesce(name, '') || ' ' || translate(email, '@.', '  '))
+    ) STORED;
+CREATE INDEX subscriptions_search_idx ON subscriptions USING GIN (search_vector);
+-- Older instances read every name as set, and fail on the subscribers without one.
+SELECT breaks_older_code(20220508012256);
@@ -0,0 +1,10 @@
+-- Would-be subscribers who signed up while the list was full, see
+-- `subscribers.max_subscribers`. Admins promote them to pending subscriptions.
+CREATE TABLE waitlist (
+    tenant_id uuid NOT NULL REFERENCES tenants (tenant_id),
+    email TEXT NOT NULL,
+    name TEXT NULL,
+    locale TEXT NULL,
+    joined_at timestamptz NOT NULL,
+    PRIMARY KEY (tenant_id, email)
+);
@@ -0,0 +1,8 @@
+-- Confirmation links are single-use. The token itself lives on, in unsubscribe links.
+ALTER TABLE subscription_tokens ADD COLUMN confirmation_used_at timestamptz NULL;
+-- Every subscriber past the pending state - confirmed, then maybe unsubscribed or
+-- bounced - used their link already.
+UPDATE subscription_tokens t
+SET confirmation_used_at = now()
+FROM subscriptions s
+WHERE s.id = t.subscriber_id AND s.status <> 'pending_confirmation';
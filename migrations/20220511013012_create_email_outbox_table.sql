@@ -0,0 +1,14 @@
+-- Confirmation emails waiting for the email provider to be reachable again, see
+-- `crate::email_outbox`.
+CREATE TABLE email_outbox (
+    email_id uuid PRIMARY KEY,
+    tenant_id uuid NOT NULL REFERENCES tenants (tenant_id),
+    -- NULL for the default sender address.
+    sender TEXT NULL,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    enqueued_at timestamptz NOT NULL
+);
+CREATE INDEX email_outbox_enqueued_at_idx ON email_outbox (enqueued_at);
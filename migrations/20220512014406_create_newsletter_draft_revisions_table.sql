@@ -0,0 +1,16 @@
+-- Every save of a draft, see `crate::drafts`. Dropped with the draft once published.
+CREATE TABLE newsletter_draft_revisions (
+    draft_id uuid NOT NULL REFERENCES newsletter_drafts (draft_id) ON DELETE CASCADE,
+    revision INT NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    saved_at timestamptz NOT NULL,
+    PRIMARY KEY (draft_id, revision)
+);
+-- The drafts saved so far start their history with their current content.
+INSERT INTO newsletter_draft_revisions (
+    draft_id, revision, title, text_content, html_content, saved_at
+)
+SELECT draft_id, 1, title, text_content, html_content, saved_at
+FROM newsletter_drafts;
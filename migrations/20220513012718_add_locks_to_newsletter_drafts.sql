@@ -0,0 +1,9 @@
+-- Drafts are shared by the admins: whoever edits one holds its lock, kept alive by the
+-- heartbeat of the compose form. Stale locks can be taken over, see `crate::drafts`.
+ALTER TABLE newsletter_drafts ADD COLUMN locked_by uuid NULL REFERENCES users (user_id);
+ALTER TABLE newsletter_drafts ADD COLUMN lock_heartbeat_at timestamptz NULL;
+UPDATE newsletter_drafts SET locked_by = user_id, lock_heartbeat_at = saved_at;
+ALTER TABLE newsletter_drafts ALTER COLUMN locked_by SET NOT NULL;
+ALTER TABLE newsletter_drafts ALTER COLUMN lock_heartbeat_at SET NOT NULL;
+-- Older instances save drafts without a lock.
+SELECT breaks_older_code(20220513012718);
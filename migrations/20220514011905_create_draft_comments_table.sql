@@ -0,0 +1,11 @@
+-- Review feedback left on a draft, see `crate::drafts`. Dropped with the draft once
+-- published.
+CREATE TABLE draft_comments (
+    comment_id uuid PRIMARY KEY,
+    draft_id uuid NOT NULL REFERENCES newsletter_drafts (draft_id) ON DELETE CASCADE,
+    author_id uuid NOT NULL REFERENCES users (user_id),
+    body TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    resolved_at timestamptz NULL
+);
+CREATE INDEX draft_comments_draft_id_idx ON draft_comments (draft_id, created_at);
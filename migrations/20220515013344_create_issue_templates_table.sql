@@ -0,0 +1,9 @@
+-- Reusable starting points for issues, see `crate::issue_templates`.
+CREATE TABLE issue_templates (
+    template_id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
@@ -0,0 +1,19 @@
+-- The subscribers an issue of tenant $1 goes to: the confirmed ones, only those tagged
+-- with segment $2 if there is one. The delivery queue and the recipients preview of
+-- the confirmation screen both select from it, so that the preview cannot drift from
+-- who actually receives the issue.
+CREATE FUNCTION issue_recipients(tenant_id uuid, segment text) RETURNS SETOF subscriptions AS $$
+    SELECT s.*
+    FROM subscriptions s
+    WHERE
+        s.tenant_id = $1 AND
+        s.status = 'confirmed' AND
+        s.deleted_at IS NULL AND
+        (
+            $2 IS NULL OR
+            EXISTS (
+                SELECT 1 FROM subscriber_tags t
+                WHERE t.subscriber_id = s.id AND t.tag = $2
+            )
+        )
+$$ LANGUAGE SQL STABLE;
@@ -0,0 +1,3 @@
+-- Admins without it see the names and email addresses of subscribers masked, see the
+-- `pii-access` command. The existing admins keep seeing them.
+ALTER TABLE users ADD COLUMN can_view_pii BOOLEAN NOT NULL DEFAULT TRUE;
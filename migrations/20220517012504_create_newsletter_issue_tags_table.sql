@@ -0,0 +1,9 @@
+-- The topics of an issue, for the category pages of the archive. Unlike its segment,
+-- they do not change who receives the issue.
+CREATE TABLE newsletter_issue_tags (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, tag)
+);
+CREATE INDEX newsletter_issue_tags_tag_idx ON newsletter_issue_tags (tag);
@@ -0,0 +1,3 @@
+-- The email provider which accepted a delivered email: `primary` or `fallback`, see
+-- `crate::email_failover`.
+ALTER TABLE issue_deliveries ADD COLUMN provider TEXT NULL;
@@ -0,0 +1,3 @@
+-- Bounces are classified as they are received: `hard`, `soft`, `blocked` or `spam`, see
+-- `crate::bounces`. The bounces received before are not classified.
+ALTER TABLE email_provider_events ADD COLUMN bounce_category TEXT NULL;
@@ -0,0 +1,10 @@
+-- The spam complaints of recipients, against the issue they complained about.
+CREATE TABLE issue_complaints (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues(newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    received_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
+-- The deliveries of an issue wait while it is paused, e.g. because too many of its
+-- recipients complained, until an admin resumes them.
+ALTER TABLE newsletter_issues ADD COLUMN paused_at timestamptz NULL;
@@ -0,0 +1,22 @@
+-- The verdict of the verification of imported addresses, see `crate::email_verification`.
+-- NULL for the addresses which were not verified.
+ALTER TABLE subscriptions ADD COLUMN verification TEXT NULL;
+
+-- Issues are not delivered to the addresses found undeliverable.
+CREATE OR REPLACE FUNCTION issue_recipients(tenant_id uuid, segment text)
+RETURNS SETOF subscriptions AS $$
+    SELECT s.*
+    FROM subscriptions s
+    WHERE
+        s.tenant_id = $1 AND
+        s.status = 'confirmed' AND
+        s.deleted_at IS NULL AND
+        s.verification IS DISTINCT FROM 'undeliverable' AND
+        (
+            $2 IS NULL OR
+            EXISTS (
+                SELECT 1 FROM subscriber_tags t
+                WHERE t.subscriber_id = s.id AND t.tag = $2
+            )
+        )
+$$ LANGUAGE SQL STABLE;
@@ -0,0 +1,30 @@
+-- The personal data of subscribers, encrypted by the application, see `crate::pii`.
+ALTER TABLE subscriptions
+    ADD COLUMN email_encrypted BYTEA NULL,
+    ADD COLUMN name_encrypted BYTEA NULL,
+    ADD COLUMN email_blind_index BYTEA NULL,
+    ADD COLUMN pii_encrypted_at TIMESTAMPTZ NULL;
+
+CREATE INDEX subscriptions_email_blind_index_idx
+    ON subscriptions (tenant_id, email_blind_index);
+-- The subscribers left to encrypt, usually none.
+CREATE INDEX subscriptions_pii_not_encrypted_idx
+    ON subscriptions (id) WHERE pii_encrypted_at IS NULL;
+
+-- A new email or name must be encrypted again: the stale ciphertexts go, so that they
+-- cannot outlive the values they were made from.
+CREATE FUNCTION clear_encrypted_pii() RETURNS trigger AS $$
+BEGIN
+    IF NEW.email IS DISTINCT FROM OLD.email OR NEW.name IS DISTINCT FROM OLD.name THEN
+        NEW.email_encrypted := NULL;
+        NEW.name_encrypted := NULL;
+        NEW.email_blind_index := NULL;
+        NEW.pii_encrypted_at := NULL;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER subscriptions_clear_encrypted_pii
+    BEFORE UPDATE OF email, name ON subscriptions
+    FOR EACH ROW EXECUTE FUNCTION clear_encrypted_pii();
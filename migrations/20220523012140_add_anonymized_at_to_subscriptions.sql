@@ -0,0 +1,2 @@
+-- When the retention policy anonymized the subscriber, see `crate::retention`.
+ALTER TABLE subscriptions ADD COLUMN anonymized_at timestamptz NULL;
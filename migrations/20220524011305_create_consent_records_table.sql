@@ -0,0 +1,12 @@
+-- The proof of each subscriber's opt-in: where they signed up and confirmed from.
+CREATE TABLE consent_records (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    -- `signup` or `confirmation`.
+    action TEXT NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    -- The hex-encoded SHA-256 of the form as submitted.
+    form_hash TEXT NULL,
+    recorded_at timestamptz NOT NULL
+);
+CREATE INDEX consent_records_subscriber_id_idx ON consent_records (subscriber_id);
@@ -0,0 +1,2 @@
+-- The version of the terms or privacy policy accepted at signup, if any.
+ALTER TABLE consent_records ADD COLUMN policy_version TEXT NULL;
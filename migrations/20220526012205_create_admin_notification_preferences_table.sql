@@ -0,0 +1,12 @@
+-- Which events email each admin, see `crate::notifications`. Admins without a row are
+-- not notified of anything.
+CREATE TABLE admin_notification_preferences (
+    user_id uuid PRIMARY KEY REFERENCES users (user_id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    -- The `AdminEvent`s the admin is notified of, e.g. `send_completed`.
+    events TEXT[] NOT NULL,
+    -- The share of the deliveries of an issue which must fail for a `delivery_failures`
+    -- notification, between 0 and 1.
+    failure_rate_threshold float8 NOT NULL,
+    updated_at timestamptz NOT NULL
+);
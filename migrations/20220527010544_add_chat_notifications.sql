@@ -0,0 +1,9 @@
+-- The events posted to the chat channel, see `crate::chat_notifications`.
+ALTER TABLE events ADD COLUMN chat_posted_at timestamptz NULL;
+CREATE INDEX events_occurred_at_idx ON events (occurred_at);
+
+-- When each kind of chat alert was last posted, so that they are not repeated.
+CREATE TABLE chat_alerts (
+    alert TEXT PRIMARY KEY,
+    posted_at timestamptz NOT NULL
+);
@@ -0,0 +1,6 @@
+-- When each tenant was last sent its weekly report, see `crate::weekly_report`.
+CREATE TABLE weekly_reports (
+    tenant_id uuid NOT NULL REFERENCES tenants (tenant_id) ON DELETE CASCADE,
+    sent_at timestamptz NOT NULL,
+    PRIMARY KEY(tenant_id)
+);
@@ -0,0 +1,20 @@
+-- Delivery workers LISTEN on `issue_delivery`, to wake up as soon as there is work rather
+-- than polling the queue. Notifications are sent on commit, once per transaction.
+CREATE FUNCTION notify_issue_delivery_workers() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('issue_delivery', '');
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Publishing, retrying failed deliveries, resending to soft bounces...
+CREATE TRIGGER issue_delivery_queue_notify_workers
+    AFTER INSERT ON issue_delivery_queue
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_issue_delivery_workers();
+
+-- ...and resuming a paused issue.
+CREATE TRIGGER newsletter_issues_notify_workers_on_resume
+    AFTER UPDATE OF paused_at ON newsletter_issues
+    FOR EACH ROW
+    WHEN (OLD.paused_at IS NOT NULL AND NEW.paused_at IS NULL)
+    EXECUTE FUNCTION notify_issue_delivery_workers();
@@ -0,0 +1,3 @@
+-- Operators run the deployment: the maintenance tools, the read-only switch, the backups
+-- and the query metrics work on the whole database, not on a tenant's data.
+ALTER TABLE users ADD COLUMN is_operator BOOLEAN NOT NULL DEFAULT false;
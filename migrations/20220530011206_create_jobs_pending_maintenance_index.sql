@@ -0,0 +1,3 @@
+-- A maintenance task cannot be queued while it is already queued or running.
+CREATE UNIQUE INDEX jobs_pending_maintenance_idx ON jobs ((payload->>'task'))
+    WHERE kind = 'maintenance' AND status IN ('queued', 'running');
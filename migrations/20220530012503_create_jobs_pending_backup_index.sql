@@ -0,0 +1,3 @@
+-- A backup cannot be queued while another one is queued or running.
+CREATE UNIQUE INDEX jobs_pending_backup_idx ON jobs (kind)
+    WHERE kind = 'backup' AND status IN ('queued', 'running');
@@ -0,0 +1,7 @@
+-- The re-engagement job looks up the latest issues delivered to each subscriber, and
+-- whether they opened or clicked them.
+CREATE INDEX issue_deliveries_delivered_subscriber_idx
+    ON issue_deliveries (subscriber_email, attempted_at DESC)
+    WHERE outcome = 'delivered';
+CREATE INDEX email_provider_events_recipient_idx
+    ON email_provider_events (recipient, record_type);
@@ -0,0 +1,53 @@
+-- Sealed subscribers keep neither their address nor their name in the clear, see
+-- `crate::pii`. The ciphertexts stored so far sit next to the clear values: they are
+-- dropped, and the subscribers sealed afresh.
+UPDATE subscriptions
+SET
+    email_encrypted = NULL,
+    name_encrypted = NULL,
+    email_blind_index = NULL,
+    pii_encrypted_at = NULL
+WHERE pii_encrypted_at IS NOT NULL;
+
+-- Anonymized subscribers have nothing left to seal.
+DROP INDEX subscriptions_pii_not_encrypted_idx;
+CREATE INDEX subscriptions_pii_not_encrypted_idx
+    ON subscriptions (id) WHERE pii_encrypted_at IS NULL AND anonymized_at IS NULL;
+
+-- Sealing a subscriber replaces their email and name along with the ciphertexts: only
+-- the changes which leave the ciphertexts untouched make them stale. A clear address
+-- unseals the subscriber, e.g. once anonymized; a clear name has them sealed again, with
+-- the address they keep.
+CREATE OR REPLACE FUNCTION clear_encrypted_pii() RETURNS trigger AS $$
+BEGIN
+    IF NEW.email_encrypted IS NOT DISTINCT FROM OLD.email_encrypted THEN
+        IF NEW.email IS DISTINCT FROM OLD.email THEN
+            NEW.email_encrypted := NULL;
+            NEW.name_encrypted := NULL;
+            NEW.email_blind_index := NULL;
+            NEW.pii_encrypted_at := NULL;
+        ELSIF NEW.name IS DISTINCT FROM OLD.name THEN
+            NEW.name_encrypted := NULL;
+            NEW.pii_encrypted_at := NULL;
+        END IF;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- The email provider reports events with the address in the clear: their blind index
+-- matches them with sealed subscribers.
+ALTER TABLE email_provider_events ADD COLUMN recipient_blind_index BYTEA NULL;
+CREATE INDEX email_provider_events_recipient_blind_index_idx
+    ON email_provider_events (recipient_blind_index, record_type)
+    WHERE recipient_blind_index IS NOT NULL;
+
+-- The domain an address is delivered to, for the throttling per domain. The pseudonyms
+-- of sealed subscribers keep it, followed by `.encrypted.invalid`.
+CREATE FUNCTION recipient_domain(email TEXT) RETURNS TEXT AS $$
+    SELECT regexp_replace(lower(substring(email from '[^@]*$')), '\.encrypted\.invalid$', '')
+$$ LANGUAGE SQL IMMUTABLE;
+
+-- Older instances would keep writing the personal data of new subscribers in the clear,
+-- and deliver to the pseudonyms.
+SELECT breaks_older_code(20220530015102);
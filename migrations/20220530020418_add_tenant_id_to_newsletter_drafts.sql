@@ -0,0 +1,12 @@
+-- Drafts belong to the tenant of the admin who started them: the admins of other tenants
+-- cannot open, restore or publish them, see `crate::drafts`.
+ALTER TABLE newsletter_drafts ADD COLUMN tenant_id uuid NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES tenants (tenant_id);
+UPDATE newsletter_drafts d SET tenant_id = u.tenant_id FROM users u WHERE u.user_id = d.user_id;
+CREATE INDEX newsletter_drafts_tenant_id_idx ON newsletter_drafts (tenant_id, saved_at);
+
+ALTER TABLE newsletter_draft_revisions ADD COLUMN tenant_id uuid NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES tenants (tenant_id);
+UPDATE newsletter_draft_revisions r SET tenant_id = d.tenant_id
+FROM newsletter_drafts d
+WHERE d.draft_id = r.draft_id;
@@ -0,0 +1,32 @@
+-- Domain events belong to the tenant they happened to: each admin only sees the activity
+-- of their own newsletter, see `crate::activity`.
+ALTER TABLE events ADD COLUMN tenant_id uuid NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES tenants (tenant_id);
+UPDATE events e SET tenant_id = i.tenant_id
+FROM newsletter_issues i
+WHERE i.newsletter_issue_id::text = e.payload->>'newsletter_issue_id';
+UPDATE events e SET tenant_id = s.tenant_id
+FROM subscriptions s
+WHERE s.id::text = e.payload->>'subscriber_id';
+CREATE INDEX events_tenant_id_occurred_at_idx ON events (tenant_id, occurred_at, event_id);
+
+-- The events of the email provider are attributed to the tenant of the issue they are
+-- tagged with, or else to the tenant of their recipient - unless several tenants share
+-- that address. Those attributed to nobody only count for the operators.
+ALTER TABLE email_provider_events ADD COLUMN tenant_id uuid NULL REFERENCES tenants (tenant_id);
+UPDATE email_provider_events e SET tenant_id = i.tenant_id
+FROM newsletter_issues i
+WHERE i.newsletter_issue_id::text = e.payload->>'Tag';
+UPDATE email_provider_events e SET tenant_id = (
+    SELECT (array_agg(DISTINCT s.tenant_id))[1]
+    FROM subscriptions s
+    WHERE lower(s.email) = lower(e.recipient) OR s.email_blind_index = e.recipient_blind_index
+    HAVING COUNT(DISTINCT s.tenant_id) = 1
+)
+WHERE e.tenant_id IS NULL AND e.recipient IS NOT NULL;
+CREATE INDEX email_provider_events_tenant_id_received_at_idx
+    ON email_provider_events (tenant_id, received_at);
+
+-- Each tenant names its own admins.
+ALTER TABLE users DROP CONSTRAINT users_username_key;
+ALTER TABLE users ADD CONSTRAINT users_tenant_id_username_key UNIQUE (tenant_id, username);
@@ -0,0 +1,9 @@
+-- Bounces and complaints match their recipient case-insensitively: within the tenant of
+-- the issue when retrying a delivery, across every tenant when suppressing an address,
+-- see `crate::bounces`.
+CREATE INDEX subscriptions_tenant_id_lower_email_idx ON subscriptions (tenant_id, lower(email));
+CREATE INDEX subscriptions_lower_email_idx ON subscriptions (lower(email));
+-- Soft bounces are counted per recipient before retrying.
+CREATE INDEX email_provider_events_lower_recipient_idx
+    ON email_provider_events (lower(recipient))
+    WHERE bounce_category = 'soft';
@@ -0,0 +1,9 @@
+-- The addresses which bounced or complained, kept hashed once their subscribers are
+-- anonymized so that they are not imported, and mailed, again - see `crate::retention`.
+CREATE TABLE suppressed_addresses (
+    -- The blind index of the address of a sealed subscriber, the SHA-256 of the
+    -- lowercased address otherwise.
+    email_hash BYTEA PRIMARY KEY,
+    reason TEXT NOT NULL,
+    suppressed_at timestamptz NOT NULL
+);
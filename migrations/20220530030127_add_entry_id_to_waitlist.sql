@@ -0,0 +1,5 @@
+-- Admins promote waitlist entries by id: those who cannot see the personal data of
+-- subscribers never get the address. Older instances insert entries without an id.
+ALTER TABLE waitlist
+    ADD COLUMN entry_id uuid NOT NULL DEFAULT md5(random()::text || clock_timestamp()::text)::uuid;
+ALTER TABLE waitlist ADD CONSTRAINT waitlist_entry_id_key UNIQUE (entry_id);
@@ -0,0 +1,19 @@
+-- Each tenant keeps its own issue templates, named uniquely among them.
+ALTER TABLE issue_templates ADD COLUMN tenant_id uuid NOT NULL
+    DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES tenants (tenant_id);
+ALTER TABLE issue_templates ALTER COLUMN tenant_id DROP DEFAULT;
+ALTER TABLE issue_templates DROP CONSTRAINT issue_templates_name_key;
+ALTER TABLE issue_templates ADD CONSTRAINT issue_templates_tenant_id_name_key
+    UNIQUE (tenant_id, name);
+
+-- The sections of a template are now marked as such, `{{ section: intro }}`: the other
+-- placeholders but the subscriber fields are left alone, see `crate::merge_fields`.
+UPDATE issue_templates SET
+    title = regexp_replace(title, p.pattern, '{{ section: \1 }}', 'g'),
+    text_content = regexp_replace(text_content, p.pattern, '{{ section: \1 }}', 'g'),
+    html_content = regexp_replace(html_content, p.pattern, '{{ section: \1 }}', 'g')
+FROM (
+    SELECT '\{\{\s*(?!(?:name|email)\s*\}\})([A-Za-z_][A-Za-z0-9_]*)\s*\}\}'::text AS pattern
+) p;
+
+SELECT breaks_older_code(20220530031904);
@@ -0,0 +1,7 @@
+-- Each tenant posts its chat notifications to its own channel, see
+-- `crate::chat_notifications`: the configured webhook is the default tenant's.
+ALTER TABLE tenants ADD COLUMN chat_webhook_url TEXT NULL;
+
+-- The events are claimed, then posted, rather than locked while the webhooks are called.
+-- A claim older than a few minutes was left by a run which did not finish.
+ALTER TABLE events ADD COLUMN chat_claimed_at timestamptz NULL;
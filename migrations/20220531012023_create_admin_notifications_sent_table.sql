@@ -0,0 +1,8 @@
+-- When each tenant's admins were last notified of the events they are only notified of
+-- once in a while, see `crate::notifications::notify_admins_at_most_every`.
+CREATE TABLE admin_notifications_sent (
+    tenant_id uuid NOT NULL REFERENCES tenants (tenant_id),
+    event TEXT NOT NULL,
+    sent_at timestamptz NOT NULL,
+    PRIMARY KEY (tenant_id, event)
+);
@@ -0,0 +1,11 @@
+-- Each sending domain warms up on its own, see `crate::sending_quota`: the deliveries
+-- are counted against the domain they were sent from, which is warmed up from the day
+-- it first sent.
+ALTER TABLE issue_deliveries ADD COLUMN sender_domain TEXT NULL;
+CREATE INDEX issue_deliveries_sender_domain_attempted_at_idx
+    ON issue_deliveries (sender_domain, attempted_at);
+
+CREATE TABLE sending_domains (
+    domain TEXT PRIMARY KEY,
+    first_sent_on date NOT NULL
+);
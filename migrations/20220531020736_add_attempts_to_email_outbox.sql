@@ -0,0 +1,8 @@
+-- An email of the outbox which keeps failing is retried later and later, then set aside,
+-- rather than blocking the others, see `crate::email_outbox`.
+ALTER TABLE email_outbox ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE email_outbox ADD COLUMN next_attempt_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE email_outbox ADD COLUMN last_error TEXT NULL;
+ALTER TABLE email_outbox ADD COLUMN dead_lettered_at timestamptz NULL;
+CREATE INDEX email_outbox_next_attempt_at_idx ON email_outbox (next_attempt_at, enqueued_at)
+    WHERE dead_lettered_at IS NULL;
@@ -0,0 +1,3 @@
+-- The workers skip the deliveries waiting for the send time of their recipient: the
+-- index serves both `deliver_after IS NULL` and `deliver_after <= now()`.
+CREATE INDEX issue_delivery_queue_deliver_after_idx ON issue_delivery_queue (deliver_after);
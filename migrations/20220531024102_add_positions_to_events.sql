@@ -0,0 +1,17 @@
+-- The activity feed pages on the position of events rather than on their timestamp,
+-- which a transaction committing late can set in the past of events already streamed.
+-- The existing events were all committed: they keep the order of their timestamps.
+ALTER TABLE events ADD COLUMN transaction_id bigint NOT NULL DEFAULT 0;
+ALTER TABLE events ALTER COLUMN transaction_id SET DEFAULT txid_current();
+CREATE SEQUENCE events_sequence_number_seq;
+ALTER TABLE events ADD COLUMN sequence_number bigint NULL;
+UPDATE events e SET sequence_number = ordered.n
+FROM (
+    SELECT event_id, nextval('events_sequence_number_seq') AS n
+    FROM (SELECT event_id FROM events ORDER BY occurred_at, event_id) o
+) ordered
+WHERE e.event_id = ordered.event_id;
+ALTER TABLE events ALTER COLUMN sequence_number SET DEFAULT nextval('events_sequence_number_seq');
+ALTER TABLE events ALTER COLUMN sequence_number SET NOT NULL;
+ALTER SEQUENCE events_sequence_number_seq OWNED BY events.sequence_number;
+CREATE INDEX events_tenant_id_position_idx ON events (tenant_id, transaction_id, sequence_number);
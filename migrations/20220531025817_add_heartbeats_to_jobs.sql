@@ -0,0 +1,9 @@
+-- The workers beat the heartbeat of the jobs they run: a job whose worker stopped
+-- responding is reclaimed, see `crate::jobs`. The workers of older versions would have
+-- their jobs reclaimed while still running them.
+ALTER TABLE jobs ADD COLUMN heartbeat_at timestamptz NULL;
+ALTER TABLE jobs ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+UPDATE jobs SET heartbeat_at = started_at, attempts = 1 WHERE status = 'running';
+CREATE INDEX jobs_running_heartbeat_at_idx ON jobs (heartbeat_at) WHERE status = 'running';
+
+SELECT breaks_older_code(20220531025817);
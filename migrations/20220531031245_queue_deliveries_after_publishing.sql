@@ -0,0 +1,11 @@
+-- The deliveries of an issue are queued once it is published, in batches committed one
+-- at a time: `enqueued_through` is the last recipient queued so far, and `enqueued_at`
+-- is set once they all are. The delivery workers finish the issues a publishing request
+-- left halfway, which older versions would queue a second time.
+ALTER TABLE newsletter_issues ADD COLUMN enqueued_through TEXT NULL;
+ALTER TABLE newsletter_issues ADD COLUMN enqueued_at timestamptz NULL;
+UPDATE newsletter_issues SET enqueued_at = now();
+CREATE INDEX newsletter_issues_enqueuing_idx ON newsletter_issues (newsletter_issue_id)
+    WHERE enqueued_at IS NULL;
+
+SELECT breaks_older_code(20220531031245);
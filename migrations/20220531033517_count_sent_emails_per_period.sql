@@ -0,0 +1,20 @@
+-- The emails sent in each hour and each day, in UTC. The delivery workers count an email
+-- before sending it and send it only if the count stays within the quotas: workers
+-- running side by side cannot overshoot them.
+CREATE TABLE sent_email_counts (
+    period TEXT NOT NULL,
+    period_start timestamptz NOT NULL,
+    sent BIGINT NOT NULL,
+    PRIMARY KEY (period, period_start)
+);
+
+INSERT INTO sent_email_counts (period, period_start, sent)
+SELECT 'hour', date_trunc('hour', attempted_at, 'UTC'), COUNT(*)
+FROM issue_deliveries
+WHERE outcome <> 'skipped' AND attempted_at >= date_trunc('day', now(), 'UTC')
+GROUP BY 2
+UNION ALL
+SELECT 'day', date_trunc('day', attempted_at, 'UTC'), COUNT(*)
+FROM issue_deliveries
+WHERE outcome <> 'skipped' AND attempted_at >= date_trunc('day', now(), 'UTC')
+GROUP BY 2;
@@ -0,0 +1,10 @@
+-- The personal data of waitlisted signups, sealed as that of subscribers: `email` holds
+-- a pseudonym, see `crate::pii`.
+ALTER TABLE waitlist
+    ADD COLUMN email_encrypted BYTEA NULL,
+    ADD COLUMN name_encrypted BYTEA NULL,
+    ADD COLUMN email_blind_index BYTEA NULL;
+
+-- Joining twice keeps the first entry, whatever the pseudonym.
+CREATE UNIQUE INDEX waitlist_email_blind_index_key
+    ON waitlist (tenant_id, email_blind_index);
@@ -0,0 +1,129 @@
+//! The recorded domain events, read back as a feed of what happened to the newsletter of
+//! a tenant.
+//!
+//! The feed is ordered by the position of the events: the id of the transaction which
+//! recorded them, then their sequence number. Only the events of the transactions older
+//! than every one still running are read: a transaction committing late cannot add an
+//! event before the ones already streamed - it holds the feed back until it ends.
+use crate::events::DomainEvent;
+use crate::pii::Masked;
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How many of the most recent events the feed starts with.
+pub const RECENT_ACTIVITY: i64 = 20;
+/// Events are read in batches of this size when catching up.
+const ACTIVITY_BATCH_SIZE: i64 = 100;
+
+#[derive(Debug, serde::Serialize)]
+pub struct ActivityEntry {
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+    pub summary: String,
+    /// `None` if the event does not parse anymore.
+    #[serde(skip)]
+    event: Option<DomainEvent>,
+}
+
+impl Masked for ActivityEntry {
+    fn masked(self) -> Self {
+        match self.event {
+            Some(event) => {
+                let event = event.masked();
+                Self {
+                    summary: event.summary(),
+                    event: Some(event),
+                    ..self
+                }
+            }
+            None => self,
+        }
+    }
+}
+
+struct EventRow {
+    event_id: Uuid,
+    event_type: String,
+    payload: serde_json::Value,
+    occurred_at: DateTime<Utc>,
+}
+
+impl From<EventRow> for ActivityEntry {
+    fn from(row: EventRow) -> Self {
+        // Events recorded by an older version of the application may not parse anymore.
+        let event = serde_json::from_value::<DomainEvent>(row.payload).ok();
+        let summary = event
+            .as_ref()
+            .map(DomainEvent::summary)
+            .unwrap_or_else(|| row.event_type.replace('_', " "));
+        Self {
+            event_id: row.event_id,
+            event_type: row.event_type,
+            occurred_at: row.occurred_at,
+            summary,
+            event,
+        }
+    }
+}
+
+/// The most recent events, oldest first.
+#[tracing::instrument(skip(pool))]
+pub async fn get_recent_activity(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    limit: i64,
+) -> Result<Vec<ActivityEntry>, anyhow::Error> {
+    let mut rows = sqlx::query_as!(
+        EventRow,
+        r#"
+        SELECT event_id, event_type, payload, occurred_at
+        FROM events
+        WHERE tenant_id = $2 AND transaction_id < txid_snapshot_xmin(txid_current_snapshot())
+        ORDER BY transaction_id DESC, sequence_number DESC
+        LIMIT $1
+        "#,
+        limit,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the recent events")?;
+    rows.reverse();
+    Ok(rows.into_iter().map(ActivityEntry::from).collect())
+}
+
+/// Up to a batch of the events recorded after `event_id`, oldest first.
+/// Nothing if `event_id` is unknown, or an event of another tenant.
+#[tracing::instrument(skip(pool))]
+pub async fn get_activity_after(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    event_id: Uuid,
+) -> Result<Vec<ActivityEntry>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        EventRow,
+        r#"
+        SELECT e.event_id, e.event_type, e.payload, e.occurred_at
+        FROM events e, events after
+        WHERE after.event_id = $1
+            AND after.tenant_id = $3
+            AND e.tenant_id = $3
+            AND (e.transaction_id, e.sequence_number)
+                > (after.transaction_id, after.sequence_number)
+            AND e.transaction_id < txid_snapshot_xmin(txid_current_snapshot())
+        ORDER BY e.transaction_id, e.sequence_number
+        LIMIT $2
+        "#,
+        event_id,
+        ACTIVITY_BATCH_SIZE,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the latest events")?;
+    Ok(rows.into_iter().map(ActivityEntry::from).collect())
+}
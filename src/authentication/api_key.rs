@@ -0,0 +1,123 @@
+use crate::authentication::AuthError;
+use crate::tenancy::TenantId;
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct ApiKey {
+    pub api_key_id: Uuid,
+    pub description: String,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// API keys are long random strings, so a fast hash is enough to keep them out of the
+/// database in clear text - there is no low-entropy secret to protect against brute forcing.
+fn hash_api_key(key: &Secret<String>) -> String {
+    format!("{:x}", Sha256::digest(key.expose_secret().as_bytes()))
+}
+
+fn generate_api_key() -> Secret<String> {
+    let mut rng = thread_rng();
+    Secret::new(
+        std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(40)
+            .collect(),
+    )
+}
+
+/// Only the keys of the admins of `tenant_id` are valid.
+#[tracing::instrument(name = "Validate API key", skip(key, pool))]
+pub async fn validate_api_key(
+    key: Secret<String>,
+    tenant_id: TenantId,
+    pool: &PgPool,
+) -> Result<Uuid, AuthError> {
+    let row = sqlx::query!(
+        r#"
+        SELECT k.user_id
+        FROM api_keys k
+        JOIN users u USING (user_id)
+        WHERE k.key_hash = $1 AND k.revoked_at IS NULL AND u.tenant_id = $2
+        "#,
+        hash_api_key(&key),
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to validate an API key.")?;
+
+    row.map(|r| r.user_id)
+        .ok_or_else(|| AuthError::InvalidCredentials(anyhow!("Unknown or revoked API key.")))
+}
+
+/// Create a new API key for `user_id`.
+/// The key is only ever returned here - we store its hash.
+#[tracing::instrument(name = "Create API key", skip(pool))]
+pub async fn create_api_key(
+    user_id: Uuid,
+    description: &str,
+    pool: &PgPool,
+) -> Result<Secret<String>, anyhow::Error> {
+    let key = generate_api_key();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_keys (api_key_id, user_id, description, key_hash, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        description,
+        hash_api_key(&key)
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a new API key in the database.")?;
+    Ok(key)
+}
+
+#[tracing::instrument(name = "Revoke API key", skip(pool))]
+pub async fn revoke_api_key(
+    user_id: Uuid,
+    api_key_id: Uuid,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET revoked_at = now()
+        WHERE api_key_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        api_key_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke an API key.")?
+    .rows_affected();
+    Ok(n_updated_rows > 0)
+}
+
+#[tracing::instrument(name = "List API keys", skip(pool))]
+pub async fn list_api_keys(user_id: Uuid, pool: &PgPool) -> Result<Vec<ApiKey>, anyhow::Error> {
+    let keys = sqlx::query_as!(
+        ApiKey,
+        r#"
+        SELECT api_key_id, description, created_at, revoked_at
+        FROM api_keys
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve API keys.")?;
+    Ok(keys)
+}
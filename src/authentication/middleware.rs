@@ -1,10 +1,17 @@
+use crate::authentication::{get_session_version, is_operator, validate_api_key, AuthError};
+use crate::routes::api::ApiError;
 use crate::session_state::TypedSession;
+use crate::tenancy::{Tenant, TenantId};
 use crate::utils::{e500, see_other};
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
 use actix_web_lab::middleware::Next;
+use anyhow::anyhow;
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::fmt::Formatter;
 use std::ops::Deref;
 use uuid::Uuid;
@@ -35,15 +42,95 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
-            req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
-        }
+    let user_id = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
         None => {
             let response = see_other("/login");
             let e = anyhow::anyhow!("The user has not logged in");
-            Err(InternalError::from_response(e, response).into())
+            return Err(InternalError::from_response(e, response).into());
+        }
+    };
+
+    // Changing the password bumps the session version, logging out the other sessions.
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500(anyhow!("No database pool configured.")))?
+        .clone();
+    // A session opened for another tenant's admin is no good here.
+    let tenant_id = tenant_of(&req).map_err(e500)?;
+    let current_version = get_session_version(user_id, tenant_id, &pool)
+        .await
+        .map_err(e500)?;
+    if current_version.is_none()
+        || current_version != session.get_session_version().map_err(e500)?
+    {
+        session.log_out();
+        let response = see_other("/login");
+        let e = anyhow::anyhow!("The session was opened before the last password change");
+        return Err(InternalError::from_response(e, response).into());
+    }
+
+    req.extensions_mut().insert(UserId(user_id));
+    next.call(req).await
+}
+
+/// Within the admin panel, where `reject_anonymous_users` has identified the user.
+pub async fn reject_non_operators(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let user_id = req
+        .extensions()
+        .get::<UserId>()
+        .copied()
+        .ok_or_else(|| e500(anyhow!("The user was not identified.")))?;
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500(anyhow!("No database pool configured.")))?
+        .clone();
+    if !is_operator(&pool, *user_id).await.map_err(e500)? {
+        let response = HttpResponse::Forbidden().body("Only the operators can use this page.");
+        let e = anyhow::anyhow!("The user is not an operator");
+        return Err(InternalError::from_response(e, response).into());
+    }
+    next.call(req).await
+}
+
+pub async fn reject_invalid_api_keys(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let key = bearer_token(&req)
+        .ok_or_else(|| ApiError::AuthenticationError(anyhow!("Missing bearer API key.")))?;
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| ApiError::UnexpectedError(anyhow!("No database pool configured.")))?
+        .clone();
+
+    let tenant_id = tenant_of(&req)?;
+    match validate_api_key(key, tenant_id, &pool).await {
+        Ok(user_id) => {
+            req.extensions_mut().insert(UserId(user_id));
+            next.call(req).await
         }
+        Err(AuthError::InvalidCredentials(e)) => Err(ApiError::AuthenticationError(e).into()),
+        Err(AuthError::UnexpectedError(e)) => Err(ApiError::UnexpectedError(e).into()),
+    }
+}
+
+fn tenant_of(req: &ServiceRequest) -> Result<TenantId, ApiError> {
+    req.extensions()
+        .get::<Tenant>()
+        .map(|tenant| tenant.id)
+        .ok_or_else(|| ApiError::UnexpectedError(anyhow!("The tenant was not resolved.")))
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<Secret<String>> {
+    let header_value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let token = header_value.strip_prefix("Bearer ")?.trim();
+    if token.is_empty() {
+        None
+    } else {
+        Some(Secret::new(token.to_owned()))
     }
 }
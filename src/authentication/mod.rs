@@ -1,6 +1,14 @@
+mod api_key;
 mod middleware;
 mod password;
+mod permissions;
 
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub use api_key::{create_api_key, list_api_keys, revoke_api_key, validate_api_key, ApiKey};
+pub use password::{
+    change_password, create_user, get_session_version, validate_credentials, AuthError, Credentials,
+};
+pub use permissions::{can_view_pii, is_operator, set_operator, set_pii_access};
 
-pub use middleware::{reject_anonymous_users, UserId};
+pub use middleware::{
+    reject_anonymous_users, reject_invalid_api_keys, reject_non_operators, UserId,
+};
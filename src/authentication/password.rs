@@ -1,4 +1,5 @@
 use crate::telemetry::spawn_blocking_with_tracing;
+use crate::tenancy::TenantId;
 use anyhow::{anyhow, Context};
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
@@ -11,9 +12,11 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
+/// Only the admins of `tenant_id` can log in.
 #[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
 pub async fn validate_credentials(
     credentials: Credentials,
+    tenant_id: TenantId,
     pool: &PgPool,
 ) -> Result<uuid::Uuid, AuthError> {
     let mut user_id = None;
@@ -22,7 +25,7 @@ pub async fn validate_credentials(
             .to_string()
     );
     if let Some((stored_user_id, stored_password_hash)) =
-        get_stored_credentials(&credentials.username, pool).await?
+        get_stored_credentials(&credentials.username, tenant_id, pool).await?
     {
         user_id = Some(stored_user_id);
         expected_password_hash = stored_password_hash;
@@ -60,15 +63,17 @@ fn verify_password_hash(
 #[tracing::instrument(name = "Get stored credentials", skip(username, pool))]
 async fn get_stored_credentials(
     username: &str,
+    tenant_id: TenantId,
     pool: &PgPool,
 ) -> Result<Option<(uuid::Uuid, Secret<String>)>, anyhow::Error> {
     let row: Option<_> = sqlx::query!(
         r#"
         SELECT user_id, password_hash
         FROM users
-        WHERE username = $1
+        WHERE username = $1 AND tenant_id = $2
         "#,
         username,
+        *tenant_id
     )
     .fetch_optional(pool)
     .await
@@ -78,30 +83,84 @@ async fn get_stored_credentials(
     Ok(row)
 }
 
+/// Change the password of `user_id` and bump its session version, which logs out
+/// every session opened with the old password.
+/// Returns the new session version.
 #[tracing::instrument(name = "Change password", skip(user_id, password, pool))]
 pub async fn change_password(
     user_id: Uuid,
     password: Secret<String>,
     pool: &PgPool,
-) -> Result<(), anyhow::Error> {
+) -> Result<i32, anyhow::Error> {
     let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
         .await?
         .context("Failed to hash password")?;
 
-    sqlx::query!(
+    let row = sqlx::query!(
         r#"
         UPDATE users
-        SET password_hash = $1
+        SET password_hash = $1, session_version = session_version + 1
         WHERE user_id = $2
+        RETURNING session_version
         "#,
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .fetch_one(pool)
     .await
     .context("Failed to change user's password in the database.")?;
 
-    Ok(())
+    Ok(row.session_version)
+}
+
+/// `None` if there is no such admin of `tenant_id`.
+#[tracing::instrument(name = "Get session version", skip(pool))]
+pub async fn get_session_version(
+    user_id: Uuid,
+    tenant_id: TenantId,
+    pool: &PgPool,
+) -> Result<Option<i32>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT session_version
+        FROM users
+        WHERE user_id = $1 AND tenant_id = $2
+        "#,
+        user_id,
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a session version.")?;
+    Ok(row.map(|r| r.session_version))
+}
+
+/// Create an admin of `tenant_id`. Returns the id of the new user.
+#[tracing::instrument(name = "Create user", skip(password, pool))]
+pub async fn create_user(
+    username: &str,
+    password: Secret<String>,
+    tenant_id: TenantId,
+    pool: &PgPool,
+) -> Result<Uuid, anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await?
+        .context("Failed to hash password")?;
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, tenant_id)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        *tenant_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a new user in the database.")?;
+    Ok(user_id)
 }
 
 fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
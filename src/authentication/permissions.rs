@@ -0,0 +1,69 @@
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Whether the admin may see the names and email addresses of subscribers unmasked.
+#[tracing::instrument(skip(pool))]
+pub async fn can_view_pii(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!("SELECT can_view_pii FROM users WHERE user_id = $1", user_id)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the permissions of a user")?;
+    Ok(row.is_some_and(|r| r.can_view_pii))
+}
+
+/// Grant or revoke the admin's access to the personal data of subscribers. Returns
+/// `false` if there is no such admin of the tenant.
+#[tracing::instrument(skip(pool))]
+pub async fn set_pii_access(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    username: &str,
+    allowed: bool,
+) -> Result<bool, anyhow::Error> {
+    let updated = sqlx::query!(
+        "UPDATE users SET can_view_pii = $2 WHERE username = $1 AND tenant_id = $3",
+        username,
+        allowed,
+        *tenant_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the permissions of a user")?
+    .rows_affected();
+    Ok(updated == 1)
+}
+
+/// Whether the admin runs the deployment, and may use the tools which work on the whole
+/// database rather than on the data of their tenant.
+#[tracing::instrument(skip(pool))]
+pub async fn is_operator(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!("SELECT is_operator FROM users WHERE user_id = $1", user_id)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to retrieve the permissions of a user")?;
+    Ok(row.is_some_and(|r| r.is_operator))
+}
+
+/// Make the admin an operator, or take the role back. Returns `false` if there is no
+/// such admin of the tenant.
+#[tracing::instrument(skip(pool))]
+pub async fn set_operator(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    username: &str,
+    allowed: bool,
+) -> Result<bool, anyhow::Error> {
+    let updated = sqlx::query!(
+        "UPDATE users SET is_operator = $2 WHERE username = $1 AND tenant_id = $3",
+        username,
+        allowed,
+        *tenant_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the permissions of a user")?
+    .rows_affected();
+    Ok(updated == 1)
+}
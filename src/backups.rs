@@ -0,0 +1,598 @@
+//! Backups of the database, taken with `pg_dump` from the admin panel as background jobs.
+//!
+//! The dump is in the custom format of `pg_dump`, to restore with `pg_restore`. It is
+//! written to the configured directory of the job worker, or streamed to an S3 bucket
+//! if one is configured. Like the maintenance tasks, a backup covers the whole database,
+//! so only the operators can take one.
+use crate::configuration::{BackupDestination, BackupSettings, DatabaseSettings, S3Settings};
+use crate::jobs::{enqueue_job, JobPayload};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::Method;
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::path::Path;
+use std::process::{Output, Stdio};
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+/// S3 requires every part of a multipart upload but the last to be 5 MiB or more.
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// A completed backup, as listed on the backups page.
+pub struct Backup {
+    pub file_name: String,
+    pub location: String,
+    pub size_bytes: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Backup {
+    pub fn size(&self) -> String {
+        format_size(self.size_bytes)
+    }
+}
+
+/// Dump the database to the configured destination. Returns where it went, as the
+/// result of its job.
+#[tracing::instrument(name = "Back up the database", skip(pool, database, settings))]
+pub async fn run_backup(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    database: &DatabaseSettings,
+    settings: &BackupSettings,
+) -> Result<serde_json::Value, anyhow::Error> {
+    let created_at = Utc::now();
+    let file_name = format!(
+        "{}-{}.dump",
+        database.database_name,
+        created_at.format("%Y%m%dT%H%M%SZ")
+    );
+    let (location, size_bytes) = match settings.destination() {
+        BackupDestination::Directory(directory) => {
+            tokio::fs::create_dir_all(directory)
+                .await
+                .with_context(|| format!("Failed to create the backup directory {}", directory))?;
+            let path = Path::new(directory).join(&file_name);
+            let size_bytes = dump(&settings.pg_dump, database, &path).await?;
+            (path.display().to_string(), size_bytes)
+        }
+        BackupDestination::S3(s3) => {
+            let size_bytes = dump_to_s3(&settings.pg_dump, database, s3, &file_name).await?;
+            (format!("s3://{}/{}", s3.bucket, file_name), size_bytes)
+        }
+    };
+
+    sqlx::query!(
+        r#"
+        INSERT INTO backups (backup_id, tenant_id, file_name, location, size_bytes, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        *tenant_id,
+        file_name,
+        location,
+        size_bytes,
+        created_at
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record a backup")?;
+    Ok(serde_json::json!({ "location": location, "size_bytes": size_bytes }))
+}
+
+/// `pg_dump` of the database, into `file` or to its standard output.
+fn pg_dump_command(
+    pg_dump: &str,
+    database: &DatabaseSettings,
+    file: Option<&Path>,
+) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new(pg_dump);
+    command.arg("--format=custom").arg("--no-password");
+    if let Some(file) = file {
+        command.arg("--file").arg(file);
+    }
+    command
+        .arg("--host")
+        .arg(&database.host)
+        .arg("--port")
+        .arg(database.port.to_string())
+        .arg("--username")
+        .arg(&database.username)
+        .arg(&database.database_name)
+        .env("PGPASSWORD", database.password.expose_secret())
+        .env(
+            "PGSSLMODE",
+            if database.require_ssl {
+                "require"
+            } else {
+                "prefer"
+            },
+        );
+    command
+}
+
+fn check_pg_dump_status(output: &Output) -> Result<(), anyhow::Error> {
+    if !output.status.success() {
+        anyhow::bail!(
+            "pg_dump exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Run `pg_dump` into `path`, returning the size of the dump.
+async fn dump(
+    pg_dump: &str,
+    database: &DatabaseSettings,
+    path: &Path,
+) -> Result<i64, anyhow::Error> {
+    let output = pg_dump_command(pg_dump, database, Some(path))
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", pg_dump))?;
+    check_pg_dump_status(&output)?;
+    let metadata = tokio::fs::metadata(path)
+        .await
+        .context("Failed to read the size of the dump")?;
+    Ok(metadata.len() as i64)
+}
+
+/// Stream the output of `pg_dump` to the bucket as a multipart upload, holding one part
+/// in memory at a time. Returns the size of the dump.
+async fn dump_to_s3(
+    pg_dump: &str,
+    database: &DatabaseSettings,
+    s3: &S3Settings,
+    key: &str,
+) -> Result<i64, anyhow::Error> {
+    let mut child = pg_dump_command(pg_dump, database, None)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to run {}", pg_dump))?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .context("The output of pg_dump was not captured")?;
+    let upload = MultipartUpload::start(s3, key).await?;
+    let uploaded = async {
+        let mut size_bytes = 0;
+        let mut etags = Vec::new();
+        loop {
+            let mut part = Vec::with_capacity(PART_SIZE);
+            (&mut stdout)
+                .take(PART_SIZE as u64)
+                .read_to_end(&mut part)
+                .await
+                .context("Failed to read the output of pg_dump")?;
+            if part.is_empty() && !etags.is_empty() {
+                break;
+            }
+            let is_last = part.len() < PART_SIZE;
+            size_bytes += part.len() as i64;
+            etags.push(upload.upload_part(etags.len() + 1, part).await?);
+            if is_last {
+                break;
+            }
+        }
+        let output = child
+            .wait_with_output()
+            .await
+            .context("Failed to wait for pg_dump")?;
+        check_pg_dump_status(&output)?;
+        upload.complete(&etags).await?;
+        Ok(size_bytes)
+    }
+    .await;
+    if uploaded.is_err() {
+        if let Err(e) = upload.abort().await {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to abort the upload of a backup"
+            );
+        }
+    }
+    uploaded
+}
+
+/// An upload to S3 in parts, each of them signed and sent on its own.
+struct MultipartUpload<'a> {
+    client: reqwest::Client,
+    s3: &'a S3Settings,
+    key: &'a str,
+    upload_id: String,
+}
+
+impl<'a> MultipartUpload<'a> {
+    async fn start(s3: &'a S3Settings, key: &'a str) -> Result<MultipartUpload<'a>, anyhow::Error> {
+        let client = reqwest::Client::new();
+        let body = send_s3_request(
+            &client,
+            s3,
+            Method::POST,
+            key,
+            &[("uploads", "")],
+            Vec::new(),
+        )
+        .await
+        .context("Failed to start the upload of the backup")?
+        .text()
+        .await
+        .context("Failed to read the response of the object storage")?;
+        let upload_id = xml_element(&body, "UploadId")
+            .context("The object storage did not return the ID of the upload")?;
+        Ok(Self {
+            client,
+            s3,
+            key,
+            upload_id,
+        })
+    }
+
+    /// Returns the `ETag` of the part, to complete the upload with.
+    async fn upload_part(
+        &self,
+        part_number: usize,
+        content: Vec<u8>,
+    ) -> Result<String, anyhow::Error> {
+        let part_number = part_number.to_string();
+        let query = [
+            ("partNumber", part_number.as_str()),
+            ("uploadId", &self.upload_id),
+        ];
+        let response = send_s3_request(
+            &self.client,
+            self.s3,
+            Method::PUT,
+            self.key,
+            &query,
+            content,
+        )
+        .await
+        .with_context(|| format!("Failed to upload part {} of the backup", part_number))?;
+        let etag = response
+            .headers()
+            .get("ETag")
+            .and_then(|etag| etag.to_str().ok())
+            .context("The object storage did not return the ETag of a part")?;
+        Ok(etag.to_owned())
+    }
+
+    async fn complete(&self, etags: &[String]) -> Result<(), anyhow::Error> {
+        let parts: String = etags
+            .iter()
+            .enumerate()
+            .map(|(i, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    i + 1,
+                    etag
+                )
+            })
+            .collect();
+        let body = format!(
+            "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+            parts
+        );
+        let query = [("uploadId", self.upload_id.as_str())];
+        let response = send_s3_request(
+            &self.client,
+            self.s3,
+            Method::POST,
+            self.key,
+            &query,
+            body.into_bytes(),
+        )
+        .await
+        .context("Failed to complete the upload of the backup")?
+        .text()
+        .await
+        .context("Failed to read the response of the object storage")?;
+        // S3 reports some failures to complete an upload with a 200 OK.
+        if response.contains("<Error>") {
+            anyhow::bail!(
+                "The object storage failed to complete the upload: {}",
+                response
+            );
+        }
+        Ok(())
+    }
+
+    /// Delete the parts uploaded so far.
+    async fn abort(&self) -> Result<(), anyhow::Error> {
+        let query = [("uploadId", self.upload_id.as_str())];
+        send_s3_request(
+            &self.client,
+            self.s3,
+            Method::DELETE,
+            self.key,
+            &query,
+            Vec::new(),
+        )
+        .await
+        .context("Failed to abort the upload of the backup")?;
+        Ok(())
+    }
+}
+
+/// A path-style request for the object, signed with AWS Signature Version 4.
+#[tracing::instrument(skip(client, s3, content), fields(bucket = %s3.bucket))]
+async fn send_s3_request(
+    client: &reqwest::Client,
+    s3: &S3Settings,
+    method: Method,
+    key: &str,
+    query: &[(&str, &str)],
+    content: Vec<u8>,
+) -> Result<reqwest::Response, anyhow::Error> {
+    let mut url = reqwest::Url::parse(&format!("{}/{}/{}", s3.endpoint, s3.bucket, key))
+        .context("Failed to build the URL of the backup")?;
+    let query = canonical_query_string(query);
+    if !query.is_empty() {
+        url.set_query(Some(&query));
+    }
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_owned(),
+    };
+    let now = Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let content_sha256 = hex::encode(Sha256::digest(&content));
+    let authorization = sign_s3_request(
+        s3,
+        method.as_str(),
+        url.path(),
+        &query,
+        &host,
+        &content_sha256,
+        &amz_date,
+    );
+
+    let response = client
+        .request(method, url)
+        .header("Authorization", authorization)
+        .header("x-amz-content-sha256", content_sha256)
+        .header("x-amz-date", amz_date)
+        .body(content)
+        .send()
+        .await?
+        .error_for_status()
+        .context("The object storage rejected the request")?;
+    Ok(response)
+}
+
+/// The query parameters sorted by name and URI-encoded, as they are signed.
+fn canonical_query_string(query: &[(&str, &str)]) -> String {
+    let mut query: Vec<_> = query
+        .iter()
+        .map(|(name, value)| (urlencoding::encode(name), urlencoding::encode(value)))
+        .collect();
+    query.sort();
+    query
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// The text of the first `name` element of an XML document from S3.
+fn xml_element(document: &str, name: &str) -> Option<String> {
+    let start = document.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + document[start..].find(&format!("</{}>", name))?;
+    Some(document[start..end].to_owned())
+}
+
+/// The `Authorization` header of a request to S3, signing its query, host, payload hash
+/// and date.
+fn sign_s3_request(
+    s3: &S3Settings,
+    method: &str,
+    path: &str,
+    query: &str,
+    host: &str,
+    content_sha256: &str,
+    amz_date: &str,
+) -> String {
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, s3.region);
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+        "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        method, path, query, host, content_sha256, amz_date, signed_headers, content_sha256
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let mut key = format!("AWS4{}", s3.secret_access_key.expose_secret()).into_bytes();
+    for part in [date, s3.region.as_str(), "s3", "aws4_request"] {
+        key = hmac_sha256(&key, part.as_bytes());
+    }
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        s3.access_key_id,
+        scope,
+        signed_headers,
+        hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()))
+    )
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// e.g. `1.5 MB`.
+pub fn format_size(size_bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = size_bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size_bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// The latest completed backups taken by the operators of the tenant, newest first.
+#[tracing::instrument(name = "List the backups", skip(pool))]
+pub async fn list_backups(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    limit: i64,
+) -> Result<Vec<Backup>, anyhow::Error> {
+    let backups = sqlx::query_as!(
+        Backup,
+        r#"
+        SELECT file_name, location, size_bytes, created_at
+        FROM backups
+        WHERE tenant_id = $1
+        ORDER BY created_at DESC
+        LIMIT $2
+        "#,
+        *tenant_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the backups")?;
+    Ok(backups)
+}
+
+/// The latest backup job of the tenant: whether one is pending, and why the last one
+/// failed.
+pub struct BackupJobStatus {
+    /// `queued`, `running`, `succeeded` or `failed`.
+    pub status: String,
+    pub error: Option<String>,
+}
+
+impl BackupJobStatus {
+    pub fn is_pending(&self) -> bool {
+        matches!(self.status.as_str(), "queued" | "running")
+    }
+}
+
+#[tracing::instrument(name = "Get the status of the latest backup", skip(pool))]
+pub async fn latest_backup_job(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Option<BackupJobStatus>, anyhow::Error> {
+    let status = sqlx::query_as!(
+        BackupJobStatus,
+        r#"
+        SELECT status, error
+        FROM jobs
+        WHERE kind = 'backup' AND tenant_id = $1
+        ORDER BY created_at DESC
+        LIMIT 1
+        "#,
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the latest backup job")?;
+    Ok(status)
+}
+
+/// Queue a backup for the job worker. Returns `None` if one is already queued or running,
+/// whoever started it: `jobs_pending_backup_idx` lets a single one through.
+#[tracing::instrument(name = "Queue a backup", skip(pool))]
+pub async fn start_backup_job(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    match enqueue_job(pool, tenant_id, &JobPayload::Backup).await {
+        Ok(job_id) => Ok(Some(job_id)),
+        Err(e) => {
+            let pending = matches!(
+                e.downcast_ref::<sqlx::Error>(),
+                Some(sqlx::Error::Database(e)) if e.constraint() == Some("jobs_pending_backup_idx")
+            );
+            if pending {
+                Ok(None)
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_query_string, format_size, sign_s3_request, xml_element};
+    use crate::configuration::S3Settings;
+    use secrecy::Secret;
+
+    #[test]
+    fn sizes_are_shown_in_the_largest_fitting_unit() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0 GB");
+    }
+
+    #[test]
+    fn s3_requests_are_signed_for_their_scope() {
+        let s3 = S3Settings {
+            endpoint: "https://s3.amazonaws.com".into(),
+            region: "eu-west-1".into(),
+            bucket: "backups".into(),
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: Secret::new("secret".into()),
+        };
+        let sign = |path| {
+            sign_s3_request(
+                &s3,
+                "PUT",
+                path,
+                "",
+                "s3.amazonaws.com",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                "20220503T021544Z",
+            )
+        };
+
+        let authorization = sign("/backups/a.dump");
+        assert!(authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20220503/eu-west-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature="
+        ));
+        assert_ne!(authorization, sign("/backups/b.dump"));
+    }
+
+    #[test]
+    fn query_parameters_are_signed_sorted_and_encoded() {
+        assert_eq!(
+            canonical_query_string(&[("uploadId", "a b/c~"), ("partNumber", "2")]),
+            "partNumber=2&uploadId=a%20b%2Fc~"
+        );
+        assert_eq!(canonical_query_string(&[("uploads", "")]), "uploads=");
+    }
+
+    #[test]
+    fn the_upload_id_is_read_from_the_response() {
+        let response = "<InitiateMultipartUploadResult><Bucket>backups</Bucket>\
+            <UploadId>VXBsb2FkIElE</UploadId></InitiateMultipartUploadResult>";
+
+        assert_eq!(
+            xml_element(response, "UploadId"),
+            Some("VXBsb2FkIElE".to_owned())
+        );
+        assert_eq!(xml_element(response, "ETag"), None);
+    }
+}
@@ -0,0 +1,315 @@
+//! Classifying the bounces reported by the email provider and acting on them.
+//!
+//! Each bounce falls into one category, with its own policy:
+//! - `hard`: the address does not exist, its subscriptions are suppressed right away;
+//! - `soft`: the mailbox is full or the server is down, the delivery is retried later;
+//! - `blocked` and `spam`: the receiving server refused the email because of us, the
+//!   bounce is flagged for review on the deliverability dashboard.
+use crate::events::{record_event, DomainEvent};
+use crate::pii::PiiCipher;
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+/// Stored as the unsubscribe reason of the subscribers whose address hard bounced.
+pub const BOUNCED_UNSUBSCRIBE_REASON: &str = "bounced";
+/// A soft bounced delivery is queued again after this many minutes...
+const SOFT_BOUNCE_RETRY_DELAY_MINUTES: i32 = 60;
+/// ...unless the issue already soft bounced this many times for the recipient.
+const MAX_SOFT_BOUNCE_RETRIES: i64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BounceCategory {
+    Hard,
+    Soft,
+    Blocked,
+    Spam,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BouncePolicy {
+    Suppress,
+    RetryLater,
+    FlagForReview,
+}
+
+impl BouncePolicy {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BouncePolicy::Suppress => "Suppressed",
+            BouncePolicy::RetryLater => "Retried later",
+            BouncePolicy::FlagForReview => "Flagged for review",
+        }
+    }
+}
+
+impl BounceCategory {
+    pub const ALL: [BounceCategory; 4] = [
+        BounceCategory::Hard,
+        BounceCategory::Soft,
+        BounceCategory::Blocked,
+        BounceCategory::Spam,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid bounce category.", s))
+    }
+
+    /// How the category is stored.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BounceCategory::Hard => "hard",
+            BounceCategory::Soft => "soft",
+            BounceCategory::Blocked => "blocked",
+            BounceCategory::Spam => "spam",
+        }
+    }
+
+    pub fn policy(&self) -> BouncePolicy {
+        match self {
+            BounceCategory::Hard => BouncePolicy::Suppress,
+            BounceCategory::Soft => BouncePolicy::RetryLater,
+            BounceCategory::Blocked | BounceCategory::Spam => BouncePolicy::FlagForReview,
+        }
+    }
+
+    /// The category of a bounce event, from its `Type` - or, for the types we do not know,
+    /// from the SMTP enhanced status code in its `Details`. Bounces we cannot make sense of
+    /// are soft: retrying them a few times is harmless.
+    pub fn classify(payload: &serde_json::Value) -> Self {
+        let field = |name: &str| payload.get(name).and_then(|v| v.as_str()).unwrap_or("");
+        match field("Type") {
+            "HardBounce" | "BadEmailAddress" | "ManuallyDeactivated" => BounceCategory::Hard,
+            "SoftBounce" | "Transient" | "DnsError" | "InboxFull" | "AutoResponder" => {
+                BounceCategory::Soft
+            }
+            "Blocked" | "ChallengeVerification" => BounceCategory::Blocked,
+            "SpamNotification" | "SpamComplaint" | "VirusNotification" => BounceCategory::Spam,
+            _ => Self::from_smtp_status(field("Details")).unwrap_or(BounceCategory::Soft),
+        }
+    }
+
+    /// `5.7.x` codes are refusals on policy grounds, other `5.x.x` codes are permanent
+    /// failures and `4.x.x` codes transient ones (RFC 3463).
+    fn from_smtp_status(details: &str) -> Option<Self> {
+        let is_number =
+            |p: &&str| p.len() <= 3 && !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit());
+        details.split_whitespace().find_map(|word| {
+            let code: Vec<&str> = word
+                .trim_end_matches(|c: char| !c.is_ascii_digit())
+                .split('.')
+                .collect();
+            if code.len() != 3 || !code.iter().all(is_number) {
+                return None;
+            }
+            match (code[0], code[1]) {
+                ("5", "7") => Some(BounceCategory::Blocked),
+                ("5", _) => Some(BounceCategory::Hard),
+                ("4", _) => Some(BounceCategory::Soft),
+                _ => None,
+            }
+        })
+    }
+}
+
+/// Apply the policy of a bounce of `category` for `recipient`, sent with `issue_id` as tag.
+/// Flagged bounces need nothing more than being stored.
+/// `blind_index` is that of `recipient`, to match sealed subscribers - see `crate::pii`.
+#[tracing::instrument(
+    name = "Apply the bounce policy",
+    skip(transaction, recipient, blind_index)
+)]
+pub async fn apply_bounce_policy(
+    transaction: &mut Transaction<'_, Postgres>,
+    category: BounceCategory,
+    recipient: &str,
+    blind_index: Option<&[u8]>,
+    issue_id: Option<Uuid>,
+) -> Result<(), anyhow::Error> {
+    match category.policy() {
+        BouncePolicy::Suppress => {
+            suppress_recipient(
+                transaction,
+                recipient,
+                blind_index,
+                BOUNCED_UNSUBSCRIBE_REASON,
+            )
+            .await
+        }
+        BouncePolicy::RetryLater => match issue_id {
+            Some(issue_id) => retry_delivery(transaction, recipient, blind_index, issue_id).await,
+            None => Ok(()),
+        },
+        BouncePolicy::FlagForReview => Ok(()),
+    }
+}
+
+/// Whether `email` bounced or complained as a subscriber since anonymized, see
+/// `crate::retention`.
+pub(crate) async fn is_suppressed(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    cipher: Option<&PiiCipher>,
+) -> Result<bool, anyhow::Error> {
+    let suppressed = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM suppressed_addresses
+            WHERE email_hash IN (sha256(convert_to(lower($1), 'UTF8')), $2)
+        ) AS "suppressed!"
+        "#,
+        email,
+        cipher.map(|c| c.blind_index(email))
+    )
+    .fetch_one(transaction)
+    .await
+    .context("Failed to look for a suppressed address")?;
+    Ok(suppressed.suppressed)
+}
+
+/// Unsubscribe `recipient` from the newsletters of every tenant, with `reason` as the
+/// unsubscribe reason: an address which bounces or complains does so for all of them.
+#[tracing::instrument(skip(transaction, recipient, blind_index))]
+pub(crate) async fn suppress_recipient(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipient: &str,
+    blind_index: Option<&[u8]>,
+    reason: &str,
+) -> Result<(), anyhow::Error> {
+    let unsubscribed = sqlx::query!(
+        r#"
+        WITH unsubscribed AS (
+            UPDATE subscriptions
+            SET status = 'unsubscribed', unsubscribed_at = now(), unsubscribe_reason = $2
+            WHERE
+                (lower(email) = lower($1) OR email_blind_index = $3) AND
+                status <> 'unsubscribed'
+            RETURNING id, email, tenant_id
+        ), dequeued AS (
+            -- An issue still being delivered does not reach them either.
+            DELETE FROM issue_delivery_queue q
+            USING unsubscribed u
+            WHERE q.subscriber_email = u.email AND q.tenant_id = u.tenant_id
+        )
+        SELECT id, email, tenant_id FROM unsubscribed
+        "#,
+        recipient,
+        reason,
+        blind_index
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to suppress an address")?;
+    for r in unsubscribed {
+        let event = DomainEvent::SubscriberUnsubscribed {
+            subscriber_id: r.id,
+            email: r.email,
+            reason: Some(reason.into()),
+        };
+        record_event(transaction, TenantId::from(r.tenant_id), &event).await?;
+    }
+    Ok(())
+}
+
+/// The bounce being handled is already stored, so it counts towards the retries.
+#[tracing::instrument(skip_all)]
+async fn retry_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipient: &str,
+    blind_index: Option<&[u8]>,
+    issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id, subscriber_email, tenant_id, deliver_after
+        )
+        SELECT i.newsletter_issue_id, s.email, i.tenant_id, now() + make_interval(mins => $3)
+        FROM newsletter_issues i
+        JOIN subscriptions s
+            ON s.tenant_id = i.tenant_id AND
+            (lower(s.email) = lower($2) OR s.email_blind_index = $5)
+        WHERE
+            i.newsletter_issue_id = $1 AND
+            s.status = 'confirmed' AND
+            s.deleted_at IS NULL AND
+            (
+                SELECT COUNT(*) FROM email_provider_events e
+                WHERE
+                    e.bounce_category = 'soft' AND
+                    lower(e.recipient) = lower($2) AND
+                    e.payload->>'Tag' = i.newsletter_issue_id::text
+            ) <= $4
+        ON CONFLICT DO NOTHING
+        "#,
+        issue_id,
+        recipient,
+        SOFT_BOUNCE_RETRY_DELAY_MINUTES,
+        MAX_SOFT_BOUNCE_RETRIES,
+        blind_index
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to queue a soft bounced delivery again")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BounceCategory, BouncePolicy};
+    use claim::assert_err;
+    use serde_json::json;
+
+    #[test]
+    fn categories_round_trip_through_their_stored_form() {
+        for category in BounceCategory::ALL {
+            assert_eq!(BounceCategory::parse(category.as_str()), Ok(category));
+        }
+        assert_err!(BounceCategory::parse("bounced"));
+    }
+
+    #[test]
+    fn bounces_are_classified_by_their_type() {
+        let classify = |t: &str| BounceCategory::classify(&json!({ "Type": t }));
+
+        assert_eq!(classify("HardBounce"), BounceCategory::Hard);
+        assert_eq!(classify("InboxFull"), BounceCategory::Soft);
+        assert_eq!(classify("Blocked"), BounceCategory::Blocked);
+        assert_eq!(classify("SpamNotification"), BounceCategory::Spam);
+    }
+
+    #[test]
+    fn unknown_types_fall_back_on_the_smtp_status_code() {
+        let classify = |details: &str| {
+            BounceCategory::classify(&json!({ "Type": "Unknown", "Details": details }))
+        };
+
+        assert_eq!(
+            classify("smtp;550 5.1.1 user unknown"),
+            BounceCategory::Hard
+        );
+        assert_eq!(
+            classify("554 5.7.1 Message rejected."),
+            BounceCategory::Blocked
+        );
+        assert_eq!(classify("452 4.2.2 Mailbox full"), BounceCategory::Soft);
+        assert_eq!(classify("Something went wrong"), BounceCategory::Soft);
+        assert_eq!(BounceCategory::classify(&json!({})), BounceCategory::Soft);
+    }
+
+    #[test]
+    fn only_blocked_and_spam_bounces_are_flagged_for_review() {
+        assert_eq!(BounceCategory::Hard.policy(), BouncePolicy::Suppress);
+        assert_eq!(BounceCategory::Soft.policy(), BouncePolicy::RetryLater);
+        assert_eq!(
+            BounceCategory::Blocked.policy(),
+            BouncePolicy::FlagForReview
+        );
+        assert_eq!(BounceCategory::Spam.policy(), BouncePolicy::FlagForReview);
+    }
+}
@@ -0,0 +1,172 @@
+//! The setup of a newsletter as a JSON bundle, to promote it from one instance to
+//! another - e.g. from staging to production - instead of redoing it by hand.
+//!
+//! A bundle holds settings only: never subscribers, issues or credentials. The hostname
+//! of the tenant is left out too, since each instance serves the newsletter on its own.
+use crate::configuration::validate_base_url;
+use crate::domain::SubscriberEmail;
+use crate::tenancy::TenantId;
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Formatter;
+
+/// Bumped whenever a bundle changes in a way older instances cannot import.
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SetupBundle {
+    pub format_version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub settings: NewsletterSettingsBundle,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct NewsletterSettingsBundle {
+    pub name: String,
+    /// The configured sender address is used if `None`.
+    pub sender_email: Option<String>,
+    /// The configured public base URL is used if `None`.
+    pub public_base_url: Option<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum BundleError {
+    #[error("The bundle is in format version {0}, but only version {BUNDLE_FORMAT_VERSION} can be imported.")]
+    UnsupportedVersion(u32),
+    #[error("The bundle is invalid: {0}")]
+    Invalid(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for BundleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl SetupBundle {
+    pub fn parse(json: &str) -> Result<Self, BundleError> {
+        // Checked first, for bundles whose other fields changed shape.
+        let version: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| BundleError::Invalid(e.to_string()))?;
+        match version["format_version"].as_u64() {
+            Some(v) if v == u64::from(BUNDLE_FORMAT_VERSION) => {}
+            Some(v) => return Err(BundleError::UnsupportedVersion(v as u32)),
+            None => return Err(BundleError::Invalid("format_version is missing.".into())),
+        }
+        serde_json::from_value(version).map_err(|e| BundleError::Invalid(e.to_string()))
+    }
+
+    fn validate(&self) -> Result<(), BundleError> {
+        let settings = &self.settings;
+        if settings.name.trim().is_empty() {
+            return Err(BundleError::Invalid("the name is empty.".into()));
+        }
+        if let Some(sender_email) = &settings.sender_email {
+            SubscriberEmail::parse(sender_email.clone()).map_err(BundleError::Invalid)?;
+        }
+        if let Some(public_base_url) = &settings.public_base_url {
+            validate_base_url(public_base_url)
+                .map_err(|e| BundleError::Invalid(format!("the public base URL {}.", e)))?;
+        }
+        Ok(())
+    }
+}
+
+#[tracing::instrument(name = "Export the setup of a newsletter", skip(pool))]
+pub async fn export_bundle(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<SetupBundle, anyhow::Error> {
+    let tenant = sqlx::query!(
+        "SELECT name, sender_email, public_base_url FROM tenants WHERE tenant_id = $1",
+        *tenant_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the settings of the tenant")?;
+    Ok(SetupBundle {
+        format_version: BUNDLE_FORMAT_VERSION,
+        exported_at: Utc::now(),
+        settings: NewsletterSettingsBundle {
+            name: tenant.name,
+            sender_email: tenant.sender_email,
+            public_base_url: tenant.public_base_url,
+        },
+    })
+}
+
+/// Replace the setup of the tenant's newsletter with the one in the bundle.
+#[tracing::instrument(name = "Import the setup of a newsletter", skip(pool, bundle))]
+pub async fn import_bundle(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    bundle: &SetupBundle,
+) -> Result<(), BundleError> {
+    bundle.validate()?;
+    let settings = &bundle.settings;
+    sqlx::query!(
+        r#"
+        UPDATE tenants
+        SET name = $2, sender_email = $3, public_base_url = $4
+        WHERE tenant_id = $1
+        "#,
+        *tenant_id,
+        settings.name.trim(),
+        settings.sender_email,
+        settings.public_base_url
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(e) if e.constraint() == Some("tenants_public_hostname_key") => {
+            BundleError::Invalid(
+                "another newsletter of this instance is served on the public base URL.".into(),
+            )
+        }
+        e => BundleError::UnexpectedError(
+            anyhow::Error::new(e).context("Failed to store the settings of the tenant"),
+        ),
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BundleError, SetupBundle};
+
+    #[test]
+    fn bundles_of_another_format_version_are_rejected() {
+        let bundle = r#"{"format_version": 2, "settings": {"lists": []}}"#;
+        assert!(matches!(
+            SetupBundle::parse(bundle),
+            Err(BundleError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn bundles_without_a_format_version_are_rejected() {
+        for bundle in ["{}", "not json"] {
+            assert!(matches!(
+                SetupBundle::parse(bundle),
+                Err(BundleError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let bundle = SetupBundle::parse(
+            r#"{
+                "format_version": 1,
+                "exported_at": "2022-05-01T00:00:00Z",
+                "settings": {"name": "News", "sender_email": "not-an-email", "public_base_url": null}
+            }"#,
+        )
+        .unwrap();
+        assert!(matches!(bundle.validate(), Err(BundleError::Invalid(_))));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::configuration::CacheSettings;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Rendered bodies of the public, read-heavy pages - the archive, its API and the
+/// sitemap - kept in memory so that traffic spikes do not run the same queries over
+/// and over.
+/// Entries expire after the configured TTL, and are all dropped as soon as an issue is
+/// published.
+#[derive(Clone)]
+pub struct ResponseCache {
+    entries: Arc<Mutex<HashMap<String, CachedBody>>>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+struct CachedBody {
+    expires_at: Instant,
+    body: String,
+}
+
+impl ResponseCache {
+    pub fn new(settings: &CacheSettings) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            ttl: settings.ttl(),
+            max_entries: settings.max_entries,
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| entry.body.clone())
+    }
+
+    /// A zero TTL or capacity disables caching.
+    pub fn insert(&self, key: String, body: String) {
+        if self.ttl.is_zero() || self.max_entries == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.expires_at > now);
+            // Still full: start over rather than tracking which entry is the oldest.
+            if entries.len() >= self.max_entries {
+                entries.clear();
+            }
+        }
+        entries.insert(
+            key,
+            CachedBody {
+                expires_at: now + self.ttl,
+                body,
+            },
+        );
+    }
+
+    /// The cached body for `key`, rendering and caching it on a miss.
+    pub async fn get_or_try_insert_with<F, Fut, E>(
+        &self,
+        key: String,
+        render: F,
+    ) -> Result<String, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String, E>>,
+    {
+        if let Some(body) = self.get(&key) {
+            return Ok(body);
+        }
+        let body = render().await?;
+        self.insert(key, body.clone());
+        Ok(body)
+    }
+
+    pub fn invalidate_all(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResponseCache;
+    use crate::configuration::CacheSettings;
+    use claim::{assert_none, assert_some_eq};
+
+    fn cache(ttl_seconds: u64, max_entries: usize) -> ResponseCache {
+        ResponseCache::new(&CacheSettings {
+            ttl_seconds,
+            max_entries,
+        })
+    }
+
+    #[test]
+    fn a_cached_body_is_returned_until_invalidated() {
+        let cache = cache(60, 10);
+        cache.insert("archive".into(), "body".into());
+
+        assert_some_eq!(cache.get("archive"), "body");
+        cache.invalidate_all();
+        assert_none!(cache.get("archive"));
+    }
+
+    #[test]
+    fn a_zero_ttl_disables_caching() {
+        let cache = cache(0, 10);
+        cache.insert("archive".into(), "body".into());
+
+        assert_none!(cache.get("archive"));
+    }
+
+    #[test]
+    fn the_cache_does_not_grow_past_its_capacity() {
+        let cache = cache(60, 2);
+        cache.insert("a".into(), "a".into());
+        cache.insert("b".into(), "b".into());
+        cache.insert("c".into(), "c".into());
+
+        assert_none!(cache.get("a"));
+        assert_some_eq!(cache.get("c"), "c");
+        assert!(cache.entries.lock().unwrap().len() <= 2);
+    }
+
+    #[tokio::test]
+    async fn bodies_are_only_rendered_on_a_miss() {
+        let cache = cache(60, 10);
+
+        let first: Result<_, ()> = cache
+            .get_or_try_insert_with("archive".into(), || async { Ok("first".to_string()) })
+            .await;
+        let second: Result<_, ()> = cache
+            .get_or_try_insert_with("archive".into(), || async { Ok("second".to_string()) })
+            .await;
+
+        assert_eq!(first.unwrap(), "first");
+        assert_eq!(second.unwrap(), "first");
+    }
+}
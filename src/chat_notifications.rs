@@ -0,0 +1,308 @@
+//! Operational messages posted to a Slack or Discord channel through an incoming webhook.
+//!
+//! The `post_chat_notifications` scheduled job goes through the recorded domain events
+//! (see `crate::events`) and posts the ones the team wants to hear about: an issue was
+//! published, finished sending - with its stats - or was paused for crossing the
+//! complaint threshold. It also raises an alert when failed deliveries spike, at most
+//! once per `chat_notifications.error_spike_window_minutes`.
+//!
+//! Each tenant's events go to its own channel, `tenants.chat_webhook_url`, and the default
+//! tenant's to the configured webhook: the events of the tenants without a channel are not
+//! posted. The alerts are about the whole deployment, and go to the configured webhook.
+//!
+//! Events older than an hour when the job gets to them are not posted: there is no point
+//! in flooding the channel after an outage, or when the notifications are turned on.
+use crate::configuration::{ChatNotificationSettings, ChatService};
+use crate::events::DomainEvent;
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Events are posted in batches of this size.
+const POST_BATCH_SIZE: i64 = 50;
+/// The domain events worth a message.
+const POSTED_EVENT_TYPES: [&str; 3] = ["issue_published", "delivery_completed", "delivery_paused"];
+/// Claims older than this were left by a run which did not finish: their events are
+/// claimed again.
+const CLAIM_TIMEOUT_MINUTES: i32 = 5;
+
+pub struct ChatNotifier {
+    http_client: reqwest::Client,
+    service: ChatService,
+    webhook_url: String,
+}
+
+impl ChatNotifier {
+    pub fn new(service: ChatService, webhook_url: String) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        Self {
+            http_client,
+            service,
+            webhook_url,
+        }
+    }
+
+    /// Post to the configured webhook.
+    pub async fn post(&self, text: &str) -> Result<(), anyhow::Error> {
+        self.post_to(&self.webhook_url, text).await
+    }
+
+    #[tracing::instrument(name = "Post a chat notification", skip(self, webhook_url))]
+    async fn post_to(&self, webhook_url: &str, text: &str) -> Result<(), anyhow::Error> {
+        self.http_client
+            .post(webhook_url)
+            .json(&payload(self.service, text))
+            .send()
+            .await
+            .context("Failed to reach the chat webhook")?
+            .error_for_status()
+            .context("The chat webhook rejected the message")?;
+        Ok(())
+    }
+}
+
+/// Slack and Discord only disagree on the name of the field.
+fn payload(service: ChatService, text: &str) -> serde_json::Value {
+    match service {
+        ChatService::Discord => serde_json::json!({ "content": text }),
+        ChatService::Slack | ChatService::None => serde_json::json!({ "text": text }),
+    }
+}
+
+/// The message of an event, `None` for the events which are not posted. `title` is the
+/// one of the issue the event is about.
+fn message_for(event: &DomainEvent, title: Option<&str>) -> Option<String> {
+    let title = title.unwrap_or("An issue");
+    match event {
+        DomainEvent::IssuePublished { title, .. } => Some(format!("\"{}\" was published.", title)),
+        DomainEvent::DeliveryCompleted {
+            delivered,
+            failed,
+            skipped,
+            ..
+        } => {
+            let total = delivered + failed + skipped;
+            let rate = if total > 0 {
+                *delivered as f64 * 100.0 / total as f64
+            } else {
+                100.0
+            };
+            Some(format!(
+                "\"{}\" finished sending: {} delivered ({:.1}%), {} failed, {} skipped.",
+                title, delivered, rate, failed, skipped
+            ))
+        }
+        DomainEvent::DeliveryPaused { .. } => Some(format!(
+            "{}. Resume it from the issue page once you have looked into it.",
+            event.summary()
+        )),
+        _ => None,
+    }
+}
+
+/// Post the recent events not posted yet, then alert about a spike of failed deliveries.
+/// Returns how many messages were posted.
+#[tracing::instrument(name = "Post chat notifications", skip_all)]
+pub async fn post_chat_notifications(
+    pool: &PgPool,
+    notifier: &ChatNotifier,
+    settings: &ChatNotificationSettings,
+) -> Result<u64, anyhow::Error> {
+    let mut posted = post_events(pool, notifier).await?;
+    if post_error_spike_alert(pool, notifier, settings).await? {
+        posted += 1;
+    }
+    Ok(posted)
+}
+
+/// The events are claimed in a transaction of their own, then posted without holding it
+/// open. Each event is marked as posted once the webhook accepted it: after a failure,
+/// the claims of the events left are released, and the next run picks up where this one
+/// stopped.
+async fn post_events(pool: &PgPool, notifier: &ChatNotifier) -> Result<u64, anyhow::Error> {
+    let event_types: Vec<String> = POSTED_EVENT_TYPES.iter().map(|t| t.to_string()).collect();
+    let events = sqlx::query!(
+        r#"
+        WITH claimed AS (
+            UPDATE events
+            SET chat_claimed_at = now()
+            WHERE event_id IN (
+                SELECT e.event_id
+                FROM events e
+                JOIN tenants t ON t.tenant_id = e.tenant_id
+                WHERE
+                    e.chat_posted_at IS NULL AND
+                    (
+                        e.chat_claimed_at IS NULL OR
+                        e.chat_claimed_at < now() - make_interval(mins => $3)
+                    ) AND
+                    e.event_type = ANY($1) AND
+                    e.occurred_at > now() - interval '1 hour' AND
+                    (t.chat_webhook_url IS NOT NULL OR t.tenant_id = $4)
+                ORDER BY e.occurred_at
+                LIMIT $2
+                FOR UPDATE OF e SKIP LOCKED
+            )
+            RETURNING event_id, tenant_id, payload, occurred_at
+        )
+        SELECT
+            c.event_id AS "event_id!",
+            c.payload AS "payload!",
+            i.title AS "title?",
+            t.chat_webhook_url AS "chat_webhook_url?"
+        FROM claimed c
+        JOIN tenants t ON t.tenant_id = c.tenant_id
+        LEFT JOIN newsletter_issues i
+            ON i.newsletter_issue_id = (c.payload->>'newsletter_issue_id')::uuid
+        ORDER BY c.occurred_at
+        "#,
+        &event_types,
+        POST_BATCH_SIZE,
+        CLAIM_TIMEOUT_MINUTES,
+        *TenantId::DEFAULT
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to claim the events to post")?;
+    let mut posted = 0;
+    for (i, e) in events.iter().enumerate() {
+        let event: DomainEvent = serde_json::from_value(e.payload.clone())
+            .context("Failed to parse a recorded event")?;
+        if let Some(message) = message_for(&event, e.title.as_deref()) {
+            // Only the default tenant can be without a webhook of its own here.
+            let webhook_url = e
+                .chat_webhook_url
+                .as_deref()
+                .unwrap_or(&notifier.webhook_url);
+            if let Err(error) = notifier.post_to(webhook_url, &message).await {
+                let left: Vec<Uuid> = events[i..].iter().map(|e| e.event_id).collect();
+                release_claims(pool, &left).await?;
+                return Err(error);
+            }
+            posted += 1;
+        }
+        sqlx::query!(
+            "UPDATE events SET chat_posted_at = now() WHERE event_id = $1",
+            e.event_id
+        )
+        .execute(pool)
+        .await
+        .context("Failed to mark an event as posted")?;
+    }
+    Ok(posted)
+}
+
+async fn release_claims(pool: &PgPool, event_ids: &[Uuid]) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "UPDATE events SET chat_claimed_at = NULL WHERE event_id = ANY($1)",
+        event_ids
+    )
+    .execute(pool)
+    .await
+    .context("Failed to release the claims on the events left to post")?;
+    Ok(())
+}
+
+/// Returns whether an alert was posted.
+async fn post_error_spike_alert(
+    pool: &PgPool,
+    notifier: &ChatNotifier,
+    settings: &ChatNotificationSettings,
+) -> Result<bool, anyhow::Error> {
+    let window = settings.error_spike_window_minutes as i32;
+    let failures = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "failures!"
+        FROM events
+        WHERE
+            event_type = 'delivery_failed' AND
+            occurred_at > now() - make_interval(mins => $1)
+        "#,
+        window
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the recent failed deliveries")?
+    .failures as u64;
+    if failures < settings.error_spike_threshold {
+        return Ok(false);
+    }
+    // Only one alert per window: the row is only updated once the previous one is older.
+    let due = sqlx::query!(
+        r#"
+        INSERT INTO chat_alerts (alert, posted_at)
+        VALUES ('delivery_failures_spike', now())
+        ON CONFLICT (alert) DO UPDATE
+        SET posted_at = now()
+        WHERE chat_alerts.posted_at < now() - make_interval(mins => $1)
+        RETURNING alert
+        "#,
+        window
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to record a chat alert")?;
+    if due.is_none() {
+        return Ok(false);
+    }
+    let posted = notifier
+        .post(&format!(
+            "{} deliveries failed in the last {} minutes: check the delivery reports and the \
+            email provider.",
+            failures, settings.error_spike_window_minutes
+        ))
+        .await;
+    if let Err(error) = posted {
+        // For the next run to try again.
+        sqlx::query!("DELETE FROM chat_alerts WHERE alert = 'delivery_failures_spike'")
+            .execute(pool)
+            .await
+            .context("Failed to forget a chat alert")?;
+        return Err(error);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{message_for, payload};
+    use crate::configuration::ChatService;
+    use crate::events::DomainEvent;
+    use uuid::Uuid;
+
+    #[test]
+    fn slack_and_discord_get_the_text_in_their_own_field() {
+        assert_eq!(payload(ChatService::Slack, "Hi")["text"], "Hi");
+        assert_eq!(payload(ChatService::Discord, "Hi")["content"], "Hi");
+    }
+
+    #[test]
+    fn completed_sends_are_posted_with_their_stats() {
+        let event = DomainEvent::DeliveryCompleted {
+            newsletter_issue_id: Uuid::new_v4(),
+            delivered: 3,
+            failed: 1,
+            skipped: 0,
+        };
+
+        assert_eq!(
+            message_for(&event, Some("Weekly")).unwrap(),
+            "\"Weekly\" finished sending: 3 delivered (75.0%), 1 failed, 0 skipped."
+        );
+    }
+
+    #[test]
+    fn only_key_events_are_posted() {
+        let event = DomainEvent::SubscriberConfirmed {
+            subscriber_id: Uuid::new_v4(),
+            email: "ursula@example.com".into(),
+        };
+
+        assert_eq!(message_for(&event, None), None);
+    }
+}
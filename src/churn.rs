@@ -0,0 +1,122 @@
+//! Who unsubscribes and why, from the answers to the survey of the unsubscribe page.
+use crate::domain::UnsubscribeReason;
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+const MONTHS: i32 = 6;
+
+pub struct ReasonCount {
+    /// `None` for the subscribers who skipped the survey.
+    pub reason: Option<UnsubscribeReason>,
+    pub last_30_days: i64,
+    pub all_time: i64,
+}
+
+impl ReasonCount {
+    pub fn label(&self) -> &'static str {
+        match self.reason {
+            Some(UnsubscribeReason::TooFrequent) => "Too frequent",
+            Some(UnsubscribeReason::NotRelevant) => "Not relevant",
+            Some(UnsubscribeReason::Other) => "Other",
+            None => "No answer",
+        }
+    }
+}
+
+pub struct MonthlyChurn {
+    pub month: DateTime<Utc>,
+    pub unsubscribed: i64,
+}
+
+pub struct ChurnReport {
+    /// Every reason, answered or not, in the order of the survey.
+    pub reasons: Vec<ReasonCount>,
+    /// The last months, oldest first.
+    pub months: Vec<MonthlyChurn>,
+}
+
+impl ChurnReport {
+    pub fn total_last_30_days(&self) -> i64 {
+        self.reasons.iter().map(|r| r.last_30_days).sum()
+    }
+
+    pub fn total(&self) -> i64 {
+        self.reasons.iter().map(|r| r.all_time).sum()
+    }
+}
+
+/// Subscribers who came back are not counted.
+#[tracing::instrument(name = "Compute the churn report", skip(pool))]
+pub async fn get_churn_report(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<ChurnReport, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            unsubscribe_reason,
+            COUNT(*) FILTER (WHERE unsubscribed_at >= now() - interval '30 days')
+                AS "last_30_days!",
+            COUNT(*) AS "all_time!"
+        FROM subscriptions
+        WHERE status = 'unsubscribed' AND deleted_at IS NULL AND tenant_id = $1
+        GROUP BY unsubscribe_reason
+        "#,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to count the unsubscribes by reason")?;
+    let mut reasons: Vec<ReasonCount> = UnsubscribeReason::ALL
+        .into_iter()
+        .map(Some)
+        .chain([None])
+        .map(|reason| ReasonCount {
+            reason,
+            last_30_days: 0,
+            all_time: 0,
+        })
+        .collect();
+    for row in rows {
+        // Reasons we no longer offer count as unanswered.
+        let reason = row
+            .unsubscribe_reason
+            .and_then(|r| UnsubscribeReason::parse(&r).ok());
+        let count = reasons.iter_mut().find(|c| c.reason == reason).unwrap();
+        count.last_30_days += row.last_30_days;
+        count.all_time += row.all_time;
+    }
+
+    let months = sqlx::query!(
+        r#"
+        SELECT m.month AS "month!", COUNT(s.id) AS "unsubscribed!"
+        FROM generate_series(
+            date_trunc('month', now()) - make_interval(months => $1 - 1),
+            date_trunc('month', now()),
+            interval '1 month'
+        ) AS m(month)
+        LEFT JOIN subscriptions s
+            ON s.status = 'unsubscribed'
+            AND s.deleted_at IS NULL
+            AND s.tenant_id = $2
+            AND s.unsubscribed_at >= m.month
+            AND s.unsubscribed_at < m.month + interval '1 month'
+        GROUP BY m.month
+        ORDER BY m.month
+        "#,
+        MONTHS,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to count the unsubscribes by month")?
+    .into_iter()
+    .map(|r| MonthlyChurn {
+        month: r.month,
+        unsubscribed: r.unsubscribed,
+    })
+    .collect();
+    Ok(ChurnReport { reasons, months })
+}
@@ -0,0 +1,109 @@
+//! Stop calling the email provider for a while once it keeps failing, rather than
+//! making every signup wait for its timeout.
+//!
+//! The breaker opens after `failure_threshold` consecutive failures and lets a call
+//! through again once `cooldown` has passed: a success closes it, a failure opens it for
+//! another `cooldown`. Its state is kept in memory, per application instance.
+use crate::configuration::EmailClientSettings;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// A `failure_threshold` of 0 never opens the breaker.
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold,
+            cooldown,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn from_settings(settings: &EmailClientSettings) -> Self {
+        Self::new(
+            settings.circuit_breaker_failure_threshold,
+            settings.circuit_breaker_cooldown(),
+        )
+    }
+
+    /// Whether calls must not be attempted for now.
+    pub fn is_open(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.open_until.is_some_and(|t| Instant::now() < t)
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = State::default();
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if self.failure_threshold > 0 && state.consecutive_failures >= self.failure_threshold {
+            tracing::warn!(
+                consecutive_failures = state.consecutive_failures,
+                "Opening the circuit breaker of the email provider"
+            );
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CircuitBreaker;
+    use std::time::Duration;
+
+    #[test]
+    fn the_breaker_opens_after_enough_consecutive_failures() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+        breaker.record_failure();
+
+        assert!(breaker.is_open());
+    }
+
+    #[test]
+    fn a_success_resets_the_failure_count() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+
+        assert!(!breaker.is_open());
+    }
+
+    #[test]
+    fn the_breaker_lets_calls_through_after_the_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+
+        breaker.record_failure();
+
+        assert!(!breaker.is_open());
+    }
+
+    #[test]
+    fn a_zero_threshold_never_opens_the_breaker() {
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(60));
+
+        for _ in 0..10 {
+            breaker.record_failure();
+        }
+
+        assert!(!breaker.is_open());
+    }
+}
@@ -0,0 +1,194 @@
+//! Spam complaints, relayed by the email provider from the feedback loops of mailbox
+//! providers.
+//!
+//! The subscriber who complained is unsubscribed at once, and the complaint is recorded
+//! against the issue it was about. If the complaint rate of an issue still being
+//! delivered crosses `newsletters.complaint_rate_threshold`, its deliveries are paused
+//! and a `DeliveryPaused` event - and notification - alerts the admins, who resume them
+//! from the issue page.
+//!
+//! The admins are notified of the first complaint, then of at most one complaint per
+//! `COMPLAINT_NOTIFICATION_WINDOW`: a spike of complaints does not flood their inbox.
+use crate::bounces::suppress_recipient;
+use crate::configuration::NewsletterSettings;
+use crate::events::{record_event, DomainEvent};
+use crate::notifications::{
+    notify_admins, notify_admins_at_most_every, AdminEvent, AdminNotification,
+};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use sqlx::{Postgres, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Stored as the unsubscribe reason of the subscribers who complained.
+pub const COMPLAINED_UNSUBSCRIBE_REASON: &str = "complained";
+
+/// The admins are notified of at most one complaint per window.
+pub const COMPLAINT_NOTIFICATION_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+#[tracing::instrument(
+    name = "Handle a spam complaint",
+    skip(transaction, settings, recipient, blind_index)
+)]
+pub async fn handle_complaint(
+    transaction: &mut Transaction<'_, Postgres>,
+    settings: &NewsletterSettings,
+    recipient: &str,
+    blind_index: Option<&[u8]>,
+    issue_id: Option<Uuid>,
+) -> Result<(), anyhow::Error> {
+    suppress_recipient(
+        transaction,
+        recipient,
+        blind_index,
+        COMPLAINED_UNSUBSCRIBE_REASON,
+    )
+    .await?;
+    let issue_id = match issue_id {
+        Some(issue_id) => issue_id,
+        None => return Ok(()),
+    };
+    record_complaint(transaction, recipient, blind_index, issue_id).await?;
+    if let Some(threshold) = settings.complaint_rate_threshold() {
+        pause_if_over_threshold(
+            transaction,
+            issue_id,
+            threshold,
+            settings.complaint_rate_min_deliveries,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Complaints about issues we do not know are left out, as are repeated ones: the admins
+/// are only notified of the others, once per `COMPLAINT_NOTIFICATION_WINDOW` at most. The complaint of a sealed subscriber is recorded
+/// against their pseudonym, as their deliveries are.
+#[tracing::instrument(skip(transaction, recipient, blind_index))]
+async fn record_complaint(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipient: &str,
+    blind_index: Option<&[u8]>,
+    issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let recorded = sqlx::query!(
+        r#"
+        WITH recorded AS (
+            INSERT INTO issue_complaints (newsletter_issue_id, subscriber_email, received_at)
+            SELECT
+                i.newsletter_issue_id,
+                COALESCE(
+                    (
+                        SELECT s.email FROM subscriptions s
+                        WHERE s.tenant_id = i.tenant_id AND s.email_blind_index = $3
+                        LIMIT 1
+                    ),
+                    lower($2)
+                ),
+                now()
+            FROM newsletter_issues i
+            WHERE i.newsletter_issue_id = $1
+            ON CONFLICT DO NOTHING
+            RETURNING newsletter_issue_id
+        )
+        SELECT i.tenant_id AS "tenant_id!", i.title AS "title!"
+        FROM recorded r
+        JOIN newsletter_issues i ON i.newsletter_issue_id = r.newsletter_issue_id
+        "#,
+        issue_id,
+        recipient,
+        blind_index
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to record a complaint against its issue")?;
+    if let Some(r) = recorded {
+        let notification = AdminNotification::new(
+            AdminEvent::NewComplaint,
+            format!("A subscriber marked \"{}\" as spam", r.title),
+            format!(
+                "A subscriber marked \"{}\" as spam, and was unsubscribed.\n\
+                You will not be notified of the next complaints for an hour: the issue pages \
+                count them all.",
+                r.title
+            ),
+        );
+        notify_admins_at_most_every(
+            transaction,
+            TenantId::from(r.tenant_id),
+            &notification,
+            COMPLAINT_NOTIFICATION_WINDOW,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Only issues with deliveries still queued are paused: there is nothing left to hold
+/// back for the others.
+#[tracing::instrument(skip(transaction))]
+async fn pause_if_over_threshold(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    threshold: f64,
+    min_deliveries: u64,
+) -> Result<(), anyhow::Error> {
+    let paused = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues i
+        SET paused_at = now()
+        FROM (
+            SELECT
+                (
+                    SELECT COUNT(*) FROM issue_complaints
+                    WHERE newsletter_issue_id = $1
+                ) AS complaints,
+                (
+                    SELECT COUNT(*) FROM issue_deliveries
+                    WHERE newsletter_issue_id = $1 AND outcome = 'delivered'
+                ) AS delivered
+        ) r
+        WHERE
+            i.newsletter_issue_id = $1 AND
+            i.paused_at IS NULL AND
+            EXISTS (SELECT 1 FROM issue_delivery_queue q WHERE q.newsletter_issue_id = $1) AND
+            r.delivered >= $3 AND
+            r.complaints > $2::float8 * r.delivered
+        RETURNING
+            i.tenant_id, i.title, r.complaints AS "complaints!", r.delivered AS "delivered!"
+        "#,
+        issue_id,
+        threshold,
+        min_deliveries as i64
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to pause the deliveries of an issue")?;
+    if let Some(r) = paused {
+        tracing::warn!(
+            complaints = r.complaints,
+            delivered = r.delivered,
+            "Too many spam complaints: the deliveries of the issue are paused"
+        );
+        let tenant_id = TenantId::from(r.tenant_id);
+        let notification = AdminNotification::new(
+            AdminEvent::DeliveryPaused,
+            format!("The delivery of \"{}\" is paused", r.title),
+            format!(
+                "\"{}\" got {} spam complaints for {} emails delivered: its delivery is paused.\n\
+                Resume it from the issue page once you have looked into it.",
+                r.title, r.complaints, r.delivered
+            ),
+        );
+        notify_admins(transaction, tenant_id, &notification).await?;
+        let event = DomainEvent::DeliveryPaused {
+            newsletter_issue_id: issue_id,
+            title: r.title,
+            complaints: r.complaints,
+            delivered: r.delivered,
+        };
+        record_event(transaction, tenant_id, &event).await?;
+    }
+    Ok(())
+}
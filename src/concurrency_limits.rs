@@ -0,0 +1,176 @@
+//! Caps on how many heavy requests each process serves at once for a tenant, so that a
+//! burst of them - a script retrying an import, a double-click on publish - cannot
+//! starve the rest of the application of connections and memory. Each tenant has its
+//! own caps: the exports of one do not turn away the requests of the others.
+//!
+//! The requests over the cap are turned away rather than queued: with a 409 for imports
+//! and exports, which are usually capped to one at a time, and with a 429 for publishes.
+//!
+//! Bulk imports of subscribers only enqueue a job: the job worker caps them instead, see
+//! `crate::jobs`.
+use crate::configuration::ConcurrencyLimitSettings;
+use crate::error::AppError;
+use crate::routes::api::ApiError;
+use crate::tenancy::TenantId;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeavyRoute {
+    /// Setup bundles.
+    Import,
+    /// Delivery reports and setup bundles.
+    Export,
+    Publish,
+}
+
+/// As many requests of the kind as allowed are already being served.
+#[derive(Debug)]
+pub struct RouteBusy(HeavyRoute);
+
+impl RouteBusy {
+    fn message(&self) -> &'static str {
+        match self.0 {
+            HeavyRoute::Import => "Another import is running. Try again once it is done.",
+            HeavyRoute::Export => "Another export is running. Try again once it is done.",
+            HeavyRoute::Publish => {
+                "Too many issues are being published at once. Try again in a moment."
+            }
+        }
+    }
+
+    fn is_conflict(&self) -> bool {
+        self.0 != HeavyRoute::Publish
+    }
+}
+
+impl From<RouteBusy> for AppError {
+    fn from(e: RouteBusy) -> Self {
+        if e.is_conflict() {
+            AppError::conflict(e.message()).with_code("route_busy")
+        } else {
+            AppError::too_many_requests(e.message())
+        }
+    }
+}
+
+impl From<RouteBusy> for ApiError {
+    fn from(e: RouteBusy) -> Self {
+        if e.is_conflict() {
+            ApiError::Conflict(e.message().into())
+        } else {
+            ApiError::TooManyRequests(e.message().into())
+        }
+    }
+}
+
+pub struct ConcurrencyLimits {
+    settings: ConcurrencyLimitSettings,
+    /// Created on the first heavy request of each tenant.
+    semaphores: Mutex<HashMap<(TenantId, HeavyRoute), Arc<Semaphore>>>,
+}
+
+impl ConcurrencyLimits {
+    pub fn new(settings: &ConcurrencyLimitSettings) -> Self {
+        Self {
+            settings: settings.clone(),
+            semaphores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The request counts against the cap of its route for `tenant_id` for as long as the
+    /// permit is held - for a streamed response, until the stream is done.
+    pub fn try_acquire(
+        &self,
+        tenant_id: TenantId,
+        route: HeavyRoute,
+    ) -> Result<OwnedSemaphorePermit, RouteBusy> {
+        let cap = match route {
+            HeavyRoute::Import => self.settings.imports,
+            HeavyRoute::Export => self.settings.exports,
+            HeavyRoute::Publish => self.settings.publishes,
+        };
+        let semaphore = self
+            .semaphores
+            .lock()
+            .unwrap()
+            .entry((tenant_id, route))
+            .or_insert_with(|| Arc::new(Semaphore::new(cap)))
+            .clone();
+        semaphore.try_acquire_owned().map_err(|_| RouteBusy(route))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConcurrencyLimits, HeavyRoute};
+    use crate::configuration::ConcurrencyLimitSettings;
+    use crate::error::AppError;
+    use crate::tenancy::TenantId;
+    use actix_web::ResponseError;
+    use claim::assert_err;
+    use uuid::Uuid;
+
+    const TENANT: TenantId = TenantId::DEFAULT;
+
+    fn limits() -> ConcurrencyLimits {
+        ConcurrencyLimits::new(&ConcurrencyLimitSettings {
+            imports: 1,
+            exports: 1,
+            publishes: 2,
+        })
+    }
+
+    #[test]
+    fn requests_over_the_cap_are_turned_away_until_a_permit_is_released() {
+        let limits = limits();
+
+        let first = limits.try_acquire(TENANT, HeavyRoute::Publish).unwrap();
+        let _second = limits.try_acquire(TENANT, HeavyRoute::Publish).unwrap();
+        assert_err!(limits.try_acquire(TENANT, HeavyRoute::Publish));
+
+        drop(first);
+        let _third = limits.try_acquire(TENANT, HeavyRoute::Publish).unwrap();
+    }
+
+    #[test]
+    fn each_kind_of_route_has_its_own_cap() {
+        let limits = limits();
+
+        let _import = limits.try_acquire(TENANT, HeavyRoute::Import).unwrap();
+
+        assert_err!(limits.try_acquire(TENANT, HeavyRoute::Import));
+        let _export = limits.try_acquire(TENANT, HeavyRoute::Export).unwrap();
+    }
+
+    #[test]
+    fn each_tenant_has_its_own_caps() {
+        let limits = limits();
+        let other_tenant = TenantId::from(Uuid::new_v4());
+
+        let _export = limits.try_acquire(TENANT, HeavyRoute::Export).unwrap();
+
+        assert_err!(limits.try_acquire(TENANT, HeavyRoute::Export));
+        let _other_export = limits
+            .try_acquire(other_tenant, HeavyRoute::Export)
+            .unwrap();
+    }
+
+    #[test]
+    fn busy_imports_conflict_while_busy_publishes_are_rate_limited() {
+        let limits = limits();
+        let _import = limits.try_acquire(TENANT, HeavyRoute::Import).unwrap();
+        let _publishes = [
+            limits.try_acquire(TENANT, HeavyRoute::Publish).unwrap(),
+            limits.try_acquire(TENANT, HeavyRoute::Publish).unwrap(),
+        ];
+
+        let import = AppError::from(limits.try_acquire(TENANT, HeavyRoute::Import).unwrap_err());
+        let publish = AppError::from(limits.try_acquire(TENANT, HeavyRoute::Publish).unwrap_err());
+
+        assert_eq!(import.status_code(), 409);
+        assert_eq!(import.code(), "route_busy");
+        assert_eq!(publish.status_code(), 429);
+    }
+}
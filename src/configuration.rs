@@ -1,5 +1,11 @@
+use crate::chat_notifications::ChatNotifier;
 use crate::domain::SubscriberEmail;
 use crate::email_client::EmailClient;
+use crate::email_verification::EmailVerifier;
+use crate::i18n::Locale;
+use crate::pii::PiiCipher;
+use crate::sending_quota::WarmUp;
+use crate::spam_check::SpamChecker;
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
@@ -10,7 +16,29 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub spam_check: SpamCheckSettings,
+    pub deliverability: DeliverabilitySettings,
     pub redis_uri: Secret<String>,
+    pub logging: LoggingSettings,
+    pub webhooks: WebhookSettings,
+    pub events: EventSettings,
+    pub idempotency: IdempotencySettings,
+    pub subscribers: SubscriberSettings,
+    pub newsletters: NewsletterSettings,
+    pub cache: CacheSettings,
+    pub branding: BrandingSettings,
+    pub i18n: I18nSettings,
+    pub scheduler: SchedulerSettings,
+    pub tenancy: TenancySettings,
+    pub signing: SigningSettings,
+    pub re_engagement: ReEngagementSettings,
+    pub email_footer: EmailFooterSettings,
+    pub backups: BackupSettings,
+    pub pii_encryption: PiiEncryptionSettings,
+    pub retention: RetentionSettings,
+    pub chat_notifications: ChatNotificationSettings,
+    pub load_shedding: LoadSheddingSettings,
+    pub concurrency_limits: ConcurrencyLimitSettings,
 }
 #[derive(serde::Deserialize, Clone)]
 pub struct ApplicationSettings {
@@ -18,7 +46,837 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    /// The base URL of the links sent to subscribers - confirmation, unsubscribe and
+    /// archive links - if they are served from another domain than the admin panel.
+    /// Empty to use `base_url`.
+    pub public_base_url: String,
     pub hmac_secret: Secret<String>,
+    pub startup_checks: bool,
+    /// Websites allowed to submit the embeddable subscribe form, e.g. `https://example.com`.
+    pub embed_allowed_origins: Vec<String>,
+    /// Whether search engines may index the public pages, see `/robots.txt`.
+    pub allow_indexing: bool,
+    /// Start in read-only mode, see `crate::read_only`.
+    pub read_only: bool,
+    /// The reverse proxies in front of the application, whose `X-Forwarded-For` header is
+    /// believed. Anyone else could forge it.
+    pub trusted_proxies: Vec<std::net::IpAddr>,
+}
+
+impl ApplicationSettings {
+    pub fn public_base_url(&self) -> &str {
+        Some(self.public_base_url.as_str())
+            .filter(|url| !url.is_empty())
+            .unwrap_or(&self.base_url)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.public_base_url.is_empty() {
+            validate_base_url(&self.public_base_url)
+                .map_err(|e| format!("application.public_base_url {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Links are built by appending a path to a base URL, so it must be an absolute
+/// `http(s)` URL without a query, a fragment or a trailing slash.
+pub fn validate_base_url(base_url: &str) -> Result<(), String> {
+    let url = reqwest::Url::parse(base_url).map_err(|e| format!("is not a valid URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err("must be an absolute http or https URL".into());
+    }
+    if url.query().is_some() || url.fragment().is_some() || base_url.ends_with('/') {
+        return Err("must not have a query, a fragment or a trailing slash".into());
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct LoggingSettings {
+    pub level: String,
+    pub format: LogFormat,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Json,
+    Pretty,
+}
+
+impl LogFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogFormat::Json => "json",
+            LogFormat::Pretty => "pretty",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct WebhookSettings {
+    /// Shared with the senders of inbound webhooks to sign their requests.
+    pub signing_secret: Secret<String>,
+    /// How old a signed request can be before we reject it.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timestamp_tolerance_seconds: u64,
+}
+
+impl WebhookSettings {
+    pub fn timestamp_tolerance(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.timestamp_tolerance_seconds)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct IdempotencySettings {
+    /// How long a saved response is replayed for. Past it, the key can be reused.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub key_ttl_seconds: u64,
+    /// How long a retry waits for the original request to complete before giving up.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub pending_lease_timeout_milliseconds: u64,
+    /// Requests whose response is larger than this fail instead of being saved.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_response_size_bytes: usize,
+}
+
+impl IdempotencySettings {
+    pub fn key_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.key_ttl_seconds)
+    }
+
+    pub fn pending_lease_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.pending_lease_timeout_milliseconds)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.key_ttl_seconds == 0 {
+            return Err("idempotency.key_ttl_seconds must be greater than 0".into());
+        }
+        if self.pending_lease_timeout_milliseconds == 0 {
+            return Err(
+                "idempotency.pending_lease_timeout_milliseconds must be greater than 0".into(),
+            );
+        }
+        if self.pending_lease_timeout() >= self.key_ttl() {
+            return Err(
+                "idempotency.pending_lease_timeout_milliseconds must be shorter than the key TTL"
+                    .into(),
+            );
+        }
+        if self.max_response_size_bytes == 0 {
+            return Err("idempotency.max_response_size_bytes must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SubscriberSettings {
+    /// The longest subscriber name we accept, in graphemes.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_name_length: usize,
+    /// Whether subscribers must give their name. Otherwise the forms do not ask for it,
+    /// though a name sent anyway is kept.
+    pub require_name: bool,
+    /// Whether the hosted subscribe form asks for the email address twice, so that
+    /// typos do not end up on the list.
+    pub confirm_email: bool,
+    /// The most subscribers of the deployment, across tenants: once reached, signups join
+    /// the waitlist instead. `0` for no limit.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_subscribers: u64,
+    /// Subscriptions left unconfirmed for this long are deleted by the scheduler.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub pending_subscription_ttl_days: u64,
+    /// Deleted subscribers can be recovered for this long, then the scheduler purges them.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub deleted_subscriber_retention_days: u64,
+    /// How the addresses of imported lists are verified before they are stored.
+    pub import_verification: EmailVerificationSettings,
+    /// The terms or privacy policy subscribers may have to accept to sign up.
+    pub consent_policy: ConsentPolicySettings,
+}
+
+impl SubscriberSettings {
+    pub fn pending_subscription_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.pending_subscription_ttl_days * 24 * 60 * 60)
+    }
+
+    pub fn deleted_subscriber_retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.deleted_subscriber_retention_days * 24 * 60 * 60)
+    }
+}
+
+/// A checkbox of the signup forms, which subscribers must tick to accept the policy.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ConsentPolicySettings {
+    /// Whether signups without the policy accepted are rejected. Otherwise the forms do
+    /// not show the checkbox.
+    pub required: bool,
+    /// Identifies the policy, e.g. `2022-05-01`: change it along with `text`, so that the
+    /// proof of consent of each subscriber tells which text they accepted.
+    pub version: String,
+    /// The label of the checkbox.
+    pub text: String,
+}
+
+impl ConsentPolicySettings {
+    /// The version `accepted` by a subscriber, provided it is the current one: a form
+    /// rendered before the policy changed does not count.
+    pub fn accepted_version(&self, accepted: Option<&str>) -> Option<&str> {
+        Some(self.version.as_str()).filter(|version| accepted == Some(*version))
+    }
+
+    /// Whether a signup which `accepted` the policy goes through.
+    pub fn is_satisfied_by(&self, accepted: Option<&str>) -> bool {
+        !self.required || self.accepted_version(accepted).is_some()
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct EmailVerificationSettings {
+    pub method: EmailVerificationMethod,
+    /// For `api`: the endpoint addresses are checked against, given as an `email` query
+    /// parameter.
+    pub api_url: String,
+    pub api_key: Secret<String>,
+    /// For `smtp`: the DNS server the mail servers of the addresses are looked up with,
+    /// as `host:port`.
+    pub nameserver: String,
+    /// For `smtp`: the name we greet mail servers with.
+    pub helo_domain: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+}
+
+/// How addresses are verified, if at all - see `crate::email_verification`.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailVerificationMethod {
+    None,
+    Api,
+    Smtp,
+}
+
+impl EmailVerificationSettings {
+    /// `None` if addresses are not verified.
+    pub fn verifier(&self) -> Option<EmailVerifier> {
+        let timeout = std::time::Duration::from_millis(self.timeout_milliseconds);
+        match self.method {
+            EmailVerificationMethod::None => None,
+            EmailVerificationMethod::Api => Some(EmailVerifier::api(
+                self.api_url.clone(),
+                self.api_key.clone(),
+                timeout,
+            )),
+            EmailVerificationMethod::Smtp => Some(EmailVerifier::smtp(
+                self.nameserver.clone(),
+                self.helo_domain.clone(),
+                timeout,
+            )),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct NewsletterSettings {
+    /// How long after publishing an issue can still be cancelled, before its deliveries
+    /// start. `0` sends right away.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub undo_window_seconds: u64,
+    /// The most emails sent in an hour, across tenants - match it to the email provider's
+    /// plan. `0` for no limit.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_emails_per_hour: u64,
+    /// The most emails sent in a day: issues that would go over it cannot be published.
+    /// `0` for no limit.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_emails_per_day: u64,
+    /// How long the lock of a draft outlives the last heartbeat of its editor, before
+    /// other admins can take it over.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub draft_lock_ttl_seconds: u64,
+    /// How many recipients, picked at random, the confirmation screen shows before
+    /// publishing an issue.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub recipients_sample_size: u16,
+    /// The day the domain of the configured sender started sending, `YYYY-MM-DD`, to ramp
+    /// up its volume while it builds a sender reputation. Empty for no warm-up. The domains
+    /// the tenants send from are warmed up on the same schedule, from the day they first
+    /// send.
+    pub warm_up_start_date: String,
+    /// The most emails sent a day during each week of the warm-up, e.g. `[200, 1000, 5000]`.
+    /// Past the last week only `max_emails_per_day` applies.
+    pub warm_up_daily_caps: Vec<u64>,
+    /// The most emails sent a minute to some recipient domains, e.g. `gmail.com`, so that
+    /// large sends do not get greylisted by the receiving servers.
+    pub domain_limits: Vec<DomainLimit>,
+    /// The most emails sent a minute to each of the other domains. `0` for no limit.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub other_domains_per_minute: u64,
+    /// The spam complaint rate of an issue, e.g. `0.003`, over which its deliveries are
+    /// paused and the admins alerted. `0` to never pause.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub complaint_rate_threshold: f64,
+    /// How many emails of an issue must be delivered before its complaint rate counts.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub complaint_rate_min_deliveries: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct DomainLimit {
+    pub domain: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub per_minute: u64,
+}
+
+impl NewsletterSettings {
+    pub fn undo_window(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.undo_window_seconds)
+    }
+
+    pub fn draft_lock_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.draft_lock_ttl_seconds)
+    }
+
+    pub fn hourly_quota(&self) -> Option<u64> {
+        (self.max_emails_per_hour > 0).then_some(self.max_emails_per_hour)
+    }
+
+    pub fn daily_quota(&self) -> Option<u64> {
+        (self.max_emails_per_day > 0).then_some(self.max_emails_per_day)
+    }
+
+    pub fn warm_up(&self) -> Result<Option<WarmUp>, String> {
+        if self.warm_up_start_date.is_empty() {
+            return Ok(None);
+        }
+        let start_date = chrono::NaiveDate::parse_from_str(&self.warm_up_start_date, "%Y-%m-%d")
+            .map_err(|_| "newsletters.warm_up_start_date must be a date, e.g. 2022-05-18")?;
+        if self.warm_up_daily_caps.is_empty() || self.warm_up_daily_caps.contains(&0) {
+            return Err("newsletters.warm_up_daily_caps must list a positive cap per week".into());
+        }
+        Ok(Some(WarmUp {
+            start_date,
+            daily_caps: self.warm_up_daily_caps.clone(),
+        }))
+    }
+
+    pub fn other_domains_per_minute(&self) -> Option<u64> {
+        (self.other_domains_per_minute > 0).then_some(self.other_domains_per_minute)
+    }
+
+    pub fn complaint_rate_threshold(&self) -> Option<f64> {
+        (self.complaint_rate_threshold > 0.0).then_some(self.complaint_rate_threshold)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..1.0).contains(&self.complaint_rate_threshold) {
+            return Err("newsletters.complaint_rate_threshold must be between 0 and 1".into());
+        }
+        if self.complaint_rate_min_deliveries == 0 {
+            return Err("newsletters.complaint_rate_min_deliveries must be greater than 0".into());
+        }
+        for limit in &self.domain_limits {
+            if limit.domain.is_empty() || limit.domain.contains('@') || limit.per_minute == 0 {
+                return Err(format!(
+                    "newsletters.domain_limits: `{}` needs a domain and a positive per_minute",
+                    limit.domain
+                ));
+            }
+        }
+        self.warm_up().map(|_| ())
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct CacheSettings {
+    /// How long the public pages are served from memory. `0` disables the cache.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_seconds: u64,
+    /// How many rendered pages are kept at most.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_entries: usize,
+}
+
+impl CacheSettings {
+    pub fn ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.ttl_seconds)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct BrandingSettings {
+    /// Shown in the header of every page.
+    pub site_name: String,
+    /// Shown next to the site name. Empty for no logo.
+    pub logo_url: String,
+    /// Used for links and buttons, as a CSS hex color - e.g. `#3b82f6`.
+    pub accent_color: String,
+}
+
+impl BrandingSettings {
+    pub fn logo_url(&self) -> Option<&str> {
+        Some(self.logo_url.as_str()).filter(|url| !url.is_empty())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let hex_digits = self.accent_color.strip_prefix('#').unwrap_or_default();
+        if !matches!(hex_digits.len(), 3 | 6) || !hex_digits.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err("branding.accent_color must be a hex color, e.g. #3b82f6".into());
+        }
+        if let Some(url) = self.logo_url() {
+            if !(url.starts_with("https://") || url.starts_with("http://") || url.starts_with('/'))
+            {
+                return Err("branding.logo_url must be an absolute URL or path".into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The recurring maintenance jobs, see `crate::scheduler`.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SchedulerSettings {
+    pub prune_pending_subscriptions: ScheduledJobSettings,
+    pub purge_deleted_subscribers: ScheduledJobSettings,
+    pub clean_up_idempotency_keys: ScheduledJobSettings,
+    pub re_engage_inactive_subscribers: ScheduledJobSettings,
+    pub deliver_outbox_emails: ScheduledJobSettings,
+    pub apply_retention_policy: ScheduledJobSettings,
+    /// Only scheduled if `chat_notifications.service` is set.
+    pub post_chat_notifications: ScheduledJobSettings,
+    /// Runs more often than weekly, see `crate::weekly_report`.
+    pub send_weekly_reports: ScheduledJobSettings,
+}
+
+impl SchedulerSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, job) in [
+            (
+                "prune_pending_subscriptions",
+                &self.prune_pending_subscriptions,
+            ),
+            ("purge_deleted_subscribers", &self.purge_deleted_subscribers),
+            ("clean_up_idempotency_keys", &self.clean_up_idempotency_keys),
+            (
+                "re_engage_inactive_subscribers",
+                &self.re_engage_inactive_subscribers,
+            ),
+            ("deliver_outbox_emails", &self.deliver_outbox_emails),
+            ("apply_retention_policy", &self.apply_retention_policy),
+            ("post_chat_notifications", &self.post_chat_notifications),
+            ("send_weekly_reports", &self.send_weekly_reports),
+        ] {
+            if job.enabled && job.interval_seconds == 0 {
+                return Err(format!(
+                    "scheduler.{}.interval_seconds must be greater than 0",
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ScheduledJobSettings {
+    pub enabled: bool,
+    /// How long to wait between two runs of the job.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub interval_seconds: u64,
+}
+
+impl ScheduledJobSettings {
+    /// `None` if the job is disabled.
+    pub fn interval(&self) -> Option<std::time::Duration> {
+        self.enabled
+            .then(|| std::time::Duration::from_secs(self.interval_seconds))
+    }
+}
+
+/// How long data is kept, see `crate::retention`. `0` keeps it forever.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct RetentionSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub delivery_attempts_days: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub unsubscribed_subscribers_days: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub engagement_events_days: u64,
+    /// Have the scheduler only log what it would do, to try a policy out.
+    pub dry_run: bool,
+}
+
+/// Operational messages posted to a chat channel, see `crate::chat_notifications`.
+#[derive(serde::Deserialize, Clone)]
+pub struct ChatNotificationSettings {
+    pub service: ChatService,
+    /// The incoming webhook of the channel. Anyone holding it can post there.
+    pub webhook_url: Secret<String>,
+    /// How many failed deliveries within `error_spike_window_minutes` are worth an alert.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub error_spike_threshold: u64,
+    /// Also the least time between two alerts.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub error_spike_window_minutes: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatService {
+    None,
+    Slack,
+    Discord,
+}
+
+impl ChatNotificationSettings {
+    /// `None` if no chat service is configured.
+    pub fn notifier(&self) -> Option<ChatNotifier> {
+        if self.service == ChatService::None {
+            return None;
+        }
+        Some(ChatNotifier::new(
+            self.service,
+            self.webhook_url.expose_secret().clone(),
+        ))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.service == ChatService::None {
+            return Ok(());
+        }
+        if !self.webhook_url.expose_secret().starts_with("https://") {
+            return Err("chat_notifications.webhook_url must be an https:// URL".into());
+        }
+        if self.error_spike_threshold == 0 || self.error_spike_window_minutes == 0 {
+            return Err("chat_notifications.error_spike_* must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+/// Turning non-essential traffic away while the database is saturated, see
+/// `crate::load_shedding`.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct LoadSheddingSettings {
+    pub enabled: bool,
+    /// The database is saturated once acquiring a connection takes longer than this...
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_acquire_latency_milliseconds: u64,
+    /// ...or once this share of the connections of the pool are in use.
+    pub max_active_connections_ratio: f64,
+    /// How often the time it takes to acquire a connection is measured.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub sample_interval_milliseconds: u64,
+    /// Sent in the `Retry-After` header of the requests turned away.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retry_after_seconds: u64,
+}
+
+impl LoadSheddingSettings {
+    pub fn max_acquire_latency(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.max_acquire_latency_milliseconds)
+    }
+
+    pub fn sample_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.sample_interval_milliseconds)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if !(self.max_active_connections_ratio > 0.0 && self.max_active_connections_ratio <= 1.0) {
+            return Err(
+                "load_shedding.max_active_connections_ratio must be between 0 and 1".into(),
+            );
+        }
+        if self.max_acquire_latency_milliseconds == 0 || self.sample_interval_milliseconds == 0 {
+            return Err("load_shedding.*_milliseconds must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+/// How many heavy requests of each kind a process serves at once for a tenant, see
+/// `crate::concurrency_limits`. `imports` also caps the bulk imports each tenant runs at
+/// once, across the job workers.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ConcurrencyLimitSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub imports: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub exports: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub publishes: usize,
+}
+
+impl ConcurrencyLimitSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.imports == 0 || self.exports == 0 || self.publishes == 0 {
+            return Err("concurrency_limits.* must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+/// The encryption of the personal data of subscribers at rest, see `crate::pii`.
+#[derive(serde::Deserialize, Clone)]
+pub struct PiiEncryptionSettings {
+    /// Sealed subscribers can only be read with the keys: keep them once it was enabled.
+    pub enabled: bool,
+    /// The AES-256-GCM key, base64-encoded. Keep it out of the configuration files: set
+    /// `APP_PII_ENCRYPTION__KEY` from the key management service at deploy time.
+    pub key: Secret<String>,
+    /// The HMAC-SHA256 key of the blind indexes, base64-encoded, distinct from `key`.
+    pub blind_index_key: Secret<String>,
+    /// How often the subscribers not encrypted yet are looked for.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub interval_seconds: u64,
+}
+
+impl PiiEncryptionSettings {
+    /// `None` if the encryption is disabled.
+    pub fn cipher(&self) -> Result<Option<PiiCipher>, String> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        PiiCipher::new(&self.key, &self.blind_index_key).map(Some)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enabled && self.interval_seconds == 0 {
+            return Err("pii_encryption.interval_seconds must be greater than 0".into());
+        }
+        self.cipher().map(|_| ())
+    }
+}
+
+/// Where the database backups started from the admin panel are stored, see
+/// `crate::backups`.
+#[derive(serde::Deserialize, Clone)]
+pub struct BackupSettings {
+    /// The `pg_dump` executable, looked up in the `PATH` if not a path.
+    pub pg_dump: String,
+    /// Where backups are written, unless they go to an S3 bucket.
+    pub directory: String,
+    pub s3: S3Settings,
+}
+
+/// Any S3-compatible object storage. Backups are uploaded to it if `bucket` is set.
+#[derive(serde::Deserialize, Clone)]
+pub struct S3Settings {
+    /// e.g. `https://s3.eu-west-1.amazonaws.com`.
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key_id: String,
+    pub secret_access_key: Secret<String>,
+}
+
+pub enum BackupDestination<'a> {
+    Directory(&'a str),
+    S3(&'a S3Settings),
+}
+
+impl BackupSettings {
+    pub fn destination(&self) -> BackupDestination<'_> {
+        if self.s3.bucket.is_empty() {
+            BackupDestination::Directory(&self.directory)
+        } else {
+            BackupDestination::S3(&self.s3)
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pg_dump.trim().is_empty() {
+            return Err("backups.pg_dump must be set".into());
+        }
+        match self.destination() {
+            BackupDestination::Directory(directory) if directory.trim().is_empty() => {
+                Err("backups.directory must be set unless backups go to S3".into())
+            }
+            BackupDestination::Directory(_) => Ok(()),
+            BackupDestination::S3(s3) => {
+                validate_base_url(&s3.endpoint)
+                    .map_err(|e| format!("backups.s3.endpoint {}", e))?;
+                if s3.region.is_empty()
+                    || s3.access_key_id.is_empty()
+                    || s3.secret_access_key.expose_secret().is_empty()
+                {
+                    return Err(
+                        "backups.s3.region, access_key_id and secret_access_key must be set".into(),
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Who sends the newsletter and where to reach them by post, closing every issue as the
+/// law requires of commercial email (CAN-SPAM).
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct EmailFooterSettings {
+    pub organization_name: String,
+    /// Can span several lines.
+    pub postal_address: String,
+}
+
+impl EmailFooterSettings {
+    /// Issues cannot be delivered without a footer.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.organization_name.trim().is_empty() {
+            return Err("email_footer.organization_name must be set".into());
+        }
+        if self.postal_address.trim().is_empty() {
+            return Err("email_footer.postal_address must be set".into());
+        }
+        Ok(())
+    }
+
+    pub fn text(&self) -> String {
+        format!(
+            "{}\n{}",
+            self.organization_name.trim(),
+            self.postal_address.trim()
+        )
+    }
+
+    pub fn html(&self) -> String {
+        let postal_address: Vec<_> = self
+            .postal_address
+            .trim()
+            .lines()
+            .map(|line| htmlescape::encode_minimal(line.trim()))
+            .collect();
+        format!(
+            r#"<p class="footer">{}<br>{}</p>"#,
+            htmlescape::encode_minimal(self.organization_name.trim()),
+            postal_address.join("<br>")
+        )
+    }
+}
+
+/// The campaign asking inactive subscribers whether they still want the newsletter, see
+/// `crate::re_engagement`. It runs as the `re_engage_inactive_subscribers` scheduled job.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ReEngagementSettings {
+    /// Subscribers who opened none of the last this many issues delivered to them are
+    /// asked whether they still want the newsletter.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub inactive_issues: u32,
+    /// How long they have to answer before they are unsubscribed.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub grace_period_days: u64,
+    pub subject: String,
+    /// Followed by the link to stay subscribed.
+    pub message: String,
+}
+
+impl ReEngagementSettings {
+    pub fn grace_period(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.grace_period_days * 24 * 60 * 60)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.inactive_issues == 0 {
+            return Err("re_engagement.inactive_issues must be greater than 0".into());
+        }
+        if self.grace_period_days == 0 {
+            return Err("re_engagement.grace_period_days must be greater than 0".into());
+        }
+        if self.subject.trim().is_empty() {
+            return Err("re_engagement.subject must not be empty".into());
+        }
+        Ok(())
+    }
+}
+
+/// Serving several newsletters from one deployment, see `crate::tenancy`.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct TenancySettings {
+    /// Whether requests are served the tenant registered for their hostname.
+    /// Otherwise everything belongs to the default tenant.
+    pub enabled: bool,
+}
+
+/// The keys signed URLs are signed with, see `crate::signing`.
+#[derive(serde::Deserialize, Clone)]
+pub struct SigningSettings {
+    /// URLs are signed with the first key. The others are only used to verify them, so
+    /// that a key can be rotated without breaking the links sent before.
+    pub keys: Vec<SigningKey>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SigningKey {
+    /// Sent along with the signature, to tell which key to verify it with.
+    pub id: String,
+    pub secret: Secret<String>,
+}
+
+impl SigningSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.keys.is_empty() {
+            return Err("signing.keys must contain at least one key".into());
+        }
+        for (i, key) in self.keys.iter().enumerate() {
+            if key.id.is_empty()
+                || !key
+                    .id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                return Err("signing.keys ids must be made of letters, digits and dashes".into());
+            }
+            if self.keys[..i].iter().any(|k| k.id == key.id) {
+                return Err(format!("signing.keys has two keys with the id {}", key.id));
+            }
+            if key.secret.expose_secret().len() < 32 {
+                return Err(format!(
+                    "signing.keys secret of {} must be at least 32 characters long",
+                    key.id
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct I18nSettings {
+    /// The language used when a visitor does not ask for one we have translations for.
+    pub default_locale: String,
+}
+
+impl I18nSettings {
+    pub fn default_locale(&self) -> Result<Locale, String> {
+        Locale::parse(&self.default_locale).map_err(|e| format!("i18n.default_locale: {}", e))
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct EventSettings {
+    pub publisher: EventPublisherKind,
+    /// `nats://host:port` for NATS, the base URL of the REST proxy for Kafka.
+    pub url: String,
+    /// The Kafka topic, or the prefix of the NATS subjects.
+    pub topic: String,
+}
+
+/// The message bus domain events are published to, if any.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventPublisherKind {
+    None,
+    Nats,
+    Kafka,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -27,6 +885,17 @@ pub struct EmailClientSettings {
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    /// Consecutive failures after which confirmation emails go straight to the outbox,
+    /// see `crate::circuit_breaker`. 0 to never stop calling the provider.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub circuit_breaker_failure_threshold: u32,
+    /// How long to stop calling the provider for once the breaker opens.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub circuit_breaker_cooldown_seconds: u64,
+    /// A second provider the delivery worker fails over to while this one is down, see
+    /// `crate::email_failover`. Empty for none.
+    pub fallback_base_url: String,
+    pub fallback_authorization_token: Secret<String>,
 }
 
 impl EmailClientSettings {
@@ -38,6 +907,23 @@ impl EmailClientSettings {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
 
+    pub fn circuit_breaker_cooldown(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.circuit_breaker_cooldown_seconds)
+    }
+
+    /// `None` if there is no fallback provider.
+    pub fn fallback_client(&self) -> Option<EmailClient> {
+        if self.fallback_base_url.is_empty() {
+            return None;
+        }
+        Some(EmailClient::new(
+            self.fallback_base_url.clone(),
+            self.sender().expect("Invalid sender email address"),
+            self.fallback_authorization_token.clone(),
+            self.timeout(),
+        ))
+    }
+
     pub fn client(self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address");
         let timeout = self.timeout();
@@ -50,6 +936,47 @@ impl EmailClientSettings {
     }
 }
 
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SpamCheckSettings {
+    /// Where the spam checker listens. Empty to skip the spam check.
+    pub base_url: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+}
+
+impl SpamCheckSettings {
+    /// `None` if the spam check is disabled.
+    pub fn checker(&self, email_client: &EmailClientSettings) -> Option<SpamChecker> {
+        if self.base_url.is_empty() {
+            return None;
+        }
+        let sender_email = email_client.sender().expect("Invalid sender email address");
+        Some(SpamChecker::new(
+            self.base_url.clone(),
+            sender_email,
+            std::time::Duration::from_millis(self.timeout_milliseconds),
+        ))
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct DeliverabilitySettings {
+    /// The DNS server the records of the sending domain are looked up with, as `host:port`.
+    pub nameserver: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    /// The selector of the DKIM key the email provider signs our emails with.
+    pub dkim_selector: String,
+    /// What the SPF record must include for the email provider to send for us.
+    pub spf_include: String,
+}
+
+impl DeliverabilitySettings {
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct DatabaseSettings {
     pub username: String,
@@ -59,12 +986,32 @@ pub struct DatabaseSettings {
     pub host: String,
     pub database_name: String,
     pub require_ssl: bool,
+    /// The size of the connection pool of each process.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_connections: u32,
+    /// The queries which take longer are logged as warnings.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub slow_query_threshold_milliseconds: u64,
 }
 
 impl DatabaseSettings {
+    /// Without connections, nothing could run - and load shedding would turn every
+    /// request away.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_connections == 0 {
+            return Err("database.max_connections must be greater than 0".into());
+        }
+        Ok(())
+    }
+
     pub fn with_db(&self) -> PgConnectOptions {
         let mut options = self.without_db().database(&self.database_name);
-        options.log_statements(tracing::log::LevelFilter::Trace);
+        options
+            .log_statements(tracing::log::LevelFilter::Trace)
+            .log_slow_statements(
+                tracing::log::LevelFilter::Warn,
+                std::time::Duration::from_millis(self.slow_query_threshold_milliseconds),
+            );
         options
     }
 
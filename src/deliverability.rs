@@ -0,0 +1,713 @@
+//! Checks of the DNS records which decide whether mailbox providers trust the emails we
+//! send from our domain: SPF, DKIM and DMARC.
+use crate::configuration::{DeliverabilitySettings, EmailClientSettings};
+use anyhow::Context;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+/// Receivers give up on SPF records needing more DNS lookups than this (RFC 7208).
+const MAX_SPF_LOOKUPS: usize = 10;
+const TXT: u16 = 16;
+const MX: u16 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Pass,
+    Warning,
+    Failure,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Pass => "pass",
+            Status::Warning => "warning",
+            Status::Failure => "failure",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub status: Status,
+    pub message: String,
+}
+
+impl Finding {
+    fn warning(message: impl Into<String>) -> Self {
+        Self {
+            status: Status::Warning,
+            message: message.into(),
+        }
+    }
+
+    fn failure(message: impl Into<String>) -> Self {
+        Self {
+            status: Status::Failure,
+            message: message.into(),
+        }
+    }
+}
+
+/// The outcome of checking one kind of record.
+pub struct RecordCheck {
+    pub kind: &'static str,
+    /// The name the records were looked up at.
+    pub name: String,
+    pub records: Vec<String>,
+    /// Empty if the records are fine.
+    pub findings: Vec<Finding>,
+}
+
+impl RecordCheck {
+    pub fn status(&self) -> Status {
+        self.findings
+            .iter()
+            .map(|f| f.status)
+            .max()
+            .unwrap_or(Status::Pass)
+    }
+}
+
+pub struct DeliverabilityChecker {
+    domain: String,
+    nameserver: String,
+    timeout: Duration,
+    dkim_selector: String,
+    spf_include: String,
+}
+
+impl DeliverabilityChecker {
+    pub fn new(settings: &DeliverabilitySettings, email_client: &EmailClientSettings) -> Self {
+        let domain = email_client
+            .sender_email
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_owned())
+            .unwrap_or_default();
+        Self {
+            domain,
+            nameserver: settings.nameserver.clone(),
+            timeout: settings.timeout(),
+            dkim_selector: settings.dkim_selector.clone(),
+            spf_include: settings.spf_include.clone(),
+        }
+    }
+
+    /// The domain we send emails from.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    #[tracing::instrument(name = "Check the DNS records of the sending domain", skip(self))]
+    pub async fn run(&self) -> Vec<RecordCheck> {
+        let spf_name = self.domain.clone();
+        let dkim_name = format!("{}._domainkey.{}", self.dkim_selector, self.domain);
+        let dmarc_name = format!("_dmarc.{}", self.domain);
+        let (spf, dkim, dmarc) = tokio::join!(
+            self.lookup(&spf_name),
+            self.lookup(&dkim_name),
+            self.lookup(&dmarc_name)
+        );
+        vec![
+            record_check("SPF", spf_name, spf, |r| check_spf(r, &self.spf_include)),
+            record_check("DKIM", dkim_name, dkim, check_dkim),
+            record_check("DMARC", dmarc_name, dmarc, check_dmarc),
+        ]
+    }
+
+    async fn lookup(&self, name: &str) -> Result<Vec<String>, anyhow::Error> {
+        tokio::time::timeout(self.timeout, lookup_txt(&self.nameserver, name))
+            .await
+            .context("The nameserver did not answer in time")?
+    }
+}
+
+fn record_check(
+    kind: &'static str,
+    name: String,
+    records: Result<Vec<String>, anyhow::Error>,
+    check: impl FnOnce(&[String]) -> Vec<Finding>,
+) -> RecordCheck {
+    match records {
+        Ok(records) => RecordCheck {
+            kind,
+            name,
+            findings: check(&records),
+            records,
+        },
+        Err(e) => RecordCheck {
+            kind,
+            name,
+            records: vec![],
+            findings: vec![Finding::failure(format!("The DNS lookup failed: {:#}", e))],
+        },
+    }
+}
+
+/// Only one SPF record, allowing the provider and nothing else.
+fn check_spf(records: &[String], required_include: &str) -> Vec<Finding> {
+    let spf: Vec<_> = records
+        .iter()
+        .filter(|r| r.to_ascii_lowercase().starts_with("v=spf1"))
+        .collect();
+    let record = match spf.as_slice() {
+        [] => return vec![Finding::failure("There is no SPF record.")],
+        [record] => record,
+        _ => {
+            return vec![Finding::failure(format!(
+                "There are {} SPF records: receivers treat this as an error, merge them into one.",
+                spf.len()
+            ))]
+        }
+    };
+
+    let mut findings = Vec::new();
+    let terms: Vec<_> = record.split_whitespace().skip(1).collect();
+    if !terms
+        .iter()
+        .any(|t| t.eq_ignore_ascii_case(&format!("include:{}", required_include)))
+    {
+        findings.push(Finding::failure(format!(
+            "The SPF record does not include {}: the provider is not allowed to send for the domain.",
+            required_include
+        )));
+    }
+    let lookups = terms.iter().filter(|t| needs_lookup(t)).count();
+    if lookups > MAX_SPF_LOOKUPS {
+        findings.push(Finding::failure(format!(
+            "The SPF record needs {} DNS lookups, receivers give up after {}.",
+            lookups, MAX_SPF_LOOKUPS
+        )));
+    }
+    match terms
+        .iter()
+        .map(|t| split_qualifier(t))
+        .find(|(_, mechanism)| mechanism.eq_ignore_ascii_case("all"))
+    {
+        Some(('+', _)) => findings.push(Finding::failure(
+            "The SPF record ends with +all: anyone may send emails for the domain.",
+        )),
+        Some(('?', _)) => findings.push(Finding::warning(
+            "The SPF record ends with ?all: receivers are told nothing about other senders.",
+        )),
+        Some(_) => {}
+        None => findings.push(Finding::warning(
+            "The SPF record has no all mechanism: other senders are not rejected.",
+        )),
+    }
+    findings
+}
+
+/// An SPF term split into its qualifier - `+` when left out - and the rest of it.
+fn split_qualifier(term: &str) -> (char, &str) {
+    match term.chars().next() {
+        Some(qualifier @ ('+' | '-' | '~' | '?')) => (qualifier, &term[1..]),
+        _ => ('+', term),
+    }
+}
+
+/// Whether an SPF term makes receivers look up another DNS record.
+fn needs_lookup(term: &str) -> bool {
+    let term = split_qualifier(term).1.to_ascii_lowercase();
+    let name = term.split([':', '/', '=']).next().unwrap_or_default();
+    matches!(name, "include" | "a" | "mx" | "ptr" | "exists" | "redirect")
+}
+
+/// One DKIM record for the provider's selector, with a public key.
+fn check_dkim(records: &[String]) -> Vec<Finding> {
+    let record = match records {
+        [] => {
+            return vec![Finding::failure(
+                "There is no DKIM record for the provider's selector.",
+            )]
+        }
+        [record] => record,
+        _ => {
+            return vec![Finding::failure(format!(
+                "There are {} DKIM records for the provider's selector, there should be one.",
+                records.len()
+            ))]
+        }
+    };
+
+    let tags = parse_tags(record);
+    let mut findings = Vec::new();
+    if let Some(version) = tag(&tags, "v") {
+        if version != "DKIM1" {
+            findings.push(Finding::failure(format!(
+                "The DKIM record has an unknown version: {}.",
+                version
+            )));
+        }
+    }
+    match tag(&tags, "p") {
+        None => findings.push(Finding::failure("The DKIM record has no public key (p=).")),
+        Some("") => findings.push(Finding::failure(
+            "The DKIM key has been revoked (empty p=): signatures will not verify.",
+        )),
+        Some(_) => {}
+    }
+    if let Some(key_type) = tag(&tags, "k") {
+        if !matches!(key_type, "rsa" | "ed25519") {
+            findings.push(Finding::warning(format!(
+                "The DKIM key type {} is not widely supported.",
+                key_type
+            )));
+        }
+    }
+    findings
+}
+
+/// One DMARC record, with a policy receivers act on.
+fn check_dmarc(records: &[String]) -> Vec<Finding> {
+    let dmarc: Vec<_> = records
+        .iter()
+        .filter(|r| r.starts_with("v=DMARC1"))
+        .collect();
+    let record = match dmarc.as_slice() {
+        [] => return vec![Finding::failure("There is no DMARC record.")],
+        [record] => record,
+        _ => {
+            return vec![Finding::failure(format!(
+                "There are {} DMARC records: receivers ignore them all, keep only one.",
+                dmarc.len()
+            ))]
+        }
+    };
+
+    let tags = parse_tags(record);
+    let mut findings = Vec::new();
+    match tag(&tags, "p") {
+        None => findings.push(Finding::failure("The DMARC record has no policy (p=).")),
+        Some("none") => findings.push(Finding::warning(
+            "The DMARC policy is p=none: failing emails are only reported, not rejected.",
+        )),
+        Some("quarantine" | "reject") => {}
+        Some(policy) => findings.push(Finding::failure(format!(
+            "The DMARC policy p={} is not valid.",
+            policy
+        ))),
+    }
+    if let Some(pct) = tag(&tags, "pct") {
+        if pct != "100" {
+            findings.push(Finding::warning(format!(
+                "The DMARC policy only applies to {}% of the emails.",
+                pct
+            )));
+        }
+    }
+    if tag(&tags, "rua").is_none() {
+        findings.push(Finding::warning(
+            "The DMARC record asks for no aggregate reports (rua=): failures go unnoticed.",
+        ));
+    }
+    findings
+}
+
+/// `k=v; k=v` tags, as found in DKIM and DMARC records.
+fn parse_tags(record: &str) -> Vec<(&str, &str)> {
+    record
+        .split(';')
+        .filter_map(|t| t.split_once('='))
+        .map(|(k, v)| (k.trim(), v.trim()))
+        .collect()
+}
+
+fn tag<'a>(tags: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    tags.iter().find(|(k, _)| *k == name).map(|(_, v)| *v)
+}
+
+/// The TXT records of `name`, as answered by `nameserver`.
+/// A name which does not exist has none.
+async fn lookup_txt(nameserver: &str, name: &str) -> Result<Vec<String>, anyhow::Error> {
+    let id = rand::random();
+    let answer = exchange(nameserver, &query(id, name, TXT)?).await?;
+    parse_txt_response(id, &answer)
+}
+
+/// The mail servers of `domain`, most preferred first, as answered by `nameserver`.
+/// A domain which does not exist has none.
+pub async fn lookup_mx(nameserver: &str, domain: &str) -> Result<Vec<String>, anyhow::Error> {
+    let id = rand::random();
+    let answer = exchange(nameserver, &query(id, domain, MX)?).await?;
+    parse_mx_response(id, &answer)
+}
+
+/// Over UDP, then over TCP if the answer did not fit in a datagram (RFC 7766).
+async fn exchange(nameserver: &str, query: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let answer = exchange_over_udp(nameserver, query).await?;
+    if is_truncated(&answer) {
+        return exchange_over_tcp(nameserver, query).await;
+    }
+    Ok(answer)
+}
+
+fn is_truncated(message: &[u8]) -> bool {
+    message.get(2).is_some_and(|flags| flags & 0x02 != 0)
+}
+
+async fn exchange_over_udp(nameserver: &str, query: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let local = if nameserver.starts_with('[') {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket
+        .connect(nameserver)
+        .await
+        .with_context(|| format!("Invalid nameserver {}", nameserver))?;
+    socket.send(query).await?;
+    let mut buffer = vec![0; 4096];
+    let length = socket.recv(&mut buffer).await?;
+    buffer.truncate(length);
+    Ok(buffer)
+}
+
+/// Messages sent over TCP are prefixed with their length.
+async fn exchange_over_tcp(nameserver: &str, query: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let mut stream = TcpStream::connect(nameserver).await.with_context(|| {
+        format!(
+            "Failed to connect to the nameserver {} over TCP",
+            nameserver
+        )
+    })?;
+    let mut message = Vec::with_capacity(query.len() + 2);
+    message.extend((query.len() as u16).to_be_bytes());
+    message.extend(query);
+    stream.write_all(&message).await?;
+    let length = stream.read_u16().await? as usize;
+    let mut answer = vec![0; length];
+    stream.read_exact(&mut answer).await?;
+    Ok(answer)
+}
+
+fn query(id: u16, name: &str, record_type: u16) -> Result<Vec<u8>, anyhow::Error> {
+    let mut query = Vec::with_capacity(512);
+    query.extend(id.to_be_bytes());
+    // Recursion desired, one question, one additional record for EDNS.
+    query.extend([0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 1]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            anyhow::bail!("{} is not a valid domain name", name);
+        }
+        query.push(label.len() as u8);
+        query.extend(label.as_bytes());
+    }
+    query.push(0);
+    query.extend(record_type.to_be_bytes());
+    query.extend(1u16.to_be_bytes());
+    // EDNS OPT record: accept answers up to 4096 bytes instead of 512.
+    query.extend([0, 0, 41, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+    Ok(query)
+}
+
+struct Reader<'a> {
+    message: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], anyhow::Error> {
+        let bytes = self
+            .message
+            .get(self.position..self.position + n)
+            .context("The DNS answer is truncated")?;
+        self.position += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, anyhow::Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Names may end with a pointer to another name: it is not followed.
+    fn skip_name(&mut self) -> Result<(), anyhow::Error> {
+        loop {
+            let length = self.take(1)?[0] as usize;
+            if length == 0 {
+                return Ok(());
+            }
+            if length & 0xC0 == 0xC0 {
+                self.take(1)?;
+                return Ok(());
+            }
+            self.take(length)?;
+        }
+    }
+
+    /// Pointers are followed, as long as they point backwards: a loop would not end.
+    fn read_name(&mut self) -> Result<String, anyhow::Error> {
+        let mut labels = Vec::new();
+        let mut reader = Reader {
+            message: self.message,
+            position: self.position,
+        };
+        let mut end = None;
+        loop {
+            let length = reader.take(1)?[0] as usize;
+            if length == 0 {
+                break;
+            }
+            if length & 0xC0 == 0xC0 {
+                let target = ((length & 0x3F) << 8) | reader.take(1)?[0] as usize;
+                end.get_or_insert(reader.position);
+                if target >= reader.position - 2 {
+                    anyhow::bail!("The DNS answer has a name pointing forward");
+                }
+                reader.position = target;
+                continue;
+            }
+            labels.push(String::from_utf8_lossy(reader.take(length)?).into_owned());
+        }
+        self.position = end.unwrap_or(reader.position);
+        Ok(labels.join("."))
+    }
+}
+
+/// The data of the answers of `record_type`, with its position in the message: names in
+/// the data may point to other parts of the message.
+fn parse_answers(
+    id: u16,
+    message: &[u8],
+    record_type: u16,
+) -> Result<Vec<(usize, &[u8])>, anyhow::Error> {
+    let mut reader = Reader {
+        message,
+        position: 0,
+    };
+    if reader.u16()? != id {
+        anyhow::bail!("The DNS answer does not match the question");
+    }
+    let flags = reader.u16()?;
+    if flags & 0x0200 != 0 {
+        anyhow::bail!("The DNS answer is truncated");
+    }
+    match flags & 0x000F {
+        0 => {}
+        3 => return Ok(vec![]),
+        rcode => anyhow::bail!("The nameserver answered with error code {}", rcode),
+    }
+    let questions = reader.u16()?;
+    let answers = reader.u16()?;
+    reader.take(4)?;
+    for _ in 0..questions {
+        reader.skip_name()?;
+        reader.take(4)?;
+    }
+
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        reader.skip_name()?;
+        let answer_type = reader.u16()?;
+        reader.take(6)?;
+        let length = reader.u16()? as usize;
+        let position = reader.position;
+        let data = reader.take(length)?;
+        // Aliases come along with the records of their target.
+        if answer_type == record_type {
+            records.push((position, data));
+        }
+    }
+    Ok(records)
+}
+
+fn parse_txt_response(id: u16, message: &[u8]) -> Result<Vec<String>, anyhow::Error> {
+    let mut records = Vec::new();
+    for (_, data) in parse_answers(id, message, TXT)? {
+        // A TXT record is made of strings of at most 255 bytes, to be joined.
+        let mut text = Vec::with_capacity(data.len());
+        let mut data = Reader {
+            message: data,
+            position: 0,
+        };
+        while data.position < data.message.len() {
+            let n = data.take(1)?[0] as usize;
+            text.extend(data.take(n)?);
+        }
+        records.push(String::from_utf8_lossy(&text).into_owned());
+    }
+    Ok(records)
+}
+
+fn parse_mx_response(id: u16, message: &[u8]) -> Result<Vec<String>, anyhow::Error> {
+    let mut exchanges = Vec::new();
+    for (position, _) in parse_answers(id, message, MX)? {
+        let mut reader = Reader { message, position };
+        let preference = reader.u16()?;
+        exchanges.push((preference, reader.read_name()?));
+    }
+    exchanges.sort();
+    Ok(exchanges
+        .into_iter()
+        .map(|(_, exchange)| exchange)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_dkim, check_dmarc, check_spf, parse_mx_response, parse_txt_response, query, Status,
+        TXT,
+    };
+    use claim::{assert_err, assert_ok};
+
+    fn statuses(findings: &[super::Finding]) -> Vec<Status> {
+        findings.iter().map(|f| f.status).collect()
+    }
+
+    fn records(records: &[&str]) -> Vec<String> {
+        records.iter().map(|r| r.to_string()).collect()
+    }
+
+    #[test]
+    fn queries_ask_for_the_txt_records_of_the_name() {
+        let query = query(0xabcd, "example.com", TXT).unwrap();
+
+        assert_eq!(&query[..4], &[0xab, 0xcd, 0x01, 0x00]);
+        assert_eq!(&query[12..25], b"\x07example\x03com\x00");
+        assert_eq!(&query[25..29], &[0, 16, 0, 1]);
+        assert_err!(super::query(1, "example..com", TXT));
+    }
+
+    #[test]
+    fn txt_answers_are_joined_and_other_records_skipped() {
+        let mut answer = vec![0xab, 0xcd, 0x81, 0x80, 0, 1, 0, 2, 0, 0, 0, 0];
+        answer.extend(b"\x07example\x03com\x00\x00\x10\x00\x01");
+        // A CNAME, then a TXT record split in two strings, both named with a pointer.
+        answer.extend([0xC0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xC0, 12]);
+        answer.extend([0xC0, 12, 0, 16, 0, 1, 0, 0, 0, 60, 0, 15]);
+        answer.extend(b"\x07v=spf1 \x06-all!!");
+
+        let records = parse_txt_response(0xabcd, &answer).unwrap();
+
+        assert_eq!(records, ["v=spf1 -all!!"]);
+        assert_err!(parse_txt_response(0x1234, &answer));
+        assert_err!(parse_txt_response(0xabcd, &answer[..answer.len() - 1]));
+    }
+
+    #[test]
+    fn mx_answers_are_sorted_by_preference_with_their_names_expanded() {
+        let mut answer = vec![0xab, 0xcd, 0x81, 0x80, 0, 1, 0, 2, 0, 0, 0, 0];
+        answer.extend(b"\x07example\x03com\x00\x00\x0f\x00\x01");
+        // mx2.example.com, its domain pointing at the question, then mx1.example.com.
+        answer.extend([0xC0, 12, 0, 15, 0, 1, 0, 0, 0, 60, 0, 8, 0, 20]);
+        answer.extend(b"\x03mx2\xC0\x0C");
+        answer.extend([0xC0, 12, 0, 15, 0, 1, 0, 0, 0, 60, 0, 8, 0, 10]);
+        answer.extend(b"\x03mx1\xC0\x0C");
+
+        let exchanges = parse_mx_response(0xabcd, &answer).unwrap();
+
+        assert_eq!(exchanges, ["mx1.example.com", "mx2.example.com"]);
+    }
+
+    #[test]
+    fn an_unknown_name_has_no_records() {
+        let answer = [0, 1, 0x81, 0x83, 0, 0, 0, 0, 0, 0, 0, 0];
+
+        assert_eq!(assert_ok!(parse_txt_response(1, &answer)).len(), 0);
+    }
+
+    #[test]
+    fn a_strict_spf_record_including_the_provider_passes() {
+        let findings = check_spf(
+            &records(&[
+                "google-site-verification=abc",
+                "v=spf1 include:spf.mtasv.net ~all",
+            ]),
+            "spf.mtasv.net",
+        );
+
+        assert!(findings.is_empty(), "{:?}", findings);
+    }
+
+    #[test]
+    fn spf_misconfigurations_are_flagged() {
+        let include = "spf.mtasv.net";
+        assert_eq!(statuses(&check_spf(&[], include)), [Status::Failure]);
+        assert_eq!(
+            statuses(&check_spf(
+                &records(&["v=spf1 -all", "v=spf1 ~all"]),
+                include
+            )),
+            [Status::Failure]
+        );
+        assert_eq!(
+            statuses(&check_spf(&records(&["v=spf1 mx -all"]), include)),
+            [Status::Failure]
+        );
+        assert_eq!(
+            statuses(&check_spf(
+                &records(&["v=spf1 include:spf.mtasv.net +all"]),
+                include
+            )),
+            [Status::Failure]
+        );
+        assert_eq!(
+            statuses(&check_spf(
+                &records(&["v=spf1 include:spf.mtasv.net ?all"]),
+                include
+            )),
+            [Status::Warning]
+        );
+        assert_eq!(
+            statuses(&check_spf(
+                &records(&["v=spf1 include:spf.mtasv.net a:mail.firewall"]),
+                include
+            )),
+            [Status::Warning]
+        );
+        assert_eq!(
+            statuses(&check_spf(
+                &records(&["v=spf1 include:spf.mtasv.net ALL"]),
+                include
+            )),
+            [Status::Failure]
+        );
+        let too_many = format!(
+            "v=spf1 include:spf.mtasv.net {} -all",
+            (0..10)
+                .map(|i| format!("include:{}.example.com", i))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        assert_eq!(
+            statuses(&check_spf(&[too_many], include)),
+            [Status::Failure]
+        );
+    }
+
+    #[test]
+    fn a_dkim_record_needs_a_public_key() {
+        assert!(check_dkim(&records(&[
+            "k=rsa; p=MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQ"
+        ]))
+        .is_empty());
+        assert_eq!(statuses(&check_dkim(&[])), [Status::Failure]);
+        assert_eq!(
+            statuses(&check_dkim(&records(&["v=DKIM1; k=rsa; p="]))),
+            [Status::Failure]
+        );
+    }
+
+    #[test]
+    fn a_dmarc_record_should_enforce_its_policy_and_ask_for_reports() {
+        assert!(check_dmarc(&records(&[
+            "v=DMARC1; p=reject; rua=mailto:dmarc@example.com"
+        ]))
+        .is_empty());
+        assert_eq!(statuses(&check_dmarc(&[])), [Status::Failure]);
+        assert_eq!(
+            statuses(&check_dmarc(&records(&["v=DMARC1; p=none"]))),
+            [Status::Warning, Status::Warning]
+        );
+        assert_eq!(
+            statuses(&check_dmarc(&records(&[
+                "v=DMARC1; p=quarantine; pct=50; rua=mailto:dmarc@example.com"
+            ]))),
+            [Status::Warning]
+        );
+    }
+}
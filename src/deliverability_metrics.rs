@@ -0,0 +1,404 @@
+//! How mailbox providers treat our emails, from the events our email provider reports.
+use crate::bounces::BounceCategory;
+use crate::pii::{mask_email, Masked};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Mailbox providers start filtering senders bouncing more than this.
+const BOUNCE_RATE_LIMIT: f64 = 0.02;
+/// Gmail and Yahoo ask senders to stay below this complaint rate.
+const COMPLAINT_RATE_LIMIT: f64 = 0.001;
+/// The recipient domains are measured over this many days.
+const DOMAIN_WINDOW_DAYS: i32 = 30;
+const DOMAINS: i64 = 20;
+const FLAGGED_BOUNCES: i64 = 20;
+const ISSUES: i64 = 10;
+const WEEKS: i32 = 8;
+
+/// Counts of provider events. Opens count each recipient once per issue.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Metrics {
+    pub delivered: i64,
+    pub bounced: i64,
+    pub complained: i64,
+    pub opened: i64,
+}
+
+impl Metrics {
+    /// Bounces among the emails which reached a mailbox provider.
+    pub fn bounce_rate(&self) -> Option<f64> {
+        ratio(self.bounced, self.delivered + self.bounced)
+    }
+
+    pub fn complaint_rate(&self) -> Option<f64> {
+        ratio(self.complained, self.delivered)
+    }
+
+    pub fn open_rate(&self) -> Option<f64> {
+        ratio(self.opened, self.delivered)
+    }
+
+    /// What is hurting our reputation, if anything.
+    pub fn problems(&self) -> Vec<&'static str> {
+        let mut problems = Vec::new();
+        if self.bounce_rate().unwrap_or_default() > BOUNCE_RATE_LIMIT {
+            problems.push("bounce rate above 2%");
+        }
+        if self.complaint_rate().unwrap_or_default() > COMPLAINT_RATE_LIMIT {
+            problems.push("complaint rate above 0.1%");
+        }
+        problems
+    }
+
+    pub fn bounce_rate_label(&self) -> String {
+        percent(self.bounce_rate())
+    }
+
+    pub fn complaint_rate_label(&self) -> String {
+        percent(self.complaint_rate())
+    }
+
+    pub fn open_rate_label(&self) -> String {
+        percent(self.open_rate())
+    }
+}
+
+pub(crate) fn ratio(part: i64, whole: i64) -> Option<f64> {
+    (whole > 0).then(|| part as f64 / whole as f64)
+}
+
+pub(crate) fn percent(rate: Option<f64>) -> String {
+    match rate {
+        Some(rate) => format!("{:.2}%", rate * 100.0),
+        None => "-".into(),
+    }
+}
+
+pub struct DomainMetrics {
+    pub domain: String,
+    pub metrics: Metrics,
+}
+
+pub struct IssueMetrics {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub published_at: String,
+    pub metrics: Metrics,
+}
+
+pub struct WeeklyMetrics {
+    pub week: DateTime<Utc>,
+    pub metrics: Metrics,
+}
+
+pub struct BounceCategoryCount {
+    pub category: BounceCategory,
+    pub bounced: i64,
+}
+
+/// A blocked or spam bounce, to be looked into.
+pub struct FlaggedBounce {
+    pub recipient: String,
+    pub category: BounceCategory,
+    pub description: Option<String>,
+    pub received_at: DateTime<Utc>,
+}
+
+impl Masked for FlaggedBounce {
+    fn masked(self) -> Self {
+        Self {
+            recipient: mask_email(&self.recipient),
+            ..self
+        }
+    }
+}
+
+pub struct DeliverabilityReport {
+    /// The busiest recipient domains of the last 30 days.
+    pub domains: Vec<DomainMetrics>,
+    /// The latest issues of the tenant, newest first.
+    pub issues: Vec<IssueMetrics>,
+    /// The last weeks, oldest first.
+    pub weeks: Vec<WeeklyMetrics>,
+    /// The bounces of the last 30 days, by category.
+    pub bounce_categories: Vec<BounceCategoryCount>,
+    /// The latest bounces flagged for review in the last 30 days, newest first.
+    pub flagged_bounces: Vec<FlaggedBounce>,
+}
+
+#[tracing::instrument(name = "Compute the deliverability metrics", skip(pool))]
+pub async fn get_deliverability_report(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<DeliverabilityReport, anyhow::Error> {
+    Ok(DeliverabilityReport {
+        domains: get_domain_metrics(pool, tenant_id).await?,
+        issues: get_issue_metrics(pool, tenant_id).await?,
+        weeks: get_weekly_metrics(pool, tenant_id).await?,
+        bounce_categories: get_bounce_categories(pool, tenant_id).await?,
+        flagged_bounces: get_flagged_bounces(pool, tenant_id).await?,
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_domain_metrics(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Vec<DomainMetrics>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            lower(split_part(recipient, '@', 2)) AS "domain!",
+            COUNT(*) FILTER (WHERE record_type = 'Delivery') AS "delivered!",
+            COUNT(*) FILTER (WHERE record_type = 'Bounce') AS "bounced!",
+            COUNT(*) FILTER (WHERE record_type = 'SpamComplaint') AS "complained!",
+            COUNT(DISTINCT recipient || ' ' || coalesce(payload->>'Tag', ''))
+                FILTER (WHERE record_type = 'Open') AS "opened!"
+        FROM email_provider_events
+        WHERE
+            tenant_id = $3 AND
+            recipient LIKE '%@%' AND
+            received_at >= now() - make_interval(days => $1)
+        GROUP BY 1
+        ORDER BY
+            COUNT(*) FILTER (WHERE record_type IN ('Delivery', 'Bounce')) DESC,
+            1
+        LIMIT $2
+        "#,
+        DOMAIN_WINDOW_DAYS,
+        DOMAINS,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to aggregate the provider events by domain")?;
+    Ok(rows
+        .into_iter()
+        .map(|r| DomainMetrics {
+            domain: r.domain,
+            metrics: Metrics {
+                delivered: r.delivered,
+                bounced: r.bounced,
+                complained: r.complained,
+                opened: r.opened,
+            },
+        })
+        .collect())
+}
+
+/// Events are matched to issues through the tag they are sent with.
+#[tracing::instrument(skip_all)]
+async fn get_issue_metrics(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Vec<IssueMetrics>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            COUNT(e.event_id) FILTER (WHERE e.record_type = 'Delivery') AS "delivered!",
+            COUNT(e.event_id) FILTER (WHERE e.record_type = 'Bounce') AS "bounced!",
+            COUNT(e.event_id) FILTER (WHERE e.record_type = 'SpamComplaint') AS "complained!",
+            COUNT(DISTINCT e.recipient) FILTER (WHERE e.record_type = 'Open') AS "opened!"
+        FROM (
+            SELECT newsletter_issue_id, title, published_at
+            FROM newsletter_issues
+            WHERE tenant_id = $2
+            ORDER BY published_at DESC
+            LIMIT $1
+        ) i
+        LEFT JOIN email_provider_events e
+            ON e.payload->>'Tag' = i.newsletter_issue_id::text
+        GROUP BY i.newsletter_issue_id, i.title, i.published_at
+        ORDER BY i.published_at DESC
+        "#,
+        ISSUES,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to aggregate the provider events by issue")?;
+    Ok(rows
+        .into_iter()
+        .map(|r| IssueMetrics {
+            newsletter_issue_id: r.newsletter_issue_id,
+            title: r.title,
+            published_at: r.published_at,
+            metrics: Metrics {
+                delivered: r.delivered,
+                bounced: r.bounced,
+                complained: r.complained,
+                opened: r.opened,
+            },
+        })
+        .collect())
+}
+
+/// Weeks without events are included, to keep the trend readable.
+#[tracing::instrument(skip_all)]
+async fn get_weekly_metrics(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Vec<WeeklyMetrics>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            w.week AS "week!",
+            COUNT(e.event_id) FILTER (WHERE e.record_type = 'Delivery') AS "delivered!",
+            COUNT(e.event_id) FILTER (WHERE e.record_type = 'Bounce') AS "bounced!",
+            COUNT(e.event_id) FILTER (WHERE e.record_type = 'SpamComplaint') AS "complained!",
+            COUNT(DISTINCT e.recipient || ' ' || coalesce(e.payload->>'Tag', ''))
+                FILTER (WHERE e.record_type = 'Open') AS "opened!"
+        FROM generate_series(
+            date_trunc('week', now()) - make_interval(weeks => $1 - 1),
+            date_trunc('week', now()),
+            interval '1 week'
+        ) AS w(week)
+        LEFT JOIN email_provider_events e
+            ON e.tenant_id = $2 AND
+                e.received_at >= w.week AND
+                e.received_at < w.week + interval '1 week'
+        GROUP BY w.week
+        ORDER BY w.week
+        "#,
+        WEEKS,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to aggregate the provider events by week")?;
+    Ok(rows
+        .into_iter()
+        .map(|r| WeeklyMetrics {
+            week: r.week,
+            metrics: Metrics {
+                delivered: r.delivered,
+                bounced: r.bounced,
+                complained: r.complained,
+                opened: r.opened,
+            },
+        })
+        .collect())
+}
+
+/// Every category is listed, even without bounces. Bounces received before they were
+/// classified are left out.
+#[tracing::instrument(skip_all)]
+async fn get_bounce_categories(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Vec<BounceCategoryCount>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT bounce_category AS "bounce_category!", COUNT(*) AS "bounced!"
+        FROM email_provider_events
+        WHERE
+            tenant_id = $2 AND
+            bounce_category IS NOT NULL AND
+            received_at >= now() - make_interval(days => $1)
+        GROUP BY 1
+        "#,
+        DOMAIN_WINDOW_DAYS,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to count the bounces by category")?;
+    Ok(BounceCategory::ALL
+        .into_iter()
+        .map(|category| BounceCategoryCount {
+            category,
+            bounced: rows
+                .iter()
+                .find(|r| r.bounce_category == category.as_str())
+                .map_or(0, |r| r.bounced),
+        })
+        .collect())
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_flagged_bounces(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Vec<FlaggedBounce>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            recipient AS "recipient!",
+            bounce_category AS "bounce_category!",
+            payload->>'Description' AS description,
+            received_at
+        FROM email_provider_events
+        WHERE
+            tenant_id = $3 AND
+            bounce_category IN ('blocked', 'spam') AND
+            recipient IS NOT NULL AND
+            received_at >= now() - make_interval(days => $1)
+        ORDER BY received_at DESC
+        LIMIT $2
+        "#,
+        DOMAIN_WINDOW_DAYS,
+        FLAGGED_BOUNCES,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch the bounces flagged for review")?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(FlaggedBounce {
+                recipient: r.recipient,
+                category: BounceCategory::parse(&r.bounce_category).map_err(anyhow::Error::msg)?,
+                description: r.description,
+                received_at: r.received_at,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+    use claim::assert_none;
+
+    #[test]
+    fn rates_are_undefined_without_deliveries() {
+        let metrics = Metrics::default();
+
+        assert_none!(metrics.bounce_rate());
+        assert_none!(metrics.complaint_rate());
+        assert_eq!(metrics.open_rate_label(), "-");
+        assert!(metrics.problems().is_empty());
+    }
+
+    #[test]
+    fn bounces_are_measured_against_every_attempt() {
+        let metrics = Metrics {
+            delivered: 95,
+            bounced: 5,
+            complained: 0,
+            opened: 40,
+        };
+
+        assert_eq!(metrics.bounce_rate_label(), "5.00%");
+        assert_eq!(metrics.open_rate_label(), "42.11%");
+        assert_eq!(metrics.problems(), ["bounce rate above 2%"]);
+    }
+
+    #[test]
+    fn a_single_complaint_in_a_small_audience_is_a_problem() {
+        let metrics = Metrics {
+            delivered: 500,
+            bounced: 0,
+            complained: 1,
+            opened: 0,
+        };
+
+        assert_eq!(metrics.problems(), ["complaint rate above 0.1%"]);
+    }
+}
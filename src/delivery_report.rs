@@ -0,0 +1,232 @@
+use crate::pii::{mask_email, Masked};
+use crate::tenancy::TenantId;
+use actix_web::web::Bytes;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// The rows of a report are written to the response in chunks of about this many bytes,
+/// rather than one write per row.
+const CHUNK_BYTES: usize = 32 * 1024;
+
+/// What happened to an issue for one of its recipients.
+#[derive(Clone, serde::Serialize)]
+pub struct RecipientReport {
+    pub subscriber_email: String,
+    /// `pending`, `delivered`, `failed` or `skipped`.
+    pub outcome: String,
+    pub error: Option<String>,
+    /// The email provider which accepted the email, for the delivered ones.
+    pub provider: Option<String>,
+    pub attempted_at: Option<DateTime<Utc>>,
+    pub opens: i64,
+    pub clicks: i64,
+}
+
+impl RecipientReport {
+    pub const CSV_HEADER: [&'static str; 7] = [
+        "subscriber_email",
+        "outcome",
+        "error",
+        "provider",
+        "attempted_at",
+        "opens",
+        "clicks",
+    ];
+
+    pub fn to_csv_record(&self) -> [String; 7] {
+        [
+            self.subscriber_email.clone(),
+            self.outcome.clone(),
+            self.error.clone().unwrap_or_default(),
+            self.provider.clone().unwrap_or_default(),
+            self.attempted_at
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_default(),
+            self.opens.to_string(),
+            self.clicks.to_string(),
+        ]
+    }
+}
+
+impl Masked for RecipientReport {
+    fn masked(self) -> Self {
+        Self {
+            subscriber_email: mask_email(&self.subscriber_email),
+            ..self
+        }
+    }
+}
+
+/// Whether the tenant has published an issue with this id.
+pub async fn issue_exists(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    newsletter_issue_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND tenant_id = $2
+        "#,
+        newsletter_issue_id,
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a newsletter issue")?;
+    Ok(row.is_some())
+}
+
+/// Stream the per-recipient report of an issue, ordered by email.
+/// Opens and clicks come from the email provider events tagged with the issue id.
+/// Sealed recipients are listed by their pseudonym, see `crate::pii`.
+pub fn stream_delivery_report(
+    pool: PgPool,
+    newsletter_issue_id: Uuid,
+) -> mpsc::Receiver<Result<RecipientReport, anyhow::Error>> {
+    let (sender, receiver) = mpsc::channel(64);
+    tokio::spawn(async move {
+        let mut rows = sqlx::query_as!(
+            RecipientReport,
+            r#"
+            WITH recipients AS (
+                SELECT subscriber_email, outcome, error, provider, attempted_at
+                FROM issue_deliveries
+                WHERE newsletter_issue_id = $1
+                UNION ALL
+                SELECT subscriber_email, 'pending', NULL, NULL, NULL
+                FROM issue_delivery_queue
+                WHERE newsletter_issue_id = $1
+            )
+            SELECT
+                r.subscriber_email AS "subscriber_email!",
+                r.outcome AS "outcome!",
+                r.error,
+                r.provider,
+                r.attempted_at,
+                COUNT(e.event_id) FILTER (WHERE e.record_type = 'Open') AS "opens!",
+                COUNT(e.event_id) FILTER (WHERE e.record_type = 'Click') AS "clicks!"
+            FROM recipients r
+            JOIN newsletter_issues i ON i.newsletter_issue_id = $1
+            LEFT JOIN subscriptions s
+                ON s.tenant_id = i.tenant_id AND s.email = r.subscriber_email
+            LEFT JOIN email_provider_events e
+                ON (e.recipient = r.subscriber_email OR e.recipient_blind_index = s.email_blind_index)
+                AND e.payload->>'Tag' = $1::text
+            GROUP BY r.subscriber_email, r.outcome, r.error, r.provider, r.attempted_at
+            ORDER BY r.subscriber_email
+            "#,
+            newsletter_issue_id
+        )
+        .fetch(&pool);
+        while let Some(row) = rows.next().await {
+            let row = row.context("Failed to retrieve a row of the delivery report");
+            let failed = row.is_err();
+            // The receiver is gone if the client went away - stop querying.
+            if sender.send(row).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Encode the rows streamed by `stream_delivery_report` into the chunks of a response body,
+/// with `encode` appending each row to the chunk being filled.
+///
+/// `hold` is dropped once the last chunk is sent - the permit of a capped route, say.
+/// The body ends at the first error: the client sees the response cut short.
+pub fn encode_in_chunks<F, H>(
+    rows: mpsc::Receiver<Result<RecipientReport, anyhow::Error>>,
+    encode: F,
+    hold: H,
+) -> impl Stream<Item = Result<Bytes, anyhow::Error>>
+where
+    F: FnMut(&RecipientReport, &mut Vec<u8>) -> Result<(), anyhow::Error>,
+{
+    futures_util::stream::unfold(Some((rows, encode, hold)), |state| async move {
+        let (mut rows, mut encode, hold) = state?;
+        let mut chunk = Vec::with_capacity(CHUNK_BYTES);
+        while chunk.len() < CHUNK_BYTES {
+            match rows.recv().await {
+                Some(Ok(row)) => {
+                    if let Err(e) = encode(&row, &mut chunk) {
+                        return Some((Err(e), None));
+                    }
+                }
+                Some(Err(e)) => return Some((Err(e), None)),
+                None if chunk.is_empty() => return None,
+                None => return Some((Ok(Bytes::from(chunk)), None)),
+            }
+        }
+        Some((Ok(Bytes::from(chunk)), Some((rows, encode, hold))))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_in_chunks, RecipientReport, CHUNK_BYTES};
+    use futures_util::StreamExt;
+    use tokio::sync::mpsc;
+
+    fn row(i: usize) -> RecipientReport {
+        RecipientReport {
+            subscriber_email: format!("subscriber-{:05}@example.com", i),
+            outcome: "pending".into(),
+            error: None,
+            provider: None,
+            attempted_at: None,
+            opens: 0,
+            clicks: 0,
+        }
+    }
+
+    fn encode_email(row: &RecipientReport, chunk: &mut Vec<u8>) -> Result<(), anyhow::Error> {
+        chunk.extend_from_slice(row.subscriber_email.as_bytes());
+        chunk.push(b'\n');
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn every_row_is_sent_across_chunks_of_bounded_size() {
+        let (sender, receiver) = mpsc::channel(16);
+        tokio::spawn(async move {
+            for i in 0..5000 {
+                sender.send(Ok(row(i))).await.unwrap();
+            }
+        });
+
+        let chunks: Vec<_> = encode_in_chunks(receiver, encode_email, ())
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.len() < CHUNK_BYTES + 64));
+        let body = String::from_utf8(chunks.concat()).unwrap();
+        assert_eq!(body.lines().count(), 5000);
+        assert_eq!(body.lines().last(), Some("subscriber-04999@example.com"));
+    }
+
+    #[tokio::test]
+    async fn the_body_ends_at_the_first_error() {
+        let (sender, receiver) = mpsc::channel(16);
+        sender.send(Ok(row(0))).await.unwrap();
+        sender
+            .send(Err(anyhow::anyhow!("The connection was lost")))
+            .await
+            .unwrap();
+        sender.send(Ok(row(1))).await.unwrap();
+        drop(sender);
+
+        let chunks: Vec<_> = encode_in_chunks(receiver, encode_email, ()).collect().await;
+
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! Line by line comparison of two texts, for the side-by-side diffs of the admin pages.
+
+/// Past this many line pairs, texts are shown as entirely replaced rather than compared:
+/// the comparison is quadratic.
+const MAX_COMPARED_PAIRS: usize = 4_000_000;
+
+/// A row of a side-by-side diff.
+#[derive(Debug, PartialEq)]
+pub enum DiffRow<'a> {
+    Same(&'a str),
+    /// Only in the old text.
+    Removed(&'a str),
+    /// Only in the new text.
+    Added(&'a str),
+    /// A line of the old text replaced by one of the new text.
+    Changed(&'a str, &'a str),
+}
+
+impl DiffRow<'_> {
+    /// The old line, if the row has one.
+    pub fn left(&self) -> Option<&str> {
+        match self {
+            DiffRow::Same(l) | DiffRow::Removed(l) | DiffRow::Changed(l, _) => Some(l),
+            DiffRow::Added(_) => None,
+        }
+    }
+
+    /// The new line, if the row has one.
+    pub fn right(&self) -> Option<&str> {
+        match self {
+            DiffRow::Same(r) | DiffRow::Added(r) | DiffRow::Changed(_, r) => Some(r),
+            DiffRow::Removed(_) => None,
+        }
+    }
+
+    pub fn is_same(&self) -> bool {
+        matches!(self, DiffRow::Same(_))
+    }
+}
+
+/// Compare `old` with `new` line by line, along their longest common subsequence.
+/// Removed lines directly followed by added ones are paired up as changed.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffRow<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let mut rows = Vec::new();
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    for op in edit_script(&old, &new) {
+        match op {
+            Op::Delete(line) => removed.push(line),
+            Op::Insert(line) => added.push(line),
+            Op::Equal(line) => {
+                flush(&mut rows, &mut removed, &mut added);
+                rows.push(DiffRow::Same(line));
+            }
+        }
+    }
+    flush(&mut rows, &mut removed, &mut added);
+    rows
+}
+
+enum Op<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+fn edit_script<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Op<'a>> {
+    if old.len().saturating_mul(new.len()) > MAX_COMPARED_PAIRS {
+        return old
+            .iter()
+            .map(|l| Op::Delete(l))
+            .chain(new.iter().map(|l| Op::Insert(l)))
+            .collect();
+    }
+    // lcs[i][j]: the length of the longest common subsequence of old[i..] and new[j..].
+    let mut lcs = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut ops = Vec::with_capacity(old.len() + new.len());
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            ops.push(Op::Equal(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push(Op::Delete(old[i]));
+            i += 1;
+        } else {
+            ops.push(Op::Insert(new[j]));
+            j += 1;
+        }
+    }
+    ops.extend(old[i..].iter().map(|l| Op::Delete(l)));
+    ops.extend(new[j..].iter().map(|l| Op::Insert(l)));
+    ops
+}
+
+fn flush<'a>(rows: &mut Vec<DiffRow<'a>>, removed: &mut Vec<&'a str>, added: &mut Vec<&'a str>) {
+    let paired = removed.len().min(added.len());
+    rows.extend(
+        removed
+            .iter()
+            .zip(added.iter())
+            .map(|(old, new)| DiffRow::Changed(old, new)),
+    );
+    rows.extend(removed[paired..].iter().map(|l| DiffRow::Removed(l)));
+    rows.extend(added[paired..].iter().map(|l| DiffRow::Added(l)));
+    removed.clear();
+    added.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_lines, DiffRow};
+
+    #[test]
+    fn identical_texts_only_have_same_rows() {
+        let rows = diff_lines("a\nb", "a\nb");
+
+        assert_eq!(rows, [DiffRow::Same("a"), DiffRow::Same("b")]);
+    }
+
+    #[test]
+    fn replaced_lines_are_paired_up() {
+        let rows = diff_lines("a\nb\nc", "a\nB\nc");
+
+        assert_eq!(
+            rows,
+            [
+                DiffRow::Same("a"),
+                DiffRow::Changed("b", "B"),
+                DiffRow::Same("c")
+            ]
+        );
+    }
+
+    #[test]
+    fn added_and_removed_lines_stand_on_their_own() {
+        let rows = diff_lines("a\nb\nc", "a\nc\nd");
+
+        assert_eq!(
+            rows,
+            [
+                DiffRow::Same("a"),
+                DiffRow::Removed("b"),
+                DiffRow::Same("c"),
+                DiffRow::Added("d")
+            ]
+        );
+    }
+
+    #[test]
+    fn an_empty_text_is_entirely_added() {
+        let rows = diff_lines("", "a\nb");
+
+        assert_eq!(rows, [DiffRow::Added("a"), DiffRow::Added("b")]);
+    }
+}
@@ -5,11 +5,27 @@ use unicode_segmentation::UnicodeSegmentation;
 pub struct AdminPassword(Secret<String>);
 
 impl AdminPassword {
+    const MAX_LENGTH: usize = 128;
+    /// How many of lowercase letters, uppercase letters, digits and symbols
+    /// a password must mix.
+    const MIN_CHARACTER_CLASSES: usize = 3;
+
     pub fn parse(s: Secret<String>) -> Result<Self, String> {
-        let is_too_short = s.expose_secret().graphemes(true).count() <= 12;
+        let length = s.expose_secret().graphemes(true).count();
 
-        if is_too_short {
+        if length <= 12 {
             Err("Passwords must be longer than 12 characters.".into())
+        } else if length > Self::MAX_LENGTH {
+            Err(format!(
+                "Passwords must be at most {} characters long.",
+                Self::MAX_LENGTH
+            ))
+        } else if character_classes(s.expose_secret()) < Self::MIN_CHARACTER_CLASSES {
+            Err(
+                "Passwords must mix at least three of lowercase letters, uppercase letters, \
+                digits and symbols."
+                    .into(),
+            )
         } else {
             Ok(Self(s))
         }
@@ -20,6 +36,18 @@ impl AdminPassword {
     }
 }
 
+fn character_classes(s: &str) -> usize {
+    [
+        s.chars().any(char::is_lowercase),
+        s.chars().any(char::is_uppercase),
+        s.chars().any(|c| c.is_ascii_digit()),
+        s.chars().any(|c| !c.is_alphanumeric()),
+    ]
+    .into_iter()
+    .filter(|&present| present)
+    .count()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::domain::AdminPassword;
@@ -28,25 +56,49 @@ mod tests {
 
     #[test]
     fn a_13_grapheme_long_password_is_valid() {
-        let password = Secret::new("g̈".repeat(13));
+        let password = Secret::new(format!("{}A1", "g̈".repeat(11)));
         assert_ok!(AdminPassword::parse(password));
     }
 
     #[test]
     fn a_13_character_long_password_is_valid() {
-        let password = Secret::new("a".repeat(13));
+        let password = Secret::new(format!("{}A1", "a".repeat(11)));
         assert_ok!(AdminPassword::parse(password));
     }
 
     #[test]
     fn a_12_grapheme_long_password_is_invalid() {
-        let password = Secret::new("g̈".repeat(12));
+        let password = Secret::new(format!("{}A1", "g̈".repeat(10)));
         assert_err!(AdminPassword::parse(password));
     }
 
     #[test]
     fn a_12_character_long_password_is_invalid() {
-        let password = Secret::new("a".repeat(12));
+        let password = Secret::new(format!("{}A1", "a".repeat(10)));
         assert_err!(AdminPassword::parse(password));
     }
+
+    #[test]
+    fn a_128_character_long_password_is_valid() {
+        let password = Secret::new(format!("{}A1", "a".repeat(126)));
+        assert_ok!(AdminPassword::parse(password));
+    }
+
+    #[test]
+    fn a_129_character_long_password_is_invalid() {
+        let password = Secret::new(format!("{}A1", "a".repeat(127)));
+        assert_err!(AdminPassword::parse(password));
+    }
+
+    #[test]
+    fn a_password_mixing_only_two_character_classes_is_invalid() {
+        let password = Secret::new("abcdefghijkl123".into());
+        assert_err!(AdminPassword::parse(password));
+    }
+
+    #[test]
+    fn a_uuid_mixes_enough_character_classes() {
+        let password = Secret::new("8c9b2d1e-6a0f-4b8e-9c3d-2f1a0b9c8d7e".into());
+        assert_ok!(AdminPassword::parse(password));
+    }
 }
@@ -0,0 +1,87 @@
+use sha2::{Digest, Sha256};
+
+/// The two steps of a double opt-in, each recorded as proof of consent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsentAction {
+    Signup,
+    Confirmation,
+}
+
+impl ConsentAction {
+    pub const ALL: [ConsentAction; 2] = [ConsentAction::Signup, ConsentAction::Confirmation];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid consent action.", s))
+    }
+
+    /// How the action is stored.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentAction::Signup => "signup",
+            ConsentAction::Confirmation => "confirmation",
+        }
+    }
+}
+
+/// Where a subscriber consented from. Any of it may be missing, e.g. without a
+/// `User-Agent` header.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsentEvidence {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    /// The hex-encoded SHA-256 of the form the subscriber was shown, see
+    /// `with_rendered_form`.
+    pub form_hash: Option<String>,
+    /// The version of the policy accepted at signup, see `ConsentPolicySettings`.
+    pub policy_version: Option<String>,
+}
+
+impl ConsentEvidence {
+    /// Fingerprint the markup of the form the subscriber filled in - its wording, its
+    /// checkboxes and the policy they showed: the list owner can then show that a copy of
+    /// the form they kept is the one the subscriber saw.
+    pub fn with_rendered_form(mut self, rendered_form: &str) -> Self {
+        self.form_hash = Some(hex::encode(Sha256::digest(rendered_form.as_bytes())));
+        self
+    }
+
+    pub fn with_policy_version(mut self, version: Option<&str>) -> Self {
+        self.policy_version = version.map(String::from);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConsentAction, ConsentEvidence};
+    use claim::assert_err;
+
+    #[test]
+    fn actions_round_trip_through_their_stored_form() {
+        for action in ConsentAction::ALL {
+            assert_eq!(ConsentAction::parse(action.as_str()), Ok(action));
+        }
+        assert_err!(ConsentAction::parse("unsubscribe"));
+    }
+
+    #[test]
+    fn the_same_form_always_has_the_same_hash() {
+        let hash = |policy: &str| {
+            ConsentEvidence::default()
+                .with_rendered_form(&format!(
+                    r#"<form><input type="checkbox" value="{}"></form>"#,
+                    policy
+                ))
+                .form_hash
+                .unwrap()
+        };
+
+        assert_eq!(hash("2022-05"), hash("2022-05"));
+        assert_ne!(hash("2022-05"), hash("2022-06"));
+        assert_eq!(hash("2022-05").len(), 64);
+    }
+}
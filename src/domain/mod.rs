@@ -1,11 +1,17 @@
 mod admin_password;
+mod consent;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_status;
 mod subscription_token;
+mod unsubscribe_reason;
 
 pub use admin_password::AdminPassword;
-pub use new_subscriber::NewSubscriber;
+pub use consent::{ConsentAction, ConsentEvidence};
+pub use new_subscriber::{InvalidSubscriber, NewSubscriber};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscription_status::SubscriptionStatus;
 pub use subscription_token::SubscriptionToken;
+pub use unsubscribe_reason::UnsubscribeReason;
@@ -1,7 +1,170 @@
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
+use crate::i18n::Locale;
+use std::fmt::Formatter;
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
-    pub name: SubscriberName,
+    /// `None` if names are not required and they did not give theirs.
+    pub name: Option<SubscriberName>,
+    /// The language they chose to receive our emails in, if any.
+    pub locale: Option<Locale>,
+}
+
+impl NewSubscriber {
+    /// Every field is validated, so that every mistake can be reported at once.
+    /// An empty name or locale counts as none.
+    pub fn parse(
+        name: Option<String>,
+        email: String,
+        locale: Option<String>,
+        max_name_length: usize,
+        require_name: bool,
+    ) -> Result<NewSubscriber, InvalidSubscriber> {
+        let name = match name.filter(|n| !n.trim().is_empty()) {
+            Some(name) => SubscriberName::parse_with_max_length(name, max_name_length).map(Some),
+            None if require_name => SubscriberName::parse(String::new()).map(Some),
+            None => Ok(None),
+        };
+        let email = SubscriberEmail::parse(email);
+        let locale = locale
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| Locale::parse(l.trim()))
+            .transpose();
+        match (name, email, locale) {
+            (Ok(name), Ok(email), Ok(locale)) => Ok(NewSubscriber {
+                email,
+                name,
+                locale,
+            }),
+            (name, email, locale) => Err(InvalidSubscriber {
+                name: name.err(),
+                email: email.err(),
+                locale: locale.err(),
+                email_confirmation: None,
+                accepted_policy: None,
+            }),
+        }
+    }
+}
+
+/// Why each of the rejected fields of a would-be subscriber is invalid.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct InvalidSubscriber {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// The address typed a second time, on forms asking for it, differs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_confirmation: Option<String>,
+    /// The policy, when subscribers must accept it, was not.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accepted_policy: Option<String>,
+}
+
+impl std::fmt::Display for InvalidSubscriber {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reasons: Vec<&str> = [
+            &self.name,
+            &self.email,
+            &self.email_confirmation,
+            &self.locale,
+            &self.accepted_policy,
+        ]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+        write!(f, "{}", reasons.join(" "))
+    }
+}
+
+impl std::error::Error for InvalidSubscriber {}
+
+#[cfg(test)]
+mod tests {
+    use super::NewSubscriber;
+    use claim::{assert_none, assert_ok, assert_some};
+
+    #[test]
+    fn a_valid_name_and_email_are_accepted() {
+        assert_ok!(NewSubscriber::parse(
+            Some("Ursula".into()),
+            "ursula@domain.com".into(),
+            None,
+            256,
+            true
+        ));
+    }
+
+    #[test]
+    fn the_locale_must_be_supported() {
+        let parse = |locale: &str| {
+            NewSubscriber::parse(
+                Some("Ursula".into()),
+                "ursula@domain.com".into(),
+                Some(locale.into()),
+                256,
+                true,
+            )
+        };
+
+        assert_eq!(parse("fr-CH").unwrap().locale.unwrap().language(), "fr");
+        assert_none!(parse(" ").unwrap().locale);
+        let e = parse("klingon").err().unwrap();
+        assert_none!(e.name);
+        assert_some!(e.locale);
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let e = NewSubscriber::parse(Some("".into()), "ursuladomain.com".into(), None, 256, true)
+            .err()
+            .unwrap();
+
+        assert_some!(&e.name);
+        assert_some!(&e.email);
+        assert_eq!(
+            e.to_string(),
+            format!("{} {}", e.name.unwrap(), e.email.unwrap())
+        );
+    }
+
+    #[test]
+    fn valid_fields_are_not_reported() {
+        let e = NewSubscriber::parse(
+            Some("Ursula".into()),
+            "ursuladomain.com".into(),
+            None,
+            256,
+            true,
+        )
+        .err()
+        .unwrap();
+
+        assert_none!(e.name);
+        assert_some!(e.email);
+    }
+
+    #[test]
+    fn the_name_is_optional_unless_required() {
+        let parse = |name: Option<&str>, require_name| {
+            NewSubscriber::parse(
+                name.map(Into::into),
+                "ursula@domain.com".into(),
+                None,
+                256,
+                require_name,
+            )
+        };
+
+        assert_none!(parse(None, false).unwrap().name);
+        assert_none!(parse(Some("  "), false).unwrap().name);
+        assert_some!(parse(None, true).err().unwrap().name);
+        // A name given anyway must still be valid.
+        assert_some!(parse(Some("<Ursula>"), false).err().unwrap().name);
+    }
 }
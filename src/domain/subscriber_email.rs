@@ -1,7 +1,7 @@
 use std::fmt::Formatter;
 use validator::validate_email;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
@@ -12,6 +12,14 @@ impl SubscriberEmail {
             Err(format!("{} is not a valid subscriber email.", s))
         }
     }
+
+    /// The address with its local part hidden but for the first character,
+    /// e.g. `u***@gmail.com`, to show it back without disclosing it.
+    pub fn masked(&self) -> String {
+        let (local_part, domain) = self.0.rsplit_once('@').unwrap_or((&self.0, ""));
+        let first = local_part.chars().next().unwrap_or('*');
+        format!("{}***@{}", first, domain)
+    }
 }
 
 impl AsRef<str> for SubscriberEmail {
@@ -65,4 +73,10 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn masking_keeps_the_first_character_and_the_domain() {
+        let email = SubscriberEmail::parse("ursula_le_guin@gmail.com".into()).unwrap();
+        assert_eq!(email.masked(), "u***@gmail.com");
+    }
 }
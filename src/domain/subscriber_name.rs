@@ -1,25 +1,52 @@
 use unicode_segmentation::UnicodeSegmentation;
 
+/// The longest name we accept, in graphemes, unless configured otherwise.
+pub const DEFAULT_MAX_NAME_LENGTH: usize = 256;
+
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
 impl SubscriberName {
     pub fn parse(s: String) -> Result<SubscriberName, String> {
-        let is_empty_or_whitespace = s.trim().is_empty();
+        Self::parse_with_max_length(s, DEFAULT_MAX_NAME_LENGTH)
+    }
+
+    /// Names end up in the admin pages and in emails, so we normalise their shape:
+    /// surrounding whitespace is trimmed, inner runs of whitespace become a single space
+    /// and characters that are invisible or reorder the text around them are rejected.
+    pub fn parse_with_max_length(s: String, max_length: usize) -> Result<SubscriberName, String> {
+        let name = s.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        let is_empty = name.is_empty();
 
-        let is_too_long = s.graphemes(true).count() > 256;
+        let is_too_long = name.graphemes(true).count() > max_length;
 
         let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
+        let contains_forbidden_characters = name
+            .chars()
+            .any(|c| forbidden_characters.contains(&c) || c.is_control() || is_invisible(c));
 
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("{} is not a valid subscriber name.", s))
+        if is_empty || is_too_long || contains_forbidden_characters {
+            Err(format!("{:?} is not a valid subscriber name.", s))
         } else {
-            Ok(Self(s))
+            Ok(Self(name))
         }
     }
 }
 
+/// Zero-width characters and bidirectional formatting characters.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{061C}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{FEFF}'
+    )
+}
+
 impl AsRef<str> for SubscriberName {
     fn as_ref(&self) -> &str {
         &self.0
@@ -62,6 +89,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn names_containing_control_characters_are_rejected() {
+        for name in ["Ursula\u{0}", "Ursula\u{7}Le Guin", "\u{1b}[31mUrsula"] {
+            assert_err!(SubscriberName::parse(name.into()));
+        }
+    }
+
+    #[test]
+    fn names_containing_invisible_characters_are_rejected() {
+        for c in ['\u{200B}', '\u{200D}', '\u{202E}', '\u{2066}', '\u{FEFF}'] {
+            let name = format!("Ursula{}Le Guin", c);
+            assert_err!(SubscriberName::parse(name));
+        }
+    }
+
+    #[test]
+    fn whitespace_is_trimmed_and_collapsed() {
+        let name = SubscriberName::parse("  Ursula \t\n Le   Guin ".into()).unwrap();
+        assert_eq!(name.as_ref(), "Ursula Le Guin");
+    }
+
+    #[test]
+    fn the_length_is_checked_after_collapsing_whitespace() {
+        let name = format!("{}   {}", "a".repeat(127), "a".repeat(128));
+        assert_ok!(SubscriberName::parse(name));
+    }
+
+    #[test]
+    fn the_maximum_length_can_be_configured() {
+        assert_ok!(SubscriberName::parse_with_max_length("Ursula".into(), 6));
+        assert_err!(SubscriberName::parse_with_max_length("Ursula".into(), 5));
+    }
+
     #[test]
     fn a_valid_name_is_parsed_successfully() {
         let name = "Ursula Le Guin".to_string();
@@ -1,7 +1,7 @@
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SubscriptionToken(String);
 
 impl SubscriptionToken {
@@ -0,0 +1,50 @@
+/// Why a subscriber left, as answered on the unsubscribe page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsubscribeReason {
+    TooFrequent,
+    NotRelevant,
+    Other,
+}
+
+impl UnsubscribeReason {
+    pub const ALL: [UnsubscribeReason; 3] = [
+        UnsubscribeReason::TooFrequent,
+        UnsubscribeReason::NotRelevant,
+        UnsubscribeReason::Other,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|reason| reason.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid unsubscribe reason.", s))
+    }
+
+    /// How the reason is stored and submitted.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnsubscribeReason::TooFrequent => "too_frequent",
+            UnsubscribeReason::NotRelevant => "not_relevant",
+            UnsubscribeReason::Other => "other",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeReason;
+    use claim::assert_err;
+
+    #[test]
+    fn reasons_round_trip_through_their_stored_form() {
+        for reason in UnsubscribeReason::ALL {
+            assert_eq!(UnsubscribeReason::parse(reason.as_str()), Ok(reason));
+        }
+    }
+
+    #[test]
+    fn unknown_reasons_are_rejected() {
+        assert_err!(UnsubscribeReason::parse("bored"));
+        assert_err!(UnsubscribeReason::parse(""));
+    }
+}
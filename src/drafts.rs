@@ -0,0 +1,494 @@
+//! The content of the compose form, autosaved so that a crashed browser does not lose it.
+//!
+//! Every save changing the content is also kept as a revision of the draft, which can be
+//! compared with the others and restored until the draft is published. Only the latest
+//! `MAX_REVISIONS` are kept.
+//!
+//! Drafts are shared by the admins of their tenant, one editor at a time: saving a draft
+//! takes its lock, which the compose form keeps alive with a heartbeat. Others cannot
+//! save the draft until the lock has gone without a heartbeat for the lock TTL.
+//!
+//! The other admins review a draft through its comment thread, resolving each comment
+//! once it has been addressed.
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The revisions kept for each draft: older ones are pruned as new ones are saved.
+const MAX_REVISIONS: i32 = 50;
+
+#[derive(Debug)]
+pub struct Draft {
+    /// Also the idempotency key of the form the draft is written in.
+    pub draft_id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub saved_at: DateTime<Utc>,
+}
+
+/// A draft listed on the compose form.
+#[derive(Debug)]
+pub struct DraftSummary {
+    pub draft_id: Uuid,
+    pub title: String,
+    pub saved_at: DateTime<Utc>,
+    /// Who holds the lock, unless it is stale.
+    pub editor: Option<String>,
+}
+
+/// Review feedback on a draft.
+#[derive(Debug)]
+pub struct DraftComment {
+    pub comment_id: Uuid,
+    /// The username of the admin who wrote it.
+    pub author: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+impl DraftComment {
+    pub fn is_resolved(&self) -> bool {
+        self.resolved_at.is_some()
+    }
+}
+
+/// What a save writes to a draft.
+pub struct DraftContent<'a> {
+    pub title: &'a str,
+    pub text_content: &'a str,
+    pub html_content: &'a str,
+}
+
+/// The content of a draft as of one of its saves.
+#[derive(Debug)]
+pub struct DraftRevision {
+    /// Counts the saves of the draft which changed its content, from 1.
+    pub revision: i32,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub saved_at: DateTime<Utc>,
+}
+
+/// Store the latest content of a draft, taking its lock, and as a new revision unless it
+/// is the content of the latest one. Returns `false` if another user holds the lock.
+#[tracing::instrument(skip(pool, content))]
+pub async fn save_draft(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    user_id: Uuid,
+    draft_id: Uuid,
+    content: DraftContent<'_>,
+    lock_ttl: Duration,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let saved = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (
+            draft_id, tenant_id, user_id, title, text_content, html_content, saved_at,
+            locked_by, lock_heartbeat_at
+        )
+        VALUES ($1, $7, $2, $3, $4, $5, now(), $2, now())
+        ON CONFLICT (draft_id) DO UPDATE
+        SET
+            title = EXCLUDED.title,
+            text_content = EXCLUDED.text_content,
+            html_content = EXCLUDED.html_content,
+            saved_at = EXCLUDED.saved_at,
+            locked_by = EXCLUDED.locked_by,
+            lock_heartbeat_at = EXCLUDED.lock_heartbeat_at
+        WHERE newsletter_drafts.tenant_id = EXCLUDED.tenant_id
+            AND (
+                newsletter_drafts.locked_by = EXCLUDED.locked_by
+                OR newsletter_drafts.lock_heartbeat_at < now() - make_interval(secs => $6)
+            )
+        "#,
+        draft_id,
+        user_id,
+        content.title,
+        content.text_content,
+        content.html_content,
+        lock_ttl.as_secs_f64(),
+        *tenant_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to save a draft")?
+    .rows_affected();
+    if saved != 1 {
+        return Ok(false);
+    }
+    // The draft row is locked until the commit: concurrent saves get distinct numbers.
+    let stored = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_draft_revisions (
+            draft_id, tenant_id, revision, title, text_content, html_content, saved_at
+        )
+        SELECT
+            d.draft_id,
+            d.tenant_id,
+            COALESCE(latest.revision, 0) + 1,
+            d.title, d.text_content, d.html_content, d.saved_at
+        FROM newsletter_drafts d
+        LEFT JOIN LATERAL (
+            SELECT revision, title, text_content, html_content
+            FROM newsletter_draft_revisions
+            WHERE draft_id = d.draft_id
+            ORDER BY revision DESC
+            LIMIT 1
+        ) latest ON true
+        WHERE d.draft_id = $1
+            AND (latest.title, latest.text_content, latest.html_content)
+                IS DISTINCT FROM (d.title, d.text_content, d.html_content)
+        RETURNING revision
+        "#,
+        draft_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to store a revision of a draft")?;
+    if let Some(stored) = stored {
+        sqlx::query!(
+            r#"
+            DELETE FROM newsletter_draft_revisions
+            WHERE draft_id = $1 AND revision <= $2
+            "#,
+            draft_id,
+            stored.revision - MAX_REVISIONS
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to prune the revisions of a draft")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to save a draft")?;
+    Ok(true)
+}
+
+/// Keep editing a draft: take its lock, or refresh it. Returns who holds the lock if it
+/// is someone else - nobody does before the first save of the draft.
+#[tracing::instrument(skip(pool))]
+pub async fn lock_draft(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    user_id: Uuid,
+    draft_id: Uuid,
+    lock_ttl: Duration,
+) -> Result<Option<String>, anyhow::Error> {
+    let locked = sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+        SET locked_by = $2, lock_heartbeat_at = now()
+        WHERE draft_id = $1
+            AND tenant_id = $4
+            AND (locked_by = $2 OR lock_heartbeat_at < now() - make_interval(secs => $3))
+        "#,
+        draft_id,
+        user_id,
+        lock_ttl.as_secs_f64(),
+        *tenant_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to lock a draft")?
+    .rows_affected();
+    if locked == 1 {
+        return Ok(None);
+    }
+    get_draft_editor(pool, tenant_id, user_id, draft_id, lock_ttl).await
+}
+
+/// Who is editing the draft, if someone other than the user is.
+#[tracing::instrument(skip(pool))]
+pub async fn get_draft_editor(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    user_id: Uuid,
+    draft_id: Uuid,
+    lock_ttl: Duration,
+) -> Result<Option<String>, anyhow::Error> {
+    let editor = sqlx::query!(
+        r#"
+        SELECT u.username
+        FROM newsletter_drafts d
+        JOIN users u ON u.user_id = d.locked_by
+        WHERE d.draft_id = $1
+            AND d.tenant_id = $4
+            AND d.locked_by <> $2
+            AND d.lock_heartbeat_at >= now() - make_interval(secs => $3)
+        "#,
+        draft_id,
+        user_id,
+        lock_ttl.as_secs_f64(),
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the editor of a draft")?;
+    Ok(editor.map(|r| r.username))
+}
+
+/// The drafts not published yet, latest saved first.
+#[tracing::instrument(skip(pool))]
+pub async fn list_drafts(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    lock_ttl: Duration,
+) -> Result<Vec<DraftSummary>, anyhow::Error> {
+    sqlx::query_as!(
+        DraftSummary,
+        r#"
+        SELECT
+            d.draft_id,
+            d.title,
+            d.saved_at,
+            CASE
+                WHEN d.lock_heartbeat_at >= now() - make_interval(secs => $1) THEN u.username
+            END AS editor
+        FROM newsletter_drafts d
+        JOIN users u ON u.user_id = d.locked_by
+        WHERE d.tenant_id = $2
+        ORDER BY d.saved_at DESC
+        "#,
+        lock_ttl.as_secs_f64(),
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the drafts")
+}
+
+/// A draft, if it was not published yet.
+#[tracing::instrument(skip(pool))]
+pub async fn get_draft(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    draft_id: Uuid,
+) -> Result<Option<Draft>, anyhow::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT draft_id, title, text_content, html_content, saved_at
+        FROM newsletter_drafts
+        WHERE draft_id = $1 AND tenant_id = $2
+        "#,
+        draft_id,
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a draft")
+}
+
+/// The revisions of a draft, latest first.
+#[tracing::instrument(skip(pool))]
+pub async fn list_revisions(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    draft_id: Uuid,
+) -> Result<Vec<DraftRevision>, anyhow::Error> {
+    sqlx::query_as!(
+        DraftRevision,
+        r#"
+        SELECT revision, title, text_content, html_content, saved_at
+        FROM newsletter_draft_revisions
+        WHERE draft_id = $1 AND tenant_id = $2
+        ORDER BY revision DESC
+        "#,
+        draft_id,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the revisions of a draft")
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_revision(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    draft_id: Uuid,
+    revision: i32,
+) -> Result<Option<DraftRevision>, anyhow::Error> {
+    sqlx::query_as!(
+        DraftRevision,
+        r#"
+        SELECT revision, title, text_content, html_content, saved_at
+        FROM newsletter_draft_revisions
+        WHERE draft_id = $1 AND revision = $2 AND tenant_id = $3
+        "#,
+        draft_id,
+        revision,
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a revision of a draft")
+}
+
+/// Make an older revision the content of the draft again - as a new revision, so that
+/// restoring can be undone. Returns `false` if there is no such revision, or if another
+/// user holds the lock of the draft.
+#[tracing::instrument(skip(pool))]
+pub async fn restore_revision(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    user_id: Uuid,
+    draft_id: Uuid,
+    revision: i32,
+    lock_ttl: Duration,
+) -> Result<bool, anyhow::Error> {
+    let revision = match get_revision(pool, tenant_id, draft_id, revision).await? {
+        Some(revision) => revision,
+        None => return Ok(false),
+    };
+    save_draft(
+        pool,
+        tenant_id,
+        user_id,
+        draft_id,
+        DraftContent {
+            title: &revision.title,
+            text_content: &revision.text_content,
+            html_content: &revision.html_content,
+        },
+        lock_ttl,
+    )
+    .await
+}
+
+/// The latest draft whose lock the user holds, or held last, if any.
+#[tracing::instrument(skip(pool))]
+pub async fn get_latest_draft(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<Draft>, anyhow::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT draft_id, title, text_content, html_content, saved_at
+        FROM newsletter_drafts
+        WHERE locked_by = $1
+        ORDER BY saved_at DESC
+        LIMIT 1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the latest draft")
+}
+
+/// Comment on a draft. Returns `false` if there is no such draft.
+#[tracing::instrument(skip(pool, body))]
+pub async fn add_comment(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    user_id: Uuid,
+    draft_id: Uuid,
+    body: &str,
+) -> Result<bool, anyhow::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO draft_comments (comment_id, draft_id, author_id, body, created_at)
+        SELECT $1, draft_id, $3, $4, now()
+        FROM newsletter_drafts
+        WHERE draft_id = $2 AND tenant_id = $5
+        "#,
+        Uuid::new_v4(),
+        draft_id,
+        user_id,
+        body,
+        *tenant_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a comment on a draft")?
+    .rows_affected();
+    Ok(inserted == 1)
+}
+
+/// The comments on a draft, oldest first.
+#[tracing::instrument(skip(pool))]
+pub async fn list_comments(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    draft_id: Uuid,
+) -> Result<Vec<DraftComment>, anyhow::Error> {
+    sqlx::query_as!(
+        DraftComment,
+        r#"
+        SELECT c.comment_id, u.username AS author, c.body, c.created_at, c.resolved_at
+        FROM draft_comments c
+        JOIN newsletter_drafts d ON d.draft_id = c.draft_id
+        JOIN users u ON u.user_id = c.author_id
+        WHERE c.draft_id = $1 AND d.tenant_id = $2
+        ORDER BY c.created_at
+        "#,
+        draft_id,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the comments on a draft")
+}
+
+/// Mark a comment as addressed, or as not addressed anymore. Returns `false` if there is
+/// no such comment.
+#[tracing::instrument(skip(pool))]
+pub async fn resolve_comment(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    draft_id: Uuid,
+    comment_id: Uuid,
+    resolved: bool,
+) -> Result<bool, anyhow::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE draft_comments c
+        SET resolved_at = CASE WHEN $3 THEN COALESCE(c.resolved_at, now()) END
+        FROM newsletter_drafts d
+        WHERE c.draft_id = $1
+            AND c.comment_id = $2
+            AND d.draft_id = c.draft_id
+            AND d.tenant_id = $4
+        "#,
+        draft_id,
+        comment_id,
+        resolved,
+        *tenant_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to resolve a comment on a draft")?
+    .rows_affected();
+    Ok(updated == 1)
+}
+
+/// Drop a draft once it has been published.
+#[tracing::instrument(skip(transaction))]
+pub async fn delete_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    draft_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM newsletter_drafts WHERE draft_id = $1 AND tenant_id = $2"#,
+        draft_id,
+        *tenant_id
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to delete a draft")?;
+    Ok(())
+}
@@ -1,4 +1,6 @@
 use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use reqwest::header::HeaderValue;
 use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
 
@@ -17,6 +19,8 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
 }
 
 impl EmailClient {
@@ -41,32 +45,146 @@ impl EmailClient {
         subject: &str,
         html_content: &str,
         text_content: &str,
+    ) -> Result<(), reqwest::Error> {
+        self.send(
+            None,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            None,
+            None,
+        )
+        .await
+    }
+
+    /// Send an email tagged with `tag`.
+    /// The provider includes the tag in the events it sends back about this email.
+    pub async fn send_tagged_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: &str,
+    ) -> Result<(), reqwest::Error> {
+        self.send(
+            None,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            Some(tag),
+            None,
+        )
+        .await
+    }
+
+    /// Send an email from `sender` - the configured sender address if `None` - tagged
+    /// with `tag` if there is one.
+    pub async fn send_email_from(
+        &self,
+        sender: Option<&SubscriberEmail>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: Option<&str>,
+    ) -> Result<(), reqwest::Error> {
+        self.send(
+            sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            tag,
+            None,
+        )
+        .await
+    }
+
+    /// Send like `send_email_from`, with an `Idempotency-Key` header: the provider
+    /// accepts a single email per key, however many times the request is retried.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn send_email_once(
+        &self,
+        sender: Option<&SubscriberEmail>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: Option<&str>,
+        idempotency_key: &str,
+    ) -> Result<(), reqwest::Error> {
+        self.send(
+            sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            tag,
+            Some(idempotency_key),
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn send(
+        &self,
+        sender: Option<&SubscriberEmail>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: Option<&str>,
+        idempotency_key: Option<&str>,
     ) -> Result<(), reqwest::Error> {
         let url = reqwest::Url::parse(&self.base_url)
             .unwrap()
             .join("email")
             .unwrap();
         let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
+            from: sender.unwrap_or(&self.sender).as_ref(),
             to: recipient.as_ref(),
             subject,
             html_body: html_content,
             text_body: text_content,
+            tag,
         };
 
-        let _builder = self
-            .http_client
-            .post(url)
-            .header(
-                "X-Postmark-Server-Token",
-                self.authorization_token.expose_secret(),
-            )
+        let mut request = self.http_client.post(url).header(
+            "X-Postmark-Server-Token",
+            self.authorization_token.expose_secret(),
+        );
+        if let Some(key) = idempotency_key {
+            request = request.header("Idempotency-Key", key);
+        }
+        let _builder = request
             .json(&request_body)
             .send()
             .await?
             .error_for_status()?;
         Ok(())
     }
+
+    /// Issue a no-op request to the provider to make sure it accepts our authorization token.
+    /// A token which cannot even be sent as a header is rejected the way the provider
+    /// would reject it, as unauthorized.
+    pub async fn verify_authorization_token(&self) -> Result<(), anyhow::Error> {
+        let url = reqwest::Url::parse(&self.base_url)
+            .and_then(|url| url.join("server"))
+            .context("The base URL of the email provider is invalid")?;
+        let token = HeaderValue::from_str(self.authorization_token.expose_secret())
+            .map_err(|_| anyhow::anyhow!("401 Unauthorized: the token is not a valid header"))?;
+        self.http_client
+            .get(url)
+            .header("X-Postmark-Server-Token", token)
+            .header("Accept", "application/json")
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -78,7 +196,7 @@ mod tests {
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use secrecy::Secret;
-    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
     struct SendEmailBodyMatcher;
@@ -147,6 +265,27 @@ mod tests {
         // Mock expectations are checked on drop!
     }
 
+    #[tokio::test]
+    async fn send_tagged_email_includes_the_tag() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .and(body_partial_json(serde_json::json!({"Tag": "a-tag"})))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_tagged_email(&email(), &subject(), &content(), &content(), "a-tag")
+            .await;
+
+        assert_ok!(outcome);
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
         let mock_server = MockServer::start().await;
@@ -183,6 +322,64 @@ mod tests {
         assert_err!(outcome);
     }
 
+    #[tokio::test]
+    async fn verify_authorization_token_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/server"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.verify_authorization_token().await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn verify_authorization_token_fails_if_the_server_returns_401() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.verify_authorization_token().await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn a_token_which_is_not_a_valid_header_is_unauthorized() {
+        let mock_server = MockServer::start().await;
+        let email_client = EmailClient::new(
+            mock_server.uri(),
+            email(),
+            Secret::new("my-token\n".into()),
+            std::time::Duration::from_millis(200),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.verify_authorization_token().await;
+
+        assert!(outcome
+            .unwrap_err()
+            .to_string()
+            .contains("401 Unauthorized"));
+    }
+
     #[tokio::test]
     async fn send_email_times_out_if_server_takes_too_long() {
         let mock_server = MockServer::start().await;
@@ -0,0 +1,303 @@
+//! Delivering issues through a chain of email providers: the primary one, then the
+//! fallback one while the primary is down.
+//!
+//! Each provider has its own circuit breaker, and is skipped while it is open. A server
+//! error counts against the breaker of the provider: the email is handed to the next
+//! provider only once the breaker opens - the provider is down, not having a hiccup -
+//! and is otherwise left for the delivery to be retried. Client errors - e.g. a rejected
+//! recipient - would fail the same way everywhere: they are returned as they are.
+//!
+//! A request which timed out may have been accepted all the same: it is retried on the
+//! same provider, with the same idempotency key, and never handed to the next one - that
+//! could send the email twice.
+use crate::circuit_breaker::CircuitBreaker;
+use crate::configuration::EmailClientSettings;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::services::EmailSender;
+use uuid::Uuid;
+
+pub const PRIMARY: &str = "primary";
+pub const FALLBACK: &str = "fallback";
+
+/// How many times a request which timed out is sent again to the same provider.
+const TIMEOUT_RETRIES: u32 = 2;
+
+struct Provider {
+    name: &'static str,
+    client: EmailClient,
+    breaker: CircuitBreaker,
+}
+
+pub struct FailoverEmailSender {
+    providers: Vec<Provider>,
+}
+
+impl FailoverEmailSender {
+    pub fn new(primary: EmailClient, breaker: CircuitBreaker) -> Self {
+        Self {
+            providers: vec![Provider {
+                name: PRIMARY,
+                client: primary,
+                breaker,
+            }],
+        }
+    }
+
+    /// Fail over to `fallback` when the primary provider is down.
+    pub fn with_fallback(mut self, fallback: EmailClient, breaker: CircuitBreaker) -> Self {
+        self.providers.push(Provider {
+            name: FALLBACK,
+            client: fallback,
+            breaker,
+        });
+        self
+    }
+
+    pub fn from_settings(settings: &EmailClientSettings) -> Self {
+        let sender = Self::new(
+            settings.clone().client(),
+            CircuitBreaker::from_settings(settings),
+        );
+        match settings.fallback_client() {
+            Some(fallback) => {
+                sender.with_fallback(fallback, CircuitBreaker::from_settings(settings))
+            }
+            None => sender,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for FailoverEmailSender {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.send_email_from(None, recipient, subject, html_content, text_content, None)
+            .await
+    }
+
+    async fn send_email_from(
+        &self,
+        sender: Option<&SubscriberEmail>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        self.send_email_via_provider(sender, recipient, subject, html_content, text_content, tag)
+            .await?;
+        Ok(())
+    }
+
+    async fn send_email_via_provider(
+        &self,
+        sender: Option<&SubscriberEmail>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: Option<&str>,
+    ) -> Result<Option<&'static str>, anyhow::Error> {
+        let mut last_error = None;
+        for provider in &self.providers {
+            if provider.breaker.is_open() {
+                continue;
+            }
+            let idempotency_key = Uuid::new_v4().to_string();
+            let mut retries = 0;
+            let outcome = loop {
+                let outcome = provider
+                    .client
+                    .send_email_once(
+                        sender,
+                        recipient,
+                        subject,
+                        html_content,
+                        text_content,
+                        tag,
+                        &idempotency_key,
+                    )
+                    .await;
+                match outcome {
+                    Err(e) if e.is_timeout() && retries < TIMEOUT_RETRIES => {
+                        tracing::warn!(
+                            provider = provider.name,
+                            error.cause_chain = ?e,
+                            "The email provider timed out, sending the email again"
+                        );
+                        retries += 1;
+                    }
+                    outcome => break outcome,
+                }
+            };
+            match outcome {
+                Ok(()) => {
+                    provider.breaker.record_success();
+                    return Ok(Some(provider.name));
+                }
+                // The provider is up: it is the email it refused.
+                Err(e) if e.status().is_some_and(|s| s.is_client_error()) => {
+                    provider.breaker.record_success();
+                    return Err(e.into());
+                }
+                Err(e) if e.is_timeout() => {
+                    provider.breaker.record_failure();
+                    return Err(anyhow::anyhow!(e).context(format!(
+                        "The {} email provider timed out: it may have sent the email",
+                        provider.name
+                    )));
+                }
+                Err(e) => {
+                    provider.breaker.record_failure();
+                    if !provider.breaker.is_open() {
+                        return Err(anyhow::anyhow!(e)
+                            .context(format!("The {} email provider failed", provider.name)));
+                    }
+                    tracing::warn!(
+                        provider = provider.name,
+                        error.cause_chain = ?e,
+                        "The email provider keeps failing, failing over to the next one"
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) => Err(anyhow::anyhow!(e).context("Every email provider failed")),
+            None => Err(anyhow::anyhow!(
+                "Every email provider is unavailable until its circuit breaker closes"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FailoverEmailSender, FALLBACK, PRIMARY, TIMEOUT_RETRIES};
+    use crate::circuit_breaker::CircuitBreaker;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::EmailClient;
+    use crate::services::EmailSender;
+    use claim::assert_err;
+    use secrecy::Secret;
+    use std::time::Duration;
+    use wiremock::matchers::any;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn client(server: &MockServer) -> EmailClient {
+        EmailClient::new(
+            server.uri(),
+            SubscriberEmail::parse("sender@example.com".into()).unwrap(),
+            Secret::new("token".into()),
+            Duration::from_millis(200),
+        )
+    }
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(2, Duration::from_secs(60))
+    }
+
+    async fn send(sender: &FailoverEmailSender) -> Result<Option<&'static str>, anyhow::Error> {
+        let recipient = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+        sender
+            .send_email_via_provider(None, &recipient, "Subject", "<p>Hi</p>", "Hi", None)
+            .await
+    }
+
+    async fn provider_answering(status: u16, expected_calls: u64) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(status))
+            .expect(expected_calls)
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn the_primary_provider_is_used_while_it_works() {
+        let primary = provider_answering(200, 1).await;
+        let fallback = provider_answering(200, 0).await;
+        let sender = FailoverEmailSender::new(client(&primary), breaker())
+            .with_fallback(client(&fallback), breaker());
+
+        assert_eq!(send(&sender).await.unwrap(), Some(PRIMARY));
+    }
+
+    #[tokio::test]
+    async fn a_single_server_error_does_not_fail_over() {
+        let primary = provider_answering(503, 1).await;
+        let fallback = provider_answering(200, 0).await;
+        let sender = FailoverEmailSender::new(client(&primary), breaker())
+            .with_fallback(client(&fallback), breaker());
+
+        assert_err!(send(&sender).await);
+    }
+
+    #[tokio::test]
+    async fn emails_fail_over_once_the_breaker_of_the_primary_provider_opens() {
+        // The second failure opens the breaker: that email and the next go to the fallback.
+        let primary = provider_answering(500, 2).await;
+        let fallback = provider_answering(200, 2).await;
+        let sender = FailoverEmailSender::new(client(&primary), breaker())
+            .with_fallback(client(&fallback), breaker());
+
+        assert_err!(send(&sender).await);
+        for _ in 0..2 {
+            assert_eq!(send(&sender).await.unwrap(), Some(FALLBACK));
+        }
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_retried_on_the_same_provider_with_the_same_key() {
+        let primary = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+            .expect(1 + u64::from(TIMEOUT_RETRIES))
+            .mount(&primary)
+            .await;
+        let fallback = provider_answering(200, 0).await;
+        let sender = FailoverEmailSender::new(client(&primary), breaker())
+            .with_fallback(client(&fallback), breaker());
+
+        assert_err!(send(&sender).await);
+        let keys: Vec<_> = primary
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|r| {
+                r.headers
+                    .get(&"Idempotency-Key".into())
+                    .unwrap()
+                    .as_str()
+                    .to_owned()
+            })
+            .collect();
+        assert!(keys.windows(2).all(|pair| pair[0] == pair[1]));
+    }
+
+    #[tokio::test]
+    async fn client_errors_do_not_fail_over() {
+        let primary = provider_answering(422, 1).await;
+        let fallback = provider_answering(200, 0).await;
+        let sender = FailoverEmailSender::new(client(&primary), breaker())
+            .with_fallback(client(&fallback), breaker());
+
+        assert_err!(send(&sender).await);
+    }
+
+    #[tokio::test]
+    async fn an_error_is_returned_when_every_provider_fails() {
+        let primary = provider_answering(500, 1).await;
+        let sender = FailoverEmailSender::new(client(&primary), breaker());
+
+        assert_err!(send(&sender).await);
+    }
+}
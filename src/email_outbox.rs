@@ -0,0 +1,179 @@
+//! Confirmation emails which could not be sent when the subscriber signed up, e.g.
+//! during an outage of the email provider.
+//!
+//! The `deliver_outbox_emails` scheduled job sends them later, oldest first, and stops
+//! at the first failure: the provider is likely still unavailable. The email which failed
+//! is retried later and later, so that one the provider keeps rejecting does not hold
+//! the others back, and is set aside - dead-lettered - after `MAX_ATTEMPTS`.
+use crate::domain::SubscriberEmail;
+use crate::services::{EmailOutbox, EmailSender, OutboxEmail};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// An email still failing after this many attempts is dead-lettered.
+const MAX_ATTEMPTS: i32 = 8;
+/// The wait before the second attempt, doubled after each failure.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+pub struct PostgresEmailOutbox<'a> {
+    pool: &'a PgPool,
+    tenant_id: TenantId,
+}
+
+impl<'a> PostgresEmailOutbox<'a> {
+    pub fn new(pool: &'a PgPool, tenant_id: TenantId) -> Self {
+        Self { pool, tenant_id }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailOutbox for PostgresEmailOutbox<'_> {
+    #[tracing::instrument(name = "Put an email in the outbox", skip_all)]
+    async fn enqueue(&self, email: OutboxEmail) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO email_outbox (
+                email_id, tenant_id, sender, recipient, subject, html_content,
+                text_content, enqueued_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+            "#,
+            Uuid::new_v4(),
+            *self.tenant_id,
+            email.sender,
+            email.recipient,
+            email.subject,
+            email.html_content,
+            email.text_content
+        )
+        .execute(self.pool)
+        .await
+        .context("Failed to insert an email in the outbox")?;
+        Ok(())
+    }
+}
+
+/// Send the emails waiting in the outbox. Returns how many were sent.
+#[tracing::instrument(name = "Deliver the emails of the outbox", skip_all)]
+pub async fn deliver_outbox_emails(
+    pool: &PgPool,
+    email_sender: &dyn EmailSender,
+) -> Result<u64, anyhow::Error> {
+    let mut delivered = 0;
+    loop {
+        let mut transaction = pool.begin().await?;
+        let email = sqlx::query!(
+            r#"
+            SELECT email_id, sender, recipient, subject, html_content, text_content, attempts
+            FROM email_outbox
+            WHERE dead_lettered_at IS NULL AND next_attempt_at <= now()
+            ORDER BY next_attempt_at, enqueued_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+            "#
+        )
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to fetch an email from the outbox")?;
+        let email = match email {
+            Some(email) => email,
+            None => return Ok(delivered),
+        };
+        let addresses = SubscriberEmail::parse(email.recipient).and_then(|recipient| {
+            let sender = email.sender.map(SubscriberEmail::parse).transpose()?;
+            Ok((sender, recipient))
+        });
+        match addresses {
+            Ok((sender, recipient)) => {
+                let sent = email_sender
+                    .send_email_from(
+                        sender.as_ref(),
+                        &recipient,
+                        &email.subject,
+                        &email.html_content,
+                        &email.text_content,
+                        None,
+                    )
+                    .await;
+                if let Err(e) = sent {
+                    record_failed_attempt(transaction, email.email_id, email.attempts + 1, &e)
+                        .await?;
+                    return Err(e.context("Failed to send an email of the outbox"));
+                }
+                delivered += 1;
+            }
+            // Addresses are validated before landing here: there is no point in keeping
+            // an email which can never be sent.
+            Err(e) => tracing::error!(
+                error.message = %e,
+                "Dropping an email of the outbox with an invalid address"
+            ),
+        }
+        sqlx::query!(
+            "DELETE FROM email_outbox WHERE email_id = $1",
+            email.email_id
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to remove an email from the outbox")?;
+        transaction.commit().await?;
+    }
+}
+
+/// The email is retried after `retry_delay`, or dead-lettered after `MAX_ATTEMPTS`.
+async fn record_failed_attempt(
+    mut transaction: sqlx::Transaction<'_, sqlx::Postgres>,
+    email_id: Uuid,
+    attempts: i32,
+    error: &anyhow::Error,
+) -> Result<(), anyhow::Error> {
+    let dead_lettered = attempts >= MAX_ATTEMPTS;
+    if dead_lettered {
+        tracing::error!(
+            error.cause_chain = ?error,
+            attempts,
+            "Setting aside an email of the outbox which keeps failing"
+        );
+    }
+    sqlx::query!(
+        r#"
+        UPDATE email_outbox
+        SET
+            attempts = $2,
+            next_attempt_at = now() + make_interval(secs => $3),
+            last_error = $4,
+            dead_lettered_at = CASE WHEN $5 THEN now() END
+        WHERE email_id = $1
+        "#,
+        email_id,
+        attempts,
+        retry_delay(attempts).as_secs_f64(),
+        error.to_string(),
+        dead_lettered
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to record a failed attempt to send an email of the outbox")?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// The wait before the next attempt, after `attempts` failed ones.
+fn retry_delay(attempts: i32) -> Duration {
+    FIRST_RETRY_DELAY * 2u32.pow(attempts.clamp(1, MAX_ATTEMPTS) as u32 - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_delay, FIRST_RETRY_DELAY};
+
+    #[test]
+    fn the_retry_delay_doubles_after_each_failure() {
+        assert_eq!(retry_delay(1), FIRST_RETRY_DELAY);
+        assert_eq!(retry_delay(2), FIRST_RETRY_DELAY * 2);
+        assert_eq!(retry_delay(4), FIRST_RETRY_DELAY * 8);
+    }
+}
@@ -0,0 +1,245 @@
+//! Verifying addresses before anything is sent to them, so that the dead addresses of an
+//! imported list do not bounce off the email provider and hurt our reputation.
+//!
+//! Two methods are available:
+//! - `api`: a verification service (Kickbox, ZeroBounce behind a proxy...) is asked
+//!   about each address, and answers with a `result`;
+//! - `smtp`: the mail server of the address is asked whether it would accept an email
+//!   for it, hanging up before anything is sent.
+//!
+//! A verification that fails - the service is down, the mail server does not answer -
+//! gives an `unknown` verdict rather than an error: it must not hold the import back.
+use crate::deliverability::lookup_mx;
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+    Deliverable,
+    /// The address may exist, but emails to it might bounce: catch-all domains, full
+    /// mailboxes, greylisting...
+    Risky,
+    /// Issues are never delivered to these.
+    Undeliverable,
+    Unknown,
+}
+
+impl Verdict {
+    /// How the verdict is stored.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verdict::Deliverable => "deliverable",
+            Verdict::Risky => "risky",
+            Verdict::Undeliverable => "undeliverable",
+            Verdict::Unknown => "unknown",
+        }
+    }
+
+    /// The verdict of a verification service. Services disagree on names beyond these.
+    fn from_api_result(result: &str) -> Self {
+        match result.to_lowercase().as_str() {
+            "deliverable" | "valid" => Verdict::Deliverable,
+            "risky" | "catch-all" | "catch_all" | "accept_all" => Verdict::Risky,
+            "undeliverable" | "invalid" => Verdict::Undeliverable,
+            _ => Verdict::Unknown,
+        }
+    }
+
+    /// The verdict of a mail server, from its reply to `RCPT TO`.
+    fn from_rcpt_reply(code: u16) -> Self {
+        match code {
+            250 | 251 => Verdict::Deliverable,
+            500..=599 => Verdict::Undeliverable,
+            400..=499 => Verdict::Risky,
+            _ => Verdict::Unknown,
+        }
+    }
+}
+
+pub enum EmailVerifier {
+    Api {
+        http_client: Client,
+        url: String,
+        api_key: Secret<String>,
+    },
+    Smtp {
+        nameserver: String,
+        helo_domain: String,
+        timeout: Duration,
+    },
+}
+
+#[derive(serde::Deserialize)]
+struct ApiResponse {
+    result: String,
+}
+
+impl EmailVerifier {
+    pub fn api(url: String, api_key: Secret<String>, timeout: Duration) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self::Api {
+            http_client,
+            url,
+            api_key,
+        }
+    }
+
+    pub fn smtp(nameserver: String, helo_domain: String, timeout: Duration) -> Self {
+        Self::Smtp {
+            nameserver,
+            helo_domain,
+            timeout,
+        }
+    }
+
+    #[tracing::instrument(name = "Verify an email address", skip(self))]
+    pub async fn verify(&self, email: &SubscriberEmail) -> Verdict {
+        let verdict = match self {
+            EmailVerifier::Api {
+                http_client,
+                url,
+                api_key,
+            } => verify_with_api(http_client, url, api_key, email).await,
+            EmailVerifier::Smtp {
+                nameserver,
+                helo_domain,
+                timeout,
+            } => tokio::time::timeout(*timeout, verify_with_smtp(nameserver, helo_domain, email))
+                .await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("The verification timed out"))),
+        };
+        verdict.unwrap_or_else(|e| {
+            tracing::warn!(error.cause_chain = ?e, "Failed to verify an email address");
+            Verdict::Unknown
+        })
+    }
+}
+
+async fn verify_with_api(
+    http_client: &Client,
+    url: &str,
+    api_key: &Secret<String>,
+    email: &SubscriberEmail,
+) -> Result<Verdict, anyhow::Error> {
+    let response: ApiResponse = http_client
+        .get(url)
+        .query(&[("email", email.as_ref())])
+        .bearer_auth(api_key.expose_secret())
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+        .context("The verification service answered with an unexpected body")?;
+    Ok(Verdict::from_api_result(&response.result))
+}
+
+/// Only the most preferred mail server is asked: the others are backups, which often
+/// accept anything to relay it later.
+async fn verify_with_smtp(
+    nameserver: &str,
+    helo_domain: &str,
+    email: &SubscriberEmail,
+) -> Result<Verdict, anyhow::Error> {
+    let domain = email
+        .as_ref()
+        .rsplit('@')
+        .next()
+        .context("The address has no domain")?;
+    let mail_server = match lookup_mx(nameserver, domain).await?.into_iter().next() {
+        Some(mail_server) => mail_server,
+        // No mail server, no mailbox.
+        None => return Ok(Verdict::Undeliverable),
+    };
+    let stream = TcpStream::connect((mail_server.as_str(), 25))
+        .await
+        .with_context(|| format!("Failed to connect to {}", mail_server))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    expect_success(read_reply(&mut reader).await?)?;
+    writer
+        .write_all(format!("EHLO {}\r\n", helo_domain).as_bytes())
+        .await?;
+    expect_success(read_reply(&mut reader).await?)?;
+    // The null sender: bounces of a probe must not come back to anyone.
+    writer.write_all(b"MAIL FROM:<>\r\n").await?;
+    expect_success(read_reply(&mut reader).await?)?;
+    writer
+        .write_all(format!("RCPT TO:<{}>\r\n", email.as_ref()).as_bytes())
+        .await?;
+    let verdict = Verdict::from_rcpt_reply(read_reply(&mut reader).await?);
+    // Hanging up is all we need, whether the server acknowledges it or not.
+    let _ = writer.write_all(b"QUIT\r\n").await;
+    Ok(verdict)
+}
+
+/// The code of the next reply, which spans several lines as long as they read `250-...`.
+async fn read_reply<R>(reader: &mut BufReader<R>) -> Result<u16, anyhow::Error>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            anyhow::bail!("The mail server hung up");
+        }
+        let (code, continued) = parse_reply_line(&line)?;
+        if !continued {
+            return Ok(code);
+        }
+    }
+}
+
+fn parse_reply_line(line: &str) -> Result<(u16, bool), anyhow::Error> {
+    let code = line
+        .get(..3)
+        .and_then(|code| code.parse().ok())
+        .with_context(|| format!("Unexpected reply from the mail server: {}", line.trim()))?;
+    Ok((code, line.as_bytes().get(3) == Some(&b'-')))
+}
+
+fn expect_success(code: u16) -> Result<(), anyhow::Error> {
+    if !(200..400).contains(&code) {
+        anyhow::bail!("The mail server refused the conversation with {}", code);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_reply_line, Verdict};
+    use claim::assert_err;
+
+    #[test]
+    fn api_results_are_mapped_to_verdicts() {
+        assert_eq!(
+            Verdict::from_api_result("deliverable"),
+            Verdict::Deliverable
+        );
+        assert_eq!(Verdict::from_api_result("Catch-All"), Verdict::Risky);
+        assert_eq!(Verdict::from_api_result("invalid"), Verdict::Undeliverable);
+        assert_eq!(Verdict::from_api_result("spamtrap"), Verdict::Unknown);
+    }
+
+    #[test]
+    fn rcpt_replies_are_mapped_to_verdicts() {
+        assert_eq!(Verdict::from_rcpt_reply(250), Verdict::Deliverable);
+        assert_eq!(Verdict::from_rcpt_reply(550), Verdict::Undeliverable);
+        assert_eq!(Verdict::from_rcpt_reply(451), Verdict::Risky);
+    }
+
+    #[test]
+    fn multiline_replies_are_recognised() {
+        assert_eq!(parse_reply_line("250-PIPELINING\r\n").unwrap(), (250, true));
+        assert_eq!(parse_reply_line("250 OK\r\n").unwrap(), (250, false));
+        assert_eq!(parse_reply_line("220\r\n").unwrap(), (220, false));
+        assert_err!(parse_reply_line("hello"));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::problem_details::Problem;
+use crate::utils::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use std::fmt::Formatter;
+
+/// The error type shared by the routes outside of `/api`.
+/// Every variant carries a stable, machine-readable code - a generic one by default,
+/// which routes can refine with `with_code` (e.g. `invalid_subscriber`).
+#[derive(thiserror::Error)]
+pub enum AppError {
+    #[error("{message}")]
+    ValidationError { code: &'static str, message: String },
+
+    #[error("{message}")]
+    Unauthorized { code: &'static str, message: String },
+
+    #[error("{message}")]
+    NotFound { code: &'static str, message: String },
+
+    #[error("{message}")]
+    Conflict { code: &'static str, message: String },
+
+    /// Too many requests of the kind are being served, see `crate::concurrency_limits`.
+    #[error("{message}")]
+    TooManyRequests { code: &'static str, message: String },
+
+    /// The request is fine, but cannot be served right now - e.g. in read-only mode.
+    #[error("{message}")]
+    Unavailable { code: &'static str, message: String },
+
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl AppError {
+    pub fn validation(message: impl Into<String>) -> Self {
+        AppError::ValidationError {
+            code: "validation_error",
+            message: message.into(),
+        }
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        AppError::Unauthorized {
+            code: "unauthorized",
+            message: message.into(),
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        AppError::NotFound {
+            code: "not_found",
+            message: message.into(),
+        }
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        AppError::Conflict {
+            code: "conflict",
+            message: message.into(),
+        }
+    }
+
+    pub fn too_many_requests(message: impl Into<String>) -> Self {
+        AppError::TooManyRequests {
+            code: "too_many_requests",
+            message: message.into(),
+        }
+    }
+
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        AppError::Unavailable {
+            code: "unavailable",
+            message: message.into(),
+        }
+    }
+
+    /// Replace the generic code of the error. Unexpected errors are always `internal_error`.
+    pub fn with_code(mut self, new_code: &'static str) -> Self {
+        match &mut self {
+            AppError::ValidationError { code, .. }
+            | AppError::Unauthorized { code, .. }
+            | AppError::NotFound { code, .. }
+            | AppError::Conflict { code, .. }
+            | AppError::TooManyRequests { code, .. }
+            | AppError::Unavailable { code, .. } => *code = new_code,
+            AppError::UnexpectedError(_) => {}
+        }
+        self
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::ValidationError { code, .. }
+            | AppError::Unauthorized { code, .. }
+            | AppError::NotFound { code, .. }
+            | AppError::Conflict { code, .. }
+            | AppError::TooManyRequests { code, .. }
+            | AppError::Unavailable { code, .. } => code,
+            AppError::UnexpectedError(_) => "internal_error",
+        }
+    }
+}
+
+impl std::fmt::Debug for AppError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl Problem for AppError {
+    fn problem_code(&self) -> &'static str {
+        self.code()
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::ValidationError { .. } => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            AppError::NotFound { .. } => StatusCode::NOT_FOUND,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // Do not leak the details of unexpected errors - they are logged.
+        let message = match self {
+            AppError::UnexpectedError(_) => "An unexpected error occurred.".to_string(),
+            e => e.to_string(),
+        };
+        HttpResponse::build(self.status_code())
+            .content_type("text/plain; charset=utf-8")
+            .body(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AppError;
+    use actix_web::ResponseError;
+
+    #[test]
+    fn routes_can_refine_the_code_of_an_error() {
+        let e = AppError::validation("Invalid token.").with_code("invalid_subscription_token");
+
+        assert_eq!(e.code(), "invalid_subscription_token");
+        assert_eq!(e.status_code(), 400);
+        assert_eq!(e.to_string(), "Invalid token.");
+    }
+
+    #[test]
+    fn unexpected_errors_keep_their_code() {
+        let e = AppError::from(anyhow::anyhow!("boom")).with_code("something_else");
+
+        assert_eq!(e.code(), "internal_error");
+        assert_eq!(e.status_code(), 500);
+    }
+}
@@ -0,0 +1,97 @@
+//! Branded HTML error pages for browsers, instead of the plain text bodies of our errors
+//! and the empty bodies of actix's own - e.g. for an unknown path.
+//! Clients asking for JSON get problem details instead, see `crate::problem_details`.
+use crate::configuration::BrandingSettings;
+use crate::i18n::Locale;
+use crate::utils::{accepts, is_bare_error};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, ContentType};
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpResponse};
+use actix_web_lab::middleware::Next;
+use askama_actix::Template;
+
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorTemplate {
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    title: String,
+    message: String,
+}
+
+/// The title and explanation of the page, along with the message of the error if it is
+/// worth showing - never for server errors, whose details are logged instead.
+fn render_page(
+    status: StatusCode,
+    error: Option<&actix_web::Error>,
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+) -> HttpResponse {
+    let page = match status.as_u16() {
+        code @ (403 | 404 | 500) => code.to_string(),
+        _ if status.is_server_error() => "500".into(),
+        _ => "other".into(),
+    };
+    let message = match error {
+        Some(e) if status.is_client_error() => e.to_string(),
+        _ => locale.t(&format!("error-{}-body", page)),
+    };
+    let template = ErrorTemplate {
+        title: locale.t(&format!("error-{}-title", page)),
+        message,
+        branding,
+        locale,
+    };
+    match template.render() {
+        Ok(body) => HttpResponse::build(status)
+            .content_type(ContentType::html())
+            .body(body),
+        Err(e) => {
+            tracing::error!(error.message = %e, "Failed to render an error page");
+            HttpResponse::new(status)
+        }
+    }
+}
+
+/// Render the errors of the requests coming from a browser as HTML pages.
+pub async fn render_error_pages(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let branding = req.app_data::<web::Data<BrandingSettings>>().cloned();
+    let (branding, locale) = match branding {
+        Some(branding) if accepts(req.request(), "text/html") => {
+            match Locale::extract(req.request()).await {
+                Ok(locale) => (branding, locale),
+                Err(_) => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+            }
+        }
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+    match next.call(req).await {
+        Ok(res) if is_bare_error(&res) => {
+            let mut page = render_page(res.status(), res.response().error(), branding, locale);
+            // Keep the headers set alongside the error, e.g. `Set-Cookie`.
+            for (name, value) in res.headers() {
+                if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+                    page.headers_mut().append(name.clone(), value.clone());
+                }
+            }
+            Ok(res.into_response(page))
+        }
+        Ok(res) => Ok(res.map_into_boxed_body()),
+        // Errors returned by other middlewares, e.g. read-only mode.
+        Err(e) => {
+            let status = e.as_response_error().status_code();
+            if status.is_client_error() || status.is_server_error() {
+                let page = render_page(status, Some(&e), branding, locale);
+                Err(InternalError::from_response(e, page).into())
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
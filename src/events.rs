@@ -0,0 +1,580 @@
+use crate::configuration::{EventPublisherKind, EventSettings, Settings};
+use crate::notifications::notify_send_completed;
+use crate::pii::{mask_email, Masked};
+use crate::startup::get_connection_pool;
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use uuid::Uuid;
+
+/// Events are published in batches of this size.
+const PUBLISH_BATCH_SIZE: i64 = 100;
+
+/// Something which happened to the newsletter that other systems may want to react to.
+///
+/// Events are stored in the `events` table, with the tenant they happened to, in the same
+/// transaction as the change they describe, then published to the message bus configured in `events` by the event
+/// publisher - at least once, in the order they occurred.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DomainEvent {
+    /// Someone signed up with an email address we did not know yet.
+    SubscriptionRequested {
+        subscriber_id: Uuid,
+        email: String,
+    },
+    SubscriberConfirmed {
+        subscriber_id: Uuid,
+        email: String,
+    },
+    SubscriberUnsubscribed {
+        subscriber_id: Uuid,
+        email: String,
+        /// The answer to the survey of the unsubscribe page, if any - or `inactive` for
+        /// the subscribers unsubscribed by the re-engagement campaign, `bounced` for those
+        /// whose address hard bounced.
+        reason: Option<String>,
+    },
+    IssuePublished {
+        newsletter_issue_id: Uuid,
+        title: String,
+    },
+    /// An issue was withdrawn during its undo window, before any delivery.
+    IssueCancelled {
+        newsletter_issue_id: Uuid,
+        title: String,
+    },
+    /// Failed deliveries of an issue were queued again.
+    DeliveriesRetried {
+        newsletter_issue_id: Uuid,
+        subscriber_emails: Vec<String>,
+    },
+    /// An issue could not be delivered to a subscriber.
+    DeliveryFailed {
+        newsletter_issue_id: Uuid,
+        subscriber_email: String,
+        /// `failed` or `skipped`.
+        outcome: String,
+        error: Option<String>,
+    },
+    /// Every delivery task of an issue has been processed.
+    DeliveryCompleted {
+        newsletter_issue_id: Uuid,
+        delivered: i64,
+        failed: i64,
+        skipped: i64,
+    },
+    /// Too many recipients of an issue complained: its remaining deliveries wait for an
+    /// admin to look into it.
+    DeliveryPaused {
+        newsletter_issue_id: Uuid,
+        title: String,
+        complaints: i64,
+        delivered: i64,
+    },
+    DeliveryResumed {
+        newsletter_issue_id: Uuid,
+        title: String,
+    },
+}
+
+impl DomainEvent {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            DomainEvent::SubscriptionRequested { .. } => "subscription_requested",
+            DomainEvent::SubscriberConfirmed { .. } => "subscriber_confirmed",
+            DomainEvent::SubscriberUnsubscribed { .. } => "subscriber_unsubscribed",
+            DomainEvent::IssuePublished { .. } => "issue_published",
+            DomainEvent::IssueCancelled { .. } => "issue_cancelled",
+            DomainEvent::DeliveriesRetried { .. } => "deliveries_retried",
+            DomainEvent::DeliveryFailed { .. } => "delivery_failed",
+            DomainEvent::DeliveryCompleted { .. } => "delivery_completed",
+            DomainEvent::DeliveryPaused { .. } => "delivery_paused",
+            DomainEvent::DeliveryResumed { .. } => "delivery_resumed",
+        }
+    }
+
+    /// A one-line description, for the admin activity feed.
+    pub fn summary(&self) -> String {
+        match self {
+            DomainEvent::SubscriptionRequested { email, .. } => {
+                format!("{} signed up", email)
+            }
+            DomainEvent::SubscriberConfirmed { email, .. } => {
+                format!("{} confirmed their subscription", email)
+            }
+            DomainEvent::SubscriberUnsubscribed { email, reason, .. } => match reason {
+                Some(reason) => format!("{} unsubscribed ({})", email, reason),
+                None => format!("{} unsubscribed", email),
+            },
+            DomainEvent::IssuePublished { title, .. } => format!("\"{}\" was published", title),
+            DomainEvent::IssueCancelled { title, .. } => {
+                format!("\"{}\" was cancelled before going out", title)
+            }
+            DomainEvent::DeliveriesRetried {
+                subscriber_emails, ..
+            } => match subscriber_emails.as_slice() {
+                [email] => format!("Retrying the delivery to {}", email),
+                emails => format!("Retrying the delivery to {} recipients", emails.len()),
+            },
+            DomainEvent::DeliveryFailed {
+                subscriber_email,
+                outcome,
+                error,
+                ..
+            } => match error {
+                Some(error) => format!("Delivery to {} {}: {}", subscriber_email, outcome, error),
+                None => format!("Delivery to {} {}", subscriber_email, outcome),
+            },
+            DomainEvent::DeliveryCompleted {
+                delivered,
+                failed,
+                skipped,
+                ..
+            } => format!(
+                "An issue finished delivering: {} delivered, {} failed, {} skipped",
+                delivered, failed, skipped
+            ),
+            DomainEvent::DeliveryPaused {
+                title,
+                complaints,
+                delivered,
+                ..
+            } => format!(
+                "The delivery of \"{}\" was paused: {} spam complaints for {} emails delivered",
+                title, complaints, delivered
+            ),
+            DomainEvent::DeliveryResumed { title, .. } => {
+                format!("The delivery of \"{}\" was resumed", title)
+            }
+        }
+    }
+}
+
+impl Masked for DomainEvent {
+    fn masked(self) -> Self {
+        match self {
+            DomainEvent::SubscriptionRequested {
+                subscriber_id,
+                email,
+            } => DomainEvent::SubscriptionRequested {
+                subscriber_id,
+                email: mask_email(&email),
+            },
+            DomainEvent::SubscriberConfirmed {
+                subscriber_id,
+                email,
+            } => DomainEvent::SubscriberConfirmed {
+                subscriber_id,
+                email: mask_email(&email),
+            },
+            DomainEvent::SubscriberUnsubscribed {
+                subscriber_id,
+                email,
+                reason,
+            } => DomainEvent::SubscriberUnsubscribed {
+                subscriber_id,
+                email: mask_email(&email),
+                reason,
+            },
+            DomainEvent::DeliveriesRetried {
+                newsletter_issue_id,
+                subscriber_emails,
+            } => DomainEvent::DeliveriesRetried {
+                newsletter_issue_id,
+                subscriber_emails: subscriber_emails.iter().map(|e| mask_email(e)).collect(),
+            },
+            DomainEvent::DeliveryFailed {
+                newsletter_issue_id,
+                subscriber_email,
+                outcome,
+                error,
+            } => DomainEvent::DeliveryFailed {
+                newsletter_issue_id,
+                subscriber_email: mask_email(&subscriber_email),
+                outcome,
+                error,
+            },
+            event => event,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Record a domain event",
+    skip(transaction, event),
+    fields(event_type = event.event_type())
+)]
+pub async fn record_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    event: &DomainEvent,
+) -> Result<Uuid, anyhow::Error> {
+    let event_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO events (event_id, tenant_id, event_type, payload, occurred_at)
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        event_id,
+        *tenant_id,
+        event.event_type(),
+        serde_json::to_value(event).context("Failed to serialize a domain event")?
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to record a domain event")?;
+    Ok(event_id)
+}
+
+/// Record a `DeliveryCompleted` event if no delivery task is left for the issue, and
+/// notify the admins. An issue whose deliveries are still being queued is not done,
+/// even if the worker went through the ones queued so far.
+///
+/// The issue row is locked first: when the last two tasks of an issue complete
+/// concurrently, the second transaction waits for the first one to commit and is the
+/// only one to see an empty queue.
+pub async fn record_delivery_completed_if_done(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT tenant_id, title, enqueued_at IS NOT NULL AS "enqueued!"
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to lock the newsletter issue")?;
+    if !issue.enqueued {
+        return Ok(());
+    }
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            (
+                SELECT COUNT(*)
+                FROM issue_delivery_queue
+                WHERE newsletter_issue_id = $1
+            ) AS "pending!",
+            COUNT(*) FILTER (WHERE outcome = 'delivered') AS "delivered!",
+            COUNT(*) FILTER (WHERE outcome = 'failed') AS "failed!",
+            COUNT(*) FILTER (WHERE outcome = 'skipped') AS "skipped!"
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to count the delivery tasks of the newsletter issue")?;
+    if r.pending == 0 {
+        let tenant_id = TenantId::from(issue.tenant_id);
+        let event = DomainEvent::DeliveryCompleted {
+            newsletter_issue_id,
+            delivered: r.delivered,
+            failed: r.failed,
+            skipped: r.skipped,
+        };
+        record_event(transaction, tenant_id, &event).await?;
+        notify_send_completed(
+            transaction,
+            tenant_id,
+            &issue.title,
+            r.delivered,
+            r.failed + r.skipped,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// A recorded event, as sent on the message bus.
+#[derive(Debug, serde::Serialize)]
+pub struct PublishedEvent {
+    pub event_id: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: serde_json::Value,
+}
+
+impl PublishedEvent {
+    fn event_type(&self) -> &str {
+        self.event["type"].as_str().unwrap_or("unknown")
+    }
+}
+
+/// The message bus recorded events are published to.
+pub enum EventPublisher {
+    /// Core NATS: each event goes to the `{topic}.{event type}` subject.
+    Nats { address: String, topic: String },
+    /// Kafka, through its REST proxy: events go to `topic`, keyed by event id.
+    Kafka {
+        http_client: reqwest::Client,
+        base_url: String,
+        topic: String,
+    },
+}
+
+impl EventPublisher {
+    /// `None` if no message bus is configured.
+    pub fn from_settings(settings: &EventSettings) -> Result<Option<Self>, anyhow::Error> {
+        let publisher = match settings.publisher {
+            EventPublisherKind::None => return Ok(None),
+            EventPublisherKind::Nats => EventPublisher::Nats {
+                address: settings
+                    .url
+                    .strip_prefix("nats://")
+                    .context("The NATS URL must start with nats://")?
+                    .to_owned(),
+                topic: settings.topic.clone(),
+            },
+            EventPublisherKind::Kafka => EventPublisher::Kafka {
+                http_client: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()?,
+                base_url: settings.url.trim_end_matches('/').to_owned(),
+                topic: settings.topic.clone(),
+            },
+        };
+        Ok(Some(publisher))
+    }
+
+    async fn publish(&self, events: &[PublishedEvent]) -> Result<(), anyhow::Error> {
+        match self {
+            EventPublisher::Nats { address, topic } => {
+                publish_to_nats(address, topic, events).await
+            }
+            EventPublisher::Kafka {
+                http_client,
+                base_url,
+                topic,
+            } => {
+                let records: Vec<_> = events
+                    .iter()
+                    .map(|e| serde_json::json!({ "key": e.event_id, "value": e }))
+                    .collect();
+                http_client
+                    .post(format!("{}/topics/{}", base_url, topic))
+                    .header("Content-Type", "application/vnd.kafka.json.v2+json")
+                    .body(serde_json::to_vec(
+                        &serde_json::json!({ "records": records }),
+                    )?)
+                    .send()
+                    .await
+                    .context("Failed to reach the Kafka REST proxy")?
+                    .error_for_status()
+                    .context("The Kafka REST proxy rejected the events")?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Speak just enough of the NATS protocol to publish: the trailing `PING` makes sure
+/// the server processed every `PUB` before we mark the events as published.
+async fn publish_to_nats(
+    address: &str,
+    topic: &str,
+    events: &[PublishedEvent],
+) -> Result<(), anyhow::Error> {
+    let stream = TcpStream::connect(address)
+        .await
+        .context("Failed to connect to NATS")?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    anyhow::ensure!(
+        line.starts_with("INFO"),
+        "Unexpected NATS greeting: {}",
+        line
+    );
+
+    let mut frames = b"CONNECT {\"verbose\":false,\"pedantic\":false}\r\n".to_vec();
+    for event in events {
+        let subject = format!("{}.{}", topic, event.event_type());
+        frames.extend(nats_pub_frame(&subject, &serde_json::to_vec(event)?));
+    }
+    frames.extend(b"PING\r\n");
+    writer.write_all(&frames).await?;
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            anyhow::bail!("NATS closed the connection");
+        }
+        match line.trim_end() {
+            "PONG" => return Ok(()),
+            "PING" => writer.write_all(b"PONG\r\n").await?,
+            l if l.starts_with("-ERR") => anyhow::bail!("NATS rejected the events: {}", l),
+            _ => {}
+        }
+    }
+}
+
+fn nats_pub_frame(subject: &str, payload: &[u8]) -> Vec<u8> {
+    let mut frame = format!("PUB {} {}\r\n", subject, payload.len()).into_bytes();
+    frame.extend(payload);
+    frame.extend(b"\r\n");
+    frame
+}
+
+pub enum ExecutionOutcome {
+    EventsPublished(usize),
+    EmptyQueue,
+}
+
+pub async fn run_event_publisher_until_stopped(
+    configuration: Settings,
+) -> Result<(), anyhow::Error> {
+    let publisher = match EventPublisher::from_settings(&configuration.events)? {
+        Some(publisher) => publisher,
+        None => {
+            tracing::info!("No message bus is configured - domain events are only recorded");
+            return std::future::pending().await;
+        }
+    };
+    let connection_pool = get_connection_pool(&configuration.database);
+    publisher_loop(connection_pool, publisher).await
+}
+
+async fn publisher_loop(pool: PgPool, publisher: EventPublisher) -> Result<(), anyhow::Error> {
+    loop {
+        match try_publish_events(&pool, &publisher).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+            Ok(ExecutionOutcome::EventsPublished(_)) => {}
+        }
+    }
+}
+
+/// Publish the oldest batch of unpublished events.
+/// They are only marked as published once the message bus acknowledged them.
+#[tracing::instrument(skip_all, err)]
+pub async fn try_publish_events(
+    pool: &PgPool,
+    publisher: &EventPublisher,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let events = sqlx::query!(
+        r#"
+        SELECT event_id, payload, occurred_at
+        FROM events
+        WHERE published_at IS NULL
+        ORDER BY occurred_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT $1
+        "#,
+        PUBLISH_BATCH_SIZE
+    )
+    .fetch_all(&mut transaction)
+    .await?
+    .into_iter()
+    .map(|r| PublishedEvent {
+        event_id: r.event_id,
+        occurred_at: r.occurred_at,
+        event: r.payload,
+    })
+    .collect::<Vec<_>>();
+    if events.is_empty() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+
+    publisher.publish(&events).await?;
+    let event_ids: Vec<Uuid> = events.iter().map(|e| e.event_id).collect();
+    sqlx::query!(
+        r#"UPDATE events SET published_at = now() WHERE event_id = ANY($1)"#,
+        &event_ids
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::EventsPublished(events.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nats_pub_frame, publish_to_nats, DomainEvent, PublishedEvent};
+    use claim::assert_ok;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use uuid::Uuid;
+
+    #[test]
+    fn events_are_serialized_with_their_type() {
+        let event = DomainEvent::IssuePublished {
+            newsletter_issue_id: Uuid::nil(),
+            title: "Spring edition".into(),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], event.event_type());
+        assert_eq!(json["title"], "Spring edition");
+        assert_eq!(serde_json::from_value::<DomainEvent>(json).unwrap(), event);
+    }
+
+    #[test]
+    fn a_failed_delivery_is_summarized_with_its_error() {
+        let event = DomainEvent::DeliveryFailed {
+            newsletter_issue_id: Uuid::nil(),
+            subscriber_email: "ursula@example.com".into(),
+            outcome: "failed".into(),
+            error: Some("The email provider is down".into()),
+        };
+        assert_eq!(
+            event.summary(),
+            "Delivery to ursula@example.com failed: The email provider is down"
+        );
+    }
+
+    #[test]
+    fn a_nats_pub_frame_carries_the_payload_size() {
+        assert_eq!(
+            nats_pub_frame("newsletter.issue_published", b"{}"),
+            b"PUB newsletter.issue_published 2\r\n{}\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn events_are_published_to_their_nats_subject() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            socket.write_all(b"INFO {}\r\n").await.unwrap();
+            let mut received = Vec::new();
+            let mut reader = BufReader::new(socket);
+            while !received.ends_with(b"PING\r\n") {
+                reader.read_until(b'\n', &mut received).await.unwrap();
+            }
+            reader.get_mut().write_all(b"PONG\r\n").await.unwrap();
+            // Wait for the client to hang up.
+            let _ = reader.read(&mut [0; 1]).await;
+            String::from_utf8(received).unwrap()
+        });
+        let event = PublishedEvent {
+            event_id: Uuid::new_v4(),
+            occurred_at: chrono::Utc::now(),
+            event: serde_json::json!({ "type": "subscriber_confirmed" }),
+        };
+
+        assert_ok!(publish_to_nats(&address, "newsletter", &[event]).await);
+
+        let received = server.await.unwrap();
+        assert!(received.starts_with("CONNECT "));
+        assert!(received.contains("PUB newsletter.subscriber_confirmed "));
+    }
+}
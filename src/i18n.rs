@@ -0,0 +1,163 @@
+//! Localization of what subscribers see - the subscribe form responses, the
+//! confirmation and unsubscribe pages and the confirmation email - with Fluent.
+//! Translations live in `locales/<language>/main.ftl`. Missing ones fall back to English.
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{web, FromRequest, HttpRequest};
+use fluent_templates::fluent_bundle::FluentValue;
+use fluent_templates::{static_loader, LanguageIdentifier, Loader};
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+
+static_loader! {
+    static LOCALES = {
+        locales: "./locales",
+        fallback_language: "en",
+        // Unicode isolation marks around arguments would end up in the links we interpolate.
+        customise: |bundle| bundle.set_use_isolating(false),
+    };
+}
+
+/// A language we have translations for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Locale(LanguageIdentifier);
+
+/// The locale used when a request does not ask for a language we support.
+pub struct DefaultLocale(pub Locale);
+
+impl Locale {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        s.parse()
+            .ok()
+            .and_then(|requested| Self::supported(&requested))
+            .ok_or_else(|| format!("{} is not a supported locale.", s))
+    }
+
+    /// Regional variants fall back to the language we have, e.g. `fr-CH` to `fr`.
+    fn supported(requested: &LanguageIdentifier) -> Option<Self> {
+        LOCALES
+            .locales()
+            .find(|available| available.language == requested.language)
+            .map(|available| Self(available.clone()))
+    }
+
+    /// Every language we have translations for, e.g. for subscribers to pick one.
+    pub fn all() -> Vec<Self> {
+        let mut locales: Vec<Self> = LOCALES.locales().map(|l| Self(l.clone())).collect();
+        locales.sort_by_key(Self::language);
+        locales
+    }
+
+    /// The supported language an `Accept-Language` header prefers, if any.
+    pub fn negotiate(accept_language: &str) -> Option<Self> {
+        let mut ranges: Vec<(f32, &str)> = accept_language
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let quality = match parts.find_map(|p| p.trim().strip_prefix("q=")) {
+                    Some(q) => q.parse().ok()?,
+                    None => 1.0,
+                };
+                Some((quality, tag))
+            })
+            .filter(|(quality, _)| *quality > 0.0)
+            .collect();
+        // A stable sort: equally preferred languages keep the order of the header.
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranges
+            .into_iter()
+            .find_map(|(_, tag)| Self::supported(&tag.parse().ok()?))
+    }
+
+    /// The language tag, e.g. for the `lang` attribute of a page.
+    pub fn language(&self) -> String {
+        self.0.to_string()
+    }
+
+    pub fn t(&self, message_id: &str) -> String {
+        LOCALES
+            .lookup(&self.0, message_id)
+            .unwrap_or_else(|| message_id.into())
+    }
+
+    pub fn t_with(&self, message_id: &str, args: &[(&str, &str)]) -> String {
+        let args: HashMap<&str, FluentValue> = args
+            .iter()
+            .map(|(name, value)| (*name, FluentValue::from(*value)))
+            .collect();
+        LOCALES
+            .lookup_with_args(&self.0, message_id, &args)
+            .unwrap_or_else(|| message_id.into())
+    }
+}
+
+/// The locale negotiated from the `Accept-Language` header of the request.
+impl FromRequest for Locale {
+    type Error = actix_web::Error;
+
+    type Future = Ready<Result<Locale, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let negotiated = req
+            .headers()
+            .get_all(header::ACCEPT_LANGUAGE)
+            .filter_map(|v| v.to_str().ok())
+            .find_map(Locale::negotiate);
+        let locale = match negotiated {
+            Some(locale) => Ok(locale),
+            None => req
+                .app_data::<web::Data<DefaultLocale>>()
+                .map(|default| default.0.clone())
+                .ok_or_else(|| {
+                    actix_web::error::ErrorInternalServerError("No default locale is configured.")
+                }),
+        };
+        ready(locale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Locale;
+    use claim::{assert_err, assert_none};
+
+    fn locale(s: &str) -> Locale {
+        Locale::parse(s).unwrap()
+    }
+
+    #[test]
+    fn only_languages_with_translations_are_supported() {
+        assert_eq!(locale("fr-CH"), locale("fr"));
+        assert_err!(Locale::parse("de"));
+        assert_err!(Locale::parse("not a language"));
+    }
+
+    #[test]
+    fn the_most_preferred_supported_language_wins() {
+        assert_eq!(
+            Locale::negotiate("fr-CH, fr;q=0.9, en;q=0.8"),
+            Some(locale("fr"))
+        );
+        assert_eq!(
+            Locale::negotiate("de, en;q=0.5, fr;q=0.4"),
+            Some(locale("en"))
+        );
+        assert_eq!(Locale::negotiate("en;q=0.5, fr"), Some(locale("fr")));
+        assert_none!(Locale::negotiate("de, *;q=0.1"));
+        assert_none!(Locale::negotiate("fr;q=0"));
+    }
+
+    #[test]
+    fn arguments_are_interpolated_without_isolation_marks() {
+        let text = locale("en").t_with(
+            "confirmation-email-text",
+            &[("confirmation_link", "https://example.com/confirm")],
+        );
+
+        assert_eq!(
+            text,
+            "Welcome to our newsletter!\nVisit https://example.com/confirm to confirm your subscription"
+        );
+    }
+}
@@ -2,4 +2,6 @@ mod key;
 mod persistence;
 
 pub use key::IdempotencyKey;
-pub use persistence::{save_response, try_processing, NextAction};
+pub use persistence::{
+    delete_expired_keys, save_response, try_processing, NextAction, ResponseTooLarge,
+};
@@ -1,7 +1,10 @@
+use crate::configuration::IdempotencySettings;
 use crate::idempotency::IdempotencyKey;
-use actix_web::body::to_bytes;
+use actix_web::body::to_bytes_limited;
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
 use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
+use anyhow::Context;
 use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
@@ -23,8 +26,22 @@ impl PgHasArrayType for HeaderPairRecord {
 pub enum NextAction {
     StartProcessing(Transaction<'static, Postgres>),
     ReturnSavedResponse(HttpResponse),
+    /// The original request did not complete within the pending lease timeout.
+    StillProcessing,
 }
 
+/// The response of a request could not be saved for replay, so the request was rolled back.
+#[derive(thiserror::Error, Debug)]
+#[error(
+    "The response is too large to be saved: at most {max_response_size_bytes} bytes are allowed"
+)]
+pub struct ResponseTooLarge {
+    pub max_response_size_bytes: usize,
+}
+
+/// Postgres' `lock_not_available` error code, raised when `lock_timeout` expires.
+const LOCK_NOT_AVAILABLE: &str = "55P03";
+
 pub async fn get_saved_response(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
@@ -47,36 +64,33 @@ pub async fn get_saved_response(
     .fetch_optional(pool)
     .await?;
 
-    if let Some(r) = saved_response {
-        let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
-        let mut response = HttpResponse::build(status_code);
-        for HeaderPairRecord { name, value } in r.response_headers {
-            response.append_header((name, value));
-        }
-        Ok(Some(response.body(r.response_body)))
-    } else {
-        Ok(None)
-    }
+    saved_response
+        .map(|r| restore_response(r.response_status_code, r.response_headers, r.response_body))
+        .transpose()
 }
 
+/// Save the response and commit the transaction started by `try_processing`.
+/// A response too large to be saved rolls back the whole request.
 pub async fn save_response(
     mut transaction: Transaction<'static, Postgres>,
     idempotency_key: &IdempotencyKey,
     user_id: Uuid,
     http_response: HttpResponse,
+    settings: &IdempotencySettings,
 ) -> Result<HttpResponse, anyhow::Error> {
     let (response_head, body) = http_response.into_parts();
-    let body = to_bytes(body).await.map_err(|e| anyhow::anyhow!("{}", e))?;
-    let status_code = response_head.status().as_u16() as i16;
-    let headers = {
-        let mut h = Vec::with_capacity(response_head.headers().len());
-        for (name, value) in response_head.headers().iter() {
-            let name = name.as_str().to_owned();
-            let value = value.as_bytes().to_owned();
-            h.push(HeaderPairRecord { name, value });
+    // The cap is enforced while reading, so an oversized body is never fully buffered.
+    let body = match to_bytes_limited(body, settings.max_response_size_bytes).await {
+        Ok(body) => body.map_err(|e| anyhow::anyhow!("{}", e))?,
+        Err(_) => {
+            return Err(ResponseTooLarge {
+                max_response_size_bytes: settings.max_response_size_bytes,
+            }
+            .into())
         }
-        h
     };
+    let status_code = response_head.status().as_u16() as i16;
+    let headers = header_records(response_head.headers());
 
     sqlx::query_unchecked!(
         r#"
@@ -103,13 +117,86 @@ pub async fn save_response(
     Ok(http_response)
 }
 
+/// Every header value is kept, in order: a header can be repeated, as `Set-Cookie` often is.
+fn header_records(headers: &HeaderMap) -> Vec<HeaderPairRecord> {
+    headers
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect()
+}
+
+/// Rebuild a saved response. The body is replayed byte for byte, whatever its encoding.
+fn restore_response(
+    status_code: i16,
+    headers: Vec<HeaderPairRecord>,
+    body: Vec<u8>,
+) -> Result<HttpResponse, anyhow::Error> {
+    let status_code = StatusCode::from_u16(status_code.try_into()?)?;
+    let mut response = HttpResponse::with_body(status_code, body);
+    for HeaderPairRecord { name, value } in headers {
+        let name = HeaderName::try_from(name.as_str())
+            .with_context(|| format!("Invalid saved header name: {}", name))?;
+        let value = HeaderValue::from_bytes(&value)
+            .with_context(|| format!("Invalid saved value for the {} header", name))?;
+        response.headers_mut().append(name, value);
+    }
+    Ok(response.map_into_boxed_body())
+}
+
+/// Forget the keys past their TTL. `try_processing` ignores them already, this only
+/// keeps the table from growing forever. Returns how many keys were deleted.
+#[tracing::instrument(name = "Delete the expired idempotency keys", skip_all)]
+pub async fn delete_expired_keys(
+    pool: &PgPool,
+    settings: &IdempotencySettings,
+) -> Result<u64, anyhow::Error> {
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE created_at < now() - make_interval(secs => $1)
+        "#,
+        settings.key_ttl().as_secs_f64()
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(deleted)
+}
+
 pub async fn try_processing(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: Uuid,
+    settings: &IdempotencySettings,
 ) -> Result<NextAction, anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let n_inserted_rows = sqlx::query!(
+    // Past its TTL, a key is forgotten and can be reused.
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2 AND
+            created_at < now() - make_interval(secs => $3)
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        settings.key_ttl().as_secs_f64()
+    )
+    .execute(&mut transaction)
+    .await?;
+    // A retry blocks on the row inserted by the original request until it commits -
+    // but only for as long as the pending lease.
+    sqlx::query!(
+        "SELECT set_config('lock_timeout', $1, true)",
+        format!("{}ms", settings.pending_lease_timeout().as_millis())
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    let inserted = sqlx::query!(
         r#"
         INSERT INTO idempotency (
             user_id,
@@ -123,9 +210,18 @@ pub async fn try_processing(
         idempotency_key.as_ref()
     )
     .execute(&mut transaction)
-    .await?
-    .rows_affected();
+    .await;
+    let n_inserted_rows = match inserted {
+        Ok(r) => r.rows_affected(),
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(LOCK_NOT_AVAILABLE) => {
+            return Ok(NextAction::StillProcessing)
+        }
+        Err(e) => return Err(e.into()),
+    };
     if n_inserted_rows > 0 {
+        sqlx::query!("RESET lock_timeout")
+            .execute(&mut transaction)
+            .await?;
         Ok(NextAction::StartProcessing(transaction))
     } else {
         let saved_response = get_saved_response(pool, idempotency_key, user_id)
@@ -134,3 +230,47 @@ pub async fn try_processing(
         Ok(NextAction::ReturnSavedResponse(saved_response))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{header_records, restore_response, HeaderPairRecord};
+    use actix_web::body::to_bytes;
+    use actix_web::cookie::Cookie;
+    use actix_web::http::header::SET_COOKIE;
+    use actix_web::http::StatusCode;
+    use actix_web::HttpResponse;
+    use claim::assert_err;
+
+    #[tokio::test]
+    async fn every_header_and_a_binary_body_survive_a_round_trip() {
+        let body = vec![0x89, b'P', b'N', b'G', 0xff, 0x00];
+        let response = HttpResponse::Ok()
+            .content_type("image/png")
+            .cookie(Cookie::new("first", "1"))
+            .cookie(Cookie::new("second", "2"))
+            .body(body.clone());
+
+        let restored = restore_response(
+            response.status().as_u16() as i16,
+            header_records(response.headers()),
+            body.clone(),
+        )
+        .unwrap();
+
+        assert_eq!(restored.status(), StatusCode::OK);
+        assert_eq!(restored.headers().get("content-type").unwrap(), "image/png");
+        let cookies: Vec<_> = restored.headers().get_all(SET_COOKIE).collect();
+        assert_eq!(cookies, ["first=1", "second=2"]);
+        assert_eq!(to_bytes(restored.into_body()).await.unwrap(), body);
+    }
+
+    #[test]
+    fn a_corrupted_header_is_an_error() {
+        let headers = vec![HeaderPairRecord {
+            name: "not a header".into(),
+            value: b"value".to_vec(),
+        }];
+
+        assert_err!(restore_response(200, headers, vec![]));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::import::{split_tags, ImportedStatus, ImportedSubscriber};
+use std::io::Read;
+
+fn map_status(subscriber_type: &str) -> ImportedStatus {
+    match subscriber_type {
+        "regular" | "premium" | "gifted" | "trialed" | "churning" | "unpaid" | "past_due" => {
+            ImportedStatus::Confirmed
+        }
+        "unactivated" => ImportedStatus::PendingConfirmation,
+        other => ImportedStatus::Excluded(other.to_owned()),
+    }
+}
+
+fn name_from_metadata(metadata: &serde_json::Value) -> Option<String> {
+    ["name", "full_name", "first_name"]
+        .iter()
+        .find_map(|key| metadata.get(key).and_then(|v| v.as_str()))
+        .map(str::to_owned)
+}
+
+#[derive(serde::Deserialize)]
+struct CsvRecord {
+    email: String,
+    #[serde(default)]
+    subscriber_type: Option<String>,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    metadata: String,
+}
+
+pub fn parse_csv(reader: impl Read) -> Result<Vec<ImportedSubscriber>, anyhow::Error> {
+    let mut records = Vec::new();
+    for record in csv::Reader::from_reader(reader).deserialize() {
+        let record: CsvRecord = record?;
+        let metadata = serde_json::from_str(&record.metadata).unwrap_or(serde_json::Value::Null);
+        records.push(ImportedSubscriber {
+            name: name_from_metadata(&metadata),
+            email: record.email,
+            status: map_status(record.subscriber_type.as_deref().unwrap_or("regular")),
+            tags: split_tags(&record.tags),
+        });
+    }
+    Ok(records)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum JsonExport {
+    Page { results: Vec<JsonSubscriber> },
+    List(Vec<JsonSubscriber>),
+}
+
+#[derive(serde::Deserialize)]
+struct JsonSubscriber {
+    email: String,
+    #[serde(default)]
+    subscriber_type: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    metadata: serde_json::Value,
+}
+
+/// Parse subscribers as returned by the Buttondown API (`/v1/subscribers`).
+pub fn parse_json(reader: impl Read) -> Result<Vec<ImportedSubscriber>, anyhow::Error> {
+    let subscribers = match serde_json::from_reader(reader)? {
+        JsonExport::Page { results } => results,
+        JsonExport::List(subscribers) => subscribers,
+    };
+    Ok(subscribers
+        .into_iter()
+        .map(|s| ImportedSubscriber {
+            name: name_from_metadata(&s.metadata),
+            email: s.email,
+            status: map_status(s.subscriber_type.as_deref().unwrap_or("regular")),
+            tags: s.tags,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_csv, parse_json};
+    use crate::import::ImportedStatus;
+
+    #[test]
+    fn csv_exports_are_parsed() {
+        let csv = "id,email,notes,metadata,tags,subscriber_type\n\
+            1,ursula@example.com,,\"{\"\"name\"\": \"\"Ursula\"\"}\",\"['fiction', 'vip']\",regular\n\
+            2,new@example.com,,{},,unactivated\n\
+            3,gone@example.com,,{},,unsubscribed\n";
+
+        let records = parse_csv(csv.as_bytes()).unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].name.as_deref(), Some("Ursula"));
+        assert_eq!(records[0].tags, vec!["fiction", "vip"]);
+        assert_eq!(records[0].status, ImportedStatus::Confirmed);
+        assert_eq!(records[1].status, ImportedStatus::PendingConfirmation);
+        assert_eq!(
+            records[2].status,
+            ImportedStatus::Excluded("unsubscribed".into())
+        );
+    }
+
+    #[test]
+    fn json_exports_are_parsed() {
+        let json = r#"{"results": [
+            {"email": "a@example.com", "subscriber_type": "premium", "tags": ["paid"]},
+            {"email": "b@example.com", "subscriber_type": "spammy", "tags": []}
+        ]}"#;
+
+        let records = parse_json(json.as_bytes()).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].status, ImportedStatus::Confirmed);
+        assert_eq!(records[0].tags, vec!["paid"]);
+        assert_eq!(records[1].status, ImportedStatus::Excluded("spammy".into()));
+    }
+}
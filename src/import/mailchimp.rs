@@ -0,0 +1,169 @@
+use crate::import::{split_tags, ImportedStatus, ImportedSubscriber};
+use std::io::Read;
+
+/// Mailchimp exports one CSV per audience status, e.g. `subscribed_members_export_xxx.csv`,
+/// `unsubscribed_members_export_xxx.csv` and `cleaned_members_export_xxx.csv`.
+pub fn status_from_file_name(file_name: &str) -> ImportedStatus {
+    if file_name.contains("unsubscribed") {
+        ImportedStatus::Excluded("unsubscribed".into())
+    } else if file_name.contains("cleaned") {
+        ImportedStatus::Excluded("cleaned".into())
+    } else if file_name.contains("pending") {
+        ImportedStatus::PendingConfirmation
+    } else {
+        ImportedStatus::Confirmed
+    }
+}
+
+fn map_status(status: &str) -> ImportedStatus {
+    match status {
+        "subscribed" => ImportedStatus::Confirmed,
+        "pending" => ImportedStatus::PendingConfirmation,
+        other => ImportedStatus::Excluded(other.to_owned()),
+    }
+}
+
+fn full_name(first_name: &str, last_name: &str) -> Option<String> {
+    let name = format!("{} {}", first_name.trim(), last_name.trim());
+    let name = name.trim();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_owned())
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CsvRecord {
+    #[serde(rename = "Email Address")]
+    email: String,
+    #[serde(rename = "First Name", default)]
+    first_name: String,
+    #[serde(rename = "Last Name", default)]
+    last_name: String,
+    #[serde(rename = "TAGS", default)]
+    tags: String,
+}
+
+pub fn parse_csv(
+    reader: impl Read,
+    status: ImportedStatus,
+) -> Result<Vec<ImportedSubscriber>, anyhow::Error> {
+    let mut records = Vec::new();
+    for record in csv::Reader::from_reader(reader).deserialize() {
+        let record: CsvRecord = record?;
+        records.push(ImportedSubscriber {
+            name: full_name(&record.first_name, &record.last_name),
+            email: record.email,
+            status: status.clone(),
+            tags: split_tags(&record.tags),
+        });
+    }
+    Ok(records)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum JsonExport {
+    Members { members: Vec<JsonMember> },
+    List(Vec<JsonMember>),
+}
+
+#[derive(serde::Deserialize)]
+struct JsonMember {
+    email_address: String,
+    status: String,
+    #[serde(default)]
+    merge_fields: MergeFields,
+    #[serde(default)]
+    tags: Vec<JsonTag>,
+}
+
+#[derive(serde::Deserialize, Default)]
+struct MergeFields {
+    #[serde(rename = "FNAME", default)]
+    first_name: String,
+    #[serde(rename = "LNAME", default)]
+    last_name: String,
+}
+
+#[derive(serde::Deserialize)]
+struct JsonTag {
+    name: String,
+}
+
+/// Parse the member list returned by the Mailchimp API (`/lists/{id}/members`).
+pub fn parse_json(reader: impl Read) -> Result<Vec<ImportedSubscriber>, anyhow::Error> {
+    let members = match serde_json::from_reader(reader)? {
+        JsonExport::Members { members } => members,
+        JsonExport::List(members) => members,
+    };
+    Ok(members
+        .into_iter()
+        .map(|m| ImportedSubscriber {
+            name: full_name(&m.merge_fields.first_name, &m.merge_fields.last_name),
+            email: m.email_address,
+            status: map_status(&m.status),
+            tags: m.tags.into_iter().map(|t| t.name).collect(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_csv, parse_json, status_from_file_name};
+    use crate::import::ImportedStatus;
+
+    #[test]
+    fn csv_exports_are_parsed() {
+        let csv = "Email Address,First Name,Last Name,MEMBER_RATING,TAGS\n\
+            ursula@example.com,Ursula,Le Guin,2,\"\"\"Customer\"\",\"\"VIP\"\"\"\n\
+            anonymous@example.com,,,1,\n";
+
+        let records = parse_csv(csv.as_bytes(), ImportedStatus::Confirmed).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].email, "ursula@example.com");
+        assert_eq!(records[0].name.as_deref(), Some("Ursula Le Guin"));
+        assert_eq!(records[0].tags, vec!["Customer", "VIP"]);
+        assert_eq!(records[1].name, None);
+        assert!(records[1].tags.is_empty());
+    }
+
+    #[test]
+    fn the_status_of_csv_exports_is_inferred_from_the_file_name() {
+        assert_eq!(
+            status_from_file_name("subscribed_members_export_1a2b.csv"),
+            ImportedStatus::Confirmed
+        );
+        assert_eq!(
+            status_from_file_name("unsubscribed_members_export_1a2b.csv"),
+            ImportedStatus::Excluded("unsubscribed".into())
+        );
+        assert_eq!(
+            status_from_file_name("cleaned_members_export_1a2b.csv"),
+            ImportedStatus::Excluded("cleaned".into())
+        );
+    }
+
+    #[test]
+    fn json_exports_are_parsed() {
+        let json = r#"{"members": [
+            {"email_address": "a@example.com", "status": "subscribed",
+             "merge_fields": {"FNAME": "Ann", "LNAME": ""}, "tags": [{"id": 1, "name": "VIP"}]},
+            {"email_address": "b@example.com", "status": "pending"},
+            {"email_address": "c@example.com", "status": "cleaned"}
+        ]}"#;
+
+        let records = parse_json(json.as_bytes()).unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].name.as_deref(), Some("Ann"));
+        assert_eq!(records[0].tags, vec!["VIP"]);
+        assert_eq!(records[1].status, ImportedStatus::PendingConfirmation);
+        assert_eq!(
+            records[2].status,
+            ImportedStatus::Excluded("cleaned".into())
+        );
+    }
+}
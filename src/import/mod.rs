@@ -0,0 +1,455 @@
+mod buttondown;
+mod mailchimp;
+mod report;
+
+use crate::bounces::is_suppressed;
+use crate::configuration::SubscriberSettings;
+use crate::domain::{
+    NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus, SubscriptionToken,
+};
+use crate::email_verification::{EmailVerifier, Verdict};
+use crate::pii::PiiCipher;
+use crate::repositories::{purge_deleted_subscriber, store_token};
+use crate::tenancy::TenantId;
+use crate::waitlist::{join_waitlist, spots_left};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use uuid::Uuid;
+
+pub use report::ImportReport;
+
+/// The newsletter providers we know how to import subscribers from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportSource {
+    Mailchimp,
+    Buttondown,
+}
+
+impl TryFrom<String> for ExportSource {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "mailchimp" => Ok(Self::Mailchimp),
+            "buttondown" => Ok(Self::Buttondown),
+            other => Err(format!(
+                "{} is not a supported export source. Use either `mailchimp` or `buttondown`.",
+                other
+            )),
+        }
+    }
+}
+
+/// How a subscriber status in the export maps onto our own statuses.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportedStatus {
+    Confirmed,
+    PendingConfirmation,
+    /// The provider no longer sends to this address (unsubscribed, bounced, complained...).
+    /// We do not import these, the provider status is kept for the report.
+    Excluded(String),
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ImportedSubscriber {
+    pub email: String,
+    pub name: Option<String>,
+    pub status: ImportedStatus,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Parse an export file - CSV or JSON, based on its extension.
+pub fn parse_export(
+    source: ExportSource,
+    path: &Path,
+) -> Result<Vec<ImportedSubscriber>, anyhow::Error> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let is_json = path
+        .extension()
+        .map(|e| e.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let records = match (source, is_json) {
+        (ExportSource::Mailchimp, true) => mailchimp::parse_json(file),
+        (ExportSource::Mailchimp, false) => {
+            mailchimp::parse_csv(file, mailchimp::status_from_file_name(&file_name))
+        }
+        (ExportSource::Buttondown, true) => buttondown::parse_json(file),
+        (ExportSource::Buttondown, false) => buttondown::parse_csv(file),
+    };
+    records.with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// How many addresses are verified at once, see `verify_new_addresses`.
+const VERIFICATION_CONCURRENCY: usize = 10;
+
+/// A record of the export which passed validation, to import unless its address is known.
+struct Candidate {
+    email: SubscriberEmail,
+    name: Option<SubscriberName>,
+    status: SubscriptionStatus,
+    tags: Vec<String>,
+}
+
+/// Store the parsed subscribers for `tenant_id`, skipping addresses it already knows about.
+/// With `dry_run` nothing is persisted, but the report is computed all the same.
+/// New addresses are verified first if `subscribers.import_verification` says so: those
+/// found undeliverable are stored all the same, but issues are not delivered to them.
+/// Dry runs skip the verification, which may be billed per address.
+/// With a `cipher`, they are stored sealed, see `crate::pii`.
+/// Once the list is full, the new addresses join the waitlist instead, see
+/// `crate::waitlist`.
+#[tracing::instrument(name = "Import subscribers", skip(pool, records, settings, cipher), fields(n_records = records.len()))]
+pub async fn import_subscribers(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    records: Vec<ImportedSubscriber>,
+    dry_run: bool,
+    settings: &SubscriberSettings,
+    cipher: Option<&PiiCipher>,
+) -> Result<ImportReport, anyhow::Error> {
+    let mut report = ImportReport {
+        dry_run,
+        ..ImportReport::default()
+    };
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    for record in records {
+        let email = match SubscriberEmail::parse(record.email.trim().to_owned()) {
+            Ok(email) => email,
+            Err(e) => {
+                report.invalid.push((record.email, e));
+                continue;
+            }
+        };
+        if !seen.insert(email.as_ref().to_lowercase()) {
+            report.duplicates_in_export += 1;
+            continue;
+        }
+        let status = match record.status {
+            ImportedStatus::Confirmed => SubscriptionStatus::Confirmed,
+            ImportedStatus::PendingConfirmation => SubscriptionStatus::PendingConfirmation,
+            ImportedStatus::Excluded(provider_status) => {
+                *report.excluded.entry(provider_status).or_default() += 1;
+                continue;
+            }
+        };
+        let name = match record.name.filter(|n| !n.trim().is_empty()) {
+            Some(name) => Some(name),
+            None if settings.require_name => Some(fallback_name(&email)),
+            None => None,
+        };
+        let name = match name
+            .map(|name| SubscriberName::parse_with_max_length(name, settings.max_name_length))
+            .transpose()
+        {
+            Ok(name) => name,
+            Err(e) => {
+                report.invalid.push((record.email, e));
+                continue;
+            }
+        };
+        candidates.push(Candidate {
+            email,
+            name,
+            status,
+            tags: record.tags,
+        });
+    }
+
+    // Verifying can take seconds per address: it is done before the import transaction
+    // is opened, not while it holds its locks.
+    let verifier = settings.import_verification.verifier();
+    let verdicts = match &verifier {
+        Some(verifier) if !dry_run => {
+            verify_new_addresses(pool, tenant_id, &candidates, verifier, cipher).await?
+        }
+        _ => HashMap::new(),
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let mut spots = spots_left(&mut transaction, settings.max_subscribers).await?;
+    for candidate in candidates {
+        let Candidate {
+            email,
+            name,
+            status,
+            tags,
+        } = candidate;
+        if !is_new_address(&mut transaction, tenant_id, &email, cipher, &mut report).await? {
+            continue;
+        }
+        match &mut spots {
+            Some(0) => {
+                let new_subscriber = NewSubscriber {
+                    email,
+                    name,
+                    locale: None,
+                };
+                join_waitlist(&mut transaction, tenant_id, &new_subscriber, cipher).await?;
+                report.waitlisted += 1;
+                continue;
+            }
+            Some(left) => *left -= 1,
+            None => {}
+        }
+
+        let verdict = verdicts.get(&email.as_ref().to_lowercase()).copied();
+        if let Some(verdict) = verdict {
+            *report.verified.entry(verdict.as_str().into()).or_default() += 1;
+        }
+
+        let subscriber_id = insert_imported_subscriber(
+            &mut transaction,
+            tenant_id,
+            &email,
+            name.as_ref(),
+            status,
+            verdict,
+            cipher,
+        )
+        .await?;
+        if status == SubscriptionStatus::PendingConfirmation {
+            store_token(
+                &mut transaction,
+                subscriber_id,
+                &SubscriptionToken::generate(),
+            )
+            .await
+            .context("Failed to store subscription token in the database.")?;
+            report.imported_pending += 1;
+        } else {
+            report.imported_confirmed += 1;
+        }
+        store_tags(&mut transaction, subscriber_id, &tags).await?;
+    }
+
+    if dry_run {
+        transaction
+            .rollback()
+            .await
+            .context("Failed to roll back the import dry run.")?;
+    } else {
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the imported subscribers.")?;
+    }
+    Ok(report)
+}
+
+/// Whether `email` is to be imported, counting it in `report` otherwise: neither
+/// subscribed to `tenant_id` nor suppressed. A deleted subscriber is imported afresh.
+async fn is_new_address(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    email: &SubscriberEmail,
+    cipher: Option<&PiiCipher>,
+    report: &mut ImportReport,
+) -> Result<bool, anyhow::Error> {
+    purge_deleted_subscriber(transaction, tenant_id, email.as_ref(), cipher).await?;
+    if subscriber_exists(transaction, tenant_id, email, cipher).await? {
+        report.already_subscribed += 1;
+        return Ok(false);
+    }
+    if is_suppressed(transaction, email.as_ref(), cipher).await? {
+        *report.excluded.entry("suppressed".into()).or_default() += 1;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// The verdicts of the addresses of `candidates` which would be imported, keyed by their
+/// lowercased address. They are looked up in a transaction rolled back straight away, then
+/// verified `VERIFICATION_CONCURRENCY` at a time.
+async fn verify_new_addresses(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    candidates: &[Candidate],
+    verifier: &EmailVerifier,
+    cipher: Option<&PiiCipher>,
+) -> Result<HashMap<String, Verdict>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let mut new_addresses = Vec::new();
+    for candidate in candidates {
+        let mut ignored = ImportReport::default();
+        if is_new_address(
+            &mut transaction,
+            tenant_id,
+            &candidate.email,
+            cipher,
+            &mut ignored,
+        )
+        .await?
+        {
+            new_addresses.push(candidate.email.clone());
+        }
+    }
+    transaction
+        .rollback()
+        .await
+        .context("Failed to roll back the lookup of the addresses to verify.")?;
+
+    let mut verdicts = HashMap::new();
+    for chunk in new_addresses.chunks(VERIFICATION_CONCURRENCY) {
+        let chunk_verdicts =
+            futures_util::future::join_all(chunk.iter().map(|email| verifier.verify(email))).await;
+        verdicts.extend(
+            chunk
+                .iter()
+                .map(|email| email.as_ref().to_lowercase())
+                .zip(chunk_verdicts),
+        );
+    }
+    Ok(verdicts)
+}
+
+/// Providers do not always have a name for their subscribers, while we may require one.
+fn fallback_name(email: &SubscriberEmail) -> String {
+    email
+        .as_ref()
+        .split('@')
+        .next()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// Split a tag list as found in provider exports: `"a","b"`, `['a', 'b']` or `a, b`.
+fn split_tags(raw: &str) -> Vec<String> {
+    raw.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|t| t.trim().trim_matches(|c| c == '"' || c == '\'').trim())
+        .filter(|t| !t.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+async fn subscriber_exists(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    email: &SubscriberEmail,
+    cipher: Option<&PiiCipher>,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT id
+        FROM subscriptions
+        WHERE tenant_id = $1 AND (lower(email) = lower($2) OR email_blind_index = $3)
+        "#,
+        *tenant_id,
+        email.as_ref(),
+        cipher.map(|c| c.blind_index(email.as_ref()))
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to check if the subscriber already exists in database.")?;
+    Ok(row.is_some())
+}
+
+async fn insert_imported_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    email: &SubscriberEmail,
+    name: Option<&SubscriberName>,
+    status: SubscriptionStatus,
+    verdict: Option<Verdict>,
+    cipher: Option<&PiiCipher>,
+) -> Result<Uuid, anyhow::Error> {
+    let subscriber_id = Uuid::new_v4();
+    let name = name.map(AsRef::as_ref);
+    let sealed = cipher
+        .map(|c| c.seal(subscriber_id, email.as_ref(), name))
+        .transpose()?;
+    let (stored_email, stored_name) = match &sealed {
+        Some(sealed) => (sealed.email.as_str(), None),
+        None => (email.as_ref(), name),
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (
+            id, email, name, subscribed_at, status, tenant_id, verification,
+            email_encrypted, name_encrypted, email_blind_index, pii_encrypted_at
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
+            CASE WHEN $8::bytea IS NULL THEN NULL ELSE now() END
+        )
+        "#,
+        subscriber_id,
+        stored_email,
+        stored_name,
+        Utc::now(),
+        status as SubscriptionStatus,
+        *tenant_id,
+        verdict.map(|v| v.as_str()),
+        sealed.as_ref().map(|s| s.email_encrypted.as_slice()),
+        sealed.as_ref().and_then(|s| s.name_encrypted.as_deref()),
+        sealed.as_ref().map(|s| s.email_blind_index.as_slice())
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to insert an imported subscriber in the database.")?;
+    Ok(subscriber_id)
+}
+
+async fn store_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    tags: &[String],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT $1, tag FROM UNNEST($2::text[]) AS tag
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        tags
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to store the tags of an imported subscriber.")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_tags;
+
+    #[test]
+    fn mailchimp_style_tags_are_split() {
+        assert_eq!(split_tags(r#""Customer","VIP""#), vec!["Customer", "VIP"]);
+    }
+
+    #[test]
+    fn list_style_tags_are_split() {
+        assert_eq!(
+            split_tags("['early adopter', 'beta']"),
+            vec!["early adopter", "beta"]
+        );
+    }
+
+    #[test]
+    fn empty_tags_are_ignored() {
+        assert!(split_tags("").is_empty());
+        assert!(split_tags("[]").is_empty());
+    }
+}
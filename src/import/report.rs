@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+use std::fmt::Formatter;
+
+/// What happened to each record of an export.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub imported_confirmed: usize,
+    pub imported_pending: usize,
+    pub already_subscribed: usize,
+    pub duplicates_in_export: usize,
+    /// New addresses which joined the waitlist, the list being full.
+    pub waitlisted: usize,
+    /// The verdicts of the addresses verified before being imported - none on dry runs.
+    pub verified: BTreeMap<String, usize>,
+    /// Records we deliberately skipped, grouped by their status in the provider.
+    pub excluded: BTreeMap<String, usize>,
+    /// Records we could not import, with the reason.
+    pub invalid: Vec<(String, String)>,
+}
+
+impl std::fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.dry_run {
+            writeln!(f, "Import report (dry run - nothing was saved)")?;
+        } else {
+            writeln!(f, "Import report")?;
+        }
+        writeln!(f, "  imported as confirmed: {}", self.imported_confirmed)?;
+        writeln!(
+            f,
+            "  imported as pending confirmation: {}",
+            self.imported_pending
+        )?;
+        writeln!(f, "  already subscribed: {}", self.already_subscribed)?;
+        writeln!(
+            f,
+            "  duplicates within the export: {}",
+            self.duplicates_in_export
+        )?;
+        if self.waitlisted > 0 {
+            writeln!(f, "  joined the waitlist: {}", self.waitlisted)?;
+        }
+        for (verdict, count) in &self.verified {
+            writeln!(f, "  verified as {}: {}", verdict, count)?;
+        }
+        for (status, count) in &self.excluded {
+            writeln!(f, "  excluded ({}): {}", status, count)?;
+        }
+        writeln!(f, "  invalid: {}", self.invalid.len())?;
+        for (record, reason) in &self.invalid {
+            writeln!(f, "    - {}: {}", record, reason)?;
+        }
+        Ok(())
+    }
+}
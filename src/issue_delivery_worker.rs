@@ -1,8 +1,19 @@
-use crate::configuration::Settings;
+use crate::configuration::{EmailFooterSettings, Settings};
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_failover::FailoverEmailSender;
+use crate::events::{record_delivery_completed_if_done, record_event, DomainEvent};
+use crate::i18n::Locale;
+use crate::merge_fields::fill;
+use crate::pii::{reveal_email, reveal_name, PiiCipher};
+use crate::read_only::{wait_while_read_only, ForcedReadOnly};
+use crate::repositories::{decompress_content, enqueue_unfinished_issues};
+use crate::sending_quota::SendingQuota;
+use crate::services::{EmailSender, NewIssue};
 use crate::startup::get_connection_pool;
-use sqlx::{PgPool, Postgres, Transaction};
+use crate::tenancy::{get_tenant, TenantId};
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgListener;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
 use std::time::Duration;
 use tracing::field::display;
 use tracing::Span;
@@ -10,29 +21,206 @@ use uuid::Uuid;
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+/// Notified on commit whenever deliveries are queued or an issue is resumed, see the
+/// `notify_issue_delivery_workers` trigger.
+pub const DELIVERY_CHANNEL: &str = "issue_delivery";
+
+/// An idle worker looks at the queue again after this long even if it was not notified:
+/// notifications are lost while the listener reconnects.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Deliveries left to domains throttled for the current minute are retried this often.
+const THROTTLED_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An issue as one of its recipients receives it.
+#[derive(Debug, serde::Serialize)]
+pub struct RenderedEmail {
+    /// The configured sender if `None`.
+    #[serde(skip)]
+    pub sender: Option<SubscriberEmail>,
+    /// Decrypted if the recipient is sealed, see `crate::pii`.
+    pub recipient: String,
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
 }
 
+impl NewsletterIssue {
+    /// Fill in the merge fields of the issue with the recipient's data.
+    fn with_merge_fields(self, name: Option<&str>, email: &str) -> Self {
+        let value = |field: &str| match field {
+            "name" => name,
+            "email" => Some(email),
+            _ => None,
+        };
+        Self {
+            title: fill(&self.title, value, str::to_owned),
+            text_content: fill(&self.text_content, value, str::to_owned),
+            html_content: fill(&self.html_content, value, htmlescape::encode_minimal),
+        }
+    }
+
+    /// Lead both versions of the content with a link to the issue in the public
+    /// archive, for recipients whose mail client mangles it.
+    fn with_view_in_browser_link(self, url: &str, locale: &Locale) -> Self {
+        let label = locale.t("issue-view-in-browser");
+        let link = format!(r#"<p><a href="{}">{}</a></p>"#, url, label);
+        // Right after the opening `<body>` tag, if the content is a full document.
+        let html_content = match find_body_start(&self.html_content) {
+            Some(i) => format!(
+                "{}\n{}{}",
+                &self.html_content[..i],
+                link,
+                &self.html_content[i..]
+            ),
+            None => format!("{}\n{}", link, self.html_content),
+        };
+        Self {
+            text_content: format!("{}: {}\n\n{}", label, url, self.text_content),
+            html_content,
+            title: self.title,
+        }
+    }
+
+    /// Close both versions of the content with the recipient's own unsubscribe link.
+    fn with_unsubscribe_link(self, url: &str, locale: &Locale) -> Self {
+        let label = locale.t("issue-unsubscribe");
+        let link = format!(r#"<p><a href="{}">{}</a></p>"#, url, label);
+        Self {
+            text_content: format!("{}\n\n{}: {}", self.text_content, label, url),
+            html_content: append_to_body(&self.html_content, &link),
+            title: self.title,
+        }
+    }
+
+    /// Close both versions of the content with who sends it and their postal address.
+    fn with_footer(self, footer: &EmailFooterSettings) -> Self {
+        Self {
+            text_content: format!("{}\n\n--\n{}", self.text_content, footer.text()),
+            html_content: append_to_body(&self.html_content, &footer.html()),
+            title: self.title,
+        }
+    }
+}
+
+/// Right before the closing `</body>` tag, if the content is a full document.
+fn append_to_body(html: &str, snippet: &str) -> String {
+    match html.to_ascii_lowercase().rfind("</body") {
+        Some(i) => format!("{}{}\n{}", &html[..i], snippet, &html[i..]),
+        None => format!("{}\n{}", html, snippet),
+    }
+}
+
+/// Where the content of the `<body>` element starts.
+fn find_body_start(html: &str) -> Option<usize> {
+    let tag = html.to_ascii_lowercase().find("<body")?;
+    html[tag..].find('>').map(|end| tag + end + 1)
+}
+
 pub enum ExecutionOutcome {
     TaskCompleted,
+    /// No delivery is due, or those left go to domains throttled for the current minute,
+    /// are sent from domains which reached the cap of their warm-up today, or belong to
+    /// paused issues.
     EmptyQueue,
+    /// Nothing is sent until the sending quota frees up.
+    QuotaExhausted,
 }
 
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    configuration
+        .database
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
     let connection_pool = get_connection_pool(&configuration.database);
 
-    let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    configuration
+        .email_footer
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    configuration
+        .newsletters
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    let default_locale = configuration
+        .i18n
+        .default_locale()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    let cipher = configuration
+        .pii_encryption
+        .cipher()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    let sender = configuration
+        .email_client
+        .sender()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    let email_sender = FailoverEmailSender::from_settings(&configuration.email_client);
+    worker_loop(
+        connection_pool,
+        email_sender,
+        configuration.application.public_base_url().to_owned(),
+        SendingQuota::from_settings(&configuration.newsletters, &sender),
+        configuration.email_footer,
+        default_locale,
+        ForcedReadOnly(configuration.application.read_only),
+        cipher,
+    )
+    .await
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+#[allow(clippy::too_many_arguments)]
+async fn worker_loop(
+    pool: PgPool,
+    email_sender: FailoverEmailSender,
+    base_url: String,
+    quota: SendingQuota,
+    footer: EmailFooterSettings,
+    default_locale: Locale,
+    read_only: ForcedReadOnly,
+    cipher: Option<PiiCipher>,
+) -> Result<(), anyhow::Error> {
+    let mut listener = listen_for_deliveries(&pool).await;
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        wait_while_read_only(&pool, read_only).await;
+        match try_execute_task(
+            &pool,
+            &email_sender,
+            &base_url,
+            &quota,
+            &footer,
+            &default_locale,
+            cipher.as_ref(),
+        )
+        .await
+        {
             Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                // The publishing requests which stopped halfway left deliveries to queue.
+                if let Err(e) = enqueue_unfinished_issues(&pool).await {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        "Failed to queue the deliveries of the issues left halfway"
+                    );
+                }
+                let next_due = match get_next_due_delivery(&pool).await {
+                    Ok(next_due) => next_due,
+                    Err(e) => {
+                        tracing::warn!(
+                            error.cause_chain = ?e,
+                            "Failed to find when the next delivery is due"
+                        );
+                        None
+                    }
+                };
+                wait_for_deliveries(listener.as_mut(), idle_wait(next_due, Utc::now())).await;
+            }
+            Ok(ExecutionOutcome::QuotaExhausted) => {
+                tokio::time::sleep(Duration::from_secs(60)).await;
             }
             Err(_) => {
                 tokio::time::sleep(Duration::from_secs(1)).await;
@@ -42,6 +230,71 @@ async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyh
     }
 }
 
+/// Without a listener, the worker falls back to polling the queue.
+async fn listen_for_deliveries(pool: &PgPool) -> Option<PgListener> {
+    let listener = async {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(DELIVERY_CHANNEL).await?;
+        Ok::<_, sqlx::Error>(listener)
+    };
+    match listener.await {
+        Ok(listener) => Some(listener),
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                "Failed to listen for queued deliveries. Polling the queue instead"
+            );
+            None
+        }
+    }
+}
+
+/// Return after `timeout`, or as soon as deliveries are queued.
+async fn wait_for_deliveries(listener: Option<&mut PgListener>, timeout: Duration) {
+    let listener = match listener {
+        Some(listener) => listener,
+        None => return tokio::time::sleep(timeout.min(THROTTLED_POLL_INTERVAL)).await,
+    };
+    if let Ok(Err(e)) = tokio::time::timeout(timeout, listener.recv()).await {
+        // The listener reconnects on the next call, and the queue is looked at again in
+        // the meantime in case a notification was missed.
+        tracing::warn!(
+            error.cause_chain = ?e,
+            "Lost the connection listening for queued deliveries"
+        );
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// How long to wait for a notification, given when the next delivery held back - by the
+/// undo window of its issue or the local send time of its recipient - is due.
+fn idle_wait(next_due: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Duration {
+    match next_due {
+        // Those already due go to throttled domains: no notification frees them up.
+        Some(due) if due <= now => THROTTLED_POLL_INTERVAL,
+        Some(due) => (due - now)
+            .to_std()
+            .map_or(IDLE_POLL_INTERVAL, |d| d.min(IDLE_POLL_INTERVAL)),
+        None => IDLE_POLL_INTERVAL,
+    }
+}
+
+/// Deliveries of paused issues are left out: resuming an issue notifies the workers.
+#[tracing::instrument(skip_all)]
+async fn get_next_due_delivery(pool: &PgPool) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT MIN(GREATEST(i.deliver_after, q.deliver_after)) AS next_due
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE i.paused_at IS NULL
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(r.next_due)
+}
+
 #[tracing::instrument(
     skip_all,
     fields(
@@ -52,34 +305,63 @@ async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyh
 )]
 pub async fn try_execute_task(
     pool: &PgPool,
-    email_client: &EmailClient,
+    email_sender: &dyn EmailSender,
+    base_url: &str,
+    quota: &SendingQuota,
+    footer: &EmailFooterSettings,
+    default_locale: &Locale,
+    cipher: Option<&PiiCipher>,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
+    if quota.is_exhausted(pool).await? {
+        return Ok(ExecutionOutcome::QuotaExhausted);
+    }
+    let warming_up = quota.domains_over_warm_up_cap(pool).await?;
+    let task = dequeue_task(pool, quota, &warming_up).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap();
+    let (mut transaction, issue_id, email, tenant_id) = task.unwrap();
     Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
+        .record("newsletter_issue_id", display(issue_id))
+        .record("subscriber_email", display(&email));
+
+    let rendered = render_email(
+        &mut transaction,
+        issue_id,
+        tenant_id,
+        &email,
+        base_url,
+        footer,
+        default_locale,
+        cipher,
+    )
+    .await?;
+    let (outcome, error, provider) = match SubscriberEmail::parse(rendered.recipient.clone()) {
+        Ok(recipient) => {
+            if !quota.try_reserve(pool).await? {
+                // Another worker sent the last email of the quota: the task stays queued.
+                return Ok(ExecutionOutcome::QuotaExhausted);
+            }
+            match email_sender
+                .send_email_via_provider(
+                    rendered.sender.as_ref(),
+                    &recipient,
+                    &rendered.subject,
+                    &rendered.html_content,
+                    &rendered.text_content,
+                    Some(&issue_id.to_string()),
                 )
                 .await
             {
-                tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Failed to deliver issue to a confirmed subscriber. Skipping"
-                );
+                Ok(provider) => ("delivered", None, provider),
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber. Skipping"
+                    );
+                    ("failed", Some(e.to_string()), None)
+                }
             }
         }
         Err(e) => {
@@ -88,27 +370,200 @@ pub async fn try_execute_task(
                 error.message = %e,
                 "Skipping a confirmed subscriber. Their stored contact details are invalid."
             );
+            ("skipped", Some(e), None)
         }
-    }
-    delete_task(transaction, issue_id, &email).await?;
+    };
+    let sender_domain = quota.sending_domain_of(rendered.sender.as_ref());
+    delete_task(
+        transaction,
+        tenant_id,
+        issue_id,
+        &email,
+        &sender_domain,
+        outcome,
+        error,
+        provider,
+    )
+    .await?;
 
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+/// Stands for the tokens and ids of the links of sample emails, which must not work.
+const SAMPLE_PLACEHOLDER: &str = "SAMPLE";
+
+/// Personalize the issue for one of its recipients: merge fields, links and footer.
+/// `email` is the address as queued - the pseudonym of a sealed recipient.
+#[tracing::instrument(skip(connection, base_url, footer, default_locale, cipher))]
+#[allow(clippy::too_many_arguments)]
+pub async fn render_email(
+    connection: &mut PgConnection,
+    issue_id: Uuid,
+    tenant_id: TenantId,
+    email: &str,
+    base_url: &str,
+    footer: &EmailFooterSettings,
+    default_locale: &Locale,
+    cipher: Option<&PiiCipher>,
+) -> Result<RenderedEmail, anyhow::Error> {
+    let issue = get_issue(&mut *connection, issue_id).await?;
+    personalize(
+        connection,
+        issue,
+        IssueLinks::Live(issue_id),
+        tenant_id,
+        email,
+        base_url,
+        footer,
+        default_locale,
+        cipher,
+    )
+    .await
+}
+
+/// `render_email` for an issue which is not published: its links to the archive and to
+/// unsubscribe carry placeholders instead of the issue id and of the recipient's token.
+#[tracing::instrument(skip(connection, issue, base_url, footer, default_locale, cipher))]
+#[allow(clippy::too_many_arguments)]
+pub async fn render_sample_email(
+    connection: &mut PgConnection,
+    issue: &NewIssue,
+    tenant_id: TenantId,
+    email: &str,
+    base_url: &str,
+    footer: &EmailFooterSettings,
+    default_locale: &Locale,
+    cipher: Option<&PiiCipher>,
+) -> Result<RenderedEmail, anyhow::Error> {
+    let issue = NewsletterIssue {
+        title: issue.title().into(),
+        text_content: issue.text_content().into(),
+        html_content: issue.html_content().into(),
+    };
+    personalize(
+        connection,
+        issue,
+        IssueLinks::Sample,
+        tenant_id,
+        email,
+        base_url,
+        footer,
+        default_locale,
+        cipher,
+    )
+    .await
+}
+
+enum IssueLinks {
+    Live(Uuid),
+    /// See `SAMPLE_PLACEHOLDER`.
+    Sample,
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn personalize(
+    connection: &mut PgConnection,
+    issue: NewsletterIssue,
+    links: IssueLinks,
+    tenant_id: TenantId,
+    email: &str,
+    base_url: &str,
+    footer: &EmailFooterSettings,
+    default_locale: &Locale,
+    cipher: Option<&PiiCipher>,
+) -> Result<RenderedEmail, anyhow::Error> {
+    let tenant = get_tenant(&mut *connection, tenant_id).await?;
+    let base_url = tenant.public_base_url(base_url);
+    let recipient = get_recipient(&mut *connection, tenant_id, email).await?;
+    let (address, name) = match &recipient {
+        Some(r) => (
+            reveal_email(cipher, r.id, email.to_owned(), r.email_encrypted.as_deref())?,
+            reveal_name(cipher, r.id, r.name.clone(), r.name_encrypted.as_deref())?,
+        ),
+        None => (email.to_owned(), None),
+    };
+    let locale = recipient
+        .as_ref()
+        .and_then(|r| r.locale.as_deref())
+        .and_then(|l| Locale::parse(l).ok())
+        .unwrap_or_else(|| default_locale.clone());
+    let (issue_id, token) = match links {
+        IssueLinks::Live(issue_id) => (
+            issue_id.to_string(),
+            recipient.and_then(|r| r.subscription_token),
+        ),
+        IssueLinks::Sample => (
+            SAMPLE_PLACEHOLDER.to_owned(),
+            Some(SAMPLE_PLACEHOLDER.to_owned()),
+        ),
+    };
+    let mut issue = issue
+        .with_merge_fields(name.as_deref(), &address)
+        .with_view_in_browser_link(&format!("{}/issues/{}", base_url, issue_id), &locale);
+    if let Some(token) = token {
+        issue = issue.with_unsubscribe_link(
+            &format!("{}/unsubscribe?subscription_token={}", base_url, token),
+            &locale,
+        );
+    }
+    let issue = issue.with_footer(footer);
+    Ok(RenderedEmail {
+        sender: tenant.sender_email.clone(),
+        recipient: address,
+        subject: issue.title,
+        html_content: issue.html_content,
+        text_content: issue.text_content,
+    })
+}
+
+/// The deliveries sent from the domains in `warming_up` are left in the queue.
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    quota: &SendingQuota,
+    warming_up: &[String],
+) -> Result<Option<(PgTransaction, Uuid, String, TenantId)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
 
+    let (domains, limits): (Vec<String>, Vec<i64>) = quota
+        .per_domain_per_minute
+        .iter()
+        .map(|(domain, limit)| (domain.clone(), *limit as i64))
+        .unzip();
     let r = sqlx::query!(
         r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
+        WITH sent_this_minute AS (
+            SELECT recipient_domain(subscriber_email) AS domain, COUNT(*) AS sent
+            FROM issue_deliveries
+            WHERE outcome <> 'skipped' AND attempted_at > now() - interval '1 minute'
+            GROUP BY 1
+        ), throttled AS (
+            SELECT s.domain
+            FROM sent_this_minute s
+            LEFT JOIN unnest($1::text[], $2::int8[]) AS l(domain, per_minute) USING (domain)
+            WHERE s.sent >= COALESCE(l.per_minute, $3)
+        )
+        SELECT q.newsletter_issue_id, q.subscriber_email, q.tenant_id
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        JOIN tenants t ON t.tenant_id = q.tenant_id
+        -- Issues still in their undo window or paused wait, and so do the recipients of
+        -- issues sent at local time until their send time.
+        WHERE
+            i.deliver_after <= now() AND
+            i.paused_at IS NULL AND
+            (q.deliver_after IS NULL OR q.deliver_after <= now()) AND
+            recipient_domain(q.subscriber_email) NOT IN (SELECT domain FROM throttled) AND
+            COALESCE(recipient_domain(t.sender_email), $5) <> ALL($4)
+        FOR UPDATE OF q
         SKIP LOCKED
         LIMIT 1
-        "#
+        "#,
+        &domains,
+        &limits,
+        quota.other_domains_per_minute.map(|l| l as i64),
+        warming_up,
+        quota.sender_domain,
     )
     .fetch_optional(&mut transaction)
     .await?;
@@ -117,6 +572,7 @@ async fn dequeue_task(
             transaction,
             r.newsletter_issue_id,
             r.subscriber_email,
+            TenantId::from(r.tenant_id),
         )))
     } else {
         Ok(None)
@@ -124,10 +580,16 @@ async fn dequeue_task(
 }
 
 #[tracing::instrument(skip_all)]
+#[allow(clippy::too_many_arguments)]
 async fn delete_task(
     mut transaction: PgTransaction,
+    tenant_id: TenantId,
     issue_id: Uuid,
     email: &str,
+    sender_domain: &str,
+    outcome: &str,
+    error: Option<String>,
+    provider: Option<&str>,
 ) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
@@ -141,23 +603,251 @@ async fn delete_task(
     )
     .execute(&mut transaction)
     .await?;
+    // Keep track of the outcome for the delivery reports.
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
+            newsletter_issue_id, subscriber_email, outcome, error, provider, sender_domain,
+            attempted_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            outcome = EXCLUDED.outcome,
+            error = EXCLUDED.error,
+            provider = EXCLUDED.provider,
+            sender_domain = EXCLUDED.sender_domain,
+            attempted_at = now()
+        "#,
+        issue_id,
+        email,
+        outcome,
+        error,
+        provider,
+        sender_domain
+    )
+    .execute(&mut transaction)
+    .await?;
+    if outcome != "skipped" {
+        // The day the domain first sent starts its warm-up, see `crate::sending_quota`.
+        sqlx::query!(
+            r#"
+            INSERT INTO sending_domains (domain, first_sent_on)
+            VALUES ($1, (now() AT TIME ZONE 'UTC')::date)
+            ON CONFLICT (domain) DO NOTHING
+            "#,
+            sender_domain
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    if outcome != "delivered" {
+        let event = DomainEvent::DeliveryFailed {
+            newsletter_issue_id: issue_id,
+            subscriber_email: email.to_owned(),
+            outcome: outcome.to_owned(),
+            error,
+        };
+        record_event(&mut transaction, tenant_id, &event).await?;
+    }
+    record_delivery_completed_if_done(&mut transaction, issue_id).await?;
     transaction.commit().await?;
     Ok(())
 }
 
 #[tracing::instrument(skip_all)]
-async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
-    let issue = sqlx::query_as!(
-        NewsletterIssue,
+async fn get_issue(
+    connection: &mut PgConnection,
+    issue_id: Uuid,
+) -> Result<NewsletterIssue, anyhow::Error> {
+    let row = sqlx::query!(
         r#"
-        SELECT title, text_content, html_content
+        SELECT title, text_content, text_content_zstd, html_content, html_content_zstd
         FROM newsletter_issues
         WHERE
             newsletter_issue_id = $1
         "#,
         issue_id
     )
-    .fetch_one(pool)
+    .fetch_one(connection)
+    .await?;
+    Ok(NewsletterIssue {
+        title: row.title,
+        text_content: decompress_content(row.text_content_zstd, row.text_content)?,
+        html_content: decompress_content(row.html_content_zstd, row.html_content)?,
+    })
+}
+
+struct Recipient {
+    id: Uuid,
+    name: Option<String>,
+    email_encrypted: Option<Vec<u8>>,
+    name_encrypted: Option<Vec<u8>>,
+    /// The token they can unsubscribe from the tenant's newsletter with.
+    subscription_token: Option<String>,
+    /// The language they chose at signup.
+    locale: Option<String>,
+}
+
+/// `None` if the recipient is no longer stored.
+#[tracing::instrument(skip_all)]
+async fn get_recipient(
+    connection: &mut PgConnection,
+    tenant_id: TenantId,
+    email: &str,
+) -> Result<Option<Recipient>, anyhow::Error> {
+    let recipient = sqlx::query_as!(
+        Recipient,
+        r#"
+        SELECT
+            s.id, s.name, s.email_encrypted, s.name_encrypted,
+            t.subscription_token AS "subscription_token?", s.locale
+        FROM subscriptions s
+        LEFT JOIN subscription_tokens t ON t.subscriber_id = s.id
+        WHERE s.email = $1 AND s.tenant_id = $2 AND s.deleted_at IS NULL
+        LIMIT 1
+        "#,
+        email,
+        *tenant_id
+    )
+    .fetch_optional(connection)
     .await?;
-    Ok(issue)
+    Ok(recipient)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{idle_wait, NewsletterIssue, IDLE_POLL_INTERVAL, THROTTLED_POLL_INTERVAL};
+    use crate::configuration::EmailFooterSettings;
+    use crate::i18n::Locale;
+    use chrono::{Duration, Utc};
+
+    fn english() -> Locale {
+        Locale::parse("en").unwrap()
+    }
+
+    fn issue(html_content: &str) -> NewsletterIssue {
+        NewsletterIssue {
+            title: "Title".into(),
+            text_content: "Hello".into(),
+            html_content: html_content.into(),
+        }
+    }
+
+    #[test]
+    fn merge_fields_are_filled_in_with_the_recipient_data() {
+        let issue = NewsletterIssue {
+            title: "News for {{ name | you }}".into(),
+            text_content: "Hi {{ name }}, sent to {{ email }}".into(),
+            html_content: "<p>Hi {{ name }}</p>".into(),
+        }
+        .with_merge_fields(Some("<Ursula>"), "ursula@example.com");
+
+        assert_eq!(issue.title, "News for <Ursula>");
+        assert_eq!(
+            issue.text_content,
+            "Hi <Ursula>, sent to ursula@example.com"
+        );
+        assert_eq!(issue.html_content, "<p>Hi &lt;Ursula&gt;</p>");
+    }
+
+    #[test]
+    fn the_link_leads_both_versions_of_the_content() {
+        let issue = issue("<p>Hello</p>")
+            .with_view_in_browser_link("https://example.com/issues/1", &english());
+
+        assert_eq!(
+            issue.text_content,
+            "View this email in your browser: https://example.com/issues/1\n\nHello"
+        );
+        assert_eq!(
+            issue.html_content,
+            "<p><a href=\"https://example.com/issues/1\">View this email in your browser</a></p>\n\
+             <p>Hello</p>"
+        );
+    }
+
+    #[test]
+    fn the_link_goes_inside_the_body_of_a_full_document() {
+        let issue = issue("<html><BODY class=\"x\"><p>Hello</p></BODY></html>")
+            .with_view_in_browser_link("https://example.com/issues/1", &english());
+
+        assert!(issue
+            .html_content
+            .starts_with("<html><BODY class=\"x\">\n<p><a href=\"https://example.com/issues/1\">"));
+        assert!(issue.html_content.ends_with("<p>Hello</p></BODY></html>"));
+    }
+
+    #[test]
+    fn the_unsubscribe_link_closes_both_versions_of_the_content() {
+        let issue = issue("<html><body><p>Hello</p></body></html>").with_unsubscribe_link(
+            "https://example.com/unsubscribe?subscription_token=abc",
+            &english(),
+        );
+
+        assert_eq!(
+            issue.text_content,
+            "Hello\n\nUnsubscribe: https://example.com/unsubscribe?subscription_token=abc"
+        );
+        assert_eq!(
+            issue.html_content,
+            "<html><body><p>Hello</p>\
+             <p><a href=\"https://example.com/unsubscribe?subscription_token=abc\">Unsubscribe</a></p>\n\
+             </body></html>"
+        );
+    }
+
+    #[test]
+    fn the_footer_comes_last_in_both_versions_of_the_content() {
+        let footer = EmailFooterSettings {
+            organization_name: "Zero To Production".into(),
+            postal_address: "1 Main Street\nSpringfield".into(),
+        };
+        let issue = issue("<html><body><p>Hello</p></body></html>")
+            .with_unsubscribe_link("https://example.com/unsubscribe", &english())
+            .with_footer(&footer);
+
+        assert!(issue
+            .text_content
+            .ends_with("Unsubscribe: https://example.com/unsubscribe\n\n--\nZero To Production\n1 Main Street\nSpringfield"));
+        assert!(issue.html_content.ends_with(
+            "<p class=\"footer\">Zero To Production<br>1 Main Street<br>Springfield</p>\n</body></html>"
+        ));
+    }
+
+    #[test]
+    fn the_links_are_in_the_language_of_the_recipient() {
+        let french = Locale::parse("fr").unwrap();
+        let issue = issue("<p>Bonjour</p>")
+            .with_view_in_browser_link("https://example.com/issues/1", &french)
+            .with_unsubscribe_link("https://example.com/unsubscribe", &french);
+
+        assert!(issue.text_content.starts_with(&format!(
+            "{}: https://example.com/issues/1",
+            french.t("issue-view-in-browser")
+        )));
+        assert!(issue.text_content.ends_with(&format!(
+            "{}: https://example.com/unsubscribe",
+            french.t("issue-unsubscribe")
+        )));
+    }
+
+    #[test]
+    fn an_idle_worker_wakes_up_in_time_for_the_next_held_back_delivery() {
+        let now = Utc::now();
+
+        assert_eq!(idle_wait(None, now), IDLE_POLL_INTERVAL);
+        assert_eq!(
+            idle_wait(Some(now + Duration::seconds(5)), now),
+            std::time::Duration::from_secs(5)
+        );
+        assert_eq!(
+            idle_wait(Some(now + Duration::hours(3)), now),
+            IDLE_POLL_INTERVAL
+        );
+        assert_eq!(
+            idle_wait(Some(now - Duration::seconds(5)), now),
+            THROTTLED_POLL_INTERVAL
+        );
+    }
 }
@@ -0,0 +1,85 @@
+//! Issue tags: the topics of the published issues, each with a category page in the
+//! public archive and its own RSS feed. Unlike segments, they do not change who
+//! receives an issue.
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use sqlx::PgPool;
+
+/// How many tags an issue can have at most.
+pub const MAX_TAGS: usize = 10;
+const MAX_TAG_LENGTH: usize = 32;
+
+/// Check a tag, returning it lowercased. Tags end up in URLs: only letters, digits
+/// and dashes are allowed.
+pub fn parse_tag(s: &str) -> Result<String, String> {
+    let tag = s.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err("A tag cannot be empty.".into());
+    }
+    if tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(format!(
+            "`{}` is too long: tags have at most {} characters.",
+            tag, MAX_TAG_LENGTH
+        ));
+    }
+    if !tag.chars().all(|c| c.is_alphanumeric() || c == '-') {
+        return Err(format!(
+            "`{}` is not a valid tag: use letters, digits and dashes.",
+            tag
+        ));
+    }
+    Ok(tag)
+}
+
+/// Split a comma-separated list of tags, as typed in the compose form.
+pub fn split_tags(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// The tags of the tenant's published issues, alphabetically.
+#[tracing::instrument(skip(pool))]
+pub async fn list_tags(pool: &PgPool, tenant_id: TenantId) -> Result<Vec<String>, anyhow::Error> {
+    let tags = sqlx::query!(
+        r#"
+        SELECT DISTINCT t.tag
+        FROM newsletter_issue_tags t
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE i.tenant_id = $1
+        ORDER BY t.tag
+        "#,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the issue tags")?;
+    Ok(tags.into_iter().map(|r| r.tag).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_tag, split_tags};
+    use claim::assert_err;
+
+    #[test]
+    fn tags_are_lowercased() {
+        assert_eq!(parse_tag(" Rust-Weekly ").unwrap(), "rust-weekly");
+    }
+
+    #[test]
+    fn tags_must_be_url_friendly() {
+        assert_err!(parse_tag(""));
+        assert_err!(parse_tag("rust weekly"));
+        assert_err!(parse_tag("rust/weekly"));
+        assert_err!(parse_tag(&"a".repeat(33)));
+    }
+
+    #[test]
+    fn tag_lists_are_split_on_commas() {
+        assert_eq!(split_tags("rust, web,, "), ["rust", "web"]);
+        assert!(split_tags(" ").is_empty());
+    }
+}
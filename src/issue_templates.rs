@@ -0,0 +1,135 @@
+//! Issue templates: reusable starting points for the issues which share a structure.
+//! Their sections - e.g. `{{ section: intro }}` - are the parts each issue must write,
+//! see `crate::merge_fields`. Each tenant has its own.
+use crate::merge_fields::unwritten_sections;
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct IssueTemplate {
+    pub template_id: Uuid,
+    pub name: String,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl IssueTemplate {
+    /// The sections the issues drafted from the template must write, once each.
+    pub fn required_fields(&self) -> Vec<&str> {
+        let mut fields: Vec<&str> = Vec::new();
+        for content in [&self.title, &self.text_content, &self.html_content] {
+            for name in unwritten_sections(content) {
+                if !fields.contains(&name) {
+                    fields.push(name);
+                }
+            }
+        }
+        fields
+    }
+}
+
+/// Check the name of a template, returning it trimmed.
+pub fn validate_template_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("The template needs a name.".into());
+    }
+    Ok(name)
+}
+
+/// The templates of `tenant_id`, by name.
+#[tracing::instrument(skip(pool))]
+pub async fn list_templates(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Vec<IssueTemplate>, anyhow::Error> {
+    sqlx::query_as!(
+        IssueTemplate,
+        r#"
+        SELECT template_id, name, title, text_content, html_content, created_at
+        FROM issue_templates
+        WHERE tenant_id = $1
+        ORDER BY name
+        "#,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the issue templates")
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_template(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    template_id: Uuid,
+) -> Result<Option<IssueTemplate>, anyhow::Error> {
+    sqlx::query_as!(
+        IssueTemplate,
+        r#"
+        SELECT template_id, name, title, text_content, html_content, created_at
+        FROM issue_templates
+        WHERE template_id = $1 AND tenant_id = $2
+        "#,
+        template_id,
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve an issue template")
+}
+
+/// Store a new template. Returns `false` if another template of `tenant_id` has the
+/// same name.
+#[tracing::instrument(skip(pool, title, text_content, html_content))]
+pub async fn add_template(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    name: &str,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+) -> Result<bool, anyhow::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO issue_templates (
+            template_id, tenant_id, name, title, text_content, html_content, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        ON CONFLICT (tenant_id, name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        *tenant_id,
+        name,
+        title,
+        text_content,
+        html_content
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store an issue template")?
+    .rows_affected();
+    Ok(inserted == 1)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn delete_template(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    template_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM issue_templates WHERE template_id = $1 AND tenant_id = $2"#,
+        template_id,
+        *tenant_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete an issue template")?;
+    Ok(())
+}
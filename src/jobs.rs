@@ -0,0 +1,465 @@
+use crate::backups::run_backup;
+use crate::configuration::{
+    BackupSettings, ConcurrencyLimitSettings, DatabaseSettings, IdempotencySettings,
+    PiiEncryptionSettings, Settings, SubscriberSettings,
+};
+use crate::import::{import_subscribers, ImportedSubscriber};
+use crate::maintenance::{run_maintenance_task, MaintenanceTask};
+use crate::read_only::{wait_while_read_only, ForcedReadOnly};
+use crate::repositories::{PostgresSubscriberRepository, SubscriberRepository};
+use crate::startup::get_connection_pool;
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::future::Future;
+use std::time::Duration;
+use tracing::field::display;
+use tracing::Span;
+use uuid::Uuid;
+
+/// Bulk deletions are committed in batches of this size, updating the job progress in between.
+const DELETE_BATCH_SIZE: usize = 100;
+
+/// How often the worker running a job records that it is still alive.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// A running job without a heartbeat for this long lost its worker: it is reclaimed.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// A job is run at most this many times: one whose worker keeps dying - e.g. running out
+/// of memory - fails instead of taking down every worker in turn.
+const MAX_JOB_ATTEMPTS: i32 = 3;
+
+/// Operations too long to run within an HTTP request.
+/// They are stored in the `jobs` table and picked up by the job worker.
+/// A job only touches the data of the tenant it was enqueued for - but for maintenance.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobPayload {
+    BulkImport {
+        subscribers: Vec<ImportedSubscriber>,
+    },
+    BulkDelete {
+        subscriber_ids: Vec<Uuid>,
+    },
+    /// Queue the delivery of an issue again to every confirmed subscriber who is not
+    /// already waiting for it, e.g. after an outage of the email provider.
+    Reenqueue {
+        newsletter_issue_id: Uuid,
+    },
+    /// Started from the admin tools page, see `crate::maintenance`.
+    Maintenance {
+        task: MaintenanceTask,
+    },
+    /// A `pg_dump` of the whole database, see `crate::backups`.
+    Backup,
+}
+
+/// The parts of the configuration the jobs need.
+#[derive(Clone)]
+pub struct JobSettings {
+    pub subscribers: SubscriberSettings,
+    pub idempotency: IdempotencySettings,
+    pub database: DatabaseSettings,
+    pub backups: BackupSettings,
+    pub pii_encryption: PiiEncryptionSettings,
+    pub concurrency_limits: ConcurrencyLimitSettings,
+}
+
+impl From<&Settings> for JobSettings {
+    fn from(configuration: &Settings) -> Self {
+        Self {
+            subscribers: configuration.subscribers.clone(),
+            idempotency: configuration.idempotency.clone(),
+            database: configuration.database.clone(),
+            backups: configuration.backups.clone(),
+            pii_encryption: configuration.pii_encryption.clone(),
+            concurrency_limits: configuration.concurrency_limits.clone(),
+        }
+    }
+}
+
+impl JobPayload {
+    fn kind(&self) -> &'static str {
+        match self {
+            JobPayload::BulkImport { .. } => "bulk_import",
+            JobPayload::BulkDelete { .. } => "bulk_delete",
+            JobPayload::Reenqueue { .. } => "reenqueue",
+            JobPayload::Maintenance { .. } => "maintenance",
+            JobPayload::Backup => "backup",
+        }
+    }
+
+    /// How many items the job will process, if known before it runs.
+    fn total(&self) -> usize {
+        match self {
+            JobPayload::BulkImport { subscribers } => subscribers.len(),
+            JobPayload::BulkDelete { subscriber_ids } => subscriber_ids.len(),
+            JobPayload::Reenqueue { .. } | JobPayload::Maintenance { .. } | JobPayload::Backup => 0,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Job {
+    pub job_id: Uuid,
+    pub kind: String,
+    /// `queued`, `running`, `succeeded` or `failed`.
+    pub status: String,
+    pub processed: i32,
+    pub total: i32,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+pub enum ExecutionOutcome {
+    JobCompleted,
+    EmptyQueue,
+}
+
+#[tracing::instrument(name = "Enqueue a job", skip(pool, payload), fields(kind = payload.kind()))]
+pub async fn enqueue_job(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    payload: &JobPayload,
+) -> Result<Uuid, anyhow::Error> {
+    let job_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO jobs (job_id, kind, payload, status, total, created_at, tenant_id)
+        VALUES ($1, $2, $3, 'queued', $4, now(), $5)
+        "#,
+        job_id,
+        payload.kind(),
+        serde_json::to_value(payload).context("Failed to serialize the job payload")?,
+        payload.total() as i32,
+        *tenant_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to enqueue a job")?;
+    Ok(job_id)
+}
+
+pub async fn get_job(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    job_id: Uuid,
+) -> Result<Option<Job>, anyhow::Error> {
+    let job = sqlx::query_as!(
+        Job,
+        r#"
+        SELECT
+            job_id, kind, status, processed, total, result, error,
+            created_at, started_at, finished_at
+        FROM jobs
+        WHERE job_id = $1 AND tenant_id = $2
+        "#,
+        job_id,
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a job")?;
+    Ok(job)
+}
+
+pub async fn run_job_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    worker_loop(
+        connection_pool,
+        JobSettings::from(&configuration),
+        ForcedReadOnly(configuration.application.read_only),
+    )
+    .await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    settings: JobSettings,
+    read_only: ForcedReadOnly,
+) -> Result<(), anyhow::Error> {
+    loop {
+        wait_while_read_only(&pool, read_only).await;
+        match try_execute_job(&pool, &settings).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::JobCompleted) => {}
+        }
+    }
+}
+
+/// Run the oldest queued job, if any.
+/// A failing job is marked as `failed` - the error is only returned if we could not
+/// record the outcome.
+#[tracing::instrument(skip_all, fields(job_id=tracing::field::Empty), err)]
+pub async fn try_execute_job(
+    pool: &PgPool,
+    settings: &JobSettings,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let (job_id, tenant_id, payload, attempt) = match dequeue_job(pool, settings).await? {
+        Some(job) => job,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("job_id", display(job_id));
+
+    let outcome = match serde_json::from_value(payload) {
+        Ok(payload) => {
+            let job = execute_job(pool, job_id, tenant_id, payload, settings);
+            with_heartbeat(pool, job_id, attempt, job).await
+        }
+        Err(e) => Err(anyhow::Error::new(e).context("Failed to deserialize the job payload")),
+    };
+    // Should the job have been reclaimed meanwhile, its outcome is the next attempt's.
+    match outcome {
+        Ok(result) => {
+            sqlx::query!(
+                r#"
+                UPDATE jobs
+                SET status = 'succeeded', processed = total, result = $2, finished_at = now()
+                WHERE job_id = $1 AND attempts = $3
+                "#,
+                job_id,
+                result,
+                attempt
+            )
+            .execute(pool)
+            .await?;
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Job failed"
+            );
+            sqlx::query!(
+                r#"
+                UPDATE jobs
+                SET status = 'failed', error = $2, finished_at = now()
+                WHERE job_id = $1 AND attempts = $3
+                "#,
+                job_id,
+                format!("{:#}", e),
+                attempt
+            )
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(ExecutionOutcome::JobCompleted)
+}
+
+/// Jobs can run for a long time, so we do not hold a lock while they run:
+/// the job is claimed by switching it to `running`, and its worker beats its heartbeat
+/// until it is over. The jobs whose heartbeat stopped are queued again first - or fail,
+/// after `MAX_JOB_ATTEMPTS` - so that they neither stay `running` forever nor hold a
+/// slot of their tenant's imports.
+///
+/// A tenant runs at most `concurrency_limits.imports` bulk imports at once, whatever the
+/// number of job workers: its next ones wait in the queue. The workers claim jobs one at
+/// a time, so that two of them cannot both take the last free slot.
+#[tracing::instrument(skip_all)]
+async fn dequeue_job(
+    pool: &PgPool,
+    settings: &JobSettings,
+) -> Result<Option<(Uuid, TenantId, serde_json::Value, i32)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"SELECT 1 AS "locked!" FROM pg_advisory_xact_lock(hashtextextended('dequeue_job', 0))"#
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to lock the job queue")?;
+    sqlx::query!(
+        r#"
+        UPDATE jobs
+        SET
+            status = 'failed',
+            error = 'The worker running the job stopped responding.',
+            finished_at = now()
+        WHERE
+            status = 'running' AND
+            heartbeat_at < now() - make_interval(secs => $1) AND
+            attempts >= $2
+        "#,
+        HEARTBEAT_TIMEOUT.as_secs_f64(),
+        MAX_JOB_ATTEMPTS
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to fail the jobs of unresponsive workers")?;
+    sqlx::query!(
+        r#"
+        UPDATE jobs
+        SET status = 'queued', started_at = NULL, heartbeat_at = NULL
+        WHERE status = 'running' AND heartbeat_at < now() - make_interval(secs => $1)
+        "#,
+        HEARTBEAT_TIMEOUT.as_secs_f64()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to queue the jobs of unresponsive workers again")?;
+    let r = sqlx::query!(
+        r#"
+        UPDATE jobs
+        SET status = 'running', started_at = now(), heartbeat_at = now(), attempts = attempts + 1
+        WHERE job_id = (
+            SELECT job_id
+            FROM jobs q
+            WHERE status = 'queued'
+                AND (
+                    kind <> 'bulk_import' OR (
+                        SELECT COUNT(*)
+                        FROM jobs r
+                        WHERE r.tenant_id = q.tenant_id
+                            AND r.kind = 'bulk_import'
+                            AND r.status = 'running'
+                    ) < $1
+                )
+            ORDER BY created_at
+            FOR UPDATE
+            SKIP LOCKED
+            LIMIT 1
+        )
+        RETURNING job_id, tenant_id, payload, attempts
+        "#,
+        settings.concurrency_limits.imports as i64
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(r.map(|r| (r.job_id, TenantId::from(r.tenant_id), r.payload, r.attempts)))
+}
+
+/// Run `job`, beating its heartbeat meanwhile.
+async fn with_heartbeat<F>(
+    pool: &PgPool,
+    job_id: Uuid,
+    attempt: i32,
+    job: F,
+) -> Result<serde_json::Value, anyhow::Error>
+where
+    F: Future<Output = Result<serde_json::Value, anyhow::Error>>,
+{
+    tokio::pin!(job);
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    // The job was claimed with a heartbeat.
+    heartbeat.tick().await;
+    loop {
+        tokio::select! {
+            outcome = &mut job => return outcome,
+            _ = heartbeat.tick() => {
+                let beat = sqlx::query!(
+                    "UPDATE jobs SET heartbeat_at = now() WHERE job_id = $1 AND attempts = $2",
+                    job_id,
+                    attempt
+                )
+                .execute(pool)
+                .await;
+                if let Err(e) = beat {
+                    tracing::warn!(error.message = %e, "Failed to beat the heartbeat of a job");
+                }
+            }
+        }
+    }
+}
+
+async fn execute_job(
+    pool: &PgPool,
+    job_id: Uuid,
+    tenant_id: TenantId,
+    payload: JobPayload,
+    settings: &JobSettings,
+) -> Result<serde_json::Value, anyhow::Error> {
+    match payload {
+        JobPayload::BulkImport { subscribers } => {
+            let cipher = settings
+                .pii_encryption
+                .cipher()
+                .map_err(anyhow::Error::msg)?;
+            let report = import_subscribers(
+                pool,
+                tenant_id,
+                subscribers,
+                false,
+                &settings.subscribers,
+                cipher.as_ref(),
+            )
+            .await?;
+            Ok(serde_json::to_value(report)?)
+        }
+        JobPayload::BulkDelete { subscriber_ids } => {
+            let repository = PostgresSubscriberRepository::new(pool, tenant_id);
+            let mut deleted = 0;
+            for (i, batch) in subscriber_ids.chunks(DELETE_BATCH_SIZE).enumerate() {
+                deleted += repository.delete_subscribers(batch).await?;
+                let processed = i * DELETE_BATCH_SIZE + batch.len();
+                update_progress(pool, job_id, processed, subscriber_ids.len()).await?;
+            }
+            Ok(serde_json::json!({ "deleted": deleted }))
+        }
+        JobPayload::Reenqueue {
+            newsletter_issue_id,
+        } => {
+            let enqueued = sqlx::query!(
+                r#"
+                INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, tenant_id)
+                SELECT i.newsletter_issue_id, s.email, i.tenant_id
+                FROM subscriptions s
+                JOIN newsletter_issues i
+                    ON i.newsletter_issue_id = $1 AND i.tenant_id = s.tenant_id
+                WHERE
+                    i.tenant_id = $2 AND
+                    s.status = 'confirmed' AND
+                    s.deleted_at IS NULL AND
+                    (
+                        i.segment IS NULL OR
+                        EXISTS (
+                            SELECT 1 FROM subscriber_tags t
+                            WHERE t.subscriber_id = s.id AND t.tag = i.segment
+                        )
+                    )
+                ON CONFLICT DO NOTHING
+                "#,
+                newsletter_issue_id,
+                *tenant_id
+            )
+            .execute(pool)
+            .await
+            .context("Failed to enqueue delivery tasks")?
+            .rows_affected() as usize;
+            update_progress(pool, job_id, enqueued, enqueued).await?;
+            Ok(serde_json::json!({ "enqueued": enqueued }))
+        }
+        JobPayload::Maintenance { task } => {
+            run_maintenance_task(pool, tenant_id, task, &settings.idempotency).await
+        }
+        JobPayload::Backup => {
+            run_backup(pool, tenant_id, &settings.database, &settings.backups).await
+        }
+    }
+}
+
+async fn update_progress(
+    pool: &PgPool,
+    job_id: Uuid,
+    processed: usize,
+    total: usize,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE jobs SET processed = $2, total = $3 WHERE job_id = $1"#,
+        job_id,
+        processed as i32,
+        total as i32
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the progress of a job")?;
+    Ok(())
+}
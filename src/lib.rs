@@ -1,11 +1,66 @@
+pub mod activity;
 pub mod authentication;
+pub mod backups;
+pub mod bounces;
+pub mod bundle;
+pub mod cache;
+pub mod chat_notifications;
+pub mod churn;
+pub mod circuit_breaker;
+pub mod complaints;
+pub mod concurrency_limits;
 pub mod configuration;
+pub mod deliverability;
+pub mod deliverability_metrics;
+pub mod delivery_report;
+pub mod diff;
 pub mod domain;
+pub mod drafts;
 pub mod email_client;
+pub mod email_failover;
+pub mod email_outbox;
+pub mod email_verification;
+pub mod error;
+pub mod error_pages;
+pub mod events;
+pub mod i18n;
 pub mod idempotency;
+pub mod import;
 pub mod issue_delivery_worker;
+pub mod issue_tags;
+pub mod issue_templates;
+pub mod jobs;
+pub mod load_shedding;
+pub mod maintenance;
+pub mod merge_fields;
+pub mod notifications;
+pub mod pagination;
+pub mod pii;
+pub mod problem_details;
+pub mod query_metrics;
+pub mod re_engagement;
+pub mod read_only;
+pub mod redirects;
+pub mod repositories;
+pub mod retention;
 pub mod routes;
+pub mod scheduler;
+pub mod search;
+pub mod seed;
+pub mod sending_quota;
+pub mod services;
 pub mod session_state;
+pub mod signing;
+pub mod simulation;
+pub mod spam_check;
 pub mod startup;
+pub mod startup_checks;
+pub mod stats;
 pub mod telemetry;
+pub mod tenancy;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod utils;
+pub mod waitlist;
+pub mod webhooks;
+pub mod weekly_report;
@@ -0,0 +1,177 @@
+//! Load shedding: while the database is saturated, the non-essential pages - the archive,
+//! its feeds, the sitemap, the polled feeds of the API - are answered with a 503 and a
+//! `Retry-After`, so that the connections left go to signing up, confirming and the admin
+//! panel rather than the whole site timing out.
+//!
+//! The database is saturated when either:
+//! - acquiring a connection took longer than `max_acquire_latency_milliseconds`, as
+//!   measured in the background every `sample_interval_milliseconds`;
+//! - the share of the connections of the pool in use reaches `max_active_connections_ratio`.
+use crate::configuration::LoadSheddingSettings;
+use crate::error::AppError;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::{web, HttpResponse};
+use actix_web_lab::middleware::Next;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+
+/// The paths turned away first, along with everything below them.
+const NON_ESSENTIAL_PATHS: [&str; 4] = [
+    "/issues",
+    "/sitemap.xml",
+    "/api/v1/issues",
+    "/api/v1/recent",
+];
+
+fn is_non_essential(path: &str) -> bool {
+    NON_ESSENTIAL_PATHS.iter().any(|prefix| {
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+/// How loaded the database is, shared by the sampler and the middleware.
+pub struct DatabaseLoad {
+    settings: LoadSheddingSettings,
+    max_connections: u32,
+    /// Of the latest sample.
+    acquire_latency_milliseconds: AtomicU64,
+}
+
+impl DatabaseLoad {
+    pub fn new(settings: LoadSheddingSettings, max_connections: u32) -> Self {
+        Self {
+            settings,
+            max_connections,
+            acquire_latency_milliseconds: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_acquire_latency(&self, latency: Duration) {
+        self.acquire_latency_milliseconds
+            .store(latency.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn is_saturated(&self, pool: &PgPool) -> bool {
+        let active = pool.size().saturating_sub(pool.num_idle() as u32);
+        self.is_saturated_with(active)
+    }
+
+    fn is_saturated_with(&self, active_connections: u32) -> bool {
+        if !self.settings.enabled {
+            return false;
+        }
+        let latency =
+            Duration::from_millis(self.acquire_latency_milliseconds.load(Ordering::Relaxed));
+        let active_ratio = active_connections as f64 / self.max_connections.max(1) as f64;
+        latency >= self.settings.max_acquire_latency()
+            || active_ratio >= self.settings.max_active_connections_ratio
+    }
+}
+
+/// Measure how long acquiring a connection takes, for as long as the application runs.
+/// A failure to acquire one counts as slow as it was.
+pub async fn sample_acquire_latency(pool: PgPool, load: web::Data<DatabaseLoad>) {
+    let mut interval = tokio::time::interval(load.settings.sample_interval());
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let started_at = Instant::now();
+        if let Err(e) = pool.acquire().await {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                "Failed to acquire a connection to measure the database load"
+            );
+        }
+        load.record_acquire_latency(started_at.elapsed());
+    }
+}
+
+/// Turn the non-essential requests away while the database is saturated.
+pub async fn shed_load_when_saturated(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if is_non_essential(req.path()) {
+        let load = req.app_data::<web::Data<DatabaseLoad>>();
+        let pool = req.app_data::<web::Data<PgPool>>();
+        if let (Some(load), Some(pool)) = (load, pool) {
+            if load.is_saturated(pool) {
+                tracing::warn!(
+                    path = req.path(),
+                    "Shedding a request: the database is saturated"
+                );
+                let mut response = HttpResponse::from_error(
+                    AppError::unavailable(
+                        "The newsletter is very busy right now. Please try again in a moment.",
+                    )
+                    .with_code("overloaded"),
+                );
+                response.headers_mut().insert(
+                    RETRY_AFTER,
+                    HeaderValue::from(load.settings.retry_after_seconds),
+                );
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+        }
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_non_essential, DatabaseLoad};
+    use crate::configuration::LoadSheddingSettings;
+    use std::time::Duration;
+
+    fn load(enabled: bool) -> DatabaseLoad {
+        let settings = LoadSheddingSettings {
+            enabled,
+            max_acquire_latency_milliseconds: 500,
+            max_active_connections_ratio: 0.9,
+            sample_interval_milliseconds: 1000,
+            retry_after_seconds: 30,
+        };
+        DatabaseLoad::new(settings, 10)
+    }
+
+    #[test]
+    fn the_archive_and_the_feeds_are_shed_but_not_signing_up_or_the_admin_panel() {
+        assert!(is_non_essential("/issues"));
+        assert!(is_non_essential("/issues/tags/rust/feed.xml"));
+        assert!(is_non_essential("/sitemap.xml"));
+        assert!(is_non_essential("/api/v1/recent/issues"));
+        assert!(!is_non_essential("/issuesandmore"));
+        assert!(!is_non_essential("/subscriptions"));
+        assert!(!is_non_essential("/subscriptions/confirm"));
+        assert!(!is_non_essential("/admin/dashboard"));
+        assert!(!is_non_essential("/api/v1/subscribers"));
+    }
+
+    #[test]
+    fn the_database_is_saturated_by_slow_acquires_or_busy_connections() {
+        let load = load(true);
+        assert!(!load.is_saturated_with(8));
+        assert!(load.is_saturated_with(9));
+
+        load.record_acquire_latency(Duration::from_millis(600));
+        assert!(load.is_saturated_with(0));
+
+        load.record_acquire_latency(Duration::from_millis(20));
+        assert!(!load.is_saturated_with(0));
+    }
+
+    #[test]
+    fn nothing_is_shed_when_disabled() {
+        let load = load(false);
+        load.record_acquire_latency(Duration::from_secs(5));
+
+        assert!(!load.is_saturated_with(10));
+    }
+}
@@ -1,31 +1,367 @@
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::fmt::{Debug, Display};
+use std::path::PathBuf;
 use tokio::task::JoinError;
+use uuid::Uuid;
+use zero2prod::authentication::{create_user, set_operator, set_pii_access};
+use zero2prod::bundle::{export_bundle, import_bundle, SetupBundle};
+use zero2prod::configuration::Settings;
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::events::run_event_publisher_until_stopped;
+use zero2prod::import::{import_subscribers, parse_export, ExportSource};
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
-use zero2prod::startup::Application;
+use zero2prod::jobs::run_job_worker_until_stopped;
+use zero2prod::retention::apply_retention_policy;
+use zero2prod::scheduler::run_scheduler_until_stopped;
+use zero2prod::seed::seed;
+use zero2prod::startup::{get_connection_pool, Application};
+use zero2prod::startup_checks::check_schema_compatibility;
+use zero2prod::tenancy::{create_tenant, get_tenant_by_hostname, NewTenant, TenantId};
 use zero2prod::{configuration::get_configuration, telemetry::*};
 
+#[derive(Parser)]
+#[clap(name = "zero2prod", about = "A newsletter delivery service")]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the API and the background workers (the default).
+    Serve,
+    /// Import subscribers from a Mailchimp or Buttondown export.
+    Import {
+        /// Where the export comes from: `mailchimp` or `buttondown`.
+        #[clap(long, parse(try_from_str = parse_export_source))]
+        source: ExportSource,
+        /// Report what would be imported without saving anything.
+        #[clap(long)]
+        dry_run: bool,
+        /// The hostname of the tenant to import into, the default tenant if omitted.
+        #[clap(long)]
+        tenant: Option<String>,
+        /// The CSV or JSON files of the export.
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Fill the database with fake subscribers and issues, for local development.
+    Seed {
+        /// How many subscribers to sign up - most of them confirmed.
+        #[clap(long, default_value = "1000")]
+        subscribers: usize,
+        /// How many issues to publish to the confirmed subscribers.
+        #[clap(long, default_value = "5")]
+        issues: usize,
+    },
+    /// Register a newsletter served on its own hostname, along with its first admin.
+    /// The admin's password is printed.
+    AddTenant {
+        #[clap(long)]
+        name: String,
+        /// The hostname its pages are served on, e.g. `news.example.com`.
+        #[clap(long)]
+        hostname: String,
+        /// The address its emails are sent from, the configured sender if omitted.
+        #[clap(long)]
+        sender_email: Option<String>,
+        /// The base URL of the links sent to its subscribers, e.g. `https://example.com`.
+        /// The configured public base URL if omitted.
+        #[clap(long)]
+        public_base_url: Option<String>,
+        /// The incoming webhook of its chat channel. Its chat notifications are not posted
+        /// if omitted.
+        #[clap(long)]
+        chat_webhook_url: Option<String>,
+        #[clap(long)]
+        admin_username: String,
+    },
+    /// Print the setup of a newsletter as a JSON bundle - its settings, without
+    /// subscribers, issues or credentials - to import into another instance.
+    ExportSetup {
+        /// The hostname of the tenant to export, the default tenant if omitted.
+        #[clap(long)]
+        tenant: Option<String>,
+    },
+    /// Replace the setup of a newsletter with an exported bundle.
+    ImportSetup {
+        /// The hostname of the tenant to import into, the default tenant if omitted.
+        #[clap(long)]
+        tenant: Option<String>,
+        /// The JSON file of the bundle.
+        file: PathBuf,
+    },
+    /// Let an admin see the names and email addresses of subscribers, or mask them.
+    PiiAccess {
+        #[clap(long)]
+        username: String,
+        /// The hostname of the tenant of the admin, the default tenant if omitted.
+        #[clap(long)]
+        tenant: Option<String>,
+        /// Mask them from now on.
+        #[clap(long)]
+        revoke: bool,
+    },
+    /// Let an admin use the tools which work on the whole deployment - maintenance,
+    /// read-only mode, backups and query metrics - or take them away.
+    Operator {
+        #[clap(long)]
+        username: String,
+        /// The hostname of the tenant of the admin, the default tenant if omitted.
+        #[clap(long)]
+        tenant: Option<String>,
+        /// Take the role away.
+        #[clap(long)]
+        revoke: bool,
+    },
+    /// Apply the data retention policy of the configuration right away.
+    Retention {
+        /// Report what would be deleted or anonymized without changing anything.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Check that this release can run against the schema of the database, e.g. before
+    /// switching traffic to it or while the previous release still serves it.
+    /// Exits with an error if it cannot.
+    CheckSchema,
+}
+
+fn parse_export_source(s: &str) -> Result<ExportSource, String> {
+    s.to_string().try_into()
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
-    init_subscriber(subscriber);
-
+    let cli = Cli::parse();
     let configuration = get_configuration().expect("Failed to read configuration");
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            let (subscriber, log_handle) =
+                get_subscriber("zero2prod".into(), &configuration.logging, std::io::stdout);
+            init_subscriber(subscriber);
+            serve(configuration, log_handle).await
+        }
+        Command::Import {
+            source,
+            dry_run,
+            tenant,
+            files,
+        } => {
+            // Keep stdout for the report.
+            let (subscriber, _) =
+                get_subscriber("zero2prod".into(), &configuration.logging, std::io::stderr);
+            init_subscriber(subscriber);
+            import(configuration, source, dry_run, tenant, files).await
+        }
+        Command::Seed {
+            subscribers,
+            issues,
+        } => {
+            let (subscriber, _) =
+                get_subscriber("zero2prod".into(), &configuration.logging, std::io::stderr);
+            init_subscriber(subscriber);
+            let pool = get_connection_pool(&configuration.database);
+            let report = seed(&pool, subscribers, issues).await?;
+            print!("{}", report);
+            Ok(())
+        }
+        Command::AddTenant {
+            name,
+            hostname,
+            sender_email,
+            public_base_url,
+            chat_webhook_url,
+            admin_username,
+        } => {
+            let (subscriber, _) =
+                get_subscriber("zero2prod".into(), &configuration.logging, std::io::stderr);
+            init_subscriber(subscriber);
+            let sender_email = sender_email
+                .map(SubscriberEmail::parse)
+                .transpose()
+                .map_err(anyhow::Error::msg)?;
+            let pool = get_connection_pool(&configuration.database);
+            let new_tenant = NewTenant {
+                name,
+                hostname,
+                sender_email,
+                public_base_url,
+                chat_webhook_url,
+            };
+            let tenant_id = create_tenant(&pool, &new_tenant).await?;
+            let password = Uuid::new_v4().to_string();
+            create_user(
+                &admin_username,
+                Secret::new(password.clone()),
+                tenant_id,
+                &pool,
+            )
+            .await?;
+            println!("Tenant {} created.", tenant_id);
+            println!("Log in as {} with password {}", admin_username, password);
+            Ok(())
+        }
+        Command::ExportSetup { tenant } => {
+            // Keep stdout for the bundle.
+            let (subscriber, _) =
+                get_subscriber("zero2prod".into(), &configuration.logging, std::io::stderr);
+            init_subscriber(subscriber);
+            let pool = get_connection_pool(&configuration.database);
+            let tenant_id = resolve_tenant(&pool, tenant).await?;
+            let bundle = export_bundle(&pool, tenant_id).await?;
+            println!("{}", serde_json::to_string_pretty(&bundle)?);
+            Ok(())
+        }
+        Command::ImportSetup { tenant, file } => {
+            let (subscriber, _) =
+                get_subscriber("zero2prod".into(), &configuration.logging, std::io::stderr);
+            init_subscriber(subscriber);
+            let bundle = std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let bundle = SetupBundle::parse(&bundle)?;
+            let pool = get_connection_pool(&configuration.database);
+            let tenant_id = resolve_tenant(&pool, tenant).await?;
+            import_bundle(&pool, tenant_id, &bundle).await?;
+            println!("The setup of {} has been imported.", bundle.settings.name);
+            Ok(())
+        }
+        Command::PiiAccess {
+            username,
+            tenant,
+            revoke,
+        } => {
+            let (subscriber, _) =
+                get_subscriber("zero2prod".into(), &configuration.logging, std::io::stderr);
+            init_subscriber(subscriber);
+            let pool = get_connection_pool(&configuration.database);
+            let tenant_id = resolve_tenant(&pool, tenant).await?;
+            if !set_pii_access(&pool, tenant_id, &username, !revoke).await? {
+                anyhow::bail!("There is no admin named {}", username);
+            }
+            if revoke {
+                println!(
+                    "{} now sees the personal data of subscribers masked.",
+                    username
+                );
+            } else {
+                println!("{} can now see the personal data of subscribers.", username);
+            }
+            Ok(())
+        }
+        Command::Operator {
+            username,
+            tenant,
+            revoke,
+        } => {
+            let (subscriber, _) =
+                get_subscriber("zero2prod".into(), &configuration.logging, std::io::stderr);
+            init_subscriber(subscriber);
+            let pool = get_connection_pool(&configuration.database);
+            let tenant_id = resolve_tenant(&pool, tenant).await?;
+            if !set_operator(&pool, tenant_id, &username, !revoke).await? {
+                anyhow::bail!("There is no admin named {}", username);
+            }
+            if revoke {
+                println!("{} is no longer an operator.", username);
+            } else {
+                println!("{} is now an operator.", username);
+            }
+            Ok(())
+        }
+        Command::Retention { dry_run } => {
+            // Keep stdout for the report.
+            let (subscriber, _) =
+                get_subscriber("zero2prod".into(), &configuration.logging, std::io::stderr);
+            init_subscriber(subscriber);
+            let pool = get_connection_pool(&configuration.database);
+            let report = apply_retention_policy(&pool, &configuration.retention, dry_run).await?;
+            print!("{}", report);
+            Ok(())
+        }
+        Command::CheckSchema => {
+            let (subscriber, _) =
+                get_subscriber("zero2prod".into(), &configuration.logging, std::io::stderr);
+            init_subscriber(subscriber);
+            let pool = get_connection_pool(&configuration.database);
+            let versions = check_schema_compatibility(&pool).await?;
+            println!(
+                "Compatible: this release knows up to migration {}, {} newer migration(s) applied.",
+                versions.latest_known,
+                versions.newer.len()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// The tenant served on `hostname`, the default tenant if `None`.
+async fn resolve_tenant(pool: &PgPool, hostname: Option<String>) -> anyhow::Result<TenantId> {
+    match hostname {
+        Some(hostname) => Ok(get_tenant_by_hostname(pool, &hostname)
+            .await?
+            .with_context(|| format!("There is no tenant for {}", hostname))?
+            .id),
+        None => Ok(TenantId::DEFAULT),
+    }
+}
+
+async fn serve(configuration: Settings, log_handle: LogHandle) -> anyhow::Result<()> {
     let application = tokio::spawn(
-        Application::build(configuration.clone())
+        Application::build(configuration.clone(), log_handle)
             .await?
             .run_until_stopped(),
     );
 
-    let worker = tokio::spawn(run_worker_until_stopped(configuration));
+    let worker = tokio::spawn(run_worker_until_stopped(configuration.clone()));
+    let job_worker = tokio::spawn(run_job_worker_until_stopped(configuration.clone()));
+    let scheduler = tokio::spawn(run_scheduler_until_stopped(configuration.clone()));
+    let event_publisher = tokio::spawn(run_event_publisher_until_stopped(configuration));
 
     tokio::select! {
         o = application => report_exit("API", o),
         o = worker => report_exit("Background Worker", o),
+        o = job_worker => report_exit("Job Worker", o),
+        o = scheduler => report_exit("Scheduler", o),
+        o = event_publisher => report_exit("Event Publisher", o),
     };
 
     Ok(())
 }
 
+async fn import(
+    configuration: Settings,
+    source: ExportSource,
+    dry_run: bool,
+    tenant: Option<String>,
+    files: Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut records = Vec::new();
+    for file in files {
+        records.extend(parse_export(source, &file)?);
+    }
+    let pool = get_connection_pool(&configuration.database);
+    let tenant_id = resolve_tenant(&pool, tenant).await?;
+    let cipher = configuration
+        .pii_encryption
+        .cipher()
+        .map_err(anyhow::Error::msg)?;
+    let report = import_subscribers(
+        &pool,
+        tenant_id,
+        records,
+        dry_run,
+        &configuration.subscribers,
+        cipher.as_ref(),
+    )
+    .await?;
+    print!("{}", report);
+    Ok(())
+}
+
 fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
     match outcome {
         Ok(Ok(())) => {
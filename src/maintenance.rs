@@ -0,0 +1,228 @@
+//! Housekeeping run on demand from the admin tools page, as background jobs.
+//!
+//! The tasks work on the whole database, so only the operators can start them. The purge
+//! of the failed jobs is the exception: it only deletes the jobs of the tenant whose
+//! operator started it.
+use crate::configuration::IdempotencySettings;
+use crate::idempotency::delete_expired_keys;
+use crate::jobs::{enqueue_job, JobPayload};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+/// The tables written to on every delivery, request or event, which bloat the fastest.
+const HOT_TABLES: [&str; 6] = [
+    "issue_delivery_queue",
+    "issue_deliveries",
+    "idempotency",
+    "jobs",
+    "events",
+    "email_provider_events",
+];
+
+/// The full-text indexes searched by the admin search and the public archive.
+const SEARCH_INDEXES: [&str; 2] = ["subscriptions_search_idx", "newsletter_issues_search_idx"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceTask {
+    VacuumAnalyze,
+    PurgeIdempotencyKeys,
+    /// The jobs which failed: they are never retried.
+    PurgeDeadLetters,
+    RebuildSearchIndexes,
+}
+
+impl MaintenanceTask {
+    pub const ALL: [MaintenanceTask; 4] = [
+        MaintenanceTask::VacuumAnalyze,
+        MaintenanceTask::PurgeIdempotencyKeys,
+        MaintenanceTask::PurgeDeadLetters,
+        MaintenanceTask::RebuildSearchIndexes,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|task| task.as_str() == s)
+            .ok_or_else(|| format!("{} is not a maintenance task.", s))
+    }
+
+    /// How the task is stored and submitted.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MaintenanceTask::VacuumAnalyze => "vacuum_analyze",
+            MaintenanceTask::PurgeIdempotencyKeys => "purge_idempotency_keys",
+            MaintenanceTask::PurgeDeadLetters => "purge_dead_letters",
+            MaintenanceTask::RebuildSearchIndexes => "rebuild_search_indexes",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MaintenanceTask::VacuumAnalyze => "Vacuum and analyze the hot tables",
+            MaintenanceTask::PurgeIdempotencyKeys => "Purge the expired idempotency keys",
+            MaintenanceTask::PurgeDeadLetters => "Purge the failed jobs",
+            MaintenanceTask::RebuildSearchIndexes => "Rebuild the search indexes",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            MaintenanceTask::VacuumAnalyze => {
+                "Reclaims the space of deleted rows and refreshes the planner statistics of \
+                 the delivery queue, the delivery log, the jobs and the events."
+            }
+            MaintenanceTask::PurgeIdempotencyKeys => {
+                "Deletes the saved responses past their TTL now, instead of at the next run \
+                 of the scheduled job."
+            }
+            MaintenanceTask::PurgeDeadLetters => {
+                "Deletes the jobs of this newsletter which failed, with their errors. They are \
+                 never retried."
+            }
+            MaintenanceTask::RebuildSearchIndexes => {
+                "Rebuilds the full-text indexes of the subscribers and the issues, e.g. after \
+                 a large import. Searches are slower while it runs."
+            }
+        }
+    }
+}
+
+/// Returns what the task did, as the result of its job.
+#[tracing::instrument(name = "Run a maintenance task", skip(pool, idempotency))]
+pub async fn run_maintenance_task(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    task: MaintenanceTask,
+    idempotency: &IdempotencySettings,
+) -> Result<serde_json::Value, anyhow::Error> {
+    match task {
+        MaintenanceTask::VacuumAnalyze => {
+            for table in HOT_TABLES {
+                // `VACUUM` cannot run within a transaction, nor as a prepared statement.
+                pool.execute(format!("VACUUM (ANALYZE) {}", table).as_str())
+                    .await
+                    .with_context(|| format!("Failed to vacuum {}", table))?;
+            }
+            Ok(serde_json::json!({ "tables": HOT_TABLES }))
+        }
+        MaintenanceTask::PurgeIdempotencyKeys => {
+            let deleted = delete_expired_keys(pool, idempotency).await?;
+            Ok(serde_json::json!({ "deleted": deleted }))
+        }
+        MaintenanceTask::PurgeDeadLetters => {
+            let deleted = sqlx::query!(
+                "DELETE FROM jobs WHERE status = 'failed' AND tenant_id = $1",
+                *tenant_id
+            )
+            .execute(pool)
+            .await
+            .context("Failed to purge the failed jobs")?
+            .rows_affected();
+            Ok(serde_json::json!({ "deleted": deleted }))
+        }
+        MaintenanceTask::RebuildSearchIndexes => {
+            for index in SEARCH_INDEXES {
+                // Without `CONCURRENTLY`, the rebuild would block the writes to the table.
+                pool.execute(format!("REINDEX INDEX CONCURRENTLY {}", index).as_str())
+                    .await
+                    .with_context(|| format!("Failed to rebuild {}", index))?;
+            }
+            Ok(serde_json::json!({ "indexes": SEARCH_INDEXES }))
+        }
+    }
+}
+
+/// A maintenance job, as listed on the tools page.
+pub struct MaintenanceRun {
+    pub task: Option<MaintenanceTask>,
+    /// `queued`, `running`, `succeeded` or `failed`.
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl MaintenanceRun {
+    pub fn is_pending(&self) -> bool {
+        matches!(self.status.as_str(), "queued" | "running")
+    }
+}
+
+/// The latest maintenance jobs started by the admins of the tenant, newest first.
+#[tracing::instrument(name = "List the maintenance jobs", skip(pool))]
+pub async fn recent_maintenance_runs(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    limit: i64,
+) -> Result<Vec<MaintenanceRun>, anyhow::Error> {
+    let runs = sqlx::query!(
+        r#"
+        SELECT payload->>'task' AS task, status, error, created_at, finished_at
+        FROM jobs
+        WHERE kind = 'maintenance' AND tenant_id = $1
+        ORDER BY created_at DESC
+        LIMIT $2
+        "#,
+        *tenant_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the maintenance jobs")?
+    .into_iter()
+    .map(|r| MaintenanceRun {
+        task: r.task.and_then(|t| MaintenanceTask::parse(&t).ok()),
+        status: r.status,
+        error: r.error,
+        created_at: r.created_at,
+        finished_at: r.finished_at,
+    })
+    .collect();
+    Ok(runs)
+}
+
+/// Queue the task for the job worker. Returns `None` if it is already queued or running,
+/// whoever started it: `jobs_pending_maintenance_idx` lets a single one through.
+#[tracing::instrument(name = "Start a maintenance task", skip(pool))]
+pub async fn start_maintenance_task(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    task: MaintenanceTask,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    match enqueue_job(pool, tenant_id, &JobPayload::Maintenance { task }).await {
+        Ok(job_id) => Ok(Some(job_id)),
+        Err(e) => {
+            let pending = matches!(
+                e.downcast_ref::<sqlx::Error>(),
+                Some(sqlx::Error::Database(e)) if e.constraint() == Some("jobs_pending_maintenance_idx")
+            );
+            if pending {
+                Ok(None)
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MaintenanceTask;
+    use claim::assert_err;
+
+    #[test]
+    fn tasks_round_trip_through_their_stored_form() {
+        for task in MaintenanceTask::ALL {
+            assert_eq!(MaintenanceTask::parse(task.as_str()), Ok(task));
+            assert_eq!(
+                serde_json::to_value(task).unwrap(),
+                serde_json::json!(task.as_str())
+            );
+        }
+        assert_err!(MaintenanceTask::parse("drop_database"));
+    }
+}
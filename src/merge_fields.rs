@@ -0,0 +1,197 @@
+//! Merge fields: `{{ field }}` placeholders in the content of an issue, filled in for
+//! each recipient by the delivery worker - `Hi {{ name | there }},` falls back to
+//! "there" for the subscribers who left their name blank. The pages anyone can read,
+//! e.g. the archive, fill them in with their fallback alone.
+//!
+//! Only `SUBSCRIBER_FIELDS` are filled in from subscriber data. The sections of an issue
+//! template, `{{ section: intro }}`, are for the author to replace: an issue still
+//! containing one cannot be published. Any other placeholder is content like the rest.
+use std::ops::Range;
+
+/// The merge fields filled in from the data of each recipient.
+pub const SUBSCRIBER_FIELDS: [&str; 2] = ["name", "email"];
+
+/// Marks the sections of an issue template: `{{ section: intro }}`.
+const SECTION_PREFIX: &str = "section:";
+
+#[derive(Debug, PartialEq)]
+pub struct MergeField<'a> {
+    pub name: &'a str,
+    /// Used when the recipient has no value for the field.
+    pub fallback: Option<&'a str>,
+    /// A section of an issue template, for the author to write.
+    pub section: bool,
+}
+
+impl MergeField<'_> {
+    pub fn is_subscriber_field(&self) -> bool {
+        !self.section && SUBSCRIBER_FIELDS.contains(&self.name)
+    }
+}
+
+/// The placeholders of `content`, in order, with where they are.
+fn placeholders(content: &str) -> Vec<(Range<usize>, MergeField<'_>)> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(start) = content[from..].find("{{").map(|i| from + i) {
+        let end = match content[start + 2..].find("}}") {
+            Some(i) => start + 2 + i + 2,
+            None => break,
+        };
+        let inner = content[start + 2..end - 2].trim();
+        let (section, inner) = match inner.strip_prefix(SECTION_PREFIX) {
+            Some(name) => (true, name),
+            None => (false, inner),
+        };
+        let (name, fallback) = match inner.split_once('|') {
+            Some((name, fallback)) => (name.trim(), Some(fallback.trim())),
+            None => (inner.trim(), None),
+        };
+        found.push((
+            start..end,
+            MergeField {
+                name,
+                fallback,
+                section,
+            },
+        ));
+        from = end;
+    }
+    found
+}
+
+/// The merge fields of `content`, in order.
+pub fn merge_fields(content: &str) -> Vec<MergeField<'_>> {
+    placeholders(content).into_iter().map(|(_, f)| f).collect()
+}
+
+/// The template sections of `content` left to write, once each.
+pub fn unwritten_sections(content: &str) -> Vec<&str> {
+    let mut names: Vec<&str> = Vec::new();
+    for field in merge_fields(content) {
+        if field.section && !names.contains(&field.name) {
+            names.push(field.name);
+        }
+    }
+    names
+}
+
+/// Fill in the subscriber fields of `content` with the values of a recipient, through
+/// `escape` - HTML content needs its values escaped. The other placeholders are left as
+/// they are.
+pub fn fill<'a>(
+    content: &str,
+    value: impl Fn(&str) -> Option<&'a str>,
+    escape: impl Fn(&str) -> String,
+) -> String {
+    let mut filled = String::with_capacity(content.len());
+    let mut last = 0;
+    for (range, field) in placeholders(content) {
+        if !field.is_subscriber_field() {
+            continue;
+        }
+        let value = value(field.name)
+            .filter(|v| !v.trim().is_empty())
+            .or(field.fallback)
+            .unwrap_or_default();
+        filled.push_str(&content[last..range.start]);
+        filled.push_str(&escape(value));
+        last = range.end;
+    }
+    filled.push_str(&content[last..]);
+    filled
+}
+
+/// Fill in the subscriber fields of `content` for readers who are not one of its
+/// recipients, e.g. on the archive: with their fallback, or nothing.
+pub fn fill_with_fallbacks(content: &str, escape: impl Fn(&str) -> String) -> String {
+    fill(content, |_| None, escape)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill, fill_with_fallbacks, merge_fields, unwritten_sections, MergeField};
+
+    fn values(field: &str) -> Option<&'static str> {
+        match field {
+            "name" => Some("Ursula"),
+            "email" => Some("ursula@example.com"),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn placeholders_are_parsed_with_their_fallback() {
+        assert_eq!(
+            merge_fields("Hi {{name}}, {{ section: intro }} {{ name | there }}"),
+            [
+                MergeField {
+                    name: "name",
+                    fallback: None,
+                    section: false,
+                },
+                MergeField {
+                    name: "intro",
+                    fallback: None,
+                    section: true,
+                },
+                MergeField {
+                    name: "name",
+                    fallback: Some("there"),
+                    section: false,
+                },
+            ]
+        );
+        assert!(merge_fields("p { color: red; } {{ unclosed").is_empty());
+    }
+
+    #[test]
+    fn only_the_sections_are_left_to_write() {
+        assert_eq!(
+            unwritten_sections(
+                "{{ name }} {{ section: intro }} {{ email }} {{section:intro}} {{ section: outro }}"
+            ),
+            ["intro", "outro"]
+        );
+        assert!(unwritten_sections("Hi {{ name | there }}, {{ x }}").is_empty());
+    }
+
+    #[test]
+    fn subscriber_fields_are_filled_in() {
+        let filled = fill(
+            "Hi {{ name }} <{{email}}>, {{ section: name }} {{ x }}",
+            values,
+            str::to_owned,
+        );
+        assert_eq!(
+            filled,
+            "Hi Ursula <ursula@example.com>, {{ section: name }} {{ x }}"
+        );
+    }
+
+    #[test]
+    fn readers_who_are_not_recipients_get_the_fallbacks() {
+        let filled = fill_with_fallbacks("Hi {{ name | there }}{{ email }}!", str::to_owned);
+        assert_eq!(filled, "Hi there!");
+    }
+
+    #[test]
+    fn blank_values_fall_back() {
+        let filled = fill(
+            "Hi {{ name | there }}{{ name }}!",
+            |_| Some(" "),
+            str::to_owned,
+        );
+        assert_eq!(filled, "Hi there!");
+    }
+
+    #[test]
+    fn values_go_through_escape() {
+        let filled = fill(
+            "<p>{{ name }}</p>",
+            |_| Some("<b>"),
+            htmlescape::encode_minimal,
+        );
+        assert_eq!(filled, "<p>&lt;b&gt;</p>");
+    }
+}
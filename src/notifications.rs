@@ -0,0 +1,357 @@
+//! Emails to the admins about what happens to the newsletter.
+//!
+//! Each admin picks the events they want to hear about on `/admin/notifications`, and the
+//! address they are sent to. `notify_admins` is called in the transaction of the change
+//! it is about: the notifications go through the email outbox, sent by the
+//! `deliver_outbox_emails` scheduled job once the change is committed.
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use sqlx::{PgConnection, PgPool};
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminEvent {
+    /// Every delivery task of an issue has been processed.
+    SendCompleted,
+    /// An issue finished delivering with more failures than the admin tolerates.
+    DeliveryFailures,
+    /// At most once per `complaints::COMPLAINT_NOTIFICATION_WINDOW`.
+    NewComplaint,
+    /// The deliveries of an issue were paused for too many spam complaints.
+    DeliveryPaused,
+    NewLogin,
+    /// The weekly summary of the newsletter, see `crate::weekly_report`.
+    WeeklyReport,
+}
+
+impl AdminEvent {
+    pub const ALL: [AdminEvent; 6] = [
+        AdminEvent::SendCompleted,
+        AdminEvent::DeliveryFailures,
+        AdminEvent::NewComplaint,
+        AdminEvent::DeliveryPaused,
+        AdminEvent::NewLogin,
+        AdminEvent::WeeklyReport,
+    ];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|event| event.as_str() == s)
+            .ok_or_else(|| format!("{} is not a notification event.", s))
+    }
+
+    /// How the event is stored.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdminEvent::SendCompleted => "send_completed",
+            AdminEvent::DeliveryFailures => "delivery_failures",
+            AdminEvent::NewComplaint => "new_complaint",
+            AdminEvent::DeliveryPaused => "delivery_paused",
+            AdminEvent::NewLogin => "new_login",
+            AdminEvent::WeeklyReport => "weekly_report",
+        }
+    }
+
+    /// For the preferences form.
+    pub fn description(&self) -> &'static str {
+        match self {
+            AdminEvent::SendCompleted => "An issue finished sending",
+            AdminEvent::DeliveryFailures => "An issue finished sending with too many failures",
+            AdminEvent::NewComplaint => "A subscriber marked an issue as spam",
+            AdminEvent::DeliveryPaused => "An issue was paused for too many spam complaints",
+            AdminEvent::NewLogin => "An admin logged in",
+            AdminEvent::WeeklyReport => "A weekly report on the newsletter",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotificationPreferences {
+    /// `None` to receive no notification at all.
+    pub email: Option<String>,
+    pub events: Vec<AdminEvent>,
+    /// See `AdminEvent::DeliveryFailures`: the share of the deliveries which failed.
+    pub failure_rate_threshold: f64,
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        Self {
+            email: None,
+            events: Vec::new(),
+            failure_rate_threshold: 0.05,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get the notification preferences of an admin", skip(pool))]
+pub async fn get_notification_preferences(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<NotificationPreferences, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email, events, failure_rate_threshold
+        FROM admin_notification_preferences
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the notification preferences of an admin")?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(NotificationPreferences::default()),
+    };
+    Ok(NotificationPreferences {
+        email: Some(row.email),
+        // Events which are no longer sent are left out.
+        events: row
+            .events
+            .iter()
+            .filter_map(|event| AdminEvent::parse(event).ok())
+            .collect(),
+        failure_rate_threshold: row.failure_rate_threshold,
+    })
+}
+
+/// Without an address, the preferences are forgotten.
+#[tracing::instrument(name = "Save the notification preferences of an admin", skip(pool))]
+pub async fn save_notification_preferences(
+    pool: &PgPool,
+    user_id: Uuid,
+    preferences: &NotificationPreferences,
+) -> Result<(), anyhow::Error> {
+    let email = match &preferences.email {
+        Some(email) => email,
+        None => {
+            sqlx::query!(
+                "DELETE FROM admin_notification_preferences WHERE user_id = $1",
+                user_id
+            )
+            .execute(pool)
+            .await
+            .context("Failed to delete the notification preferences of an admin")?;
+            return Ok(());
+        }
+    };
+    let events: Vec<String> = preferences
+        .events
+        .iter()
+        .map(|event| event.as_str().to_owned())
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_notification_preferences (
+            user_id, email, events, failure_rate_threshold, updated_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (user_id) DO UPDATE
+        SET
+            email = EXCLUDED.email,
+            events = EXCLUDED.events,
+            failure_rate_threshold = EXCLUDED.failure_rate_threshold,
+            updated_at = now()
+        "#,
+        user_id,
+        email,
+        &events,
+        preferences.failure_rate_threshold
+    )
+    .execute(pool)
+    .await
+    .context("Failed to save the notification preferences of an admin")?;
+    Ok(())
+}
+
+pub struct AdminNotification {
+    pub event: AdminEvent,
+    pub subject: String,
+    /// Plain text, a paragraph per line.
+    pub body: String,
+    /// The HTML version of `body`, made of its paragraphs if `None`.
+    pub html_body: Option<String>,
+    /// For `AdminEvent::DeliveryFailures`: only the admins whose threshold is below it
+    /// are notified.
+    pub failure_rate: Option<f64>,
+}
+
+impl AdminNotification {
+    pub fn new(event: AdminEvent, subject: impl Into<String>, body: impl Into<String>) -> Self {
+        Self {
+            event,
+            subject: subject.into(),
+            body: body.into(),
+            html_body: None,
+            failure_rate: None,
+        }
+    }
+
+    fn html_content(&self) -> String {
+        if let Some(html_body) = &self.html_body {
+            return html_body.clone();
+        }
+        self.body
+            .lines()
+            .map(|line| format!("<p>{}</p>", htmlescape::encode_minimal(line)))
+            .collect()
+    }
+}
+
+/// Queue `notification` for the admins of the tenant who want it.
+/// Returns how many were notified.
+#[tracing::instrument(
+    name = "Notify the admins",
+    skip(connection, notification),
+    fields(event = notification.event.as_str())
+)]
+pub async fn notify_admins(
+    connection: &mut PgConnection,
+    tenant_id: TenantId,
+    notification: &AdminNotification,
+) -> Result<u64, anyhow::Error> {
+    let recipients = sqlx::query!(
+        r#"
+        SELECT p.email, t.sender_email
+        FROM admin_notification_preferences p
+        JOIN users u ON u.user_id = p.user_id
+        JOIN tenants t ON t.tenant_id = u.tenant_id
+        WHERE
+            u.tenant_id = $1 AND
+            $2 = ANY(p.events) AND
+            ($3::float8 IS NULL OR $3 > p.failure_rate_threshold)
+        "#,
+        *tenant_id,
+        notification.event.as_str(),
+        notification.failure_rate
+    )
+    .fetch_all(&mut *connection)
+    .await
+    .context("Failed to find the admins to notify")?;
+    let html_content = notification.html_content();
+    for r in &recipients {
+        sqlx::query!(
+            r#"
+            INSERT INTO email_outbox (
+                email_id, tenant_id, sender, recipient, subject, html_content,
+                text_content, enqueued_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+            "#,
+            Uuid::new_v4(),
+            *tenant_id,
+            r.sender_email,
+            r.email,
+            notification.subject,
+            html_content,
+            notification.body
+        )
+        .execute(&mut *connection)
+        .await
+        .context("Failed to queue the notification of an admin")?;
+    }
+    Ok(recipients.len() as u64)
+}
+
+/// Like `notify_admins`, but at most once per `window` for the tenant and the event: the
+/// notifications in between are dropped. Returns how many admins were notified.
+#[tracing::instrument(
+    name = "Notify the admins at most once in a while",
+    skip(connection, notification),
+    fields(event = notification.event.as_str())
+)]
+pub async fn notify_admins_at_most_every(
+    connection: &mut PgConnection,
+    tenant_id: TenantId,
+    notification: &AdminNotification,
+    window: Duration,
+) -> Result<u64, anyhow::Error> {
+    // The row is only updated once the previous notification is older than the window.
+    let due = sqlx::query!(
+        r#"
+        INSERT INTO admin_notifications_sent (tenant_id, event, sent_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (tenant_id, event) DO UPDATE
+        SET sent_at = now()
+        WHERE admin_notifications_sent.sent_at < now() - $3::float8 * interval '1 second'
+        RETURNING event
+        "#,
+        *tenant_id,
+        notification.event.as_str(),
+        window.as_secs_f64()
+    )
+    .fetch_optional(&mut *connection)
+    .await
+    .context("Failed to record when the admins were notified")?;
+    if due.is_none() {
+        return Ok(0);
+    }
+    notify_admins(connection, tenant_id, notification).await
+}
+
+/// The notifications of an issue which finished delivering: `SendCompleted`, and
+/// `DeliveryFailures` for the admins who find that too many failed.
+pub async fn notify_send_completed(
+    connection: &mut PgConnection,
+    tenant_id: TenantId,
+    title: &str,
+    delivered: i64,
+    failed: i64,
+) -> Result<(), anyhow::Error> {
+    let summary = format!(
+        "\"{}\" finished sending: {} delivered, {} failed or skipped.",
+        title, delivered, failed
+    );
+    let notification = AdminNotification::new(
+        AdminEvent::SendCompleted,
+        format!("\"{}\" has been sent", title),
+        summary.clone(),
+    );
+    notify_admins(&mut *connection, tenant_id, &notification).await?;
+    if failed > 0 {
+        let notification = AdminNotification {
+            failure_rate: Some(failed as f64 / (delivered + failed) as f64),
+            ..AdminNotification::new(
+                AdminEvent::DeliveryFailures,
+                format!("Many deliveries of \"{}\" failed", title),
+                format!(
+                    "{}\nIts delivery report tells why: failed deliveries can be retried from it.",
+                    summary
+                ),
+            )
+        };
+        notify_admins(connection, tenant_id, &notification).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AdminEvent, AdminNotification};
+    use claim::assert_err;
+
+    #[test]
+    fn events_round_trip_through_their_stored_form() {
+        for event in AdminEvent::ALL {
+            assert_eq!(AdminEvent::parse(event.as_str()), Ok(event));
+        }
+        assert_err!(AdminEvent::parse("new_subscriber"));
+    }
+
+    #[test]
+    fn each_line_of_the_body_is_an_escaped_paragraph() {
+        let notification = AdminNotification::new(
+            AdminEvent::NewLogin,
+            "New login",
+            "ursula logged in.\nFrom <127.0.0.1>.",
+        );
+
+        assert_eq!(
+            notification.html_content(),
+            "<p>ursula logged in.</p><p>From &lt;127.0.0.1&gt;.</p>"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Keyset pagination: a page starts right after the last item of the previous one,
+//! instead of skipping rows with `OFFSET` - which gets slower the further you go on
+//! large tables.
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 100;
+
+/// Opaque position in a list ordered by creation time, with the id as a tie-breaker.
+/// It points at the last item of the previous page.
+#[derive(Debug, PartialEq)]
+pub struct Cursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        base64::encode_config(
+            format!("{}|{}", self.created_at.to_rfc3339(), self.id),
+            base64::URL_SAFE_NO_PAD,
+        )
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("{} is not a valid cursor.", s);
+        let decoded = base64::decode_config(s, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let (created_at, id) = decoded.split_once('|').ok_or_else(invalid)?;
+        Ok(Self {
+            created_at: DateTime::parse_from_rfc3339(created_at)
+                .map_err(|_| invalid())?
+                .with_timezone(&Utc),
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+
+    /// Split the cursor for a query taking it as two nullable parameters.
+    pub fn unzip(cursor: Option<&Cursor>) -> (Option<DateTime<Utc>>, Option<Uuid>) {
+        cursor.map(|c| (c.created_at, c.id)).unzip()
+    }
+}
+
+/// `?cursor=...&limit=...` - both optional.
+#[derive(serde::Deserialize, Debug)]
+pub struct PageQuery {
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+impl PageQuery {
+    pub fn new(cursor: Option<String>, limit: Option<i64>) -> Self {
+        Self { cursor, limit }
+    }
+
+    pub fn parse(self) -> Result<(Option<Cursor>, i64), String> {
+        let cursor = self.cursor.map(|c| Cursor::parse(&c)).transpose()?;
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT);
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(format!("`limit` must be between 1 and {}.", MAX_LIMIT));
+        }
+        Ok((cursor, limit))
+    }
+}
+
+/// The cursor of the page after `items`, `None` once the end of the list is reached.
+pub fn next_cursor<T>(items: &[T], limit: i64, cursor_of: impl Fn(&T) -> Cursor) -> Option<String> {
+    if items.len() as i64 == limit {
+        items.last().map(|item| cursor_of(item).encode())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_cursor, Cursor, PageQuery};
+    use chrono::{TimeZone, Utc};
+    use claim::{assert_err, assert_none, assert_some};
+    use uuid::Uuid;
+
+    #[test]
+    fn a_cursor_survives_a_round_trip() {
+        let cursor = Cursor {
+            created_at: Utc.timestamp_opt(1_650_000_000, 123_456_000).unwrap(),
+            id: Uuid::new_v4(),
+        };
+        assert_eq!(Cursor::parse(&cursor.encode()).unwrap(), cursor);
+    }
+
+    #[test]
+    fn garbage_is_not_a_valid_cursor() {
+        assert_err!(Cursor::parse("not a cursor"));
+        assert_err!(Cursor::parse(&base64::encode_config(
+            "2022-04-15T00:00:00Z|not-a-uuid",
+            base64::URL_SAFE_NO_PAD
+        )));
+    }
+
+    #[test]
+    fn the_limit_must_be_within_bounds() {
+        for limit in [0, 101] {
+            let query = PageQuery {
+                cursor: None,
+                limit: Some(limit),
+            };
+            assert_err!(query.parse());
+        }
+    }
+
+    #[test]
+    fn there_is_no_next_page_after_a_short_one() {
+        let cursor_of = |id: &Uuid| Cursor {
+            created_at: Utc.timestamp_opt(1_650_000_000, 0).unwrap(),
+            id: *id,
+        };
+        let items = [Uuid::new_v4(), Uuid::new_v4()];
+
+        assert_some!(next_cursor(&items, 2, cursor_of));
+        assert_none!(next_cursor(&items, 3, cursor_of));
+    }
+}
@@ -0,0 +1,436 @@
+//! Application-level encryption of the personal data of subscribers, for deployments
+//! whose data-protection requirements go beyond the encryption of the disks.
+//!
+//! With `pii_encryption.enabled`, subscribers are sealed: the clear columns of
+//! `subscriptions` no longer hold their personal data.
+//! - `email` holds a pseudonym instead, see `pseudonymous_email`: the tables keyed by
+//!   address keep working, but nothing can be delivered to it. `name` is `NULL`.
+//! - `email_encrypted` and `name_encrypted` hold the values, sealed with AES-256-GCM
+//!   under `pii_encryption.key`, with the subscriber id as associated data so that a
+//!   ciphertext cannot be moved to another row. The delivery worker, the API and the
+//!   admin pages decrypt them - see `reveal_email` and `reveal_name`.
+//! - `email_blind_index` holds the HMAC-SHA256 of the lowercased address under
+//!   `pii_encryption.blind_index_key`: lookups by address - signups, imports, provider
+//!   events - match it rather than `email`.
+//!
+//! New subscribers are sealed as they are stored. The scheduler seals the others, e.g.
+//! those stored before the encryption was enabled.
+use crate::domain::SubscriberEmail;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Reserved by RFC 2606: nothing is ever delivered there.
+const PSEUDONYM_DOMAIN: &str = "encrypted.invalid";
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+/// Rows encrypted per transaction, so that a large backlog does not hold locks for long.
+const ENCRYPTION_BATCH_SIZE: i64 = 500;
+
+/// The cipher shared by the request handlers, `None` if the encryption is disabled.
+pub struct PiiEncryption(pub Option<PiiCipher>);
+
+impl PiiEncryption {
+    pub fn cipher(&self) -> Option<&PiiCipher> {
+        self.0.as_ref()
+    }
+}
+
+/// The personal data of a subscriber, as stored once sealed.
+pub struct SealedPii {
+    /// What the clear `email` column holds, see `pseudonymous_email`.
+    pub email: String,
+    pub email_encrypted: Vec<u8>,
+    pub name_encrypted: Option<Vec<u8>>,
+    pub email_blind_index: Vec<u8>,
+}
+
+pub struct PiiCipher {
+    cipher: Aes256Gcm,
+    blind_index_key: Secret<Vec<u8>>,
+}
+
+impl PiiCipher {
+    /// Both keys are base64-encoded and 32 bytes long.
+    pub fn new(key: &Secret<String>, blind_index_key: &Secret<String>) -> Result<Self, String> {
+        let key = decode_key(key).map_err(|e| format!("pii_encryption.key {}", e))?;
+        let blind_index_key = decode_key(blind_index_key)
+            .map_err(|e| format!("pii_encryption.blind_index_key {}", e))?;
+        if key == blind_index_key {
+            return Err("pii_encryption.key and blind_index_key must be different".into());
+        }
+        Ok(Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+            blind_index_key: Secret::new(blind_index_key),
+        })
+    }
+
+    /// A random nonce, followed by the ciphertext and its tag.
+    pub fn encrypt(&self, subscriber_id: Uuid, plaintext: &str) -> Result<Vec<u8>, anyhow::Error> {
+        let nonce: [u8; NONCE_LENGTH] = rand::random();
+        let payload = Payload {
+            msg: plaintext.as_bytes(),
+            aad: subscriber_id.as_bytes(),
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt a value"))?;
+        Ok([&nonce[..], &ciphertext].concat())
+    }
+
+    pub fn decrypt(&self, subscriber_id: Uuid, sealed: &[u8]) -> Result<String, anyhow::Error> {
+        if sealed.len() < NONCE_LENGTH {
+            anyhow::bail!("The encrypted value is truncated");
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: subscriber_id.as_bytes(),
+        };
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt a value: wrong key or tampered"))?;
+        String::from_utf8(plaintext).context("The decrypted value is not UTF-8")
+    }
+
+    pub fn seal(
+        &self,
+        subscriber_id: Uuid,
+        email: &str,
+        name: Option<&str>,
+    ) -> Result<SealedPii, anyhow::Error> {
+        Ok(SealedPii {
+            email: pseudonymous_email(subscriber_id, email),
+            email_encrypted: self.encrypt(subscriber_id, email)?,
+            name_encrypted: name
+                .map(|name| self.encrypt(subscriber_id, name))
+                .transpose()?,
+            email_blind_index: self.blind_index(email),
+        })
+    }
+
+    /// The same for an address whatever its case, as addresses are matched.
+    pub fn blind_index(&self, email: &str) -> Vec<u8> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.blind_index_key.expose_secret())
+            .expect("HMAC can take key of any size");
+        mac.update(email.to_lowercase().as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+fn decode_key(key: &Secret<String>) -> Result<Vec<u8>, String> {
+    let key = base64::decode(key.expose_secret().trim())
+        .map_err(|_| "must be base64-encoded".to_string())?;
+    if key.len() != KEY_LENGTH {
+        return Err(format!("must be {} bytes long", KEY_LENGTH));
+    }
+    Ok(key)
+}
+
+/// What the clear `email` column of a sealed subscriber holds: unique, stable and
+/// undeliverable. The domain of the address is kept for the throttling per recipient
+/// domain, see the `recipient_domain` SQL function.
+pub fn pseudonymous_email(subscriber_id: Uuid, email: &str) -> String {
+    let domain = email.rsplit_once('@').map_or("", |(_, domain)| domain);
+    format!(
+        "{}@{}.{}",
+        subscriber_id,
+        domain.to_lowercase(),
+        PSEUDONYM_DOMAIN
+    )
+}
+
+/// The address of a subscriber: `email` as stored, decrypted if they are sealed.
+pub fn reveal_email(
+    cipher: Option<&PiiCipher>,
+    subscriber_id: Uuid,
+    email: String,
+    email_encrypted: Option<&[u8]>,
+) -> Result<String, anyhow::Error> {
+    match (email_encrypted, cipher) {
+        (None, _) => Ok(email),
+        (Some(sealed), Some(cipher)) => cipher.decrypt(subscriber_id, sealed),
+        (Some(_), None) => {
+            anyhow::bail!("The subscriber is sealed, but pii_encryption is disabled")
+        }
+    }
+}
+
+/// The name of a subscriber: `name` as stored, decrypted if they are sealed.
+pub fn reveal_name(
+    cipher: Option<&PiiCipher>,
+    subscriber_id: Uuid,
+    name: Option<String>,
+    name_encrypted: Option<&[u8]>,
+) -> Result<Option<String>, anyhow::Error> {
+    match (name_encrypted, cipher) {
+        (None, _) => Ok(name),
+        (Some(sealed), Some(cipher)) => cipher.decrypt(subscriber_id, sealed).map(Some),
+        (Some(_), None) => {
+            anyhow::bail!("The subscriber is sealed, but pii_encryption is disabled")
+        }
+    }
+}
+
+/// What the admins who cannot see the personal data of subscribers are shown instead,
+/// see `crate::authentication::can_view_pii`: addresses go through `mask_email`, names
+/// through `mask_name`.
+pub trait Masked {
+    fn masked(self) -> Self;
+}
+
+/// An address as shown to the admins who cannot see the personal data of subscribers,
+/// see `crate::authentication::can_view_pii`: `u***@gmail.com`, or `***` if it is not one.
+pub fn mask_email(email: &str) -> String {
+    SubscriberEmail::parse(email.to_owned())
+        .map(|email| email.masked())
+        .unwrap_or_else(|_| "***".into())
+}
+
+/// A name as shown to the admins who cannot see the personal data of subscribers: its
+/// first character only.
+pub fn mask_name(name: &str) -> String {
+    format!("{}***", name.chars().next().unwrap_or('*'))
+}
+
+/// Seal the subscribers stored in the clear, batch after batch. The tables keyed by
+/// address follow them to their pseudonym. Those whose name was set in the clear since
+/// they were sealed are sealed again. Returns how many were sealed.
+#[tracing::instrument(name = "Seal the personal data of subscribers", skip_all)]
+pub async fn encrypt_subscribers(pool: &PgPool, cipher: &PiiCipher) -> Result<u64, anyhow::Error> {
+    let mut encrypted = 0;
+    loop {
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        let batch = sqlx::query!(
+            r#"
+            SELECT id, email, name, email_encrypted
+            FROM subscriptions
+            WHERE pii_encrypted_at IS NULL AND anonymized_at IS NULL
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+            ENCRYPTION_BATCH_SIZE
+        )
+        .fetch_all(&mut transaction)
+        .await
+        .context("Failed to find the subscribers to seal")?;
+        let (mut ids, mut emails, mut pseudonyms) = (Vec::new(), Vec::new(), Vec::new());
+        for r in batch {
+            let email = reveal_email(Some(cipher), r.id, r.email, r.email_encrypted.as_deref())?;
+            let sealed = cipher.seal(r.id, &email, r.name.as_deref())?;
+            sqlx::query!(
+                r#"
+                UPDATE subscriptions
+                SET
+                    email = $2,
+                    name = NULL,
+                    email_encrypted = $3,
+                    name_encrypted = $4,
+                    email_blind_index = $5,
+                    pii_encrypted_at = now()
+                WHERE id = $1
+                "#,
+                r.id,
+                sealed.email,
+                sealed.email_encrypted,
+                sealed.name_encrypted,
+                sealed.email_blind_index
+            )
+            .execute(&mut transaction)
+            .await
+            .context("Failed to seal the personal data of a subscriber")?;
+            sqlx::query!(
+                r#"
+                UPDATE email_provider_events
+                SET recipient_blind_index = $2
+                WHERE recipient = $1 AND recipient_blind_index IS NULL
+                "#,
+                email,
+                sealed.email_blind_index
+            )
+            .execute(&mut transaction)
+            .await
+            .context("Failed to index the provider events of a sealed subscriber")?;
+            ids.push(r.id);
+            emails.push(email);
+            pseudonyms.push(sealed.email);
+        }
+        sqlx::query!(
+            r#"
+            WITH sealed AS (
+                SELECT s.tenant_id, u.email, u.pseudonym
+                FROM UNNEST($1::uuid[], $2::text[], $3::text[]) AS u(id, email, pseudonym)
+                JOIN subscriptions s ON s.id = u.id
+            ), queued AS (
+                UPDATE issue_delivery_queue q
+                SET subscriber_email = s.pseudonym
+                FROM sealed s
+                WHERE q.tenant_id = s.tenant_id AND q.subscriber_email = s.email
+            ), deliveries AS (
+                UPDATE issue_deliveries d
+                SET subscriber_email = s.pseudonym
+                FROM sealed s, newsletter_issues i
+                WHERE
+                    d.newsletter_issue_id = i.newsletter_issue_id AND
+                    i.tenant_id = s.tenant_id AND
+                    d.subscriber_email = s.email
+            )
+            UPDATE issue_complaints c
+            SET subscriber_email = s.pseudonym
+            FROM sealed s, newsletter_issues i
+            WHERE
+                c.newsletter_issue_id = i.newsletter_issue_id AND
+                i.tenant_id = s.tenant_id AND
+                c.subscriber_email = lower(s.email)
+            "#,
+            &ids,
+            &emails,
+            &pseudonyms
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to move the deliveries of sealed subscribers to their pseudonym")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the encrypted personal data of subscribers")?;
+        encrypted += ids.len() as u64;
+        if (ids.len() as i64) < ENCRYPTION_BATCH_SIZE {
+            return Ok(encrypted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pseudonymous_email, reveal_email, reveal_name, PiiCipher};
+    use claim::{assert_err, assert_none};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn key(byte: u8) -> Secret<String> {
+        Secret::new(base64::encode([byte; 32]))
+    }
+
+    fn cipher() -> PiiCipher {
+        PiiCipher::new(&key(1), &key(2)).unwrap()
+    }
+
+    #[test]
+    fn values_round_trip_through_encryption() {
+        let (cipher, id) = (cipher(), Uuid::new_v4());
+
+        let sealed = cipher.encrypt(id, "ursula@example.com").unwrap();
+
+        assert_ne!(&sealed[12..], b"ursula@example.com");
+        assert_eq!(cipher.decrypt(id, &sealed).unwrap(), "ursula@example.com");
+    }
+
+    #[test]
+    fn a_value_cannot_be_decrypted_for_another_subscriber() {
+        let cipher = cipher();
+
+        let sealed = cipher.encrypt(Uuid::new_v4(), "Ursula").unwrap();
+
+        assert_err!(cipher.decrypt(Uuid::new_v4(), &sealed));
+    }
+
+    #[test]
+    fn blind_indexes_ignore_case_but_depend_on_the_key() {
+        let other = PiiCipher::new(&key(1), &key(3)).unwrap();
+
+        assert_eq!(
+            cipher().blind_index("Ursula@Example.com"),
+            cipher().blind_index("ursula@example.com")
+        );
+        assert_ne!(
+            cipher().blind_index("ursula@example.com"),
+            other.blind_index("ursula@example.com")
+        );
+    }
+
+    #[test]
+    fn sealed_subscribers_keep_nothing_in_the_clear() {
+        let (cipher, id) = (cipher(), Uuid::new_v4());
+
+        let sealed = cipher
+            .seal(id, "Ursula@Example.com", Some("Ursula"))
+            .unwrap();
+
+        assert_eq!(
+            sealed.email,
+            format!("{}@example.com.encrypted.invalid", id)
+        );
+        assert_eq!(sealed.email, pseudonymous_email(id, "ursula@example.com"));
+        assert_eq!(
+            sealed.email_blind_index,
+            cipher.blind_index("ursula@example.com")
+        );
+        assert_eq!(
+            cipher.decrypt(id, &sealed.email_encrypted).unwrap(),
+            "Ursula@Example.com"
+        );
+        assert_eq!(
+            cipher.decrypt(id, &sealed.name_encrypted.unwrap()).unwrap(),
+            "Ursula"
+        );
+        assert_none!(
+            cipher
+                .seal(id, "ursula@example.com", None)
+                .unwrap()
+                .name_encrypted
+        );
+    }
+
+    #[test]
+    fn only_sealed_values_are_decrypted() {
+        let (cipher, id) = (cipher(), Uuid::new_v4());
+        let sealed = cipher
+            .seal(id, "ursula@example.com", Some("Ursula"))
+            .unwrap();
+
+        assert_eq!(
+            reveal_email(None, id, "ursula@example.com".into(), None).unwrap(),
+            "ursula@example.com"
+        );
+        assert_eq!(
+            reveal_email(
+                Some(&cipher),
+                id,
+                sealed.email.clone(),
+                Some(&sealed.email_encrypted)
+            )
+            .unwrap(),
+            "ursula@example.com"
+        );
+        assert_eq!(
+            reveal_name(Some(&cipher), id, None, sealed.name_encrypted.as_deref()).unwrap(),
+            Some("Ursula".into())
+        );
+        // Without the key, a sealed subscriber is an error rather than their pseudonym.
+        assert_err!(reveal_email(
+            None,
+            id,
+            sealed.email,
+            Some(&sealed.email_encrypted)
+        ));
+    }
+
+    #[test]
+    fn keys_must_be_distinct_32_byte_base64_strings() {
+        assert!(PiiCipher::new(&key(1), &key(2)).is_ok());
+        assert!(PiiCipher::new(&key(1), &key(1)).is_err());
+        assert!(PiiCipher::new(&Secret::new("not base64!".into()), &key(2)).is_err());
+        assert!(PiiCipher::new(&Secret::new(base64::encode([1; 16])), &key(2)).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::error::AppError;
+use crate::routes::api::ApiError;
+use crate::utils::{accepts, is_bare_error};
+use crate::webhooks::WebhookError;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header;
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use actix_web_lab::middleware::Next;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Errors which can be rendered as RFC 7807 problem details.
+pub trait Problem: ResponseError {
+    /// A stable, machine-readable identifier for the error - e.g. `invalid_subscriber`.
+    fn problem_code(&self) -> &'static str;
+}
+
+/// The RFC 7807 document, with our machine-readable `code` as an extension member.
+#[derive(serde::Serialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    code: &'static str,
+}
+
+impl ProblemDetails {
+    pub fn new<E: Problem>(e: &E) -> Self {
+        let status = e.status_code();
+        // Do not leak the details of unexpected errors - they are logged.
+        let detail = if status == StatusCode::INTERNAL_SERVER_ERROR {
+            "An unexpected error occurred.".into()
+        } else {
+            e.to_string()
+        };
+        Self {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Unknown error"),
+            status: status.as_u16(),
+            detail,
+            code: e.problem_code(),
+        }
+    }
+
+    /// For the errors which are not ours, e.g. the 404 of an unknown path or a query
+    /// string which does not deserialize.
+    fn for_status(status: StatusCode, error: Option<&actix_web::Error>) -> Self {
+        let detail = match error {
+            Some(e) if status.is_client_error() => e.to_string(),
+            _ if status.is_server_error() => "An unexpected error occurred.".into(),
+            _ => status.canonical_reason().unwrap_or("Unknown error").into(),
+        };
+        let code = match status {
+            StatusCode::BAD_REQUEST => "bad_request",
+            StatusCode::UNAUTHORIZED => "unauthorized",
+            StatusCode::FORBIDDEN => "forbidden",
+            StatusCode::NOT_FOUND => "not_found",
+            StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+            StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+            StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+            status if status.is_server_error() => "internal_error",
+            _ => "error",
+        };
+        Self {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Unknown error"),
+            status: status.as_u16(),
+            detail,
+            code,
+        }
+    }
+
+    pub fn into_response(self) -> HttpResponse {
+        HttpResponse::build(StatusCode::from_u16(self.status).unwrap())
+            .content_type(PROBLEM_JSON)
+            .json(self)
+    }
+}
+
+fn from_error(e: &actix_web::Error) -> ProblemDetails {
+    if let Some(e) = e.as_error::<AppError>() {
+        ProblemDetails::new(e)
+    } else if let Some(e) = e.as_error::<ApiError>() {
+        ProblemDetails::new(e)
+    } else if let Some(e) = e.as_error::<WebhookError>() {
+        ProblemDetails::new(e)
+    } else {
+        ProblemDetails::for_status(e.as_response_error().status_code(), Some(e))
+    }
+}
+
+fn from_response<B: MessageBody>(res: &ServiceResponse<B>) -> Option<ProblemDetails> {
+    if !is_bare_error(res) {
+        return None;
+    }
+    Some(match res.response().error() {
+        Some(e) => from_error(e),
+        None => ProblemDetails::for_status(res.status(), None),
+    })
+}
+
+fn accepts_json(req: &HttpRequest) -> bool {
+    accepts(req, "application/json") || accepts(req, PROBLEM_JSON)
+}
+
+/// Render errors as `application/problem+json` for clients asking for JSON - ours, and
+/// actix's own. Browsers get HTML pages instead, see `crate::error_pages`.
+pub async fn render_problem_details(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    if !accepts_json(req.request()) {
+        return next.call(req).await.map(|res| res.map_into_boxed_body());
+    }
+    match next.call(req).await {
+        Ok(res) => match from_response(&res) {
+            Some(problem) => {
+                let mut response = problem.into_response();
+                // Keep the headers set alongside the error, e.g. `WWW-Authenticate`.
+                for (name, value) in res.headers() {
+                    if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+                        response.headers_mut().append(name.clone(), value.clone());
+                    }
+                }
+                Ok(res.into_response(response))
+            }
+            None => Ok(res.map_into_boxed_body()),
+        },
+        // Errors returned by other middlewares, e.g. an invalid API key.
+        Err(e) => {
+            let status = e.as_response_error().status_code();
+            if status.is_client_error() || status.is_server_error() {
+                let problem = from_error(&e);
+                Err(InternalError::from_response(e, problem.into_response()).into())
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
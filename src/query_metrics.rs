@@ -0,0 +1,272 @@
+//! The latency of each query the application runs, shown on `/admin/queries`, to find the
+//! hotspots of the database without an external APM.
+//!
+//! sqlx logs every statement it executes, along with how long it took, to the `log`
+//! crate under the `sqlx::query` target. `QueryMetricsLogger` sits in front of the bridge
+//! from `log` to `tracing`: it records the latency of every statement, whatever the log
+//! filter, and forwards the records the filter lets through. The statements slower than
+//! `slow_query_threshold_milliseconds` are logged at `WARN`, in the span of the request or
+//! the job which ran them - see `DatabaseSettings::with_db`.
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::log::{self, Log, Metadata, Record};
+use tracing_log::LogTracer;
+
+const QUERY_TARGET: &str = "sqlx::query";
+
+/// The upper bounds of the buckets of the histograms, in milliseconds. Slower queries
+/// fall into an overflow bucket.
+const BUCKETS_MILLISECONDS: [u64; 12] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000];
+
+/// Queries beyond this many distinct ones are not recorded, should some SQL be built on
+/// the fly.
+const MAX_QUERIES: usize = 1000;
+
+/// `log` supports a single logger per process, so there is a single set of metrics too.
+static QUERY_METRICS: Lazy<QueryMetrics> = Lazy::new(QueryMetrics::default);
+
+pub fn query_metrics() -> &'static QueryMetrics {
+    &QUERY_METRICS
+}
+
+/// Install `QueryMetricsLogger` as the logger of the process.
+pub fn init_query_metrics_logger() -> Result<(), log::SetLoggerError> {
+    log::set_boxed_logger(Box::new(QueryMetricsLogger {
+        inner: LogTracer::new(),
+    }))?;
+    log::set_max_level(log::LevelFilter::Trace);
+    Ok(())
+}
+
+pub struct QueryMetricsLogger {
+    inner: LogTracer,
+}
+
+impl Log for QueryMetricsLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target() == QUERY_TARGET || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if record.target() == QUERY_TARGET {
+            if let Some((query, elapsed)) = parse_query_log(&record.args().to_string()) {
+                QUERY_METRICS.record(query, elapsed);
+            }
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// The query and how long it took, out of a statement logged by sqlx:
+/// `{summary}; rows affected: {n}, rows returned: {n}, elapsed: {elapsed}`, followed by the
+/// whole query - pretty-printed - when the summary cuts it short.
+fn parse_query_log(message: &str) -> Option<(String, Duration)> {
+    let (head, sql) = match message.split_once("\n\n") {
+        Some((head, sql)) => (head, Some(sql)),
+        None => (message, None),
+    };
+    let (summary, stats) = head.rsplit_once("; rows affected: ")?;
+    let (_, elapsed) = stats.rsplit_once("elapsed: ")?;
+    let elapsed = parse_duration(elapsed.trim())?;
+    let query = sql
+        .unwrap_or(summary)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some((query, elapsed))
+}
+
+/// A `Duration` as formatted by `{:.3?}`, e.g. `12.345ms`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (value, nanos_per_unit) = if let Some(value) = s.strip_suffix("ms") {
+        (value, 1e6)
+    } else if let Some(value) = s.strip_suffix("µs") {
+        (value, 1e3)
+    } else if let Some(value) = s.strip_suffix("ns") {
+        (value, 1.0)
+    } else {
+        (s.strip_suffix('s')?, 1e9)
+    };
+    let value: f64 = value.parse().ok()?;
+    (value.is_finite() && value >= 0.0)
+        .then(|| Duration::from_nanos((value * nanos_per_unit).round() as u64))
+}
+
+#[derive(Debug, Default, Clone)]
+struct LatencyHistogram {
+    /// One more than `BUCKETS_MILLISECONDS`, for the overflow.
+    buckets: [u64; BUCKETS_MILLISECONDS.len() + 1],
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, elapsed: Duration) {
+        let bucket = BUCKETS_MILLISECONDS
+            .iter()
+            .position(|&bound| elapsed <= Duration::from_millis(bound))
+            .unwrap_or(BUCKETS_MILLISECONDS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+
+    /// The upper bound of the bucket of the `quantile` - or the slowest query, if it
+    /// overflowed.
+    fn quantile(&self, quantile: f64) -> Duration {
+        let rank = ((self.count as f64) * quantile).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS_MILLISECONDS) {
+            seen += bucket;
+            if seen >= rank {
+                return Duration::from_millis(bound).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// The latency of a query since the process started.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryStats {
+    /// With its whitespace collapsed.
+    pub query: String,
+    pub count: u64,
+    pub total: Duration,
+    /// Estimated from the histogram.
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl QueryStats {
+    pub fn mean(&self) -> Duration {
+        Duration::from_nanos((self.total.as_nanos() / self.count.max(1) as u128) as u64)
+    }
+}
+
+#[derive(Default)]
+pub struct QueryMetrics {
+    queries: Mutex<HashMap<String, LatencyHistogram>>,
+}
+
+impl QueryMetrics {
+    pub fn record(&self, query: String, elapsed: Duration) {
+        let mut queries = self.queries.lock().unwrap();
+        let size = queries.len();
+        match queries.get_mut(&query) {
+            Some(histogram) => histogram.record(elapsed),
+            None if size < MAX_QUERIES => {
+                queries.entry(query).or_default().record(elapsed);
+            }
+            None => {}
+        }
+    }
+
+    /// The `limit` queries the database spent the most time on.
+    pub fn top_queries(&self, limit: usize) -> Vec<QueryStats> {
+        let mut stats: Vec<_> = self
+            .queries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(query, histogram)| QueryStats {
+                query: query.clone(),
+                count: histogram.count,
+                total: histogram.total,
+                p50: histogram.quantile(0.5),
+                p95: histogram.quantile(0.95),
+                max: histogram.max,
+            })
+            .collect();
+        stats.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.query.cmp(&b.query)));
+        stats.truncate(limit);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_duration, parse_query_log, LatencyHistogram, QueryMetrics};
+    use std::time::Duration;
+
+    #[test]
+    fn short_queries_are_logged_whole() {
+        let message = "SELECT can_view_pii FROM users WHERE user_id = $1; \
+            rows affected: 0, rows returned: 1, elapsed: 1.250ms";
+
+        let (query, elapsed) = parse_query_log(message).unwrap();
+
+        assert_eq!(query, "SELECT can_view_pii FROM users WHERE user_id = $1");
+        assert_eq!(elapsed, Duration::from_micros(1250));
+    }
+
+    #[test]
+    fn long_queries_are_logged_after_their_summary() {
+        let message = "SELECT email, name FROM …; rows affected: 0, rows returned: 3, \
+            elapsed: 2.000s\n\nSELECT\n  email,\n  name\nFROM\n  subscriptions\nWHERE\n  status = $1\n";
+
+        let (query, elapsed) = parse_query_log(message).unwrap();
+
+        assert_eq!(
+            query,
+            "SELECT email, name FROM subscriptions WHERE status = $1"
+        );
+        assert_eq!(elapsed, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn durations_are_parsed_in_every_unit() {
+        assert_eq!(parse_duration("1.500s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("12.000ms"), Some(Duration::from_millis(12)));
+        assert_eq!(
+            parse_duration("250.000µs"),
+            Some(Duration::from_micros(250))
+        );
+        assert_eq!(parse_duration("40.000ns"), Some(Duration::from_nanos(40)));
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_query_log("Not a query"), None);
+    }
+
+    #[test]
+    fn quantiles_are_the_bounds_of_their_bucket() {
+        let mut histogram = LatencyHistogram::default();
+        for _ in 0..90 {
+            histogram.record(Duration::from_micros(800));
+        }
+        for _ in 0..10 {
+            histogram.record(Duration::from_millis(30));
+        }
+
+        assert_eq!(histogram.quantile(0.5), Duration::from_millis(1));
+        assert_eq!(histogram.quantile(0.95), Duration::from_millis(30));
+        histogram.record(Duration::from_secs(9));
+        assert_eq!(histogram.quantile(1.0), Duration::from_secs(9));
+    }
+
+    #[test]
+    fn the_queries_the_database_spent_the_most_time_on_come_first() {
+        let metrics = QueryMetrics::default();
+        for _ in 0..100 {
+            metrics.record("SELECT 1".into(), Duration::from_millis(1));
+        }
+        metrics.record("SELECT 2".into(), Duration::from_secs(1));
+        metrics.record("SELECT 3".into(), Duration::from_millis(5));
+
+        let top = metrics.top_queries(2);
+
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].query, "SELECT 2");
+        assert_eq!(top[1].query, "SELECT 1");
+        assert_eq!(top[1].count, 100);
+        assert_eq!(top[1].mean(), Duration::from_millis(1));
+    }
+}
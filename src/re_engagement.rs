@@ -0,0 +1,251 @@
+//! Asking the subscribers who stopped opening the issues whether they still want them.
+//!
+//! Subscribers who neither opened nor clicked any of the last `inactive_issues` issues
+//! delivered to them are sent an email with a signed link to stay subscribed. Those who
+//! have not followed it once the grace period is over are unsubscribed, with `inactive`
+//! as their unsubscribe reason. Following the link - or opening an issue during the grace
+//! period - resets the count: only the issues delivered since make them inactive again.
+use crate::configuration::ReEngagementSettings;
+use crate::domain::SubscriberEmail;
+use crate::events::{record_event, DomainEvent};
+use crate::pii::{reveal_email, PiiCipher};
+use crate::services::EmailSender;
+use crate::signing::UrlSigner;
+use crate::tenancy::{get_tenant, TenantId};
+use anyhow::Context;
+use sqlx::PgPool;
+
+/// Stored as the unsubscribe reason of the subscribers who did not answer.
+pub const INACTIVE_UNSUBSCRIBE_REASON: &str = "inactive";
+
+/// Unsubscribe the subscribers whose grace period is over, then ask the newly inactive
+/// ones. Returns how many subscribers were unsubscribed or asked.
+#[tracing::instrument(
+    name = "Re-engage inactive subscribers",
+    skip(pool, email_sender, signer, settings, cipher)
+)]
+pub async fn re_engage_inactive_subscribers(
+    pool: &PgPool,
+    email_sender: &dyn EmailSender,
+    signer: &UrlSigner,
+    base_url: &str,
+    settings: &ReEngagementSettings,
+    cipher: Option<&PiiCipher>,
+) -> Result<u64, anyhow::Error> {
+    let unsubscribed = unsubscribe_non_responders(pool, settings).await?;
+    let asked =
+        ask_inactive_subscribers(pool, email_sender, signer, base_url, settings, cipher).await?;
+    Ok(unsubscribed + asked)
+}
+
+#[tracing::instrument(skip_all)]
+async fn unsubscribe_non_responders(
+    pool: &PgPool,
+    settings: &ReEngagementSettings,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // Opening or clicking an issue answers the question as well as following the link.
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions s
+        SET re_engagement_sent_at = NULL, re_engaged_at = now()
+        WHERE s.status = 'confirmed'
+            AND s.deleted_at IS NULL
+            AND s.re_engagement_sent_at IS NOT NULL
+            AND EXISTS (
+                SELECT 1
+                FROM email_provider_events e
+                WHERE e.record_type IN ('Open', 'Click')
+                    AND (e.recipient = s.email OR e.recipient_blind_index = s.email_blind_index)
+                    AND e.received_at >= s.re_engagement_sent_at
+            )
+        "#
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to keep the inactive subscribers who opened an issue since")?;
+    let unsubscribed = sqlx::query!(
+        r#"
+        WITH unsubscribed AS (
+            UPDATE subscriptions
+            SET status = 'unsubscribed',
+                unsubscribed_at = now(),
+                unsubscribe_reason = $2,
+                re_engagement_sent_at = NULL
+            WHERE status = 'confirmed'
+                AND deleted_at IS NULL
+                AND re_engagement_sent_at < now() - make_interval(days => $1)
+            RETURNING id, email, tenant_id
+        ), dequeued AS (
+            -- An issue still being delivered does not reach them either.
+            DELETE FROM issue_delivery_queue q
+            USING unsubscribed u
+            WHERE q.subscriber_email = u.email AND q.tenant_id = u.tenant_id
+        )
+        SELECT id, email, tenant_id FROM unsubscribed
+        "#,
+        settings.grace_period_days as i32,
+        INACTIVE_UNSUBSCRIBE_REASON
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to unsubscribe the subscribers who did not answer")?;
+    for r in &unsubscribed {
+        let event = DomainEvent::SubscriberUnsubscribed {
+            subscriber_id: r.id,
+            email: r.email.clone(),
+            reason: Some(INACTIVE_UNSUBSCRIBE_REASON.into()),
+        };
+        record_event(&mut transaction, TenantId::from(r.tenant_id), &event).await?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the unsubscribes of the inactive subscribers")?;
+    Ok(unsubscribed.len() as u64)
+}
+
+#[tracing::instrument(skip_all)]
+async fn ask_inactive_subscribers(
+    pool: &PgPool,
+    email_sender: &dyn EmailSender,
+    signer: &UrlSigner,
+    base_url: &str,
+    settings: &ReEngagementSettings,
+    cipher: Option<&PiiCipher>,
+) -> Result<u64, anyhow::Error> {
+    let inactive = sqlx::query!(
+        r#"
+        SELECT
+            s.id,
+            s.email,
+            s.email_encrypted,
+            s.tenant_id,
+            (
+                SELECT t.subscription_token
+                FROM subscription_tokens t
+                WHERE t.subscriber_id = s.id
+                LIMIT 1
+            ) AS subscription_token
+        FROM subscriptions s
+        JOIN LATERAL (
+            SELECT d.newsletter_issue_id
+            FROM issue_deliveries d
+            JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+            WHERE d.subscriber_email = s.email
+                AND i.tenant_id = s.tenant_id
+                AND d.outcome = 'delivered'
+                AND d.attempted_at > coalesce(s.re_engaged_at, '-infinity')
+            ORDER BY d.attempted_at DESC
+            LIMIT $1
+        ) recent ON true
+        WHERE s.status = 'confirmed'
+            AND s.deleted_at IS NULL
+            AND s.re_engagement_sent_at IS NULL
+        GROUP BY s.id
+        HAVING COUNT(*) = $1 AND bool_and(NOT EXISTS (
+            SELECT 1
+            FROM email_provider_events e
+            WHERE e.record_type IN ('Open', 'Click')
+                AND (e.recipient = s.email OR e.recipient_blind_index = s.email_blind_index)
+                AND e.payload->>'Tag' = recent.newsletter_issue_id::text
+        ))
+        "#,
+        i64::from(settings.inactive_issues)
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to find the inactive subscribers")?;
+
+    let mut asked = 0;
+    for r in inactive {
+        let email = reveal_email(cipher, r.id, r.email, r.email_encrypted.as_deref())?;
+        let (email, subscription_token) =
+            match (SubscriberEmail::parse(email), r.subscription_token) {
+                (Ok(email), Some(token)) => (email, token),
+                _ => {
+                    tracing::warn!(
+                        subscriber_id = %r.id,
+                        "Skipping an inactive subscriber without a valid email or token"
+                    );
+                    continue;
+                }
+            };
+        let tenant = get_tenant(pool, TenantId::from(r.tenant_id)).await?;
+        let stay_link = format!(
+            "{}{}",
+            tenant.public_base_url(base_url),
+            signer.sign(
+                &format!(
+                    "/subscriptions/stay?subscription_token={}",
+                    subscription_token
+                ),
+                settings.grace_period()
+            )
+        );
+        let text_body = format!("{}\n\nStay subscribed: {}", settings.message, stay_link);
+        let html_body = format!(
+            r#"<p>{}</p><p><a href="{}">Yes, keep me subscribed</a></p>"#,
+            htmlescape::encode_minimal(&settings.message),
+            htmlescape::encode_minimal(&stay_link)
+        );
+        if let Err(e) = email_sender
+            .send_email_from(
+                tenant.sender_email.as_ref(),
+                &email,
+                &settings.subject,
+                &html_body,
+                &text_body,
+                None,
+            )
+            .await
+        {
+            // Asked again at the next run.
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                subscriber_id = %r.id,
+                "Failed to send the re-engagement email"
+            );
+            continue;
+        }
+        sqlx::query!(
+            "UPDATE subscriptions SET re_engagement_sent_at = now() WHERE id = $1",
+            r.id
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record that an inactive subscriber was asked")?;
+        asked += 1;
+    }
+    Ok(asked)
+}
+
+/// The subscriber wants to keep receiving the newsletter. Returns `false` if the token
+/// does not belong to a confirmed subscriber of the tenant.
+#[tracing::instrument(name = "Keep an inactive subscriber", skip(pool, subscription_token))]
+pub async fn stay_subscribed(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    subscription_token: &str,
+) -> Result<bool, anyhow::Error> {
+    let kept = sqlx::query!(
+        r#"
+        UPDATE subscriptions s
+        SET re_engagement_sent_at = NULL, re_engaged_at = now()
+        FROM subscription_tokens t
+        WHERE t.subscriber_id = s.id
+            AND t.subscription_token = $1
+            AND s.tenant_id = $2
+            AND s.status = 'confirmed'
+            AND s.deleted_at IS NULL
+        "#,
+        subscription_token,
+        *tenant_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to keep an inactive subscriber")?
+    .rows_affected();
+    Ok(kept > 0)
+}
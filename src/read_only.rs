@@ -0,0 +1,166 @@
+//! Read-only mode, for migrations and incident response: the pages and the API keep
+//! serving reads, but every request which would write is rejected with a 503, and the
+//! workers stop picking up deliveries and jobs.
+//!
+//! It is switched on either by `application.read_only` - for the instances started
+//! with it - or from the admin tools page, for the whole deployment.
+use crate::error::AppError;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::web;
+use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often paused workers check whether read-only mode is over.
+const PAUSED_WORKER_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The writes still accepted, so that admins can log in and switch read-only mode off.
+const ALLOWED_WRITES: [&str; 3] = ["/login", "/admin/logout", "/admin/read_only"];
+
+/// Pages which write although they are fetched with `GET`, from the links of our emails.
+const WRITING_READS: [&str; 2] = ["/subscriptions/confirm", "/subscriptions/stay"];
+
+/// Whether `application.read_only` is set.
+#[derive(Clone, Copy, Debug)]
+pub struct ForcedReadOnly(pub bool);
+
+/// Why the deployment is read-only.
+pub enum ReadOnlyStatus {
+    /// By `application.read_only`: only a restart without it switches it off.
+    Configured,
+    /// From the admin panel.
+    Switched {
+        enabled_at: DateTime<Utc>,
+        enabled_by: Option<String>,
+    },
+}
+
+pub async fn read_only_status(
+    pool: &PgPool,
+    forced: ForcedReadOnly,
+) -> Result<Option<ReadOnlyStatus>, anyhow::Error> {
+    if forced.0 {
+        return Ok(Some(ReadOnlyStatus::Configured));
+    }
+    let status = sqlx::query!(
+        r#"
+        SELECT r.enabled_at, u.username AS "enabled_by?"
+        FROM read_only_mode r
+        LEFT JOIN users u ON u.user_id = r.enabled_by
+        "#
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the read-only mode")?
+    .map(|r| ReadOnlyStatus::Switched {
+        enabled_at: r.enabled_at,
+        enabled_by: r.enabled_by,
+    });
+    Ok(status)
+}
+
+pub async fn is_read_only(pool: &PgPool, forced: ForcedReadOnly) -> Result<bool, anyhow::Error> {
+    Ok(read_only_status(pool, forced).await?.is_some())
+}
+
+#[tracing::instrument(name = "Switch read-only mode", skip(pool))]
+pub async fn set_read_only(
+    pool: &PgPool,
+    enabled: bool,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    if enabled {
+        sqlx::query!(
+            r#"
+            INSERT INTO read_only_mode (enabled_at, enabled_by)
+            VALUES (now(), $1)
+            ON CONFLICT DO NOTHING
+            "#,
+            user_id
+        )
+        .execute(pool)
+        .await
+        .context("Failed to switch read-only mode on")?;
+    } else {
+        sqlx::query!("DELETE FROM read_only_mode")
+            .execute(pool)
+            .await
+            .context("Failed to switch read-only mode off")?;
+    }
+    Ok(())
+}
+
+/// Sleep until read-only mode is over, for the workers to call before picking up work.
+/// Errors are logged: the worker goes on if we cannot tell.
+pub async fn wait_while_read_only(pool: &PgPool, forced: ForcedReadOnly) {
+    loop {
+        match is_read_only(pool, forced).await {
+            Ok(true) => tokio::time::sleep(PAUSED_WORKER_POLL_INTERVAL).await,
+            Ok(false) => return,
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Failed to check whether the deployment is read-only"
+                );
+                return;
+            }
+        }
+    }
+}
+
+fn is_write(method: &Method, path: &str) -> bool {
+    match *method {
+        Method::GET | Method::HEAD | Method::OPTIONS => WRITING_READS.contains(&path),
+        _ => !ALLOWED_WRITES.contains(&path),
+    }
+}
+
+/// Reject the requests which would write while the deployment is read-only.
+pub async fn reject_writes_when_read_only(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if is_write(req.method(), req.path()) {
+        let pool = req
+            .app_data::<web::Data<PgPool>>()
+            .context("No database pool configured.")
+            .map_err(AppError::from)?;
+        let forced = req
+            .app_data::<web::Data<ForcedReadOnly>>()
+            .map_or(ForcedReadOnly(false), |forced| **forced.clone());
+        if is_read_only(pool, forced).await.map_err(AppError::from)? {
+            return Err(AppError::unavailable(
+                "The newsletter is in read-only mode for maintenance. Please try again later.",
+            )
+            .with_code("read_only")
+            .into());
+        }
+    }
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_write;
+    use actix_web::http::Method;
+
+    #[test]
+    fn reads_are_served_but_for_the_links_which_write() {
+        assert!(!is_write(&Method::GET, "/issues"));
+        assert!(!is_write(&Method::HEAD, "/admin/dashboard"));
+        assert!(is_write(&Method::GET, "/subscriptions/confirm"));
+    }
+
+    #[test]
+    fn admins_can_still_log_in_and_switch_read_only_mode_off() {
+        assert!(is_write(&Method::POST, "/subscriptions"));
+        assert!(is_write(&Method::DELETE, "/api/v1/subscribers/1"));
+        assert!(!is_write(&Method::POST, "/login"));
+        assert!(!is_write(&Method::POST, "/admin/read_only"));
+    }
+}
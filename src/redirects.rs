@@ -0,0 +1,164 @@
+//! Redirects for the paths which moved - e.g. the archive URLs of a previous platform -
+//! edited by the admins. They are only looked up for the paths which match no route, so
+//! they cannot shadow a page.
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+/// Longer paths are not worth storing: no platform generates them.
+const MAX_PATH_LENGTH: usize = 2048;
+
+pub struct Redirect {
+    pub from_path: String,
+    pub to_location: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Check the path which moved and where it moved to, returning them trimmed.
+pub fn validate_redirect(from_path: &str, to_location: &str) -> Result<(String, String), String> {
+    let from_path = from_path.trim();
+    let to_location = to_location.trim();
+    if !from_path.starts_with('/') || from_path.starts_with("//") {
+        return Err("The old path must start with a single /, e.g. /p/my-first-issue.".into());
+    }
+    if from_path.len() > MAX_PATH_LENGTH
+        || from_path.contains(|c: char| c.is_whitespace() || c == '?' || c == '#')
+    {
+        return Err("The old path must be a path, without a query string.".into());
+    }
+    if ["/admin", "/api/"].iter().any(|p| from_path.starts_with(p)) {
+        return Err("Paths of the admin panel and of the API cannot be redirected.".into());
+    }
+    let is_local = to_location.starts_with('/') && !to_location.starts_with("//");
+    let is_absolute = reqwest::Url::parse(to_location)
+        .map(|url| matches!(url.scheme(), "http" | "https"))
+        .unwrap_or(false);
+    if !is_local && !is_absolute {
+        return Err("The new location must be a path of this site or an http(s) URL.".into());
+    }
+    if from_path == to_location {
+        return Err("A path cannot redirect to itself.".into());
+    }
+    Ok((from_path.to_owned(), to_location.to_owned()))
+}
+
+#[tracing::instrument(name = "List the redirects", skip(pool))]
+pub async fn list_redirects(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Vec<Redirect>, anyhow::Error> {
+    let redirects = sqlx::query_as!(
+        Redirect,
+        r#"
+        SELECT from_path, to_location, created_at
+        FROM redirects
+        WHERE tenant_id = $1
+        ORDER BY from_path
+        "#,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the redirects")?;
+    Ok(redirects)
+}
+
+/// Where `path` moved to, if it did.
+#[tracing::instrument(name = "Look up a redirect", skip(pool))]
+pub async fn find_redirect(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    path: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let to_location = sqlx::query!(
+        "SELECT to_location FROM redirects WHERE tenant_id = $1 AND from_path = $2",
+        *tenant_id,
+        path
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up a redirect")?
+    .map(|r| r.to_location);
+    Ok(to_location)
+}
+
+/// Add a redirect, replacing the one of the same path if any.
+/// The paths must have gone through `validate_redirect`.
+#[tracing::instrument(name = "Save a redirect", skip(pool))]
+pub async fn save_redirect(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    from_path: &str,
+    to_location: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO redirects (tenant_id, from_path, to_location, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (tenant_id, from_path)
+        DO UPDATE SET to_location = EXCLUDED.to_location, created_at = EXCLUDED.created_at
+        "#,
+        *tenant_id,
+        from_path,
+        to_location
+    )
+    .execute(pool)
+    .await
+    .context("Failed to save a redirect")?;
+    Ok(())
+}
+
+/// Returns `false` if there was no redirect for the path.
+#[tracing::instrument(name = "Delete a redirect", skip(pool))]
+pub async fn delete_redirect(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    from_path: &str,
+) -> Result<bool, anyhow::Error> {
+    let deleted = sqlx::query!(
+        "DELETE FROM redirects WHERE tenant_id = $1 AND from_path = $2",
+        *tenant_id,
+        from_path
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete a redirect")?
+    .rows_affected();
+    Ok(deleted > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_redirect;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn old_paths_and_new_locations_are_trimmed() {
+        assert_eq!(
+            validate_redirect(" /p/first ", "https://example.com/issues/1 "),
+            Ok(("/p/first".into(), "https://example.com/issues/1".into()))
+        );
+        assert_ok!(validate_redirect("/p/first", "/issues/1"));
+    }
+
+    #[test]
+    fn only_paths_outside_of_the_admin_panel_and_the_api_are_redirected() {
+        for from_path in [
+            "p/first",
+            "//evil.com",
+            "/p?id=1",
+            "/admin/dashboard",
+            "/api/v1/x",
+        ] {
+            assert_err!(validate_redirect(from_path, "/issues/1"));
+        }
+    }
+
+    #[test]
+    fn redirects_go_to_a_local_path_or_an_http_url() {
+        for to_location in ["issues/1", "//evil.com", "javascript:alert(1)", "/p/first"] {
+            assert_err!(validate_redirect("/p/first", to_location));
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! In-memory fakes of the repositories, for unit tests.
+use crate::domain::{
+    ConsentAction, ConsentEvidence, NewSubscriber, SubscriptionStatus, SubscriptionToken,
+    UnsubscribeReason,
+};
+use crate::events::DomainEvent;
+use crate::i18n::Locale;
+use crate::pagination::Cursor;
+use crate::repositories::{
+    Confirmation, ConsentRecord, NewsletterRepository, PendingSubscription, Signup, Subscriber,
+    SubscriberRepository,
+};
+use crate::services::NewIssue;
+use crate::tenancy::TenantId;
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+#[derive(Default)]
+pub struct InMemorySubscriberRepository {
+    subscribers: Mutex<Vec<(Subscriber, SubscriptionToken)>>,
+    /// Tokens whose confirmation link was already followed.
+    used_tokens: Mutex<HashSet<String>>,
+    consents: Mutex<Vec<(Uuid, ConsentRecord)>>,
+    /// `0` for no limit.
+    max_subscribers: usize,
+}
+
+impl InMemorySubscriberRepository {
+    pub fn with_max_subscribers(max_subscribers: usize) -> Self {
+        Self {
+            max_subscribers,
+            ..Self::default()
+        }
+    }
+
+    fn subscribers(&self) -> MutexGuard<'_, Vec<(Subscriber, SubscriptionToken)>> {
+        self.subscribers.lock().unwrap()
+    }
+
+    pub fn token_of(&self, subscriber_id: Uuid) -> Option<SubscriptionToken> {
+        self.subscribers()
+            .iter()
+            .find(|(s, _)| s.id == subscriber_id)
+            .map(|(_, token)| token.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl SubscriberRepository for InMemorySubscriberRepository {
+    async fn store_pending_subscription(
+        &self,
+        new_subscriber: &NewSubscriber,
+    ) -> Result<Signup, anyhow::Error> {
+        let mut subscribers = self.subscribers();
+        if let Some((s, token)) = subscribers
+            .iter_mut()
+            .find(|(s, _)| s.email == new_subscriber.email.as_ref())
+        {
+            if let Some(locale) = &new_subscriber.locale {
+                s.locale = Some(locale.language());
+            }
+            if s.status != SubscriptionStatus::Confirmed {
+                self.used_tokens.lock().unwrap().remove(token.as_ref());
+            }
+            return Ok(Signup::Pending(PendingSubscription {
+                subscriber_id: s.id,
+                subscription_token: token.clone(),
+                already_existed: true,
+                locale: s.locale.as_deref().and_then(|l| Locale::parse(l).ok()),
+            }));
+        }
+        let counted = subscribers
+            .iter()
+            .filter(|(s, _)| s.status != SubscriptionStatus::Unsubscribed)
+            .count();
+        if self.max_subscribers != 0 && counted >= self.max_subscribers {
+            return Ok(Signup::Waitlisted);
+        }
+        let subscriber = Subscriber {
+            id: Uuid::new_v4(),
+            email: new_subscriber.email.as_ref().into(),
+            name: new_subscriber.name.as_ref().map(|n| n.as_ref().into()),
+            status: SubscriptionStatus::PendingConfirmation,
+            subscribed_at: chrono::Utc::now(),
+            locale: new_subscriber.locale.as_ref().map(Locale::language),
+        };
+        let pending = PendingSubscription {
+            subscriber_id: subscriber.id,
+            subscription_token: SubscriptionToken::generate(),
+            already_existed: false,
+            locale: new_subscriber.locale.clone(),
+        };
+        subscribers.push((subscriber, pending.subscription_token.clone()));
+        Ok(Signup::Pending(pending))
+    }
+
+    async fn confirm_subscription(
+        &self,
+        subscription_token: &SubscriptionToken,
+    ) -> Result<Option<Confirmation>, anyhow::Error> {
+        let mut used_tokens = self.used_tokens.lock().unwrap();
+        Ok(self
+            .subscribers()
+            .iter_mut()
+            .find(|(_, token)| token.as_ref() == subscription_token.as_ref())
+            .map(|(s, token)| {
+                if !used_tokens.insert(token.as_ref().to_owned()) {
+                    return Confirmation::AlreadyUsed;
+                }
+                s.status = SubscriptionStatus::Confirmed;
+                Confirmation::Confirmed(s.id)
+            }))
+    }
+
+    async fn unsubscribe(
+        &self,
+        subscription_token: &SubscriptionToken,
+        _reason: Option<UnsubscribeReason>,
+    ) -> Result<Option<Uuid>, anyhow::Error> {
+        Ok(self
+            .subscribers()
+            .iter_mut()
+            .find(|(_, token)| token.as_ref() == subscription_token.as_ref())
+            .map(|(s, _)| {
+                s.status = SubscriptionStatus::Unsubscribed;
+                s.id
+            }))
+    }
+
+    async fn get_subscriber(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Option<Subscriber>, anyhow::Error> {
+        Ok(self
+            .subscribers()
+            .iter()
+            .find(|(s, _)| s.id == subscriber_id)
+            .map(|(s, _)| s.clone()))
+    }
+
+    async fn list_subscribers(
+        &self,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<Subscriber>, anyhow::Error> {
+        let mut subscribers: Vec<_> = self
+            .subscribers()
+            .iter()
+            .map(|(s, _)| s.clone())
+            .filter(|s| after.is_none_or(|c| (s.subscribed_at, s.id) > (c.created_at, c.id)))
+            .collect();
+        subscribers.sort_by_key(|s| (s.subscribed_at, s.id));
+        subscribers.truncate(limit as usize);
+        Ok(subscribers)
+    }
+
+    async fn delete_subscribers(&self, subscriber_ids: &[Uuid]) -> Result<u64, anyhow::Error> {
+        let mut subscribers = self.subscribers();
+        let before = subscribers.len();
+        subscribers.retain(|(s, _)| !subscriber_ids.contains(&s.id));
+        Ok((before - subscribers.len()) as u64)
+    }
+
+    async fn record_consent(
+        &self,
+        subscriber_id: Uuid,
+        action: ConsentAction,
+        evidence: &ConsentEvidence,
+    ) -> Result<(), anyhow::Error> {
+        let record = ConsentRecord {
+            action,
+            ip_address: evidence.ip_address.clone(),
+            user_agent: evidence.user_agent.clone(),
+            form_hash: evidence.form_hash.clone(),
+            policy_version: evidence.policy_version.clone(),
+            recorded_at: chrono::Utc::now(),
+        };
+        self.consents.lock().unwrap().push((subscriber_id, record));
+        Ok(())
+    }
+
+    async fn consent_records(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Vec<ConsentRecord>, anyhow::Error> {
+        Ok(self
+            .consents
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(id, _)| *id == subscriber_id)
+            .map(|(_, r)| r.clone())
+            .collect())
+    }
+}
+
+/// The issues of a single tenant.
+#[derive(Default)]
+pub struct InMemoryNewsletterRepository {
+    pub confirmed_subscribers: Vec<String>,
+    /// Those of `confirmed_subscribers` who left their name blank.
+    pub nameless_subscribers: Vec<String>,
+    /// The tags of subscribers, as `(email, tag)`.
+    pub tags: Vec<(String, String)>,
+    /// The stored issues, as `(newsletter_issue_id, title, deliver_after, segment)`.
+    pub issues: Vec<(Uuid, String, Instant, Option<String>)>,
+    /// The delivery queue, as `(newsletter_issue_id, subscriber_email)`.
+    pub queued: Vec<(Uuid, String)>,
+    /// The deliveries which did not succeed, as `(newsletter_issue_id, subscriber_email)`.
+    pub failed: Vec<(Uuid, String)>,
+    /// The issues whose deliveries are paused.
+    pub paused: Vec<Uuid>,
+    /// How many emails were sent today, queued ones aside.
+    pub sent_today: u64,
+    pub events: Vec<DomainEvent>,
+}
+
+impl InMemoryNewsletterRepository {
+    pub fn with_confirmed_subscribers(emails: &[&str]) -> Self {
+        Self {
+            confirmed_subscribers: emails.iter().map(|e| e.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The confirmed subscribers in the segment of the issue.
+    fn recipients(&self, newsletter_issue_id: Uuid) -> Vec<String> {
+        let segment = self
+            .issues
+            .iter()
+            .find(|(id, ..)| *id == newsletter_issue_id)
+            .and_then(|(.., segment)| segment.clone());
+        self.confirmed_subscribers
+            .iter()
+            .filter(|email| {
+                segment.as_ref().is_none_or(|segment| {
+                    self.tags
+                        .iter()
+                        .any(|(tagged, tag)| tagged == *email && tag == segment)
+                })
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl NewsletterRepository for InMemoryNewsletterRepository {
+    async fn insert_issue(
+        &mut self,
+        _tenant_id: TenantId,
+        issue: &NewIssue,
+        undo_window: Duration,
+    ) -> Result<Uuid, anyhow::Error> {
+        let newsletter_issue_id = Uuid::new_v4();
+        self.issues.push((
+            newsletter_issue_id,
+            issue.title().to_owned(),
+            Instant::now() + undo_window,
+            issue.segment().map(Into::into),
+        ));
+        Ok(newsletter_issue_id)
+    }
+
+    async fn withdraw_issue(
+        &mut self,
+        _tenant_id: TenantId,
+        newsletter_issue_id: Uuid,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let position = self.issues.iter().position(|(id, _, deliver_after, _)| {
+            *id == newsletter_issue_id && *deliver_after > Instant::now()
+        });
+        Ok(position.map(|i| {
+            self.queued.retain(|(id, _)| *id != newsletter_issue_id);
+            self.issues.remove(i).1
+        }))
+    }
+
+    async fn count_recipients(&mut self, newsletter_issue_id: Uuid) -> Result<u64, anyhow::Error> {
+        Ok(self.recipients(newsletter_issue_id).len() as u64)
+    }
+
+    async fn emails_scheduled_today(&mut self) -> Result<u64, anyhow::Error> {
+        Ok(self.sent_today + self.queued.len() as u64)
+    }
+
+    async fn count_recipients_without_name(
+        &mut self,
+        newsletter_issue_id: Uuid,
+    ) -> Result<u64, anyhow::Error> {
+        let nameless = self
+            .recipients(newsletter_issue_id)
+            .into_iter()
+            .filter(|email| self.nameless_subscribers.contains(email));
+        Ok(nameless.count() as u64)
+    }
+
+    async fn requeue_failed_deliveries(
+        &mut self,
+        _tenant_id: TenantId,
+        newsletter_issue_id: Uuid,
+        subscriber_emails: &[String],
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let (requeued, failed) =
+            std::mem::take(&mut self.failed)
+                .into_iter()
+                .partition(|(id, email)| {
+                    *id == newsletter_issue_id
+                        && subscriber_emails.contains(email)
+                        && self.confirmed_subscribers.contains(email)
+                });
+        self.failed = failed;
+        self.queued.extend(requeued.iter().cloned());
+        let mut emails: Vec<String> = requeued.into_iter().map(|(_, email)| email).collect();
+        emails.sort();
+        Ok(emails)
+    }
+
+    async fn resume_issue(
+        &mut self,
+        _tenant_id: TenantId,
+        newsletter_issue_id: Uuid,
+    ) -> Result<Option<String>, anyhow::Error> {
+        if !self.paused.contains(&newsletter_issue_id) {
+            return Ok(None);
+        }
+        self.paused.retain(|id| *id != newsletter_issue_id);
+        Ok(self
+            .issues
+            .iter()
+            .find(|(id, ..)| *id == newsletter_issue_id)
+            .map(|(_, title, ..)| title.clone()))
+    }
+
+    async fn record_event(
+        &mut self,
+        _tenant_id: TenantId,
+        event: &DomainEvent,
+    ) -> Result<(), anyhow::Error> {
+        self.events.push(event.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use anyhow::Context;
+
+/// Issue bodies are stored zstd-compressed: inline CSS and base64 images make them
+/// large, and they compress well.
+pub fn compress_content(content: &str) -> Result<Vec<u8>, anyhow::Error> {
+    zstd::encode_all(content.as_bytes(), zstd::DEFAULT_COMPRESSION_LEVEL)
+        .context("Failed to compress the issue content")
+}
+
+/// Read back an issue body - compressed, or in clear text for issues published before
+/// we started compressing them.
+pub fn decompress_content(
+    compressed: Option<Vec<u8>>,
+    plain: Option<String>,
+) -> Result<String, anyhow::Error> {
+    match (compressed, plain) {
+        (Some(compressed), _) => {
+            let content = zstd::decode_all(compressed.as_slice())
+                .context("Failed to decompress the issue content")?;
+            String::from_utf8(content).context("The decompressed issue content is not UTF-8")
+        }
+        (None, Some(plain)) => Ok(plain),
+        (None, None) => anyhow::bail!("The issue content is missing"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress_content, decompress_content};
+    use claim::assert_err;
+
+    #[test]
+    fn content_survives_a_round_trip() {
+        let content = format!(
+            "<style>p {{ color: red; }}</style>{}",
+            "<p>é</p>".repeat(1000)
+        );
+
+        let compressed = compress_content(&content).unwrap();
+
+        assert!(compressed.len() < content.len() / 10);
+        assert_eq!(decompress_content(Some(compressed), None).unwrap(), content);
+    }
+
+    #[test]
+    fn uncompressed_content_is_read_as_is() {
+        let content = decompress_content(None, Some("Hello".into())).unwrap();
+        assert_eq!(content, "Hello");
+    }
+
+    #[test]
+    fn corrupted_content_is_an_error() {
+        assert_err!(decompress_content(Some(b"not zstd".to_vec()), None));
+        assert_err!(decompress_content(None, None));
+    }
+}
@@ -0,0 +1,11 @@
+//! Persistence behind traits: services only talk to repositories, so they can run
+//! against the in-memory fakes in unit tests.
+#[cfg(test)]
+pub mod in_memory;
+mod issue_content;
+mod newsletters;
+mod subscribers;
+
+pub use issue_content::*;
+pub use newsletters::*;
+pub use subscribers::*;
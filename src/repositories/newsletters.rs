@@ -0,0 +1,549 @@
+use crate::events::{record_delivery_completed_if_done, record_event, DomainEvent};
+use crate::pii::{mask_email, mask_name, Masked};
+use crate::repositories::compress_content;
+use crate::sending_quota::emails_scheduled_today;
+use crate::services::NewIssue;
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Where issues are stored.
+/// Every call made while publishing an issue belongs to the same unit of work - for
+/// Postgres, the transaction the repository is implemented for. Its deliveries are only
+/// queued once it is committed, see `enqueue_issue_deliveries`.
+/// Issues belong to a tenant: those of other tenants are treated as unknown.
+#[async_trait::async_trait]
+pub trait NewsletterRepository: Send {
+    /// Deliveries of the issue wait for `undo_window` to pass.
+    async fn insert_issue(
+        &mut self,
+        tenant_id: TenantId,
+        issue: &NewIssue,
+        undo_window: Duration,
+    ) -> Result<Uuid, anyhow::Error>;
+
+    /// Remove the issue and its queued deliveries if none of them has started yet.
+    /// Returns the title of the issue, `None` if it is too late or there is no such issue.
+    async fn withdraw_issue(
+        &mut self,
+        tenant_id: TenantId,
+        newsletter_issue_id: Uuid,
+    ) -> Result<Option<String>, anyhow::Error>;
+
+    /// How many subscribers the issue goes to: the confirmed subscribers of its segment,
+    /// among the subscribers of its tenant.
+    async fn count_recipients(&mut self, newsletter_issue_id: Uuid) -> Result<u64, anyhow::Error>;
+
+    /// The emails sent today or queued, across tenants, for the daily sending quota.
+    async fn emails_scheduled_today(&mut self) -> Result<u64, anyhow::Error>;
+
+    /// How many of the recipients of the issue left their name blank.
+    async fn count_recipients_without_name(
+        &mut self,
+        newsletter_issue_id: Uuid,
+    ) -> Result<u64, anyhow::Error>;
+
+    /// Queue the issue again for those of `subscriber_emails` it failed to reach, and who
+    /// are still confirmed subscribers. Returns the recipients queued.
+    async fn requeue_failed_deliveries(
+        &mut self,
+        tenant_id: TenantId,
+        newsletter_issue_id: Uuid,
+        subscriber_emails: &[String],
+    ) -> Result<Vec<String>, anyhow::Error>;
+
+    /// Let the deliveries of a paused issue go out again.
+    /// Returns the title of the issue, `None` if it is not paused or there is no such issue.
+    async fn resume_issue(
+        &mut self,
+        tenant_id: TenantId,
+        newsletter_issue_id: Uuid,
+    ) -> Result<Option<String>, anyhow::Error>;
+
+    async fn record_event(
+        &mut self,
+        tenant_id: TenantId,
+        event: &DomainEvent,
+    ) -> Result<(), anyhow::Error>;
+}
+
+#[async_trait::async_trait]
+impl NewsletterRepository for Transaction<'static, Postgres> {
+    async fn insert_issue(
+        &mut self,
+        tenant_id: TenantId,
+        issue: &NewIssue,
+        undo_window: Duration,
+    ) -> Result<Uuid, anyhow::Error> {
+        insert_newsletter_issue(self, tenant_id, issue, undo_window)
+            .await
+            .context("Failed to store newsletter issue details")
+    }
+
+    async fn withdraw_issue(
+        &mut self,
+        tenant_id: TenantId,
+        newsletter_issue_id: Uuid,
+    ) -> Result<Option<String>, anyhow::Error> {
+        withdraw_newsletter_issue(self, tenant_id, newsletter_issue_id)
+            .await
+            .context("Failed to withdraw a newsletter issue")
+    }
+
+    async fn count_recipients(&mut self, newsletter_issue_id: Uuid) -> Result<u64, anyhow::Error> {
+        let r = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM newsletter_issues i, issue_recipients(i.tenant_id, i.segment) s
+            WHERE i.newsletter_issue_id = $1
+            "#,
+            newsletter_issue_id
+        )
+        .fetch_one(self)
+        .await
+        .context("Failed to count the recipients of the issue")?;
+        Ok(r.count as u64)
+    }
+
+    async fn emails_scheduled_today(&mut self) -> Result<u64, anyhow::Error> {
+        emails_scheduled_today(self).await
+    }
+
+    async fn count_recipients_without_name(
+        &mut self,
+        newsletter_issue_id: Uuid,
+    ) -> Result<u64, anyhow::Error> {
+        let r = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM newsletter_issues i, issue_recipients(i.tenant_id, i.segment) s
+            WHERE
+                i.newsletter_issue_id = $1 AND
+                COALESCE(trim(s.name), '') = '' AND
+                -- Sealed names are never blank.
+                s.name_encrypted IS NULL
+            "#,
+            newsletter_issue_id
+        )
+        .fetch_one(self)
+        .await
+        .context("Failed to count the recipients without a name")?;
+        Ok(r.count as u64)
+    }
+
+    async fn requeue_failed_deliveries(
+        &mut self,
+        tenant_id: TenantId,
+        newsletter_issue_id: Uuid,
+        subscriber_emails: &[String],
+    ) -> Result<Vec<String>, anyhow::Error> {
+        requeue_failed_delivery_tasks(self, tenant_id, newsletter_issue_id, subscriber_emails)
+            .await
+            .context("Failed to requeue delivery tasks")
+    }
+
+    async fn resume_issue(
+        &mut self,
+        tenant_id: TenantId,
+        newsletter_issue_id: Uuid,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let r = sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET paused_at = NULL
+            WHERE newsletter_issue_id = $1 AND tenant_id = $2 AND paused_at IS NOT NULL
+            RETURNING title
+            "#,
+            newsletter_issue_id,
+            *tenant_id
+        )
+        .fetch_optional(self)
+        .await
+        .context("Failed to resume the deliveries of an issue")?;
+        Ok(r.map(|r| r.title))
+    }
+
+    async fn record_event(
+        &mut self,
+        tenant_id: TenantId,
+        event: &DomainEvent,
+    ) -> Result<(), anyhow::Error> {
+        record_event(self, tenant_id, event).await?;
+        Ok(())
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    issue: &NewIssue,
+    undo_window: Duration,
+) -> Result<Uuid, anyhow::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content_zstd,
+            html_content_zstd,
+            segment,
+            search_vector,
+            published_at,
+            deliver_after,
+            tenant_id,
+            local_send_hour
+        )
+        VALUES (
+            $1, $2, $3, $4, $5,
+            setweight(to_tsvector('english', $2), 'A') ||
+                setweight(to_tsvector('english', $7), 'B'),
+            now(),
+            now() + make_interval(secs => $6),
+            $8,
+            $9
+        )
+        "#,
+        newsletter_issue_id,
+        issue.title(),
+        compress_content(issue.text_content())?,
+        compress_content(issue.html_content())?,
+        issue.segment(),
+        undo_window.as_secs_f64(),
+        issue.text_content(),
+        *tenant_id,
+        issue.local_send_hour().map(i16::from)
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_tags (newsletter_issue_id, tag)
+        SELECT $1, unnest($2::text[])
+        "#,
+        newsletter_issue_id,
+        issue.tags()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+/// The issue row is locked first, then its queued deliveries with `SKIP LOCKED`: a
+/// delivery the worker has already picked up is skipped, and means it is too late.
+#[tracing::instrument(skip(transaction))]
+async fn withdraw_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT title
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND tenant_id = $2
+        FOR UPDATE
+        "#,
+        newsletter_issue_id,
+        *tenant_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let issue = match issue {
+        Some(issue) => issue,
+        None => return Ok(None),
+    };
+    // A new statement, to see the deliveries committed while we waited for the lock.
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            i.deliver_after > now() AS "pending!",
+            EXISTS (
+                SELECT 1 FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "attempted!",
+            (
+                SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "queued!"
+        FROM newsletter_issues i
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    if !r.pending || r.attempted {
+        return Ok(None);
+    }
+    let locked = sqlx::query!(
+        r#"
+        SELECT subscriber_email
+        FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        SKIP LOCKED
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    if locked.len() as i64 != r.queued {
+        return Ok(None);
+    }
+
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM newsletter_issues WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(Some(issue.title))
+}
+
+/// A recipient of an issue, shown before publishing it.
+#[derive(Debug)]
+pub struct SampleRecipient {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+impl Masked for SampleRecipient {
+    fn masked(self) -> Self {
+        Self {
+            name: self.name.as_deref().map(mask_name),
+            email: mask_email(&self.email),
+        }
+    }
+}
+
+/// `size` recipients picked at random among those an issue for `segment` would be
+/// queued for, from the same selection as the delivery queue.
+#[tracing::instrument(skip(pool))]
+pub async fn sample_recipients(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    segment: Option<&str>,
+    size: i64,
+) -> Result<Vec<SampleRecipient>, anyhow::Error> {
+    sqlx::query_as!(
+        SampleRecipient,
+        r#"
+        SELECT name, email AS "email!"
+        FROM issue_recipients($1, $2)
+        ORDER BY random()
+        LIMIT $3
+        "#,
+        *tenant_id,
+        segment,
+        size
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to sample the recipients of an issue")
+}
+
+/// Confirmed subscribers are queued in batches of this size, each committed on its own,
+/// so that publishing to a large audience holds no transaction open for long.
+const ENQUEUE_BATCH_SIZE: i64 = 1000;
+
+/// Queue the delivery of a published issue to its recipients, batch after batch - picking
+/// up after the last recipient queued, should it have been left halfway. Returns how
+/// many deliveries were queued.
+///
+/// Each batch locks the issue row: two calls for the same issue queue their batches in
+/// turn, and a cancelled issue stops being queued.
+#[tracing::instrument(skip(pool))]
+pub async fn enqueue_issue_deliveries(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<u64, anyhow::Error> {
+    let mut enqueued = 0;
+    loop {
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        let issue = sqlx::query!(
+            r#"
+            SELECT segment, tenant_id, local_send_hour, enqueued_through
+            FROM newsletter_issues
+            WHERE newsletter_issue_id = $1 AND enqueued_at IS NULL
+            FOR UPDATE
+            "#,
+            newsletter_issue_id
+        )
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to lock the newsletter issue")?;
+        let issue = match issue {
+            Some(issue) => issue,
+            None => return Ok(enqueued),
+        };
+        let emails: Vec<String> = sqlx::query!(
+            r#"
+            SELECT email AS "email!"
+            FROM issue_recipients($4, $3)
+            WHERE email > $1
+            ORDER BY email
+            LIMIT $2
+            "#,
+            issue.enqueued_through.unwrap_or_default(),
+            ENQUEUE_BATCH_SIZE,
+            issue.segment,
+            issue.tenant_id
+        )
+        .fetch_all(&mut transaction)
+        .await
+        .context("Failed to select the next recipients of the issue")?
+        .into_iter()
+        .map(|r| r.email)
+        .collect();
+
+        // Sent at local time, the delivery to a subscriber with a known timezone waits
+        // for the next occurrence of the hour in their day - today's if it is still
+        // to come.
+        sqlx::query!(
+            r#"
+            INSERT INTO issue_delivery_queue (
+                newsletter_issue_id,
+                subscriber_email,
+                tenant_id,
+                deliver_after
+            )
+            SELECT $1, s.email, $3, CASE
+                WHEN $4::int IS NULL OR s.timezone IS NULL THEN NULL
+                ELSE (
+                    date_trunc('day', now() AT TIME ZONE s.timezone)
+                    + make_interval(hours => $4::int)
+                    + CASE
+                        WHEN (now() AT TIME ZONE s.timezone)::time > make_time($4::int, 0, 0)
+                        THEN interval '1 day'
+                        ELSE interval '0'
+                    END
+                ) AT TIME ZONE s.timezone
+            END
+            FROM subscriptions s
+            WHERE s.tenant_id = $3 AND s.email = ANY($2::text[])
+            "#,
+            newsletter_issue_id,
+            &emails,
+            issue.tenant_id,
+            issue.local_send_hour.map(i32::from)
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+        enqueued += emails.len() as u64;
+        let done = (emails.len() as i64) < ENQUEUE_BATCH_SIZE;
+        sqlx::query!(
+            r#"
+            UPDATE newsletter_issues
+            SET
+                enqueued_through = COALESCE($2, enqueued_through),
+                enqueued_at = CASE WHEN $3 THEN now() END
+            WHERE newsletter_issue_id = $1
+            "#,
+            newsletter_issue_id,
+            emails.last(),
+            done
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to record how far the issue is queued")?;
+        if done {
+            // Without recipients, the delivery is already over.
+            record_delivery_completed_if_done(&mut transaction, newsletter_issue_id).await?;
+        }
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit a batch of delivery tasks")?;
+        tracing::info!(enqueued, "Enqueued a batch of delivery tasks");
+        if done {
+            return Ok(enqueued);
+        }
+    }
+}
+
+/// Queue the deliveries of the issues their publishing request left halfway.
+#[tracing::instrument(skip(pool))]
+pub async fn enqueue_unfinished_issues(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let unfinished =
+        sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues WHERE enqueued_at IS NULL")
+            .fetch_all(pool)
+            .await
+            .context("Failed to find the issues left halfway queued")?;
+    for r in unfinished {
+        enqueue_issue_deliveries(pool, r.newsletter_issue_id).await?;
+    }
+    Ok(())
+}
+
+/// The outcome of a failed delivery is removed: the worker records the new one, and
+/// the recipient counts as remaining in the meantime.
+#[tracing::instrument(skip(transaction))]
+async fn requeue_failed_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    newsletter_issue_id: Uuid,
+    subscriber_emails: &[String],
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut emails: Vec<String> = sqlx::query!(
+        r#"
+        DELETE FROM issue_deliveries d
+        USING newsletter_issues i
+        WHERE
+            i.newsletter_issue_id = d.newsletter_issue_id AND
+            i.tenant_id = $3 AND
+            d.newsletter_issue_id = $1 AND
+            d.subscriber_email = ANY($2) AND
+            d.outcome <> 'delivered' AND
+            -- Those who left since are not sent the issue again.
+            EXISTS (
+                SELECT 1 FROM subscriptions s
+                WHERE
+                    s.email = d.subscriber_email AND
+                    s.tenant_id = $3 AND
+                    s.status = 'confirmed' AND
+                    s.deleted_at IS NULL
+            )
+        RETURNING d.subscriber_email
+        "#,
+        newsletter_issue_id,
+        subscriber_emails,
+        *tenant_id
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|r| r.subscriber_email)
+    .collect();
+    emails.sort();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email,
+            tenant_id
+        )
+        SELECT $1, email, $3 FROM UNNEST($2::text[]) AS email
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        &emails,
+        *tenant_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(emails)
+}
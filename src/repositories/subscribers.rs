@@ -0,0 +1,1005 @@
+use crate::domain::{
+    ConsentAction, ConsentEvidence, NewSubscriber, SubscriptionStatus, SubscriptionToken,
+    UnsubscribeReason,
+};
+use crate::events::{record_event, DomainEvent};
+use crate::i18n::Locale;
+use crate::pagination::Cursor;
+use crate::pii::{mask_email, mask_name, reveal_email, reveal_name, Masked, PiiCipher};
+use crate::tenancy::TenantId;
+use crate::utils::error_chain_fmt;
+use crate::waitlist::{is_list_full_for, join_waitlist};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Formatter;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Subscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: Option<String>,
+    pub status: SubscriptionStatus,
+    pub subscribed_at: DateTime<Utc>,
+    pub locale: Option<String>,
+}
+
+impl Masked for Subscriber {
+    fn masked(self) -> Self {
+        Self {
+            email: mask_email(&self.email),
+            name: self.name.as_deref().map(mask_name),
+            ..self
+        }
+    }
+}
+
+/// A subscriber as stored, sealed or not.
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: Option<String>,
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>,
+    locale: Option<String>,
+    email_encrypted: Option<Vec<u8>>,
+    name_encrypted: Option<Vec<u8>>,
+}
+
+impl SubscriberRow {
+    fn reveal(self, cipher: Option<&PiiCipher>) -> Result<Subscriber, anyhow::Error> {
+        let email_encrypted = self.email_encrypted.as_deref();
+        let name_encrypted = self.name_encrypted.as_deref();
+        Ok(Subscriber {
+            email: reveal_email(cipher, self.id, self.email, email_encrypted)?,
+            name: reveal_name(cipher, self.id, self.name, name_encrypted)?,
+            id: self.id,
+            status: self.status,
+            subscribed_at: self.subscribed_at,
+            locale: self.locale,
+        })
+    }
+}
+
+/// A step of a subscriber's opt-in, with where it was taken from.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConsentRecord {
+    pub action: ConsentAction,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub form_hash: Option<String>,
+    pub policy_version: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl Masked for ConsentRecord {
+    /// Where the step was taken from is hidden.
+    fn masked(self) -> Self {
+        Self {
+            ip_address: self.ip_address.map(|_| "***".into()),
+            user_agent: self.user_agent.map(|_| "***".into()),
+            ..self
+        }
+    }
+}
+
+pub struct PendingSubscription {
+    pub subscriber_id: Uuid,
+    pub subscription_token: SubscriptionToken,
+    /// Whether the subscriber was already stored before.
+    pub already_existed: bool,
+    /// The language the subscriber chose, now or when they first signed up.
+    pub locale: Option<Locale>,
+}
+
+/// What became of a signup.
+pub enum Signup {
+    Pending(PendingSubscription),
+    /// The list is full: the subscriber joined the waitlist instead, see `crate::waitlist`.
+    Waitlisted,
+}
+
+impl Signup {
+    /// The pending subscription, for the callers storing subscribers regardless of the
+    /// cap.
+    pub fn pending(self) -> Result<PendingSubscription, anyhow::Error> {
+        match self {
+            Signup::Pending(pending) => Ok(pending),
+            Signup::Waitlisted => Err(anyhow::anyhow!("The subscriber joined the waitlist")),
+        }
+    }
+}
+
+/// What following a confirmation link did.
+#[derive(Debug, PartialEq)]
+pub enum Confirmation {
+    Confirmed(Uuid),
+    /// Confirmation links are single-use: this one was already followed.
+    AlreadyUsed,
+}
+
+#[async_trait::async_trait]
+pub trait SubscriberRepository: Send + Sync {
+    /// Store a pending subscription unless the email address is already known.
+    /// Either way, the returned token is the one the subscriber can confirm with - again,
+    /// if they are not confirmed anymore. New subscribers join the waitlist instead
+    /// once the list is full.
+    async fn store_pending_subscription(
+        &self,
+        new_subscriber: &NewSubscriber,
+    ) -> Result<Signup, anyhow::Error>;
+
+    /// Confirm the subscription the token was issued for, using up its confirmation link.
+    /// `None` if the token is unknown.
+    async fn confirm_subscription(
+        &self,
+        subscription_token: &SubscriptionToken,
+    ) -> Result<Option<Confirmation>, anyhow::Error>;
+
+    /// Unsubscribe the subscriber the token was issued for, returning the subscriber id.
+    /// `None` if the token is unknown. Unsubscribing twice keeps the first reason.
+    async fn unsubscribe(
+        &self,
+        subscription_token: &SubscriptionToken,
+        reason: Option<UnsubscribeReason>,
+    ) -> Result<Option<Uuid>, anyhow::Error>;
+
+    async fn get_subscriber(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Option<Subscriber>, anyhow::Error>;
+
+    /// Up to `limit` subscribers, oldest first, starting right after `after`.
+    async fn list_subscribers(
+        &self,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<Subscriber>, anyhow::Error>;
+
+    /// Delete subscribers, cancelling the deliveries still queued for them. They are kept
+    /// until the retention window elapses, but excluded from everything in the meantime.
+    /// Returns how many subscribers were actually deleted - unknown ids are ignored.
+    async fn delete_subscribers(&self, subscriber_ids: &[Uuid]) -> Result<u64, anyhow::Error>;
+
+    /// Keep `evidence` of the subscriber taking `action`, as proof of their consent.
+    async fn record_consent(
+        &self,
+        subscriber_id: Uuid,
+        action: ConsentAction,
+        evidence: &ConsentEvidence,
+    ) -> Result<(), anyhow::Error>;
+
+    /// The proof of consent of a subscriber, oldest first.
+    async fn consent_records(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Vec<ConsentRecord>, anyhow::Error>;
+}
+
+/// The subscribers of a single tenant.
+pub struct PostgresSubscriberRepository<'a> {
+    pool: &'a PgPool,
+    tenant_id: TenantId,
+    cipher: Option<&'a PiiCipher>,
+    max_subscribers: u64,
+}
+
+impl<'a> PostgresSubscriberRepository<'a> {
+    pub fn new(pool: &'a PgPool, tenant_id: TenantId) -> Self {
+        Self {
+            pool,
+            tenant_id,
+            cipher: None,
+            max_subscribers: 0,
+        }
+    }
+
+    /// Send new subscribers to the waitlist once the list has `max_subscribers`, see
+    /// `crate::waitlist`. `0` - the default - for no limit.
+    pub fn with_max_subscribers(mut self, max_subscribers: u64) -> Self {
+        self.max_subscribers = max_subscribers;
+        self
+    }
+
+    /// Seal the subscribers stored, and decrypt those read, see `crate::pii`.
+    pub fn with_cipher(mut self, cipher: Option<&'a PiiCipher>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl SubscriberRepository for PostgresSubscriberRepository<'_> {
+    async fn store_pending_subscription(
+        &self,
+        new_subscriber: &NewSubscriber,
+    ) -> Result<Signup, anyhow::Error> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        if is_list_full_for(
+            &mut transaction,
+            self.tenant_id,
+            new_subscriber.email.as_ref(),
+            self.max_subscribers,
+            self.cipher,
+        )
+        .await?
+        {
+            join_waitlist(
+                &mut transaction,
+                self.tenant_id,
+                new_subscriber,
+                self.cipher,
+            )
+            .await?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit the SQL query to the database.")?;
+            return Ok(Signup::Waitlisted);
+        }
+        // Signing up again starts afresh, even within the retention window.
+        purge_deleted_subscriber(
+            &mut transaction,
+            self.tenant_id,
+            new_subscriber.email.as_ref(),
+            self.cipher,
+        )
+        .await?;
+        let StoredSubscriber {
+            id: subscriber_id,
+            email,
+            already_existed,
+            locale,
+        } = match self.cipher {
+            Some(cipher) => {
+                upsert_sealed_subscriber(&mut transaction, self.tenant_id, new_subscriber, cipher)
+                    .await
+            }
+            None => upsert_subscriber(&mut transaction, self.tenant_id, new_subscriber)
+                .await
+                .map_err(Into::into),
+        }
+        .context("Failed to insert new subscriber in the database.")?;
+        if !already_existed {
+            let event = DomainEvent::SubscriptionRequested {
+                subscriber_id,
+                email,
+            };
+            record_event(&mut transaction, self.tenant_id, &event).await?;
+        }
+        let subscription_token = match get_past_subscription_token(&mut transaction, subscriber_id)
+            .await
+            .context("Failed to check for existing subscription token in database.")?
+        {
+            Some(token) => {
+                reopen_confirmation_link(&mut transaction, subscriber_id).await?;
+                token
+            }
+            None => {
+                let subscription_token = SubscriptionToken::generate();
+                store_token(&mut transaction, subscriber_id, &subscription_token)
+                    .await
+                    .context("Failed to store subscription token in the database.")?;
+                subscription_token
+            }
+        };
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the SQL query to the database.")?;
+        Ok(Signup::Pending(PendingSubscription {
+            subscriber_id,
+            subscription_token,
+            already_existed,
+            locale,
+        }))
+    }
+
+    async fn confirm_subscription(
+        &self,
+        subscription_token: &SubscriptionToken,
+    ) -> Result<Option<Confirmation>, anyhow::Error> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        let subscriber_id =
+            match use_confirmation_link(&mut transaction, self.tenant_id, subscription_token)
+                .await?
+            {
+                Some(id) => id,
+                None => {
+                    transaction
+                        .rollback()
+                        .await
+                        .context("Failed to roll back the confirmation")?;
+                    let known =
+                        get_subscriber_id_from_token(self.pool, self.tenant_id, subscription_token)
+                            .await
+                            .context(
+                                "Failed to retrieve subscriber ID from subscription_tokens.",
+                            )?;
+                    return Ok(known.map(|_| Confirmation::AlreadyUsed));
+                }
+            };
+        confirm_subscriber(&mut transaction, subscriber_id).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the SQL transaction to confirm a subscriber.")?;
+        Ok(Some(Confirmation::Confirmed(subscriber_id)))
+    }
+
+    async fn unsubscribe(
+        &self,
+        subscription_token: &SubscriptionToken,
+        reason: Option<UnsubscribeReason>,
+    ) -> Result<Option<Uuid>, anyhow::Error> {
+        let subscriber_id =
+            match get_subscriber_id_from_token(self.pool, self.tenant_id, subscription_token)
+                .await
+                .context("Failed to retrieve subscriber ID from subscription_tokens.")?
+            {
+                Some(id) => id,
+                None => return Ok(None),
+            };
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        unsubscribe_subscriber(&mut transaction, self.tenant_id, subscriber_id, reason).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the SQL transaction to unsubscribe a subscriber.")?;
+        Ok(Some(subscriber_id))
+    }
+
+    async fn get_subscriber(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Option<Subscriber>, anyhow::Error> {
+        let subscriber = sqlx::query_as!(
+            SubscriberRow,
+            r#"
+            SELECT
+                id, email, name, status AS "status: SubscriptionStatus", subscribed_at, locale,
+                email_encrypted, name_encrypted
+            FROM subscriptions
+            WHERE id = $1 AND tenant_id = $2 AND deleted_at IS NULL
+            "#,
+            subscriber_id,
+            *self.tenant_id
+        )
+        .fetch_optional(self.pool)
+        .await
+        .context("Failed to retrieve a subscriber")?;
+        subscriber.map(|r| r.reveal(self.cipher)).transpose()
+    }
+
+    async fn list_subscribers(
+        &self,
+        after: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<Subscriber>, anyhow::Error> {
+        let (subscribed_at, id) = Cursor::unzip(after);
+        let subscribers = sqlx::query_as!(
+            SubscriberRow,
+            r#"
+            SELECT
+                id, email, name, status AS "status: SubscriptionStatus", subscribed_at, locale,
+                email_encrypted, name_encrypted
+            FROM subscriptions
+            WHERE
+                tenant_id = $4 AND
+                deleted_at IS NULL AND
+                ($1::timestamptz IS NULL OR (subscribed_at, id) > ($1, $2))
+            ORDER BY subscribed_at, id
+            LIMIT $3
+            "#,
+            subscribed_at,
+            id,
+            limit,
+            *self.tenant_id
+        )
+        .fetch_all(self.pool)
+        .await
+        .context("Failed to retrieve subscribers")?;
+        subscribers
+            .into_iter()
+            .map(|r| r.reveal(self.cipher))
+            .collect()
+    }
+
+    async fn delete_subscribers(&self, subscriber_ids: &[Uuid]) -> Result<u64, anyhow::Error> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        let deleted = sqlx::query!(
+            r#"
+            UPDATE subscriptions
+            SET deleted_at = now()
+            WHERE id = ANY($1) AND tenant_id = $2 AND deleted_at IS NULL
+            RETURNING email
+            "#,
+            subscriber_ids,
+            *self.tenant_id
+        )
+        .fetch_all(&mut transaction)
+        .await
+        .context("Failed to delete subscribers")?;
+        let emails: Vec<String> = deleted.into_iter().map(|r| r.email).collect();
+        sqlx::query!(
+            r#"
+            DELETE FROM issue_delivery_queue
+            WHERE subscriber_email = ANY($1) AND tenant_id = $2
+            "#,
+            &emails,
+            *self.tenant_id
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to remove pending deliveries to deleted subscribers")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the SQL transaction to delete subscribers.")?;
+        Ok(emails.len() as u64)
+    }
+
+    async fn record_consent(
+        &self,
+        subscriber_id: Uuid,
+        action: ConsentAction,
+        evidence: &ConsentEvidence,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO consent_records (
+                subscriber_id, action, ip_address, user_agent, form_hash, policy_version,
+                recorded_at
+            )
+            SELECT id, $3, $4, $5, $6, $7, now()
+            FROM subscriptions
+            WHERE id = $1 AND tenant_id = $2
+            "#,
+            subscriber_id,
+            *self.tenant_id,
+            action.as_str(),
+            evidence.ip_address,
+            evidence.user_agent,
+            evidence.form_hash,
+            evidence.policy_version
+        )
+        .execute(self.pool)
+        .await
+        .context("Failed to record the consent of a subscriber")?;
+        Ok(())
+    }
+
+    async fn consent_records(
+        &self,
+        subscriber_id: Uuid,
+    ) -> Result<Vec<ConsentRecord>, anyhow::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                c.action, c.ip_address, c.user_agent, c.form_hash, c.policy_version,
+                c.recorded_at
+            FROM consent_records c
+            JOIN subscriptions s ON s.id = c.subscriber_id
+            WHERE c.subscriber_id = $1 AND s.tenant_id = $2
+            ORDER BY c.recorded_at
+            "#,
+            subscriber_id,
+            *self.tenant_id
+        )
+        .fetch_all(self.pool)
+        .await
+        .context("Failed to retrieve the consent records of a subscriber")?;
+        rows.into_iter()
+            .map(|r| {
+                Ok(ConsentRecord {
+                    action: ConsentAction::parse(&r.action).map_err(anyhow::Error::msg)?,
+                    ip_address: r.ip_address,
+                    user_agent: r.user_agent,
+                    form_hash: r.form_hash,
+                    policy_version: r.policy_version,
+                    recorded_at: r.recorded_at,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Permanently delete the subscribers deleted longer than `retention` ago.
+/// Returns how many were purged.
+#[tracing::instrument(name = "Purge the deleted subscribers", skip(pool))]
+pub async fn purge_deleted_subscribers(
+    pool: &PgPool,
+    retention: std::time::Duration,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let expired: Vec<Uuid> = sqlx::query!(
+        r#"
+        SELECT id
+        FROM subscriptions
+        WHERE deleted_at < now() - make_interval(secs => $1)
+        FOR UPDATE
+        "#,
+        retention.as_secs_f64()
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to find the subscribers to purge")?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+    let purged = purge_subscribers(&mut transaction, &expired).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to purge deleted subscribers.")?;
+    Ok(purged)
+}
+
+/// Delete the subscriptions which were never confirmed, along with their tokens.
+/// Returns how many were deleted.
+#[tracing::instrument(name = "Prune the expired pending subscriptions", skip(pool))]
+pub async fn prune_pending_subscriptions(
+    pool: &PgPool,
+    ttl: std::time::Duration,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let expired: Vec<Uuid> = sqlx::query!(
+        r#"
+        SELECT id
+        FROM subscriptions
+        WHERE
+            status = 'pending_confirmation' AND
+            subscribed_at < now() - make_interval(secs => $1)
+        FOR UPDATE
+        "#,
+        ttl.as_secs_f64()
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to find the expired pending subscriptions")?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+    let deleted = purge_subscribers(&mut transaction, &expired).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to prune pending subscriptions.")?;
+    Ok(deleted)
+}
+
+/// Permanently delete a subscriber previously deleted, if the tenant has one with this
+/// email - sealed or not.
+pub(crate) async fn purge_deleted_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    email: &str,
+    cipher: Option<&PiiCipher>,
+) -> Result<(), anyhow::Error> {
+    let deleted = sqlx::query!(
+        r#"
+        SELECT id
+        FROM subscriptions
+        WHERE
+            tenant_id = $1 AND
+            (lower(email) = lower($2) OR email_blind_index = $3) AND
+            deleted_at IS NOT NULL
+        "#,
+        *tenant_id,
+        email,
+        cipher.map(|c| c.blind_index(email))
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look for a deleted subscriber")?;
+    if let Some(r) = deleted {
+        purge_subscribers(transaction, &[r.id]).await?;
+    }
+    Ok(())
+}
+
+/// Delete subscribers for good, along with their tokens. Their tags go with them.
+async fn purge_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_ids: &[Uuid],
+) -> Result<u64, anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = ANY($1)"#,
+        subscriber_ids
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the subscription tokens of subscribers")?;
+    let purged = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = ANY($1)"#,
+        subscriber_ids
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete subscribers")?
+    .rows_affected();
+    Ok(purged)
+}
+
+#[tracing::instrument(
+    name = "Storing subscription token in the database",
+    skip(transaction, subscription_token)
+)]
+pub async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscription_token: &SubscriptionToken,
+) -> Result<(), StoreTokenError> {
+    sqlx::query!(
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+        VALUES ($1, $2)
+        "#,
+        subscription_token.as_ref(),
+        subscriber_id
+    )
+    .execute(transaction)
+    .await
+    .map_err(StoreTokenError)?;
+    Ok(())
+}
+
+/// A subscriber as stored by a signup.
+struct StoredSubscriber {
+    id: Uuid,
+    /// As stored: the pseudonym of a sealed subscriber.
+    email: String,
+    already_existed: bool,
+    /// The language the subscriber chose, now or when they first signed up.
+    locale: Option<Locale>,
+}
+
+/// A single statement, so concurrent signups for the same email settle on one row: the
+/// later ones wait for the row lock and get the existing subscriber back.
+#[tracing::instrument(
+    name = "Saving new subscriber details in the database",
+    skip(new_subscriber, transaction)
+)]
+async fn upsert_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    new_subscriber: &NewSubscriber,
+) -> Result<StoredSubscriber, sqlx::Error> {
+    // The update makes `RETURNING` yield the existing row on conflict, keeping the
+    // locale chosen before unless a new one is. `xmax` is only zero for a freshly
+    // inserted row.
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, tenant_id, locale)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5, $6)
+        ON CONFLICT (tenant_id, email) DO UPDATE
+        SET locale = COALESCE(EXCLUDED.locale, subscriptions.locale)
+        RETURNING id, email, (xmax <> 0) AS "already_existed!", locale
+        "#,
+        Uuid::new_v4(),
+        new_subscriber.email.as_ref(),
+        new_subscriber.name.as_ref().map(AsRef::as_ref),
+        Utc::now(),
+        *tenant_id,
+        new_subscriber.locale.as_ref().map(Locale::language)
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(StoredSubscriber {
+        id: row.id,
+        email: row.email,
+        already_existed: row.already_existed,
+        // Stored locales were supported when stored: one we dropped since falls back.
+        locale: row.locale.and_then(|l| Locale::parse(&l).ok()),
+    })
+}
+
+/// `upsert_subscriber` for sealed subscribers: the address is matched through its blind
+/// index - or in the clear, should the subscriber not be sealed yet. The unique
+/// constraint on `email` cannot see through pseudonyms, so concurrent signups for the
+/// same address are serialized by an advisory lock instead.
+#[tracing::instrument(
+    name = "Saving new sealed subscriber details in the database",
+    skip(new_subscriber, transaction, cipher)
+)]
+async fn upsert_sealed_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    new_subscriber: &NewSubscriber,
+    cipher: &PiiCipher,
+) -> Result<StoredSubscriber, anyhow::Error> {
+    let email = new_subscriber.email.as_ref();
+    let locale = new_subscriber.locale.as_ref().map(Locale::language);
+    let blind_index = cipher.blind_index(email);
+    sqlx::query!(
+        r#"
+        SELECT 1 AS "locked!"
+        FROM pg_advisory_xact_lock(hashtextextended($1::uuid::text || encode($2, 'hex'), 0))
+        "#,
+        *tenant_id,
+        blind_index
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to lock the address of a new subscriber")?;
+    let existing = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET locale = COALESCE($4, locale)
+        WHERE tenant_id = $1 AND (email_blind_index = $2 OR email = $3)
+        RETURNING id, email, locale
+        "#,
+        *tenant_id,
+        blind_index,
+        email,
+        locale
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look for an existing subscriber")?;
+    if let Some(r) = existing {
+        return Ok(StoredSubscriber {
+            id: r.id,
+            email: r.email,
+            already_existed: true,
+            locale: r.locale.and_then(|l| Locale::parse(&l).ok()),
+        });
+    }
+    let subscriber_id = Uuid::new_v4();
+    let sealed = cipher.seal(
+        subscriber_id,
+        email,
+        new_subscriber.name.as_ref().map(AsRef::as_ref),
+    )?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (
+            id, email, name, subscribed_at, status, tenant_id, locale,
+            email_encrypted, name_encrypted, email_blind_index, pii_encrypted_at
+        )
+        VALUES ($1, $2, NULL, $3, 'pending_confirmation', $4, $5, $6, $7, $8, now())
+        "#,
+        subscriber_id,
+        sealed.email,
+        Utc::now(),
+        *tenant_id,
+        locale,
+        sealed.email_encrypted,
+        sealed.name_encrypted,
+        sealed.email_blind_index
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to insert a sealed subscriber")?;
+    Ok(StoredSubscriber {
+        id: subscriber_id,
+        email: sealed.email,
+        already_existed: false,
+        locale: new_subscriber.locale.clone(),
+    })
+}
+
+#[tracing::instrument(
+    name = "Checking for past subscription token in the database",
+    skip(subscriber_id, transaction)
+)]
+async fn get_past_subscription_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriptionToken>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(result.map(|r| SubscriptionToken::parse(r.subscription_token).unwrap()))
+}
+
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed"
+    skip(transaction, subscriber_id)
+)]
+async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    // Following the confirmation link twice does not confirm the subscriber twice.
+    // Subscribers who come back are no longer counted as having unsubscribed.
+    // Following the link also proves the address receives email, whatever its verification
+    // said.
+    let confirmed = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            status = 'confirmed',
+            unsubscribed_at = NULL,
+            unsubscribe_reason = NULL,
+            verification = NULL
+        WHERE id = $1 AND status <> 'confirmed'
+        RETURNING email, tenant_id
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to mark the subscriber as confirmed")?;
+    if let Some(r) = confirmed {
+        let event = DomainEvent::SubscriberConfirmed {
+            subscriber_id,
+            email: r.email,
+        };
+        record_event(transaction, TenantId::from(r.tenant_id), &event).await?;
+    }
+    Ok(())
+}
+
+/// Mark the confirmation link of the token as used, returning the subscriber id. `None`
+/// if the token is unknown or its link was already used. Concurrent clicks on the link
+/// wait for the row lock, then find it used.
+#[tracing::instrument(name = "Use a confirmation link", skip_all)]
+async fn use_confirmation_link(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    subscription_token: &SubscriptionToken,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE subscription_tokens t
+        SET confirmation_used_at = now()
+        FROM subscriptions s
+        WHERE
+            t.subscription_token = $1 AND
+            t.confirmation_used_at IS NULL AND
+            s.id = t.subscriber_id AND
+            s.tenant_id = $2 AND
+            s.deleted_at IS NULL
+        RETURNING t.subscriber_id
+        "#,
+        subscription_token.as_ref(),
+        *tenant_id
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to mark the confirmation link as used")?;
+    Ok(row.map(|r| r.subscriber_id))
+}
+
+/// Subscribers who are not confirmed anymore - e.g. they unsubscribed - and sign up
+/// again get a working confirmation link.
+#[tracing::instrument(name = "Reopen the confirmation link", skip(transaction))]
+async fn reopen_confirmation_link(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens t
+        SET confirmation_used_at = NULL
+        FROM subscriptions s
+        WHERE s.id = t.subscriber_id AND s.id = $1 AND s.status <> 'confirmed'
+        "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to reopen the confirmation link")?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Mark subscriber as unsubscribed"
+    skip(transaction, subscriber_id)
+)]
+async fn unsubscribe_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    subscriber_id: Uuid,
+    reason: Option<UnsubscribeReason>,
+) -> Result<(), anyhow::Error> {
+    let reason = reason.map(|r| r.as_str());
+    let unsubscribed = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed', unsubscribed_at = now(), unsubscribe_reason = $2
+        WHERE id = $1 AND status <> 'unsubscribed'
+        RETURNING email
+        "#,
+        subscriber_id,
+        reason
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to mark the subscriber as unsubscribed")?;
+    if let Some(r) = unsubscribed {
+        // An issue still being delivered does not reach them either.
+        sqlx::query!(
+            r#"
+            DELETE FROM issue_delivery_queue
+            WHERE subscriber_email = $1 AND tenant_id = $2
+            "#,
+            r.email,
+            *tenant_id
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to remove pending deliveries to an unsubscribed subscriber")?;
+        let event = DomainEvent::SubscriberUnsubscribed {
+            subscriber_id,
+            email: r.email,
+            reason: reason.map(Into::into),
+        };
+        record_event(transaction, tenant_id, &event).await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Get subscriber_id from token"
+    skip(pool, subscription_token)
+)]
+async fn get_subscriber_id_from_token(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    subscription_token: &SubscriptionToken,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT t.subscriber_id
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token = $1 AND s.tenant_id = $2 AND s.deleted_at IS NULL
+        "#,
+        subscription_token.as_ref(),
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(result.map(|r| r.subscriber_id))
+}
+
+pub struct StoreTokenError(sqlx::Error);
+
+impl std::fmt::Display for StoreTokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "A database error was encountered while \
+            trying to store a subscription token."
+        )
+    }
+}
+impl std::fmt::Debug for StoreTokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+impl std::error::Error for StoreTokenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
@@ -0,0 +1,267 @@
+//! The data retention policy, applied by the scheduler or from the command line.
+//!
+//! Each rule of `retention` covers one kind of data, and is off when set to `0` days:
+//! - `delivery_attempts_days`: the outcome of each delivery of an issue is deleted;
+//! - `unsubscribed_subscribers_days`: the subscribers who left are anonymized - their
+//!   address is replaced by a placeholder, in their deliveries, complaints, domain events
+//!   and the events reported by the email provider too, and their name dropped - so that
+//!   they still count in the stats of the issues they received. The payload of the
+//!   provider events is cut down to what the stats read, the IP address and user agent
+//!   of their consent records are dropped, and the emails still in the outbox for them
+//!   deleted. The address of those who bounced or complained is kept hashed, see
+//!   `crate::bounces::is_suppressed`;
+//! - `engagement_events_days`: the opens and clicks reported by the email provider are
+//!   deleted.
+//!
+//! A dry run applies the policy in a transaction which is rolled back, to report what it
+//! would do.
+use crate::bounces::BOUNCED_UNSUBSCRIBE_REASON;
+use crate::complaints::COMPLAINED_UNSUBSCRIBE_REASON;
+use crate::configuration::RetentionSettings;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Formatter;
+
+/// What applying the policy did, or would have done.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    pub deleted_delivery_attempts: u64,
+    pub anonymized_subscribers: u64,
+    pub deleted_engagement_events: u64,
+}
+
+impl RetentionReport {
+    pub fn total(&self) -> u64 {
+        self.deleted_delivery_attempts
+            + self.anonymized_subscribers
+            + self.deleted_engagement_events
+    }
+}
+
+impl std::fmt::Display for RetentionReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.dry_run {
+            writeln!(f, "Retention report (dry run - nothing was changed)")?;
+        } else {
+            writeln!(f, "Retention report")?;
+        }
+        writeln!(
+            f,
+            "  delivery attempts deleted: {}",
+            self.deleted_delivery_attempts
+        )?;
+        writeln!(
+            f,
+            "  unsubscribed subscribers anonymized: {}",
+            self.anonymized_subscribers
+        )?;
+        writeln!(
+            f,
+            "  opens and clicks deleted: {}",
+            self.deleted_engagement_events
+        )
+    }
+}
+
+#[tracing::instrument(name = "Apply the retention policy", skip(pool))]
+pub async fn apply_retention_policy(
+    pool: &PgPool,
+    settings: &RetentionSettings,
+    dry_run: bool,
+) -> Result<RetentionReport, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let mut report = RetentionReport {
+        dry_run,
+        ..RetentionReport::default()
+    };
+    if settings.delivery_attempts_days > 0 {
+        report.deleted_delivery_attempts =
+            delete_delivery_attempts(&mut transaction, settings.delivery_attempts_days).await?;
+    }
+    if settings.unsubscribed_subscribers_days > 0 {
+        report.anonymized_subscribers = anonymize_unsubscribed_subscribers(
+            &mut transaction,
+            settings.unsubscribed_subscribers_days,
+        )
+        .await?;
+    }
+    if settings.engagement_events_days > 0 {
+        report.deleted_engagement_events =
+            delete_engagement_events(&mut transaction, settings.engagement_events_days).await?;
+    }
+    if dry_run {
+        transaction
+            .rollback()
+            .await
+            .context("Failed to roll back the retention dry run.")?;
+    } else {
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the retention policy.")?;
+    }
+    tracing::info!(
+        deleted_delivery_attempts = report.deleted_delivery_attempts,
+        anonymized_subscribers = report.anonymized_subscribers,
+        deleted_engagement_events = report.deleted_engagement_events,
+        dry_run,
+        "Applied the retention policy"
+    );
+    Ok(report)
+}
+
+async fn delete_delivery_attempts(
+    transaction: &mut Transaction<'_, Postgres>,
+    days: u64,
+) -> Result<u64, anyhow::Error> {
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM issue_deliveries
+        WHERE attempted_at < now() - make_interval(days => $1)
+        "#,
+        days as i32
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to delete the old delivery attempts")?;
+    Ok(deleted.rows_affected())
+}
+
+/// Subscribers who came back in the meantime are confirmed, so they are left alone.
+/// Those who bounced or complained leave their address hashed in `suppressed_addresses`:
+/// anonymizing them must not let them be imported again.
+async fn anonymize_unsubscribed_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    days: u64,
+) -> Result<u64, anyhow::Error> {
+    let anonymized = sqlx::query!(
+        r#"
+        WITH expired AS (
+            SELECT id, email, email_blind_index, unsubscribe_reason, tenant_id
+            FROM subscriptions
+            WHERE
+                status = 'unsubscribed' AND
+                anonymized_at IS NULL AND
+                unsubscribed_at < now() - make_interval(days => $1)
+            FOR UPDATE
+        ), suppressed AS (
+            INSERT INTO suppressed_addresses (email_hash, reason, suppressed_at)
+            SELECT
+                COALESCE(email_blind_index, sha256(convert_to(lower(email), 'UTF8'))),
+                min(unsubscribe_reason),
+                now()
+            FROM expired
+            WHERE unsubscribe_reason IN ($2, $3)
+            GROUP BY 1
+            ON CONFLICT (email_hash) DO NOTHING
+        ), anonymized AS (
+            UPDATE subscriptions s
+            SET
+                email = 'anonymized+' || s.id || '@anonymized.invalid',
+                name = NULL,
+                anonymized_at = now()
+            FROM expired e
+            WHERE s.id = e.id
+            RETURNING
+                s.id, e.email AS old_email, e.email_blind_index AS old_blind_index, s.email,
+                s.tenant_id
+        ), deliveries AS (
+            UPDATE issue_deliveries d
+            SET subscriber_email = a.email
+            FROM anonymized a, newsletter_issues i
+            WHERE
+                d.newsletter_issue_id = i.newsletter_issue_id AND
+                i.tenant_id = a.tenant_id AND
+                d.subscriber_email = a.old_email
+        ), complaints AS (
+            UPDATE issue_complaints c
+            SET subscriber_email = a.email
+            FROM anonymized a, newsletter_issues i
+            WHERE
+                c.newsletter_issue_id = i.newsletter_issue_id AND
+                i.tenant_id = a.tenant_id AND
+                c.subscriber_email = lower(a.old_email)
+        ), subscriber_events AS (
+            UPDATE events ev
+            SET payload = jsonb_set(ev.payload, '{email}', to_jsonb(a.email))
+            FROM anonymized a
+            WHERE ev.payload->>'subscriber_id' = a.id::text AND ev.payload ? 'email'
+        ), delivery_events AS (
+            UPDATE events ev
+            SET payload = jsonb_set(ev.payload, '{subscriber_email}', to_jsonb(a.email))
+            FROM anonymized a
+            WHERE ev.tenant_id = a.tenant_id AND ev.payload->>'subscriber_email' = a.old_email
+        ), retry_events AS (
+            UPDATE events ev
+            SET payload = jsonb_set(ev.payload, '{subscriber_emails}', (
+                SELECT jsonb_agg(COALESCE(a.email, r.email) ORDER BY r.position)
+                FROM jsonb_array_elements_text(ev.payload->'subscriber_emails')
+                    WITH ORDINALITY AS r (email, position)
+                LEFT JOIN anonymized a ON a.old_email = r.email AND a.tenant_id = ev.tenant_id
+            ))
+            WHERE
+                ev.tenant_id IN (SELECT tenant_id FROM anonymized) AND
+                ev.payload->'subscriber_emails' ?| ARRAY(SELECT old_email FROM anonymized)
+        ), provider_events AS (
+            -- Untagged events of an address several tenants share have no tenant.
+            UPDATE email_provider_events pe
+            SET
+                recipient = a.email,
+                recipient_blind_index = NULL,
+                payload = jsonb_strip_nulls(jsonb_build_object(
+                    'RecordType', pe.payload->'RecordType',
+                    'Type', pe.payload->'Type',
+                    'Tag', pe.payload->'Tag',
+                    'Description', pe.payload->'Description'
+                ))
+            FROM anonymized a
+            WHERE
+                (pe.tenant_id = a.tenant_id OR pe.tenant_id IS NULL) AND
+                (
+                    lower(pe.recipient) = lower(a.old_email) OR
+                    pe.recipient_blind_index = a.old_blind_index
+                )
+        ), outbox AS (
+            DELETE FROM email_outbox o
+            USING anonymized a
+            WHERE o.tenant_id = a.tenant_id AND lower(o.recipient) = lower(a.old_email)
+        ), consent AS (
+            UPDATE consent_records c
+            SET ip_address = NULL, user_agent = NULL
+            FROM anonymized a
+            WHERE c.subscriber_id = a.id
+        )
+        SELECT COUNT(*) AS "anonymized!" FROM anonymized
+        "#,
+        days as i32,
+        BOUNCED_UNSUBSCRIBE_REASON,
+        COMPLAINED_UNSUBSCRIBE_REASON
+    )
+    .fetch_one(transaction)
+    .await
+    .context("Failed to anonymize the unsubscribed subscribers")?;
+    Ok(anonymized.anonymized as u64)
+}
+
+async fn delete_engagement_events(
+    transaction: &mut Transaction<'_, Postgres>,
+    days: u64,
+) -> Result<u64, anyhow::Error> {
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM email_provider_events
+        WHERE
+            record_type IN ('Open', 'Click') AND
+            received_at < now() - make_interval(days => $1)
+        "#,
+        days as i32
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to delete the old opens and clicks")?;
+    Ok(deleted.rows_affected())
+}
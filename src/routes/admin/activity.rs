@@ -0,0 +1,84 @@
+use crate::activity::{get_activity_after, get_recent_activity, ActivityEntry, RECENT_ACTIVITY};
+use crate::authentication::{can_view_pii, UserId};
+use crate::pii::Masked;
+use crate::tenancy::Tenant;
+use crate::utils::e500;
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often new events are looked for while the stream is open.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(serde::Deserialize)]
+pub struct ActivityQuery {
+    /// Only stream the events recorded after this one.
+    after: Option<Uuid>,
+}
+
+/// Server-sent events with the domain events, as they are recorded.
+/// The stream starts after the event given by `Last-Event-ID` - set by browsers when they
+/// reconnect - or by `after`; with neither, it starts with the most recent events.
+/// Polls without news send a comment, so that dropped connections are noticed.
+/// The addresses of subscribers are masked for the admins who cannot see them.
+#[tracing::instrument(name = "Stream the activity feed", skip_all)]
+pub async fn get_activity_events(
+    request: HttpRequest,
+    query: web::Query<ActivityQuery>,
+    pool: web::Data<PgPool>,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let last_event_id = request
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<Uuid>().ok())
+        .or(query.after);
+
+    let masked = !can_view_pii(&pool, **user_id).await.map_err(e500)?;
+    let pool = pool.get_ref().clone();
+    let tenant_id = tenant.id;
+    let events =
+        futures_util::stream::unfold((last_event_id, true), move |(last_event_id, first_poll)| {
+            let pool = pool.clone();
+            async move {
+                if !first_poll {
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+                let entries = match last_event_id {
+                    Some(event_id) => get_activity_after(&pool, tenant_id, event_id).await,
+                    None => get_recent_activity(&pool, tenant_id, RECENT_ACTIVITY).await,
+                };
+                let entries = match entries {
+                    Ok(entries) if masked => {
+                        entries.into_iter().map(ActivityEntry::masked).collect()
+                    }
+                    Ok(entries) => entries,
+                    // The response is aborted; browsers reconnect with `Last-Event-ID`.
+                    Err(e) => return Some((Err(e), (last_event_id, false))),
+                };
+                let next_event_id = entries.last().map(|e| e.event_id).or(last_event_id);
+                let chunk = if entries.is_empty() {
+                    ": waiting\n\n".to_owned()
+                } else {
+                    entries.iter().map(sse_event).collect()
+                };
+                Some((Ok(web::Bytes::from(chunk)), (next_event_id, false)))
+            }
+        });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(events))
+}
+
+fn sse_event(entry: &ActivityEntry) -> String {
+    format!(
+        "id: {}\nevent: activity\ndata: {}\n\n",
+        entry.event_id,
+        serde_json::to_string(entry).expect("Activity entries are always serializable")
+    )
+}
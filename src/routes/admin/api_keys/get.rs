@@ -0,0 +1,36 @@
+use crate::authentication::{list_api_keys, ApiKey, UserId};
+use crate::configuration::BrandingSettings;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama_actix::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "admin/api_keys.html")]
+struct ApiKeysTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    keys: Vec<ApiKey>,
+}
+
+pub async fn api_keys_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let keys = list_api_keys(*user_id, &pool).await.map_err(e500)?;
+    let body = ApiKeysTemplate {
+        branding,
+        flash_messages,
+        keys,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::api_keys_form;
+pub use post::{create_api_key, revoke_api_key};
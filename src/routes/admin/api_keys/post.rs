@@ -0,0 +1,67 @@
+use crate::authentication::UserId;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct CreateFormData {
+    description: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RevokeFormData {
+    api_key_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Create an API key",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn create_api_key(
+    form: web::Form<CreateFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let description = form.0.description.trim().to_owned();
+    if description.is_empty() {
+        FlashMessage::error("Please describe what the API key will be used for.").send();
+        return Ok(see_other("/admin/api_keys"));
+    }
+
+    let key = crate::authentication::create_api_key(*user_id, &description, &pool)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "Your new API key is {} - copy it now, it will not be shown again.",
+        key.expose_secret()
+    ))
+    .send();
+    Ok(see_other("/admin/api_keys"))
+}
+
+#[tracing::instrument(
+    name = "Revoke an API key",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn revoke_api_key(
+    form: web::Form<RevokeFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    if crate::authentication::revoke_api_key(*user_id, form.0.api_key_id, &pool)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The API key has been revoked.").send();
+    } else {
+        FlashMessage::error("The API key does not exist or has already been revoked.").send();
+    }
+    Ok(see_other("/admin/api_keys"))
+}
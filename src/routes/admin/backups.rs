@@ -0,0 +1,75 @@
+use crate::authentication::UserId;
+use crate::backups::{latest_backup_job, list_backups, start_backup_job, Backup, BackupJobStatus};
+use crate::configuration::BrandingSettings;
+use crate::tenancy::Tenant;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama_actix::Template;
+use sqlx::PgPool;
+
+/// How many of the latest backups are listed.
+const LISTED_BACKUPS: i64 = 50;
+
+#[derive(Template)]
+#[template(path = "admin/backups.html")]
+struct BackupsTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    backups: Vec<Backup>,
+    latest_job: Option<BackupJobStatus>,
+}
+
+impl BackupsTemplate {
+    fn is_pending(&self) -> bool {
+        self.latest_job.as_ref().is_some_and(|job| job.is_pending())
+    }
+}
+
+/// The completed backups, with their size.
+#[tracing::instrument(name = "Show the backups", skip_all)]
+pub async fn get_backups(
+    pool: web::Data<PgPool>,
+    tenant: web::ReqData<Tenant>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let backups = list_backups(&pool, tenant.id, LISTED_BACKUPS)
+        .await
+        .map_err(e500)?;
+    let latest_job = latest_backup_job(&pool, tenant.id).await.map_err(e500)?;
+    let body = BackupsTemplate {
+        branding,
+        flash_messages,
+        backups,
+        latest_job,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+#[tracing::instrument(
+    name = "Start a backup",
+    skip(pool, tenant, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn start_backup(
+    pool: web::Data<PgPool>,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if start_backup_job(&pool, tenant.id)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        FlashMessage::error("A backup is already in progress.").send();
+        return Ok(see_other("/admin/backups"));
+    }
+    FlashMessage::info("The backup has been started. It is listed below once completed.").send();
+    Ok(see_other("/admin/backups"))
+}
@@ -0,0 +1,61 @@
+use crate::bundle::{export_bundle, import_bundle, BundleError, SetupBundle};
+use crate::concurrency_limits::{ConcurrencyLimits, HeavyRoute};
+use crate::error::AppError;
+use crate::tenancy::Tenant;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    /// The content of an exported bundle.
+    bundle: String,
+}
+
+/// Download the setup of the newsletter, see `crate::bundle`.
+#[tracing::instrument(name = "Download the setup bundle", skip_all)]
+pub async fn get_setup_bundle(
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    limits: web::Data<ConcurrencyLimits>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let _permit = limits
+        .try_acquire(tenant.id, HeavyRoute::Export)
+        .map_err(AppError::from)?;
+    let bundle = export_bundle(&pool, tenant.id).await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "newsletter-setup-{}.json",
+                bundle.exported_at.format("%Y-%m-%d")
+            ))],
+        })
+        .json(bundle))
+}
+
+#[tracing::instrument(name = "Import a setup bundle", skip_all)]
+pub async fn import_setup_bundle(
+    form: web::Form<FormData>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    limits: web::Data<ConcurrencyLimits>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let _permit = limits
+        .try_acquire(tenant.id, HeavyRoute::Import)
+        .map_err(AppError::from)?;
+    let outcome = match SetupBundle::parse(&form.bundle) {
+        Ok(bundle) => import_bundle(&pool, tenant.id, &bundle).await,
+        Err(e) => Err(e),
+    };
+    match outcome {
+        Ok(()) => FlashMessage::info("The setup of the newsletter has been imported.").send(),
+        Err(e @ (BundleError::UnsupportedVersion(_) | BundleError::Invalid(_))) => {
+            FlashMessage::error(e.to_string()).send()
+        }
+        Err(e @ BundleError::UnexpectedError(_)) => return Err(e500(e)),
+    }
+    Ok(see_other("/admin/dashboard"))
+}
@@ -0,0 +1,38 @@
+use crate::churn::{get_churn_report, ChurnReport};
+use crate::configuration::BrandingSettings;
+use crate::tenancy::Tenant;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama_actix::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "admin/churn.html")]
+struct ChurnTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    report: ChurnReport,
+}
+
+/// Unsubscribes by reason and by month.
+#[tracing::instrument(name = "Show the churn report", skip_all)]
+pub async fn churn_report(
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let report = get_churn_report(&pool, tenant.id).await.map_err(e500)?;
+    let body = ChurnTemplate {
+        branding,
+        flash_messages,
+        report,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
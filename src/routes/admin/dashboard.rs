@@ -1,43 +1,66 @@
+use crate::activity::{get_recent_activity, ActivityEntry, RECENT_ACTIVITY};
+use crate::authentication::can_view_pii;
+use crate::configuration::BrandingSettings;
+use crate::pii::Masked;
 use crate::session_state::TypedSession;
+use crate::stats::{get_overview, DeliveryFailure, Overview};
+use crate::tenancy::Tenant;
 use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
 use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
 use anyhow::Context;
+use askama_actix::Template;
 use sqlx::PgPool;
 use uuid::Uuid;
 
+#[derive(Template)]
+#[template(path = "admin/dashboard.html")]
+struct DashboardTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    username: String,
+    overview: Overview,
+    /// Oldest first.
+    activity: Vec<ActivityEntry>,
+}
+
 pub async fn admin_dashboard(
     session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    tenant: web::ReqData<Tenant>,
     pool: web::Data<PgPool>,
+    branding: web::Data<BrandingSettings>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let username = if let Some(user_id) = session.get_user_id().map_err(e500)? {
-        get_username(user_id, &pool).await.map_err(e500)?
-    } else {
-        return Ok(see_other("/login"));
+    let user_id = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
+        None => return Ok(see_other("/login")),
     };
-    Ok(HttpResponse::Ok().body(format!(
-        r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-<meta http-equiv="content-type" content="text/html; charset=utf-8">
-<title>Admin dashboard</title>
-</head>
-<body>
-<p>Welcome {username}!</p>
-<p>Available actions:</p>
-<ol>
-<li><a href="/admin/password">Change password</a></li>
-<li>
-<a href="/admin/newsletters">Send a newsletter</a>
-</li>
-<li>
-<form name="logoutForm" action="/admin/logout" method="post">
-<input type="submit" value="Logout">
-</form>
-</li>
-</ol>
-</body>
-</html>"#
-    )))
+    let username = get_username(user_id, &pool).await.map_err(e500)?;
+    let mut overview = get_overview(&pool, tenant.id).await.map_err(e500)?;
+    let mut activity = get_recent_activity(&pool, tenant.id, RECENT_ACTIVITY)
+        .await
+        .map_err(e500)?;
+    if !can_view_pii(&pool, user_id).await.map_err(e500)? {
+        overview.recent_failures = overview
+            .recent_failures
+            .into_iter()
+            .map(DeliveryFailure::masked)
+            .collect();
+        activity = activity.into_iter().map(ActivityEntry::masked).collect();
+    }
+    let body = DashboardTemplate {
+        branding,
+        flash_messages,
+        username,
+        overview,
+        activity,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
 }
 
 #[tracing::instrument(name = "Get username", skip(pool))]
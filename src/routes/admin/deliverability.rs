@@ -0,0 +1,51 @@
+use crate::authentication::{can_view_pii, UserId};
+use crate::configuration::BrandingSettings;
+use crate::deliverability_metrics::{
+    get_deliverability_report, DeliverabilityReport, FlaggedBounce,
+};
+use crate::pii::Masked;
+use crate::tenancy::Tenant;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama_actix::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "admin/deliverability.html")]
+struct DeliverabilityTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    report: DeliverabilityReport,
+}
+
+/// Bounces, complaints and opens by recipient domain, by issue and by week.
+#[tracing::instrument(name = "Show the deliverability dashboard", skip_all)]
+pub async fn deliverability_dashboard(
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut report = get_deliverability_report(&pool, tenant.id)
+        .await
+        .map_err(e500)?;
+    if !can_view_pii(&pool, **user_id).await.map_err(e500)? {
+        report.flagged_bounces = std::mem::take(&mut report.flagged_bounces)
+            .into_iter()
+            .map(FlaggedBounce::masked)
+            .collect();
+    }
+    let body = DeliverabilityTemplate {
+        branding,
+        flash_messages,
+        report,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
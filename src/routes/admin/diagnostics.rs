@@ -0,0 +1,37 @@
+use crate::configuration::BrandingSettings;
+use crate::deliverability::{DeliverabilityChecker, RecordCheck};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama_actix::Template;
+
+#[derive(Template)]
+#[template(path = "admin/diagnostics.html")]
+struct DiagnosticsTemplate<'a> {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    domain: &'a str,
+    checks: Vec<RecordCheck>,
+}
+
+/// The SPF, DKIM and DMARC records of the sending domain, as the world sees them now.
+#[tracing::instrument(name = "Show the deliverability diagnostics", skip_all)]
+pub async fn deliverability_diagnostics(
+    checker: web::Data<DeliverabilityChecker>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let checks = checker.run().await;
+    let body = DiagnosticsTemplate {
+        branding,
+        flash_messages,
+        domain: checker.domain(),
+        checks,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
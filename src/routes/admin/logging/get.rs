@@ -0,0 +1,47 @@
+use crate::configuration::{BrandingSettings, LogFormat};
+use crate::telemetry::LogHandle;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama_actix::Template;
+
+struct FormatOption {
+    value: &'static str,
+    selected: bool,
+}
+
+#[derive(Template)]
+#[template(path = "admin/logging.html")]
+struct LoggingTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    level: String,
+    format_options: Vec<FormatOption>,
+}
+
+pub async fn get_logging_form(
+    flash_messages: IncomingFlashMessages,
+    log_handle: web::Data<LogHandle>,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current = log_handle.current();
+    let format_options = [LogFormat::Json, LogFormat::Pretty]
+        .into_iter()
+        .map(|format| FormatOption {
+            value: format.as_str(),
+            selected: format == current.format,
+        })
+        .collect();
+    let body = LoggingTemplate {
+        branding,
+        flash_messages,
+        level: current.level,
+        format_options,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::get_logging_form;
+pub use post::update_logging;
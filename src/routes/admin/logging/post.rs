@@ -0,0 +1,39 @@
+use crate::authentication::UserId;
+use crate::configuration::{LogFormat, LoggingSettings};
+use crate::telemetry::LogHandle;
+use crate::utils::see_other;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    level: String,
+    format: LogFormat,
+}
+
+#[tracing::instrument(
+    name = "Update logging configuration",
+    skip(form, log_handle, user_id),
+    fields(user_id=%*user_id, level=%form.level, format=?form.format)
+)]
+pub async fn update_logging(
+    form: web::Form<FormData>,
+    log_handle: web::Data<LogHandle>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { level, format } = form.0;
+    if level.trim().is_empty() {
+        FlashMessage::error("The log filter cannot be empty.").send();
+        return Ok(see_other("/admin/logging"));
+    }
+
+    if let Err(e) = log_handle.reload(LoggingSettings { level, format }) {
+        tracing::warn!(error.cause_chain = ?e, "Rejected a logging configuration update");
+        FlashMessage::error(format!("{:#}", e)).send();
+        return Ok(see_other("/admin/logging"));
+    }
+
+    tracing::info!("Logging configuration has been updated");
+    FlashMessage::info("The logging configuration has been updated.").send();
+    Ok(see_other("/admin/logging"))
+}
@@ -1,9 +1,41 @@
+mod activity;
+mod api_keys;
+mod backups;
+mod bundle;
+mod churn;
 mod dashboard;
+mod deliverability;
+mod diagnostics;
+mod logging;
 mod logout;
 mod newsletters;
+mod notifications;
 mod password;
+mod preview;
+mod queries;
+mod read_only;
+mod redirects;
+mod search;
+mod tools;
+mod waitlist;
 
+pub use activity::get_activity_events;
+pub use api_keys::*;
+pub use backups::{get_backups, start_backup};
+pub use bundle::{get_setup_bundle, import_setup_bundle};
+pub use churn::churn_report;
 pub use dashboard::admin_dashboard;
+pub use deliverability::deliverability_dashboard;
+pub use diagnostics::deliverability_diagnostics;
+pub use logging::*;
 pub use logout::log_out;
 pub use newsletters::*;
+pub use notifications::{get_notification_preferences_form, update_notification_preferences};
 pub use password::*;
+pub use preview::{preview_form, preview_page};
+pub use queries::get_query_metrics;
+pub use read_only::switch_read_only;
+pub use redirects::{add_redirect, get_redirects, remove_redirect};
+pub use search::admin_search;
+pub use tools::*;
+pub use waitlist::{get_waitlist, promote_waitlist_entry};
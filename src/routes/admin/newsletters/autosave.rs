@@ -0,0 +1,95 @@
+use crate::authentication::UserId;
+use crate::configuration::NewsletterSettings;
+use crate::drafts::{get_draft_editor, lock_draft, save_draft, DraftContent};
+use crate::tenancy::Tenant;
+use crate::utils::e500;
+use actix_web::error::{ErrorConflict, ErrorForbidden};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    title: String,
+    html: String,
+    text: String,
+}
+
+/// Called in the background by the compose form, with whatever has been written so far.
+#[tracing::instrument(
+    name = "Autosave a newsletter draft",
+    skip(form, pool, user_id, tenant, newsletters),
+    fields(user_id=%*user_id)
+)]
+pub async fn autosave_newsletter_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+    newsletters: web::Data<NewsletterSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let FormData { title, html, text } = form.0;
+    let saved = save_draft(
+        &pool,
+        tenant.id,
+        **user_id,
+        draft_id,
+        DraftContent {
+            title: &title,
+            text_content: &text,
+            html_content: &html,
+        },
+        newsletters.draft_lock_ttl(),
+    )
+    .await
+    .map_err(e500)?;
+    if !saved {
+        let editor = get_draft_editor(
+            &pool,
+            tenant.id,
+            **user_id,
+            draft_id,
+            newsletters.draft_lock_ttl(),
+        )
+        .await
+        .map_err(e500)?
+        .unwrap_or_default();
+        return Err(ErrorForbidden(being_edited_by(&editor)));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Called in the background by the compose form while it is open, to keep the lock of
+/// its draft.
+#[tracing::instrument(
+    name = "Keep the lock of a newsletter draft",
+    skip(pool, user_id, tenant, newsletters),
+    fields(user_id=%*user_id)
+)]
+pub async fn draft_heartbeat(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+    newsletters: web::Data<NewsletterSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let editor = lock_draft(
+        &pool,
+        tenant.id,
+        **user_id,
+        draft_id.into_inner(),
+        newsletters.draft_lock_ttl(),
+    )
+    .await
+    .map_err(e500)?;
+    match editor {
+        Some(editor) => Err(ErrorConflict(being_edited_by(&editor))),
+        None => Ok(HttpResponse::NoContent().finish()),
+    }
+}
+
+pub(crate) fn being_edited_by(editor: &str) -> String {
+    format!("This draft is being edited by {}.", editor)
+}
@@ -0,0 +1,301 @@
+use crate::configuration::BrandingSettings;
+use crate::issue_tags::list_tags;
+use crate::tenancy::{Tenant, TenantId};
+use crate::utils::{e400, e500};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama_actix::Template;
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct CalendarQuery {
+    /// `YYYY-MM`, the current month if missing.
+    month: Option<String>,
+    /// Only the issues tagged with it.
+    tag: Option<String>,
+}
+
+pub struct CalendarIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    /// Some deliveries are still waiting in the queue.
+    pub delivering: bool,
+}
+
+impl CalendarIssue {
+    pub fn status(&self) -> &'static str {
+        if self.delivering {
+            "sending"
+        } else {
+            "sent"
+        }
+    }
+}
+
+pub struct CalendarDay {
+    pub date: NaiveDate,
+    /// Days of the previous and next month pad the first and last weeks.
+    pub in_month: bool,
+    pub issues: Vec<CalendarIssue>,
+}
+
+impl CalendarDay {
+    /// More than one issue went out that day.
+    pub fn has_conflict(&self) -> bool {
+        self.issues.len() > 1
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/newsletter_calendar.html")]
+struct CalendarTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    month: NaiveDate,
+    previous_month: NaiveDate,
+    next_month: NaiveDate,
+    weeks: Vec<Vec<CalendarDay>>,
+    tag: Option<String>,
+    tags: Vec<String>,
+    /// The tag filter as a query parameter, for the links to the other months.
+    tag_query: String,
+}
+
+/// The issues of a month, on a Monday-first grid - all of them, or those with a tag.
+#[tracing::instrument(name = "Show the newsletter calendar", skip_all)]
+pub async fn get_newsletter_calendar(
+    query: web::Query<CalendarQuery>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let month = match &query.month {
+        Some(month) => parse_month(month)
+            .ok_or_else(|| e400("The month must be formatted as YYYY-MM, e.g. 2022-04."))?,
+        None => Utc::now().date_naive().with_day(1).unwrap(),
+    };
+    let (previous_month, next_month) = neighbouring_months(month)
+        .ok_or_else(|| e400("The month is out of the range of the calendar."))?;
+    let tag = query.tag.clone().filter(|t| !t.is_empty());
+    let issues = get_issues_between(&pool, tenant.id, month, next_month, tag.as_deref())
+        .await
+        .map_err(e500)?;
+    let tags = list_tags(&pool, tenant.id).await.map_err(e500)?;
+    let tag_query = tag
+        .as_ref()
+        .map(|t| format!("&tag={}", urlencoding::encode(t)))
+        .unwrap_or_default();
+
+    let body = CalendarTemplate {
+        branding,
+        flash_messages,
+        month,
+        previous_month,
+        next_month,
+        weeks: month_grid(month, next_month, issues),
+        tag,
+        tags,
+        tag_query,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+fn parse_month(s: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{}-01", s), "%Y-%m-%d").ok()
+}
+
+/// The first days of the months before and after `month`, `None` at the edges of the
+/// dates chrono can represent - the grid spills over into the weeks around the month too.
+fn neighbouring_months(month: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    let previous = month.checked_sub_months(Months::new(1))?;
+    let next = month.checked_add_months(Months::new(1))?;
+    next.checked_add_months(Months::new(1))?;
+    Some((previous, next))
+}
+
+/// Lay `issues` out on the weeks covering `month`, from the Monday on or before its first
+/// day to the Sunday on or after its last one - the day before `next_month`.
+fn month_grid(
+    month: NaiveDate,
+    next_month: NaiveDate,
+    issues: Vec<CalendarIssue>,
+) -> Vec<Vec<CalendarDay>> {
+    let last_day = next_month - Duration::days(1);
+    let first = month - Duration::days(month.weekday().num_days_from_monday().into());
+    let last = last_day + Duration::days((6 - last_day.weekday().num_days_from_monday()).into());
+
+    let mut issues = issues.into_iter().peekable();
+    let mut weeks = Vec::new();
+    let mut date = first;
+    while date <= last {
+        let mut week = Vec::with_capacity(7);
+        for _ in 0..7 {
+            let mut day = CalendarDay {
+                date,
+                in_month: date.month() == month.month(),
+                issues: Vec::new(),
+            };
+            while let Some(issue) = issues.next_if(|i| i.published_at.date_naive() == date) {
+                day.issues.push(issue);
+            }
+            week.push(day);
+            date += Duration::days(1);
+        }
+        weeks.push(week);
+    }
+    weeks
+}
+
+#[tracing::instrument(name = "Get the issues published in a month", skip(pool))]
+async fn get_issues_between(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    from: NaiveDate,
+    to: NaiveDate,
+    tag: Option<&str>,
+) -> Result<Vec<CalendarIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        CalendarIssue,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at::timestamptz AS "published_at!",
+            EXISTS (
+                SELECT 1 FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "delivering!"
+        FROM newsletter_issues i
+        WHERE i.published_at::timestamptz >= $1
+            AND i.published_at::timestamptz < $2
+            AND i.tenant_id = $3
+            AND ($4::text IS NULL OR EXISTS (
+                SELECT 1 FROM newsletter_issue_tags t
+                WHERE t.newsletter_issue_id = i.newsletter_issue_id AND t.tag = $4
+            ))
+        ORDER BY i.published_at::timestamptz
+        "#,
+        DateTime::<Utc>::from_naive_utc_and_offset(from.and_hms_opt(0, 0, 0).unwrap(), Utc),
+        DateTime::<Utc>::from_naive_utc_and_offset(to.and_hms_opt(0, 0, 0).unwrap(), Utc),
+        *tenant_id,
+        tag
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the issues of the month")?;
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{month_grid, neighbouring_months, parse_month, CalendarIssue};
+    use chrono::{Datelike, NaiveDate, TimeZone, Utc};
+    use claim::assert_none;
+    use uuid::Uuid;
+
+    fn issue_on(day: u32) -> CalendarIssue {
+        CalendarIssue {
+            newsletter_issue_id: Uuid::new_v4(),
+            title: format!("Issue of the {}", day),
+            published_at: Utc.with_ymd_and_hms(2022, 4, day, 9, 0, 0).unwrap(),
+            delivering: false,
+        }
+    }
+
+    fn may() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2022, 5, 1).unwrap()
+    }
+
+    #[test]
+    fn months_are_formatted_as_year_and_month() {
+        assert_eq!(parse_month("2022-04"), NaiveDate::from_ymd_opt(2022, 4, 1));
+        assert_none!(parse_month("2022-13"));
+        assert_none!(parse_month("April"));
+    }
+
+    #[test]
+    fn the_neighbouring_months_wrap_around_the_year() {
+        let december = NaiveDate::from_ymd_opt(2022, 12, 1).unwrap();
+        let january = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+
+        assert_eq!(
+            neighbouring_months(december),
+            Some((NaiveDate::from_ymd_opt(2022, 11, 1).unwrap(), january))
+        );
+        assert_eq!(
+            neighbouring_months(january),
+            Some((december, NaiveDate::from_ymd_opt(2023, 2, 1).unwrap()))
+        );
+    }
+
+    #[test]
+    fn the_months_at_the_edges_of_the_calendar_have_no_neighbours() {
+        assert_none!(neighbouring_months(NaiveDate::MAX.with_day(1).unwrap()));
+        assert_none!(neighbouring_months(NaiveDate::MIN.with_day(1).unwrap()));
+    }
+
+    #[test]
+    fn the_grid_covers_whole_weeks_starting_on_monday() {
+        // April 2022 starts on a Friday and ends on a Saturday.
+        let april = NaiveDate::from_ymd_opt(2022, 4, 1).unwrap();
+
+        let weeks = month_grid(april, may(), vec![]);
+
+        assert_eq!(weeks.len(), 5);
+        assert!(weeks.iter().all(|w| w.len() == 7));
+        assert_eq!(
+            weeks[0][0].date,
+            NaiveDate::from_ymd_opt(2022, 3, 28).unwrap()
+        );
+        assert!(!weeks[0][0].in_month);
+        assert_eq!(
+            weeks[4][6].date,
+            NaiveDate::from_ymd_opt(2022, 5, 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn a_month_ending_on_sunday_is_not_followed_by_an_empty_week() {
+        // July 2022 ends on a Sunday.
+        let july = NaiveDate::from_ymd_opt(2022, 7, 1).unwrap();
+
+        let weeks = month_grid(july, NaiveDate::from_ymd_opt(2022, 8, 1).unwrap(), vec![]);
+
+        assert_eq!(weeks.len(), 5);
+        assert_eq!(
+            weeks[4][6].date,
+            NaiveDate::from_ymd_opt(2022, 7, 31).unwrap()
+        );
+    }
+
+    #[test]
+    fn issues_land_on_their_day_and_same_day_issues_conflict() {
+        let april = NaiveDate::from_ymd_opt(2022, 4, 1).unwrap();
+
+        let weeks = month_grid(april, may(), vec![issue_on(4), issue_on(12), issue_on(12)]);
+        let days: Vec<_> = weeks.into_iter().flatten().collect();
+
+        let fourth = days
+            .iter()
+            .find(|d| d.date.to_string() == "2022-04-04")
+            .unwrap();
+        assert_eq!(fourth.issues.len(), 1);
+        assert!(!fourth.has_conflict());
+        let twelfth = days
+            .iter()
+            .find(|d| d.date.to_string() == "2022-04-12")
+            .unwrap();
+        assert!(twelfth.has_conflict());
+        assert_eq!(days.iter().map(|d| d.issues.len()).sum::<usize>(), 3);
+    }
+}
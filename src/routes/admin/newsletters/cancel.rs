@@ -0,0 +1,50 @@
+use crate::authentication::UserId;
+use crate::cache::ResponseCache;
+use crate::services::NewsletterService;
+use crate::tenancy::Tenant;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// "Undo send": withdraw an issue whose deliveries have not started yet.
+#[tracing::instrument(
+    name = "Cancel a newsletter issue",
+    skip(pool, user_id, cache, tenant),
+    fields(user_id=%*user_id)
+)]
+pub async fn cancel_newsletter(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+    cache: web::Data<ResponseCache>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let cancelled = NewsletterService::new(&mut transaction, tenant.id)
+        .cancel(newsletter_issue_id.into_inner())
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to cancel a newsletter issue.")
+        .map_err(e500)?;
+
+    if cancelled {
+        cache.invalidate_all();
+        FlashMessage::info("The newsletter issue has been cancelled - no email was sent.").send();
+    } else {
+        FlashMessage::error(
+            "The newsletter issue can no longer be cancelled - its delivery has started.",
+        )
+        .send();
+    }
+    Ok(see_other("/admin/newsletters"))
+}
@@ -0,0 +1,77 @@
+use crate::authentication::UserId;
+use crate::drafts::{add_comment, resolve_comment};
+use crate::routes::admin::newsletters::revisions::draft_gone;
+use crate::tenancy::Tenant;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct CommentFormData {
+    body: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResolveFormData {
+    /// `false` to reopen a resolved comment.
+    resolved: bool,
+}
+
+/// Back to the compose form, where the comments are listed below the draft.
+fn back_to_draft(draft_id: Uuid) -> HttpResponse {
+    see_other(&format!("/admin/newsletters?draft_id={}", draft_id))
+}
+
+/// Leave review feedback on a draft.
+#[tracing::instrument(
+    name = "Comment on a draft",
+    skip(form, pool, user_id, tenant),
+    fields(user_id=%*user_id)
+)]
+pub async fn post_draft_comment(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<CommentFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let body = form.0.body;
+    let body = body.trim();
+    if body.is_empty() {
+        FlashMessage::error("The comment is empty.").send();
+        return Ok(back_to_draft(draft_id));
+    }
+    if !add_comment(&pool, tenant.id, **user_id, draft_id, body)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(draft_gone());
+    }
+    Ok(back_to_draft(draft_id))
+}
+
+/// Mark a comment as addressed, or reopen it.
+#[tracing::instrument(
+    name = "Resolve a comment on a draft",
+    skip(form, pool, user_id, tenant),
+    fields(user_id=%*user_id)
+)]
+pub async fn resolve_draft_comment(
+    path: web::Path<(Uuid, Uuid)>,
+    form: web::Form<ResolveFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (draft_id, comment_id) = path.into_inner();
+    if !resolve_comment(&pool, tenant.id, draft_id, comment_id, form.resolved)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(draft_gone());
+    }
+    Ok(back_to_draft(draft_id))
+}
@@ -0,0 +1,153 @@
+use crate::authentication::can_view_pii;
+use crate::configuration::BrandingSettings;
+use crate::idempotency::IdempotencyKey;
+use crate::pii::Masked;
+use crate::repositories::{sample_recipients, SampleRecipient};
+use crate::services::NewIssue;
+use crate::spam_check::{SpamChecker, SpamReport};
+use crate::stats::{get_seconds_per_delivery, get_subscriber_stats};
+use crate::tenancy::TenantId;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama_actix::Template;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Assumed until the worker has delivered an issue we can measure.
+const DEFAULT_SECONDS_PER_DELIVERY: f64 = 1.0;
+
+pub enum SpamCheck {
+    /// No spam checker is configured.
+    Skipped,
+    Scored(SpamReport),
+    /// The checker could not score the issue. It can still be published.
+    Failed(String),
+}
+
+#[derive(Template)]
+#[template(path = "admin/newsletter_confirm.html")]
+struct ConfirmationTemplate<'a> {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    issue: &'a NewIssue,
+    idempotency_key: &'a str,
+    draft_id: Option<Uuid>,
+    recipients: i64,
+    estimated_duration: String,
+    spam_check: SpamCheck,
+    /// Picked at random among the recipients, masked if the admin cannot see them.
+    sample: Vec<SampleRecipient>,
+    masked: bool,
+}
+
+/// The last step before publishing: what is about to go out, and to how many people.
+/// Confirming posts the issue again, with the same idempotency key and draft.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn render_confirmation(
+    issue: &NewIssue,
+    idempotency_key: &IdempotencyKey,
+    draft_id: Option<Uuid>,
+    pool: &PgPool,
+    tenant_id: TenantId,
+    user_id: Uuid,
+    sample_size: u16,
+    spam_checker: Option<&SpamChecker>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let recipients = get_subscriber_stats(pool, tenant_id)
+        .await
+        .map_err(e500)?
+        .confirmed;
+    let seconds_per_delivery = get_seconds_per_delivery(pool, tenant_id)
+        .await
+        .map_err(e500)?
+        .unwrap_or(DEFAULT_SECONDS_PER_DELIVERY);
+    let masked = !can_view_pii(pool, user_id).await.map_err(e500)?;
+    let mut sample = sample_recipients(pool, tenant_id, issue.segment(), sample_size.into())
+        .await
+        .map_err(e500)?;
+    if masked {
+        sample = sample.into_iter().map(SampleRecipient::masked).collect();
+    }
+    let spam_check = match spam_checker {
+        Some(checker) => match checker
+            .check(issue.title(), issue.html_content(), issue.text_content())
+            .await
+        {
+            Ok(report) => SpamCheck::Scored(report),
+            Err(e) => {
+                tracing::warn!(error.cause_chain = ?e, "The spam check failed");
+                SpamCheck::Failed(e.to_string())
+            }
+        },
+        None => SpamCheck::Skipped,
+    };
+
+    let body = ConfirmationTemplate {
+        branding,
+        flash_messages,
+        issue,
+        idempotency_key: idempotency_key.as_ref(),
+        draft_id,
+        recipients,
+        estimated_duration: describe_duration(estimate_send_duration(
+            recipients,
+            seconds_per_delivery,
+        )),
+        spam_check,
+        sample,
+        masked,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+fn estimate_send_duration(recipients: i64, seconds_per_delivery: f64) -> Duration {
+    Duration::from_secs_f64(recipients.max(0) as f64 * seconds_per_delivery)
+}
+
+fn describe_duration(duration: Duration) -> String {
+    let minutes = (duration.as_secs() + 30) / 60;
+    match minutes {
+        0 => "less than a minute".into(),
+        1 => "about a minute".into(),
+        m if m < 90 => format!("about {} minutes", m),
+        m => format!("about {} hours", (m + 30) / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe_duration, estimate_send_duration};
+    use std::time::Duration;
+
+    #[test]
+    fn the_estimate_grows_with_the_number_of_recipients() {
+        assert_eq!(estimate_send_duration(0, 0.5), Duration::ZERO);
+        assert_eq!(estimate_send_duration(120, 0.5), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn durations_are_rounded_to_a_readable_unit() {
+        assert_eq!(
+            describe_duration(Duration::from_secs(20)),
+            "less than a minute"
+        );
+        assert_eq!(describe_duration(Duration::from_secs(70)), "about a minute");
+        assert_eq!(
+            describe_duration(Duration::from_secs(600)),
+            "about 10 minutes"
+        );
+        assert_eq!(
+            describe_duration(Duration::from_secs(3 * 3600)),
+            "about 3 hours"
+        );
+    }
+}
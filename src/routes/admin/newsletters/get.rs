@@ -1,56 +1,136 @@
+use crate::authentication::UserId;
+use crate::configuration::{BrandingSettings, NewsletterSettings};
+use crate::drafts::{
+    get_draft, get_draft_editor, get_latest_draft, list_comments, list_drafts, Draft, DraftComment,
+    DraftSummary,
+};
+use crate::services::LOCAL_SEND_HOUR;
+use crate::tenancy::{Tenant, TenantId};
+use crate::utils::e500;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use anyhow::Context;
+use askama_actix::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
 use uuid::Uuid;
 
-pub async fn get_newsletter_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let idempotency_key = Uuid::new_v4().to_string();
-    let mut message_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(message_html, "<p><i>{}</i></p>", m.content()).unwrap()
+/// A published issue whose deliveries have not started yet.
+pub struct UndoableIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub deliver_after: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/newsletters.html")]
+struct NewsletterFormTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    /// Fresh on every render: publishing the draft again after editing it is a new request.
+    idempotency_key: Uuid,
+    /// Where the form autosaves its content.
+    draft_id: Uuid,
+    /// The autosaved draft the form picks up from.
+    draft: Option<Draft>,
+    /// The review feedback on the draft, oldest first.
+    comments: Vec<DraftComment>,
+    /// Another admin editing the draft: saving it will fail until they stop.
+    editor: Option<String>,
+    /// The other drafts not published yet.
+    other_drafts: Vec<DraftSummary>,
+    undoable_issues: Vec<UndoableIssue>,
+    local_send_hour: u8,
+}
+
+impl NewsletterFormTemplate {
+    fn title(&self) -> &str {
+        self.draft.as_ref().map_or("", |d| &d.title)
+    }
+
+    fn html_content(&self) -> &str {
+        self.draft.as_ref().map_or("", |d| &d.html_content)
+    }
+
+    fn text_content(&self) -> &str {
+        self.draft.as_ref().map_or("", |d| &d.text_content)
     }
-    HttpResponse::Ok()
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormParameters {
+    /// The draft to open, the latest one of the user otherwise.
+    draft_id: Option<Uuid>,
+}
+
+pub async fn get_newsletter_form(
+    parameters: web::Query<FormParameters>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+    newsletters: web::Data<NewsletterSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lock_ttl = newsletters.draft_lock_ttl();
+    let draft = match parameters.draft_id {
+        Some(draft_id) => get_draft(&pool, tenant.id, draft_id).await,
+        None => get_latest_draft(&pool, **user_id).await,
+    }
+    .map_err(e500)?;
+    let (editor, comments) = match &draft {
+        Some(draft) => (
+            get_draft_editor(&pool, tenant.id, **user_id, draft.draft_id, lock_ttl)
+                .await
+                .map_err(e500)?,
+            list_comments(&pool, tenant.id, draft.draft_id)
+                .await
+                .map_err(e500)?,
+        ),
+        None => (None, Vec::new()),
+    };
+    let mut other_drafts = list_drafts(&pool, tenant.id, lock_ttl)
+        .await
+        .map_err(e500)?;
+    other_drafts.retain(|d| Some(d.draft_id) != draft.as_ref().map(|d| d.draft_id));
+    let undoable_issues = get_undoable_issues(&pool, tenant.id).await.map_err(e500)?;
+    let body = NewsletterFormTemplate {
+        branding,
+        flash_messages,
+        idempotency_key: Uuid::new_v4(),
+        // Keep saving to the restored draft.
+        draft_id: draft.as_ref().map_or_else(Uuid::new_v4, |d| d.draft_id),
+        draft,
+        comments,
+        editor,
+        other_drafts,
+        undoable_issues,
+        local_send_hour: LOCAL_SEND_HOUR,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-<meta http-equiv="content-type" content="text/html; charset=utf-8">
-<title>Send a news letter</title>
-</head>
-<body>
-{message_html}
-<form action="/admin/newsletters" method="post">
-<label>Title
-<input
-type="text"
-placeholder="Enter newsletter title"
-name="title"
->
-</label>
-<br>
-<label>HTML Content
-<input
-type="text"
-placeholder="Enter HTML content"
-name="html"
->
-</label>
-<br>
-<label>Enter text content
-<input
-type="text"
-placeholder="Enter text content"
-name="text"
->
-</label>
-<br>
-<input hidden type="text" name="idempotency_key" value="{idempotency_key}">
-<button type="submit">Publish</button>
-</form>
-<p><a href="/admin/dashboard">&lt;- Back</a></p>
-</body>
-</html>"#,
-        ))
+        .body(body))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_undoable_issues(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Vec<UndoableIssue>, anyhow::Error> {
+    sqlx::query_as!(
+        UndoableIssue,
+        r#"
+        SELECT newsletter_issue_id, title, deliver_after
+        FROM newsletter_issues
+        WHERE deliver_after > now() AND tenant_id = $1
+        ORDER BY deliver_after
+        "#,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the issues which can still be cancelled")
 }
@@ -0,0 +1,55 @@
+use crate::configuration::BrandingSettings;
+use crate::stats::{get_issue_failures, get_issue_stats, DeliveryFailure, IssueStats};
+use crate::tenancy::Tenant;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama_actix::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Template)]
+#[template(path = "admin/newsletter_issue.html")]
+struct IssueTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    issue: IssueStats,
+    failures: Vec<DeliveryFailure>,
+}
+
+/// The delivery of one issue, with the recipients it failed to reach.
+#[tracing::instrument(
+    name = "Show a newsletter issue",
+    skip(pool, tenant, flash_messages, branding)
+)]
+pub async fn get_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue = match get_issue_stats(&pool, tenant.id, newsletter_issue_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let failures = get_issue_failures(&pool, newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    let body = IssueTemplate {
+        branding,
+        flash_messages,
+        issue,
+        failures,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
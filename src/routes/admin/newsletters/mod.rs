@@ -1,5 +1,30 @@
+mod autosave;
+mod calendar;
+mod cancel;
+mod comments;
+mod confirm;
 mod get;
+mod issue;
 mod post;
+mod progress;
+mod report;
+mod resume;
+mod retry;
+mod revisions;
+mod templates;
 
+pub use autosave::{autosave_newsletter_draft, draft_heartbeat};
+pub use calendar::get_newsletter_calendar;
+pub use cancel::cancel_newsletter;
+pub use comments::{post_draft_comment, resolve_draft_comment};
 pub use get::get_newsletter_form;
+pub use issue::get_newsletter_issue;
 pub use post::publish_newsletter;
+pub use progress::get_delivery_progress_events;
+pub use report::get_delivery_report_csv;
+pub use resume::resume_deliveries;
+pub use retry::retry_deliveries;
+pub use revisions::{get_draft_revision_diff, get_draft_revisions, restore_draft_revision};
+pub use templates::{
+    add_issue_template, draft_from_template, get_issue_templates, remove_issue_template,
+};
@@ -1,10 +1,22 @@
 use crate::authentication::UserId;
+use crate::cache::ResponseCache;
+use crate::concurrency_limits::{ConcurrencyLimits, HeavyRoute};
+use crate::configuration::{BrandingSettings, IdempotencySettings, NewsletterSettings};
+use crate::drafts::{delete_draft, get_draft_editor};
+use crate::error::AppError;
 use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::issue_tags::split_tags;
+use crate::repositories::enqueue_issue_deliveries;
+use crate::routes::admin::newsletters::autosave::being_edited_by;
+use crate::routes::admin::newsletters::confirm::render_confirmation;
+use crate::services::{NewIssue, NewsletterService, PublishError, LOCAL_SEND_HOUR};
+use crate::spam_check::SpamChecker;
+use crate::tenancy::Tenant;
 use crate::utils::{e400, e500, see_other};
+use actix_web::error::ErrorConflict;
 use actix_web::{web, HttpResponse};
-use actix_web_flash_messages::FlashMessage;
-use anyhow::Context;
-use sqlx::{PgPool, Postgres, Transaction};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -13,17 +25,38 @@ pub struct FormData {
     html: String,
     text: String,
     idempotency_key: String,
+    /// The draft the compose form autosaved the issue to, discarded once published.
+    #[serde(default)]
+    draft_id: Option<Uuid>,
+    /// Comma-separated.
+    #[serde(default)]
+    tags: String,
+    /// Deliver at `LOCAL_SEND_HOUR` in the timezone of each subscriber.
+    #[serde(default)]
+    send_at_local_time: bool,
+    /// Set by the confirmation screen: until then the issue is only previewed.
+    #[serde(default)]
+    confirmed: bool,
 }
 
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(form, pool, user_id),
+    skip_all,
     fields(user_id=%*user_id)
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn publish_newsletter(
     form: web::Form<FormData>,
     user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
     pool: web::Data<PgPool>,
+    idempotency: web::Data<IdempotencySettings>,
+    newsletters: web::Data<NewsletterSettings>,
+    cache: web::Data<ResponseCache>,
+    spam_checker: web::Data<Option<SpamChecker>>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+    limits: web::Data<ConcurrencyLimits>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let FormData {
@@ -31,9 +64,52 @@ pub async fn publish_newsletter(
         html,
         text,
         idempotency_key,
+        draft_id,
+        tags,
+        send_at_local_time,
+        confirmed,
     } = form.0;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+    if let Some(draft_id) = draft_id {
+        let editor = get_draft_editor(
+            &pool,
+            tenant.id,
+            *user_id,
+            draft_id,
+            newsletters.draft_lock_ttl(),
+        )
+        .await
+        .map_err(e500)?;
+        if let Some(editor) = editor {
+            FlashMessage::error(being_edited_by(&editor)).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    }
+    let mut issue = NewIssue::parse(title, text, html)
+        .and_then(|issue| issue.with_tags(split_tags(&tags)))
+        .map_err(e400)?;
+    if send_at_local_time {
+        issue = issue.sent_at_local_hour(LOCAL_SEND_HOUR).map_err(e400)?;
+    }
+    if !confirmed {
+        return render_confirmation(
+            &issue,
+            &idempotency_key,
+            draft_id,
+            &pool,
+            tenant.id,
+            *user_id,
+            newsletters.recipients_sample_size,
+            spam_checker.as_ref().as_ref(),
+            flash_messages,
+            branding,
+        )
+        .await;
+    }
+    let _permit = limits
+        .try_acquire(tenant.id, HeavyRoute::Publish)
+        .map_err(AppError::from)?;
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id, &idempotency)
         .await
         .map_err(e500)?
     {
@@ -42,76 +118,53 @@ pub async fn publish_newsletter(
             success_message().send();
             return Ok(saved_response);
         }
+        NextAction::StillProcessing => {
+            return Err(ErrorConflict(
+                "This newsletter issue is still being submitted - try again later.",
+            ))
+        }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text, &html)
+    let issue_id = match NewsletterService::new(&mut transaction, tenant.id)
+        .with_undo_window(newsletters.undo_window())
+        .with_daily_quota(newsletters.daily_quota())
+        .publish(&issue)
         .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
-    enqueue_delivery_tasks(&mut transaction, issue_id)
-        .await
-        .context("Failed to enqueue delivery tasks")
-        .map_err(e500)?;
+    {
+        Ok(issue_id) => issue_id,
+        Err(
+            e @ (PublishError::QuotaExceeded { .. } | PublishError::MissingSubscriberName { .. }),
+        ) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+        Err(PublishError::UnexpectedError(e)) => return Err(e500(e)),
+    };
+    if let Some(draft_id) = draft_id {
+        delete_draft(&mut transaction, tenant.id, draft_id)
+            .await
+            .map_err(e500)?;
+    }
 
     let response = see_other("/admin/newsletters");
-    let response = save_response(transaction, &idempotency_key, *user_id, response)
-        .await
-        .map_err(e500)?;
+    let response = save_response(
+        transaction,
+        &idempotency_key,
+        *user_id,
+        response,
+        &idempotency,
+    )
+    .await
+    .map_err(e500)?;
+    cache.invalidate_all();
+    if let Err(e) = enqueue_issue_deliveries(&pool, issue_id).await {
+        // The issue is published: the delivery workers queue what is left.
+        tracing::warn!(error.cause_chain = ?e, "Failed to queue the deliveries of the issue");
+    }
     success_message().send();
     Ok(response)
 }
 
-#[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
-    transaction: &mut Transaction<'_, Postgres>,
-    title: &str,
-    text_content: &str,
-    html_content: &str,
-) -> Result<Uuid, sqlx::Error> {
-    let newsletter_issue_id = Uuid::new_v4();
-    sqlx::query!(
-        r#"
-        INSERT INTO newsletter_issues (
-            newsletter_issue_id,
-            title,
-            text_content,
-            html_content,
-            published_at
-        )
-        VALUES ($1, $2, $3, $4, now())
-        "#,
-        newsletter_issue_id,
-        title,
-        text_content,
-        html_content
-    )
-    .execute(transaction)
-    .await?;
-    Ok(newsletter_issue_id)
-}
-
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id
-    )
-    .execute(transaction)
-    .await?;
-    Ok(())
-}
-
 fn success_message() -> FlashMessage {
     FlashMessage::info("The newsletter issue has been accepted - emails will go out shortly.")
 }
@@ -0,0 +1,81 @@
+use crate::delivery_report::issue_exists;
+use crate::stats::{get_delivery_progress, DeliveryProgress};
+use crate::tenancy::Tenant;
+use crate::utils::e500;
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often the delivery queue is checked while the stream is open.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+enum StreamState {
+    Polling(Option<DeliveryProgress>),
+    Done,
+}
+
+/// Server-sent events with the delivery progress of an issue.
+/// A `progress` event is sent whenever the counts change, and a final `done` event once
+/// the queue holds nothing for the issue - the stream is then closed.
+/// Polls without news send a comment, so that dropped connections are noticed.
+#[tracing::instrument(name = "Stream the delivery progress of an issue", skip(pool, tenant))]
+pub async fn get_delivery_progress_events(
+    newsletter_issue_id: web::Path<Uuid>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    if !issue_exists(&pool, tenant.id, newsletter_issue_id)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let pool = pool.get_ref().clone();
+    let events = futures_util::stream::unfold(StreamState::Polling(None), move |state| {
+        let pool = pool.clone();
+        async move {
+            let last = match state {
+                StreamState::Polling(last) => last,
+                StreamState::Done => return None,
+            };
+            if last.is_some() {
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+            let progress = match get_delivery_progress(&pool, newsletter_issue_id).await {
+                Ok(progress) => progress,
+                Err(e) => return Some((Err(e), StreamState::Done)),
+            };
+            if last.as_ref() == Some(&progress) {
+                return Some((
+                    Ok(web::Bytes::from_static(b": waiting\n\n")),
+                    StreamState::Polling(last),
+                ));
+            }
+            let mut event = sse_event("progress", &progress);
+            if progress.is_done() {
+                event.push_str(&sse_event("done", &progress));
+                return Some((Ok(web::Bytes::from(event)), StreamState::Done));
+            }
+            Some((
+                Ok(web::Bytes::from(event)),
+                StreamState::Polling(Some(progress)),
+            ))
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(events))
+}
+
+fn sse_event(name: &str, progress: &DeliveryProgress) -> String {
+    format!(
+        "event: {}\ndata: {}\n\n",
+        name,
+        serde_json::to_string(progress).expect("Delivery progress is always serializable")
+    )
+}
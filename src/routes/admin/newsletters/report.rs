@@ -0,0 +1,74 @@
+use crate::authentication::{can_view_pii, UserId};
+use crate::concurrency_limits::{ConcurrencyLimits, HeavyRoute};
+use crate::delivery_report::{
+    encode_in_chunks, issue_exists, stream_delivery_report, RecipientReport,
+};
+use crate::error::AppError;
+use crate::pii::Masked;
+use crate::tenancy::Tenant;
+use crate::utils::e500;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[tracing::instrument(
+    name = "Export the delivery report of an issue",
+    skip(pool, tenant, user_id, limits)
+)]
+pub async fn get_delivery_report_csv(
+    newsletter_issue_id: web::Path<Uuid>,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    limits: web::Data<ConcurrencyLimits>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let permit = limits
+        .try_acquire(tenant.id, HeavyRoute::Export)
+        .map_err(AppError::from)?;
+    if !issue_exists(&pool, tenant.id, newsletter_issue_id)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let masked = !can_view_pii(&pool, **user_id).await.map_err(e500)?;
+
+    let header = futures_util::stream::once(async { csv_line(&RecipientReport::CSV_HEADER) });
+    // The export runs until the last row is sent: the permit goes along with the rows.
+    let rows = encode_in_chunks(
+        stream_delivery_report(pool.get_ref().clone(), newsletter_issue_id),
+        move |row, chunk| {
+            if masked {
+                write_csv_line(&row.clone().masked().to_csv_record(), chunk)
+            } else {
+                write_csv_line(&row.to_csv_record(), chunk)
+            }
+        },
+        permit,
+    );
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "{}-report.csv",
+                newsletter_issue_id
+            ))],
+        })
+        .streaming(futures_util::StreamExt::chain(header, rows)))
+}
+
+fn csv_line<T: AsRef<[u8]>>(record: &[T]) -> Result<web::Bytes, anyhow::Error> {
+    let mut line = vec![];
+    write_csv_line(record, &mut line)?;
+    Ok(web::Bytes::from(line))
+}
+
+fn write_csv_line<T: AsRef<[u8]>>(record: &[T], out: &mut Vec<u8>) -> Result<(), anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(record)?;
+    writer.flush()?;
+    Ok(())
+}
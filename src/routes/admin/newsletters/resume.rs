@@ -0,0 +1,48 @@
+use crate::authentication::UserId;
+use crate::services::NewsletterService;
+use crate::tenancy::Tenant;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Let the deliveries of an issue paused because of its spam complaints go out again.
+#[tracing::instrument(
+    name = "Resume the deliveries of a newsletter issue",
+    skip(pool, user_id, tenant),
+    fields(user_id=%*user_id)
+)]
+pub async fn resume_deliveries(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let resumed = NewsletterService::new(&mut transaction, tenant.id)
+        .resume(newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to resume deliveries.")
+        .map_err(e500)?;
+
+    if resumed {
+        FlashMessage::info("The deliveries of the newsletter issue have resumed.").send();
+    } else {
+        FlashMessage::error("The deliveries of the newsletter issue are not paused.").send();
+    }
+    Ok(see_other(&format!(
+        "/admin/newsletters/{}",
+        newsletter_issue_id
+    )))
+}
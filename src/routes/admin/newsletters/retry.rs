@@ -0,0 +1,61 @@
+use crate::authentication::UserId;
+use crate::services::NewsletterService;
+use crate::tenancy::Tenant;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Queue an issue again for the failed recipients selected on its page.
+/// The form repeats `subscriber_email` once per selected recipient.
+#[tracing::instrument(
+    name = "Retry failed deliveries",
+    skip(form, pool, user_id, tenant),
+    fields(user_id=%*user_id)
+)]
+pub async fn retry_deliveries(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let subscriber_emails: Vec<String> = form
+        .into_inner()
+        .into_iter()
+        .filter(|(key, _)| key == "subscriber_email")
+        .map(|(_, email)| email)
+        .collect();
+    let issue_page = format!("/admin/newsletters/{}", newsletter_issue_id);
+    if subscriber_emails.is_empty() {
+        FlashMessage::error("Select the recipients to retry.").send();
+        return Ok(see_other(&issue_page));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let requeued = NewsletterService::new(&mut transaction, tenant.id)
+        .retry_deliveries(newsletter_issue_id, &subscriber_emails)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to retry deliveries.")
+        .map_err(e500)?;
+
+    match requeued {
+        0 => FlashMessage::error("None of the selected deliveries can be retried.").send(),
+        1 => FlashMessage::info("The delivery to 1 recipient will be retried.").send(),
+        n => {
+            FlashMessage::info(format!("The delivery to {} recipients will be retried.", n)).send()
+        }
+    }
+    Ok(see_other(&issue_page))
+}
@@ -0,0 +1,190 @@
+use crate::authentication::UserId;
+use crate::configuration::{BrandingSettings, NewsletterSettings};
+use crate::diff::{diff_lines, DiffRow};
+use crate::drafts::{
+    get_draft, get_draft_editor, get_revision, list_revisions, restore_revision, DraftRevision,
+};
+use crate::routes::admin::newsletters::autosave::being_edited_by;
+use crate::tenancy::Tenant;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama_actix::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Template)]
+#[template(path = "admin/draft_revisions.html")]
+struct RevisionsTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    draft_id: Uuid,
+    revisions: Vec<DraftRevision>,
+}
+
+/// One of the fields of the compose form, compared between two versions of the draft.
+struct FieldDiff<'a> {
+    name: &'static str,
+    rows: Vec<DiffRow<'a>>,
+}
+
+impl FieldDiff<'_> {
+    fn is_unchanged(&self) -> bool {
+        self.rows.iter().all(DiffRow::is_same)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/draft_revision.html")]
+struct RevisionDiffTemplate<'a> {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    draft_id: Uuid,
+    revision: &'a DraftRevision,
+    /// `None` when comparing with the current content of the draft.
+    compare_to: Option<i32>,
+    fields: Vec<FieldDiff<'a>>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DiffParameters {
+    /// The revision to compare with, the current content of the draft otherwise.
+    compare_to: Option<i32>,
+}
+
+pub(crate) fn draft_gone() -> HttpResponse {
+    FlashMessage::error("This draft does not exist - or it was published.").send();
+    see_other("/admin/newsletters")
+}
+
+/// Every save of a draft, latest first.
+#[tracing::instrument(
+    name = "List the revisions of a draft",
+    skip(pool, user_id, tenant, flash_messages, branding),
+    fields(user_id=%*user_id)
+)]
+pub async fn get_draft_revisions(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let revisions = list_revisions(&pool, tenant.id, draft_id)
+        .await
+        .map_err(e500)?;
+    if revisions.is_empty() {
+        return Ok(draft_gone());
+    }
+    let body = RevisionsTemplate {
+        branding,
+        flash_messages,
+        draft_id,
+        revisions,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// A revision side by side with another one, or with the current content of the draft.
+#[tracing::instrument(
+    name = "Compare revisions of a draft",
+    skip(parameters, pool, user_id, tenant, flash_messages, branding),
+    fields(user_id=%*user_id)
+)]
+pub async fn get_draft_revision_diff(
+    path: web::Path<(Uuid, i32)>,
+    parameters: web::Query<DiffParameters>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (draft_id, revision) = path.into_inner();
+    let revision = match get_revision(&pool, tenant.id, draft_id, revision)
+        .await
+        .map_err(e500)?
+    {
+        Some(revision) => revision,
+        None => return Ok(draft_gone()),
+    };
+    let (title, text_content, html_content) = match parameters.compare_to {
+        Some(compare_to) => match get_revision(&pool, tenant.id, draft_id, compare_to)
+            .await
+            .map_err(e500)?
+        {
+            Some(r) => (r.title, r.text_content, r.html_content),
+            None => return Ok(draft_gone()),
+        },
+        None => match get_draft(&pool, tenant.id, draft_id).await.map_err(e500)? {
+            Some(d) => (d.title, d.text_content, d.html_content),
+            None => return Ok(draft_gone()),
+        },
+    };
+    let fields = vec![
+        FieldDiff {
+            name: "Title",
+            rows: diff_lines(&revision.title, &title),
+        },
+        FieldDiff {
+            name: "HTML content",
+            rows: diff_lines(&revision.html_content, &html_content),
+        },
+        FieldDiff {
+            name: "Text content",
+            rows: diff_lines(&revision.text_content, &text_content),
+        },
+    ];
+    let body = RevisionDiffTemplate {
+        branding,
+        flash_messages,
+        draft_id,
+        revision: &revision,
+        compare_to: parameters.compare_to,
+        fields,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Bring the content of an older revision back into the compose form.
+#[tracing::instrument(
+    name = "Restore a revision of a draft",
+    skip(pool, user_id, tenant, newsletters),
+    fields(user_id=%*user_id)
+)]
+pub async fn restore_draft_revision(
+    path: web::Path<(Uuid, i32)>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+    newsletters: web::Data<NewsletterSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (draft_id, revision) = path.into_inner();
+    let lock_ttl = newsletters.draft_lock_ttl();
+    if let Some(editor) = get_draft_editor(&pool, tenant.id, **user_id, draft_id, lock_ttl)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error(being_edited_by(&editor)).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    if !restore_revision(&pool, tenant.id, **user_id, draft_id, revision, lock_ttl)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(draft_gone());
+    }
+    FlashMessage::info(format!("Restored revision {}.", revision)).send();
+    Ok(see_other("/admin/newsletters"))
+}
@@ -0,0 +1,143 @@
+use crate::authentication::UserId;
+use crate::configuration::{BrandingSettings, NewsletterSettings};
+use crate::drafts::{save_draft, DraftContent};
+use crate::issue_templates::{
+    add_template, delete_template, get_template, list_templates, validate_template_name,
+    IssueTemplate,
+};
+use crate::tenancy::Tenant;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama_actix::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Template)]
+#[template(path = "admin/issue_templates.html")]
+struct TemplatesTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    templates: Vec<IssueTemplate>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TemplateFormData {
+    name: String,
+    title: String,
+    html: String,
+    text: String,
+}
+
+/// The issue templates, and a form to add one.
+#[tracing::instrument(name = "Show the issue templates", skip_all)]
+pub async fn get_issue_templates(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let templates = list_templates(&pool, tenant.id).await.map_err(e500)?;
+    let body = TemplatesTemplate {
+        branding,
+        flash_messages,
+        templates,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+#[tracing::instrument(
+    name = "Add an issue template",
+    skip(form, pool, user_id, tenant),
+    fields(user_id=%*user_id, name=%form.name)
+)]
+pub async fn add_issue_template(
+    form: web::Form<TemplateFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = match validate_template_name(&form.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters/templates"));
+        }
+    };
+    if add_template(&pool, tenant.id, name, &form.title, &form.text, &form.html)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info(format!("Added the template \"{}\".", name)).send();
+    } else {
+        FlashMessage::error(format!("There is already a template named \"{}\".", name)).send();
+    }
+    Ok(see_other("/admin/newsletters/templates"))
+}
+
+#[tracing::instrument(
+    name = "Remove an issue template",
+    skip(pool, user_id, tenant),
+    fields(user_id=%*user_id)
+)]
+pub async fn remove_issue_template(
+    template_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, actix_web::Error> {
+    delete_template(&pool, tenant.id, template_id.into_inner())
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("Removed the template.").send();
+    Ok(see_other("/admin/newsletters/templates"))
+}
+
+/// Start a new draft with the content of a template, and open it in the compose form.
+#[tracing::instrument(
+    name = "Draft an issue from a template",
+    skip(pool, user_id, tenant, newsletters),
+    fields(user_id=%*user_id)
+)]
+pub async fn draft_from_template(
+    template_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+    newsletters: web::Data<NewsletterSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let template = match get_template(&pool, tenant.id, template_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(template) => template,
+        None => {
+            FlashMessage::error("The template does not exist or has been removed.").send();
+            return Ok(see_other("/admin/newsletters/templates"));
+        }
+    };
+    let draft_id = Uuid::new_v4();
+    save_draft(
+        &pool,
+        tenant.id,
+        **user_id,
+        draft_id,
+        DraftContent {
+            title: &template.title,
+            text_content: &template.text_content,
+            html_content: &template.html_content,
+        },
+        newsletters.draft_lock_ttl(),
+    )
+    .await
+    .map_err(e500)?;
+    Ok(see_other(&format!(
+        "/admin/newsletters?draft_id={}",
+        draft_id
+    )))
+}
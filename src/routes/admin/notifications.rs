@@ -0,0 +1,156 @@
+use crate::authentication::UserId;
+use crate::configuration::BrandingSettings;
+use crate::domain::SubscriberEmail;
+use crate::notifications::{
+    get_notification_preferences, save_notification_preferences, AdminEvent,
+    NotificationPreferences,
+};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama_actix::Template;
+use sqlx::PgPool;
+
+struct EventOption {
+    value: &'static str,
+    description: &'static str,
+    checked: bool,
+}
+
+#[derive(Template)]
+#[template(path = "admin/notifications.html")]
+struct NotificationsTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    email: String,
+    events: Vec<EventOption>,
+    failure_rate_percent: f64,
+}
+
+pub async fn get_notification_preferences_form(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let preferences = get_notification_preferences(&pool, **user_id)
+        .await
+        .map_err(e500)?;
+    let events = AdminEvent::ALL
+        .into_iter()
+        .map(|event| EventOption {
+            value: event.as_str(),
+            description: event.description(),
+            checked: preferences.events.contains(&event),
+        })
+        .collect();
+    let body = NotificationsTemplate {
+        branding,
+        flash_messages,
+        email: preferences.email.unwrap_or_default(),
+        events,
+        failure_rate_percent: preferences.failure_rate_threshold * 100.0,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// The form repeats `event` once per checked event. Without an address, the admin is no
+/// longer notified of anything.
+#[tracing::instrument(
+    name = "Update the notification preferences of an admin",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn update_notification_preferences(
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match parse_preferences(form.into_inner()) {
+        Ok(preferences) => {
+            save_notification_preferences(&pool, **user_id, &preferences)
+                .await
+                .map_err(e500)?;
+            FlashMessage::info("Your notification preferences have been saved.").send();
+        }
+        Err(e) => FlashMessage::error(e).send(),
+    }
+    Ok(see_other("/admin/notifications"))
+}
+
+fn parse_preferences(form: Vec<(String, String)>) -> Result<NotificationPreferences, String> {
+    let mut preferences = NotificationPreferences::default();
+    for (key, value) in form {
+        match key.as_str() {
+            "email" if !value.trim().is_empty() => {
+                let email = SubscriberEmail::parse(value.trim().to_owned())?;
+                preferences.email = Some(email.as_ref().to_owned());
+            }
+            "event" => preferences.events.push(AdminEvent::parse(&value)?),
+            "failure_rate_percent" => {
+                let percent: f64 = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| "The failure rate must be a number.".to_string())?;
+                if !(0.0..=100.0).contains(&percent) {
+                    return Err("The failure rate must be between 0 and 100%.".into());
+                }
+                preferences.failure_rate_threshold = percent / 100.0;
+            }
+            _ => {}
+        }
+    }
+    Ok(preferences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_preferences;
+    use crate::notifications::AdminEvent;
+    use claim::assert_err;
+
+    fn form(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn every_checked_event_is_kept() {
+        let preferences = parse_preferences(form(&[
+            ("email", "ursula@example.com"),
+            ("event", "send_completed"),
+            ("event", "new_login"),
+            ("failure_rate_percent", "50"),
+        ]))
+        .unwrap();
+
+        assert_eq!(preferences.email.as_deref(), Some("ursula@example.com"));
+        assert_eq!(
+            preferences.events,
+            vec![AdminEvent::SendCompleted, AdminEvent::NewLogin]
+        );
+        assert_eq!(preferences.failure_rate_threshold, 0.5);
+    }
+
+    #[test]
+    fn an_empty_address_turns_notifications_off() {
+        let preferences =
+            parse_preferences(form(&[("email", " "), ("event", "new_login")])).unwrap();
+
+        assert_eq!(preferences.email, None);
+    }
+
+    #[test]
+    fn invalid_fields_are_rejected() {
+        assert_err!(parse_preferences(form(&[("email", "not-an-email")])));
+        assert_err!(parse_preferences(form(&[("event", "new_subscriber")])));
+        assert_err!(parse_preferences(form(&[("failure_rate_percent", "120")])));
+    }
+}
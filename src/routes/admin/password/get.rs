@@ -1,53 +1,53 @@
+use crate::configuration::BrandingSettings;
+use crate::utils::e500;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use askama_actix::Template;
 
-pub async fn change_password_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let mut msg_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+/// Why each of the fields of the change password form was rejected.
+#[derive(Default)]
+pub struct ChangePasswordErrors {
+    pub current_password: Option<String>,
+    pub new_password: Option<String>,
+    pub new_password_check: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/password.html")]
+struct ChangePasswordTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    errors: ChangePasswordErrors,
+}
+
+pub async fn change_password_form(
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    render_form(
+        StatusCode::OK,
+        branding,
+        flash_messages,
+        ChangePasswordErrors::default(),
+    )
+}
+
+pub(super) fn render_form(
+    status: StatusCode,
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    errors: ChangePasswordErrors,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = ChangePasswordTemplate {
+        branding,
+        flash_messages,
+        errors,
     }
-    HttpResponse::Ok()
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::build(status)
         .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-<meta http-equiv="content-type" content="text/html; charset=utf-8">
-<title>Change Password</title>
-</head>
-<body>
-{msg_html}
-<form action="/admin/password" method="post">
-<label>Current password
-<input
-type="password"
-placeholder="Enter current password"
-name="current_password"
->
-</label>
-<br>
-<label>New password
-<input
-type="password"
-placeholder="Enter new password"
-name="new_password"
->
-</label>
-<br>
-<label>Confirm new password
-<input
-type="password"
-placeholder="Type the new password again"
-name="new_password_check"
->
-</label>
-<br>
-<button type="submit">Change password</button>
-</form>
-<p><a href="/admin/dashboard">&lt;- Back</a></p>
-</body>
-</html>"#,
-        ))
+        .body(body))
 }
@@ -1,9 +1,14 @@
 use crate::authentication::{validate_credentials, AuthError, Credentials, UserId};
+use crate::configuration::BrandingSettings;
 use crate::domain::AdminPassword;
 use crate::routes::admin::dashboard::get_username;
+use crate::routes::admin::password::get::{render_form, ChangePasswordErrors};
+use crate::session_state::TypedSession;
+use crate::tenancy::Tenant;
 use crate::utils::{e500, see_other};
+use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse};
-use actix_web_flash_messages::FlashMessage;
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 
@@ -14,45 +19,65 @@ pub struct FormData {
     new_password_check: Secret<String>,
 }
 
+/// Invalid submissions get the form back, with the errors next to the fields they are about.
+/// On success every other session of the user is logged out.
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+    tenant: web::ReqData<Tenant>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
-    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
-        FlashMessage::error(
-            "You entered two different new passwords - the field values must match.",
-        )
-        .send();
-        return Ok(see_other("/admin/password"));
-    }
+    let FormData {
+        current_password,
+        new_password,
+        new_password_check,
+    } = form.0;
+    let mut errors = ChangePasswordErrors::default();
 
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
-
     let credentials = Credentials {
         username,
-        password: form.0.current_password,
+        password: current_password.clone(),
     };
-
-    if let Err(e) = validate_credentials(credentials, &pool).await {
-        return match e {
+    if let Err(e) = validate_credentials(credentials, tenant.id, &pool).await {
+        match e {
             AuthError::InvalidCredentials(_) => {
-                FlashMessage::error("The current password is incorrect.").send();
-                Ok(see_other("/admin/password"))
+                errors.current_password = Some("The current password is incorrect.".into())
             }
-            AuthError::UnexpectedError(_) => Err(e500(e)),
-        };
+            AuthError::UnexpectedError(_) => return Err(e500(e)),
+        }
+    }
+
+    if let Err(e) = AdminPassword::parse(new_password.clone()) {
+        errors.new_password = Some(e);
+    } else if new_password.expose_secret() == current_password.expose_secret() {
+        errors.new_password =
+            Some("The new password must be different from the current one.".into());
+    }
+
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        errors.new_password_check =
+            Some("You entered two different new passwords - the field values must match.".into());
     }
 
-    if let Err(e) = AdminPassword::parse(form.0.new_password.clone()) {
-        FlashMessage::error(e).send();
-        return Ok(see_other("/admin/password"));
+    if errors.current_password.is_some()
+        || errors.new_password.is_some()
+        || errors.new_password_check.is_some()
+    {
+        return render_form(StatusCode::BAD_REQUEST, branding, flash_messages, errors);
     }
 
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
+    let session_version = crate::authentication::change_password(*user_id, new_password, &pool)
         .await
         .map_err(e500)?;
+    session.renew();
+    session
+        .insert_session_version(session_version)
+        .map_err(e500)?;
     FlashMessage::info("Your password has been changed.").send();
     Ok(see_other("/admin/password"))
 }
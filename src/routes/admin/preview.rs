@@ -0,0 +1,143 @@
+use crate::configuration::BrandingSettings;
+use crate::i18n::Locale;
+use crate::pii::{PiiCipher, PiiEncryption};
+use crate::routes::preferences::render_preferences;
+use crate::routes::subscriptions_confirm::render_confirmed;
+use crate::routes::unsubscribe::render_unsubscribe_form;
+use crate::signing::UrlSigner;
+use crate::tenancy::{Tenant, TenantId};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use askama_actix::Template;
+use sqlx::PgPool;
+
+/// Stands in for the subscription token in the previewed pages: submitting their forms
+/// is rejected, rather than acting on the real subscriber.
+const PREVIEW_TOKEN: &str = "preview";
+
+/// The subscriber-facing pages admins can preview.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewPage {
+    Confirmation,
+    Preferences,
+    Unsubscribe,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreviewParameters {
+    email: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/preview.html")]
+struct PreviewTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+}
+
+/// Pick a subscriber - typically a test one - and a page to see as they would.
+pub async fn preview_form(
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = PreviewTemplate {
+        branding,
+        flash_messages,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Render a subscriber-facing page with the data and locale of a subscriber, without
+/// their token: nothing is confirmed, saved or unsubscribed.
+#[tracing::instrument(
+    name = "Preview a subscriber-facing page",
+    skip(parameters, pool, pii, signer, branding, locale, tenant),
+    fields(subscriber_email = %parameters.email)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn preview_page(
+    page: web::Path<PreviewPage>,
+    parameters: web::Query<PreviewParameters>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiEncryption>,
+    signer: web::Data<UrlSigner>,
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber =
+        match find_preview_subscriber(&pool, tenant.id, parameters.email.trim(), pii.cipher())
+            .await
+            .map_err(e500)?
+        {
+            Some(subscriber) => subscriber,
+            None => {
+                FlashMessage::error("No subscriber has this email address.").send();
+                return Ok(see_other("/admin/preview"));
+            }
+        };
+    // Stored locales were supported when stored: one we dropped since falls back.
+    let locale = subscriber
+        .locale
+        .and_then(|l| Locale::parse(&l).ok())
+        .unwrap_or(locale);
+    let response = match page.into_inner() {
+        PreviewPage::Confirmation => render_confirmed(branding, locale),
+        PreviewPage::Preferences => {
+            render_preferences(
+                &pool,
+                branding,
+                locale,
+                PREVIEW_TOKEN,
+                subscriber.timezone,
+                false,
+            )
+            .await
+        }
+        PreviewPage::Unsubscribe => {
+            render_unsubscribe_form(branding, locale, PREVIEW_TOKEN, &signer)
+        }
+    };
+    response.map_err(e500)
+}
+
+struct PreviewSubscriber {
+    locale: Option<String>,
+    timezone: Option<String>,
+}
+
+#[tracing::instrument(skip(pool, cipher))]
+async fn find_preview_subscriber(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    email: &str,
+    cipher: Option<&PiiCipher>,
+) -> Result<Option<PreviewSubscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        PreviewSubscriber,
+        r#"
+        SELECT locale, timezone
+        FROM subscriptions
+        WHERE
+            tenant_id = $1 AND
+            (email = $2 OR email_blind_index = $3) AND
+            deleted_at IS NULL
+        LIMIT 1
+        "#,
+        *tenant_id,
+        email,
+        cipher.map(|c| c.blind_index(email))
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to find the subscriber to preview the pages of")?;
+    Ok(subscriber)
+}
@@ -0,0 +1,43 @@
+use crate::configuration::BrandingSettings;
+use crate::query_metrics::{query_metrics, QueryStats};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama_actix::Template;
+use std::time::Duration;
+
+/// How many of the queries the database spent the most time on are listed.
+const LISTED_QUERIES: usize = 50;
+
+#[derive(Template)]
+#[template(path = "admin/queries.html")]
+struct QueriesTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    queries: Vec<QueryStats>,
+}
+
+impl QueriesTemplate {
+    fn milliseconds(&self, duration: Duration) -> String {
+        format!("{:.1}", duration.as_secs_f64() * 1000.0)
+    }
+}
+
+/// The latency of the queries run by this process since it started.
+#[tracing::instrument(name = "Show the query metrics", skip_all)]
+pub async fn get_query_metrics(
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = QueriesTemplate {
+        branding,
+        flash_messages,
+        queries: query_metrics().top_queries(LISTED_QUERIES),
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
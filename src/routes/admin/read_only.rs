@@ -0,0 +1,43 @@
+use crate::authentication::UserId;
+use crate::read_only::{set_read_only, ForcedReadOnly};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+
+#[derive(serde::Deserialize)]
+pub struct ReadOnlyFormData {
+    enabled: bool,
+}
+
+#[tracing::instrument(
+    name = "Switch read-only mode",
+    skip(form, pool, forced, user_id),
+    fields(user_id=%*user_id, enabled=%form.enabled)
+)]
+pub async fn switch_read_only(
+    form: web::Form<ReadOnlyFormData>,
+    pool: web::Data<sqlx::PgPool>,
+    forced: web::Data<ForcedReadOnly>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let enabled = form.0.enabled;
+    if !enabled && forced.0 {
+        FlashMessage::error(
+            "Read-only mode is set in the configuration: restart without it to switch it off.",
+        )
+        .send();
+        return Ok(see_other("/admin/tools"));
+    }
+    set_read_only(&pool, enabled, **user_id)
+        .await
+        .map_err(e500)?;
+    if enabled {
+        tracing::warn!("Read-only mode has been switched on");
+        FlashMessage::info("Read-only mode is on: writes are rejected and the workers paused.")
+            .send();
+    } else {
+        tracing::warn!("Read-only mode has been switched off");
+        FlashMessage::info("Read-only mode is off.").send();
+    }
+    Ok(see_other("/admin/tools"))
+}
@@ -0,0 +1,99 @@
+use crate::authentication::UserId;
+use crate::configuration::BrandingSettings;
+use crate::redirects::{
+    delete_redirect, list_redirects, save_redirect, validate_redirect, Redirect,
+};
+use crate::tenancy::Tenant;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama_actix::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "admin/redirects.html")]
+struct RedirectsTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    redirects: Vec<Redirect>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SaveRedirectFormData {
+    from_path: String,
+    to_location: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeleteRedirectFormData {
+    from_path: String,
+}
+
+/// The paths which moved, and where to.
+#[tracing::instrument(name = "Show the redirects", skip_all)]
+pub async fn get_redirects(
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let redirects = list_redirects(&pool, tenant.id).await.map_err(e500)?;
+    let body = RedirectsTemplate {
+        branding,
+        flash_messages,
+        redirects,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+#[tracing::instrument(
+    name = "Add a redirect",
+    skip(form, pool, tenant, user_id),
+    fields(user_id=%*user_id, from_path=%form.from_path)
+)]
+pub async fn add_redirect(
+    form: web::Form<SaveRedirectFormData>,
+    pool: web::Data<PgPool>,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (from_path, to_location) = match validate_redirect(&form.from_path, &form.to_location) {
+        Ok(redirect) => redirect,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/redirects"));
+        }
+    };
+    save_redirect(&pool, tenant.id, &from_path, &to_location)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!("{} now redirects to {}.", from_path, to_location)).send();
+    Ok(see_other("/admin/redirects"))
+}
+
+#[tracing::instrument(
+    name = "Remove a redirect",
+    skip(form, pool, tenant, user_id),
+    fields(user_id=%*user_id, from_path=%form.from_path)
+)]
+pub async fn remove_redirect(
+    form: web::Form<DeleteRedirectFormData>,
+    pool: web::Data<PgPool>,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if delete_redirect(&pool, tenant.id, &form.from_path)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info(format!("{} no longer redirects.", form.from_path)).send();
+    } else {
+        FlashMessage::error("The redirect does not exist or has already been removed.").send();
+    }
+    Ok(see_other("/admin/redirects"))
+}
@@ -0,0 +1,69 @@
+use crate::authentication::{can_view_pii, UserId};
+use crate::configuration::BrandingSettings;
+use crate::pii::{Masked, PiiEncryption};
+use crate::search::{search, SearchQuery, SearchResults, SubscriberHit};
+use crate::tenancy::Tenant;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama_actix::Template;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct SearchParameters {
+    #[serde(default)]
+    q: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/search.html")]
+struct SearchTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    q: String,
+    /// `None` until something is searched for.
+    results: Option<SearchResults>,
+}
+
+/// Subscribers and issues matching every word of `q`. The subscribers are masked for the
+/// admins who cannot see their personal data - they are still found by it.
+#[tracing::instrument(name = "Search from the admin panel", skip_all, fields(q = %parameters.q))]
+pub async fn admin_search(
+    parameters: web::Query<SearchParameters>,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiEncryption>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let q = parameters.into_inner().q;
+    let mut results = match SearchQuery::parse(&q) {
+        Some(query) => Some(
+            search(&pool, tenant.id, &query, &q, pii.cipher())
+                .await
+                .map_err(e500)?,
+        ),
+        None => None,
+    };
+    if let Some(results) = results.as_mut() {
+        if !can_view_pii(&pool, **user_id).await.map_err(e500)? {
+            results.subscribers = std::mem::take(&mut results.subscribers)
+                .into_iter()
+                .map(SubscriberHit::masked)
+                .collect();
+        }
+    }
+    let body = SearchTemplate {
+        branding,
+        flash_messages,
+        q,
+        results,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
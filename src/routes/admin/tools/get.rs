@@ -0,0 +1,60 @@
+use crate::configuration::BrandingSettings;
+use crate::maintenance::{recent_maintenance_runs, MaintenanceRun, MaintenanceTask};
+use crate::read_only::{read_only_status, ForcedReadOnly, ReadOnlyStatus};
+use crate::tenancy::Tenant;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama_actix::Template;
+use sqlx::PgPool;
+
+/// How many of the latest maintenance jobs are listed.
+const RECENT_RUNS: i64 = 20;
+
+#[derive(Template)]
+#[template(path = "admin/tools.html")]
+struct ToolsTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    tasks: [MaintenanceTask; 4],
+    runs: Vec<MaintenanceRun>,
+    read_only: Option<ReadOnlyStatus>,
+}
+
+impl ToolsTemplate {
+    fn is_pending(&self, task: &MaintenanceTask) -> bool {
+        self.runs
+            .iter()
+            .any(|run| run.task.as_ref() == Some(task) && run.is_pending())
+    }
+}
+
+/// The maintenance tasks and how the latest ones went, and the read-only mode switch.
+#[tracing::instrument(name = "Show the admin tools", skip_all)]
+pub async fn get_tools(
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+    forced_read_only: web::Data<ForcedReadOnly>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let runs = recent_maintenance_runs(&pool, tenant.id, RECENT_RUNS)
+        .await
+        .map_err(e500)?;
+    let read_only = read_only_status(&pool, **forced_read_only)
+        .await
+        .map_err(e500)?;
+    let body = ToolsTemplate {
+        branding,
+        flash_messages,
+        tasks: MaintenanceTask::ALL,
+        runs,
+        read_only,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
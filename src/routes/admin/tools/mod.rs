@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::get_tools;
+pub use post::run_tool;
@@ -0,0 +1,57 @@
+use crate::authentication::UserId;
+use crate::maintenance::{start_maintenance_task, MaintenanceTask};
+use crate::tenancy::Tenant;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    task: String,
+    /// The tasks lock or slow down tables, so they have to be confirmed.
+    #[serde(default)]
+    confirm: bool,
+}
+
+#[tracing::instrument(
+    name = "Start a maintenance task",
+    skip(form, pool, tenant, user_id),
+    fields(user_id=%*user_id, task=%form.task)
+)]
+pub async fn run_tool(
+    form: web::Form<FormData>,
+    pool: web::Data<sqlx::PgPool>,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { task, confirm } = form.0;
+    let task = match MaintenanceTask::parse(&task) {
+        Ok(task) => task,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/tools"));
+        }
+    };
+    if !confirm {
+        FlashMessage::error(format!(
+            "Tick the confirmation box to {}.",
+            task.label().to_lowercase()
+        ))
+        .send();
+        return Ok(see_other("/admin/tools"));
+    }
+    if start_maintenance_task(&pool, tenant.id, task)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        FlashMessage::error(format!("{} is already in progress.", task.label())).send();
+        return Ok(see_other("/admin/tools"));
+    }
+    FlashMessage::info(format!(
+        "{} has been started. Its progress is listed below.",
+        task.label()
+    ))
+    .send();
+    Ok(see_other("/admin/tools"))
+}
@@ -0,0 +1,122 @@
+use crate::authentication::{can_view_pii, UserId};
+use crate::configuration::{BrandingSettings, SubscriberSettings};
+use crate::domain::NewSubscriber;
+use crate::email_client::EmailClient;
+use crate::i18n::Locale;
+use crate::pii::{mask_email, Masked, PiiEncryption};
+use crate::repositories::PostgresSubscriberRepository;
+use crate::services::SubscriptionService;
+use crate::startup::PublicBaseUrl;
+use crate::tenancy::Tenant;
+use crate::utils::{e500, see_other};
+use crate::waitlist::{find_waitlist_entry, list_waitlist, remove_waitlist_entry, WaitlistEntry};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama_actix::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Template)]
+#[template(path = "admin/waitlist.html")]
+struct WaitlistTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    entries: Vec<WaitlistEntry>,
+    max_subscribers: u64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PromoteFormData {
+    entry_id: Uuid,
+}
+
+/// Who signed up while the list was full, masked for the admins who cannot see the
+/// personal data of subscribers.
+#[tracing::instrument(name = "Show the waitlist", skip_all)]
+pub async fn get_waitlist(
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiEncryption>,
+    settings: web::Data<SubscriberSettings>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut entries = list_waitlist(&pool, tenant.id, pii.cipher())
+        .await
+        .map_err(e500)?;
+    if !can_view_pii(&pool, **user_id).await.map_err(e500)? {
+        entries = entries.into_iter().map(WaitlistEntry::masked).collect();
+    }
+    let body = WaitlistTemplate {
+        branding,
+        flash_messages,
+        entries,
+        max_subscribers: settings.max_subscribers,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Turn an entry into a pending subscription, sending the confirmation email in the
+/// locale chosen at signup - regardless of the cap, admins decide.
+#[tracing::instrument(
+    name = "Promote a waitlist entry",
+    skip(form, pool, pii, email_client, base_url, settings, locale, tenant, user_id),
+    fields(user_id=%*user_id, entry_id=%form.entry_id)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn promote_waitlist_entry(
+    form: web::Form<PromoteFormData>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiEncryption>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<PublicBaseUrl>,
+    settings: web::Data<SubscriberSettings>,
+    locale: Locale,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let entry = match find_waitlist_entry(&pool, tenant.id, form.entry_id, pii.cipher())
+        .await
+        .map_err(e500)?
+    {
+        Some(entry) => entry,
+        None => {
+            FlashMessage::error("This address is not on the waitlist anymore.").send();
+            return Ok(see_other("/admin/waitlist"));
+        }
+    };
+    let promoted = if can_view_pii(&pool, **user_id).await.map_err(e500)? {
+        entry.email.clone()
+    } else {
+        mask_email(&entry.email)
+    };
+    // Entries were validated when they joined: only a locale dropped since can fail.
+    let new_subscriber = NewSubscriber::parse(
+        entry.name,
+        entry.email,
+        entry.locale.filter(|l| Locale::parse(l).is_ok()),
+        settings.max_name_length,
+        false,
+    )
+    .map_err(e500)?;
+    SubscriptionService::new(
+        &PostgresSubscriberRepository::new(&pool, tenant.id).with_cipher(pii.cipher()),
+        email_client.get_ref(),
+        tenant.public_base_url(&base_url.0),
+    )
+    .with_sender(tenant.sender_email.as_ref())
+    .subscribe(new_subscriber, &locale)
+    .await
+    .map_err(e500)?;
+    remove_waitlist_entry(&pool, tenant.id, form.entry_id)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!("{} has been sent a confirmation link.", promoted)).send();
+    Ok(see_other("/admin/waitlist"))
+}
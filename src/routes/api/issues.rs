@@ -0,0 +1,329 @@
+use crate::cache::ResponseCache;
+use crate::pagination::{next_cursor, Cursor, PageQuery};
+use crate::repositories::decompress_content;
+use crate::routes::api::ApiError;
+use crate::tenancy::{Tenant, TenantId};
+use actix_web::http::header::{CacheControl, CacheDirective, ETag, EntityTag, Header, IfNoneMatch};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const DEFAULT_PER_PAGE: i64 = 20;
+const MAX_PER_PAGE: i64 = 100;
+
+#[derive(serde::Serialize)]
+pub struct PublishedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    html: String,
+    text: String,
+}
+
+struct PublishedIssueRow {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    html_content: Option<String>,
+    html_content_zstd: Option<Vec<u8>>,
+    text_content: Option<String>,
+    text_content_zstd: Option<Vec<u8>>,
+}
+
+impl TryFrom<PublishedIssueRow> for PublishedIssue {
+    type Error = anyhow::Error;
+
+    fn try_from(row: PublishedIssueRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            newsletter_issue_id: row.newsletter_issue_id,
+            title: row.title,
+            published_at: row.published_at,
+            html: decompress_content(row.html_content_zstd, row.html_content)?,
+            text: decompress_content(row.text_content_zstd, row.text_content)?,
+        })
+    }
+}
+
+/// `next_cursor` is `null` once the end of the archive is reached.
+#[derive(serde::Serialize)]
+pub struct PublishedIssuePage {
+    issues: Vec<PublishedIssue>,
+    total_issues: i64,
+    next_cursor: Option<String>,
+}
+
+/// A page of the archive as the first version of the API served it, for the clients
+/// still paging with `page` and `per_page`.
+#[derive(serde::Serialize)]
+pub struct NumberedIssuePage {
+    issues: Vec<PublishedIssue>,
+    page: i64,
+    per_page: i64,
+    total_issues: i64,
+    total_pages: i64,
+}
+
+/// `?cursor=...&limit=...`, or the former `?page=...&per_page=...` - all optional.
+#[derive(serde::Deserialize, Debug)]
+pub struct IssuesQuery {
+    cursor: Option<String>,
+    limit: Option<i64>,
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+impl IssuesQuery {
+    /// `Some((page, per_page))` if the query pages the former way.
+    fn numbered_page(&self) -> Result<Option<(i64, i64)>, ApiError> {
+        if self.page.is_none() && self.per_page.is_none() {
+            return Ok(None);
+        }
+        if self.cursor.is_some() || self.limit.is_some() {
+            return Err(ApiError::ValidationError(
+                "`page` and `per_page` cannot be combined with `cursor` and `limit`.".into(),
+            ));
+        }
+        let page = self.page.unwrap_or(1);
+        let per_page = self.per_page.unwrap_or(DEFAULT_PER_PAGE);
+        if page < 1 {
+            return Err(ApiError::ValidationError(
+                "`page` must be greater than zero.".into(),
+            ));
+        }
+        if !(1..=MAX_PER_PAGE).contains(&per_page) {
+            return Err(ApiError::ValidationError(format!(
+                "`per_page` must be between 1 and {}.",
+                MAX_PER_PAGE
+            )));
+        }
+        Ok(Some((page, per_page)))
+    }
+}
+
+/// The newsletter archive, most recent issues first.
+#[tracing::instrument(
+    name = "List published issues through the API",
+    skip(request, tenant, pool, cache)
+)]
+pub async fn list_issues(
+    request: HttpRequest,
+    query: web::Query<IssuesQuery>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<ResponseCache>,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    if let Some((page, per_page)) = query.numbered_page()? {
+        let key = format!(
+            "{}/api/issues?page={}&per_page={}",
+            tenant.id, page, per_page
+        );
+        let body = cache
+            .get_or_try_insert_with(key, || async {
+                let page = get_numbered_issue_page(&pool, tenant.id, page, per_page).await?;
+                serde_json::to_string(&page).context("Failed to serialize the response body")
+            })
+            .await?;
+        return json_with_etag(&request, body);
+    }
+    let (cursor, limit) = PageQuery::new(query.cursor, query.limit)
+        .parse()
+        .map_err(ApiError::ValidationError)?;
+    let key = format!(
+        "{}/api/issues?cursor={}&limit={}",
+        tenant.id,
+        cursor.as_ref().map(Cursor::encode).unwrap_or_default(),
+        limit
+    );
+    let body = cache
+        .get_or_try_insert_with(key, || async {
+            let page = get_issue_page(&pool, tenant.id, cursor.as_ref(), limit).await?;
+            serde_json::to_string(&page).context("Failed to serialize the response body")
+        })
+        .await?;
+    json_with_etag(&request, body)
+}
+
+async fn count_issues(pool: &PgPool, tenant_id: TenantId) -> Result<i64, anyhow::Error> {
+    let total_issues = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM newsletter_issues
+        WHERE tenant_id = $1 AND deliver_after <= now()
+        "#,
+        *tenant_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count published issues")?
+    .count;
+    Ok(total_issues)
+}
+
+async fn get_numbered_issue_page(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    page: i64,
+    per_page: i64,
+) -> Result<NumberedIssuePage, anyhow::Error> {
+    let total_issues = count_issues(pool, tenant_id).await?;
+    let issues = sqlx::query_as!(
+        PublishedIssueRow,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            published_at::timestamptz AS "published_at!",
+            html_content,
+            html_content_zstd,
+            text_content,
+            text_content_zstd
+        FROM newsletter_issues
+        WHERE tenant_id = $3 AND deliver_after <= now()
+        ORDER BY published_at::timestamptz DESC, newsletter_issue_id DESC
+        LIMIT $1 OFFSET $2
+        "#,
+        per_page,
+        (page - 1) * per_page,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve published issues")?
+    .into_iter()
+    .map(PublishedIssue::try_from)
+    .collect::<Result<_, _>>()?;
+
+    Ok(NumberedIssuePage {
+        issues,
+        page,
+        per_page,
+        total_issues,
+        total_pages: (total_issues + per_page - 1) / per_page,
+    })
+}
+
+async fn get_issue_page(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    cursor: Option<&Cursor>,
+    limit: i64,
+) -> Result<PublishedIssuePage, anyhow::Error> {
+    let (published_at, id) = Cursor::unzip(cursor);
+    let total_issues = count_issues(pool, tenant_id).await?;
+    let issues: Vec<PublishedIssue> = sqlx::query_as!(
+        PublishedIssueRow,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            published_at::timestamptz AS "published_at!",
+            html_content,
+            html_content_zstd,
+            text_content,
+            text_content_zstd
+        FROM newsletter_issues
+        WHERE tenant_id = $4
+            AND deliver_after <= now()
+            AND ($1::timestamptz IS NULL
+                OR (published_at::timestamptz, newsletter_issue_id) < ($1, $2))
+        ORDER BY published_at::timestamptz DESC, newsletter_issue_id DESC
+        LIMIT $3
+        "#,
+        published_at,
+        id,
+        limit,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve published issues")?
+    .into_iter()
+    .map(PublishedIssue::try_from)
+    .collect::<Result<_, _>>()?;
+
+    let next_cursor = next_cursor(&issues, limit, |i| Cursor {
+        created_at: i.published_at,
+        id: i.newsletter_issue_id,
+    });
+    Ok(PublishedIssuePage {
+        issues,
+        total_issues,
+        next_cursor,
+    })
+}
+
+#[tracing::instrument(
+    name = "Get a published issue through the API",
+    skip(request, tenant, pool, cache)
+)]
+pub async fn get_issue(
+    request: HttpRequest,
+    newsletter_issue_id: web::Path<Uuid>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<ResponseCache>,
+) -> Result<HttpResponse, ApiError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let key = format!("{}/api/issues/{}", tenant.id, newsletter_issue_id);
+    if let Some(body) = cache.get(&key) {
+        return json_with_etag(&request, body);
+    }
+    let issue = sqlx::query_as!(
+        PublishedIssueRow,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            published_at::timestamptz AS "published_at!",
+            html_content,
+            html_content_zstd,
+            text_content,
+            text_content_zstd
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND tenant_id = $2 AND deliver_after <= now()
+        "#,
+        newsletter_issue_id,
+        *tenant.id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a published issue")?
+    .ok_or_else(|| ApiError::NotFound("There is no newsletter issue with this id.".into()))?;
+    let issue = PublishedIssue::try_from(issue)?;
+    let body = serde_json::to_string(&issue).context("Failed to serialize the response body")?;
+    cache.insert(key, body.clone());
+
+    json_with_etag(&request, body)
+}
+
+/// Tag the serialized `body` with a hash of its content.
+/// Clients presenting a matching `If-None-Match` get an empty `304 Not Modified` instead.
+fn json_with_etag(request: &HttpRequest, body: String) -> Result<HttpResponse, ApiError> {
+    let etag = EntityTag::new_strong(format!("{:x}", Sha256::digest(&body)));
+    let not_modified = match IfNoneMatch::parse(request) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        Err(_) => false,
+    };
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(ETag(etag))
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::NoCache,
+        ]));
+    if not_modified {
+        Ok(response.finish())
+    } else {
+        Ok(response.content_type("application/json").body(body))
+    }
+}
@@ -0,0 +1,112 @@
+use crate::delivery_report::issue_exists;
+use crate::import::ImportedSubscriber;
+use crate::jobs::{enqueue_job, get_job, JobPayload};
+use crate::routes::api::ApiError;
+use crate::tenancy::{Tenant, TenantId};
+use actix_web::http::header::LOCATION;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct BulkImportBody {
+    subscribers: Vec<ImportedSubscriber>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct BulkDeleteBody {
+    subscriber_ids: Vec<Uuid>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ReenqueueBody {
+    newsletter_issue_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Start a bulk import job through the API",
+    skip(body, tenant, pool)
+)]
+pub async fn start_bulk_import(
+    body: web::Json<BulkImportBody>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let BulkImportBody { subscribers } = body.0;
+    if subscribers.is_empty() {
+        return Err(ApiError::ValidationError(
+            "There are no subscribers to import.".into(),
+        ));
+    }
+    start_job(&pool, tenant.id, JobPayload::BulkImport { subscribers }).await
+}
+
+#[tracing::instrument(
+    name = "Start a bulk delete job through the API",
+    skip(body, tenant, pool)
+)]
+pub async fn start_bulk_delete(
+    body: web::Json<BulkDeleteBody>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let BulkDeleteBody { subscriber_ids } = body.0;
+    if subscriber_ids.is_empty() {
+        return Err(ApiError::ValidationError(
+            "There are no subscribers to delete.".into(),
+        ));
+    }
+    start_job(&pool, tenant.id, JobPayload::BulkDelete { subscriber_ids }).await
+}
+
+#[tracing::instrument(
+    name = "Start a re-enqueue job through the API",
+    skip(body, tenant, pool)
+)]
+pub async fn start_reenqueue(
+    body: web::Json<ReenqueueBody>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let newsletter_issue_id = body.0.newsletter_issue_id;
+    if !issue_exists(&pool, tenant.id, newsletter_issue_id).await? {
+        return Err(ApiError::NotFound(
+            "There is no newsletter issue with this id.".into(),
+        ));
+    }
+    start_job(
+        &pool,
+        tenant.id,
+        JobPayload::Reenqueue {
+            newsletter_issue_id,
+        },
+    )
+    .await
+}
+
+#[tracing::instrument(name = "Get a job status through the API", skip(tenant, pool))]
+pub async fn get_job_status(
+    job_id: web::Path<Uuid>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let job = get_job(&pool, tenant.id, job_id.into_inner())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("There is no job with this id.".into()))?;
+    Ok(HttpResponse::Ok().json(job))
+}
+
+async fn start_job(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    payload: JobPayload,
+) -> Result<HttpResponse, ApiError> {
+    let job_id = enqueue_job(pool, tenant_id, &payload).await?;
+    let job = get_job(pool, tenant_id, job_id)
+        .await?
+        .context("The job we just enqueued could not be found")?;
+    Ok(HttpResponse::Accepted()
+        .insert_header((LOCATION, format!("/api/v1/jobs/{}", job_id)))
+        .json(job))
+}
@@ -0,0 +1,13 @@
+mod error;
+mod issues;
+mod jobs;
+mod newsletters;
+mod recent;
+mod subscribers;
+
+pub use error::{json_config, path_config, query_config, ApiError};
+pub use issues::*;
+pub use jobs::*;
+pub use newsletters::*;
+pub use recent::*;
+pub use subscribers::*;
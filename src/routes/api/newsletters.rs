@@ -0,0 +1,339 @@
+use crate::authentication::{can_view_pii, UserId};
+use crate::cache::ResponseCache;
+use crate::concurrency_limits::{ConcurrencyLimits, HeavyRoute};
+use crate::configuration::{EmailFooterSettings, IdempotencySettings, NewsletterSettings};
+use crate::delivery_report::{encode_in_chunks, issue_exists, stream_delivery_report};
+use crate::i18n::DefaultLocale;
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::pii::{Masked, PiiEncryption};
+use crate::repositories::enqueue_issue_deliveries;
+use crate::routes::api::ApiError;
+use crate::services::{NewIssue, NewsletterService, PublishError, LOCAL_SEND_HOUR};
+use crate::simulation::simulate_publish;
+use crate::startup::PublicBaseUrl;
+use crate::tenancy::{Tenant, TenantId};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use futures_util::StreamExt;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// Retries carrying the same key get the original response back, and the issue is
+/// published only once.
+const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+/// Sample emails rendered by a simulation, unless the request asks for another number.
+const DEFAULT_SIMULATION_SAMPLES: u16 = 3;
+/// Rendering an email takes a few queries: keep simulations cheap.
+const MAX_SIMULATION_SAMPLES: u16 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct PublishNewsletterBody {
+    title: String,
+    html: String,
+    text: String,
+    /// Only the confirmed subscribers with this tag receive the issue.
+    #[serde(default)]
+    segment: Option<String>,
+    /// Topics, for the category pages of the archive.
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Deliver at `LOCAL_SEND_HOUR` in the timezone of each subscriber.
+    #[serde(default)]
+    send_at_local_time: bool,
+}
+
+impl PublishNewsletterBody {
+    fn parse(self) -> Result<NewIssue, ApiError> {
+        let mut issue =
+            NewIssue::parse(self.title, self.text, self.html).map_err(ApiError::ValidationError)?;
+        if let Some(segment) = self.segment {
+            issue = issue
+                .with_segment(segment)
+                .map_err(ApiError::ValidationError)?;
+        }
+        if !self.tags.is_empty() {
+            issue = issue
+                .with_tags(self.tags)
+                .map_err(ApiError::ValidationError)?;
+        }
+        if self.send_at_local_time {
+            issue = issue
+                .sent_at_local_hour(LOCAL_SEND_HOUR)
+                .map_err(ApiError::ValidationError)?;
+        }
+        Ok(issue)
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct SimulateNewsletterBody {
+    #[serde(flatten)]
+    issue: PublishNewsletterBody,
+    /// How many of the emails to render.
+    #[serde(default)]
+    samples: Option<u16>,
+}
+
+#[derive(serde::Serialize)]
+pub struct NewsletterIssueStatus {
+    newsletter_issue_id: Uuid,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    segment: Option<String>,
+    published_at: String,
+    status: &'static str,
+    pending_deliveries: i64,
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter issue through the API",
+    skip(request, body, pool, user_id, tenant, idempotency, newsletters, cache, limits),
+    fields(user_id=%*user_id)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn publish_newsletter(
+    request: HttpRequest,
+    body: web::Json<PublishNewsletterBody>,
+    user_id: web::ReqData<UserId>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    idempotency: web::Data<IdempotencySettings>,
+    newsletters: web::Data<NewsletterSettings>,
+    cache: web::Data<ResponseCache>,
+    limits: web::Data<ConcurrencyLimits>,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = user_id.into_inner();
+    let idempotency_key = get_idempotency_key(&request)?;
+    let issue = body.0.parse()?;
+    let _permit = limits.try_acquire(tenant.id, HeavyRoute::Publish)?;
+    let mut transaction = match &idempotency_key {
+        Some(key) => match try_processing(&pool, key, *user_id, &idempotency).await? {
+            NextAction::StartProcessing(transaction) => transaction,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+            NextAction::StillProcessing => return Err(ApiError::Conflict(
+                "A request with this idempotency key is still being processed - try again later."
+                    .into(),
+            )),
+        },
+        None => pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?,
+    };
+    let issue_id = match NewsletterService::new(&mut transaction, tenant.id)
+        .with_undo_window(newsletters.undo_window())
+        .with_daily_quota(newsletters.daily_quota())
+        .publish(&issue)
+        .await
+    {
+        Ok(issue_id) => issue_id,
+        Err(e @ PublishError::QuotaExceeded { .. }) => {
+            return Err(ApiError::TooManyRequests(e.to_string()))
+        }
+        Err(e @ PublishError::MissingSubscriberName { .. }) => {
+            return Err(ApiError::Conflict(e.to_string()))
+        }
+        Err(PublishError::UnexpectedError(e)) => return Err(e.into()),
+    };
+    let status = get_issue_status(&mut transaction, tenant.id, issue_id)
+        .await?
+        .context("The newsletter issue we just published could not be found")?;
+
+    let response = HttpResponse::Accepted().json(status);
+    let response = match &idempotency_key {
+        Some(key) => save_response(transaction, key, *user_id, response, &idempotency).await?,
+        None => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit the SQL transaction to publish a newsletter issue.")?;
+            response
+        }
+    };
+    cache.invalidate_all();
+    if let Err(e) = enqueue_issue_deliveries(&pool, issue_id).await {
+        // The issue is published: the delivery workers queue what is left.
+        tracing::warn!(error.cause_chain = ?e, "Failed to queue the deliveries of the issue");
+    }
+    Ok(response)
+}
+
+/// Run a publish request without sending anything: how many deliveries it would queue,
+/// and a few of the emails, also written to the `sandbox` log.
+#[tracing::instrument(
+    name = "Simulate publishing a newsletter issue through the API",
+    skip(body, pool, tenant, newsletters, base_url, footer, default_locale, pii)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn simulate_newsletter(
+    body: web::Json<SimulateNewsletterBody>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    newsletters: web::Data<NewsletterSettings>,
+    base_url: web::Data<PublicBaseUrl>,
+    footer: web::Data<EmailFooterSettings>,
+    default_locale: web::Data<DefaultLocale>,
+    pii: web::Data<PiiEncryption>,
+) -> Result<HttpResponse, ApiError> {
+    let SimulateNewsletterBody { issue, samples } = body.0;
+    let samples = samples.unwrap_or(DEFAULT_SIMULATION_SAMPLES);
+    if samples > MAX_SIMULATION_SAMPLES {
+        return Err(ApiError::ValidationError(format!(
+            "At most {} sample emails can be rendered.",
+            MAX_SIMULATION_SAMPLES
+        )));
+    }
+    let issue = issue.parse()?;
+    let simulation = match simulate_publish(
+        &pool,
+        tenant.id,
+        &issue,
+        samples,
+        newsletters.daily_quota(),
+        &base_url.0,
+        &footer,
+        &default_locale.0,
+        pii.cipher(),
+    )
+    .await
+    {
+        Ok(simulation) => simulation,
+        Err(e @ PublishError::QuotaExceeded { .. }) => {
+            return Err(ApiError::TooManyRequests(e.to_string()))
+        }
+        Err(e @ PublishError::MissingSubscriberName { .. }) => {
+            return Err(ApiError::Conflict(e.to_string()))
+        }
+        Err(PublishError::UnexpectedError(e)) => return Err(e.into()),
+    };
+    Ok(HttpResponse::Ok().json(simulation))
+}
+
+fn get_idempotency_key(request: &HttpRequest) -> Result<Option<IdempotencyKey>, ApiError> {
+    let header_value = match request.headers().get(IDEMPOTENCY_KEY) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let key = header_value
+        .to_str()
+        .map_err(|_| {
+            ApiError::ValidationError("The Idempotency-Key header must be a valid string.".into())
+        })?
+        .to_owned();
+    key.try_into()
+        .map(Some)
+        .map_err(|e: anyhow::Error| ApiError::ValidationError(e.to_string()))
+}
+
+#[tracing::instrument(
+    name = "Get a newsletter issue status through the API",
+    skip(pool, tenant)
+)]
+pub async fn get_newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let status = get_issue_status(pool.get_ref(), tenant.id, newsletter_issue_id.into_inner())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("There is no newsletter issue with this id.".into()))?;
+    Ok(HttpResponse::Ok().json(status))
+}
+
+#[tracing::instrument(
+    name = "Get the delivery report of an issue through the API",
+    skip(pool, tenant, user_id, limits)
+)]
+pub async fn get_delivery_report(
+    newsletter_issue_id: web::Path<Uuid>,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    limits: web::Data<ConcurrencyLimits>,
+) -> Result<HttpResponse, ApiError> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let permit = limits.try_acquire(tenant.id, HeavyRoute::Export)?;
+    if !issue_exists(&pool, tenant.id, newsletter_issue_id).await? {
+        return Err(ApiError::NotFound(
+            "There is no newsletter issue with this id.".into(),
+        ));
+    }
+    let masked = !can_view_pii(&pool, **user_id).await?;
+
+    // `{"newsletter_issue_id": ..., "recipients": [...]}`, written as the rows come in.
+    let opening = format!(
+        r#"{{"newsletter_issue_id":{},"recipients":["#,
+        serde_json::json!(newsletter_issue_id)
+    );
+    let mut first = true;
+    let recipients = encode_in_chunks(
+        stream_delivery_report(pool.get_ref().clone(), newsletter_issue_id),
+        move |row, chunk| {
+            if !std::mem::take(&mut first) {
+                chunk.push(b',');
+            }
+            if masked {
+                serde_json::to_writer(chunk, &row.clone().masked())?;
+            } else {
+                serde_json::to_writer(chunk, row)?;
+            }
+            Ok(())
+        },
+        permit,
+    );
+    let body = futures_util::stream::once(async { Ok(web::Bytes::from(opening)) })
+        .chain(recipients)
+        .chain(futures_util::stream::once(async {
+            Ok(web::Bytes::from_static(b"]}"))
+        }));
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .streaming(body))
+}
+
+#[tracing::instrument(skip(executor))]
+async fn get_issue_status(
+    executor: impl PgExecutor<'_>,
+    tenant_id: TenantId,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<NewsletterIssueStatus>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            title,
+            segment,
+            published_at,
+            (
+                SELECT COUNT(*)
+                FROM issue_delivery_queue
+                WHERE newsletter_issue_id = $1
+            ) + (
+                -- The recipients not queued yet.
+                SELECT COUNT(*)
+                FROM issue_recipients(i.tenant_id, i.segment)
+                WHERE i.enqueued_at IS NULL AND email > COALESCE(i.enqueued_through, '')
+            ) AS "pending_deliveries!"
+        FROM newsletter_issues i
+        WHERE newsletter_issue_id = $1 AND tenant_id = $2
+        "#,
+        newsletter_issue_id,
+        *tenant_id
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to retrieve the newsletter issue status")?;
+
+    Ok(row.map(|r| NewsletterIssueStatus {
+        newsletter_issue_id,
+        title: r.title,
+        segment: r.segment,
+        published_at: r.published_at,
+        status: if r.pending_deliveries > 0 {
+            "delivering"
+        } else {
+            "delivered"
+        },
+        pending_deliveries: r.pending_deliveries,
+    }))
+}
@@ -0,0 +1,153 @@
+use crate::domain::SubscriptionStatus;
+use crate::pagination::{next_cursor, Cursor, PageQuery};
+use crate::pii::{reveal_email, reveal_name, PiiEncryption};
+use crate::routes::api::ApiError;
+use crate::tenancy::Tenant;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A page of a feed. `next_cursor` is `null` once the end of the feed is reached.
+#[derive(serde::Serialize)]
+pub struct Feed<T> {
+    items: Vec<T>,
+    next_cursor: Option<String>,
+}
+
+impl<T> Feed<T> {
+    fn new(items: Vec<T>, limit: i64, cursor_of: impl Fn(&T) -> Cursor) -> Self {
+        let next_cursor = next_cursor(&items, limit, cursor_of);
+        Self { items, next_cursor }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct RecentSubscriber {
+    id: Uuid,
+    email: String,
+    name: Option<String>,
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>,
+}
+
+struct RecentSubscriberRow {
+    id: Uuid,
+    email: String,
+    name: Option<String>,
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>,
+    email_encrypted: Option<Vec<u8>>,
+    name_encrypted: Option<Vec<u8>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct RecentIssue {
+    id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+/// The most recent subscribers first, for integration platforms polling for new signups.
+#[tracing::instrument(
+    name = "Poll recent subscribers through the API",
+    skip(tenant, pool, pii)
+)]
+pub async fn recent_subscribers(
+    query: web::Query<PageQuery>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiEncryption>,
+) -> Result<HttpResponse, ApiError> {
+    let (cursor, limit) = query
+        .into_inner()
+        .parse()
+        .map_err(ApiError::ValidationError)?;
+    let (created_at, id) = Cursor::unzip(cursor.as_ref());
+    let rows = sqlx::query_as!(
+        RecentSubscriberRow,
+        r#"
+        SELECT
+            id, email, name, status AS "status: SubscriptionStatus", subscribed_at,
+            email_encrypted, name_encrypted
+        FROM subscriptions
+        WHERE
+            deleted_at IS NULL AND
+            tenant_id = $4 AND
+            ($1::timestamptz IS NULL OR (subscribed_at, id) < ($1, $2))
+        ORDER BY subscribed_at DESC, id DESC
+        LIMIT $3
+        "#,
+        created_at,
+        id,
+        limit,
+        *tenant.id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve recent subscribers")?;
+    let cipher = pii.cipher();
+    let subscribers = rows
+        .into_iter()
+        .map(|r| {
+            Ok(RecentSubscriber {
+                id: r.id,
+                email: reveal_email(cipher, r.id, r.email, r.email_encrypted.as_deref())?,
+                name: reveal_name(cipher, r.id, r.name, r.name_encrypted.as_deref())?,
+                status: r.status,
+                subscribed_at: r.subscribed_at,
+            })
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+    Ok(
+        HttpResponse::Ok().json(Feed::new(subscribers, limit, |s| Cursor {
+            created_at: s.subscribed_at,
+            id: s.id,
+        })),
+    )
+}
+
+/// The most recent newsletter issues first, for integration platforms polling for new issues.
+#[tracing::instrument(name = "Poll recent issues through the API", skip(tenant, pool))]
+pub async fn recent_issues(
+    query: web::Query<PageQuery>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (cursor, limit) = query
+        .into_inner()
+        .parse()
+        .map_err(ApiError::ValidationError)?;
+    let (created_at, id) = Cursor::unzip(cursor.as_ref());
+    let issues = sqlx::query_as!(
+        RecentIssue,
+        r#"
+        SELECT
+            newsletter_issue_id AS id,
+            title,
+            published_at::timestamptz AS "published_at!"
+        FROM newsletter_issues
+        WHERE tenant_id = $4
+            AND ($1::timestamptz IS NULL
+                OR (published_at::timestamptz, newsletter_issue_id) < ($1, $2))
+        ORDER BY published_at::timestamptz DESC, newsletter_issue_id DESC
+        LIMIT $3
+        "#,
+        created_at,
+        id,
+        limit,
+        *tenant.id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve recent issues")?;
+
+    Ok(
+        HttpResponse::Ok().json(Feed::new(issues, limit, |i| Cursor {
+            created_at: i.published_at,
+            id: i.id,
+        })),
+    )
+}
@@ -0,0 +1,227 @@
+use crate::authentication::{can_view_pii, UserId};
+use crate::configuration::SubscriberSettings;
+use crate::domain::{ConsentEvidence, NewSubscriber};
+use crate::email_client::EmailClient;
+use crate::i18n::Locale;
+use crate::pagination::{next_cursor, Cursor, PageQuery};
+use crate::pii::{Masked, PiiEncryption};
+use crate::repositories::{
+    ConsentRecord, PostgresSubscriberRepository, Subscriber, SubscriberRepository,
+};
+use crate::routes::api::ApiError;
+use crate::services::{SubscriptionError, SubscriptionService};
+use crate::startup::PublicBaseUrl;
+use crate::tenancy::Tenant;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// `next_cursor` is `null` once the end of the list is reached.
+#[derive(serde::Serialize)]
+pub struct SubscriberList {
+    subscribers: Vec<Subscriber>,
+    next_cursor: Option<String>,
+}
+
+/// The proof of consent of a subscriber: when and from where they signed up and
+/// confirmed their subscription.
+#[derive(serde::Serialize)]
+pub struct ConsentExport {
+    subscriber: Subscriber,
+    records: Vec<ConsentRecord>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct CreateSubscriberBody {
+    email: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    locale: Option<String>,
+    /// The version of the policy the subscriber accepted, see
+    /// `SubscriberSettings::consent_policy`.
+    #[serde(default)]
+    accepted_policy: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "List subscribers through the API",
+    skip(pool, pii, tenant, user_id)
+)]
+pub async fn list_subscribers(
+    query: web::Query<PageQuery>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiEncryption>,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, ApiError> {
+    let (cursor, limit) = query
+        .into_inner()
+        .parse()
+        .map_err(ApiError::ValidationError)?;
+    let subscribers = PostgresSubscriberRepository::new(&pool, tenant.id)
+        .with_cipher(pii.cipher())
+        .list_subscribers(cursor.as_ref(), limit)
+        .await?;
+    let next_cursor = next_cursor(&subscribers, limit, |s| Cursor {
+        created_at: s.subscribed_at,
+        id: s.id,
+    });
+    let subscribers = if can_view_pii(&pool, **user_id).await? {
+        subscribers
+    } else {
+        subscribers.into_iter().map(Subscriber::masked).collect()
+    };
+    Ok(HttpResponse::Ok().json(SubscriberList {
+        subscribers,
+        next_cursor,
+    }))
+}
+
+#[tracing::instrument(
+    name = "Get a subscriber through the API",
+    skip(pool, pii, tenant, user_id)
+)]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiEncryption>,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, ApiError> {
+    let subscriber = PostgresSubscriberRepository::new(&pool, tenant.id)
+        .with_cipher(pii.cipher())
+        .get_subscriber(subscriber_id.into_inner())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("There is no subscriber with this id.".into()))?;
+    if can_view_pii(&pool, **user_id).await? {
+        Ok(HttpResponse::Ok().json(subscriber))
+    } else {
+        Ok(HttpResponse::Ok().json(subscriber.masked()))
+    }
+}
+
+#[tracing::instrument(
+    name = "Export the proof of consent of a subscriber",
+    skip(pool, pii, tenant, user_id)
+)]
+pub async fn get_subscriber_consent(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiEncryption>,
+    tenant: web::ReqData<Tenant>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, ApiError> {
+    let repository = PostgresSubscriberRepository::new(&pool, tenant.id).with_cipher(pii.cipher());
+    let subscriber = repository
+        .get_subscriber(subscriber_id.into_inner())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("There is no subscriber with this id.".into()))?;
+    let records = repository.consent_records(subscriber.id).await?;
+    if !can_view_pii(&pool, **user_id).await? {
+        return Ok(HttpResponse::Ok().json(ConsentExport {
+            subscriber: subscriber.masked(),
+            records: records.into_iter().map(ConsentRecord::masked).collect(),
+        }));
+    }
+    Ok(HttpResponse::Ok().json(ConsentExport {
+        subscriber,
+        records,
+    }))
+}
+
+/// Create a subscriber through the same flow as the public signup form: the subscriber
+/// starts as `pending_confirmation` and receives a confirmation email. Once the list is
+/// full, they join the waitlist instead: `202 Accepted`.
+#[tracing::instrument(
+    name = "Create a subscriber through the API",
+    skip(body, pool, pii, email_client, base_url, settings, locale, tenant),
+    fields(subscriber_email = %body.email)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn create_subscriber(
+    body: web::Json<CreateSubscriberBody>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiEncryption>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<PublicBaseUrl>,
+    settings: web::Data<SubscriberSettings>,
+    locale: Locale,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, ApiError> {
+    let CreateSubscriberBody {
+        email,
+        name,
+        locale: chosen_locale,
+        accepted_policy,
+    } = body.0;
+    let policy_accepted = settings
+        .consent_policy
+        .is_satisfied_by(accepted_policy.as_deref());
+    let new_subscriber = NewSubscriber::parse(
+        name,
+        email,
+        chosen_locale,
+        settings.max_name_length,
+        settings.require_name,
+    );
+    let new_subscriber = match new_subscriber {
+        Ok(new_subscriber) if policy_accepted => new_subscriber,
+        new_subscriber => {
+            let mut e = new_subscriber.err().unwrap_or_default();
+            if !policy_accepted {
+                e.accepted_policy = Some("The policy must be accepted.".into());
+            }
+            return Err(ApiError::ValidationError(e.to_string()));
+        }
+    };
+    // The request comes from the integration, not the subscriber: only the version of the
+    // policy they accepted is worth keeping.
+    let consent = ConsentEvidence::default().with_policy_version(
+        settings
+            .consent_policy
+            .accepted_version(accepted_policy.as_deref()),
+    );
+
+    let repository = PostgresSubscriberRepository::new(&pool, tenant.id)
+        .with_cipher(pii.cipher())
+        .with_max_subscribers(settings.max_subscribers);
+    let base_url = tenant.public_base_url(&base_url.0);
+    let mut service = SubscriptionService::new(&repository, email_client.get_ref(), base_url)
+        .with_sender(tenant.sender_email.as_ref());
+    if consent.policy_version.is_some() {
+        service = service.with_consent(&consent);
+    }
+    let subscriber_id = match service.create(new_subscriber, &locale).await {
+        Ok(subscriber_id) => subscriber_id,
+        Err(e @ SubscriptionError::AlreadyExists) => return Err(ApiError::Conflict(e.to_string())),
+        Err(SubscriptionError::Waitlisted) => {
+            return Ok(HttpResponse::Accepted().json(serde_json::json!({ "status": "waitlisted" })))
+        }
+        Err(SubscriptionError::UnexpectedError(e)) => return Err(e.into()),
+    };
+
+    let subscriber = repository
+        .get_subscriber(subscriber_id)
+        .await?
+        .context("The subscriber we just created could not be found")?;
+    Ok(HttpResponse::Created().json(subscriber))
+}
+
+#[tracing::instrument(name = "Delete a subscriber through the API", skip(pool, tenant))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, ApiError> {
+    let deleted = PostgresSubscriberRepository::new(&pool, tenant.id)
+        .delete_subscribers(&[subscriber_id.into_inner()])
+        .await?;
+    if deleted == 0 {
+        return Err(ApiError::NotFound(
+            "There is no subscriber with this id.".into(),
+        ));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
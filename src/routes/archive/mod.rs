@@ -0,0 +1,5 @@
+mod pages;
+mod seo;
+
+pub use pages::*;
+pub use seo::*;
@@ -0,0 +1,335 @@
+use crate::cache::ResponseCache;
+use crate::configuration::BrandingSettings;
+use crate::issue_tags::{list_tags, parse_tag};
+use crate::merge_fields::fill_with_fallbacks;
+use crate::pagination::{next_cursor, Cursor, PageQuery};
+use crate::repositories::decompress_content;
+use crate::search::SearchQuery;
+use crate::tenancy::{Tenant, TenantId};
+use crate::utils::{e400, e500};
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama_actix::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct ArchivedIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+}
+
+impl ArchivedIssue {
+    /// The archive is not addressed to anyone: its merge fields fall back, see
+    /// `crate::merge_fields`.
+    fn with_fallbacks(self) -> Self {
+        Self {
+            title: fill_with_fallbacks(&self.title, str::to_owned),
+            ..self
+        }
+    }
+}
+
+struct ArchivedIssueContent {
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+struct ArchivedIssueRow {
+    title: String,
+    html_content: Option<String>,
+    html_content_zstd: Option<Vec<u8>>,
+    published_at: DateTime<Utc>,
+}
+
+/// `?q=...` - optional.
+#[derive(serde::Deserialize, Debug)]
+pub struct ArchiveFilters {
+    /// Words to look for in the title and content of the issues.
+    q: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "archive.html")]
+struct ArchiveTemplate {
+    branding: web::Data<BrandingSettings>,
+    issues: Vec<ArchivedIssue>,
+    next_cursor: Option<String>,
+    /// Carried over to the next page.
+    limit: i64,
+    /// The listing itself: the archive, or the category page of `tag`.
+    path: String,
+    /// The search as a query parameter, for the link to the next page.
+    filter_query: String,
+    q: String,
+    tag: Option<String>,
+    tags: Vec<ArchiveTag>,
+}
+
+struct ArchiveTag {
+    name: String,
+    /// The archive is restricted to it.
+    current: bool,
+}
+
+impl ArchiveTag {
+    fn path(&self) -> String {
+        tag_path(&self.name)
+    }
+}
+
+/// The category page of a tag.
+pub fn tag_path(tag: &str) -> String {
+    format!("/issues/tags/{}", urlencoding::encode(tag))
+}
+
+#[derive(Template)]
+#[template(path = "archive_issue.html")]
+struct ArchiveIssueTemplate {
+    branding: web::Data<BrandingSettings>,
+    issue: ArchivedIssueContent,
+}
+
+/// Every issue the tenant published, most recent first. Issues within their undo window
+/// are left out until then, here and everywhere in the archive.
+pub async fn list_archived_issues(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Vec<ArchivedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            published_at::timestamptz AS "published_at!"
+        FROM newsletter_issues
+        WHERE tenant_id = $1 AND deliver_after <= now()
+        ORDER BY published_at::timestamptz DESC, newsletter_issue_id
+        "#,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the archived issues")?;
+    Ok(issues
+        .into_iter()
+        .map(ArchivedIssue::with_fallbacks)
+        .collect())
+}
+
+/// Published issues matching `search` and tagged with `tag`, most recent first, starting
+/// right after `after`.
+pub(super) async fn list_archived_issues_page(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    search: Option<&SearchQuery>,
+    tag: Option<&str>,
+    after: Option<&Cursor>,
+    limit: i64,
+) -> Result<Vec<ArchivedIssue>, anyhow::Error> {
+    let (published_at, id) = Cursor::unzip(after);
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            published_at::timestamptz AS "published_at!"
+        FROM newsletter_issues
+        WHERE tenant_id = $4
+            AND deliver_after <= now()
+            AND ($1::timestamptz IS NULL
+                OR (published_at::timestamptz, newsletter_issue_id) < ($1, $2))
+            AND ($5::text IS NULL OR search_vector @@ to_tsquery('english', $5))
+            AND ($6::text IS NULL OR EXISTS (
+                SELECT 1 FROM newsletter_issue_tags t
+                WHERE t.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+                    AND t.tag = $6
+            ))
+        ORDER BY published_at::timestamptz DESC, newsletter_issue_id DESC
+        LIMIT $3
+        "#,
+        published_at,
+        id,
+        limit,
+        *tenant_id,
+        search.map(AsRef::as_ref),
+        tag
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the archived issues")?;
+    Ok(issues
+        .into_iter()
+        .map(ArchivedIssue::with_fallbacks)
+        .collect())
+}
+
+pub async fn archive(
+    query: web::Query<PageQuery>,
+    filters: web::Query<ArchiveFilters>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<ResponseCache>,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    render_archive(None, query, filters, tenant, pool, cache, branding).await
+}
+
+/// The category page of a tag: the archive, restricted to the issues tagged with it.
+pub async fn archive_category(
+    tag: web::Path<String>,
+    query: web::Query<PageQuery>,
+    filters: web::Query<ArchiveFilters>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<ResponseCache>,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let tag = match parse_tag(&tag) {
+        Ok(tag) => tag,
+        Err(_) => return Ok(HttpResponse::NotFound().finish()),
+    };
+    render_archive(Some(tag), query, filters, tenant, pool, cache, branding).await
+}
+
+async fn render_archive(
+    tag: Option<String>,
+    query: web::Query<PageQuery>,
+    filters: web::Query<ArchiveFilters>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<ResponseCache>,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (cursor, limit) = query.into_inner().parse().map_err(e400)?;
+    let search = filters.q.as_deref().and_then(SearchQuery::parse);
+    // What the search box shows, as searched for.
+    let q = search
+        .as_ref()
+        .map(|s| s.terms().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default();
+    let path = match &tag {
+        Some(tag) => tag_path(tag),
+        None => "/issues".to_string(),
+    };
+    // Keyed on the normalized search, so that `?q=Rust` and `?q=rust!` share an entry.
+    let key = format!(
+        "{}{}?cursor={}&limit={}&q={}",
+        tenant.id,
+        path,
+        cursor.as_ref().map(Cursor::encode).unwrap_or_default(),
+        limit,
+        search.as_ref().map(AsRef::as_ref).unwrap_or_default()
+    );
+    let body = cache
+        .get_or_try_insert_with(key, || async {
+            let issues = list_archived_issues_page(
+                &pool,
+                tenant.id,
+                search.as_ref(),
+                tag.as_deref(),
+                cursor.as_ref(),
+                limit,
+            )
+            .await?;
+            let next_cursor = next_cursor(&issues, limit, |i| Cursor {
+                created_at: i.published_at,
+                id: i.newsletter_issue_id,
+            });
+            let filter_query = if q.is_empty() {
+                String::new()
+            } else {
+                format!("&q={}", urlencoding::encode(&q))
+            };
+            let tags = list_tags(&pool, tenant.id)
+                .await?
+                .into_iter()
+                .map(|name| ArchiveTag {
+                    current: tag.as_ref() == Some(&name),
+                    name,
+                })
+                .collect();
+            ArchiveTemplate {
+                branding,
+                issues,
+                next_cursor,
+                limit,
+                path: path.clone(),
+                filter_query,
+                q: q.clone(),
+                tag: tag.clone(),
+                tags,
+            }
+            .render()
+            .context("Failed to render the archive")
+        })
+        .await
+        .map_err(e500)?;
+    // Let browsers and proxies absorb the traffic too, e.g. when a search is linked from
+    // a popular post.
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(cache.ttl().as_secs() as u32),
+        ]))
+        .body(body))
+}
+
+pub async fn archived_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    cache: web::Data<ResponseCache>,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let key = format!("{}/archive/{}", tenant.id, newsletter_issue_id);
+    if let Some(body) = cache.get(&key) {
+        return Ok(HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(body));
+    }
+    let row = sqlx::query_as!(
+        ArchivedIssueRow,
+        r#"
+        SELECT
+            title,
+            html_content,
+            html_content_zstd,
+            published_at::timestamptz AS "published_at!"
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND tenant_id = $2 AND deliver_after <= now()
+        "#,
+        newsletter_issue_id,
+        *tenant.id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve an archived issue")
+    .map_err(e500)?;
+    match row {
+        Some(row) => {
+            let html_content =
+                decompress_content(row.html_content_zstd, row.html_content).map_err(e500)?;
+            let issue = ArchivedIssueContent {
+                html_content: fill_with_fallbacks(&html_content, htmlescape::encode_minimal),
+                title: fill_with_fallbacks(&row.title, str::to_owned),
+                published_at: row.published_at,
+            };
+            let body = ArchiveIssueTemplate { branding, issue }
+                .render()
+                .map_err(e500)?;
+            cache.insert(key, body.clone());
+            Ok(HttpResponse::Ok()
+                .content_type(ContentType::html())
+                .body(body))
+        }
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
@@ -0,0 +1,162 @@
+use super::pages::{list_archived_issues_page, tag_path};
+use crate::cache::ResponseCache;
+use crate::configuration::BrandingSettings;
+use crate::issue_tags::{list_tags, parse_tag};
+use crate::routes::list_archived_issues;
+use crate::startup::{AllowIndexing, PublicBaseUrl};
+use crate::tenancy::{Tenant, TenantId};
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// How many of the latest issues a feed lists.
+const FEED_ITEMS: i64 = 20;
+
+pub async fn sitemap(
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<PublicBaseUrl>,
+    cache: web::Data<ResponseCache>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = cache
+        .get_or_try_insert_with(format!("{}/sitemap.xml", tenant.id), || {
+            render_sitemap(&pool, tenant.id, tenant.public_base_url(&base_url.0))
+        })
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/xml; charset=utf-8")
+        .body(body))
+}
+
+async fn render_sitemap(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    base_url: &str,
+) -> Result<String, anyhow::Error> {
+    let issues = list_archived_issues(pool, tenant_id).await?;
+    let tags = list_tags(pool, tenant_id).await?;
+    let base_url = encode_minimal(base_url);
+
+    let mut urls = String::new();
+    for path in ["/", "/issues"] {
+        writeln!(urls, "  <url><loc>{}{}</loc></url>", base_url, path).unwrap();
+    }
+    for tag in tags {
+        writeln!(
+            urls,
+            "  <url><loc>{}{}</loc></url>",
+            base_url,
+            tag_path(&tag)
+        )
+        .unwrap();
+    }
+    for issue in issues {
+        writeln!(
+            urls,
+            "  <url><loc>{}/issues/{}</loc><lastmod>{}</lastmod></url>",
+            base_url,
+            issue.newsletter_issue_id,
+            issue.published_at.format("%Y-%m-%d")
+        )
+        .unwrap();
+    }
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+{urls}</urlset>
+"#
+    ))
+}
+
+/// The latest issues tagged with a tag, as an RSS feed.
+pub async fn tag_feed(
+    tag: web::Path<String>,
+    tenant: web::ReqData<Tenant>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<PublicBaseUrl>,
+    cache: web::Data<ResponseCache>,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let tag = match parse_tag(&tag) {
+        Ok(tag) => tag,
+        Err(_) => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let body = cache
+        .get_or_try_insert_with(format!("{}{}/feed.xml", tenant.id, tag_path(&tag)), || {
+            render_tag_feed(
+                &pool,
+                tenant.id,
+                &tag,
+                tenant.public_base_url(&base_url.0),
+                &branding.site_name,
+            )
+        })
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(body))
+}
+
+async fn render_tag_feed(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    tag: &str,
+    base_url: &str,
+    site_name: &str,
+) -> Result<String, anyhow::Error> {
+    let issues =
+        list_archived_issues_page(pool, tenant_id, None, Some(tag), None, FEED_ITEMS).await?;
+    let base_url = encode_minimal(base_url);
+
+    let mut items = String::new();
+    for issue in issues {
+        let link = format!("{}/issues/{}", base_url, issue.newsletter_issue_id);
+        writeln!(
+            items,
+            "    <item><title>{}</title><link>{}</link><guid>{}</guid><pubDate>{}</pubDate></item>",
+            encode_minimal(&issue.title),
+            link,
+            link,
+            issue.published_at.to_rfc2822()
+        )
+        .unwrap();
+    }
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+  <channel>
+    <title>{site_name} - {tag}</title>
+    <link>{base_url}{path}</link>
+    <description>Issues tagged {tag}</description>
+{items}  </channel>
+</rss>
+"#,
+        site_name = encode_minimal(site_name),
+        tag = encode_minimal(tag),
+        path = tag_path(tag),
+    ))
+}
+
+/// Crawlers are pointed at the sitemap of the public pages - or kept away entirely
+/// if indexing is disabled.
+pub async fn robots_txt(
+    allow_indexing: web::Data<AllowIndexing>,
+    base_url: web::Data<PublicBaseUrl>,
+    tenant: web::ReqData<Tenant>,
+) -> HttpResponse {
+    let body = if allow_indexing.0 {
+        format!(
+            "User-agent: *\nDisallow: /admin/\nDisallow: /api/\nDisallow: /login\n\nSitemap: {}/sitemap.xml\n",
+            tenant.public_base_url(&base_url.0)
+        )
+    } else {
+        "User-agent: *\nDisallow: /\n".to_string()
+    };
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(body)
+}
@@ -0,0 +1,44 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse};
+
+/// The files of the `static` folder, bundled into the binary: name, content type, body.
+const ASSETS: &[(&str, &str, &str)] = &[
+    (
+        "activity_feed.js",
+        "text/javascript; charset=utf-8",
+        include_str!("../../static/activity_feed.js"),
+    ),
+    (
+        "theme.css",
+        "text/css; charset=utf-8",
+        include_str!("../../static/theme.css"),
+    ),
+    (
+        "delivery_progress.js",
+        "text/javascript; charset=utf-8",
+        include_str!("../../static/delivery_progress.js"),
+    ),
+    (
+        "newsletter_autosave.js",
+        "text/javascript; charset=utf-8",
+        include_str!("../../static/newsletter_autosave.js"),
+    ),
+    (
+        "password_strength.js",
+        "text/javascript; charset=utf-8",
+        include_str!("../../static/password_strength.js"),
+    ),
+];
+
+pub async fn static_asset(filename: web::Path<String>) -> HttpResponse {
+    match ASSETS.iter().find(|(name, ..)| *name == filename.as_str()) {
+        Some((_, content_type, body)) => HttpResponse::Ok()
+            .content_type(*content_type)
+            .insert_header(CacheControl(vec![
+                CacheDirective::Public,
+                CacheDirective::MaxAge(3600),
+            ]))
+            .body(*body),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
@@ -0,0 +1,136 @@
+use crate::configuration::SubscriberSettings;
+use crate::email_client::EmailClient;
+use crate::i18n::Locale;
+use crate::pii::PiiEncryption;
+use crate::repositories::PostgresSubscriberRepository;
+use crate::routes::api::ApiError;
+use crate::routes::FormData;
+use crate::services::{ConfirmationEmail, SubscriptionService};
+use crate::startup::PublicBaseUrl;
+use crate::tenancy::Tenant;
+use crate::utils::e500;
+use actix_cors::Cors;
+use actix_web::http::header::{self, CacheControl, CacheDirective};
+use actix_web::http::Method;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use askama_actix::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "subscribe.js", escape = "none")]
+struct SubscribeScript<'a> {
+    base_url: &'a str,
+    require_name: bool,
+    require_policy: bool,
+    /// The version and label of the policy checkbox, as JavaScript strings.
+    policy_version: String,
+    policy_text: String,
+}
+
+/// The script external websites include to render a subscribe form talking to this instance.
+pub async fn subscribe_script(
+    base_url: web::Data<PublicBaseUrl>,
+    settings: web::Data<SubscriberSettings>,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let script =
+        render_subscribe_script(tenant.public_base_url(&base_url.0), &settings).map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/javascript; charset=utf-8")
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(3600),
+        ]))
+        .body(script))
+}
+
+/// Rendered again by `embed_subscribe`, to fingerprint the form the subscriber was shown.
+fn render_subscribe_script(
+    base_url: &str,
+    settings: &SubscriberSettings,
+) -> Result<String, anyhow::Error> {
+    let script = SubscribeScript {
+        base_url,
+        require_name: settings.require_name,
+        require_policy: settings.consent_policy.required,
+        policy_version: serde_json::to_string(&settings.consent_policy.version)?,
+        policy_text: serde_json::to_string(&settings.consent_policy.text)?,
+    }
+    .render()?;
+    Ok(script)
+}
+
+#[derive(serde::Serialize)]
+struct EmbedSubscribeResponse {
+    status: EmbedSubscribeStatus,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum EmbedSubscribeStatus {
+    PendingConfirmation,
+    /// The list is full, see `crate::waitlist`.
+    Waitlisted,
+}
+
+#[tracing::instrument(
+    name = "Adding a new subscriber from the embedded form",
+    skip(
+        request,
+        body,
+        pool,
+        pii,
+        email_client,
+        base_url,
+        settings,
+        locale,
+        tenant
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn embed_subscribe(
+    request: HttpRequest,
+    body: web::Json<FormData>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiEncryption>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<PublicBaseUrl>,
+    settings: web::Data<SubscriberSettings>,
+    locale: Locale,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, ApiError> {
+    let script = render_subscribe_script(tenant.public_base_url(&base_url.0), &settings)
+        .context("Failed to render the subscribe script")?;
+    let consent = body.consent_evidence(&request, &settings, &script);
+    let new_subscriber = body
+        .0
+        .parse(&settings)
+        .map_err(|e| ApiError::ValidationError(e.to_string()))?;
+    let confirmation_email = SubscriptionService::new(
+        &PostgresSubscriberRepository::new(&pool, tenant.id)
+            .with_cipher(pii.cipher())
+            .with_max_subscribers(settings.max_subscribers),
+        email_client.get_ref(),
+        tenant.public_base_url(&base_url.0),
+    )
+    .with_sender(tenant.sender_email.as_ref())
+    .with_consent(&consent)
+    .subscribe(new_subscriber, &locale)
+    .await?;
+    let status = match confirmation_email {
+        ConfirmationEmail::Waitlisted => EmbedSubscribeStatus::Waitlisted,
+        _ => EmbedSubscribeStatus::PendingConfirmation,
+    };
+    Ok(HttpResponse::Ok().json(EmbedSubscribeResponse { status }))
+}
+
+/// Only the configured websites may submit the embedded form from a browser.
+pub fn embed_cors(allowed_origins: &[String]) -> Cors {
+    allowed_origins
+        .iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .allowed_methods([Method::POST])
+        .allowed_header(header::CONTENT_TYPE)
+        .max_age(3600)
+}
@@ -1,49 +1,28 @@
+use crate::configuration::BrandingSettings;
+use crate::utils::e500;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use askama_actix::Template;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let mut error_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(
-            error_html,
-            "<p><i>{}</i></p>",
-            htmlescape::encode_minimal(m.content())
-        )
-        .unwrap();
-    }
+#[derive(Template)]
+#[template(path = "login.html")]
+struct LoginTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+}
 
-    HttpResponse::Ok()
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = LoginTemplate {
+        branding,
+        flash_messages,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-<meta http-equiv="content-type" content="text/html; charset=utf-8">
-<title>Login</title>
-</head>
-<body>
-{}
-<form action="/login" method="post">
-<label>Username
-<input
-type="text"
-placeholder="Enter Username"
-name="username"
->
-</label>
-<label>Password
-<input
-type="password"
-placeholder="Enter Password"
-name="password"
->
-</label>
-<button type="submit">Login</button>
-</form>
-</body>
-</html>"#,
-            error_html
-        ))
+        .body(body))
 }
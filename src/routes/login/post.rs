@@ -1,9 +1,11 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::authentication::{get_session_version, validate_credentials, AuthError, Credentials};
+use crate::notifications::{notify_admins, AdminEvent, AdminNotification};
 use crate::session_state::TypedSession;
-use crate::utils::error_chain_fmt;
+use crate::tenancy::{Tenant, TenantId};
+use crate::utils::{client_ip, error_chain_fmt};
 use actix_web::error::InternalError;
 use actix_web::http::header::LOCATION;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::Secret;
 use sqlx::PgPool;
@@ -17,27 +19,38 @@ pub struct FormData {
 
 #[tracing::instrument(
     name = "Login",
-    skip(form, pool, session),
+    skip(request, form, pool, session, tenant),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
+    request: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    tenant: web::ReqData<Tenant>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let username = credentials.username.clone();
 
-    match validate_credentials(credentials, &pool).await {
+    match validate_credentials(credentials, tenant.id, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            let session_version = get_session_version(user_id, tenant.id, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?
+                .unwrap_or_default();
             session.renew();
             session
                 .insert_user_id(user_id)
                 .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            session
+                .insert_session_version(session_version)
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            notify_login(&request, &pool, tenant.id, &username).await;
             Ok(HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
                 .finish())
@@ -52,6 +65,28 @@ pub async fn login(
     }
 }
 
+/// A notification which cannot be queued must not lock the admin out.
+async fn notify_login(request: &HttpRequest, pool: &PgPool, tenant_id: TenantId, username: &str) {
+    let address = client_ip(request)
+        .map(|addr| addr.to_string())
+        .unwrap_or_else(|| "an unknown address".into());
+    let notification = AdminNotification::new(
+        AdminEvent::NewLogin,
+        format!("{} logged in", username),
+        format!(
+            "{} logged in to the admin panel from {}.",
+            username, address
+        ),
+    );
+    let notified = match pool.acquire().await {
+        Ok(mut connection) => notify_admins(&mut connection, tenant_id, &notification).await,
+        Err(e) => Err(anyhow::Error::new(e)),
+    };
+    if let Err(e) = notified {
+        tracing::warn!(error.cause_chain = ?e, "Failed to notify the admins of a login");
+    }
+}
+
 fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
     let response = HttpResponse::SeeOther()
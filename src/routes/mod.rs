@@ -1,13 +1,32 @@
 mod admin;
+pub mod api;
+mod archive;
+mod assets;
+mod embed;
 mod health_check;
 mod home;
 mod login;
+mod not_found;
+mod preferences;
+mod subscribe_form;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_stay;
+mod unsubscribe;
+mod webhooks;
 
 pub use admin::*;
+pub use archive::*;
+pub use assets::*;
+pub use embed::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
+pub use not_found::*;
+pub use preferences::*;
+pub use subscribe_form::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_stay::*;
+pub use unsubscribe::*;
+pub use webhooks::*;
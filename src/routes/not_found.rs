@@ -0,0 +1,26 @@
+use crate::error::AppError;
+use crate::redirects::find_redirect;
+use crate::tenancy::Tenant;
+use actix_web::http::header::LOCATION;
+use actix_web::http::Method;
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+
+/// The default service, for the paths which match no route: a redirect if the path
+/// moved, a 404 otherwise - rendered as a page for browsers, see `crate::error_pages`.
+pub async fn not_found(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, AppError> {
+    let path = request.path();
+    if matches!(*request.method(), Method::GET | Method::HEAD) {
+        if let Some(to_location) = find_redirect(&pool, tenant.id, path).await? {
+            return Ok(HttpResponse::MovedPermanently()
+                .insert_header((LOCATION, to_location))
+                .finish());
+        }
+    }
+    tracing::debug!(method = %request.method(), path, "No route matches the path");
+    Ok(HttpResponse::NotFound().finish())
+}
@@ -0,0 +1,224 @@
+use crate::configuration::BrandingSettings;
+use crate::domain::SubscriptionToken;
+use crate::error::AppError;
+use crate::i18n::Locale;
+use crate::signing::SignedUrl;
+use crate::tenancy::{Tenant, TenantId};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama_actix::Template;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long the link from the unsubscribe page to the preferences stays valid.
+pub(crate) const PREFERENCES_LINK_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    subscription_token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesFormData {
+    subscription_token: String,
+    /// Empty to forget the timezone.
+    #[serde(default)]
+    timezone: String,
+}
+
+#[derive(Template)]
+#[template(path = "preferences.html")]
+struct PreferencesTemplate<'a> {
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    subscription_token: &'a str,
+    timezone: Option<String>,
+    timezones: Vec<String>,
+    saved: bool,
+}
+
+impl PreferencesTemplate<'_> {
+    fn is_selected(&self, timezone: &str) -> bool {
+        self.timezone.as_deref() == Some(timezone)
+    }
+}
+
+fn parse_token(subscription_token: String) -> Result<SubscriptionToken, AppError> {
+    SubscriptionToken::parse(subscription_token)
+        .map_err(|e| AppError::validation(e).with_code("invalid_subscription_token"))
+}
+
+fn unknown_token() -> AppError {
+    AppError::unauthorized("Failed to find token in database.")
+        .with_code("unknown_subscription_token")
+}
+
+/// Where subscribers tell us their timezone, for the issues sent at a set local time.
+/// It is linked from the unsubscribe page, through a signed URL.
+#[tracing::instrument(name = "Show the preferences page", skip_all)]
+pub async fn preferences_form(
+    _signed: SignedUrl,
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, AppError> {
+    let subscription_token = parse_token(parameters.into_inner().subscription_token)?;
+    let (_, timezone) = get_preferences(&pool, tenant.id, &subscription_token)
+        .await?
+        .ok_or_else(unknown_token)?;
+    render_preferences(
+        &pool,
+        branding,
+        locale,
+        subscription_token.as_ref(),
+        timezone,
+        false,
+    )
+    .await
+}
+
+#[tracing::instrument(
+    name = "Save the preferences of a subscriber",
+    skip(form, pool, branding, locale, tenant),
+    fields(timezone = %form.timezone)
+)]
+pub async fn save_preferences(
+    form: web::Form<PreferencesFormData>,
+    pool: web::Data<PgPool>,
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, AppError> {
+    let PreferencesFormData {
+        subscription_token,
+        timezone,
+    } = form.into_inner();
+    let subscription_token = parse_token(subscription_token)?;
+    let timezone = match timezone.trim() {
+        "" => None,
+        timezone => Some(timezone.to_owned()),
+    };
+    if let Some(timezone) = &timezone {
+        if !is_known_timezone(&pool, timezone).await? {
+            return Err(
+                AppError::validation(format!("Unknown timezone: {}.", timezone))
+                    .with_code("invalid_timezone"),
+            );
+        }
+    }
+    let (subscriber_id, _) = get_preferences(&pool, tenant.id, &subscription_token)
+        .await?
+        .ok_or_else(unknown_token)?;
+    store_timezone(&pool, subscriber_id, timezone.as_deref()).await?;
+    render_preferences(
+        &pool,
+        branding,
+        locale,
+        subscription_token.as_ref(),
+        timezone,
+        true,
+    )
+    .await
+}
+
+pub(crate) async fn render_preferences(
+    pool: &PgPool,
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    subscription_token: &str,
+    timezone: Option<String>,
+    saved: bool,
+) -> Result<HttpResponse, AppError> {
+    let body = PreferencesTemplate {
+        branding,
+        locale,
+        subscription_token,
+        timezone,
+        timezones: list_timezones(pool).await?,
+        saved,
+    }
+    .render()
+    .context("Failed to render the preferences page")?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// The id and timezone of the subscriber the token belongs to.
+#[tracing::instrument(skip(pool, subscription_token))]
+async fn get_preferences(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    subscription_token: &SubscriptionToken,
+) -> Result<Option<(Uuid, Option<String>)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT s.id, s.timezone
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token = $1 AND s.tenant_id = $2 AND s.deleted_at IS NULL
+        "#,
+        subscription_token.as_ref(),
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the preferences of a subscriber")?;
+    Ok(row.map(|r| (r.id, r.timezone)))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn store_timezone(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    timezone: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "UPDATE subscriptions SET timezone = $1 WHERE id = $2",
+        timezone,
+        subscriber_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the timezone of a subscriber")?;
+    Ok(())
+}
+
+/// The timezones Postgres knows about, by their IANA name - without the aliases kept
+/// for backward compatibility.
+#[tracing::instrument(skip(pool))]
+async fn list_timezones(pool: &PgPool) -> Result<Vec<String>, anyhow::Error> {
+    let timezones = sqlx::query!(
+        r#"
+        SELECT name AS "name!"
+        FROM pg_timezone_names
+        WHERE name ~ '^(Africa|America|Antarctica|Asia|Atlantic|Australia|Europe|Indian|Pacific)/'
+            OR name = 'UTC'
+        ORDER BY name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the timezones")?
+    .into_iter()
+    .map(|r| r.name)
+    .collect();
+    Ok(timezones)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn is_known_timezone(pool: &PgPool, timezone: &str) -> Result<bool, anyhow::Error> {
+    let known = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1) AS "known!""#,
+        timezone
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check a timezone")?
+    .known;
+    Ok(known)
+}
@@ -0,0 +1,127 @@
+use crate::configuration::{BrandingSettings, ConsentPolicySettings, SubscriberSettings};
+use crate::i18n::Locale;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use askama_actix::Template;
+
+/// The form of `/subscribe`, rendered on its own so that `subscribe` can fingerprint the
+/// markup the subscriber was shown, see `ConsentEvidence::with_rendered_form`.
+#[derive(Template)]
+#[template(path = "subscribe_form.html")]
+pub struct SignupForm {
+    locale: Locale,
+    /// To pick the language of the emails, the one of the page by default.
+    locales: Vec<Locale>,
+    require_name: bool,
+    confirm_email: bool,
+    consent_policy: ConsentPolicySettings,
+}
+
+impl SignupForm {
+    pub fn new(settings: &SubscriberSettings, locale: Locale) -> Self {
+        Self {
+            locale,
+            locales: Locale::all(),
+            require_name: settings.require_name,
+            confirm_email: settings.confirm_email,
+            consent_policy: settings.consent_policy.clone(),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "subscribe.html")]
+struct SubscribeTemplate {
+    branding: web::Data<BrandingSettings>,
+    flash_messages: IncomingFlashMessages,
+    locale: Locale,
+    form: String,
+}
+
+/// A hosted signup page, posting to `/subscriptions`.
+pub async fn subscribe_form(
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+    settings: web::Data<SubscriberSettings>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = SignupForm::new(&settings, locale.clone())
+        .render()
+        .map_err(e500)?;
+    let body = SubscribeTemplate {
+        branding,
+        flash_messages,
+        locale,
+        form,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+#[derive(Template)]
+#[template(path = "subscribe_waitlisted.html")]
+struct SubscribeWaitlistedTemplate {
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+}
+
+/// Where browsers land after signing up to a full list.
+pub async fn subscribe_waitlisted(
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = SubscribeWaitlistedTemplate { branding, locale }
+        .render()
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PendingParameters {
+    /// Whether the confirmation email waits in the outbox.
+    #[serde(default)]
+    delayed: bool,
+}
+
+#[derive(Template)]
+#[template(path = "subscribe_pending.html")]
+struct SubscribePendingTemplate {
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    /// Where the confirmation link went, with the address masked.
+    sent_to: Option<String>,
+    delayed: bool,
+}
+
+/// Where browsers land after subscribing: what to do next to confirm the subscription.
+/// The address the link went to comes from the flash message `subscribe` sent - never
+/// from the URL, which anyone can craft to show their own text on our page.
+pub async fn subscribe_pending(
+    parameters: web::Query<PendingParameters>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let sent_to = flash_messages
+        .iter()
+        .find(|m| m.level() == Level::Info)
+        .map(|m| m.content().to_owned());
+    let body = SubscribePendingTemplate {
+        branding,
+        locale,
+        sent_to,
+        delayed: parameters.0.delayed,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
@@ -1,253 +1,198 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionToken};
+use crate::circuit_breaker::CircuitBreaker;
+use crate::configuration::SubscriberSettings;
+use crate::domain::{ConsentEvidence, InvalidSubscriber, NewSubscriber};
 use crate::email_client::EmailClient;
-use crate::startup::ApplicationBaseUrl;
-use crate::utils::error_chain_fmt;
-use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use crate::email_outbox::PostgresEmailOutbox;
+use crate::error::AppError;
+use crate::i18n::Locale;
+use crate::pii::PiiEncryption;
+use crate::repositories::PostgresSubscriberRepository;
+use crate::routes::SignupForm;
+use crate::services::{ConfirmationEmail, SubscriptionService};
+use crate::startup::PublicBaseUrl;
+use crate::tenancy::Tenant;
+use crate::utils::{accepts, consent_evidence, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
 use askama_actix::Template;
-use chrono::Utc;
-use sqlx::{PgPool, Postgres, Transaction};
-use std::fmt::Formatter;
-use uuid::Uuid;
+use sqlx::PgPool;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
-    name: String,
+    /// Only asked for if names are required, see `SubscriberSettings::require_name`.
+    #[serde(default)]
+    name: Option<String>,
+    /// The address typed a second time, only asked for if
+    /// `SubscriberSettings::confirm_email` is set. Checked whenever it is sent.
+    #[serde(default)]
+    email_confirmation: Option<String>,
+    /// The language of the emails, if the subscriber picked one.
+    #[serde(default)]
+    locale: Option<String>,
+    /// The version of the policy, sent by the checkbox of forms asking for it, see
+    /// `SubscriberSettings::consent_policy`.
+    #[serde(default)]
+    accepted_policy: Option<String>,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+impl FormData {
+    /// What to keep as proof of the subscriber's consent: where `request` came from, the
+    /// hash of the `rendered_form` it was filled in and the version of the policy it
+    /// accepted.
+    pub fn consent_evidence(
+        &self,
+        request: &HttpRequest,
+        settings: &SubscriberSettings,
+        rendered_form: &str,
+    ) -> ConsentEvidence {
+        consent_evidence(request)
+            .with_rendered_form(rendered_form)
+            .with_policy_version(
+                settings
+                    .consent_policy
+                    .accepted_version(self.accepted_policy.as_deref()),
+            )
+    }
 
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(NewSubscriber { email, name })
+    pub fn parse(self, settings: &SubscriberSettings) -> Result<NewSubscriber, InvalidSubscriber> {
+        let mismatch = self
+            .email_confirmation
+            .as_deref()
+            .is_some_and(|confirmation| !is_same_email(confirmation, &self.email));
+        let policy_accepted = settings
+            .consent_policy
+            .is_satisfied_by(self.accepted_policy.as_deref());
+        let new_subscriber = NewSubscriber::parse(
+            self.name,
+            self.email,
+            self.locale,
+            settings.max_name_length,
+            settings.require_name,
+        );
+        if !mismatch && policy_accepted {
+            return new_subscriber;
+        }
+        let mut e = new_subscriber.err().unwrap_or_default();
+        if mismatch {
+            e.email_confirmation = Some("The two email addresses do not match.".into());
+        }
+        if !policy_accepted {
+            e.accepted_policy = Some("The policy must be accepted.".into());
+        }
+        Err(e)
     }
 }
 
-#[derive(Template)]
-#[template(path = "confirmation.html")]
-pub struct ConfirmationTemplate<'a> {
-    confirmation_link: &'a str,
+/// Email addresses are not case sensitive in practice.
+fn is_same_email(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
 }
 
 #[tracing::instrument(
     name = "Adding as a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(request, form, pool, pii, email_client, breaker, base_url, settings, locale, tenant),
     fields(
         subscriber_email = % form.email,
-        subscriber_name = % form.name
+        subscriber_name = ? form.name
     )
 )]
+/// Browsers submitting the form of `/subscribe` are sent to `/subscribe/pending` on
+/// success, and back to the form otherwise - with a flash message for each invalid field.
+/// Other clients get the outcome as the response body.
+///
+/// Once the list is full, new subscribers join the waitlist instead: browsers are sent to
+/// `/subscribe/waitlisted`, other clients get a `202 Accepted`.
+///
+/// While the email provider is unavailable, the confirmation email waits in the outbox:
+/// browsers are told it may take a while to arrive, other clients get a `202 Accepted`.
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
+    request: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    pii: web::Data<PiiEncryption>,
     email_client: web::Data<EmailClient>,
-    base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
-    let mut transaction = pool
-        .begin()
-        .await
-        .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = match get_past_subscription(&mut transaction, &new_subscriber)
-        .await
-        .context("Failed to check if the subscriber already exists in database.")?
-    {
-        Some(id) => id,
-        None => insert_subscriber(&mut transaction, &new_subscriber)
-            .await
-            .context("Failed to insert new subscriber in the database.")?,
-    };
-    let subscription_token = match get_past_subscription_token(&mut transaction, subscriber_id)
-        .await
-        .context("Failed to check for existing subscription token in database.")?
-    {
-        Some(token) => token,
-        None => {
-            let subscription_token = SubscriptionToken::generate();
-            store_token(&mut transaction, subscriber_id, &subscription_token)
-                .await
-                .context("Failed to store subscription token in the database.")?;
-            subscription_token
+    breaker: web::Data<CircuitBreaker>,
+    base_url: web::Data<PublicBaseUrl>,
+    settings: web::Data<SubscriberSettings>,
+    locale: Locale,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, AppError> {
+    let from_browser = accepts(&request, "text/html");
+    let rendered_form = SignupForm::new(&settings, locale.clone())
+        .render()
+        .context("Failed to render the signup form")?;
+    let consent = form.consent_evidence(&request, &settings, &rendered_form);
+    let new_subscriber = match form.0.parse(&settings) {
+        Ok(new_subscriber) => new_subscriber,
+        Err(e) => {
+            tracing::info!(error.message = %e, "Rejected an invalid subscriber");
+            if from_browser {
+                if e.name.is_some() {
+                    FlashMessage::error(locale.t("subscribe-invalid-name")).send();
+                }
+                if e.email.is_some() {
+                    FlashMessage::error(locale.t("subscribe-invalid-email")).send();
+                }
+                if e.email_confirmation.is_some() {
+                    FlashMessage::error(locale.t("subscribe-invalid-email-confirmation")).send();
+                }
+                if e.locale.is_some() {
+                    FlashMessage::error(locale.t("subscribe-invalid-locale")).send();
+                }
+                if e.accepted_policy.is_some() {
+                    FlashMessage::error(locale.t("subscribe-invalid-policy")).send();
+                }
+                return Ok(see_other("/subscribe"));
+            }
+            return Err(
+                AppError::validation(locale.t("subscribe-invalid")).with_code("invalid_subscriber")
+            );
         }
     };
-
-    transaction
-        .commit()
-        .await
-        .context("Failed to commit the SQL query to the database.")?;
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send a confirmation email.")?;
-
-    Ok(HttpResponse::Ok().finish())
-}
-
-#[tracing::instrument(
-    name = "Storing subscription token in the database",
-    skip(transaction, subscription_token)
-)]
-pub async fn store_token(
-    transaction: &mut Transaction<'_, Postgres>,
-    subscriber_id: Uuid,
-    subscription_token: &SubscriptionToken,
-) -> Result<(), StoreTokenError> {
-    sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)
-        "#,
-        subscription_token.as_ref(),
-        subscriber_id
-    )
-    .execute(transaction)
-    .await
-    .map_err(StoreTokenError)?;
-    Ok(())
-}
-#[tracing::instrument(
-    name = "Sending a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
-)]
-pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
-    base_url: &ApplicationBaseUrl,
-    subscription_token: &SubscriptionToken,
-) -> Result<(), reqwest::Error> {
-    let confirmation_link = format!(
-        "{}/subscriptions/confirm?subscription_token={}",
-        base_url.0,
-        subscription_token.as_ref()
-    );
-
-    let template = ConfirmationTemplate {
-        confirmation_link: confirmation_link.as_str(),
-    };
-
-    let rendered_html = template.render().unwrap();
-    email_client
-        .send_email(
-            &new_subscriber.email,
-            "Welcome!",
-            &rendered_html,
-            &format!(
-                "Welcome to our newsletter!\nVisit {} to confirm your subscription",
-                confirmation_link
-            ),
-        )
-        .await
-}
-
-#[tracing::instrument(
-    name = "Saving new subscriber details in the database",
-    skip(new_subscriber, transaction)
-)]
-pub async fn insert_subscriber(
-    transaction: &mut Transaction<'_, Postgres>,
-    new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
-        r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
-        ON CONFLICT DO NOTHING
-        "#,
-        subscriber_id,
-        new_subscriber.email.as_ref(),
-        new_subscriber.name.as_ref(),
-        Utc::now()
+    let masked_email = new_subscriber.email.masked();
+    let confirmation_email = SubscriptionService::new(
+        &PostgresSubscriberRepository::new(&pool, tenant.id)
+            .with_cipher(pii.cipher())
+            .with_max_subscribers(settings.max_subscribers),
+        email_client.get_ref(),
+        tenant.public_base_url(&base_url.0),
     )
-    .execute(transaction)
+    .with_sender(tenant.sender_email.as_ref())
+    .with_outbox(&PostgresEmailOutbox::new(&pool, tenant.id), &breaker)
+    .with_consent(&consent)
+    .subscribe(new_subscriber, &locale)
     .await?;
-    Ok(subscriber_id)
-}
-
-#[tracing::instrument(
-    name = "Checking for past subscription in the database",
-    skip(new_subscriber, transaction)
-)]
-pub async fn get_past_subscription(
-    transaction: &mut Transaction<'_, Postgres>,
-    new_subscriber: &NewSubscriber,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"
-        SELECT id FROM subscriptions WHERE email = $1
-        "#,
-        new_subscriber.email.as_ref(),
-    )
-    .fetch_optional(transaction)
-    .await?;
-    Ok(result.map(|r| r.id))
-}
-
-#[tracing::instrument(
-    name = "Checking for past subscription token in the database",
-    skip(subscriber_id, transaction)
-)]
-pub async fn get_past_subscription_token(
-    transaction: &mut Transaction<'_, Postgres>,
-    subscriber_id: Uuid,
-) -> Result<Option<SubscriptionToken>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"
-        SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1
-        "#,
-        subscriber_id,
-    )
-    .fetch_optional(transaction)
-    .await?;
-    Ok(result.map(|r| SubscriptionToken::parse(r.subscription_token).unwrap()))
-}
-
-#[derive(thiserror::Error)]
-pub enum SubscribeError {
-    #[error("{0}")]
-    ValidationError(String),
-    #[error(transparent)]
-    UnexpectedError(#[from] anyhow::Error),
-}
-impl ResponseError for SubscribeError {
-    fn status_code(&self) -> StatusCode {
-        match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
-            SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    if confirmation_email == ConfirmationEmail::Waitlisted {
+        if from_browser {
+            return Ok(see_other("/subscribe/waitlisted"));
         }
+        return Ok(HttpResponse::Accepted()
+            .content_type(ContentType::plaintext())
+            .body(locale.t("subscribe-waitlisted")));
     }
-}
-
-impl std::fmt::Debug for SubscribeError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        error_chain_fmt(self, f)
+    let delayed = confirmation_email == ConfirmationEmail::Delayed;
+    if from_browser {
+        FlashMessage::info(locale.t_with(
+            "subscribe-pending-sent-to",
+            &[("email", masked_email.as_str())],
+        ))
+        .send();
+        return Ok(see_other(if delayed {
+            "/subscribe/pending?delayed=true"
+        } else {
+            "/subscribe/pending"
+        }));
     }
-}
-pub struct StoreTokenError(sqlx::Error);
-
-impl std::fmt::Display for StoreTokenError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "A database error was encountered while \
-            trying to store a subscription token."
-        )
-    }
-}
-impl std::fmt::Debug for StoreTokenError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        error_chain_fmt(self, f)
-    }
-}
-impl std::error::Error for StoreTokenError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        Some(&self.0)
+    if delayed {
+        return Ok(HttpResponse::Accepted()
+            .content_type(ContentType::plaintext())
+            .body(locale.t("subscribe-delayed")));
     }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(locale.t("subscribe-success")))
 }
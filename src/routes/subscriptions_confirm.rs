@@ -1,96 +1,88 @@
+use crate::configuration::BrandingSettings;
 use crate::domain::SubscriptionToken;
-use crate::utils::error_chain_fmt;
-use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use crate::email_client::EmailClient;
+use crate::error::AppError;
+use crate::i18n::Locale;
+use crate::repositories::{Confirmation, PostgresSubscriberRepository};
+use crate::services::SubscriptionService;
+use crate::startup::PublicBaseUrl;
+use crate::tenancy::Tenant;
+use crate::utils::consent_evidence;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
 use anyhow::Context;
+use askama_actix::Template;
 use sqlx::PgPool;
-use std::fmt::Formatter;
-use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
+#[derive(Template)]
+#[template(path = "subscription_confirmed.html")]
+struct SubscriptionConfirmedTemplate {
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+}
+
+#[derive(Template)]
+#[template(path = "subscription_link_used.html")]
+struct SubscriptionLinkUsedTemplate {
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+}
+
 #[tracing::instrument(
     name = "Confirm a pending subscriber"
-    skip(parameters, pool)
+    skip(request, parameters, pool, email_client, base_url, branding, locale, tenant)
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn confirm(
+    request: HttpRequest,
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
-) -> Result<HttpResponse, SubscriptionConfirmationError> {
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<PublicBaseUrl>,
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, AppError> {
     let subscription_token = SubscriptionToken::parse(parameters.subscription_token.to_string())
-        .map_err(SubscriptionConfirmationError::ValidationError)?;
-
-    let id = get_subscriber_id_from_token(&pool, &subscription_token)
-        .await
-        .context("Failed to retrieve subscriber ID from subscription_tokens.")?
-        .ok_or_else(|| {
-            SubscriptionConfirmationError::UnauthorizedError(
-                "Failed to find token in database.".into(),
-            )
-        })?;
+        .map_err(|e| AppError::validation(e).with_code("invalid_subscription_token"))?;
 
-    confirm_subscriber(&pool, id).await.context("")?;
-    Ok(HttpResponse::Ok().finish())
-}
-
-#[tracing::instrument(
-    name = "Mark subscriber as confirmed"
-    skip(pool, subscriber_id)
-)]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id
+    let consent = consent_evidence(&request);
+    let confirmation = SubscriptionService::new(
+        &PostgresSubscriberRepository::new(&pool, tenant.id),
+        email_client.get_ref(),
+        tenant.public_base_url(&base_url.0),
     )
-    .execute(pool)
-    .await?;
-    Ok(())
-}
-
-#[tracing::instrument(
-    name = "Get subscriber_id from token"
-    skip(pool, subscription_token)
-)]
-pub async fn get_subscriber_id_from_token(
-    pool: &PgPool,
-    subscription_token: &SubscriptionToken,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
-        subscription_token.as_ref()
-    )
-    .fetch_optional(pool)
-    .await?;
-    Ok(result.map(|r| r.subscriber_id))
-}
-
-#[derive(thiserror::Error)]
-pub enum SubscriptionConfirmationError {
-    #[error("{0}")]
-    ValidationError(String),
-
-    #[error("{0}")]
-    UnauthorizedError(String),
-
-    #[error(transparent)]
-    UnexpectedError(#[from] anyhow::Error),
-}
-
-impl std::fmt::Debug for SubscriptionConfirmationError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        error_chain_fmt(self, f)
+    .with_consent(&consent)
+    .confirm(&subscription_token)
+    .await?
+    .ok_or_else(|| {
+        AppError::unauthorized("Failed to find token in database.")
+            .with_code("unknown_subscription_token")
+    })?;
+    if confirmation == Confirmation::AlreadyUsed {
+        let body = SubscriptionLinkUsedTemplate { branding, locale }
+            .render()
+            .context("Failed to render the link used page")?;
+        return Ok(HttpResponse::Gone()
+            .content_type(ContentType::html())
+            .body(body));
     }
+    render_confirmed(branding, locale)
 }
 
-impl ResponseError for SubscriptionConfirmationError {
-    fn status_code(&self) -> StatusCode {
-        match self {
-            SubscriptionConfirmationError::ValidationError(_) => StatusCode::BAD_REQUEST,
-            SubscriptionConfirmationError::UnauthorizedError(_) => StatusCode::UNAUTHORIZED,
-            SubscriptionConfirmationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        }
-    }
+pub(crate) fn render_confirmed(
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+) -> Result<HttpResponse, AppError> {
+    let body = SubscriptionConfirmedTemplate { branding, locale }
+        .render()
+        .context("Failed to render the confirmation page")?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
 }
@@ -0,0 +1,52 @@
+use crate::configuration::BrandingSettings;
+use crate::domain::SubscriptionToken;
+use crate::error::AppError;
+use crate::i18n::Locale;
+use crate::re_engagement::stay_subscribed;
+use crate::signing::SignedUrl;
+use crate::tenancy::Tenant;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama_actix::Template;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct StayParameters {
+    subscription_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "subscription_kept.html")]
+struct SubscriptionKeptTemplate {
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+}
+
+/// The link sent to inactive subscribers, see `crate::re_engagement`.
+#[tracing::instrument(
+    name = "Keep an inactive subscriber subscribed",
+    skip(_signed, parameters, pool, branding, locale, tenant)
+)]
+pub async fn stay(
+    _signed: SignedUrl,
+    parameters: web::Query<StayParameters>,
+    pool: web::Data<PgPool>,
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, AppError> {
+    let subscription_token = SubscriptionToken::parse(parameters.subscription_token.to_string())
+        .map_err(|e| AppError::validation(e).with_code("invalid_subscription_token"))?;
+
+    if !stay_subscribed(&pool, tenant.id, subscription_token.as_ref()).await? {
+        return Err(AppError::unauthorized("Failed to find token in database.")
+            .with_code("unknown_subscription_token"));
+    }
+    let body = SubscriptionKeptTemplate { branding, locale }
+        .render()
+        .context("Failed to render the stay subscribed page")?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
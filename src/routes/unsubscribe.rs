@@ -0,0 +1,144 @@
+use crate::configuration::BrandingSettings;
+use crate::domain::{SubscriptionToken, UnsubscribeReason};
+use crate::email_client::EmailClient;
+use crate::error::AppError;
+use crate::i18n::Locale;
+use crate::repositories::PostgresSubscriberRepository;
+use crate::routes::preferences::PREFERENCES_LINK_TTL;
+use crate::services::SubscriptionService;
+use crate::signing::UrlSigner;
+use crate::startup::PublicBaseUrl;
+use crate::tenancy::Tenant;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama_actix::Template;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscription_token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeFormData {
+    subscription_token: String,
+    /// Empty or missing when the survey is skipped.
+    #[serde(default)]
+    reason: String,
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribe.html")]
+struct UnsubscribeTemplate<'a> {
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    subscription_token: &'a str,
+    /// Signed, see `crate::routes::preferences`.
+    preferences_link: String,
+    reasons: [UnsubscribeReason; 3],
+}
+
+impl UnsubscribeTemplate<'_> {
+    fn reason_label(&self, reason: &UnsubscribeReason) -> String {
+        self.locale.t(&format!(
+            "unsubscribe-reason-{}",
+            reason.as_str().replace('_', "-")
+        ))
+    }
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribed.html")]
+struct UnsubscribedTemplate {
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+}
+
+fn parse_token(subscription_token: String) -> Result<SubscriptionToken, AppError> {
+    SubscriptionToken::parse(subscription_token)
+        .map_err(|e| AppError::validation(e).with_code("invalid_subscription_token"))
+}
+
+/// The page the unsubscribe link of every issue leads to. Following the link changes
+/// nothing - link checkers of mail providers visit it too - the form does.
+#[tracing::instrument(name = "Show the unsubscribe page", skip_all)]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    signer: web::Data<UrlSigner>,
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+) -> Result<HttpResponse, AppError> {
+    let subscription_token = parse_token(parameters.into_inner().subscription_token)?;
+    render_unsubscribe_form(branding, locale, subscription_token.as_ref(), &signer)
+}
+
+pub(crate) fn render_unsubscribe_form(
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    subscription_token: &str,
+    signer: &UrlSigner,
+) -> Result<HttpResponse, AppError> {
+    let preferences_link = signer.sign(
+        &format!("/preferences?subscription_token={}", subscription_token),
+        PREFERENCES_LINK_TTL,
+    );
+    let body = UnsubscribeTemplate {
+        branding,
+        locale,
+        subscription_token,
+        preferences_link,
+        reasons: UnsubscribeReason::ALL,
+    }
+    .render()
+    .context("Failed to render the unsubscribe page")?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe",
+    skip(form, pool, email_client, base_url, branding, locale, tenant),
+    fields(reason = %form.reason)
+)]
+pub async fn unsubscribe(
+    form: web::Form<UnsubscribeFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<PublicBaseUrl>,
+    branding: web::Data<BrandingSettings>,
+    locale: Locale,
+    tenant: web::ReqData<Tenant>,
+) -> Result<HttpResponse, AppError> {
+    let UnsubscribeFormData {
+        subscription_token,
+        reason,
+    } = form.into_inner();
+    let subscription_token = parse_token(subscription_token)?;
+    let reason = match reason.as_str() {
+        "" => None,
+        reason => Some(
+            UnsubscribeReason::parse(reason)
+                .map_err(|e| AppError::validation(e).with_code("invalid_unsubscribe_reason"))?,
+        ),
+    };
+
+    SubscriptionService::new(
+        &PostgresSubscriberRepository::new(&pool, tenant.id),
+        email_client.get_ref(),
+        tenant.public_base_url(&base_url.0),
+    )
+    .unsubscribe(&subscription_token, reason)
+    .await?
+    .ok_or_else(|| {
+        AppError::unauthorized("Failed to find token in database.")
+            .with_code("unknown_subscription_token")
+    })?;
+    let body = UnsubscribedTemplate { branding, locale }
+        .render()
+        .context("Failed to render the unsubscribed page")?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
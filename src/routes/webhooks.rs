@@ -0,0 +1,117 @@
+use crate::bounces::{apply_bounce_policy, BounceCategory};
+use crate::complaints::handle_complaint;
+use crate::configuration::NewsletterSettings;
+use crate::pii::PiiEncryption;
+use crate::utils::e500;
+use crate::webhooks::{VerifiedWebhook, WebhookError};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The fields we rely on in the events sent by the email provider.
+/// The whole payload is stored, so we can look at the other fields later on.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailProviderEvent {
+    /// `Delivery`, `Bounce`, `SpamComplaint`, `Open`...
+    record_type: String,
+    recipient: Option<String>,
+    email: Option<String>,
+    /// The id of the issue the email belongs to, if any.
+    tag: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Record an email provider event",
+    skip(webhook, pool, settings, pii),
+    fields(event_id = %webhook.event_id)
+)]
+pub async fn record_email_provider_event(
+    webhook: VerifiedWebhook<serde_json::Value>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<NewsletterSettings>,
+    pii: web::Data<PiiEncryption>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let event: EmailProviderEvent = serde_json::from_value(webhook.payload.clone())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let recipient = event.recipient.or(event.email);
+    // Matched against the sealed subscribers, see `crate::pii`.
+    let blind_index = recipient
+        .as_deref()
+        .zip(pii.cipher())
+        .map(|(recipient, cipher)| cipher.blind_index(recipient));
+    let bounce_category =
+        (event.record_type == "Bounce").then(|| BounceCategory::classify(&webhook.payload));
+    let issue_id = event.tag.and_then(|tag| Uuid::parse_str(&tag).ok());
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // Attributed to the tenant of the issue, or else to the only tenant the recipient
+    // subscribed to.
+    let stored = sqlx::query!(
+        r#"
+        INSERT INTO email_provider_events (
+            event_id, record_type, recipient, recipient_blind_index, payload,
+            bounce_category, received_at, tenant_id
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now(), COALESCE(
+            (SELECT tenant_id FROM newsletter_issues WHERE newsletter_issue_id = $7),
+            (
+                SELECT (array_agg(DISTINCT tenant_id))[1]
+                FROM subscriptions
+                WHERE lower(email) = lower($3) OR email_blind_index = $4
+                HAVING COUNT(DISTINCT tenant_id) = 1
+            )
+        ))
+        ON CONFLICT DO NOTHING
+        "#,
+        webhook.event_id,
+        event.record_type,
+        recipient,
+        blind_index,
+        webhook.payload,
+        bounce_category.map(|c| c.as_str()),
+        issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store an email provider event")
+    .map_err(e500)?;
+    // An event we have already seen has already been acted on. The event is only stored
+    // along with what it did: should acting on it fail, the retry is not a replay.
+    if stored.rows_affected() == 0 {
+        return Err(WebhookError::Replayed(webhook.event_id).into());
+    }
+    if let Some(recipient) = &recipient {
+        if let Some(category) = bounce_category {
+            apply_bounce_policy(
+                &mut transaction,
+                category,
+                recipient,
+                blind_index.as_deref(),
+                issue_id,
+            )
+            .await
+            .map_err(e500)?;
+        } else if event.record_type == "SpamComplaint" {
+            handle_complaint(
+                &mut transaction,
+                &settings,
+                recipient,
+                blind_index.as_deref(),
+                issue_id,
+            )
+            .await
+            .map_err(e500)?;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the email provider event")
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,378 @@
+//! Recurring maintenance jobs, each run on its own interval next to the API.
+//!
+//! A run which fails is logged and retried at the next tick. Every run is traced in its
+//! own span, which carries how many runs of the job failed so far.
+use crate::chat_notifications::post_chat_notifications;
+use crate::configuration::Settings;
+use crate::email_outbox::deliver_outbox_emails;
+use crate::idempotency::delete_expired_keys;
+use crate::pii::encrypt_subscribers;
+use crate::re_engagement::re_engage_inactive_subscribers;
+use crate::read_only::{is_read_only, ForcedReadOnly};
+use crate::repositories::{prune_pending_subscriptions, purge_deleted_subscribers};
+use crate::retention::apply_retention_policy;
+use crate::signing::UrlSigner;
+use crate::startup::get_connection_pool;
+use crate::weekly_report::send_weekly_reports;
+use futures_util::future::BoxFuture;
+use sqlx::PgPool;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::field::Empty;
+use tracing::Instrument;
+
+type JobFn = Box<dyn Fn(PgPool) -> BoxFuture<'static, Result<u64, anyhow::Error>> + Send + Sync>;
+
+pub struct ScheduledJob {
+    name: &'static str,
+    interval: Duration,
+    run: JobFn,
+}
+
+impl ScheduledJob {
+    /// `run` returns how many rows it processed, for the logs.
+    pub fn new<F, Fut>(name: &'static str, interval: Duration, run: F) -> Self
+    where
+        F: Fn(PgPool) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<u64, anyhow::Error>> + Send + 'static,
+    {
+        Self {
+            name,
+            interval,
+            run: Box::new(move |pool| Box::pin(run(pool))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// How a job has fared since the scheduler started.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct JobStats {
+    pub runs: u64,
+    pub failures: u64,
+    /// Reset by every successful run.
+    pub consecutive_failures: u64,
+}
+
+impl JobStats {
+    fn record(&mut self, succeeded: bool) {
+        self.runs += 1;
+        if succeeded {
+            self.consecutive_failures = 0;
+        } else {
+            self.failures += 1;
+            self.consecutive_failures += 1;
+        }
+    }
+}
+
+pub struct Scheduler {
+    pool: PgPool,
+    jobs: Vec<ScheduledJob>,
+    read_only: ForcedReadOnly,
+}
+
+impl Scheduler {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            jobs: Vec::new(),
+            read_only: ForcedReadOnly(false),
+        }
+    }
+
+    /// Skip every run while `application.read_only` is set, not only while read-only
+    /// mode is switched on from the admin panel.
+    pub fn with_forced_read_only(mut self, read_only: ForcedReadOnly) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn with_job(mut self, job: ScheduledJob) -> Self {
+        self.jobs.push(job);
+        self
+    }
+
+    pub fn jobs(&self) -> impl Iterator<Item = &ScheduledJob> {
+        self.jobs.iter()
+    }
+
+    /// Run every job on its interval, the first run happening right away.
+    pub async fn run_until_stopped(self) -> Result<(), anyhow::Error> {
+        let tasks: Vec<_> = self
+            .jobs
+            .into_iter()
+            .map(|job| tokio::spawn(job_loop(self.pool.clone(), job, self.read_only)))
+            .collect();
+        for task in tasks {
+            task.await?;
+        }
+        Ok(())
+    }
+}
+
+async fn job_loop(pool: PgPool, job: ScheduledJob, read_only: ForcedReadOnly) {
+    let mut interval = tokio::time::interval(job.interval);
+    // A run slower than the interval delays the next one rather than stacking them up.
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut stats = JobStats::default();
+    loop {
+        interval.tick().await;
+        // Skipped rather than delayed: the next tick comes soon enough.
+        if is_read_only(&pool, read_only).await.unwrap_or(false) {
+            tracing::info!(
+                job.name = job.name,
+                "Skipping a scheduled job in read-only mode"
+            );
+            continue;
+        }
+        run_job(&pool, &job, &mut stats).await;
+    }
+}
+
+/// Run a job once, recording the outcome in its stats.
+pub async fn run_job(pool: &PgPool, job: &ScheduledJob, stats: &mut JobStats) {
+    let span = tracing::info_span!(
+        "Run a scheduled job",
+        job.name = job.name,
+        job.processed = Empty,
+        job.runs = Empty,
+        job.failures = Empty,
+        job.consecutive_failures = Empty,
+    );
+    let outcome = (job.run)(pool.clone()).instrument(span.clone()).await;
+    stats.record(outcome.is_ok());
+    span.record("job.runs", stats.runs);
+    span.record("job.failures", stats.failures);
+    span.record("job.consecutive_failures", stats.consecutive_failures);
+    let _guard = span.enter();
+    match outcome {
+        Ok(processed) => {
+            span.record("job.processed", processed);
+            tracing::info!("The scheduled job completed");
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "The scheduled job failed"
+            );
+        }
+    }
+}
+
+/// The jobs enabled in the configuration.
+pub fn build_scheduler(pool: PgPool, configuration: &Settings) -> Scheduler {
+    let settings = &configuration.scheduler;
+    let mut scheduler = Scheduler::new(pool)
+        .with_forced_read_only(ForcedReadOnly(configuration.application.read_only));
+    // The settings were validated: the keys are fine.
+    let cipher = configuration
+        .pii_encryption
+        .cipher()
+        .ok()
+        .flatten()
+        .map(Arc::new);
+    if let Some(interval) = settings.prune_pending_subscriptions.interval() {
+        let ttl = configuration.subscribers.pending_subscription_ttl();
+        scheduler = scheduler.with_job(ScheduledJob::new(
+            "prune_pending_subscriptions",
+            interval,
+            move |pool| async move { prune_pending_subscriptions(&pool, ttl).await },
+        ));
+    }
+    if let Some(interval) = settings.purge_deleted_subscribers.interval() {
+        let retention = configuration.subscribers.deleted_subscriber_retention();
+        scheduler = scheduler.with_job(ScheduledJob::new(
+            "purge_deleted_subscribers",
+            interval,
+            move |pool| async move { purge_deleted_subscribers(&pool, retention).await },
+        ));
+    }
+    if let Some(interval) = settings.clean_up_idempotency_keys.interval() {
+        let idempotency = configuration.idempotency.clone();
+        scheduler = scheduler.with_job(ScheduledJob::new(
+            "clean_up_idempotency_keys",
+            interval,
+            move |pool| {
+                let idempotency = idempotency.clone();
+                async move { delete_expired_keys(&pool, &idempotency).await }
+            },
+        ));
+    }
+    if let Some(interval) = settings.re_engage_inactive_subscribers.interval() {
+        let email_client = Arc::new(configuration.email_client.clone().client());
+        let signer = Arc::new(UrlSigner::new(&configuration.signing));
+        let base_url: Arc<str> = configuration.application.public_base_url().into();
+        let re_engagement = Arc::new(configuration.re_engagement.clone());
+        let cipher = cipher.clone();
+        scheduler = scheduler.with_job(ScheduledJob::new(
+            "re_engage_inactive_subscribers",
+            interval,
+            move |pool| {
+                let (email_client, signer, base_url, re_engagement, cipher) = (
+                    email_client.clone(),
+                    signer.clone(),
+                    base_url.clone(),
+                    re_engagement.clone(),
+                    cipher.clone(),
+                );
+                async move {
+                    re_engage_inactive_subscribers(
+                        &pool,
+                        email_client.as_ref(),
+                        &signer,
+                        &base_url,
+                        &re_engagement,
+                        cipher.as_deref(),
+                    )
+                    .await
+                }
+            },
+        ));
+    }
+    if let Some(interval) = settings.deliver_outbox_emails.interval() {
+        let email_client = Arc::new(configuration.email_client.clone().client());
+        scheduler = scheduler.with_job(ScheduledJob::new(
+            "deliver_outbox_emails",
+            interval,
+            move |pool| {
+                let email_client = email_client.clone();
+                async move { deliver_outbox_emails(&pool, email_client.as_ref()).await }
+            },
+        ));
+    }
+    if let Some(interval) = settings.apply_retention_policy.interval() {
+        let retention = Arc::new(configuration.retention.clone());
+        scheduler = scheduler.with_job(ScheduledJob::new(
+            "apply_retention_policy",
+            interval,
+            move |pool| {
+                let retention = retention.clone();
+                async move {
+                    let report =
+                        apply_retention_policy(&pool, &retention, retention.dry_run).await?;
+                    Ok(report.total())
+                }
+            },
+        ));
+    }
+    if let (Some(interval), Some(notifier)) = (
+        settings.post_chat_notifications.interval(),
+        configuration.chat_notifications.notifier(),
+    ) {
+        let notifier = Arc::new(notifier);
+        let chat_notifications = Arc::new(configuration.chat_notifications.clone());
+        scheduler = scheduler.with_job(ScheduledJob::new(
+            "post_chat_notifications",
+            interval,
+            move |pool| {
+                let (notifier, chat_notifications) = (notifier.clone(), chat_notifications.clone());
+                async move { post_chat_notifications(&pool, &notifier, &chat_notifications).await }
+            },
+        ));
+    }
+    if let Some(interval) = settings.send_weekly_reports.interval() {
+        scheduler = scheduler.with_job(ScheduledJob::new(
+            "send_weekly_reports",
+            interval,
+            move |pool| async move { send_weekly_reports(&pool).await },
+        ));
+    }
+    if let Some(cipher) = cipher {
+        let interval = Duration::from_secs(configuration.pii_encryption.interval_seconds);
+        scheduler = scheduler.with_job(ScheduledJob::new(
+            "encrypt_subscribers",
+            interval,
+            move |pool| {
+                let cipher = cipher.clone();
+                async move { encrypt_subscribers(&pool, &cipher).await }
+            },
+        ));
+    }
+    scheduler
+}
+
+pub async fn run_scheduler_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    configuration
+        .database
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    configuration
+        .scheduler
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    configuration
+        .re_engagement
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    configuration
+        .pii_encryption
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    configuration
+        .chat_notifications
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+    let connection_pool = get_connection_pool(&configuration.database);
+    build_scheduler(connection_pool, &configuration)
+        .run_until_stopped()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_job, JobStats, ScheduledJob};
+    use sqlx::postgres::PgPoolOptions;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn failures_are_counted_until_a_run_succeeds() {
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let failing = Arc::new(AtomicBool::new(true));
+        let job = ScheduledJob::new("flaky", Duration::from_secs(60), {
+            let failing = failing.clone();
+            move |_| {
+                let failing = failing.load(Ordering::SeqCst);
+                async move {
+                    if failing {
+                        anyhow::bail!("The database is down");
+                    }
+                    Ok(3)
+                }
+            }
+        });
+        let mut stats = JobStats::default();
+
+        run_job(&pool, &job, &mut stats).await;
+        run_job(&pool, &job, &mut stats).await;
+        assert_eq!(
+            stats,
+            JobStats {
+                runs: 2,
+                failures: 2,
+                consecutive_failures: 2
+            }
+        );
+
+        failing.store(false, Ordering::SeqCst);
+        run_job(&pool, &job, &mut stats).await;
+        assert_eq!(
+            stats,
+            JobStats {
+                runs: 3,
+                failures: 2,
+                consecutive_failures: 0
+            }
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Full-text search across subscribers and newsletter issues, for the admin search page.
+//!
+//! Sealed subscribers - see `crate::pii` - are only found by their whole address.
+use crate::domain::SubscriptionStatus;
+use crate::pii::{mask_email, mask_name, reveal_email, reveal_name, Masked, PiiCipher};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How many results of each kind are shown.
+const RESULTS: i64 = 20;
+/// Words past this are ignored.
+const MAX_WORDS: usize = 8;
+
+/// What the admin typed, as a Postgres `tsquery` matching every word as a prefix -
+/// `ursu exam` finds `ursula@example.com`.
+#[derive(Debug, PartialEq)]
+pub struct SearchQuery(String);
+
+impl SearchQuery {
+    /// `None` if there is nothing to search for.
+    /// Only letters and digits are kept, so the query is always a valid `tsquery`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let terms: Vec<String> = s
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .take(MAX_WORDS)
+            .map(|word| format!("{}:*", word.to_lowercase()))
+            .collect();
+        (!terms.is_empty()).then(|| Self(terms.join(" & ")))
+    }
+}
+
+impl SearchQuery {
+    /// The words searched for, as they are matched.
+    pub fn terms(&self) -> impl Iterator<Item = &str> {
+        self.0.split(" & ").map(|term| term.trim_end_matches(":*"))
+    }
+}
+
+impl AsRef<str> for SearchQuery {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+pub struct SubscriberHit {
+    pub id: Uuid,
+    pub email: String,
+    pub name: Option<String>,
+    pub status: SubscriptionStatus,
+}
+
+impl Masked for SubscriberHit {
+    fn masked(self) -> Self {
+        Self {
+            email: mask_email(&self.email),
+            name: self.name.as_deref().map(mask_name),
+            ..self
+        }
+    }
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: Option<String>,
+    status: SubscriptionStatus,
+    email_encrypted: Option<Vec<u8>>,
+    name_encrypted: Option<Vec<u8>>,
+}
+
+impl SubscriberRow {
+    fn reveal(self, cipher: Option<&PiiCipher>) -> Result<SubscriberHit, anyhow::Error> {
+        Ok(SubscriberHit {
+            id: self.id,
+            email: reveal_email(cipher, self.id, self.email, self.email_encrypted.as_deref())?,
+            name: reveal_name(cipher, self.id, self.name, self.name_encrypted.as_deref())?,
+            status: self.status,
+        })
+    }
+}
+
+pub struct IssueHit {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub published_at: String,
+}
+
+pub struct SearchResults {
+    /// The best matches first.
+    pub subscribers: Vec<SubscriberHit>,
+    /// The best matches first. Titles weigh more than content.
+    pub issues: Vec<IssueHit>,
+}
+
+/// `typed` is what the admin typed, matched as a whole address against sealed subscribers.
+#[tracing::instrument(name = "Search subscribers and issues", skip(pool, typed, cipher))]
+pub async fn search(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    query: &SearchQuery,
+    typed: &str,
+    cipher: Option<&PiiCipher>,
+) -> Result<SearchResults, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT
+            id, email, name, status AS "status: SubscriptionStatus",
+            email_encrypted, name_encrypted
+        FROM subscriptions
+        WHERE (search_vector @@ to_tsquery('simple', $1) OR email_blind_index = $4)
+            AND deleted_at IS NULL
+            AND tenant_id = $3
+        ORDER BY
+            COALESCE(email_blind_index = $4, false) DESC,
+            ts_rank(search_vector, to_tsquery('simple', $1)) DESC,
+            email
+        LIMIT $2
+        "#,
+        query.as_ref(),
+        RESULTS,
+        *tenant_id,
+        cipher.map(|c| c.blind_index(typed.trim()))
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to search subscribers")?
+    .into_iter()
+    .map(|row| row.reveal(cipher))
+    .collect::<Result<_, _>>()?;
+    let issues = sqlx::query_as!(
+        IssueHit,
+        r#"
+        SELECT newsletter_issue_id, title, published_at
+        FROM newsletter_issues
+        WHERE search_vector @@ to_tsquery('english', $1) AND tenant_id = $3
+        ORDER BY
+            ts_rank(search_vector, to_tsquery('english', $1)) DESC,
+            published_at DESC
+        LIMIT $2
+        "#,
+        query.as_ref(),
+        RESULTS,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to search newsletter issues")?;
+    Ok(SearchResults {
+        subscribers,
+        issues,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SearchQuery;
+    use claim::assert_none;
+
+    #[test]
+    fn every_word_is_matched_as_a_prefix() {
+        assert_eq!(
+            SearchQuery::parse("Ursula exam").unwrap().as_ref(),
+            "ursula:* & exam:*"
+        );
+    }
+
+    #[test]
+    fn tsquery_operators_are_dropped() {
+        assert_eq!(
+            SearchQuery::parse("a' | !b:* & (c)").unwrap().as_ref(),
+            "a:* & b:* & c:*"
+        );
+        assert_eq!(
+            SearchQuery::parse("ursula@example.com").unwrap().as_ref(),
+            "ursula:* & example:* & com:*"
+        );
+    }
+
+    #[test]
+    fn the_terms_are_the_normalized_words() {
+        let query = SearchQuery::parse("Ursula, exam!").unwrap();
+        assert_eq!(query.terms().collect::<Vec<_>>(), ["ursula", "exam"]);
+    }
+
+    #[test]
+    fn a_query_without_words_is_rejected() {
+        assert_none!(SearchQuery::parse(""));
+        assert_none!(SearchQuery::parse(" @&! "));
+    }
+}
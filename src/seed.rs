@@ -0,0 +1,183 @@
+//! Fake but realistic data for local development and for load testing the delivery
+//! queue. Everything goes through the domain layer, as real sign-ups and issues do.
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::repositories::{
+    enqueue_issue_deliveries, PostgresSubscriberRepository, SubscriberRepository,
+};
+use crate::services::{NewIssue, NewsletterService};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use sqlx::PgPool;
+use std::fmt::Formatter;
+use std::ops::Range;
+
+/// The share of seeded subscribers who confirm their subscription.
+const CONFIRMED_SHARE: f64 = 0.8;
+
+const FIRST_NAMES: [&str; 16] = [
+    "Ada", "Alan", "Barbara", "Brian", "Edsger", "Frances", "Grace", "Guido", "Hedy", "John",
+    "Ken", "Linus", "Margaret", "Niklaus", "Radia", "Sophie",
+];
+const LAST_NAMES: [&str; 16] = [
+    "Allen",
+    "Backus",
+    "Dijkstra",
+    "Hamilton",
+    "Hopper",
+    "Kernighan",
+    "Knuth",
+    "Lamarr",
+    "Liskov",
+    "Lovelace",
+    "McCarthy",
+    "Perlman",
+    "Ritchie",
+    "Thompson",
+    "Turing",
+    "Wirth",
+];
+/// What issues are written with, lorem ipsum style.
+const WORDS: [&str; 24] = [
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "consectetur",
+    "adipiscing",
+    "elit",
+    "sed",
+    "do",
+    "eiusmod",
+    "tempor",
+    "incididunt",
+    "ut",
+    "labore",
+    "et",
+    "dolore",
+    "magna",
+    "aliqua",
+    "enim",
+    "ad",
+    "minim",
+    "veniam",
+    "quis",
+];
+
+#[derive(Debug, Default)]
+pub struct SeedReport {
+    pub confirmed_subscribers: usize,
+    pub pending_subscribers: usize,
+    /// Generated email addresses which were already taken.
+    pub already_subscribed: usize,
+    pub issues: usize,
+}
+
+impl std::fmt::Display for SeedReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Seed report")?;
+        writeln!(f, "  confirmed subscribers: {}", self.confirmed_subscribers)?;
+        writeln!(f, "  pending subscribers: {}", self.pending_subscribers)?;
+        writeln!(f, "  already subscribed: {}", self.already_subscribed)?;
+        writeln!(f, "  published issues: {}", self.issues)
+    }
+}
+
+/// Sign up `subscribers` subscribers - most of them confirmed - then publish `issues`
+/// issues, which are queued for delivery to the confirmed ones.
+#[tracing::instrument(name = "Seeding demo data", skip(pool))]
+pub async fn seed(
+    pool: &PgPool,
+    subscribers: usize,
+    issues: usize,
+) -> Result<SeedReport, anyhow::Error> {
+    let mut report = SeedReport::default();
+    let repository = PostgresSubscriberRepository::new(pool, TenantId::DEFAULT);
+    for _ in 0..subscribers {
+        let new_subscriber = fake_subscriber()?;
+        let pending = repository
+            .store_pending_subscription(&new_subscriber)
+            .await?
+            .pending()?;
+        if pending.already_existed {
+            report.already_subscribed += 1;
+        } else if rand::thread_rng().gen_bool(CONFIRMED_SHARE) {
+            repository
+                .confirm_subscription(&pending.subscription_token)
+                .await?;
+            report.confirmed_subscribers += 1;
+        } else {
+            report.pending_subscribers += 1;
+        }
+    }
+    for _ in 0..issues {
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        let issue_id = NewsletterService::new(&mut transaction, TenantId::DEFAULT)
+            .publish(&fake_issue()?)
+            .await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the SQL transaction to publish a newsletter issue.")?;
+        enqueue_issue_deliveries(pool, issue_id).await?;
+        report.issues += 1;
+    }
+    Ok(report)
+}
+
+fn fake_subscriber() -> Result<NewSubscriber, anyhow::Error> {
+    let mut rng = rand::thread_rng();
+    let first_name = FIRST_NAMES.choose(&mut rng).unwrap();
+    let last_name = LAST_NAMES.choose(&mut rng).unwrap();
+    let email = format!(
+        "{}.{}.{}@example.com",
+        first_name.to_lowercase(),
+        last_name.to_lowercase(),
+        rng.gen_range(1000..10000)
+    );
+    Ok(NewSubscriber {
+        email: SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?,
+        name: Some(
+            SubscriberName::parse(format!("{} {}", first_name, last_name))
+                .map_err(anyhow::Error::msg)?,
+        ),
+        locale: None,
+    })
+}
+
+/// Between `words.start` and `words.end` random words, the first one capitalized.
+fn sentence(rng: &mut impl Rng, words: Range<usize>) -> String {
+    let words = rng.gen_range(words);
+    let sentence = (0..words)
+        .map(|_| *WORDS.choose(rng).unwrap())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut chars = sentence.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => sentence,
+    }
+}
+
+fn fake_issue() -> Result<NewIssue, anyhow::Error> {
+    let mut rng = rand::thread_rng();
+    let title = sentence(&mut rng, 3..8);
+    let paragraphs: Vec<String> = (0..rng.gen_range(2..5))
+        .map(|_| {
+            (0..rng.gen_range(3..6))
+                .map(|_| format!("{}.", sentence(&mut rng, 6..14)))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    let html_content = paragraphs
+        .iter()
+        .map(|p| format!("<p>{}</p>", p))
+        .collect::<String>();
+    NewIssue::parse(title, paragraphs.join("\n\n"), html_content).map_err(anyhow::Error::msg)
+}
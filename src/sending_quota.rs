@@ -0,0 +1,312 @@
+//! Global caps on the number of emails sent, to stay within the limits of the email
+//! provider's plan - going over them gets the account suspended, not just throttled.
+//!
+//! Quotas are shared by every tenant and count the deliveries attempted since the start
+//! of the current hour or day, in UTC. Skipped deliveries never reach the provider and
+//! are not counted. Each email is counted before it is sent, and only sent if the count
+//! stays within the quotas - see `SendingQuota::try_reserve`.
+//!
+//! A new sending domain can also be warmed up: its daily volume ramps up week after week,
+//! and the deliveries over the cap of the day wait in the queue for the next days. Each
+//! sending domain - the configured sender's, and those of the tenants sending from their
+//! own address - builds its reputation on its own, so each is warmed up on its own: the
+//! configured sender's from `newsletters.warm_up_start_date`, the others from the day they
+//! first sent.
+//!
+//! Per-domain limits throttle the emails sent to each recipient domain instead: once a
+//! domain got its share of the current minute, the worker picks deliveries to the
+//! other domains.
+use crate::configuration::NewsletterSettings;
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use chrono::{NaiveDate, Utc};
+use sqlx::{PgExecutor, PgPool};
+
+#[derive(Clone, Debug, Default)]
+pub struct SendingQuota {
+    pub per_hour: Option<u64>,
+    pub per_day: Option<u64>,
+    pub warm_up: Option<WarmUp>,
+    /// The domain of the configured sender, lowercased.
+    pub sender_domain: String,
+    /// The most emails sent a minute to each of these domains.
+    pub per_domain_per_minute: Vec<(String, u64)>,
+    /// The most emails sent a minute to each of the domains not listed above.
+    pub other_domains_per_minute: Option<u64>,
+}
+
+/// A ramp-up of the daily volume, from the day a new sending domain started sending.
+#[derive(Clone, Debug)]
+pub struct WarmUp {
+    /// The day the domain of the configured sender started sending.
+    pub start_date: NaiveDate,
+    /// The cap of each week, in order.
+    pub daily_caps: Vec<u64>,
+}
+
+impl WarmUp {
+    /// The most emails that can be sent on `date` from a domain which started sending on
+    /// `start_date`, if its warm-up is not over.
+    pub fn daily_cap(&self, start_date: NaiveDate, date: NaiveDate) -> Option<u64> {
+        let days = (date - start_date).num_days().max(0);
+        self.daily_caps.get((days / 7) as usize).copied()
+    }
+}
+
+impl SendingQuota {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Panics on an invalid warm-up schedule: check it with `NewsletterSettings::validate`.
+    pub fn from_settings(settings: &NewsletterSettings, sender: &SubscriberEmail) -> Self {
+        Self {
+            per_hour: settings.hourly_quota(),
+            per_day: settings.daily_quota(),
+            warm_up: settings.warm_up().expect("Invalid warm-up schedule"),
+            sender_domain: sending_domain(sender),
+            per_domain_per_minute: settings
+                .domain_limits
+                .iter()
+                .map(|l| (l.domain.to_lowercase(), l.per_minute))
+                .collect(),
+            other_domains_per_minute: settings.other_domains_per_minute(),
+        }
+    }
+
+    /// The domain emails from `sender` are sent from - the configured sender's if `None`.
+    pub fn sending_domain_of(&self, sender: Option<&SubscriberEmail>) -> String {
+        sender
+            .map(sending_domain)
+            .unwrap_or_else(|| self.sender_domain.clone())
+    }
+
+    /// The most emails that can be sent on `date` from `domain`, which first sent on
+    /// `first_sent_on`, if its warm-up is not over.
+    fn warm_up_cap(
+        &self,
+        domain: &str,
+        first_sent_on: Option<NaiveDate>,
+        date: NaiveDate,
+    ) -> Option<u64> {
+        let warm_up = self.warm_up.as_ref()?;
+        let start_date = if domain == self.sender_domain {
+            warm_up.start_date
+        } else {
+            first_sent_on.unwrap_or(date)
+        };
+        warm_up.daily_cap(start_date, date)
+    }
+
+    /// The sending domains which reached the cap of their warm-up today: the deliveries
+    /// sent from them wait in the queue for the next days.
+    #[tracing::instrument(name = "Find the sending domains over their warm-up cap", skip(pool))]
+    pub async fn domains_over_warm_up_cap(
+        &self,
+        pool: &PgPool,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        if self.warm_up.is_none() {
+            return Ok(Vec::new());
+        }
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                d.sender_domain AS "domain!",
+                COUNT(*) AS "sent!",
+                s.first_sent_on AS "first_sent_on?"
+            FROM issue_deliveries d
+            LEFT JOIN sending_domains s ON s.domain = d.sender_domain
+            WHERE
+                d.sender_domain IS NOT NULL AND
+                d.outcome <> 'skipped' AND
+                d.attempted_at >= date_trunc('day', now(), 'UTC')
+            GROUP BY d.sender_domain, s.first_sent_on
+            "#
+        )
+        .fetch_all(pool)
+        .await
+        .context("Failed to count the emails sent from each domain")?;
+        let today = Utc::now().date_naive();
+        Ok(rows
+            .into_iter()
+            .filter(|r| {
+                self.warm_up_cap(&r.domain, r.first_sent_on, today)
+                    .is_some_and(|cap| r.sent as u64 >= cap)
+            })
+            .map(|r| r.domain)
+            .collect())
+    }
+
+    /// Whether the worker must stop sending until the current hour - or day - is over.
+    #[tracing::instrument(name = "Check the sending quota", skip(pool))]
+    pub async fn is_exhausted(&self, pool: &PgPool) -> Result<bool, anyhow::Error> {
+        if self.per_hour.is_none() && self.per_day.is_none() {
+            return Ok(false);
+        }
+        let sent = sqlx::query!(
+            r#"
+            SELECT
+                COALESCE(SUM(sent) FILTER (
+                    WHERE period = 'hour' AND period_start = date_trunc('hour', now(), 'UTC')
+                ), 0)::bigint AS "this_hour!",
+                COALESCE(SUM(sent) FILTER (WHERE period = 'day'), 0)::bigint AS "today!"
+            FROM sent_email_counts
+            WHERE period_start >= date_trunc('day', now(), 'UTC')
+            "#
+        )
+        .fetch_one(pool)
+        .await
+        .context("Failed to count the emails sent")?;
+        let reached = |quota: Option<u64>, sent: i64| quota.is_some_and(|q| sent as u64 >= q);
+        Ok(reached(self.per_hour, sent.this_hour) || reached(self.per_day, sent.today))
+    }
+
+    /// Count one more email sent this hour and today, if the quotas leave room for it.
+    /// The counts are incremented first, then checked: of the workers racing for the last
+    /// email of the quota, only one gets it. The others take their increment back.
+    #[tracing::instrument(name = "Reserve an email of the sending quota", skip(pool))]
+    pub async fn try_reserve(&self, pool: &PgPool) -> Result<bool, anyhow::Error> {
+        if self.per_hour.is_none() && self.per_day.is_none() {
+            return Ok(true);
+        }
+        let counts = sqlx::query!(
+            r#"
+            INSERT INTO sent_email_counts (period, period_start, sent)
+            VALUES
+                ('hour', date_trunc('hour', now(), 'UTC'), 1),
+                ('day', date_trunc('day', now(), 'UTC'), 1)
+            ON CONFLICT (period, period_start)
+            DO UPDATE SET sent = sent_email_counts.sent + 1
+            RETURNING period, period_start, sent
+            "#
+        )
+        .fetch_all(pool)
+        .await
+        .context("Failed to count an email against the sending quota")?;
+        let over = counts.iter().any(|c| {
+            let quota = match c.period.as_str() {
+                "hour" => self.per_hour,
+                _ => self.per_day,
+            };
+            quota.is_some_and(|q| c.sent as u64 > q)
+        });
+        if over {
+            let (periods, starts): (Vec<_>, Vec<_>) = counts
+                .into_iter()
+                .map(|c| (c.period, c.period_start))
+                .unzip();
+            sqlx::query!(
+                r#"
+                UPDATE sent_email_counts
+                SET sent = sent - 1
+                WHERE (period, period_start) IN (
+                    SELECT * FROM UNNEST($1::text[], $2::timestamptz[])
+                )
+                "#,
+                &periods,
+                &starts
+            )
+            .execute(pool)
+            .await
+            .context("Failed to take an email back from the sending quota")?;
+        }
+        Ok(!over)
+    }
+}
+
+/// The part of the address after the `@`, lowercased - as `recipient_domain` does in SQL.
+fn sending_domain(sender: &SubscriberEmail) -> String {
+    let address: &str = sender.as_ref();
+    address
+        .rsplit('@')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// The emails sent today, plus those still queued - as far as publishing is concerned,
+/// they will all be sent today.
+#[tracing::instrument(name = "Count the emails sent or queued today", skip(executor))]
+pub async fn emails_scheduled_today(executor: impl PgExecutor<'_>) -> Result<u64, anyhow::Error> {
+    let scheduled = sqlx::query!(
+        r#"
+        SELECT (
+            SELECT COUNT(*)
+            FROM issue_deliveries
+            WHERE outcome <> 'skipped' AND attempted_at >= date_trunc('day', now(), 'UTC')
+        ) + (
+            SELECT COUNT(*) FROM issue_delivery_queue
+        ) AS "scheduled!"
+        "#
+    )
+    .fetch_one(executor)
+    .await
+    .context("Failed to count the emails sent or queued today")?
+    .scheduled;
+    Ok(scheduled as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SendingQuota, WarmUp};
+    use crate::domain::SubscriberEmail;
+    use chrono::NaiveDate;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2022, 5, day).unwrap()
+    }
+
+    fn warm_up() -> WarmUp {
+        WarmUp {
+            start_date: date(2),
+            daily_caps: vec![50, 200],
+        }
+    }
+
+    #[test]
+    fn the_warm_up_cap_ramps_up_every_week() {
+        let warm_up = warm_up();
+        assert_eq!(warm_up.daily_cap(date(2), date(1)), Some(50));
+        assert_eq!(warm_up.daily_cap(date(2), date(2)), Some(50));
+        assert_eq!(warm_up.daily_cap(date(2), date(8)), Some(50));
+        assert_eq!(warm_up.daily_cap(date(2), date(9)), Some(200));
+        assert_eq!(warm_up.daily_cap(date(2), date(16)), None);
+    }
+
+    #[test]
+    fn each_sending_domain_is_warmed_up_from_its_own_start() {
+        let quota = SendingQuota {
+            warm_up: Some(warm_up()),
+            sender_domain: "example.com".into(),
+            ..SendingQuota::default()
+        };
+
+        // The configured sender's domain started on the configured date...
+        assert_eq!(quota.warm_up_cap("example.com", None, date(9)), Some(200));
+        // ...the others on the day they first sent, or today if they never did.
+        assert_eq!(
+            quota.warm_up_cap("news.example.org", Some(date(9)), date(9)),
+            Some(50)
+        );
+        assert_eq!(
+            quota.warm_up_cap("news.example.org", None, date(16)),
+            Some(50)
+        );
+        assert_eq!(
+            SendingQuota::unlimited().warm_up_cap("example.com", None, date(2)),
+            None
+        );
+    }
+
+    #[test]
+    fn the_sending_domain_is_the_one_of_the_sender() {
+        let quota = SendingQuota {
+            sender_domain: "example.com".into(),
+            ..SendingQuota::default()
+        };
+        let sender = SubscriberEmail::parse("Editor@News.Example.org".into()).unwrap();
+
+        assert_eq!(quota.sending_domain_of(Some(&sender)), "news.example.org");
+        assert_eq!(quota.sending_domain_of(None), "example.com");
+    }
+}
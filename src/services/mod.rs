@@ -0,0 +1,132 @@
+//! The business logic behind the HTML routes, the JSON API and the CLI.
+//!
+//! Services get their dependencies through traits, so they can be exercised without
+//! a database or an email provider.
+mod newsletter;
+mod subscription;
+
+pub use newsletter::*;
+pub use subscription::*;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::email_failover::PRIMARY;
+
+#[async_trait::async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Send an email the provider reports back about with `tag`.
+    /// Senders without such reports can ignore the tag.
+    async fn send_tagged_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        _tag: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.send_email(recipient, subject, html_content, text_content)
+            .await
+    }
+
+    /// Send an email from `sender`, a tenant's own address, or from the default sender
+    /// address if `None`. Tagged with `tag` if there is one.
+    async fn send_email_from(
+        &self,
+        sender: Option<&SubscriberEmail>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: Option<&str>,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Send like `send_email_from`, returning the name of the provider which accepted
+    /// the email - `PRIMARY` for a sender with a single one, see `crate::email_failover`.
+    async fn send_email_via_provider(
+        &self,
+        sender: Option<&SubscriberEmail>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: Option<&str>,
+    ) -> Result<Option<&'static str>, anyhow::Error> {
+        self.send_email_from(sender, recipient, subject, html_content, text_content, tag)
+            .await?;
+        Ok(Some(PRIMARY))
+    }
+}
+
+/// An email waiting in the outbox until the email provider is reachable again.
+#[derive(Debug, Clone)]
+pub struct OutboxEmail {
+    /// `None` for the default sender address.
+    pub sender: Option<String>,
+    pub recipient: String,
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+/// Where emails go when they cannot be sent right away, see `crate::email_outbox`.
+#[async_trait::async_trait]
+pub trait EmailOutbox: Send + Sync {
+    async fn enqueue(&self, email: OutboxEmail) -> Result<(), anyhow::Error>;
+}
+
+#[async_trait::async_trait]
+impl EmailSender for EmailClient {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        EmailClient::send_email(self, recipient, subject, html_content, text_content).await?;
+        Ok(())
+    }
+
+    async fn send_tagged_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: &str,
+    ) -> Result<(), anyhow::Error> {
+        EmailClient::send_tagged_email(self, recipient, subject, html_content, text_content, tag)
+            .await?;
+        Ok(())
+    }
+
+    async fn send_email_from(
+        &self,
+        sender: Option<&SubscriberEmail>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        EmailClient::send_email_from(
+            self,
+            sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            tag,
+        )
+        .await?;
+        Ok(())
+    }
+}
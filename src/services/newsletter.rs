@@ -0,0 +1,592 @@
+use crate::events::DomainEvent;
+use crate::issue_tags::{parse_tag, MAX_TAGS};
+use crate::merge_fields::{merge_fields, unwritten_sections};
+use crate::repositories::NewsletterRepository;
+use crate::tenancy::TenantId;
+use crate::utils::error_chain_fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error(
+        "The issue would be sent to {recipients} subscribers, but only {remaining} more emails \
+        can be sent today without exceeding the daily sending quota."
+    )]
+    QuotaExceeded { recipients: u64, remaining: u64 },
+    #[error(
+        "{subscribers} of the recipients have no name: give {{{{ name }}}} a fallback, as in \
+        {{{{ name | there }}}}."
+    )]
+    MissingSubscriberName { subscribers: u64 },
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// The hour of the day issues sent at local time reach their recipients, in their own
+/// timezone.
+pub const LOCAL_SEND_HOUR: u8 = 9;
+
+/// A newsletter issue ready to be published.
+#[derive(Debug)]
+pub struct NewIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    segment: Option<String>,
+    tags: Vec<String>,
+    local_send_hour: Option<u8>,
+}
+
+impl NewIssue {
+    pub fn parse(
+        title: String,
+        text_content: String,
+        html_content: String,
+    ) -> Result<Self, String> {
+        if title.trim().is_empty() {
+            return Err("The newsletter title cannot be empty.".into());
+        }
+        let mut unwritten = Vec::new();
+        for content in [&title, &text_content, &html_content] {
+            for name in unwritten_sections(content) {
+                if !unwritten.contains(&name) {
+                    unwritten.push(name);
+                }
+            }
+        }
+        if !unwritten.is_empty() {
+            let placeholders: Vec<String> = unwritten
+                .iter()
+                .map(|n| format!("{{{{ section: {} }}}}", n))
+                .collect();
+            return Err(format!(
+                "Replace {} before publishing: the sections of a template are for you to \
+                write.",
+                placeholders.join(", ")
+            ));
+        }
+        Ok(Self {
+            title,
+            text_content,
+            html_content,
+            segment: None,
+            tags: Vec::new(),
+            local_send_hour: None,
+        })
+    }
+
+    /// Send the issue only to the confirmed subscribers tagged with `segment`.
+    pub fn with_segment(mut self, segment: String) -> Result<Self, String> {
+        if segment.trim().is_empty() {
+            return Err("The segment cannot be empty.".into());
+        }
+        self.segment = Some(segment);
+        Ok(self)
+    }
+
+    /// Tag the issue with topics, for the category pages of the archive. Duplicates are
+    /// dropped.
+    pub fn with_tags(mut self, tags: Vec<String>) -> Result<Self, String> {
+        let mut parsed: Vec<String> = Vec::new();
+        for tag in tags {
+            let tag = parse_tag(&tag)?;
+            if !parsed.contains(&tag) {
+                parsed.push(tag);
+            }
+        }
+        if parsed.len() > MAX_TAGS {
+            return Err(format!("An issue can have at most {} tags.", MAX_TAGS));
+        }
+        self.tags = parsed;
+        Ok(self)
+    }
+
+    /// Hold back the delivery to each subscriber whose timezone is known until the next
+    /// `hour` o'clock of their day. The others receive the issue right away.
+    pub fn sent_at_local_hour(mut self, hour: u8) -> Result<Self, String> {
+        if hour > 23 {
+            return Err(format!("{} is not an hour of the day.", hour));
+        }
+        self.local_send_hour = Some(hour);
+        Ok(self)
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn text_content(&self) -> &str {
+        &self.text_content
+    }
+
+    pub fn html_content(&self) -> &str {
+        &self.html_content
+    }
+
+    pub fn segment(&self) -> Option<&str> {
+        self.segment.as_deref()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Whether the issue says `{{ name }}` somewhere without a fallback, for the
+    /// subscribers who left their name blank.
+    pub fn needs_subscriber_names(&self) -> bool {
+        [&self.title, &self.text_content, &self.html_content]
+            .iter()
+            .flat_map(|content| merge_fields(content))
+            .any(|field| field.name == "name" && field.fallback.is_none())
+    }
+
+    pub fn local_send_hour(&self) -> Option<u8> {
+        self.local_send_hour
+    }
+}
+
+/// Publishing the newsletter issues of a tenant.
+pub struct NewsletterService<'a> {
+    repository: &'a mut dyn NewsletterRepository,
+    tenant_id: TenantId,
+    undo_window: Duration,
+    daily_quota: Option<u64>,
+}
+
+impl<'a> NewsletterService<'a> {
+    pub fn new(repository: &'a mut dyn NewsletterRepository, tenant_id: TenantId) -> Self {
+        Self {
+            repository,
+            tenant_id,
+            undo_window: Duration::ZERO,
+            daily_quota: None,
+        }
+    }
+
+    /// Hold back the deliveries of the issues published from now on for `undo_window`,
+    /// during which they can be cancelled.
+    pub fn with_undo_window(mut self, undo_window: Duration) -> Self {
+        self.undo_window = undo_window;
+        self
+    }
+
+    /// Refuse to publish the issues that would take the emails sent and queued today
+    /// beyond `daily_quota`, if any.
+    pub fn with_daily_quota(mut self, daily_quota: Option<u64>) -> Self {
+        self.daily_quota = daily_quota;
+        self
+    }
+
+    /// Store the issue, for every confirmed subscriber of its segment. Nothing must be
+    /// committed if the daily quota is exceeded, or if the issue needs the name of
+    /// recipients who have none. Once it is committed, its deliveries are queued by
+    /// `crate::repositories::enqueue_issue_deliveries`.
+    #[tracing::instrument(name = "Publishing a newsletter issue", skip_all)]
+    pub async fn publish(&mut self, issue: &NewIssue) -> Result<Uuid, PublishError> {
+        let newsletter_issue_id = self
+            .repository
+            .insert_issue(self.tenant_id, issue, self.undo_window)
+            .await?;
+        let recipients = self
+            .repository
+            .count_recipients(newsletter_issue_id)
+            .await?;
+        if issue.needs_subscriber_names() {
+            let subscribers = self
+                .repository
+                .count_recipients_without_name(newsletter_issue_id)
+                .await?;
+            if subscribers > 0 {
+                return Err(PublishError::MissingSubscriberName { subscribers });
+            }
+        }
+        if let Some(daily_quota) = self.daily_quota {
+            let scheduled = self.repository.emails_scheduled_today().await?;
+            if scheduled + recipients > daily_quota {
+                return Err(PublishError::QuotaExceeded {
+                    recipients,
+                    remaining: daily_quota.saturating_sub(scheduled),
+                });
+            }
+        }
+        let event = DomainEvent::IssuePublished {
+            newsletter_issue_id,
+            title: issue.title.clone(),
+        };
+        self.repository.record_event(self.tenant_id, &event).await?;
+        Ok(newsletter_issue_id)
+    }
+
+    /// Cancel an issue whose undo window is still open: it is removed with its queued
+    /// deliveries. Returns `false` if it is too late.
+    #[tracing::instrument(name = "Cancelling a newsletter issue", skip(self))]
+    pub async fn cancel(&mut self, newsletter_issue_id: Uuid) -> Result<bool, anyhow::Error> {
+        let title = match self
+            .repository
+            .withdraw_issue(self.tenant_id, newsletter_issue_id)
+            .await?
+        {
+            Some(title) => title,
+            None => return Ok(false),
+        };
+        let event = DomainEvent::IssueCancelled {
+            newsletter_issue_id,
+            title,
+        };
+        self.repository.record_event(self.tenant_id, &event).await?;
+        Ok(true)
+    }
+
+    /// Deliver the issue again to those of `subscriber_emails` it failed to reach,
+    /// through the delivery queue. Returns how many recipients were queued.
+    #[tracing::instrument(name = "Retrying failed deliveries", skip(self))]
+    pub async fn retry_deliveries(
+        &mut self,
+        newsletter_issue_id: Uuid,
+        subscriber_emails: &[String],
+    ) -> Result<usize, anyhow::Error> {
+        let subscriber_emails = self
+            .repository
+            .requeue_failed_deliveries(self.tenant_id, newsletter_issue_id, subscriber_emails)
+            .await?;
+        if subscriber_emails.is_empty() {
+            return Ok(0);
+        }
+        let requeued = subscriber_emails.len();
+        let event = DomainEvent::DeliveriesRetried {
+            newsletter_issue_id,
+            subscriber_emails,
+        };
+        self.repository.record_event(self.tenant_id, &event).await?;
+        Ok(requeued)
+    }
+
+    /// Resume the deliveries of an issue paused because of its spam complaints.
+    /// Returns `false` if it was not paused.
+    #[tracing::instrument(name = "Resuming the deliveries of an issue", skip(self))]
+    pub async fn resume(&mut self, newsletter_issue_id: Uuid) -> Result<bool, anyhow::Error> {
+        let title = match self
+            .repository
+            .resume_issue(self.tenant_id, newsletter_issue_id)
+            .await?
+        {
+            Some(title) => title,
+            None => return Ok(false),
+        };
+        let event = DomainEvent::DeliveryResumed {
+            newsletter_issue_id,
+            title,
+        };
+        self.repository.record_event(self.tenant_id, &event).await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NewIssue, NewsletterService, PublishError};
+    use crate::events::DomainEvent;
+    use crate::repositories::in_memory::InMemoryNewsletterRepository;
+    use crate::tenancy::TenantId;
+    use claim::{assert_err, assert_ok};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn issue() -> NewIssue {
+        NewIssue::parse("Title".into(), "text".into(), "html".into()).unwrap()
+    }
+
+    #[test]
+    fn a_segment_cannot_be_blank() {
+        assert_err!(issue().with_segment(" ".into()));
+        assert_eq!(
+            issue().with_segment("rust".into()).unwrap().segment(),
+            Some("rust")
+        );
+    }
+
+    #[test]
+    fn tags_are_validated_and_deduplicated() {
+        assert_err!(issue().with_tags(vec!["rust weekly".into()]));
+        assert_err!(issue().with_tags((0..11).map(|i| i.to_string()).collect()));
+        assert_eq!(
+            issue()
+                .with_tags(vec!["Rust".into(), "web".into(), "rust".into()])
+                .unwrap()
+                .tags(),
+            ["rust", "web"]
+        );
+    }
+
+    #[test]
+    fn issues_are_sent_at_an_hour_of_the_day() {
+        assert_err!(issue().sent_at_local_hour(24));
+        assert_eq!(
+            issue().sent_at_local_hour(9).unwrap().local_send_hour(),
+            Some(9)
+        );
+    }
+
+    #[test]
+    fn an_issue_needs_a_title() {
+        for title in ["", "  "] {
+            assert_err!(NewIssue::parse(title.into(), "text".into(), "html".into()));
+        }
+        assert_ok!(NewIssue::parse("Title".into(), "".into(), "".into()));
+    }
+
+    #[test]
+    fn sections_must_be_replaced_before_publishing() {
+        let error = NewIssue::parse(
+            "{{ section: title }}".into(),
+            "Hi {{ name }}, {{ section: intro }}".into(),
+            "<p>{{ section: intro }}</p>".into(),
+        )
+        .unwrap_err();
+        assert!(error
+            .starts_with("Replace {{ section: title }}, {{ section: intro }} before publishing"));
+        assert_ok!(NewIssue::parse(
+            "Title".into(),
+            "Hi {{ name | there }} <{{ email }}>, {{ x }} and {{ y }}".into(),
+            "".into()
+        ));
+    }
+
+    #[tokio::test]
+    async fn an_issue_needing_names_is_refused_if_a_recipient_has_none() {
+        let mut repository = InMemoryNewsletterRepository::with_confirmed_subscribers(&[
+            "ursula@example.com",
+            "ged@example.com",
+        ]);
+        repository.nameless_subscribers = vec!["ged@example.com".into()];
+        let issue = NewIssue::parse("Title".into(), "Hi {{ name }}".into(), "".into()).unwrap();
+
+        let outcome = NewsletterService::new(&mut repository, TenantId::DEFAULT)
+            .publish(&issue)
+            .await;
+
+        assert!(matches!(
+            outcome,
+            Err(PublishError::MissingSubscriberName { subscribers: 1 })
+        ));
+        let issue =
+            NewIssue::parse("Title".into(), "Hi {{ name | there }}".into(), "".into()).unwrap();
+        assert_ok!(
+            NewsletterService::new(&mut repository, TenantId::DEFAULT)
+                .publish(&issue)
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn publishing_stores_the_issue_without_queuing_it_yet() {
+        let mut repository =
+            InMemoryNewsletterRepository::with_confirmed_subscribers(&["ursula@example.com"]);
+
+        let issue_id = NewsletterService::new(&mut repository, TenantId::DEFAULT)
+            .publish(&issue())
+            .await
+            .unwrap();
+
+        assert_eq!(repository.issues[0].0, issue_id);
+        assert!(repository.queued.is_empty());
+        assert_eq!(
+            repository.events,
+            [DomainEvent::IssuePublished {
+                newsletter_issue_id: issue_id,
+                title: "Title".into()
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_tagged_subscribers_of_a_segmented_issue_count_against_the_quota() {
+        let mut repository = InMemoryNewsletterRepository::with_confirmed_subscribers(&[
+            "ursula@example.com",
+            "ged@example.com",
+        ]);
+        repository.tags = vec![("ged@example.com".into(), "rust".into())];
+        let issue = issue().with_segment("rust".into()).unwrap();
+
+        let outcome = NewsletterService::new(&mut repository, TenantId::DEFAULT)
+            .with_daily_quota(Some(1))
+            .publish(&issue)
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn an_issue_that_would_exceed_the_daily_quota_is_refused() {
+        let mut repository = InMemoryNewsletterRepository::with_confirmed_subscribers(&[
+            "ursula@example.com",
+            "ged@example.com",
+        ]);
+        repository.sent_today = 9;
+
+        let outcome = NewsletterService::new(&mut repository, TenantId::DEFAULT)
+            .with_daily_quota(Some(10))
+            .publish(&issue())
+            .await;
+
+        assert!(matches!(
+            outcome,
+            Err(PublishError::QuotaExceeded {
+                recipients: 2,
+                remaining: 1
+            })
+        ));
+        assert!(repository.events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn an_issue_can_use_up_the_daily_quota() {
+        let mut repository = InMemoryNewsletterRepository::with_confirmed_subscribers(&[
+            "ursula@example.com",
+            "ged@example.com",
+        ]);
+        repository.sent_today = 8;
+
+        let outcome = NewsletterService::new(&mut repository, TenantId::DEFAULT)
+            .with_daily_quota(Some(10))
+            .publish(&issue())
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn an_issue_can_be_cancelled_during_its_undo_window() {
+        let mut repository =
+            InMemoryNewsletterRepository::with_confirmed_subscribers(&["ursula@example.com"]);
+        let mut service = NewsletterService::new(&mut repository, TenantId::DEFAULT)
+            .with_undo_window(Duration::from_secs(120));
+        let issue_id = service.publish(&issue()).await.unwrap();
+
+        assert!(service.cancel(issue_id).await.unwrap());
+
+        assert!(repository.issues.is_empty());
+        assert!(repository.queued.is_empty());
+        assert_eq!(
+            repository.events.last(),
+            Some(&DomainEvent::IssueCancelled {
+                newsletter_issue_id: issue_id,
+                title: "Title".into()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn an_issue_cannot_be_cancelled_once_its_deliveries_may_have_started() {
+        let mut repository =
+            InMemoryNewsletterRepository::with_confirmed_subscribers(&["ursula@example.com"]);
+        let mut service = NewsletterService::new(&mut repository, TenantId::DEFAULT);
+        let issue_id = service.publish(&issue()).await.unwrap();
+
+        assert!(!service.cancel(issue_id).await.unwrap());
+
+        assert_eq!(repository.issues.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn only_the_selected_failed_deliveries_are_retried() {
+        let mut repository = InMemoryNewsletterRepository::with_confirmed_subscribers(&[
+            "ursula@example.com",
+            "ged@example.com",
+        ]);
+        let issue_id = Uuid::new_v4();
+        let other_issue_id = Uuid::new_v4();
+        repository.failed = vec![
+            (issue_id, "ursula@example.com".into()),
+            (issue_id, "ged@example.com".into()),
+            (other_issue_id, "ursula@example.com".into()),
+        ];
+
+        let requeued = NewsletterService::new(&mut repository, TenantId::DEFAULT)
+            .retry_deliveries(
+                issue_id,
+                &["ursula@example.com".into(), "tenar@example.com".into()],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(requeued, 1);
+        assert_eq!(repository.queued, [(issue_id, "ursula@example.com".into())]);
+        assert_eq!(repository.failed.len(), 2);
+        assert_eq!(
+            repository.events,
+            [DomainEvent::DeliveriesRetried {
+                newsletter_issue_id: issue_id,
+                subscriber_emails: vec!["ursula@example.com".into()]
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn deliveries_to_those_who_left_since_are_not_retried() {
+        let mut repository =
+            InMemoryNewsletterRepository::with_confirmed_subscribers(&["ursula@example.com"]);
+        let issue_id = Uuid::new_v4();
+        repository.failed = vec![
+            (issue_id, "ursula@example.com".into()),
+            (issue_id, "ged@example.com".into()),
+        ];
+
+        let requeued = NewsletterService::new(&mut repository, TenantId::DEFAULT)
+            .retry_deliveries(
+                issue_id,
+                &["ursula@example.com".into(), "ged@example.com".into()],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(requeued, 1);
+        assert_eq!(repository.queued, [(issue_id, "ursula@example.com".into())]);
+    }
+
+    #[tokio::test]
+    async fn retrying_nothing_records_no_event() {
+        let mut repository = InMemoryNewsletterRepository::default();
+
+        let requeued = NewsletterService::new(&mut repository, TenantId::DEFAULT)
+            .retry_deliveries(Uuid::new_v4(), &["ursula@example.com".into()])
+            .await
+            .unwrap();
+
+        assert_eq!(requeued, 0);
+        assert!(repository.events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_a_paused_issue_can_be_resumed() {
+        let mut repository =
+            InMemoryNewsletterRepository::with_confirmed_subscribers(&["ursula@example.com"]);
+        let mut service = NewsletterService::new(&mut repository, TenantId::DEFAULT);
+        let issue_id = service.publish(&issue()).await.unwrap();
+        assert!(!service.resume(issue_id).await.unwrap());
+        repository.paused.push(issue_id);
+
+        let resumed = NewsletterService::new(&mut repository, TenantId::DEFAULT)
+            .resume(issue_id)
+            .await
+            .unwrap();
+
+        assert!(resumed);
+        assert!(repository.paused.is_empty());
+        assert_eq!(
+            repository.events.last(),
+            Some(&DomainEvent::DeliveryResumed {
+                newsletter_issue_id: issue_id,
+                title: "Title".into()
+            })
+        );
+    }
+}
@@ -0,0 +1,489 @@
+use crate::circuit_breaker::CircuitBreaker;
+use crate::domain::{
+    ConsentAction, ConsentEvidence, NewSubscriber, SubscriberEmail, SubscriptionToken,
+    UnsubscribeReason,
+};
+use crate::i18n::Locale;
+use crate::repositories::{Confirmation, Signup, SubscriberRepository};
+use crate::services::{EmailOutbox, EmailSender, OutboxEmail};
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
+use askama::Template;
+use std::fmt::Formatter;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum SubscriptionError {
+    #[error("A subscriber with this email address already exists.")]
+    AlreadyExists,
+    #[error("The list is full: the subscriber joined the waitlist instead.")]
+    Waitlisted,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscriptionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// What became of a confirmation email.
+#[derive(Debug, PartialEq)]
+pub enum ConfirmationEmail {
+    Sent,
+    /// The email provider is unavailable: the email waits in the outbox.
+    Delayed,
+    /// The list is full: the subscriber joined the waitlist instead, and is sent nothing.
+    Waitlisted,
+}
+
+#[derive(Template)]
+#[template(path = "confirmation.html")]
+pub struct ConfirmationTemplate<'a> {
+    locale: &'a Locale,
+    /// Greeting the subscriber by their name, if we have it.
+    heading: String,
+    confirmation_link: &'a str,
+}
+
+/// Signing subscribers up, confirming their subscriptions and letting them go.
+pub struct SubscriptionService<'a> {
+    repository: &'a dyn SubscriberRepository,
+    email_sender: &'a dyn EmailSender,
+    sender: Option<&'a SubscriberEmail>,
+    outbox: Option<(&'a dyn EmailOutbox, &'a CircuitBreaker)>,
+    consent: Option<&'a ConsentEvidence>,
+    base_url: &'a str,
+}
+
+impl<'a> SubscriptionService<'a> {
+    pub fn new(
+        repository: &'a dyn SubscriberRepository,
+        email_sender: &'a dyn EmailSender,
+        base_url: &'a str,
+    ) -> Self {
+        Self {
+            repository,
+            email_sender,
+            sender: None,
+            outbox: None,
+            consent: None,
+            base_url,
+        }
+    }
+
+    /// Send the confirmation emails from `sender` - a tenant's own address - rather
+    /// than from the default sender address.
+    pub fn with_sender(mut self, sender: Option<&'a SubscriberEmail>) -> Self {
+        self.sender = sender;
+        self
+    }
+
+    /// Put the confirmation emails the provider fails to send - or is not even asked to,
+    /// while `breaker` is open - in `outbox` instead of failing.
+    pub fn with_outbox(mut self, outbox: &'a dyn EmailOutbox, breaker: &'a CircuitBreaker) -> Self {
+        self.outbox = Some((outbox, breaker));
+        self
+    }
+
+    /// Record `evidence` as proof of consent when the subscriber signs up or confirms:
+    /// the request came from them, not from an admin or an API client.
+    pub fn with_consent(mut self, evidence: &'a ConsentEvidence) -> Self {
+        self.consent = Some(evidence);
+        self
+    }
+
+    /// Store a pending subscription and send the confirmation email - in the locale the
+    /// subscriber chose, `locale` otherwise. Subscribing again with the same email
+    /// re-sends the confirmation email with the same token. See `with_outbox` for when
+    /// the email is delayed.
+    #[tracing::instrument(name = "Registering a new subscriber", skip_all)]
+    pub async fn subscribe(
+        &self,
+        new_subscriber: NewSubscriber,
+        locale: &Locale,
+    ) -> Result<ConfirmationEmail, anyhow::Error> {
+        let pending = match self
+            .repository
+            .store_pending_subscription(&new_subscriber)
+            .await?
+        {
+            Signup::Pending(pending) => pending,
+            Signup::Waitlisted => return Ok(ConfirmationEmail::Waitlisted),
+        };
+        self.record_consent(pending.subscriber_id, ConsentAction::Signup)
+            .await?;
+        let locale = pending.locale.as_ref().unwrap_or(locale);
+        self.send_confirmation_email(&new_subscriber, &pending.subscription_token, locale)
+            .await
+    }
+
+    /// Like `subscribe`, but failing if the email address is already known.
+    #[tracing::instrument(name = "Creating a new subscriber", skip_all)]
+    pub async fn create(
+        &self,
+        new_subscriber: NewSubscriber,
+        locale: &Locale,
+    ) -> Result<Uuid, SubscriptionError> {
+        let pending = match self
+            .repository
+            .store_pending_subscription(&new_subscriber)
+            .await?
+        {
+            Signup::Pending(pending) => pending,
+            Signup::Waitlisted => return Err(SubscriptionError::Waitlisted),
+        };
+        if pending.already_existed {
+            return Err(SubscriptionError::AlreadyExists);
+        }
+        self.record_consent(pending.subscriber_id, ConsentAction::Signup)
+            .await?;
+        let locale = pending.locale.as_ref().unwrap_or(locale);
+        self.send_confirmation_email(&new_subscriber, &pending.subscription_token, locale)
+            .await?;
+        Ok(pending.subscriber_id)
+    }
+
+    /// Confirmation links are single-use. `None` if the token is unknown.
+    #[tracing::instrument(name = "Confirm a pending subscriber", skip_all)]
+    pub async fn confirm(
+        &self,
+        subscription_token: &SubscriptionToken,
+    ) -> Result<Option<Confirmation>, anyhow::Error> {
+        let confirmation = self
+            .repository
+            .confirm_subscription(subscription_token)
+            .await?;
+        if let Some(Confirmation::Confirmed(subscriber_id)) = confirmation {
+            self.record_consent(subscriber_id, ConsentAction::Confirmation)
+                .await?;
+        }
+        Ok(confirmation)
+    }
+
+    /// Stop sending issues to the subscriber the token was issued for.
+    /// `None` if the token is unknown.
+    #[tracing::instrument(name = "Unsubscribe a subscriber", skip(self, subscription_token))]
+    pub async fn unsubscribe(
+        &self,
+        subscription_token: &SubscriptionToken,
+        reason: Option<UnsubscribeReason>,
+    ) -> Result<Option<Uuid>, anyhow::Error> {
+        self.repository
+            .unsubscribe(subscription_token, reason)
+            .await
+    }
+
+    async fn record_consent(
+        &self,
+        subscriber_id: Uuid,
+        action: ConsentAction,
+    ) -> Result<(), anyhow::Error> {
+        match self.consent {
+            Some(evidence) => {
+                self.repository
+                    .record_consent(subscriber_id, action, evidence)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Sending a confirmation email to a new subscriber", skip_all)]
+    async fn send_confirmation_email(
+        &self,
+        new_subscriber: &NewSubscriber,
+        subscription_token: &SubscriptionToken,
+        locale: &Locale,
+    ) -> Result<ConfirmationEmail, anyhow::Error> {
+        let confirmation_link = format!(
+            "{}/subscriptions/confirm?subscription_token={}",
+            self.base_url,
+            subscription_token.as_ref()
+        );
+        let heading = match &new_subscriber.name {
+            Some(name) => locale.t_with(
+                "confirmation-email-heading-named",
+                &[("name", name.as_ref())],
+            ),
+            None => locale.t("confirmation-email-heading"),
+        };
+        let rendered_html = ConfirmationTemplate {
+            locale,
+            heading,
+            confirmation_link: &confirmation_link,
+        }
+        .render()
+        .context("Failed to render the confirmation email.")?;
+        let email = OutboxEmail {
+            sender: self.sender.map(|s| s.as_ref().into()),
+            recipient: new_subscriber.email.as_ref().into(),
+            subject: locale.t("confirmation-email-subject"),
+            html_content: rendered_html,
+            text_content: locale.t_with(
+                "confirmation-email-text",
+                &[("confirmation_link", &confirmation_link)],
+            ),
+        };
+        let (outbox, breaker) = match self.outbox {
+            Some(outbox) => outbox,
+            None => {
+                self.send(&new_subscriber.email, &email)
+                    .await
+                    .context("Failed to send a confirmation email.")?;
+                return Ok(ConfirmationEmail::Sent);
+            }
+        };
+        if !breaker.is_open() {
+            match self.send(&new_subscriber.email, &email).await {
+                Ok(()) => {
+                    breaker.record_success();
+                    return Ok(ConfirmationEmail::Sent);
+                }
+                Err(e) => {
+                    breaker.record_failure();
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        "Failed to send a confirmation email, putting it in the outbox"
+                    );
+                }
+            }
+        }
+        outbox
+            .enqueue(email)
+            .await
+            .context("Failed to put a confirmation email in the outbox.")?;
+        Ok(ConfirmationEmail::Delayed)
+    }
+
+    async fn send(
+        &self,
+        recipient: &SubscriberEmail,
+        email: &OutboxEmail,
+    ) -> Result<(), anyhow::Error> {
+        self.email_sender
+            .send_email_from(
+                self.sender,
+                recipient,
+                &email.subject,
+                &email.html_content,
+                &email.text_content,
+                None,
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfirmationEmail, SubscriptionError, SubscriptionService};
+    use crate::circuit_breaker::CircuitBreaker;
+    use crate::domain::{
+        ConsentAction, ConsentEvidence, NewSubscriber, SubscriberEmail, SubscriberName,
+        SubscriptionStatus, SubscriptionToken, UnsubscribeReason,
+    };
+    use crate::i18n::Locale;
+    use crate::repositories::in_memory::InMemorySubscriberRepository;
+    use crate::repositories::{Confirmation, SubscriberRepository};
+    use crate::testing::{FakeEmailOutbox, FakeEmailSender};
+    use claim::{assert_none, assert_ok, assert_some};
+    use std::time::Duration;
+
+    fn english() -> Locale {
+        Locale::parse("en").unwrap()
+    }
+
+    fn new_subscriber() -> NewSubscriber {
+        NewSubscriber {
+            email: SubscriberEmail::parse("ursula@example.com".into()).unwrap(),
+            name: Some(SubscriberName::parse("Ursula".into()).unwrap()),
+            locale: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribing_twice_sends_the_same_confirmation_link() {
+        let repository = InMemorySubscriberRepository::default();
+        let sender = FakeEmailSender::default();
+        let service = SubscriptionService::new(&repository, &sender, "https://example.com");
+
+        assert_ok!(service.subscribe(new_subscriber(), &english()).await);
+        assert_ok!(service.subscribe(new_subscriber(), &english()).await);
+
+        assert_eq!(
+            repository.list_subscribers(None, 10).await.unwrap().len(),
+            1
+        );
+        let sent = sender.sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].recipient, "ursula@example.com");
+        assert_eq!(sent[0].subject, "Welcome!");
+        assert!(sent[0]
+            .text_content
+            .contains("https://example.com/subscriptions/confirm?subscription_token="));
+        assert_eq!(sent[0].text_content, sent[1].text_content);
+    }
+
+    #[tokio::test]
+    async fn the_confirmation_email_is_sent_in_the_locale_the_subscriber_chose() {
+        let repository = InMemorySubscriberRepository::default();
+        let sender = FakeEmailSender::default();
+        let service = SubscriptionService::new(&repository, &sender, "https://example.com");
+        let french = NewSubscriber {
+            locale: Some(Locale::parse("fr").unwrap()),
+            ..new_subscriber()
+        };
+
+        assert_ok!(service.subscribe(french, &english()).await);
+        // Signing up again without choosing keeps the first choice.
+        assert_ok!(service.subscribe(new_subscriber(), &english()).await);
+
+        let sent = sender.sent();
+        let french_subject = Locale::parse("fr").unwrap().t("confirmation-email-subject");
+        assert_eq!(sent[0].subject, french_subject);
+        assert_eq!(sent[1].subject, french_subject);
+    }
+
+    #[tokio::test]
+    async fn the_confirmation_email_waits_in_the_outbox_when_sending_fails() {
+        let repository = InMemorySubscriberRepository::default();
+        let sender = FakeEmailSender::failing_for(&["ursula@example.com"]);
+        let outbox = FakeEmailOutbox::default();
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60));
+        let service = SubscriptionService::new(&repository, &sender, "https://example.com")
+            .with_outbox(&outbox, &breaker);
+
+        let outcome = service.subscribe(new_subscriber(), &english()).await;
+
+        assert_eq!(outcome.unwrap(), ConfirmationEmail::Delayed);
+        assert!(breaker.is_open());
+        let emails = outbox.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].recipient, "ursula@example.com");
+        assert!(emails[0]
+            .text_content
+            .contains("https://example.com/subscriptions/confirm?subscription_token="));
+    }
+
+    #[tokio::test]
+    async fn creating_a_known_subscriber_fails_without_sending_anything() {
+        let repository = InMemorySubscriberRepository::default();
+        let sender = FakeEmailSender::default();
+        let service = SubscriptionService::new(&repository, &sender, "https://example.com");
+        assert_ok!(service.create(new_subscriber(), &english()).await);
+
+        let outcome = service.create(new_subscriber(), &english()).await;
+
+        assert!(matches!(outcome, Err(SubscriptionError::AlreadyExists)));
+        assert_eq!(sender.sent().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn new_subscribers_are_waitlisted_once_the_list_is_full() {
+        let repository = InMemorySubscriberRepository::with_max_subscribers(1);
+        let sender = FakeEmailSender::default();
+        let service = SubscriptionService::new(&repository, &sender, "https://example.com");
+        let ged = || NewSubscriber {
+            email: SubscriberEmail::parse("ged@example.com".into()).unwrap(),
+            ..new_subscriber()
+        };
+        assert_ok!(service.subscribe(new_subscriber(), &english()).await);
+
+        let outcome = service.subscribe(ged(), &english()).await;
+
+        assert_eq!(outcome.unwrap(), ConfirmationEmail::Waitlisted);
+        assert!(matches!(
+            service.create(ged(), &english()).await,
+            Err(SubscriptionError::Waitlisted)
+        ));
+        // Known subscribers signing up again are not waitlisted.
+        let again = service.subscribe(new_subscriber(), &english()).await;
+        assert_eq!(again.unwrap(), ConfirmationEmail::Sent);
+        assert_eq!(sender.sent().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn confirming_marks_the_subscriber_as_confirmed() {
+        let repository = InMemorySubscriberRepository::default();
+        let sender = FakeEmailSender::default();
+        let service = SubscriptionService::new(&repository, &sender, "https://example.com");
+        let subscriber_id = service.create(new_subscriber(), &english()).await.unwrap();
+        let token = repository.token_of(subscriber_id).unwrap();
+
+        assert_some!(service.confirm(&token).await.unwrap());
+
+        let subscriber = repository.get_subscriber(subscriber_id).await.unwrap();
+        assert_eq!(subscriber.unwrap().status, SubscriptionStatus::Confirmed);
+    }
+
+    #[tokio::test]
+    async fn signing_up_and_confirming_are_recorded_as_proof_of_consent() {
+        let repository = InMemorySubscriberRepository::default();
+        let sender = FakeEmailSender::default();
+        let evidence = ConsentEvidence {
+            ip_address: Some("203.0.113.7".into()),
+            user_agent: Some("Mozilla/5.0".into()),
+            ..ConsentEvidence::default()
+        };
+        let service = SubscriptionService::new(&repository, &sender, "https://example.com")
+            .with_consent(&evidence);
+        let subscriber_id = service.create(new_subscriber(), &english()).await.unwrap();
+        let token = repository.token_of(subscriber_id).unwrap();
+
+        service.confirm(&token).await.unwrap();
+        // Following the link again proves nothing more.
+        service.confirm(&token).await.unwrap();
+
+        let records = repository.consent_records(subscriber_id).await.unwrap();
+        let actions: Vec<_> = records.iter().map(|r| r.action).collect();
+        assert_eq!(
+            actions,
+            [ConsentAction::Signup, ConsentAction::Confirmation]
+        );
+        assert_eq!(records[1].ip_address.as_deref(), Some("203.0.113.7"));
+    }
+
+    #[tokio::test]
+    async fn a_confirmation_link_only_works_once() {
+        let repository = InMemorySubscriberRepository::default();
+        let sender = FakeEmailSender::default();
+        let service = SubscriptionService::new(&repository, &sender, "https://example.com");
+        let subscriber_id = service.create(new_subscriber(), &english()).await.unwrap();
+        let token = repository.token_of(subscriber_id).unwrap();
+        service.confirm(&token).await.unwrap();
+
+        let outcome = service.confirm(&token).await;
+
+        assert_eq!(outcome.unwrap(), Some(Confirmation::AlreadyUsed));
+    }
+
+    #[tokio::test]
+    async fn an_unknown_token_confirms_nobody() {
+        let repository = InMemorySubscriberRepository::default();
+        let sender = FakeEmailSender::default();
+        let service = SubscriptionService::new(&repository, &sender, "https://example.com");
+
+        let outcome = service.confirm(&SubscriptionToken::generate()).await;
+
+        assert_none!(outcome.unwrap());
+    }
+
+    #[tokio::test]
+    async fn unsubscribing_marks_the_subscriber_as_unsubscribed() {
+        let repository = InMemorySubscriberRepository::default();
+        let sender = FakeEmailSender::default();
+        let service = SubscriptionService::new(&repository, &sender, "https://example.com");
+        let subscriber_id = service.create(new_subscriber(), &english()).await.unwrap();
+        let token = repository.token_of(subscriber_id).unwrap();
+        service.confirm(&token).await.unwrap();
+
+        let outcome = service
+            .unsubscribe(&token, Some(UnsubscribeReason::TooFrequent))
+            .await;
+
+        assert_eq!(outcome.unwrap(), Some(subscriber_id));
+        let subscriber = repository.get_subscriber(subscriber_id).await.unwrap();
+        assert_eq!(subscriber.unwrap().status, SubscriptionStatus::Unsubscribed);
+    }
+}
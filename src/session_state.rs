@@ -8,6 +8,7 @@ pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_VERSION_KEY: &'static str = "session_version";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -24,6 +25,14 @@ impl TypedSession {
     pub fn get_user_id(&self) -> Result<Option<Uuid>, serde_json::Error> {
         self.0.get(Self::USER_ID_KEY)
     }
+
+    pub fn insert_session_version(&self, session_version: i32) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::SESSION_VERSION_KEY, session_version)
+    }
+
+    pub fn get_session_version(&self) -> Result<Option<i32>, serde_json::Error> {
+        self.0.get(Self::SESSION_VERSION_KEY)
+    }
 }
 
 impl FromRequest for TypedSession {
@@ -0,0 +1,272 @@
+//! HMAC-signed, expiring URLs, for the links sent to subscribers that must not be forged
+//! or guessed - unsubscribe, preferences, view-in-browser, tracking pixel.
+//!
+//! A signed URL ends with three query parameters:
+//! - `expires`, the unix timestamp past which it is rejected;
+//! - `key`, the id of the key it was signed with;
+//! - `signature`, the hex-encoded HMAC-SHA256 of the path and query before it.
+//!
+//! Only the path and query are signed, so a link stays valid whichever base URL it is
+//! served from.
+use crate::configuration::{SigningKey, SigningSettings};
+use crate::problem_details::Problem;
+use crate::utils::error_chain_fmt;
+use actix_web::dev::Payload;
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, ResponseError};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use std::fmt::Formatter;
+use std::future::{ready, Ready};
+use std::time::Duration;
+
+const SIGNATURE_PARAMETER: &str = "&signature=";
+
+pub struct UrlSigner {
+    /// The first key signs, all of them verify.
+    keys: Vec<SigningKey>,
+}
+
+impl UrlSigner {
+    /// The settings must have been validated: there is at least one key.
+    pub fn new(settings: &SigningSettings) -> Self {
+        assert!(!settings.keys.is_empty(), "No URL signing key configured.");
+        Self {
+            keys: settings.keys.clone(),
+        }
+    }
+
+    /// Sign `path_and_query` - e.g. `/unsubscribe?subscription_token=abc` - for `ttl`.
+    /// Prepend a base URL to the result to get a link.
+    pub fn sign(&self, path_and_query: &str, ttl: Duration) -> String {
+        self.sign_until(
+            path_and_query,
+            chrono::Utc::now().timestamp() + ttl.as_secs() as i64,
+        )
+    }
+
+    fn sign_until(&self, path_and_query: &str, expires: i64) -> String {
+        let key = &self.keys[0];
+        let separator = if path_and_query.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        let unsigned = format!(
+            "{}{}expires={}&key={}",
+            path_and_query, separator, expires, key.id
+        );
+        let signature = hex::encode(mac(&key.secret, &unsigned).finalize().into_bytes());
+        format!("{}{}{}", unsigned, SIGNATURE_PARAMETER, signature)
+    }
+
+    /// Check that `path_and_query` was signed by one of our keys and has not expired.
+    pub fn verify(&self, path_and_query: &str) -> Result<(), SignatureError> {
+        self.verify_at(path_and_query, chrono::Utc::now().timestamp())
+    }
+
+    fn verify_at(&self, path_and_query: &str, now: i64) -> Result<(), SignatureError> {
+        let (unsigned, signature) = path_and_query
+            .rsplit_once(SIGNATURE_PARAMETER)
+            .ok_or(SignatureError::Unsigned)?;
+        let query = unsigned
+            .split_once('?')
+            .map(|(_, query)| query)
+            .ok_or(SignatureError::Unsigned)?;
+        // Ours are the last ones - the signed path could have parameters of the same name.
+        let parameter = |name: &str| {
+            query
+                .rsplit('&')
+                .find_map(|p| p.strip_prefix(name)?.strip_prefix('='))
+                .ok_or(SignatureError::Unsigned)
+        };
+        let key_id = parameter("key")?;
+        let expires: i64 = parameter("expires")?
+            .parse()
+            .map_err(|_| SignatureError::InvalidSignature)?;
+
+        let key = self
+            .keys
+            .iter()
+            .find(|k| k.id == key_id)
+            .ok_or(SignatureError::InvalidSignature)?;
+        let signature = hex::decode(signature).map_err(|_| SignatureError::InvalidSignature)?;
+        mac(&key.secret, unsigned)
+            .verify_slice(&signature)
+            .map_err(|_| SignatureError::InvalidSignature)?;
+        if now > expires {
+            return Err(SignatureError::Expired);
+        }
+        Ok(())
+    }
+}
+
+fn mac(secret: &Secret<String>, unsigned: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(unsigned.as_bytes());
+    mac
+}
+
+/// Extractor for routes only reachable through a signed URL: the request is handed over
+/// to the handler once the signature of its path and query is verified.
+/// It requires a `web::Data<UrlSigner>` in the application data.
+pub struct SignedUrl;
+
+impl FromRequest for SignedUrl {
+    type Error = SignatureError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(verify_request(req).map(|_| SignedUrl))
+    }
+}
+
+fn verify_request(req: &HttpRequest) -> Result<(), SignatureError> {
+    let signer = req
+        .app_data::<web::Data<UrlSigner>>()
+        .context("The URL signer is missing from the application data")?;
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map_or_else(|| req.path(), |p| p.as_str());
+    signer.verify(path_and_query)
+}
+
+#[derive(thiserror::Error)]
+pub enum SignatureError {
+    #[error("The link is not signed.")]
+    Unsigned,
+    #[error("The link signature is invalid.")]
+    InvalidSignature,
+    #[error("The link has expired.")]
+    Expired,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl Problem for SignatureError {
+    fn problem_code(&self) -> &'static str {
+        match self {
+            SignatureError::Unsigned => "unsigned_link",
+            SignatureError::InvalidSignature => "invalid_signature",
+            SignatureError::Expired => "expired_link",
+            SignatureError::UnexpectedError(_) => "internal_error",
+        }
+    }
+}
+
+impl ResponseError for SignatureError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SignatureError::Unsigned | SignatureError::InvalidSignature => StatusCode::FORBIDDEN,
+            SignatureError::Expired => StatusCode::GONE,
+            SignatureError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SignatureError, UrlSigner};
+    use crate::configuration::{SigningKey, SigningSettings};
+    use claim::assert_ok;
+    use secrecy::Secret;
+
+    const NOW: i64 = 1_650_000_000;
+
+    fn key(id: &str) -> SigningKey {
+        SigningKey {
+            id: id.into(),
+            secret: Secret::new(format!("a-long-enough-secret-for-key-{}", id)),
+        }
+    }
+
+    fn signer(ids: &[&str]) -> UrlSigner {
+        UrlSigner::new(&SigningSettings {
+            keys: ids.iter().map(|id| key(id)).collect(),
+        })
+    }
+
+    #[test]
+    fn a_signed_url_is_accepted_until_it_expires() {
+        let signer = signer(&["k1"]);
+        let url = signer.sign_until("/unsubscribe?subscription_token=abc", NOW + 60);
+
+        assert!(url.starts_with("/unsubscribe?subscription_token=abc&expires="));
+        assert_ok!(signer.verify_at(&url, NOW + 60));
+        assert!(matches!(
+            signer.verify_at(&url, NOW + 61),
+            Err(SignatureError::Expired)
+        ));
+    }
+
+    #[test]
+    fn paths_without_a_query_can_be_signed() {
+        let signer = signer(&["k1"]);
+        let url = signer.sign_until("/issues/42", NOW);
+        assert!(url.starts_with("/issues/42?expires="));
+        assert_ok!(signer.verify_at(&url, NOW));
+    }
+
+    #[test]
+    fn a_tampered_url_is_rejected() {
+        let signer = signer(&["k1"]);
+        let url = signer.sign_until("/unsubscribe?subscription_token=abc", NOW);
+
+        for tampered in [
+            url.replace("token=abc", "token=abd"),
+            url.replace(&format!("expires={}", NOW), &format!("expires={}", NOW + 1)),
+            format!("/preferences{}", url.trim_start_matches("/unsubscribe")),
+        ] {
+            assert!(
+                matches!(
+                    signer.verify_at(&tampered, NOW),
+                    Err(SignatureError::InvalidSignature)
+                ),
+                "{}",
+                tampered
+            );
+        }
+    }
+
+    #[test]
+    fn urls_without_a_signature_are_rejected() {
+        let signer = signer(&["k1"]);
+        for url in ["/unsubscribe?subscription_token=abc", "/issues/42"] {
+            assert!(matches!(
+                signer.verify_at(url, NOW),
+                Err(SignatureError::Unsigned)
+            ));
+        }
+    }
+
+    #[test]
+    fn urls_signed_with_a_rotated_out_key_are_still_accepted() {
+        let before_rotation = signer(&["k1"]);
+        let after_rotation = signer(&["k2", "k1"]);
+        let url = before_rotation.sign_until("/issues/42", NOW);
+
+        assert_ok!(after_rotation.verify_at(&url, NOW));
+        assert!(after_rotation
+            .sign_until("/issues/42", NOW)
+            .contains("&key=k2&"));
+    }
+
+    #[test]
+    fn urls_signed_with_a_removed_key_are_rejected() {
+        let url = signer(&["k1"]).sign_until("/issues/42", NOW);
+        assert!(matches!(
+            signer(&["k2"]).verify_at(&url, NOW),
+            Err(SignatureError::InvalidSignature)
+        ));
+    }
+}
@@ -0,0 +1,127 @@
+//! Dry runs of publishing an issue, to check who it would reach before sending it -
+//! e.g. after changing how segments select their subscribers.
+//!
+//! Nothing is written: the recipients are selected by `issue_recipients`, as when
+//! publishing, and the emails rendered by the same code as for a real issue - but with
+//! placeholders for the links to the archive and to unsubscribe, which must not work.
+//! The checks of publishing apply too: the names of the recipients, if the issue needs
+//! them, and the daily sending quota.
+use crate::configuration::EmailFooterSettings;
+use crate::domain::SubscriberEmail;
+use crate::i18n::Locale;
+use crate::issue_delivery_worker::{render_sample_email, RenderedEmail};
+use crate::pii::PiiCipher;
+use crate::sending_quota::emails_scheduled_today;
+use crate::services::{NewIssue, PublishError};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use sqlx::PgPool;
+
+/// What publishing an issue would do.
+#[derive(Debug, serde::Serialize)]
+pub struct Simulation {
+    /// How many deliveries would be queued.
+    pub recipients: u64,
+    /// Some of the emails, as their recipients would receive them.
+    pub samples: Vec<RenderedEmail>,
+}
+
+/// Simulate publishing the issue, rendering the emails of `sample_size` recipients
+/// picked at random. They are also written to the `sandbox` log target.
+#[tracing::instrument(
+    name = "Simulating a newsletter issue",
+    skip(pool, issue, footer, default_locale, cipher)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn simulate_publish(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    issue: &NewIssue,
+    sample_size: u16,
+    daily_quota: Option<u64>,
+    base_url: &str,
+    footer: &EmailFooterSettings,
+    default_locale: &Locale,
+    cipher: Option<&PiiCipher>,
+) -> Result<Simulation, PublishError> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS "recipients!",
+            COUNT(*) FILTER (
+                WHERE COALESCE(trim(name), '') = '' AND
+                -- Sealed names are never blank.
+                name_encrypted IS NULL
+            ) AS "without_name!"
+        FROM issue_recipients($1, $2)
+        "#,
+        *tenant_id,
+        issue.segment()
+    )
+    .fetch_one(&mut connection)
+    .await
+    .context("Failed to count the recipients of the simulated issue")?;
+    let recipients = r.recipients as u64;
+    if issue.needs_subscriber_names() && r.without_name > 0 {
+        return Err(PublishError::MissingSubscriberName {
+            subscribers: r.without_name as u64,
+        });
+    }
+    if let Some(daily_quota) = daily_quota {
+        let scheduled = emails_scheduled_today(&mut connection).await?;
+        if scheduled + recipients > daily_quota {
+            return Err(PublishError::QuotaExceeded {
+                recipients,
+                remaining: daily_quota.saturating_sub(scheduled),
+            });
+        }
+    }
+    let picked = sqlx::query!(
+        r#"
+        SELECT email AS "email!"
+        FROM issue_recipients($1, $2)
+        ORDER BY random()
+        LIMIT $3
+        "#,
+        *tenant_id,
+        issue.segment(),
+        i64::from(sample_size)
+    )
+    .fetch_all(&mut connection)
+    .await
+    .context("Failed to sample the recipients of the simulated issue")?;
+    let mut samples = Vec::new();
+    for r in picked {
+        let rendered = render_sample_email(
+            &mut connection,
+            issue,
+            tenant_id,
+            &r.email,
+            base_url,
+            footer,
+            default_locale,
+            cipher,
+        )
+        .await?;
+        if SubscriberEmail::parse(rendered.recipient.clone()).is_err() {
+            // The worker skips them too.
+            continue;
+        }
+        tracing::info!(
+            target: "sandbox",
+            recipient = %rendered.recipient,
+            subject = %rendered.subject,
+            text_content = %rendered.text_content,
+            "Simulated email"
+        );
+        samples.push(rendered);
+    }
+    Ok(Simulation {
+        recipients,
+        samples,
+    })
+}
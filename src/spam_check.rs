@@ -0,0 +1,220 @@
+//! Scoring an issue with an external spam checker before it goes out.
+//!
+//! The checker speaks the Postmark SpamCheck API, a thin JSON front for SpamAssassin
+//! which can also be self-hosted: the raw email goes in, its score and the rules it hit
+//! come out.
+use crate::domain::SubscriberEmail;
+use anyhow::Context;
+use reqwest::Client;
+use serde_aux::field_attributes::deserialize_number_from_string;
+use uuid::Uuid;
+
+/// SpamAssassin flags messages scoring this much or more as spam by default.
+const SPAM_THRESHOLD: f64 = 5.0;
+
+pub struct SpamChecker {
+    http_client: Client,
+    base_url: String,
+    sender: SubscriberEmail,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SpamReport {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub score: f64,
+    #[serde(default)]
+    pub rules: Vec<SpamRule>,
+}
+
+impl SpamReport {
+    pub fn is_likely_spam(&self) -> bool {
+        self.score >= SPAM_THRESHOLD
+    }
+}
+
+/// A rule the issue hit, and how much it added to the score.
+#[derive(Debug, serde::Deserialize)]
+pub struct SpamRule {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub score: f64,
+    pub description: String,
+}
+
+#[derive(serde::Serialize)]
+struct SpamCheckRequest<'a> {
+    email: &'a str,
+    /// Ask for the rules hit, not just the score.
+    options: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+struct SpamCheckResponse {
+    success: bool,
+    message: Option<String>,
+    #[serde(flatten)]
+    report: Option<SpamReport>,
+}
+
+impl SpamChecker {
+    pub fn new(base_url: String, sender: SubscriberEmail, timeout: std::time::Duration) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            sender,
+        }
+    }
+
+    /// Score the issue as our subscribers would receive it.
+    #[tracing::instrument(name = "Check an issue for spam", skip_all)]
+    pub async fn check(
+        &self,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<SpamReport, anyhow::Error> {
+        let url = reqwest::Url::parse(&self.base_url)
+            .context("Invalid spam checker URL")?
+            .join("filter")
+            .unwrap();
+        let email = raw_message(self.sender.as_ref(), subject, html_content, text_content);
+        let response: SpamCheckResponse = self
+            .http_client
+            .post(url)
+            .header("Accept", "application/json")
+            .json(&SpamCheckRequest {
+                email: &email,
+                options: "long",
+            })
+            .send()
+            .await
+            .context("Failed to reach the spam checker")?
+            .error_for_status()
+            .context("The spam checker failed")?
+            .json()
+            .await
+            .context("The spam checker sent an unexpected response")?;
+        match response {
+            SpamCheckResponse {
+                success: true,
+                report: Some(report),
+                ..
+            } => Ok(report),
+            SpamCheckResponse { message, .. } => {
+                Err(anyhow::anyhow!(message.unwrap_or_else(|| {
+                    "The spam checker could not score the issue".into()
+                })))
+            }
+        }
+    }
+}
+
+/// A `multipart/alternative` email with both versions of the content.
+fn raw_message(from: &str, subject: &str, html_content: &str, text_content: &str) -> String {
+    let boundary = Uuid::new_v4().to_simple().to_string();
+    format!(
+        "From: {from}\r\n\
+         To: {from}\r\n\
+         Subject: {subject}\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/alternative; boundary=\"{boundary}\"\r\n\
+         \r\n\
+         --{boundary}\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: 8bit\r\n\
+         \r\n\
+         {text_content}\r\n\
+         --{boundary}\r\n\
+         Content-Type: text/html; charset=utf-8\r\n\
+         Content-Transfer-Encoding: 8bit\r\n\
+         \r\n\
+         {html_content}\r\n\
+         --{boundary}--\r\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpamChecker;
+    use crate::domain::SubscriberEmail;
+    use claim::assert_err;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    fn spam_checker(base_url: String) -> SpamChecker {
+        SpamChecker::new(
+            base_url,
+            SubscriberEmail::parse("newsletter@example.com".into()).unwrap(),
+            std::time::Duration::from_millis(200),
+        )
+    }
+
+    struct RawEmailMatcher;
+
+    impl wiremock::Match for RawEmailMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let body: serde_json::Value = match serde_json::from_slice(&request.body) {
+                Ok(body) => body,
+                Err(_) => return false,
+            };
+            let email = body["email"].as_str().unwrap_or_default();
+            body["options"] == "long"
+                && email.contains("Subject: Spring edition\r\n")
+                && email.contains("multipart/alternative")
+                && email.contains("<p>Hello</p>")
+                && email.contains("Hello\r\n")
+        }
+    }
+
+    #[tokio::test]
+    async fn the_issue_is_sent_as_a_raw_email_and_its_score_parsed() {
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/filter"))
+            .and(method("POST"))
+            .and(RawEmailMatcher)
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true,
+                "score": "5.6",
+                "rules": [
+                    { "score": "2.5", "description": "Subject is all capitals" },
+                    { "score": "3.1", "description": "Message only has HTML" }
+                ],
+                "report": "..."
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let report = spam_checker(mock_server.uri())
+            .check("Spring edition", "<p>Hello</p>", "Hello")
+            .await
+            .unwrap();
+
+        assert_eq!(report.score, 5.6);
+        assert_eq!(report.rules.len(), 2);
+        assert_eq!(report.rules[1].description, "Message only has HTML");
+        assert!(report.is_likely_spam());
+    }
+
+    #[tokio::test]
+    async fn an_unsuccessful_check_is_an_error() {
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/filter"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": false,
+                "message": "Could not parse the email"
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let outcome = spam_checker(mock_server.uri())
+            .check("Spring edition", "<p>Hello</p>", "Hello")
+            .await;
+
+        assert_err!(&outcome);
+        assert_eq!(
+            outcome.unwrap_err().to_string(),
+            "Could not parse the email"
+        );
+    }
+}
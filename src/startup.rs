@@ -1,6 +1,24 @@
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::authentication::{
+    reject_anonymous_users, reject_invalid_api_keys, reject_non_operators,
+};
+use crate::cache::ResponseCache;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::concurrency_limits::ConcurrencyLimits;
+use crate::configuration::{ApplicationSettings, DatabaseSettings, Settings};
+use crate::deliverability::DeliverabilityChecker;
 use crate::email_client::EmailClient;
+use crate::error_pages::render_error_pages;
+use crate::i18n::DefaultLocale;
+use crate::load_shedding::{sample_acquire_latency, shed_load_when_saturated, DatabaseLoad};
+use crate::pii::PiiEncryption;
+use crate::problem_details::render_problem_details;
+use crate::read_only::{reject_writes_when_read_only, ForcedReadOnly};
+use crate::signing::UrlSigner;
+use crate::startup_checks::run_startup_checks;
+use crate::telemetry::LogHandle;
+use crate::tenancy::resolve_tenant;
+use crate::tenancy::validate_public_base_urls;
+use crate::webhooks::WebhookVerifier;
 use actix_session::{storage::RedisSessionStore, SessionMiddleware};
 use actix_web::cookie::Key;
 use actix_web::dev::Server;
@@ -15,11 +33,33 @@ use std::net::TcpListener;
 use tracing_actix_web::TracingLogger;
 
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, get_newsletter_form,
-    health_check, home, log_out, login, login_form, publish_newsletter, subscribe,
+    add_issue_template, add_redirect, admin_dashboard, admin_search, api, api_keys_form, archive,
+    archive_category, archived_issue, autosave_newsletter_draft, cancel_newsletter,
+    change_password, change_password_form, churn_report, confirm, create_api_key,
+    deliverability_dashboard, deliverability_diagnostics, draft_from_template, draft_heartbeat,
+    embed_cors, embed_subscribe, get_activity_events, get_backups, get_delivery_progress_events,
+    get_delivery_report_csv, get_draft_revision_diff, get_draft_revisions, get_issue_templates,
+    get_logging_form, get_newsletter_calendar, get_newsletter_form, get_newsletter_issue,
+    get_notification_preferences_form, get_query_metrics, get_redirects, get_setup_bundle,
+    get_tools, get_waitlist, health_check, home, import_setup_bundle, log_out, login, login_form,
+    not_found, post_draft_comment, preferences_form, preview_form, preview_page,
+    promote_waitlist_entry, publish_newsletter, record_email_provider_event, remove_issue_template,
+    remove_redirect, resolve_draft_comment, restore_draft_revision, resume_deliveries,
+    retry_deliveries, revoke_api_key, robots_txt, run_tool, save_preferences, sitemap,
+    start_backup, static_asset, stay, subscribe, subscribe_form, subscribe_pending,
+    subscribe_script, subscribe_waitlisted, switch_read_only, tag_feed, unsubscribe,
+    unsubscribe_form, update_logging, update_notification_preferences,
 };
 pub struct ApplicationBaseUrl(pub String);
 
+/// The base URL of the links sent to subscribers, unless their tenant has its own.
+pub struct PublicBaseUrl(pub String);
+
+pub struct AllowIndexing(pub bool);
+
+/// See `ApplicationSettings::trusted_proxies`.
+pub struct TrustedProxies(pub Vec<std::net::IpAddr>);
+
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
 
@@ -27,13 +67,80 @@ async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: String,
-    hmac_secret: Secret<String>,
-    redis_uri: Secret<String>,
+    configuration: Settings,
+    log_handle: LogHandle,
+    concurrency_limits: web::Data<ConcurrencyLimits>,
 ) -> Result<Server, anyhow::Error> {
+    let spam_checker = web::Data::new(
+        configuration
+            .spam_check
+            .checker(&configuration.email_client),
+    );
+    let breaker = web::Data::new(CircuitBreaker::from_settings(&configuration.email_client));
+    let deliverability_checker = web::Data::new(DeliverabilityChecker::new(
+        &configuration.deliverability,
+        &configuration.email_client,
+    ));
+    let pii_encryption = web::Data::new(PiiEncryption(
+        configuration
+            .pii_encryption
+            .cipher()
+            .map_err(anyhow::Error::msg)?,
+    ));
+    let Settings {
+        database,
+        application,
+        redis_uri,
+        webhooks,
+        idempotency,
+        subscribers,
+        newsletters,
+        cache,
+        branding,
+        i18n,
+        tenancy,
+        signing,
+        email_footer,
+        load_shedding,
+        ..
+    } = configuration;
+    let public_base_url = web::Data::new(PublicBaseUrl(application.public_base_url().to_owned()));
+    let ApplicationSettings {
+        base_url,
+        hmac_secret,
+        embed_allowed_origins,
+        allow_indexing,
+        read_only,
+        trusted_proxies,
+        ..
+    } = application;
+    let load_shedding_enabled = load_shedding.enabled;
+    let database_load = web::Data::new(DatabaseLoad::new(load_shedding, database.max_connections));
+    if load_shedding_enabled {
+        tokio::spawn(sample_acquire_latency(
+            db_pool.clone(),
+            database_load.clone(),
+        ));
+    }
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let log_handle = web::Data::new(log_handle);
+    let allow_indexing = web::Data::new(AllowIndexing(allow_indexing));
+    let read_only = web::Data::new(ForcedReadOnly(read_only));
+    let trusted_proxies = web::Data::new(TrustedProxies(trusted_proxies));
+    let idempotency = web::Data::new(idempotency);
+    let subscribers = web::Data::new(subscribers);
+    let newsletters = web::Data::new(newsletters);
+    let cache = web::Data::new(ResponseCache::new(&cache));
+    let branding = web::Data::new(branding);
+    let tenancy = web::Data::new(tenancy);
+    let url_signer = web::Data::new(UrlSigner::new(&signing));
+    let email_footer = web::Data::new(email_footer);
+    let default_locale = web::Data::new(DefaultLocale(
+        i18n.default_locale().map_err(anyhow::Error::msg)?,
+    ));
+    let webhook_verifier = web::Data::new(WebhookVerifier::new(&webhooks));
 
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
@@ -47,27 +154,255 @@ async fn run(
                 redis_store.clone(),
                 secret_key.clone(),
             ))
+            .wrap(from_fn(reject_writes_when_read_only))
+            .wrap(from_fn(resolve_tenant))
+            .wrap(from_fn(shed_load_when_saturated))
+            .wrap(from_fn(render_problem_details))
+            .wrap(from_fn(render_error_pages))
             .wrap(TracingLogger::default())
             .route("/", web::get().to(home))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .route("/health_check", web::get().to(health_check))
+            .route("/issues", web::get().to(archive))
+            .route(
+                "/issues/{newsletter_issue_id}",
+                web::get().to(archived_issue),
+            )
+            .route("/issues/tags/{tag}", web::get().to(archive_category))
+            .route("/issues/tags/{tag}/feed.xml", web::get().to(tag_feed))
+            .route("/sitemap.xml", web::get().to(sitemap))
+            .route("/preferences", web::get().to(preferences_form))
+            .route("/preferences", web::post().to(save_preferences))
+            .route("/robots.txt", web::get().to(robots_txt))
+            .route("/static/{filename}", web::get().to(static_asset))
+            .route("/subscribe", web::get().to(subscribe_form))
+            .route("/subscribe/pending", web::get().to(subscribe_pending))
+            .route("/subscribe/waitlisted", web::get().to(subscribe_waitlisted))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/stay", web::get().to(stay))
+            .route("/unsubscribe", web::get().to(unsubscribe_form))
+            .route("/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "/webhooks/email_provider",
+                web::post().to(record_email_provider_event),
+            )
+            .route("/embed/subscribe.js", web::get().to(subscribe_script))
+            .service(
+                web::resource("/embed/subscriptions")
+                    .wrap(embed_cors(&embed_allowed_origins))
+                    .app_data(api::json_config())
+                    .route(web::post().to(embed_subscribe)),
+            )
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/churn", web::get().to(churn_report))
+                    .route("/search", web::get().to(admin_search))
+                    .route("/deliverability", web::get().to(deliverability_dashboard))
+                    .route("/diagnostics", web::get().to(deliverability_diagnostics))
+                    .route("/activity", web::get().to(get_activity_events))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
+                    .route(
+                        "/notifications",
+                        web::get().to(get_notification_preferences_form),
+                    )
+                    .route(
+                        "/notifications",
+                        web::post().to(update_notification_preferences),
+                    )
                     .route("/logout", web::post().to(log_out))
                     .route("/newsletters", web::post().to(publish_newsletter))
-                    .route("/newsletters", web::get().to(get_newsletter_form)),
+                    .route("/newsletters", web::get().to(get_newsletter_form))
+                    .route(
+                        "/newsletters/{draft_id}/autosave",
+                        web::post().to(autosave_newsletter_draft),
+                    )
+                    .route(
+                        "/newsletters/{draft_id}/heartbeat",
+                        web::post().to(draft_heartbeat),
+                    )
+                    .route(
+                        "/newsletters/{draft_id}/comments",
+                        web::post().to(post_draft_comment),
+                    )
+                    .route(
+                        "/newsletters/{draft_id}/comments/{comment_id}/resolve",
+                        web::post().to(resolve_draft_comment),
+                    )
+                    .route(
+                        "/newsletters/{draft_id}/revisions",
+                        web::get().to(get_draft_revisions),
+                    )
+                    .route(
+                        "/newsletters/{draft_id}/revisions/{revision}",
+                        web::get().to(get_draft_revision_diff),
+                    )
+                    .route(
+                        "/newsletters/{draft_id}/revisions/{revision}/restore",
+                        web::post().to(restore_draft_revision),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/cancel",
+                        web::post().to(cancel_newsletter),
+                    )
+                    .route("/newsletters/templates", web::get().to(get_issue_templates))
+                    .route("/newsletters/templates", web::post().to(add_issue_template))
+                    .route(
+                        "/newsletters/templates/{template_id}/delete",
+                        web::post().to(remove_issue_template),
+                    )
+                    .route(
+                        "/newsletters/templates/{template_id}/draft",
+                        web::post().to(draft_from_template),
+                    )
+                    .route(
+                        "/newsletters/calendar",
+                        web::get().to(get_newsletter_calendar),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/progress",
+                        web::get().to(get_delivery_progress_events),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/report.csv",
+                        web::get().to(get_delivery_report_csv),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/retry",
+                        web::post().to(retry_deliveries),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/resume",
+                        web::post().to(resume_deliveries),
+                    )
+                    // After the other pages under /newsletters, which it would shadow.
+                    .route(
+                        "/newsletters/{newsletter_issue_id}",
+                        web::get().to(get_newsletter_issue),
+                    )
+                    .route("/logging", web::get().to(get_logging_form))
+                    .route("/logging", web::post().to(update_logging))
+                    .service(
+                        web::resource("/queries")
+                            .wrap(from_fn(reject_non_operators))
+                            .route(web::get().to(get_query_metrics)),
+                    )
+                    .route("/api_keys", web::get().to(api_keys_form))
+                    .route("/api_keys", web::post().to(create_api_key))
+                    .route("/api_keys/revoke", web::post().to(revoke_api_key))
+                    .service(
+                        web::resource("/tools")
+                            .wrap(from_fn(reject_non_operators))
+                            .route(web::get().to(get_tools))
+                            .route(web::post().to(run_tool)),
+                    )
+                    .service(
+                        web::resource("/read_only")
+                            .wrap(from_fn(reject_non_operators))
+                            .route(web::post().to(switch_read_only)),
+                    )
+                    .route("/redirects", web::get().to(get_redirects))
+                    .route("/redirects", web::post().to(add_redirect))
+                    .route("/redirects/delete", web::post().to(remove_redirect))
+                    .route("/preview", web::get().to(preview_form))
+                    .route("/preview/{page}", web::get().to(preview_page))
+                    .route("/waitlist", web::get().to(get_waitlist))
+                    .route("/waitlist/promote", web::post().to(promote_waitlist_entry))
+                    .service(
+                        web::resource("/backups")
+                            .wrap(from_fn(reject_non_operators))
+                            .route(web::get().to(get_backups))
+                            .route(web::post().to(start_backup)),
+                    )
+                    .route("/bundle", web::get().to(get_setup_bundle))
+                    .route("/bundle", web::post().to(import_setup_bundle)),
+            )
+            // The archive is public, so it lives outside of the API key protected scope.
+            .service(
+                web::scope("/api/v1/issues")
+                    .app_data(api::path_config())
+                    .app_data(api::query_config())
+                    .route("", web::get().to(api::list_issues))
+                    .route("/{newsletter_issue_id}", web::get().to(api::get_issue)),
+            )
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(reject_invalid_api_keys))
+                    .app_data(api::json_config())
+                    .app_data(api::path_config())
+                    .app_data(api::query_config())
+                    .service(
+                        web::scope("/jobs")
+                            // Bulk payloads are much larger than the default 32kB limit.
+                            .app_data(api::json_config().limit(16 * 1024 * 1024))
+                            .route("/bulk_import", web::post().to(api::start_bulk_import))
+                            .route("/bulk_delete", web::post().to(api::start_bulk_delete))
+                            .route("/reenqueue", web::post().to(api::start_reenqueue))
+                            .route("/{job_id}", web::get().to(api::get_job_status)),
+                    )
+                    .route("/newsletters", web::post().to(api::publish_newsletter))
+                    .route(
+                        "/newsletters/simulate",
+                        web::post().to(api::simulate_newsletter),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}",
+                        web::get().to(api::get_newsletter_issue),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}/report",
+                        web::get().to(api::get_delivery_report),
+                    )
+                    .route("/recent/issues", web::get().to(api::recent_issues))
+                    .route(
+                        "/recent/subscribers",
+                        web::get().to(api::recent_subscribers),
+                    )
+                    .route("/subscribers", web::get().to(api::list_subscribers))
+                    .route("/subscribers", web::post().to(api::create_subscriber))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(api::get_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::delete().to(api::delete_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/consent",
+                        web::get().to(api::get_subscriber_consent),
+                    ),
             )
             .app_data(db_pool.clone())
+            .app_data(database_load.clone())
             .app_data(email_client.clone())
+            .app_data(breaker.clone())
+            .app_data(spam_checker.clone())
+            .app_data(deliverability_checker.clone())
             .app_data(base_url.clone())
+            .app_data(public_base_url.clone())
+            .app_data(url_signer.clone())
+            .app_data(log_handle.clone())
+            .app_data(allow_indexing.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(read_only.clone())
+            .app_data(idempotency.clone())
+            .app_data(subscribers.clone())
+            .app_data(pii_encryption.clone())
+            .app_data(newsletters.clone())
+            .app_data(cache.clone())
+            .app_data(concurrency_limits.clone())
+            .app_data(branding.clone())
+            .app_data(tenancy.clone())
+            .app_data(default_locale.clone())
+            .app_data(email_footer.clone())
+            .app_data(webhook_verifier.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
+            .default_service(web::route().to(not_found))
     })
     .listen(listener)?
     .run();
@@ -77,13 +412,87 @@ async fn run(
 pub struct Application {
     port: u16,
     server: Server,
+    concurrency_limits: web::Data<ConcurrencyLimits>,
 }
 
 impl Application {
-    pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
+    pub async fn build(
+        configuration: Settings,
+        log_handle: LogHandle,
+    ) -> Result<Self, anyhow::Error> {
+        configuration
+            .database
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .idempotency
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .scheduler
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .application
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .signing
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .backups
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .email_footer
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .newsletters
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .re_engagement
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .pii_encryption
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .chat_notifications
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .load_shedding
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .concurrency_limits
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .branding
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        configuration
+            .i18n
+            .default_locale()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
         let connection_pool = get_connection_pool(&configuration.database);
 
-        let email_client = configuration.email_client.client();
+        if configuration.tenancy.enabled {
+            validate_public_base_urls(&connection_pool)
+                .await
+                .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+        }
+
+        let email_client = configuration.email_client.clone().client();
+
+        if configuration.application.startup_checks {
+            run_startup_checks(&connection_pool, &configuration.redis_uri, &email_client).await?;
+        }
 
         let address = format!(
             "{}:{}",
@@ -92,29 +501,41 @@ impl Application {
 
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
+        let concurrency_limits =
+            web::Data::new(ConcurrencyLimits::new(&configuration.concurrency_limits));
         let server = run(
             listener,
             connection_pool,
             email_client,
-            configuration.application.base_url,
-            configuration.application.hmac_secret,
-            configuration.redis_uri,
+            configuration,
+            log_handle,
+            concurrency_limits.clone(),
         )
         .await?;
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            concurrency_limits,
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// The caps the server serves heavy requests under.
+    pub fn concurrency_limits(&self) -> web::Data<ConcurrencyLimits> {
+        self.concurrency_limits.clone()
+    }
+
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
         self.server.await
     }
 }
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
+        .max_connections(configuration.max_connections)
         .connect_timeout(std::time::Duration::from_secs(2))
         .connect_lazy_with(configuration.with_db())
 }
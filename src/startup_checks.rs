@@ -0,0 +1,228 @@
+use crate::email_client::EmailClient;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::PgPool;
+use std::fmt::Formatter;
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Verify that the external dependencies of the application are usable before we start
+/// accepting traffic. Every check runs, so the returned error lists all the failures at once.
+#[tracing::instrument(name = "Run startup checks", skip_all)]
+pub async fn run_startup_checks(
+    pool: &PgPool,
+    redis_uri: &Secret<String>,
+    email_client: &EmailClient,
+) -> Result<(), StartupCheckError> {
+    let (migrations, redis, email_provider) = tokio::join!(
+        check_migrations(pool),
+        check_redis(redis_uri),
+        check_email_provider(email_client)
+    );
+
+    let failures: Vec<_> = [
+        ("postgres", migrations),
+        ("redis", redis),
+        ("email provider", email_provider),
+    ]
+    .into_iter()
+    .filter_map(|(dependency, outcome)| outcome.err().map(|e| (dependency, e)))
+    .collect();
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(StartupCheckError(failures))
+    }
+}
+
+#[tracing::instrument(name = "Check that database migrations are compatible", skip_all)]
+async fn check_migrations(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    if let Some(version) = connection.dirty_version().await? {
+        anyhow::bail!("Migration {} is partially applied", version);
+    }
+    let applied = connection
+        .list_applied_migrations()
+        .await
+        .context("Failed to list the applied migrations")?;
+
+    for migration in MIGRATOR.iter() {
+        if let Some(a) = applied.iter().find(|a| a.version == migration.version) {
+            if a.checksum != migration.checksum {
+                anyhow::bail!(
+                    "Migration {} was modified after being applied",
+                    migration.version
+                );
+            }
+        }
+    }
+    check_schema_compatibility(pool).await?;
+    Ok(())
+}
+
+/// Whether this code can run against the schema of the database, during a rolling deploy.
+///
+/// The code needs every migration it knows to be applied. It also runs against the
+/// newer migrations of a later release, unless one of them called `breaks_older_code`
+/// - see the migration which added `schema_compatibility`.
+#[tracing::instrument(name = "Check that the schema is compatible with this code", skip_all)]
+pub async fn check_schema_compatibility(pool: &PgPool) -> Result<SchemaVersions, anyhow::Error> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let applied: Vec<i64> = connection
+        .list_applied_migrations()
+        .await
+        .context("Failed to list the applied migrations")?
+        .into_iter()
+        .map(|m| m.version)
+        .collect();
+    let known: Vec<i64> = MIGRATOR.iter().map(|m| m.version).collect();
+    let mut versions = SchemaVersions::compare(&known, &applied, 0);
+    // The table does not exist before its own migration is applied.
+    if versions.pending.is_empty() {
+        versions.oldest_compatible =
+            sqlx::query!("SELECT oldest_compatible_migration FROM schema_compatibility")
+                .fetch_one(&mut connection)
+                .await
+                .context("Failed to retrieve the oldest compatible migration")?
+                .oldest_compatible_migration;
+    }
+    versions.check()?;
+    if !versions.newer.is_empty() {
+        tracing::info!(
+            newer_migrations = ?versions.newer,
+            "Running against migrations of a later release"
+        );
+    }
+    Ok(versions)
+}
+
+/// The migrations this code knows, compared with those applied to the database.
+#[derive(Debug, PartialEq)]
+pub struct SchemaVersions {
+    /// The latest migration this code knows.
+    pub latest_known: i64,
+    /// The known migrations which have not been applied.
+    pub pending: Vec<i64>,
+    /// The applied migrations this code does not know, from a later release.
+    pub newer: Vec<i64>,
+    /// Older code does not run against the schema, see `breaks_older_code`.
+    pub oldest_compatible: i64,
+}
+
+impl SchemaVersions {
+    fn compare(known: &[i64], applied: &[i64], oldest_compatible: i64) -> Self {
+        Self {
+            latest_known: known.iter().copied().max().unwrap_or(0),
+            pending: known
+                .iter()
+                .filter(|v| !applied.contains(v))
+                .copied()
+                .collect(),
+            newer: applied
+                .iter()
+                .filter(|v| !known.contains(v))
+                .copied()
+                .collect(),
+            oldest_compatible,
+        }
+    }
+
+    pub fn check(&self) -> Result<(), anyhow::Error> {
+        if !self.pending.is_empty() {
+            anyhow::bail!(
+                "The schema is older than this code: migrations {:?} have not been applied",
+                self.pending
+            );
+        }
+        if self.latest_known < self.oldest_compatible {
+            anyhow::bail!(
+                "The schema is too recent for this code: migration {} breaks the code which \
+                 does not know it, and this code only knows up to migration {}",
+                self.oldest_compatible,
+                self.latest_known
+            );
+        }
+        Ok(())
+    }
+}
+
+#[tracing::instrument(name = "Check that Redis responds to PING", skip_all)]
+async fn check_redis(redis_uri: &Secret<String>) -> Result<(), anyhow::Error> {
+    let client = redis::Client::open(redis_uri.expose_secret().as_str())
+        .context("Failed to parse the Redis URI")?;
+    let mut connection = client
+        .get_async_connection()
+        .await
+        .context("Failed to connect to Redis")?;
+    let reply: String = redis::cmd("PING")
+        .query_async(&mut connection)
+        .await
+        .context("Failed to PING Redis")?;
+    if reply != "PONG" {
+        anyhow::bail!("Redis replied {} to PING", reply);
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Check that the email provider accepts our token", skip_all)]
+async fn check_email_provider(email_client: &EmailClient) -> Result<(), anyhow::Error> {
+    email_client
+        .verify_authorization_token()
+        .await
+        .context("The email provider rejected our authorization token")
+}
+
+pub struct StartupCheckError(Vec<(&'static str, anyhow::Error)>);
+
+impl std::fmt::Display for StartupCheckError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} startup check(s) failed:", self.0.len())?;
+        for (dependency, e) in &self.0 {
+            writeln!(f, "- {}: {:#}", dependency, e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for StartupCheckError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for StartupCheckError {}
+
+#[cfg(test)]
+mod tests {
+    use super::SchemaVersions;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn code_runs_against_the_additive_migrations_of_a_later_release() {
+        let versions = SchemaVersions::compare(&[1, 2], &[1, 2, 3], 0);
+        assert_eq!(versions.newer, vec![3]);
+        assert_ok!(versions.check());
+    }
+
+    #[test]
+    fn code_does_not_run_against_a_migration_which_breaks_it() {
+        assert_err!(SchemaVersions::compare(&[1, 2], &[1, 2, 3], 3).check());
+        // The release which brought the migration knows it.
+        assert_ok!(SchemaVersions::compare(&[1, 2, 3], &[1, 2, 3], 3).check());
+    }
+
+    #[test]
+    fn code_does_not_run_before_its_migrations_are_applied() {
+        let versions = SchemaVersions::compare(&[1, 2, 3], &[1, 2], 0);
+        assert_eq!(versions.pending, vec![3]);
+        assert_err!(versions.check());
+    }
+}
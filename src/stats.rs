@@ -0,0 +1,350 @@
+use crate::pii::{mask_email, Masked};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How many recent delivery failures the overview lists.
+const RECENT_FAILURES: i64 = 5;
+
+#[derive(Debug, serde::Serialize)]
+pub struct SubscriberStats {
+    pub confirmed: i64,
+    pub pending: i64,
+    /// Subscribers who signed up over the last 7 days, confirmed or not.
+    pub new_last_7_days: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct IssueStats {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub published_at: String,
+    pub pending: i64,
+    pub delivered: i64,
+    /// Failed or skipped deliveries.
+    pub failed: i64,
+    pub complaints: i64,
+    /// Whether its deliveries wait for an admin to resume them, see `crate::complaints`.
+    pub paused: bool,
+}
+
+impl IssueStats {
+    pub fn status(&self) -> &'static str {
+        if self.pending > 0 && self.paused {
+            "paused"
+        } else if self.pending > 0 {
+            "delivering"
+        } else {
+            "delivered"
+        }
+    }
+}
+
+/// How far the delivery of an issue has got.
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+pub struct DeliveryProgress {
+    pub delivered: i64,
+    /// Failed or skipped deliveries.
+    pub failed: i64,
+    /// Deliveries still waiting for the worker.
+    pub remaining: i64,
+}
+
+impl DeliveryProgress {
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DeliveryFailure {
+    pub subscriber_email: String,
+    pub title: String,
+    /// `failed` or `skipped`.
+    pub outcome: String,
+    pub error: Option<String>,
+    pub attempted_at: DateTime<Utc>,
+}
+
+impl Masked for DeliveryFailure {
+    fn masked(self) -> Self {
+        Self {
+            subscriber_email: mask_email(&self.subscriber_email),
+            ..self
+        }
+    }
+}
+
+/// A snapshot of the state of the newsletter, for the admin overview.
+#[derive(Debug, serde::Serialize)]
+pub struct Overview {
+    pub subscribers: SubscriberStats,
+    pub last_issue: Option<IssueStats>,
+    /// Deliveries still waiting for the worker, across all issues.
+    pub queue_depth: i64,
+    pub recent_failures: Vec<DeliveryFailure>,
+}
+
+#[tracing::instrument(name = "Compute the newsletter overview", skip(pool))]
+pub async fn get_overview(pool: &PgPool, tenant_id: TenantId) -> Result<Overview, anyhow::Error> {
+    Ok(Overview {
+        subscribers: get_subscriber_stats(pool, tenant_id).await?,
+        last_issue: get_last_issue_stats(pool, tenant_id).await?,
+        queue_depth: get_queue_depth(pool, tenant_id).await?,
+        recent_failures: get_recent_failures(pool, tenant_id).await?,
+    })
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_subscriber_stats(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<SubscriberStats, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE status = 'confirmed') AS "confirmed!",
+            COUNT(*) FILTER (WHERE status = 'pending_confirmation') AS "pending!",
+            COUNT(*) FILTER (WHERE subscribed_at >= now() - interval '7 days')
+                AS "new_last_7_days!"
+        FROM subscriptions
+        WHERE deleted_at IS NULL AND tenant_id = $1
+        "#,
+        *tenant_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count subscribers")?;
+    Ok(SubscriberStats {
+        confirmed: row.confirmed,
+        pending: row.pending,
+        new_last_7_days: row.new_last_7_days,
+    })
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_last_issue_stats(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Option<IssueStats>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            (
+                SELECT COUNT(*)
+                FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "pending!",
+            (
+                SELECT COUNT(*)
+                FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.outcome = 'delivered'
+            ) AS "delivered!",
+            (
+                SELECT COUNT(*)
+                FROM issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id AND d.outcome <> 'delivered'
+            ) AS "failed!",
+            (
+                SELECT COUNT(*)
+                FROM issue_complaints c
+                WHERE c.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "complaints!",
+            i.paused_at IS NOT NULL AS "paused!"
+        FROM newsletter_issues i
+        WHERE i.tenant_id = $1
+        ORDER BY i.published_at DESC
+        LIMIT 1
+        "#,
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the last newsletter issue")?;
+    Ok(row.map(|r| IssueStats {
+        newsletter_issue_id: r.newsletter_issue_id,
+        title: r.title,
+        published_at: r.published_at,
+        pending: r.pending,
+        delivered: r.delivered,
+        failed: r.failed,
+        complaints: r.complaints,
+        paused: r.paused,
+    }))
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_issue_stats(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<IssueStats>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            i.title,
+            i.published_at,
+            (
+                SELECT COUNT(*)
+                FROM issue_complaints c
+                WHERE c.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "complaints!",
+            i.paused_at IS NOT NULL AS "paused!"
+        FROM newsletter_issues i
+        WHERE i.newsletter_issue_id = $1 AND i.tenant_id = $2
+        "#,
+        newsletter_issue_id,
+        *tenant_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a newsletter issue")?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let progress = get_delivery_progress(pool, newsletter_issue_id).await?;
+    Ok(Some(IssueStats {
+        newsletter_issue_id,
+        title: row.title,
+        published_at: row.published_at,
+        pending: progress.remaining,
+        delivered: progress.delivered,
+        failed: progress.failed,
+        complaints: row.complaints,
+        paused: row.paused,
+    }))
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_delivery_progress(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<DeliveryProgress, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            (
+                SELECT COUNT(*)
+                FROM issue_deliveries
+                WHERE newsletter_issue_id = $1 AND outcome = 'delivered'
+            ) AS "delivered!",
+            (
+                SELECT COUNT(*)
+                FROM issue_deliveries
+                WHERE newsletter_issue_id = $1 AND outcome <> 'delivered'
+            ) AS "failed!",
+            (
+                SELECT COUNT(*)
+                FROM issue_delivery_queue
+                WHERE newsletter_issue_id = $1
+            ) AS "remaining!"
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to measure the delivery progress of an issue")?;
+    Ok(DeliveryProgress {
+        delivered: row.delivered,
+        failed: row.failed,
+        remaining: row.remaining,
+    })
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_queue_depth(pool: &PgPool, tenant_id: TenantId) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue WHERE tenant_id = $1"#,
+        *tenant_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to measure the delivery queue")?;
+    Ok(row.count)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_recent_failures(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Vec<DeliveryFailure>, anyhow::Error> {
+    sqlx::query_as!(
+        DeliveryFailure,
+        r#"
+        SELECT d.subscriber_email, i.title, d.outcome, d.error, d.attempted_at
+        FROM issue_deliveries d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE d.outcome <> 'delivered' AND i.tenant_id = $2
+        ORDER BY d.attempted_at DESC
+        LIMIT $1
+        "#,
+        RECENT_FAILURES,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the recent delivery failures")
+}
+
+/// Every recipient the issue could not be delivered to, by email.
+#[tracing::instrument(skip(pool))]
+pub async fn get_issue_failures(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<DeliveryFailure>, anyhow::Error> {
+    sqlx::query_as!(
+        DeliveryFailure,
+        r#"
+        SELECT d.subscriber_email, i.title, d.outcome, d.error, d.attempted_at
+        FROM issue_deliveries d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE d.newsletter_issue_id = $1 AND d.outcome <> 'delivered'
+        ORDER BY d.subscriber_email
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the failed deliveries of an issue")
+}
+
+/// How long the worker took per email while delivering the most recent issue, if it
+/// delivered enough of it to tell.
+#[tracing::instrument(skip_all)]
+pub async fn get_seconds_per_delivery(
+    pool: &PgPool,
+    tenant_id: TenantId,
+) -> Result<Option<f64>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS "deliveries!",
+            EXTRACT(EPOCH FROM MAX(attempted_at) - MIN(attempted_at))::float8 AS seconds
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = (
+            SELECT d.newsletter_issue_id
+            FROM issue_deliveries d
+            JOIN newsletter_issues i USING (newsletter_issue_id)
+            WHERE i.tenant_id = $1
+            ORDER BY d.attempted_at DESC
+            LIMIT 1
+        )
+        "#,
+        *tenant_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to measure the delivery rate")?;
+    Ok(match row.seconds {
+        Some(seconds) if row.deliveries > 1 && seconds > 0.0 => {
+            Some(seconds / (row.deliveries - 1) as f64)
+        }
+        _ => None,
+    })
+}
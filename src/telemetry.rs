@@ -1,30 +1,79 @@
+use crate::configuration::{LogFormat, LoggingSettings};
+use crate::query_metrics::init_query_metrics_logger;
+use anyhow::Context;
+use std::sync::{Arc, RwLock};
 use tokio::task::JoinHandle;
 use tracing::{subscriber::set_global_default, Subscriber};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
-use tracing_log::LogTracer;
+use tracing_subscriber::filter::dynamic_filter_fn;
 use tracing_subscriber::fmt::MakeWriter;
-use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
+use tracing_subscriber::{layer::SubscriberExt, reload, EnvFilter, Layer, Registry};
+
+/// Handle to the installed subscriber, used to change the log filter and output format
+/// without restarting the application.
+#[derive(Clone)]
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>,
+    settings: Arc<RwLock<LoggingSettings>>,
+}
+
+impl LogHandle {
+    pub fn current(&self) -> LoggingSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn reload(&self, settings: LoggingSettings) -> Result<(), anyhow::Error> {
+        let env_filter =
+            EnvFilter::try_new(&settings.level).context("Failed to parse the log filter")?;
+        self.filter
+            .reload(env_filter)
+            .context("Failed to reload the log filter")?;
+        *self.settings.write().unwrap() = settings;
+        Ok(())
+    }
+}
 
 pub fn get_subscriber<Sink>(
     name: String,
-    env_filter: String,
+    settings: &LoggingSettings,
     sink: Sink,
-) -> impl Subscriber + Send + Sync
+) -> (impl Subscriber + Send + Sync, LogHandle)
 where
-    Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+    Sink: for<'a> MakeWriter<'a> + Clone + Send + Sync + 'static,
 {
     let env_filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
-    let formatting_layer = BunyanFormattingLayer::new(name, sink);
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&settings.level));
+    let settings = Arc::new(RwLock::new(LoggingSettings {
+        level: env_filter.to_string(),
+        format: settings.format,
+    }));
+    let (env_filter, filter) = reload::Layer::new(env_filter);
+
+    let json_settings = settings.clone();
+    let formatting_layer = BunyanFormattingLayer::new(name, sink.clone()).with_filter(
+        dynamic_filter_fn(move |_, _| json_settings.read().unwrap().format == LogFormat::Json),
+    );
+    let pretty_settings = settings.clone();
+    let pretty_layer = tracing_subscriber::fmt::layer()
+        .compact()
+        .with_writer(sink)
+        .with_filter(dynamic_filter_fn(move |_, _| {
+            pretty_settings.read().unwrap().format == LogFormat::Pretty
+        }));
 
-    Registry::default()
+    let subscriber = Registry::default()
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(pretty_layer);
+
+    (subscriber, LogHandle { filter, settings })
 }
 
+/// Records from the `log` crate - sqlx's among them - are forwarded to the subscriber,
+/// after the latency of each query is recorded.
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
-    LogTracer::init().expect("Failed to set logger");
+    init_query_metrics_logger().expect("Failed to set logger");
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
 
@@ -0,0 +1,254 @@
+//! Several independent newsletters served by a single deployment.
+//!
+//! Each tenant has its own hostname, subscribers, issues and admins, and can send from
+//! its own address and link subscribers to its own domain. Every request is resolved to a tenant by its hostname, and only sees
+//! that tenant's data. With tenancy disabled, everything belongs to the default tenant.
+use crate::configuration::{validate_base_url, TenancySettings};
+use crate::domain::SubscriberEmail;
+use crate::utils::e500;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{web, HttpMessage};
+use actix_web_lab::middleware::Next;
+use anyhow::{anyhow, Context};
+use sqlx::{PgExecutor, PgPool};
+use std::fmt::Formatter;
+use std::ops::Deref;
+use uuid::Uuid;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TenantId(Uuid);
+
+impl TenantId {
+    /// The deployment's own newsletter, which everything stored before tenancy belongs to.
+    pub const DEFAULT: TenantId = TenantId(Uuid::nil());
+}
+
+impl From<Uuid> for TenantId {
+    fn from(tenant_id: Uuid) -> Self {
+        Self(tenant_id)
+    }
+}
+
+impl std::fmt::Display for TenantId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for TenantId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// The tenant a request is served for.
+#[derive(Clone, Debug)]
+pub struct Tenant {
+    pub id: TenantId,
+    /// Emails are sent from the configured sender address if `None`.
+    pub sender_email: Option<SubscriberEmail>,
+    /// The base URL of the links sent to its subscribers, the configured public base URL
+    /// if `None`.
+    pub public_base_url: Option<String>,
+}
+
+impl Tenant {
+    fn default_tenant() -> Self {
+        Self::unconfigured(TenantId::DEFAULT)
+    }
+
+    fn unconfigured(id: TenantId) -> Self {
+        Self {
+            id,
+            sender_email: None,
+            public_base_url: None,
+        }
+    }
+
+    /// The base URL of the links sent to the tenant's subscribers.
+    pub fn public_base_url<'a>(&'a self, configured: &'a str) -> &'a str {
+        self.public_base_url.as_deref().unwrap_or(configured)
+    }
+}
+
+pub struct NewTenant {
+    pub name: String,
+    pub hostname: String,
+    pub sender_email: Option<SubscriberEmail>,
+    /// Requests to its hostname are served for the tenant too.
+    pub public_base_url: Option<String>,
+    /// The incoming webhook its chat notifications are posted to, see
+    /// `crate::chat_notifications`. They are not posted if `None`.
+    pub chat_webhook_url: Option<String>,
+}
+
+/// Resolve the tenant of the request from its hostname - or the hostname of its public
+/// base URL - for the handlers to scope
+/// everything to. Hostnames no tenant is registered for are served the default tenant,
+/// so the deployment's own address - and its health checks - keep working.
+pub async fn resolve_tenant(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let enabled = req
+        .app_data::<web::Data<TenancySettings>>()
+        .is_some_and(|settings| settings.enabled);
+    let tenant = if enabled {
+        let pool = req
+            .app_data::<web::Data<PgPool>>()
+            .ok_or_else(|| e500(anyhow!("No database pool configured.")))?
+            .clone();
+        let hostname = hostname(req.connection_info().host());
+        get_tenant_by_hostname(&pool, &hostname)
+            .await
+            .map_err(e500)?
+            .unwrap_or_else(Tenant::default_tenant)
+    } else {
+        Tenant::default_tenant()
+    };
+    req.extensions_mut().insert(tenant);
+    next.call(req).await
+}
+
+/// The `Host` header without its port, lowercased.
+fn hostname(host: &str) -> String {
+    let hostname = match host.strip_prefix('[') {
+        // An IPv6 address.
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    hostname.to_lowercase()
+}
+
+#[tracing::instrument(name = "Resolve the tenant of a hostname", skip(pool))]
+pub async fn get_tenant_by_hostname(
+    pool: &PgPool,
+    hostname: &str,
+) -> Result<Option<Tenant>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT tenant_id, sender_email, public_base_url
+        FROM tenants
+        WHERE hostname = $1 OR public_hostname = $1
+        "#,
+        hostname
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the tenant of a hostname")?;
+    Ok(row.map(|r| Tenant {
+        id: TenantId(r.tenant_id),
+        sender_email: r.sender_email.and_then(parse_sender),
+        public_base_url: r.public_base_url,
+    }))
+}
+
+/// The sender address and public base URL of a tenant, for the work done outside of its
+/// requests.
+#[tracing::instrument(name = "Get a tenant", skip(executor))]
+pub async fn get_tenant(
+    executor: impl PgExecutor<'_>,
+    tenant_id: TenantId,
+) -> Result<Tenant, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT sender_email, public_base_url FROM tenants WHERE tenant_id = $1"#,
+        *tenant_id
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to retrieve a tenant")?;
+    Ok(match row {
+        Some(r) => Tenant {
+            id: tenant_id,
+            sender_email: r.sender_email.and_then(parse_sender),
+            public_base_url: r.public_base_url,
+        },
+        None => Tenant::unconfigured(tenant_id),
+    })
+}
+
+/// The address was valid when stored - fall back to the configured sender otherwise.
+fn parse_sender(sender_email: String) -> Option<SubscriberEmail> {
+    SubscriberEmail::parse(sender_email)
+        .map_err(|e| tracing::warn!(error.message = %e, "Ignoring an invalid tenant sender"))
+        .ok()
+}
+
+#[tracing::instrument(name = "Create a tenant", skip(pool, new_tenant))]
+pub async fn create_tenant(
+    pool: &PgPool,
+    new_tenant: &NewTenant,
+) -> Result<TenantId, anyhow::Error> {
+    if let Some(public_base_url) = &new_tenant.public_base_url {
+        validate_base_url(public_base_url).map_err(|e| anyhow!("The public base URL {}", e))?;
+    }
+    if let Some(chat_webhook_url) = &new_tenant.chat_webhook_url {
+        if !chat_webhook_url.starts_with("https://") {
+            return Err(anyhow!("The chat webhook URL must be an https:// URL"));
+        }
+    }
+    let tenant_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO tenants (
+            tenant_id, name, hostname, sender_email, public_base_url, chat_webhook_url
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        tenant_id,
+        new_tenant.name,
+        hostname(&new_tenant.hostname),
+        new_tenant.sender_email.as_ref().map(|e| e.as_ref()),
+        new_tenant.public_base_url,
+        new_tenant.chat_webhook_url
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the new tenant")?;
+    Ok(TenantId(tenant_id))
+}
+
+/// Every stored public base URL must still be usable to build links with - they could
+/// have been edited by hand since.
+#[tracing::instrument(name = "Validate the public base URLs of the tenants", skip(pool))]
+pub async fn validate_public_base_urls(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT name, public_base_url AS "public_base_url!"
+        FROM tenants
+        WHERE public_base_url IS NOT NULL
+        ORDER BY name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the public base URLs of the tenants")?;
+    let failures: Vec<String> = rows
+        .into_iter()
+        .filter_map(|r| {
+            validate_base_url(&r.public_base_url)
+                .err()
+                .map(|e| format!("the public base URL of tenant {:?} {}", r.name, e))
+        })
+        .collect();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(failures.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hostname;
+
+    #[test]
+    fn the_port_is_not_part_of_the_hostname() {
+        assert_eq!(hostname("News.Example.com:8000"), "news.example.com");
+        assert_eq!(hostname("news.example.com"), "news.example.com");
+        assert_eq!(hostname("[::1]:8000"), "::1");
+    }
+}
@@ -0,0 +1,331 @@
+//! Test doubles and fixtures, behind the `testing` feature.
+//!
+//! They let tests - ours and those of forks - exercise the application without an
+//! email provider, and set up the database through the domain layer rather than
+//! through the HTTP routes.
+use crate::configuration::EmailFooterSettings;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionToken};
+use crate::i18n::Locale;
+use crate::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use crate::repositories::{
+    enqueue_issue_deliveries, enqueue_unfinished_issues, PostgresSubscriberRepository,
+    SubscriberRepository,
+};
+use crate::sending_quota::SendingQuota;
+use crate::services::{EmailOutbox, EmailSender, NewIssue, NewsletterService, OutboxEmail};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct SentEmail {
+    /// `None` for the default sender address.
+    pub sender: Option<String>,
+    pub recipient: String,
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+    pub tag: Option<String>,
+}
+
+/// Keeps the emails it is asked to send in memory instead of sending them.
+#[derive(Default)]
+pub struct FakeEmailSender {
+    sent: Mutex<Vec<SentEmail>>,
+    /// Recipients whose emails fail to send.
+    failing: Vec<String>,
+}
+
+impl FakeEmailSender {
+    /// Sending to any of `recipients` fails - nothing is recorded for them.
+    pub fn failing_for(recipients: &[&str]) -> Self {
+        Self {
+            failing: recipients.iter().map(|r| r.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn sent(&self) -> MutexGuard<'_, Vec<SentEmail>> {
+        self.sent.lock().unwrap()
+    }
+
+    fn check_recipient(&self, recipient: &SubscriberEmail) -> Result<(), anyhow::Error> {
+        if self.failing.iter().any(|r| r == recipient.as_ref()) {
+            anyhow::bail!("The email provider rejected {}", recipient.as_ref());
+        }
+        Ok(())
+    }
+
+    fn record(
+        &self,
+        sender: Option<&SubscriberEmail>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: Option<&str>,
+    ) {
+        self.sent().push(SentEmail {
+            sender: sender.map(|s| s.as_ref().into()),
+            recipient: recipient.as_ref().into(),
+            subject: subject.into(),
+            html_content: html_content.into(),
+            text_content: text_content.into(),
+            tag: tag.map(Into::into),
+        });
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for FakeEmailSender {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.check_recipient(recipient)?;
+        self.record(None, recipient, subject, html_content, text_content, None);
+        Ok(())
+    }
+
+    async fn send_tagged_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.check_recipient(recipient)?;
+        self.record(
+            None,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            Some(tag),
+        );
+        Ok(())
+    }
+
+    async fn send_email_from(
+        &self,
+        sender: Option<&SubscriberEmail>,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        tag: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        self.check_recipient(recipient)?;
+        self.record(sender, recipient, subject, html_content, text_content, tag);
+        Ok(())
+    }
+}
+
+/// Keeps the emails put in the outbox in memory.
+#[derive(Default)]
+pub struct FakeEmailOutbox {
+    emails: Mutex<Vec<OutboxEmail>>,
+}
+
+impl FakeEmailOutbox {
+    pub fn emails(&self) -> MutexGuard<'_, Vec<OutboxEmail>> {
+        self.emails.lock().unwrap()
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailOutbox for FakeEmailOutbox {
+    async fn enqueue(&self, email: OutboxEmail) -> Result<(), anyhow::Error> {
+        self.emails().push(email);
+        Ok(())
+    }
+}
+
+/// A subscriber to store, with a random email address unless told otherwise.
+/// Subscribers belong to the default tenant unless told otherwise.
+pub struct SubscriberFixture {
+    email: String,
+    name: String,
+    confirmed: bool,
+    tenant_id: TenantId,
+    locale: Option<Locale>,
+}
+
+pub struct StoredSubscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub subscription_token: SubscriptionToken,
+}
+
+impl SubscriberFixture {
+    pub fn confirmed() -> Self {
+        Self {
+            email: format!("{}@example.com", Uuid::new_v4()),
+            name: "Ursula".into(),
+            confirmed: true,
+            tenant_id: TenantId::DEFAULT,
+            locale: None,
+        }
+    }
+
+    pub fn pending() -> Self {
+        Self {
+            confirmed: false,
+            ..Self::confirmed()
+        }
+    }
+
+    pub fn with_email(mut self, email: &str) -> Self {
+        self.email = email.into();
+        self
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn with_locale(mut self, locale: &str) -> Self {
+        self.locale = Some(Locale::parse(locale).expect("Unsupported locale"));
+        self
+    }
+
+    pub fn for_tenant(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = tenant_id;
+        self
+    }
+
+    pub async fn store(self, pool: &PgPool) -> Result<StoredSubscriber, anyhow::Error> {
+        let new_subscriber = NewSubscriber {
+            email: SubscriberEmail::parse(self.email).map_err(anyhow::Error::msg)?,
+            name: Some(SubscriberName::parse(self.name).map_err(anyhow::Error::msg)?),
+            locale: self.locale,
+        };
+        let repository = PostgresSubscriberRepository::new(pool, self.tenant_id);
+        let pending = repository
+            .store_pending_subscription(&new_subscriber)
+            .await?
+            .pending()?;
+        if self.confirmed {
+            repository
+                .confirm_subscription(&pending.subscription_token)
+                .await?
+                .context("The subscription we just stored could not be confirmed")?;
+        }
+        Ok(StoredSubscriber {
+            id: pending.subscriber_id,
+            email: new_subscriber.email.as_ref().into(),
+            subscription_token: pending.subscription_token,
+        })
+    }
+}
+
+/// A newsletter issue to publish, queued for every confirmed subscriber.
+/// Issues belong to the default tenant unless told otherwise.
+pub struct IssueFixture {
+    title: String,
+    text_content: String,
+    html_content: String,
+    tenant_id: TenantId,
+    tags: Vec<String>,
+    local_send_hour: Option<u8>,
+}
+
+impl Default for IssueFixture {
+    fn default() -> Self {
+        Self {
+            title: "Newsletter title".into(),
+            text_content: "Newsletter body as plain text".into(),
+            html_content: "<p>Newsletter body as HTML</p>".into(),
+            tenant_id: TenantId::DEFAULT,
+            tags: Vec::new(),
+            local_send_hour: None,
+        }
+    }
+}
+
+impl IssueFixture {
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn with_content(mut self, text_content: &str, html_content: &str) -> Self {
+        self.text_content = text_content.into();
+        self.html_content = html_content.into();
+        self
+    }
+
+    pub fn for_tenant(mut self, tenant_id: TenantId) -> Self {
+        self.tenant_id = tenant_id;
+        self
+    }
+
+    pub fn with_tags(mut self, tags: &[&str]) -> Self {
+        self.tags = tags.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    pub fn sent_at_local_hour(mut self, hour: u8) -> Self {
+        self.local_send_hour = Some(hour);
+        self
+    }
+
+    pub async fn publish(self, pool: &PgPool) -> Result<Uuid, anyhow::Error> {
+        let mut issue = NewIssue::parse(self.title, self.text_content, self.html_content)
+            .and_then(|issue| issue.with_tags(self.tags))
+            .map_err(anyhow::Error::msg)?;
+        if let Some(hour) = self.local_send_hour {
+            issue = issue.sent_at_local_hour(hour).map_err(anyhow::Error::msg)?;
+        }
+        let mut transaction = pool.begin().await?;
+        let issue_id = NewsletterService::new(&mut transaction, self.tenant_id)
+            .publish(&issue)
+            .await?;
+        transaction.commit().await?;
+        enqueue_issue_deliveries(pool, issue_id).await?;
+        Ok(issue_id)
+    }
+}
+
+/// The footer closing the issues delivered by `run_worker_once`.
+pub fn email_footer() -> EmailFooterSettings {
+    EmailFooterSettings {
+        organization_name: "Zero To Production".into(),
+        postal_address: "1 Main Street, Springfield".into(),
+    }
+}
+
+/// One pass of the delivery worker, without sending quota nor personal data encryption:
+/// the issues left halfway queued are queued, then tasks are executed until the queue is
+/// empty. Returns how many tasks were executed.
+pub async fn run_worker_once(
+    pool: &PgPool,
+    email_sender: &dyn EmailSender,
+    base_url: &str,
+) -> Result<usize, anyhow::Error> {
+    let (quota, footer) = (SendingQuota::unlimited(), email_footer());
+    let english = Locale::parse("en").expect("English is supported");
+    enqueue_unfinished_issues(pool).await?;
+    let mut executed = 0;
+    while let ExecutionOutcome::TaskCompleted = try_execute_task(
+        pool,
+        email_sender,
+        base_url,
+        &quota,
+        &footer,
+        &english,
+        None,
+    )
+    .await?
+    {
+        executed += 1;
+    }
+    Ok(executed)
+}
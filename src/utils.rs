@@ -1,18 +1,24 @@
-use actix_web::http::header::LOCATION;
-use actix_web::HttpResponse;
+use crate::domain::ConsentEvidence;
+use crate::error::AppError;
+use crate::startup::TrustedProxies;
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::ServiceResponse;
+use actix_web::http::header::{ACCEPT, LOCATION, USER_AGENT};
+use actix_web::{web, HttpRequest, HttpResponse};
+use std::net::IpAddr;
 
 pub fn e500<T>(e: T) -> actix_web::Error
 where
-    T: std::fmt::Debug + std::fmt::Display + 'static,
+    T: Into<anyhow::Error>,
 {
-    actix_web::error::ErrorInternalServerError(e)
+    AppError::UnexpectedError(e.into()).into()
 }
 
 pub fn e400<T>(e: T) -> actix_web::Error
 where
-    T: std::fmt::Debug + std::fmt::Display + 'static,
+    T: std::fmt::Display,
 {
-    actix_web::error::ErrorBadRequest(e)
+    AppError::validation(e.to_string()).into()
 }
 
 pub fn see_other(location: &str) -> HttpResponse {
@@ -21,6 +27,68 @@ pub fn see_other(location: &str) -> HttpResponse {
         .finish()
 }
 
+/// An error response without a body of its own - ours, whose body is plain text, or
+/// actix's, e.g. the empty 404 of an unknown path - to render for the client.
+pub fn is_bare_error<B: MessageBody>(res: &ServiceResponse<B>) -> bool {
+    let status = res.status();
+    (status.is_client_error() || status.is_server_error())
+        && (res.response().error().is_some()
+            || matches!(
+                res.response().body().size(),
+                BodySize::None | BodySize::Sized(0)
+            ))
+}
+
+/// Whether the `Accept` header of the request lists `media_type`.
+pub fn accepts(req: &HttpRequest, media_type: &str) -> bool {
+    req.headers()
+        .get_all(ACCEPT)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.split(';').next().unwrap_or_default().trim())
+        .any(|v| v == media_type)
+}
+
+/// The address of the client the request came from. `X-Forwarded-For` is only believed
+/// when the peer is one of `ApplicationSettings::trusted_proxies`: the client is then the
+/// last address it lists which is not a proxy of ours - those before it may be forged.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let trusted_proxies = req
+        .app_data::<web::Data<TrustedProxies>>()
+        .map(|proxies| proxies.0.as_slice())
+        .unwrap_or_default();
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+    // An entry which is not an address ends the walk, on the address of the proxy.
+    let client = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|addr| addr.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .find(|addr| addr.is_none_or(|addr| !trusted_proxies.contains(&addr)))
+        .flatten();
+    Some(client.unwrap_or(peer))
+}
+
+/// The client the request came from, as proof of a subscriber's consent, see `client_ip`.
+pub fn consent_evidence(req: &HttpRequest) -> ConsentEvidence {
+    ConsentEvidence {
+        ip_address: client_ip(req).map(|addr| addr.to_string()),
+        user_agent: req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(Into::into),
+        ..ConsentEvidence::default()
+    }
+}
+
 pub fn error_chain_fmt(
     e: &impl std::error::Error,
     f: &mut std::fmt::Formatter<'_>,
@@ -0,0 +1,241 @@
+//! The waitlist of a full list: once the deployment has `subscribers.max_subscribers`
+//! subscribers, new signups are stored here instead, until an admin promotes them.
+//!
+//! The cap is enforced in the transaction storing the new subscribers, whichever way they
+//! come in - see `PostgresSubscriberRepository::with_max_subscribers` and
+//! `crate::import`. Those transactions take turns through an advisory lock, so that
+//! concurrent signups cannot all take the last spot.
+use crate::domain::NewSubscriber;
+use crate::i18n::Locale;
+use crate::pii::{mask_email, mask_name, reveal_email, reveal_name, Masked, PiiCipher};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub struct WaitlistEntry {
+    pub entry_id: Uuid,
+    pub email: String,
+    pub name: Option<String>,
+    pub locale: Option<String>,
+    pub joined_at: DateTime<Utc>,
+}
+
+impl Masked for WaitlistEntry {
+    fn masked(self) -> Self {
+        Self {
+            email: mask_email(&self.email),
+            name: self.name.as_deref().map(mask_name),
+            ..self
+        }
+    }
+}
+
+/// How many more subscribers the list takes: the subscribers who did not unsubscribe,
+/// across tenants, count against `max_subscribers`. `None` for no limit - `0`.
+/// The other transactions checking the cap wait for `transaction` to end.
+#[tracing::instrument(name = "Count the spots left on the list", skip(transaction))]
+pub async fn spots_left(
+    transaction: &mut Transaction<'_, Postgres>,
+    max_subscribers: u64,
+) -> Result<Option<u64>, anyhow::Error> {
+    if max_subscribers == 0 {
+        return Ok(None);
+    }
+    sqlx::query!(
+        r#"SELECT 1 AS "locked!" FROM pg_advisory_xact_lock(hashtextextended('max_subscribers', 0))"#
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to lock the list")?;
+    let subscribers = sqlx::query!(
+        r#"
+        SELECT count(*) AS "subscribers!"
+        FROM subscriptions
+        WHERE deleted_at IS NULL AND status <> 'unsubscribed'
+        "#
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to count the subscribers")?
+    .subscribers;
+    Ok(Some(max_subscribers.saturating_sub(subscribers as u64)))
+}
+
+/// Whether a signup of `email` goes to the waitlist: there are no `spots_left`. Known
+/// subscribers signing up again are not waitlisted.
+#[tracing::instrument(name = "Check if the list is full", skip(transaction, email, cipher))]
+pub async fn is_list_full_for(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    email: &str,
+    max_subscribers: u64,
+    cipher: Option<&PiiCipher>,
+) -> Result<bool, anyhow::Error> {
+    if spots_left(transaction, max_subscribers).await? != Some(0) {
+        return Ok(false);
+    }
+    let known = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM subscriptions
+            WHERE
+                tenant_id = $1 AND
+                (email = $2 OR email_blind_index = $3) AND
+                deleted_at IS NULL
+        ) AS "known!"
+        "#,
+        *tenant_id,
+        email,
+        cipher.map(|c| c.blind_index(email))
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to look for a known subscriber")?
+    .known;
+    Ok(!known)
+}
+
+/// Joining twice keeps the first entry, and its place in the queue. With a `cipher`, the
+/// entry is sealed as subscribers are - see `crate::pii` - with its id as associated data.
+#[tracing::instrument(name = "Join the waitlist", skip_all)]
+pub async fn join_waitlist(
+    transaction: &mut Transaction<'_, Postgres>,
+    tenant_id: TenantId,
+    new_subscriber: &NewSubscriber,
+    cipher: Option<&PiiCipher>,
+) -> Result<(), anyhow::Error> {
+    let entry_id = Uuid::new_v4();
+    let email = new_subscriber.email.as_ref();
+    let name = new_subscriber.name.as_ref().map(AsRef::as_ref);
+    let locale = new_subscriber.locale.as_ref().map(Locale::language);
+    let cipher = match cipher {
+        Some(cipher) => cipher,
+        None => {
+            sqlx::query!(
+                r#"
+                INSERT INTO waitlist (entry_id, tenant_id, email, name, locale, joined_at)
+                VALUES ($5, $1, $2, $3, $4, now())
+                ON CONFLICT (tenant_id, email) DO NOTHING
+                "#,
+                *tenant_id,
+                email,
+                name,
+                locale,
+                entry_id
+            )
+            .execute(transaction)
+            .await
+            .context("Failed to add an entry to the waitlist")?;
+            return Ok(());
+        }
+    };
+    let sealed = cipher.seal(entry_id, email, name)?;
+    // Entries stored before the encryption was enabled are matched in the clear.
+    sqlx::query!(
+        r#"
+        INSERT INTO waitlist (
+            entry_id, tenant_id, email, locale, joined_at,
+            email_encrypted, name_encrypted, email_blind_index
+        )
+        SELECT $1, $2, $3, $4, now(), $5, $6, $7
+        WHERE NOT EXISTS (
+            SELECT 1 FROM waitlist WHERE tenant_id = $2 AND email = $8
+        )
+        ON CONFLICT DO NOTHING
+        "#,
+        entry_id,
+        *tenant_id,
+        sealed.email,
+        locale,
+        sealed.email_encrypted,
+        sealed.name_encrypted,
+        sealed.email_blind_index,
+        email
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to add a sealed entry to the waitlist")?;
+    Ok(())
+}
+
+/// The waitlist, first in first, decrypted with `cipher`.
+#[tracing::instrument(name = "List the waitlist", skip(pool, cipher))]
+pub async fn list_waitlist(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    cipher: Option<&PiiCipher>,
+) -> Result<Vec<WaitlistEntry>, anyhow::Error> {
+    sqlx::query!(
+        r#"
+        SELECT entry_id, email, name, locale, joined_at, email_encrypted, name_encrypted
+        FROM waitlist
+        WHERE tenant_id = $1
+        ORDER BY joined_at, email
+        "#,
+        *tenant_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the waitlist")?
+    .into_iter()
+    .map(|r| {
+        Ok(WaitlistEntry {
+            entry_id: r.entry_id,
+            email: reveal_email(cipher, r.entry_id, r.email, r.email_encrypted.as_deref())?,
+            name: reveal_name(cipher, r.entry_id, r.name, r.name_encrypted.as_deref())?,
+            locale: r.locale,
+            joined_at: r.joined_at,
+        })
+    })
+    .collect()
+}
+
+#[tracing::instrument(name = "Find a waitlist entry", skip(pool, cipher))]
+pub async fn find_waitlist_entry(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    entry_id: Uuid,
+    cipher: Option<&PiiCipher>,
+) -> Result<Option<WaitlistEntry>, anyhow::Error> {
+    sqlx::query!(
+        r#"
+        SELECT entry_id, email, name, locale, joined_at, email_encrypted, name_encrypted
+        FROM waitlist
+        WHERE tenant_id = $1 AND entry_id = $2
+        "#,
+        *tenant_id,
+        entry_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a waitlist entry")?
+    .map(|r| {
+        Ok(WaitlistEntry {
+            entry_id: r.entry_id,
+            email: reveal_email(cipher, r.entry_id, r.email, r.email_encrypted.as_deref())?,
+            name: reveal_name(cipher, r.entry_id, r.name, r.name_encrypted.as_deref())?,
+            locale: r.locale,
+            joined_at: r.joined_at,
+        })
+    })
+    .transpose()
+}
+
+#[tracing::instrument(name = "Remove a waitlist entry", skip(pool))]
+pub async fn remove_waitlist_entry(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    entry_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM waitlist WHERE tenant_id = $1 AND entry_id = $2",
+        *tenant_id,
+        entry_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to remove a waitlist entry")?;
+    Ok(())
+}
@@ -0,0 +1,321 @@
+use crate::configuration::WebhookSettings;
+use crate::problem_details::Problem;
+use crate::utils::error_chain_fmt;
+use actix_web::dev::Payload;
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, ResponseError};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use std::fmt::Formatter;
+use std::future::Future;
+use std::pin::Pin;
+
+pub const EVENT_ID_HEADER: &str = "X-Webhook-Id";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// Checks the signature of inbound webhooks.
+///
+/// Senders sign `{event_id}.{timestamp}.{body}` with HMAC-SHA256 using the shared secret
+/// and send:
+/// - the event id in `X-Webhook-Id`;
+/// - the unix timestamp in `X-Webhook-Timestamp`;
+/// - the hex-encoded signature in `X-Webhook-Signature`.
+///
+/// The handlers reject the events they have already seen: they record the event id in the
+/// transaction acting on the event, so that an event whose handling failed is processed
+/// again when the sender retries it - see `WebhookError::Replayed`.
+pub struct WebhookVerifier {
+    signing_secret: Secret<String>,
+    tolerance: std::time::Duration,
+}
+
+impl WebhookVerifier {
+    pub fn new(settings: &WebhookSettings) -> Self {
+        Self {
+            signing_secret: settings.signing_secret.clone(),
+            tolerance: settings.timestamp_tolerance(),
+        }
+    }
+
+    fn verify(
+        &self,
+        event_id: &str,
+        timestamp: &str,
+        body: &[u8],
+        signature: &str,
+    ) -> Result<(), WebhookError> {
+        verify_signature(
+            &self.signing_secret,
+            event_id,
+            timestamp,
+            body,
+            signature,
+            chrono::Utc::now().timestamp(),
+            self.tolerance.as_secs() as i64,
+        )
+    }
+}
+
+/// Sign a webhook body the way senders are expected to.
+pub fn sign_webhook(
+    signing_secret: &Secret<String>,
+    event_id: &str,
+    timestamp: &str,
+    body: &[u8],
+) -> String {
+    hex::encode(
+        mac(signing_secret, event_id, timestamp, body)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+fn mac(
+    signing_secret: &Secret<String>,
+    event_id: &str,
+    timestamp: &str,
+    body: &[u8],
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(signing_secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(event_id.as_bytes());
+    mac.update(b".");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+fn verify_signature(
+    signing_secret: &Secret<String>,
+    event_id: &str,
+    timestamp: &str,
+    body: &[u8],
+    signature: &str,
+    now: i64,
+    tolerance_seconds: i64,
+) -> Result<(), WebhookError> {
+    let sent_at: i64 = timestamp
+        .parse()
+        .map_err(|_| WebhookError::InvalidHeader(TIMESTAMP_HEADER))?;
+    if (now - sent_at).abs() > tolerance_seconds {
+        return Err(WebhookError::StaleTimestamp);
+    }
+    let signature =
+        hex::decode(signature).map_err(|_| WebhookError::InvalidHeader(SIGNATURE_HEADER))?;
+    mac(signing_secret, event_id, timestamp, body)
+        .verify_slice(&signature)
+        .map_err(|_| WebhookError::InvalidSignature)
+}
+
+/// Extractor for webhook routes: the request is only handed over to the handler once its
+/// signature is valid. Rejecting replays is up to the handler, see `WebhookVerifier`.
+/// It requires a `web::Data<WebhookVerifier>` in the application data.
+pub struct VerifiedWebhook<T> {
+    pub event_id: String,
+    pub payload: T,
+}
+
+impl<T: serde::de::DeserializeOwned + 'static> FromRequest for VerifiedWebhook<T> {
+    type Error = WebhookError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let body = web::Bytes::from_request(&req, payload);
+        Box::pin(async move {
+            let verifier = req
+                .app_data::<web::Data<WebhookVerifier>>()
+                .cloned()
+                .context("The webhook verifier is missing from the application data")?;
+            let body = body
+                .await
+                .map_err(|e| WebhookError::InvalidPayload(e.to_string()))?;
+            let event_id = header(&req, EVENT_ID_HEADER)?;
+            let timestamp = header(&req, TIMESTAMP_HEADER)?;
+            let signature = header(&req, SIGNATURE_HEADER)?;
+
+            verifier.verify(event_id, timestamp, &body, signature)?;
+            let payload = serde_json::from_slice(&body)
+                .map_err(|e| WebhookError::InvalidPayload(e.to_string()))?;
+            Ok(VerifiedWebhook {
+                event_id: event_id.to_owned(),
+                payload,
+            })
+        })
+    }
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &'static str) -> Result<&'a str, WebhookError> {
+    req.headers()
+        .get(name)
+        .ok_or(WebhookError::MissingHeader(name))?
+        .to_str()
+        .map_err(|_| WebhookError::InvalidHeader(name))
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("The {0} header is missing.")]
+    MissingHeader(&'static str),
+    #[error("The {0} header is malformed.")]
+    InvalidHeader(&'static str),
+    #[error("The webhook signature is invalid.")]
+    InvalidSignature,
+    #[error("The webhook timestamp is outside of the accepted window.")]
+    StaleTimestamp,
+    #[error("The webhook event {0} was already received.")]
+    Replayed(String),
+    #[error("The webhook payload is invalid: {0}")]
+    InvalidPayload(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl Problem for WebhookError {
+    fn problem_code(&self) -> &'static str {
+        match self {
+            WebhookError::MissingHeader(_) => "missing_header",
+            WebhookError::InvalidHeader(_) => "invalid_header",
+            WebhookError::InvalidSignature => "invalid_signature",
+            WebhookError::StaleTimestamp => "stale_timestamp",
+            WebhookError::Replayed(_) => "replayed_event",
+            WebhookError::InvalidPayload(_) => "invalid_payload",
+            WebhookError::UnexpectedError(_) => "internal_error",
+        }
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::MissingHeader(_)
+            | WebhookError::InvalidHeader(_)
+            | WebhookError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            WebhookError::InvalidSignature | WebhookError::StaleTimestamp => {
+                StatusCode::UNAUTHORIZED
+            }
+            WebhookError::Replayed(_) => StatusCode::CONFLICT,
+            WebhookError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign_webhook, verify_signature, WebhookError};
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    const NOW: i64 = 1_650_000_000;
+    const EVENT_ID: &str = "an-event-id";
+
+    fn secret() -> Secret<String> {
+        Secret::new("my-webhook-signing-secret".into())
+    }
+
+    #[test]
+    fn a_valid_signature_is_accepted() {
+        let signature = sign_webhook(&secret(), EVENT_ID, "1650000000", b"{}");
+        assert_ok!(verify_signature(
+            &secret(),
+            EVENT_ID,
+            "1650000000",
+            b"{}",
+            &signature,
+            NOW,
+            300
+        ));
+    }
+
+    #[test]
+    fn a_tampered_body_is_rejected() {
+        let signature = sign_webhook(&secret(), EVENT_ID, "1650000000", b"{}");
+        let outcome = verify_signature(
+            &secret(),
+            EVENT_ID,
+            "1650000000",
+            b"[]",
+            &signature,
+            NOW,
+            300,
+        );
+        assert!(matches!(outcome, Err(WebhookError::InvalidSignature)));
+    }
+
+    #[test]
+    fn a_signature_made_for_another_event_is_rejected() {
+        let signature = sign_webhook(&secret(), "another-event-id", "1650000000", b"{}");
+        let outcome = verify_signature(
+            &secret(),
+            EVENT_ID,
+            "1650000000",
+            b"{}",
+            &signature,
+            NOW,
+            300,
+        );
+        assert!(matches!(outcome, Err(WebhookError::InvalidSignature)));
+    }
+
+    #[test]
+    fn a_signature_made_with_another_secret_is_rejected() {
+        let signature = sign_webhook(
+            &Secret::new("another-secret".into()),
+            EVENT_ID,
+            "1650000000",
+            b"{}",
+        );
+        let outcome = verify_signature(
+            &secret(),
+            EVENT_ID,
+            "1650000000",
+            b"{}",
+            &signature,
+            NOW,
+            300,
+        );
+        assert!(matches!(outcome, Err(WebhookError::InvalidSignature)));
+    }
+
+    #[test]
+    fn timestamps_outside_of_the_tolerance_are_rejected() {
+        for timestamp in ["1649999699", "1650000301"] {
+            let signature = sign_webhook(&secret(), EVENT_ID, timestamp, b"{}");
+            let outcome =
+                verify_signature(&secret(), EVENT_ID, timestamp, b"{}", &signature, NOW, 300);
+            assert!(matches!(outcome, Err(WebhookError::StaleTimestamp)));
+        }
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        assert_err!(verify_signature(
+            &secret(),
+            EVENT_ID,
+            "yesterday",
+            b"{}",
+            "00",
+            NOW,
+            300
+        ));
+        assert_err!(verify_signature(
+            &secret(),
+            EVENT_ID,
+            "1650000000",
+            b"{}",
+            "not-hex",
+            NOW,
+            300
+        ));
+    }
+}
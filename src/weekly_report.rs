@@ -0,0 +1,275 @@
+//! A summary of the last seven days of the newsletter, emailed to the admins who chose
+//! `AdminEvent::WeeklyReport` on `/admin/notifications`.
+//!
+//! The `send_weekly_reports` scheduled job runs more often than once a week: `weekly_reports`
+//! remembers when each tenant was last reported on, so that a restart of the scheduler
+//! does not send the report again.
+use crate::deliverability_metrics::{percent, ratio, Metrics};
+use crate::notifications::{notify_admins, AdminEvent, AdminNotification};
+use crate::tenancy::TenantId;
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+const REPORT_DAYS: i32 = 7;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WeeklyReport {
+    pub tenant_name: String,
+    pub new_subscribers: i64,
+    pub unsubscribed: i64,
+    /// Confirmed subscribers at the end of the week.
+    pub confirmed: i64,
+    pub issues_sent: i64,
+    /// The provider events of the issues of the tenant received during the week.
+    pub metrics: Metrics,
+    /// Clicks count each recipient once per issue, like opens.
+    pub clicked: i64,
+}
+
+impl WeeklyReport {
+    /// Among the subscribers who were confirmed at some point of the week.
+    pub fn churn_rate_label(&self) -> String {
+        percent(ratio(self.unsubscribed, self.confirmed + self.unsubscribed))
+    }
+
+    pub fn open_rate_label(&self) -> String {
+        self.metrics.open_rate_label()
+    }
+
+    pub fn click_rate_label(&self) -> String {
+        percent(ratio(self.clicked, self.metrics.delivered))
+    }
+
+    pub fn bounce_rate_label(&self) -> String {
+        self.metrics.bounce_rate_label()
+    }
+
+    /// The email to the admins, for the week which ends at `week_end`.
+    pub fn notification(
+        &self,
+        week_end: DateTime<Utc>,
+    ) -> Result<AdminNotification, anyhow::Error> {
+        let week_ending = week_end.format("%Y-%m-%d").to_string();
+        let text = WeeklyReportText {
+            report: self,
+            week_ending: &week_ending,
+        }
+        .render()
+        .context("Failed to render the weekly report as text")?;
+        let html = WeeklyReportHtml {
+            report: self,
+            week_ending: &week_ending,
+        }
+        .render()
+        .context("Failed to render the weekly report as HTML")?;
+        Ok(AdminNotification {
+            html_body: Some(html),
+            ..AdminNotification::new(
+                AdminEvent::WeeklyReport,
+                format!("{}: your weekly report", self.tenant_name),
+                text,
+            )
+        })
+    }
+}
+
+#[derive(Template)]
+#[template(path = "weekly_report.html")]
+struct WeeklyReportHtml<'a> {
+    report: &'a WeeklyReport,
+    week_ending: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "weekly_report.txt")]
+struct WeeklyReportText<'a> {
+    report: &'a WeeklyReport,
+    week_ending: &'a str,
+}
+
+#[tracing::instrument(name = "Compute the weekly report", skip(pool))]
+pub async fn get_weekly_report(
+    pool: &PgPool,
+    tenant_id: TenantId,
+    tenant_name: &str,
+) -> Result<WeeklyReport, anyhow::Error> {
+    let subscribers = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE subscribed_at >= now() - make_interval(days => $2))
+                AS "new_subscribers!",
+            COUNT(*) FILTER (
+                WHERE status = 'unsubscribed' AND
+                    unsubscribed_at >= now() - make_interval(days => $2)
+            ) AS "unsubscribed!",
+            COUNT(*) FILTER (WHERE status = 'confirmed') AS "confirmed!"
+        FROM subscriptions
+        WHERE deleted_at IS NULL AND tenant_id = $1
+        "#,
+        *tenant_id,
+        REPORT_DAYS
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the subscribers of the week")?;
+    let issues = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "issues_sent!"
+        FROM newsletter_issues
+        WHERE
+            tenant_id = $1 AND
+            published_at::timestamptz >= now() - make_interval(days => $2) AND
+            published_at::timestamptz <= now()
+        "#,
+        *tenant_id,
+        REPORT_DAYS
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the issues of the week")?;
+    // Events are matched to issues through the tag they are sent with.
+    let events = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE e.record_type = 'Delivery') AS "delivered!",
+            COUNT(*) FILTER (WHERE e.record_type = 'Bounce') AS "bounced!",
+            COUNT(*) FILTER (WHERE e.record_type = 'SpamComplaint') AS "complained!",
+            COUNT(DISTINCT e.recipient || ' ' || i.newsletter_issue_id)
+                FILTER (WHERE e.record_type = 'Open') AS "opened!",
+            COUNT(DISTINCT e.recipient || ' ' || i.newsletter_issue_id)
+                FILTER (WHERE e.record_type = 'Click') AS "clicked!"
+        FROM email_provider_events e
+        JOIN newsletter_issues i ON e.payload->>'Tag' = i.newsletter_issue_id::text
+        WHERE i.tenant_id = $1 AND e.received_at >= now() - make_interval(days => $2)
+        "#,
+        *tenant_id,
+        REPORT_DAYS
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to aggregate the provider events of the week")?;
+    Ok(WeeklyReport {
+        tenant_name: tenant_name.to_owned(),
+        new_subscribers: subscribers.new_subscribers,
+        unsubscribed: subscribers.unsubscribed,
+        confirmed: subscribers.confirmed,
+        issues_sent: issues.issues_sent,
+        metrics: Metrics {
+            delivered: events.delivered,
+            bounced: events.bounced,
+            complained: events.complained,
+            opened: events.opened,
+        },
+        clicked: events.clicked,
+    })
+}
+
+/// Queue the report of each tenant whose last one is a week old, for the admins who want
+/// it. Returns how many tenants were reported on.
+#[tracing::instrument(name = "Send the weekly reports", skip(pool))]
+pub async fn send_weekly_reports(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let tenants = sqlx::query!(
+        r#"
+        SELECT t.tenant_id, t.name
+        FROM tenants t
+        LEFT JOIN weekly_reports r ON r.tenant_id = t.tenant_id
+        WHERE r.sent_at IS NULL OR r.sent_at <= now() - make_interval(days => $1)
+        "#,
+        REPORT_DAYS
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to find the tenants due a weekly report")?;
+    let mut reported = 0;
+    for tenant in tenants {
+        let tenant_id = TenantId::from(tenant.tenant_id);
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        // Another scheduler may have got there first.
+        let due = sqlx::query!(
+            r#"
+            INSERT INTO weekly_reports (tenant_id, sent_at)
+            VALUES ($1, now())
+            ON CONFLICT (tenant_id) DO UPDATE
+            SET sent_at = now()
+            WHERE weekly_reports.sent_at <= now() - make_interval(days => $2)
+            RETURNING tenant_id
+            "#,
+            *tenant_id,
+            REPORT_DAYS
+        )
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to record a weekly report")?;
+        if due.is_none() {
+            continue;
+        }
+        let report = get_weekly_report(pool, tenant_id, &tenant.name).await?;
+        notify_admins(
+            &mut transaction,
+            tenant_id,
+            &report.notification(Utc::now())?,
+        )
+        .await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit a weekly report")?;
+        reported += 1;
+    }
+    Ok(reported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WeeklyReport;
+    use crate::deliverability_metrics::Metrics;
+    use chrono::{TimeZone, Utc};
+
+    fn report() -> WeeklyReport {
+        WeeklyReport {
+            tenant_name: "Earthsea <News>".into(),
+            new_subscribers: 12,
+            unsubscribed: 2,
+            confirmed: 98,
+            issues_sent: 1,
+            metrics: Metrics {
+                delivered: 200,
+                bounced: 0,
+                complained: 0,
+                opened: 90,
+            },
+            clicked: 30,
+        }
+    }
+
+    #[test]
+    fn rates_are_shown_as_percentages() {
+        let report = report();
+
+        assert_eq!(report.churn_rate_label(), "2.00%");
+        assert_eq!(report.open_rate_label(), "45.00%");
+        assert_eq!(report.click_rate_label(), "15.00%");
+        assert_eq!(report.bounce_rate_label(), "0.00%");
+        assert_eq!(WeeklyReport::default().click_rate_label(), "-");
+    }
+
+    #[test]
+    fn the_report_is_rendered_as_text_and_escaped_html() {
+        let week_end = Utc.with_ymd_and_hms(2022, 5, 28, 8, 0, 0).unwrap();
+
+        let notification = report().notification(week_end).unwrap();
+
+        assert_eq!(notification.subject, "Earthsea <News>: your weekly report");
+        assert!(notification.body.contains("week ending 2022-05-28"));
+        assert!(notification.body.contains("New subscribers: 12"));
+        assert!(notification.body.contains("Click rate: 15.00%"));
+        let html = notification.html_body.unwrap();
+        assert!(html.contains("Earthsea &lt;News&gt;"));
+        assert!(html.contains("45.00%"));
+    }
+}
@@ -0,0 +1,26 @@
+// Adds the events recorded since the dashboard was rendered to the top of the
+// activity feed, as they happen.
+(function () {
+    var feed = document.getElementById("activity-feed");
+    if (!feed || !window.EventSource) {
+        return;
+    }
+    var pad = function (n) {
+        return n < 10 ? "0" + n : "" + n;
+    };
+    var source = new EventSource(feed.dataset.activityUrl);
+    source.addEventListener("activity", function (e) {
+        var entry = JSON.parse(e.data);
+        var at = new Date(entry.occurred_at);
+        var time = document.createElement("time");
+        time.dateTime = entry.occurred_at;
+        time.textContent = at.getUTCFullYear() + "-" + pad(at.getUTCMonth() + 1) + "-" +
+            pad(at.getUTCDate()) + " " + pad(at.getUTCHours()) + ":" +
+            pad(at.getUTCMinutes()) + " UTC";
+        var item = document.createElement("li");
+        item.className = entry.event_type;
+        item.appendChild(time);
+        item.appendChild(document.createTextNode(" " + entry.summary));
+        feed.insertBefore(item, feed.firstChild);
+    });
+})();
@@ -0,0 +1,26 @@
+// Keeps the delivery counts of the last issue up to date while the worker
+// goes through the queue.
+(function () {
+    var container = document.getElementById("delivery-progress");
+    if (!container || !window.EventSource) {
+        return;
+    }
+    var bar = container.querySelector("progress");
+    var field = function (name) {
+        return container.querySelector('[data-field="' + name + '"]');
+    };
+    var source = new EventSource(container.dataset.progressUrl);
+    source.addEventListener("progress", function (e) {
+        var progress = JSON.parse(e.data);
+        field("delivered").textContent = progress.delivered;
+        field("failed").textContent = progress.failed;
+        field("remaining").textContent = progress.remaining;
+        bar.max = progress.delivered + progress.failed + progress.remaining;
+        bar.value = progress.delivered + progress.failed;
+    });
+    // The server closes the stream once the queue is empty: do not reconnect.
+    source.addEventListener("done", function () {
+        field("status").textContent = "delivered";
+        source.close();
+    });
+})();
@@ -0,0 +1,61 @@
+// Saves the compose form in the background every few seconds, so that a crashed
+// browser does not lose a long issue. The form picks the draft up when reopened.
+// While the form is open, a heartbeat keeps the draft locked for the other admins.
+(function () {
+    var form = document.getElementById("newsletter-form");
+    if (!form || !window.fetch) {
+        return;
+    }
+    var status = document.getElementById("autosave-status");
+    var fields = ["title", "html", "text"];
+    var snapshot = function () {
+        var body = new URLSearchParams();
+        fields.forEach(function (name) {
+            body.append(name, form.elements[name].value);
+        });
+        return body.toString();
+    };
+    var saved = snapshot();
+    var post = function (url, body) {
+        return fetch(url, {
+            method: "POST",
+            headers: { "Content-Type": "application/x-www-form-urlencoded" },
+            body: body,
+            credentials: "same-origin"
+        });
+    };
+    var showError = function (response) {
+        if (response.status === 403 || response.status === 409) {
+            response.text().then(function (text) {
+                status.textContent = text;
+            });
+        } else {
+            status.textContent = "The draft could not be saved";
+        }
+    };
+    var heartbeat = function () {
+        post(form.dataset.heartbeatUrl, "").then(function (response) {
+            if (!response.ok) {
+                showError(response);
+            }
+        }, function () {});
+    };
+    heartbeat();
+    setInterval(heartbeat, 30000);
+    setInterval(function () {
+        var current = snapshot();
+        if (current === saved) {
+            return;
+        }
+        post(form.dataset.autosaveUrl, current).then(function (response) {
+            if (response.ok) {
+                saved = current;
+                status.textContent = "Draft saved at " + new Date().toLocaleTimeString();
+            } else {
+                showError(response);
+            }
+        }, function () {
+            status.textContent = "The draft could not be saved";
+        });
+    }, 10000);
+})();
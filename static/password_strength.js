@@ -0,0 +1,17 @@
+// Mirrors the rules of `AdminPassword::parse`: more than 12 characters, mixing
+// lowercase letters, uppercase letters, digits and symbols.
+(function () {
+    var input = document.getElementById("new_password");
+    var meter = document.getElementById("new_password_strength");
+    if (!input || !meter) {
+        return;
+    }
+    input.addEventListener("input", function () {
+        var password = input.value;
+        var classes = [/\p{Ll}/u, /\p{Lu}/u, /[0-9]/, /[^\p{L}\p{N}]/u].filter(function (re) {
+            return re.test(password);
+        }).length;
+        var length = Array.from(password).length;
+        meter.value = length > 12 && length <= 128 ? classes : Math.min(classes, 1);
+    });
+})();
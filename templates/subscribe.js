@@ -0,0 +1,62 @@
+// Subscribe form for the {{ base_url }} newsletter.
+// Embed it with: <script src="{{ base_url }}/embed/subscribe.js" async></script>
+(function () {
+  var script = document.currentScript;
+  var form = document.createElement("form");
+  form.className = "zero2prod-subscribe";
+  form.innerHTML =
+{%- if require_name %}
+    '<label>Name <input type="text" name="name" required></label>' +
+{%- endif %}
+    '<label>Email <input type="email" name="email" required></label>' +
+{%- if require_policy %}
+    '<label><input type="checkbox" name="accepted_policy" required> <span></span></label>' +
+{%- endif %}
+    '<button type="submit">Subscribe</button>' +
+    '<p class="zero2prod-subscribe-message" role="status"></p>';
+{%- if require_policy %}
+  form.elements.accepted_policy.value = {{ policy_version }};
+  form.querySelector("[name=accepted_policy] + span").textContent = {{ policy_text }};
+{%- endif %}
+  script.parentNode.insertBefore(form, script.nextSibling);
+
+  var message = form.querySelector(".zero2prod-subscribe-message");
+  var button = form.querySelector("button");
+  form.addEventListener("submit", function (event) {
+    event.preventDefault();
+    button.disabled = true;
+    fetch("{{ base_url }}/embed/subscriptions", {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({
+{%- if require_name %}
+        name: form.elements.name.value,
+{%- endif %}
+{%- if require_policy %}
+        accepted_policy: form.elements.accepted_policy.checked
+          ? form.elements.accepted_policy.value
+          : null,
+{%- endif %}
+        email: form.elements.email.value
+      })
+    })
+      .then(function (response) {
+        return response.json().then(function (body) {
+          if (!response.ok) {
+            throw new Error(body.error.message);
+          }
+          form.reset();
+          message.textContent =
+            body.status === "waitlisted"
+              ? "The list is full: you have joined the waitlist, we will email you once a spot opens up."
+              : "Thanks! Check your inbox to confirm your subscription.";
+        });
+      })
+      .catch(function (e) {
+        message.textContent = e.message || "Something went wrong, please try again.";
+      })
+      .then(function () {
+        button.disabled = false;
+      });
+  });
+})();
@@ -0,0 +1,239 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::tenancy::TenantId;
+
+async fn get_activity(app: &TestApp, query: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/admin/activity{}", &app.address, query))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// The next chunk of the stream that is not a comment.
+async fn next_event(response: &mut reqwest::Response) -> String {
+    loop {
+        let chunk = response.chunk().await.unwrap().expect("The stream ended");
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        if !chunk.starts_with(':') {
+            return chunk;
+        }
+    }
+}
+
+async fn sign_up(app: &TestApp, email: &str) {
+    app.post_subscriptions(format!(
+        "name=le%20guin&email={}",
+        email.replace('@', "%40")
+    ))
+    .await;
+}
+
+/// A signup of the default tenant, recorded through `executor`.
+async fn record_signup(executor: impl sqlx::PgExecutor<'_>, email: &str) {
+    let event = serde_json::json!({
+        "type": "subscription_requested",
+        "subscriber_id": Uuid::new_v4(),
+        "email": email,
+    });
+    sqlx::query!(
+        r#"
+        INSERT INTO events (event_id, tenant_id, event_type, payload, occurred_at)
+        VALUES ($1, $2, 'subscription_requested', $3, now())
+        "#,
+        Uuid::new_v4(),
+        *TenantId::DEFAULT,
+        event
+    )
+    .execute(executor)
+    .await
+    .unwrap();
+}
+
+async fn last_event_id(app: &TestApp) -> String {
+    sqlx::query!("SELECT event_id FROM events ORDER BY occurred_at DESC LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .event_id
+        .to_string()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_follow_the_activity() {
+    let app = spawn_app().await;
+
+    let response = get_activity(&app, "").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_stream_starts_with_the_recent_activity() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    sign_up(&app, "ursula@example.com").await;
+    app.do_login().await;
+
+    // Act
+    let mut response = get_activity(&app, "").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "text/event-stream");
+    let event = next_event(&mut response).await;
+    assert!(event.starts_with(&format!(
+        "id: {}\nevent: activity\n",
+        last_event_id(&app).await
+    )));
+    assert!(event.contains(r#""event_type":"subscription_requested""#));
+    assert!(event.contains("ursula@example.com signed up"));
+}
+
+#[tokio::test]
+async fn new_activity_is_streamed_as_it_happens() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    sign_up(&app, "ursula@example.com").await;
+    app.do_login().await;
+    let mut response = get_activity(&app, &format!("?after={}", last_event_id(&app).await)).await;
+
+    // Act
+    sign_up(&app, "ged@example.com").await;
+
+    // Assert
+    let event = next_event(&mut response).await;
+    assert!(event.contains("ged@example.com signed up"));
+    assert!(!event.contains("ursula@example.com"));
+}
+
+#[tokio::test]
+async fn a_reconnecting_browser_resumes_after_the_last_event_it_saw() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    sign_up(&app, "ursula@example.com").await;
+    let seen = last_event_id(&app).await;
+    sign_up(&app, "ged@example.com").await;
+    app.do_login().await;
+
+    // Act
+    let mut response = app
+        .api_client
+        .get(format!("{}/admin/activity", &app.address))
+        .header("Last-Event-ID", seen)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let event = next_event(&mut response).await;
+    assert!(event.contains("ged@example.com signed up"));
+    assert!(!event.contains("ursula@example.com"));
+}
+
+#[tokio::test]
+async fn an_event_committed_after_a_later_one_is_still_streamed() {
+    // Arrange
+    let app = spawn_app().await;
+    record_signup(&app.db_pool, "ursula@example.com").await;
+    app.do_login().await;
+    let mut response = get_activity(&app, &format!("?after={}", last_event_id(&app).await)).await;
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    // Its timestamp is earlier than the next event's, its commit later.
+    record_signup(&mut transaction, "ged@example.com").await;
+    record_signup(&app.db_pool, "tenar@example.com").await;
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    // Act
+    transaction.commit().await.unwrap();
+
+    // Assert
+    let mut streamed = String::new();
+    while !streamed.contains("tenar@example.com") {
+        streamed.push_str(&next_event(&mut response).await);
+    }
+    let ged = streamed.find("ged@example.com signed up").unwrap();
+    let tenar = streamed.find("tenar@example.com signed up").unwrap();
+    assert!(ged < tenar);
+}
+
+#[tokio::test]
+async fn the_dashboard_shows_the_activity_feed() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    sign_up(&app, "ursula@example.com").await;
+    app.do_login().await;
+
+    // Act
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(html_page.contains("ursula@example.com signed up"));
+    assert!(html_page.contains(&format!(
+        r#"data-activity-url="/admin/activity?after={}""#,
+        last_event_id(&app).await
+    )));
+}
+
+#[tokio::test]
+async fn the_activity_of_another_tenant_is_not_shown() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_tenant = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO tenants (tenant_id, name) VALUES ($1, 'Other')",
+        other_tenant
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let event = serde_json::json!({
+        "type": "subscription_requested",
+        "subscriber_id": Uuid::new_v4(),
+        "email": "ged@example.com",
+    });
+    sqlx::query!(
+        r#"
+        INSERT INTO events (event_id, tenant_id, event_type, payload, occurred_at)
+        VALUES ($1, $2, 'subscription_requested', $3, now())
+        "#,
+        Uuid::new_v4(),
+        other_tenant,
+        event
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.do_login().await;
+
+    // Act
+    let html_page = app.get_admin_dashboard_html().await;
+    let mut response = get_activity(&app, "").await;
+
+    // Assert
+    assert!(!html_page.contains("ged@example.com"));
+    let chunk = response.chunk().await.unwrap().unwrap();
+    assert_eq!(&chunk[..], b": waiting\n\n");
+}
@@ -0,0 +1,73 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_create_an_api_key() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_api_keys(&serde_json::json!({"description": "automation"}))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_new_api_key_is_shown_once_and_can_be_used() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app
+        .post_api_keys(&serde_json::json!({"description": "automation"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/api_keys");
+
+    let html_page = app.get_api_keys_html().await;
+    let api_key = html_page
+        .split("Your new API key is ")
+        .nth(1)
+        .and_then(|s| s.split(' ').next())
+        .expect("The new API key was not displayed")
+        .to_owned();
+    assert!(html_page.contains("automation"));
+
+    let html_page = app.get_api_keys_html().await;
+    assert!(!html_page.contains(&api_key));
+
+    let response = app.api_get("/subscribers", &api_key).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn api_key_descriptions_are_escaped() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    app.post_api_keys(&serde_json::json!({"description": "<script>alert('hi')</script>"}))
+        .await;
+
+    let html_page = app.get_api_keys_html().await;
+    assert!(html_page.contains("&lt;script&gt;alert(&#x27;hi&#x27;)&lt;/script&gt;"));
+    assert!(!html_page.contains("<script>"));
+}
+
+#[tokio::test]
+async fn revoked_api_keys_are_rejected() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let api_key = app.create_api_key().await;
+    let api_key_id = sqlx::query!("SELECT api_key_id FROM api_keys")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .api_key_id;
+
+    let response = app
+        .post_revoke_api_key(&serde_json::json!({ "api_key_id": api_key_id }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/api_keys");
+    let html_page = app.get_api_keys_html().await;
+    assert!(html_page.contains("<p><i>The API key has been revoked.</i></p>"));
+
+    let response = app.api_get("/subscribers", &api_key).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,205 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app_with, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{header_exists, method, path_regex, query_param};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn spawn_app_backing_up_to(directory: &str) -> TestApp {
+    let directory = directory.to_owned();
+    spawn_app_with(move |c| c.backups.directory = directory).await
+}
+
+fn backup_directory() -> String {
+    std::env::temp_dir()
+        .join(format!("backups-{}", Uuid::new_v4()))
+        .display()
+        .to_string()
+}
+
+async fn backup_job_statuses(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT status FROM jobs WHERE kind = 'backup'")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.status)
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_back_up_the_database() {
+    let app = spawn_app_backing_up_to(&backup_directory()).await;
+
+    assert_is_redirect_to(&app.get_backups().await, "/login");
+    assert_is_redirect_to(&app.post_backups().await, "/login");
+    assert!(backup_job_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn backups_are_written_to_the_directory_and_listed() {
+    let directory = backup_directory();
+    let app = spawn_app_backing_up_to(&directory).await;
+    app.do_login().await;
+    app.make_operator().await;
+
+    assert_is_redirect_to(&app.post_backups().await, "/admin/backups");
+    let html_page = app.get_backups_html().await;
+    assert!(html_page.contains("A backup is in progress."));
+    // Only one backup at a time.
+    app.post_backups().await;
+    assert!(app.get_backups_html().await.contains("already in progress"));
+
+    app.run_all_pending_jobs().await;
+
+    assert_eq!(backup_job_statuses(&app).await, vec!["succeeded"]);
+    let backup = sqlx::query!("SELECT file_name, location, size_bytes FROM backups")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let dump = std::fs::read(&backup.location).unwrap();
+    assert!(dump.starts_with(b"PGDMP"));
+    assert_eq!(dump.len() as i64, backup.size_bytes);
+    assert!(backup.location.starts_with(&directory));
+    let html_page = app.get_backups_html().await;
+    assert!(html_page.contains(&backup.file_name));
+    assert!(html_page.contains(" KB"));
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+async fn spawn_app_backing_up_to_s3() -> TestApp {
+    spawn_app_with(|c| {
+        c.backups.s3.endpoint = c.email_client.base_url.clone();
+        c.backups.s3.bucket = "newsletter-backups".into();
+        c.backups.s3.access_key_id = "AKIDEXAMPLE".into();
+        c.backups.s3.secret_access_key = secrecy::Secret::new("secret".into());
+    })
+    .await
+}
+
+async fn mock_multipart_upload_start(app: &TestApp) {
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/newsletter-backups/.+\.dump$"))
+        .and(query_param("uploads", ""))
+        .and(header_exists("x-amz-content-sha256"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            "<InitiateMultipartUploadResult><UploadId>upload-1</UploadId>\
+             </InitiateMultipartUploadResult>",
+        ))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn backups_are_streamed_to_the_s3_bucket_if_configured() {
+    // Arrange
+    let app = spawn_app_backing_up_to_s3().await;
+    app.do_login().await;
+    app.make_operator().await;
+    mock_multipart_upload_start(&app).await;
+    Mock::given(method("PUT"))
+        .and(query_param("partNumber", "1"))
+        .and(query_param("uploadId", "upload-1"))
+        .respond_with(ResponseTemplate::new(200).insert_header("ETag", "\"etag-1\""))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(query_param("uploadId", "upload-1"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string("<CompleteMultipartUploadResult></CompleteMultipartUploadResult>"),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_backups().await;
+    app.run_all_pending_jobs().await;
+
+    // Assert
+    assert_eq!(backup_job_statuses(&app).await, vec!["succeeded"]);
+    let requests = app.email_server.received_requests().await.unwrap();
+    let authorization = requests[0].headers.get(&"Authorization".into()).unwrap();
+    assert!(authorization
+        .as_str()
+        .starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+    assert!(requests[1].body.starts_with(b"PGDMP"));
+    let completion = String::from_utf8(requests[2].body.clone()).unwrap();
+    assert!(completion.contains("<PartNumber>1</PartNumber><ETag>\"etag-1\"</ETag>"));
+    let backup = sqlx::query!("SELECT location, size_bytes FROM backups")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(backup.location.starts_with("s3://newsletter-backups/"));
+    assert_eq!(backup.size_bytes, requests[1].body.len() as i64);
+}
+
+#[tokio::test]
+async fn a_failed_upload_to_s3_is_aborted() {
+    // Arrange
+    let app = spawn_app_backing_up_to_s3().await;
+    app.do_login().await;
+    app.make_operator().await;
+    mock_multipart_upload_start(&app).await;
+    Mock::given(method("PUT"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(query_param("uploadId", "upload-1"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_backups().await;
+    app.run_all_pending_jobs().await;
+
+    // Assert
+    assert_eq!(backup_job_statuses(&app).await, vec!["failed"]);
+    let backups = sqlx::query!("SELECT location FROM backups")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(backups.is_empty());
+}
+
+#[tokio::test]
+async fn only_operators_can_back_up_the_database() {
+    // Arrange
+    let app = spawn_app_backing_up_to(&backup_directory()).await;
+    app.do_login().await;
+
+    // Act
+    let page = app.get_backups().await;
+    let response = app.post_backups().await;
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 403);
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(backup_job_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn a_failed_backup_is_reported() {
+    let directory = backup_directory();
+    let app = spawn_app_with(|c| {
+        c.backups.directory = directory.clone();
+        c.backups.pg_dump = "/nonexistent/pg_dump".into();
+    })
+    .await;
+    app.do_login().await;
+    app.make_operator().await;
+
+    app.post_backups().await;
+    app.run_all_pending_jobs().await;
+
+    assert_eq!(backup_job_statuses(&app).await, vec!["failed"]);
+    let html_page = app.get_backups_html().await;
+    assert!(html_page.contains("The last backup failed"));
+    assert!(html_page.contains("No backup has been taken yet."));
+    std::fs::remove_dir_all(directory).unwrap();
+}
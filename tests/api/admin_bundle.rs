@@ -0,0 +1,87 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn tenant_settings(app: &TestApp) -> (String, Option<String>, Option<String>) {
+    let r = sqlx::query!(
+        "SELECT name, sender_email, public_base_url FROM tenants WHERE tenant_id = $1",
+        uuid::Uuid::nil()
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    (r.name, r.sender_email, r.public_base_url)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_or_import_the_setup() {
+    let app = spawn_app().await;
+
+    assert_is_redirect_to(&app.get_setup_bundle().await, "/login");
+    assert_is_redirect_to(&app.post_setup_bundle("{}").await, "/login");
+}
+
+#[tokio::test]
+async fn the_setup_of_one_instance_can_be_imported_into_another() {
+    let staging = spawn_app().await;
+    sqlx::query!(
+        r#"
+        UPDATE tenants
+        SET name = 'Earthsea Weekly',
+            sender_email = 'editor@earthsea.org',
+            public_base_url = 'https://news.earthsea.org'
+        "#
+    )
+    .execute(&staging.db_pool)
+    .await
+    .unwrap();
+    staging.do_login().await;
+    let response = staging.get_setup_bundle().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let bundle = response.text().await.unwrap();
+    assert!(!bundle.contains(&staging.test_user.username));
+
+    let production = spawn_app().await;
+    production.do_login().await;
+    let response = production.post_setup_bundle(&bundle).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    assert!(production
+        .get_admin_dashboard_html()
+        .await
+        .contains("The setup of the newsletter has been imported."));
+    assert_eq!(
+        tenant_settings(&production).await,
+        (
+            "Earthsea Weekly".into(),
+            Some("editor@earthsea.org".into()),
+            Some("https://news.earthsea.org".into())
+        )
+    );
+}
+
+#[tokio::test]
+async fn an_invalid_bundle_is_rejected() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let before = tenant_settings(&app).await;
+
+    for (bundle, error) in [
+        (
+            r#"{"format_version": 99}"#,
+            "only version 1 can be imported",
+        ),
+        (
+            r#"{
+                "format_version": 1,
+                "exported_at": "2022-05-01T00:00:00Z",
+                "settings": {"name": "News", "sender_email": null, "public_base_url": "news.example.com"}
+            }"#,
+            "The bundle is invalid: the public base URL",
+        ),
+    ] {
+        let response = app.post_setup_bundle(bundle).await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+        let html_page = app.get_admin_dashboard_html().await;
+        assert!(html_page.contains(error), "{}", html_page);
+    }
+    assert_eq!(tenant_settings(&app).await, before);
+}
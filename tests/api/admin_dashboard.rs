@@ -1,4 +1,5 @@
 use crate::helpers::{assert_is_redirect_to, spawn_app};
+use zero2prod::testing::{IssueFixture, SubscriberFixture};
 
 #[tokio::test]
 async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
@@ -38,3 +39,85 @@ async fn logout_clears_session_state() {
     let response = app.get_admin_dashboard().await;
     assert_is_redirect_to(&response, "/login");
 }
+
+#[tokio::test]
+async fn the_dashboard_gives_an_overview_of_subscribers_and_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..2 {
+        SubscriberFixture::confirmed()
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    SubscriberFixture::pending()
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    let issue_id = IssueFixture::default()
+        .with_title("Overview issue")
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    app.do_login().await;
+
+    // Act
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(html_page.contains("<li>Confirmed: 2</li>"));
+    assert!(html_page.contains("<li>Pending confirmation: 1</li>"));
+    assert!(html_page.contains("<li>New over the last 7 days: 3</li>"));
+    assert!(html_page.contains("Overview issue"));
+    assert!(html_page.contains("delivering"));
+    assert!(html_page.contains(&format!("/admin/newsletters/{}/progress", issue_id)));
+    assert!(html_page.contains("Deliveries waiting to be sent: 2"));
+    assert!(html_page.contains("No failed deliveries."));
+}
+
+#[tokio::test]
+async fn the_dashboard_lists_recent_delivery_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber = SubscriberFixture::confirmed()
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    IssueFixture::default()
+        .with_title("Failing issue")
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    app.do_login().await;
+
+    // Act
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(html_page.contains(&format!("{} - Failing issue (failed", subscriber.email)));
+    assert!(html_page.contains("Deliveries waiting to be sent: 0"));
+}
+
+#[tokio::test]
+async fn admin_pages_share_the_navigation() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    // Act
+    let pages = [
+        app.get_admin_dashboard_html().await,
+        app.get_change_password_html().await,
+        app.get_newsletters_html().await,
+        app.get_logging_html().await,
+        app.get_api_keys_html().await,
+    ];
+
+    // Assert
+    for html_page in pages {
+        assert!(html_page.contains(r#"<a href="/admin/dashboard">Dashboard</a>"#));
+        assert!(html_page.contains(r#"<form name="logoutForm" action="/admin/logout""#));
+    }
+    assert!(!app.get_login_html().await.contains("logoutForm"));
+}
@@ -0,0 +1,179 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use zero2prod::tenancy::TenantId;
+use zero2prod::testing::SubscriberFixture;
+
+async fn post_event(app: &TestApp, record_type: &str, recipient: &str, issue_id: &str) {
+    let event = serde_json::json!({
+        "RecordType": record_type,
+        "Recipient": recipient,
+        "Tag": issue_id,
+    });
+    let response = app
+        .post_signed_webhook(&Uuid::new_v4().to_string(), &event)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_deliverability_dashboard() {
+    let app = spawn_app().await;
+
+    let response = app.get_deliverability().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_dashboard_renders_without_any_event() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app.get_deliverability().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h2>Weekly trend</h2>"));
+    assert!(html_page.contains("No events yet."));
+    assert!(html_page.contains("No issue has been published yet."));
+}
+
+#[tokio::test]
+async fn provider_events_are_aggregated_by_domain_and_by_issue() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let response = app
+        .api_post(
+            "/newsletters",
+            &api_key,
+            &serde_json::json!({
+                "title": "Spring edition",
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let issue_id = issue["newsletter_issue_id"].as_str().unwrap();
+    post_event(&app, "Delivery", "ursula@gmail.com", issue_id).await;
+    post_event(&app, "Delivery", "ged@gmail.com", issue_id).await;
+    post_event(&app, "SpamComplaint", "ged@gmail.com", issue_id).await;
+    // Opening twice still counts as one open.
+    post_event(&app, "Open", "ursula@gmail.com", issue_id).await;
+    post_event(&app, "Open", "ursula@gmail.com", issue_id).await;
+    post_event(&app, "Delivery", "tenar@yahoo.com", issue_id).await;
+    post_event(&app, "Bounce", "arha@Yahoo.com", issue_id).await;
+    app.do_login().await;
+
+    let response = app.get_deliverability().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(
+        "<td>gmail.com</td>\n<td>2</td>\n<td>0</td>\n<td>1</td>\n\
+         <td>0.00%</td>\n<td>50.00%</td>\n<td>50.00%</td>\n\
+         <td class=\"problems\">complaint rate above 0.1%</td>"
+    ));
+    assert!(html_page.contains(
+        "<td>yahoo.com</td>\n<td>1</td>\n<td>1</td>\n<td>0</td>\n\
+         <td>50.00%</td>\n<td>0.00%</td>\n<td>0.00%</td>\n\
+         <td class=\"problems\">bounce rate above 2%</td>"
+    ));
+    assert!(html_page.contains("<td>Spring edition</td>"));
+    assert!(html_page.contains(
+        "<td>3</td>\n<td>1</td>\n<td>1</td>\n\
+         <td>25.00%</td>\n<td>33.33%</td>\n<td>33.33%</td>\n\
+         <td class=\"problems\">bounce rate above 2%, complaint rate above 0.1%</td>"
+    ));
+}
+
+#[tokio::test]
+async fn bounces_are_broken_down_by_category_and_blocked_ones_are_flagged() {
+    let app = spawn_app().await;
+    for email in [
+        "ursula@gmail.com",
+        "ged@gmail.com",
+        "tenar@yahoo.com",
+        "arha@example.com",
+    ] {
+        SubscriberFixture::confirmed()
+            .with_email(email)
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    for (bounce_type, recipient) in [
+        ("HardBounce", "ursula@gmail.com"),
+        ("HardBounce", "ged@gmail.com"),
+        ("InboxFull", "tenar@yahoo.com"),
+    ] {
+        let event = serde_json::json!({
+            "RecordType": "Bounce",
+            "Email": recipient,
+            "Type": bounce_type,
+        });
+        app.post_signed_webhook(&Uuid::new_v4().to_string(), &event)
+            .await;
+    }
+    let blocked = serde_json::json!({
+        "RecordType": "Bounce",
+        "Email": "arha@example.com",
+        "Type": "Unknown",
+        "Description": "Rejected by the receiving server",
+        "Details": "554 5.7.1 Service unavailable; client host blocked",
+    });
+    app.post_signed_webhook(&Uuid::new_v4().to_string(), &blocked)
+        .await;
+    app.do_login().await;
+
+    let response = app.get_deliverability().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<td>hard</td>\n<td>2</td>\n<td>Suppressed</td>"));
+    assert!(html_page.contains("<td>soft</td>\n<td>1</td>\n<td>Retried later</td>"));
+    assert!(html_page.contains("<td>blocked</td>\n<td>1</td>\n<td>Flagged for review</td>"));
+    assert!(html_page.contains("<td>spam</td>\n<td>0</td>\n<td>Flagged for review</td>"));
+    assert!(html_page.contains(
+        "<td>arha@example.com</td>\n<td>blocked</td>\n<td>Rejected by the receiving server</td>"
+    ));
+    assert!(!html_page.contains("<td>ursula@gmail.com</td>"));
+}
+
+#[tokio::test]
+async fn the_bounces_of_another_tenant_are_not_listed() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_tenant = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO tenants (tenant_id, name) VALUES ($1, 'Other')",
+        other_tenant
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    SubscriberFixture::confirmed()
+        .with_email("arha@example.com")
+        .for_tenant(TenantId::from(other_tenant))
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    let blocked = serde_json::json!({
+        "RecordType": "Bounce",
+        "Email": "arha@example.com",
+        "Type": "Unknown",
+        "Description": "Rejected by the receiving server",
+        "Details": "554 5.7.1 Service unavailable; client host blocked",
+    });
+    app.post_signed_webhook(&Uuid::new_v4().to_string(), &blocked)
+        .await;
+    app.do_login().await;
+
+    // Act
+    let response = app.get_deliverability().await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<td>blocked</td>\n<td>0</td>"));
+    assert!(!html_page.contains("arha@example.com"));
+}
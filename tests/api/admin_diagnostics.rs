@@ -0,0 +1,171 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+use std::collections::HashMap;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, UdpSocket};
+
+type Zone = HashMap<String, Vec<String>>;
+
+fn zone(records: &[(&str, &str)]) -> Zone {
+    let mut zone = Zone::new();
+    for (name, record) in records {
+        zone.entry(name.to_string())
+            .or_default()
+            .push(record.to_string());
+    }
+    zone
+}
+
+/// A nameserver answering TXT queries from `records`, and NXDOMAIN for unknown names.
+async fn spawn_nameserver(records: &[(&str, &str)]) -> String {
+    let zone = zone(records);
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        let mut query = [0; 512];
+        loop {
+            let (length, peer) = socket.recv_from(&mut query).await.unwrap();
+            let response = answer(&zone, &query[..length]);
+            socket.send_to(&response, peer).await.unwrap();
+        }
+    });
+    address
+}
+
+/// A nameserver whose answers never fit in a datagram: over UDP, they are truncated to
+/// their header. They are answered in full over TCP, on the same port.
+async fn spawn_nameserver_answering_over_tcp(records: &[(&str, &str)]) -> String {
+    let zone = zone(records);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let socket = UdpSocket::bind(&address).await.unwrap();
+    tokio::spawn(async move {
+        let mut query = [0; 512];
+        loop {
+            let (length, peer) = socket.recv_from(&mut query).await.unwrap();
+            let mut response = answer(&Zone::new(), &query[..length]);
+            response.truncate(12);
+            response[2] |= 0x02;
+            response[3] &= 0xF0;
+            socket.send_to(&response, peer).await.unwrap();
+        }
+    });
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let length = stream.read_u16().await.unwrap() as usize;
+            let mut query = vec![0; length];
+            stream.read_exact(&mut query).await.unwrap();
+            let response = answer(&zone, &query);
+            stream.write_u16(response.len() as u16).await.unwrap();
+            stream.write_all(&response).await.unwrap();
+        }
+    });
+    address
+}
+
+fn answer(zone: &Zone, query: &[u8]) -> Vec<u8> {
+    let (name, question_end) = read_question(query);
+    let answers = zone.get(&name).cloned().unwrap_or_default();
+
+    let mut response = query[..2].to_vec();
+    response.extend(if zone.contains_key(&name) {
+        [0x81, 0x80]
+    } else {
+        [0x81, 0x83]
+    });
+    response.extend([0, 1, 0, answers.len() as u8, 0, 0, 0, 0]);
+    response.extend(&query[12..question_end]);
+    for answer in answers {
+        response.extend([0xC0, 12, 0, 16, 0, 1, 0, 0, 1, 0]);
+        response.extend(((answer.len() + 1) as u16).to_be_bytes());
+        response.push(answer.len() as u8);
+        response.extend(answer.as_bytes());
+    }
+    response
+}
+
+/// The name asked about, and where the question ends.
+fn read_question(query: &[u8]) -> (String, usize) {
+    let mut labels: Vec<String> = Vec::new();
+    let mut position = 12;
+    while query[position] != 0 {
+        let length = query[position] as usize;
+        labels.push(String::from_utf8_lossy(&query[position + 1..position + 1 + length]).into());
+        position += 1 + length;
+    }
+    (labels.join("."), position + 5)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_deliverability_diagnostics() {
+    let app = spawn_app().await;
+
+    let response = app.get_diagnostics().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_records_of_the_sending_domain_are_checked() {
+    // The test configuration sends from test@example.com.
+    let nameserver = spawn_nameserver(&[
+        ("example.com", "v=spf1 include:spf.mtasv.net -all"),
+        ("_dmarc.example.com", "v=DMARC1; p=none"),
+        (
+            "other._domainkey.example.com",
+            "k=rsa; p=MIGfMA0GCSqGSIb3DQEB",
+        ),
+    ])
+    .await;
+    let app = spawn_app_with(|c| c.deliverability.nameserver = nameserver).await;
+    app.do_login().await;
+
+    let response = app.get_diagnostics().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h2>SPF: pass</h2>"));
+    assert!(html_page.contains("<pre>v=spf1 include:spf.mtasv.net -all</pre>"));
+    assert!(html_page.contains("<h2>DKIM: failure</h2>"));
+    assert!(html_page.contains("There is no DKIM record for the provider&#x27;s selector."));
+    assert!(html_page.contains("<h2>DMARC: warning</h2>"));
+    assert!(html_page.contains("The DMARC policy is p=none"));
+}
+
+#[tokio::test]
+async fn answers_truncated_over_udp_are_asked_again_over_tcp() {
+    let nameserver = spawn_nameserver_answering_over_tcp(&[(
+        "example.com",
+        "v=spf1 include:spf.mtasv.net -all",
+    )])
+    .await;
+    let app = spawn_app_with(|c| c.deliverability.nameserver = nameserver).await;
+    app.do_login().await;
+
+    let response = app.get_diagnostics().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h2>SPF: pass</h2>"));
+    assert!(html_page.contains("<pre>v=spf1 include:spf.mtasv.net -all</pre>"));
+}
+
+#[tokio::test]
+async fn a_nameserver_which_does_not_answer_is_reported() {
+    // Bound, but nobody reads from it.
+    let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let nameserver = silent.local_addr().unwrap().to_string();
+    let app = spawn_app_with(|c| {
+        c.deliverability.nameserver = nameserver;
+        c.deliverability.timeout_milliseconds = 200;
+    })
+    .await;
+    app.do_login().await;
+
+    let response = app.get_diagnostics().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("The DNS lookup failed: The nameserver did not answer in time"));
+    assert!(!html_page.contains(": pass</h2>"));
+}
@@ -0,0 +1,74 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_logging_form() {
+    let app = spawn_app().await;
+
+    let response = app.get_logging().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_change_the_logging_configuration() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_logging(&serde_json::json!({
+            "level": "debug",
+            "format": "pretty",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_invalid_filter_is_rejected() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app
+        .post_logging(&serde_json::json!({
+            "level": "zero2prod=notalevel",
+            "format": "json",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/logging");
+
+    let html_page = app.get_logging_html().await;
+    assert!(html_page.contains("Failed to parse the log filter"));
+}
+
+#[tokio::test]
+async fn an_unknown_format_is_rejected() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app
+        .post_logging(&serde_json::json!({
+            "level": "info",
+            "format": "xml",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn changing_the_logging_configuration_works() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app
+        .post_logging(&serde_json::json!({
+            "level": "info,zero2prod=info",
+            "format": "json",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/logging");
+
+    let html_page = app.get_logging_html().await;
+    assert!(html_page.contains("<p><i>The logging configuration has been updated.</i></p>"));
+    assert!(html_page.contains(r#"value="info,zero2prod=info""#));
+}
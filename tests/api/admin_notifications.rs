@@ -0,0 +1,179 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::i18n::Locale;
+use zero2prod::issue_delivery_worker::try_execute_task;
+use zero2prod::sending_quota::SendingQuota;
+use zero2prod::testing::{email_footer, FakeEmailSender, IssueFixture, SubscriberFixture};
+
+/// The subjects of the emails waiting in the outbox for `recipient`, oldest first.
+async fn notifications_for(app: &TestApp, recipient: &str) -> Vec<String> {
+    sqlx::query!(
+        "SELECT subject FROM email_outbox WHERE recipient = $1 ORDER BY enqueued_at",
+        recipient
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.subject)
+    .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_notification_preferences() {
+    let app = spawn_app().await;
+
+    let response = app.get_notifications().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn notification_preferences_are_saved_per_admin() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app
+        .post_notifications(&[
+            ("email", "admin@example.com"),
+            ("event", "send_completed"),
+            ("event", "new_complaint"),
+            ("failure_rate_percent", "10"),
+        ])
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/notifications");
+    let html_page = app.get_notifications_html().await;
+    assert!(html_page.contains("Your notification preferences have been saved."));
+    assert!(html_page.contains(r#"value="admin@example.com""#));
+    assert!(html_page.contains(r#"value="send_completed" checked"#));
+    assert!(html_page.contains(r#"value="new_complaint" checked"#));
+    assert!(!html_page.contains(r#"value="new_login" checked"#));
+    let saved = sqlx::query!(
+        "SELECT email, events, failure_rate_threshold FROM admin_notification_preferences"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.email, "admin@example.com");
+    assert_eq!(saved.events, ["send_completed", "new_complaint"]);
+    assert!((saved.failure_rate_threshold - 0.1).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn admins_are_only_emailed_about_the_events_they_chose() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.post_notifications(&[("email", "admin@example.com"), ("event", "new_login")])
+        .await;
+
+    app.post_logout().await;
+    app.do_login().await;
+    app.publish_issue().await;
+
+    let notifications = notifications_for(&app, "admin@example.com").await;
+    assert_eq!(notifications.len(), 1);
+    assert!(notifications[0].ends_with("logged in"));
+}
+
+#[tokio::test]
+async fn admins_are_emailed_when_an_issue_finished_sending() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.post_notifications(&[("email", "admin@example.com"), ("event", "send_completed")])
+        .await;
+
+    app.publish_issue().await;
+
+    assert_eq!(
+        notifications_for(&app, "admin@example.com").await,
+        ["\"Newsletter title\" has been sent"]
+    );
+}
+
+#[tokio::test]
+async fn admins_are_emailed_when_failures_cross_their_threshold() {
+    let app = spawn_app().await;
+    app.import_confirmed_subscribers(&["ursula@example.com"])
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    app.do_login().await;
+    app.post_notifications(&[
+        ("email", "admin@example.com"),
+        ("event", "delivery_failures"),
+        ("failure_rate_percent", "50"),
+    ])
+    .await;
+
+    app.publish_issue().await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(
+        notifications_for(&app, "admin@example.com").await,
+        ["Many deliveries of \"Newsletter title\" failed"]
+    );
+}
+
+#[tokio::test]
+async fn admins_are_emailed_once_about_a_spike_of_complaints_and_about_the_pause() {
+    let app = spawn_app_with(|c| c.newsletters.complaint_rate_min_deliveries = 2).await;
+    for _ in 0..3 {
+        SubscriberFixture::confirmed()
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let sender = FakeEmailSender::default();
+    let (quota, footer) = (SendingQuota::unlimited(), email_footer());
+    let english = Locale::parse("en").unwrap();
+    for _ in 0..2 {
+        try_execute_task(
+            &app.db_pool,
+            &sender,
+            &app.address,
+            &quota,
+            &footer,
+            &english,
+            None,
+        )
+        .await
+        .unwrap();
+    }
+    app.do_login().await;
+    app.post_notifications(&[
+        ("email", "admin@example.com"),
+        ("event", "new_complaint"),
+        ("event", "delivery_paused"),
+    ])
+    .await;
+
+    let recipients: Vec<String> = sender.sent().iter().map(|e| e.recipient.clone()).collect();
+    for recipient in recipients {
+        let complaint = serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "Email": recipient,
+            "Tag": issue_id.to_string(),
+        });
+        let response = app
+            .post_signed_webhook(&Uuid::new_v4().to_string(), &complaint)
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let mut notifications = notifications_for(&app, "admin@example.com").await;
+    notifications.sort();
+    assert_eq!(
+        notifications,
+        [
+            "A subscriber marked \"Newsletter title\" as spam",
+            "The delivery of \"Newsletter title\" is paused"
+        ]
+    );
+}
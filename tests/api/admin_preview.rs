@@ -0,0 +1,105 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use zero2prod::testing::SubscriberFixture;
+
+async fn get(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", app.address, path))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_preview_pages() {
+    let app = spawn_app().await;
+
+    assert_is_redirect_to(&get(&app, "/admin/preview").await, "/login");
+    assert_is_redirect_to(
+        &get(
+            &app,
+            "/admin/preview/unsubscribe?email=ursula%40example.com",
+        )
+        .await,
+        "/login",
+    );
+}
+
+#[tokio::test]
+async fn previews_are_rendered_in_the_locale_of_the_subscriber() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    SubscriberFixture::confirmed()
+        .with_email("ursula@example.com")
+        .with_locale("fr")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = get(
+        &app,
+        "/admin/preview/confirmation?email=ursula%40example.com",
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Inscription confirmée"));
+}
+
+#[tokio::test]
+async fn previews_leave_the_subscriber_and_their_token_untouched() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let subscriber = SubscriberFixture::pending()
+        .with_email("ursula@example.com")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE subscriptions SET timezone = 'Europe/Paris'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    for page in ["confirmation", "preferences", "unsubscribe"] {
+        let response = get(
+            &app,
+            &format!("/admin/preview/{}?email=ursula%40example.com", page),
+        )
+        .await;
+        assert_eq!(response.status().as_u16(), 200);
+        let html = response.text().await.unwrap();
+        assert!(!html.contains(subscriber.subscription_token.as_ref()));
+        if page == "preferences" {
+            assert!(html.contains(r#"<option value="Europe/Paris" selected>"#));
+        }
+    }
+
+    let saved = sqlx::query!(
+        "SELECT s.status::text, t.confirmation_used_at
+        FROM subscriptions s JOIN subscription_tokens t ON t.subscriber_id = s.id"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status.as_deref(), Some("pending_confirmation"));
+    assert_eq!(saved.confirmation_used_at, None);
+}
+
+#[tokio::test]
+async fn previewing_an_unknown_subscriber_is_rejected() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = get(
+        &app,
+        "/admin/preview/unsubscribe?email=nobody%40example.com",
+    )
+    .await;
+
+    assert_is_redirect_to(&response, "/admin/preview");
+    let html = get(&app, "/admin/preview").await.text().await.unwrap();
+    assert!(html.contains("No subscriber has this email address."));
+}
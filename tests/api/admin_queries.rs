@@ -0,0 +1,56 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_query_metrics() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/queries", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn only_operators_can_see_the_query_metrics() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/admin/queries", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_queries_run_by_the_application_are_listed_with_their_latency() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.make_operator().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/admin/queries", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    // Logging in looks up the credentials of the admin.
+    assert!(html_page.contains("SELECT user_id, password_hash FROM users"));
+    assert!(html_page.contains("<th>p95</th>"));
+}
@@ -0,0 +1,98 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use zero2prod::testing::{IssueFixture, SubscriberFixture};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_search() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_search("ursula").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_found_by_any_part_of_their_email_or_name() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_email("ursula@example.com")
+        .with_name("Ursula Le Guin")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    SubscriberFixture::confirmed()
+        .with_email("ged@earthsea.org")
+        .with_name("Sparrowhawk")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    app.do_login().await;
+
+    for q in ["ursula", "EXAMP", "le gu"] {
+        let html_page = app.get_admin_search(q).await.text().await.unwrap();
+        assert!(html_page.contains("ursula@example.com"), "{}", q);
+        assert!(!html_page.contains("ged@earthsea.org"), "{}", q);
+    }
+}
+
+#[tokio::test]
+async fn issues_are_found_by_title_or_content() {
+    let app = spawn_app().await;
+    IssueFixture::default()
+        .with_title("Spring edition")
+        .with_content("Planting tomatoes", "<p>Planting tomatoes</p>")
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    IssueFixture::default()
+        .with_title("Autumn edition")
+        .with_content("Harvesting apples", "<p>Harvesting apples</p>")
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    app.do_login().await;
+
+    let html_page = app.get_admin_search("spring").await.text().await.unwrap();
+    assert!(html_page.contains("Spring edition"));
+    assert!(!html_page.contains("Autumn edition"));
+
+    let html_page = app.get_admin_search("apples").await.text().await.unwrap();
+    assert!(html_page.contains("Autumn edition"));
+    assert!(!html_page.contains("Spring edition"));
+    assert!(html_page.contains("No subscriber matches."));
+}
+
+#[tokio::test]
+async fn an_empty_search_shows_the_form_only() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app.get_admin_search("  ").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"name="q""#));
+    assert!(!html_page.contains("<h2>Subscribers</h2>"));
+}
+
+#[tokio::test]
+async fn subscribers_are_masked_for_admins_without_pii_access() {
+    // Arrange
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_email("ursula@example.com")
+        .with_name("Ursula Le Guin")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    app.revoke_pii_access().await;
+    app.do_login().await;
+
+    // Act
+    let html_page = app.get_admin_search("ursula").await.text().await.unwrap();
+
+    // Assert
+    assert!(html_page.contains("<td>u***@example.com</td>"));
+    assert!(html_page.contains("<td>U***</td>"));
+    assert!(!html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("Le Guin"));
+}
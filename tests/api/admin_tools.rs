@@ -0,0 +1,169 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn run_tool(app: &TestApp, task: &str) {
+    let response = app
+        .post_tools(&serde_json::json!({ "task": task, "confirm": "true" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/tools");
+}
+
+async fn job_statuses(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"SELECT payload->>'task' AS "task!", status FROM jobs WHERE kind = 'maintenance'"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.task, r.status))
+    .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_use_the_tools() {
+    let app = spawn_app().await;
+
+    assert_is_redirect_to(&app.get_tools().await, "/login");
+    let response = app
+        .post_tools(&serde_json::json!({ "task": "vacuum_analyze", "confirm": "true" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(job_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn only_operators_can_use_the_tools() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    // Act
+    let page = app.get_tools().await;
+    let response = app
+        .post_tools(&serde_json::json!({ "task": "vacuum_analyze", "confirm": "true" }))
+        .await;
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 403);
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(job_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn tasks_must_be_confirmed() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.make_operator().await;
+
+    let response = app
+        .post_tools(&serde_json::json!({ "task": "vacuum_analyze" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/tools");
+
+    let html_page = app.get_tools_html().await;
+    assert!(html_page.contains("Tick the confirmation box"));
+    assert!(job_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn every_task_runs_as_a_tracked_background_job() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.make_operator().await;
+
+    for task in [
+        "vacuum_analyze",
+        "purge_idempotency_keys",
+        "purge_dead_letters",
+        "rebuild_search_indexes",
+    ] {
+        run_tool(&app, task).await;
+    }
+    assert!(job_statuses(&app)
+        .await
+        .iter()
+        .all(|(_, status)| status == "queued"));
+    assert!(app.get_tools_html().await.contains("In progress."));
+
+    app.run_all_pending_jobs().await;
+
+    let statuses = job_statuses(&app).await;
+    assert_eq!(statuses.len(), 4);
+    assert!(statuses.iter().all(|(_, status)| status == "succeeded"));
+    let html_page = app.get_tools_html().await;
+    assert!(html_page.contains("Vacuum and analyze the hot tables"));
+    assert!(!html_page.contains("In progress."));
+}
+
+#[tokio::test]
+async fn a_task_in_progress_cannot_be_started_again() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.make_operator().await;
+
+    run_tool(&app, "vacuum_analyze").await;
+    run_tool(&app, "vacuum_analyze").await;
+
+    assert_eq!(job_statuses(&app).await.len(), 1);
+    assert!(app.get_tools_html().await.contains("already in progress"));
+}
+
+#[tokio::test]
+async fn purging_the_dead_letters_deletes_the_failed_jobs_of_the_tenant_only() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.make_operator().await;
+    for status in ["failed", "succeeded"] {
+        sqlx::query!(
+            r#"
+            INSERT INTO jobs (job_id, kind, payload, status, total, created_at)
+            VALUES ($1, 'bulk_delete', '{}', $2, 0, now())
+            "#,
+            Uuid::new_v4(),
+            status
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    // Another tenant's failed job.
+    let other_tenant = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO tenants (tenant_id, name) VALUES ($1, 'Other')",
+        other_tenant
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO jobs (job_id, kind, payload, status, total, created_at, tenant_id)
+        VALUES ($1, 'reenqueue', '{}', 'failed', 0, now(), $2)
+        "#,
+        Uuid::new_v4(),
+        other_tenant
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    run_tool(&app, "purge_dead_letters").await;
+    app.run_all_pending_jobs().await;
+
+    let kinds: Vec<_> = sqlx::query!("SELECT kind, status FROM jobs ORDER BY kind")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.kind, r.status))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            ("bulk_delete".to_owned(), "succeeded".to_owned()),
+            ("maintenance".to_owned(), "succeeded".to_owned()),
+            ("reenqueue".to_owned(), "failed".to_owned())
+        ]
+    );
+}
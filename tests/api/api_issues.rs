@@ -0,0 +1,260 @@
+use crate::helpers::{spawn_app, TestApp};
+
+async fn publish_issue(app: &TestApp, api_key: &str, title: &str) -> String {
+    let response = app
+        .api_post(
+            "/newsletters",
+            api_key,
+            &serde_json::json!({
+                "title": title,
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    issue["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn the_archive_does_not_require_an_api_key() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_archive("", None).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(page["issues"], serde_json::json!([]));
+    assert_eq!(page["total_issues"], 0);
+}
+
+#[tokio::test]
+async fn the_archive_lists_issues_most_recent_first_with_pagination() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    for title in ["First", "Second", "Third"] {
+        publish_issue(&app, &api_key, title).await;
+    }
+
+    // Act
+    let first_page: serde_json::Value = app
+        .get_archive("?limit=2", None)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let cursor = first_page["next_cursor"].as_str().unwrap();
+    let second_page: serde_json::Value = app
+        .get_archive(&format!("?limit=2&cursor={}", cursor), None)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(first_page["total_issues"], 3);
+    let titles: Vec<_> = first_page["issues"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, vec!["Third", "Second"]);
+    assert_eq!(second_page["issues"][0]["title"], "First");
+    assert_eq!(
+        second_page["issues"][0]["html"],
+        "<p>Newsletter body as HTML</p>"
+    );
+    assert_eq!(
+        second_page["issues"][0]["text"],
+        "Newsletter body as plain text"
+    );
+    assert!(second_page["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn the_archive_can_still_be_paged_by_number() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    for title in ["First", "Second", "Third"] {
+        publish_issue(&app, &api_key, title).await;
+    }
+
+    // Act
+    let second_page: serde_json::Value = app
+        .get_archive("?per_page=2&page=2", None)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(second_page["page"], 2);
+    assert_eq!(second_page["per_page"], 2);
+    assert_eq!(second_page["total_issues"], 3);
+    assert_eq!(second_page["total_pages"], 2);
+    assert_eq!(second_page["issues"].as_array().unwrap().len(), 1);
+    assert_eq!(second_page["issues"][0]["title"], "First");
+}
+
+#[tokio::test]
+async fn invalid_pagination_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for query in [
+        "?limit=0",
+        "?limit=101",
+        "?limit=abc",
+        "?cursor=not-a-cursor",
+        "?page=0",
+        "?per_page=101",
+        "?page=2&limit=10",
+    ] {
+        // Act
+        let response = app.get_archive(query, None).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The archive did not reject {}",
+            query
+        );
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "validation_error");
+    }
+}
+
+#[tokio::test]
+async fn a_single_issue_can_be_retrieved() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let issue_id = publish_issue(&app, &api_key, "Newsletter title").await;
+
+    // Act
+    let response = app.get_archive(&format!("/{}", issue_id), None).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["newsletter_issue_id"], issue_id);
+    assert_eq!(issue["title"], "Newsletter title");
+    assert!(issue["published_at"].is_string());
+}
+
+#[tokio::test]
+async fn unknown_issues_return_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .get_archive(&format!("/{}", uuid::Uuid::new_v4()), None)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_matching_if_none_match_returns_a_304() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let issue_id = publish_issue(&app, &api_key, "Newsletter title").await;
+    let path = format!("/{}", issue_id);
+    let response = app.get_archive(&path, None).await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+
+    // Act
+    let response = app.get_archive(&path, Some(&etag)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 304);
+    assert_eq!(response.headers()["ETag"], etag.as_str());
+    assert!(response.text().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn the_etag_changes_when_the_archive_changes() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    publish_issue(&app, &api_key, "First").await;
+    let response = app.get_archive("", None).await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+    publish_issue(&app, &api_key, "Second").await;
+
+    // Act
+    let response = app.get_archive("", Some(&etag)).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(response.headers()["ETag"], etag.as_str());
+}
+
+#[tokio::test]
+async fn issue_content_is_stored_compressed() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    // Act
+    let issue_id = publish_issue(&app, &api_key, "Compressed").await;
+
+    // Assert
+    let saved =
+        sqlx::query!("SELECT text_content, html_content, html_content_zstd FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert!(saved.text_content.is_none());
+    assert!(saved.html_content.is_none());
+    assert!(saved.html_content_zstd.is_some());
+    let issue: serde_json::Value = app
+        .get_archive(&format!("/{}", issue_id), None)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["text"], "Newsletter body as plain text");
+    assert_eq!(issue["html"], "<p>Newsletter body as HTML</p>");
+}
+
+#[tokio::test]
+async fn issues_stored_before_compression_can_still_be_read() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+            (newsletter_issue_id, title, text_content, html_content, published_at)
+        VALUES ($1, 'Legacy', 'Plain text', '<p>HTML</p>', now())
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let issue: serde_json::Value = app
+        .get_archive(&format!("/{}", issue_id), None)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(issue["text"], "Plain text");
+    assert_eq!(issue["html"], "<p>HTML</p>");
+}
@@ -0,0 +1,325 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn get_job(app: &TestApp, api_key: &str, location: &str) -> serde_json::Value {
+    let job_path = location.trim_start_matches("/api/v1");
+    let response = app.api_get(job_path, api_key).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+async fn start_job(app: &TestApp, api_key: &str, path: &str, body: serde_json::Value) -> String {
+    let response = app.api_post(path, api_key, &body).await;
+    assert_eq!(response.status().as_u16(), 202);
+    let location = response.headers()["Location"].to_str().unwrap().to_owned();
+    let job: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(job["status"], "queued");
+    location
+}
+
+#[tokio::test]
+async fn bulk_import_runs_in_the_background() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    let location = start_job(
+        &app,
+        &api_key,
+        "/jobs/bulk_import",
+        serde_json::json!({
+            "subscribers": [
+                {"email": "ursula@example.com", "name": "Ursula", "status": "confirmed"},
+                {"email": "ged@example.com", "status": "pending_confirmation", "tags": ["vip"]},
+            ]
+        }),
+    )
+    .await;
+    let subscribers = sqlx::query!("SELECT email FROM subscriptions WHERE deleted_at IS NULL")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+
+    app.run_all_pending_jobs().await;
+
+    let job = get_job(&app, &api_key, &location).await;
+    assert_eq!(job["kind"], "bulk_import");
+    assert_eq!(job["status"], "succeeded");
+    assert_eq!(job["processed"], 2);
+    assert_eq!(job["total"], 2);
+    assert_eq!(job["result"]["imported_confirmed"], 1);
+    assert_eq!(job["result"]["imported_pending"], 1);
+    let subscribers = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 2);
+}
+
+#[tokio::test]
+async fn a_bulk_import_waits_for_the_running_one_of_its_tenant() {
+    // Arrange
+    let app = spawn_app_with(|c| c.concurrency_limits.imports = 1).await;
+    let api_key = app.create_api_key().await;
+    let body =
+        |email: &str| serde_json::json!({"subscribers": [{"email": email, "status": "confirmed"}]});
+    let running = start_job(
+        &app,
+        &api_key,
+        "/jobs/bulk_import",
+        body("ursula@example.com"),
+    )
+    .await;
+    let waiting = start_job(&app, &api_key, "/jobs/bulk_import", body("ged@example.com")).await;
+    let running_id: uuid::Uuid = running.rsplit('/').next().unwrap().parse().unwrap();
+    // As if another worker had picked it up.
+    sqlx::query!(
+        r#"
+        UPDATE jobs
+        SET status = 'running', started_at = now(), heartbeat_at = now(), attempts = 1
+        WHERE job_id = $1
+        "#,
+        running_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.run_all_pending_jobs().await;
+
+    // Assert
+    let job = get_job(&app, &api_key, &waiting).await;
+    assert_eq!(job["status"], "queued");
+    sqlx::query!(
+        "UPDATE jobs SET status = 'succeeded', finished_at = now() WHERE job_id = $1",
+        running_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.run_all_pending_jobs().await;
+    let job = get_job(&app, &api_key, &waiting).await;
+    assert_eq!(job["status"], "succeeded");
+}
+
+#[tokio::test]
+async fn a_job_whose_worker_stopped_responding_is_run_again() {
+    // Arrange
+    let app = spawn_app_with(|c| c.concurrency_limits.imports = 1).await;
+    let api_key = app.create_api_key().await;
+    let location = start_job(
+        &app,
+        &api_key,
+        "/jobs/bulk_import",
+        serde_json::json!({"subscribers": [{"email": "ursula@example.com", "status": "confirmed"}]}),
+    )
+    .await;
+    // As if its worker had crashed ten minutes ago.
+    sqlx::query!(
+        r#"
+        UPDATE jobs
+        SET
+            status = 'running',
+            started_at = now() - interval '1 hour',
+            heartbeat_at = now() - interval '10 minutes',
+            attempts = 1
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.run_all_pending_jobs().await;
+
+    // Assert
+    let job = get_job(&app, &api_key, &location).await;
+    assert_eq!(job["status"], "succeeded");
+    assert_eq!(job["result"]["imported_confirmed"], 1);
+}
+
+#[tokio::test]
+async fn a_job_whose_workers_keep_dying_fails() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let location = start_job(
+        &app,
+        &api_key,
+        "/jobs/bulk_import",
+        serde_json::json!({"subscribers": [{"email": "ursula@example.com", "status": "confirmed"}]}),
+    )
+    .await;
+    sqlx::query!(
+        r#"
+        UPDATE jobs
+        SET
+            status = 'running',
+            started_at = now() - interval '1 hour',
+            heartbeat_at = now() - interval '10 minutes',
+            attempts = 3
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.run_all_pending_jobs().await;
+
+    // Assert
+    let job = get_job(&app, &api_key, &location).await;
+    assert_eq!(job["status"], "failed");
+    assert_eq!(
+        job["error"],
+        "The worker running the job stopped responding."
+    );
+    let subscribers = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn bulk_delete_removes_subscribers_and_ignores_unknown_ids() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let import = start_job(
+        &app,
+        &api_key,
+        "/jobs/bulk_import",
+        serde_json::json!({
+            "subscribers": [{"email": "ursula@example.com", "status": "confirmed"}]
+        }),
+    )
+    .await;
+    app.run_all_pending_jobs().await;
+    assert_eq!(
+        get_job(&app, &api_key, &import).await["status"],
+        "succeeded"
+    );
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    let location = start_job(
+        &app,
+        &api_key,
+        "/jobs/bulk_delete",
+        serde_json::json!({
+            "subscriber_ids": [subscriber_id, uuid::Uuid::new_v4()]
+        }),
+    )
+    .await;
+    app.run_all_pending_jobs().await;
+
+    let job = get_job(&app, &api_key, &location).await;
+    assert_eq!(job["status"], "succeeded");
+    assert_eq!(job["result"]["deleted"], 1);
+    let subscribers = sqlx::query!("SELECT email FROM subscriptions WHERE deleted_at IS NULL")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn reenqueue_queues_the_issue_for_confirmed_subscribers_again() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    start_job(
+        &app,
+        &api_key,
+        "/jobs/bulk_import",
+        serde_json::json!({
+            "subscribers": [{"email": "ursula@example.com", "status": "confirmed"}]
+        }),
+    )
+    .await;
+    app.run_all_pending_jobs().await;
+    let response = app
+        .api_post(
+            "/newsletters",
+            &api_key,
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let location = start_job(
+        &app,
+        &api_key,
+        "/jobs/reenqueue",
+        serde_json::json!({"newsletter_issue_id": issue["newsletter_issue_id"]}),
+    )
+    .await;
+    app.run_all_pending_jobs().await;
+
+    let job = get_job(&app, &api_key, &location).await;
+    assert_eq!(job["status"], "succeeded");
+    assert_eq!(job["result"]["enqueued"], 1);
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that the issue was sent twice
+}
+
+#[tokio::test]
+async fn reenqueue_of_an_unknown_issue_is_a_404() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    let response = app
+        .api_post(
+            "/jobs/reenqueue",
+            &api_key,
+            &serde_json::json!({"newsletter_issue_id": uuid::Uuid::new_v4()}),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn empty_bulk_operations_are_rejected() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    for (path, body) in [
+        ("/jobs/bulk_import", serde_json::json!({"subscribers": []})),
+        (
+            "/jobs/bulk_delete",
+            serde_json::json!({"subscriber_ids": []}),
+        ),
+    ] {
+        let response = app.api_post(path, &api_key, &body).await;
+
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
+
+#[tokio::test]
+async fn unknown_jobs_are_a_404() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    let response = app
+        .api_get(&format!("/jobs/{}", uuid::Uuid::new_v4()), &api_key)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,130 @@
+use crate::helpers::{spawn_app, TestApp};
+use zero2prod::import::{import_subscribers, ImportedStatus, ImportedSubscriber};
+use zero2prod::tenancy::TenantId;
+
+async fn add_subscribers(app: &TestApp, emails: &[&str]) {
+    for email in emails {
+        let record = ImportedSubscriber {
+            email: email.to_string(),
+            name: None,
+            status: ImportedStatus::Confirmed,
+            tags: vec![],
+        };
+        import_subscribers(
+            &app.db_pool,
+            TenantId::DEFAULT,
+            vec![record],
+            false,
+            &app.subscriber_settings,
+            None,
+        )
+        .await
+        .unwrap();
+    }
+}
+
+async fn get_feed(app: &TestApp, api_key: &str, path: &str) -> serde_json::Value {
+    let response = app.api_get(path, api_key).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn recent_feeds_require_an_api_key() {
+    let app = spawn_app().await;
+
+    for path in ["/recent/subscribers", "/recent/issues"] {
+        let response = app.api_get(path, "not-a-real-key").await;
+
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn recent_subscribers_are_returned_newest_first_across_pages() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    add_subscribers(
+        &app,
+        &[
+            "first@example.com",
+            "second@example.com",
+            "third@example.com",
+        ],
+    )
+    .await;
+
+    let first_page = get_feed(&app, &api_key, "/recent/subscribers?limit=2").await;
+    let cursor = first_page["next_cursor"].as_str().unwrap();
+    let second_page = get_feed(
+        &app,
+        &api_key,
+        &format!("/recent/subscribers?limit=2&cursor={}", cursor),
+    )
+    .await;
+
+    let emails: Vec<_> = first_page["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .chain(second_page["items"].as_array().unwrap())
+        .map(|s| s["email"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        emails,
+        vec![
+            "third@example.com",
+            "second@example.com",
+            "first@example.com"
+        ]
+    );
+    assert!(second_page["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn recent_issues_are_returned_newest_first() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    for title in ["First", "Second"] {
+        let response = app
+            .api_post(
+                "/newsletters",
+                &api_key,
+                &serde_json::json!({
+                    "title": title,
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }),
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 202);
+    }
+
+    let feed = get_feed(&app, &api_key, "/recent/issues").await;
+
+    assert_eq!(feed["items"][0]["title"], "Second");
+    assert_eq!(feed["items"][1]["title"], "First");
+    assert!(feed["items"][0]["id"].is_string());
+    assert!(feed["next_cursor"].is_null());
+}
+
+#[tokio::test]
+async fn invalid_feed_parameters_are_rejected() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    for query in ["?cursor=garbage", "?limit=0", "?limit=101"] {
+        let response = app
+            .api_get(&format!("/recent/subscribers{}", query), &api_key)
+            .await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject {}",
+            query
+        );
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "validation_error");
+    }
+}
@@ -0,0 +1,789 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::concurrency_limits::HeavyRoute;
+use zero2prod::import::{import_subscribers, ImportedStatus, ImportedSubscriber};
+use zero2prod::tenancy::TenantId;
+
+async fn create_subscriber(app: &TestApp, api_key: &str) -> serde_json::Value {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .api_post(
+            "/subscribers",
+            api_key,
+            &serde_json::json!({
+                "name": "le guin",
+                "email": "ursula_le_guin@gmail.com"
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    response.json().await.unwrap()
+}
+
+fn assert_error_envelope(body: &serde_json::Value, code: &str) {
+    assert_eq!(body["error"]["code"], code);
+    assert!(body["error"]["message"].is_string());
+}
+
+#[tokio::test]
+async fn requests_without_an_api_key_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/subscribers", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_error_envelope(&response.json().await.unwrap(), "unauthorized");
+}
+
+#[tokio::test]
+async fn requests_with_an_unknown_api_key_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app.api_get("/subscribers", "not-a-real-key").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_error_envelope(&response.json().await.unwrap(), "unauthorized");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_created_listed_and_deleted() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    let created = create_subscriber(&app, &api_key).await;
+    assert_eq!(created["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(created["status"], "pending_confirmation");
+    let subscriber_path = format!("/subscribers/{}", created["id"].as_str().unwrap());
+
+    let list: serde_json::Value = app
+        .api_get("/subscribers", &api_key)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(list["subscribers"].as_array().unwrap().len(), 1);
+
+    let response = app.api_get(&subscriber_path, &api_key).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.api_delete(&subscriber_path, &api_key).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let response = app.api_get(&subscriber_path, &api_key).await;
+    assert_eq!(response.status().as_u16(), 404);
+    assert_error_envelope(&response.json().await.unwrap(), "not_found");
+}
+
+#[tokio::test]
+async fn sealed_subscribers_are_stored_under_a_pseudonym_and_read_back_decrypted() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.pii_encryption.enabled = true;
+        c.pii_encryption.key = secrecy::Secret::new(base64::encode([1; 32]));
+        c.pii_encryption.blind_index_key = secrecy::Secret::new(base64::encode([2; 32]));
+    })
+    .await;
+    let api_key = app.create_api_key().await;
+
+    // Act
+    let created = create_subscriber(&app, &api_key).await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT email, name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(!saved.email.contains("ursula"));
+    assert!(saved.email.ends_with("@gmail.com.encrypted.invalid"));
+    assert!(saved.name.is_none());
+    let subscriber_path = format!("/subscribers/{}", created["id"].as_str().unwrap());
+    let fetched: serde_json::Value = app
+        .api_get(&subscriber_path, &api_key)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(fetched["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(fetched["name"], "le guin");
+    // The address is still known, through its blind index.
+    let response = app
+        .api_post(
+            "/subscribers",
+            &api_key,
+            &serde_json::json!({
+                "name": "le guin",
+                "email": "Ursula_Le_Guin@gmail.com"
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn the_proof_of_consent_of_a_subscriber_can_be_exported() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "signup-browser")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request, 3, 1);
+    app.api_client
+        .get(confirmation_links.html)
+        .header("User-Agent", "confirmation-browser")
+        .send()
+        .await
+        .unwrap();
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    let response = app
+        .api_get(&format!("/subscribers/{}/consent", subscriber_id), &api_key)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], "ursula_le_guin@gmail.com");
+    let records = export["records"].as_array().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["action"], "signup");
+    assert_eq!(records[0]["ip_address"], "127.0.0.1");
+    assert_eq!(records[0]["user_agent"], "signup-browser");
+    assert_eq!(records[0]["form_hash"].as_str().unwrap().len(), 64);
+    assert_eq!(records[1]["action"], "confirmation");
+    assert_eq!(records[1]["user_agent"], "confirmation-browser");
+    assert!(records[1]["form_hash"].is_null());
+
+    let response = app
+        .api_get(
+            &format!("/subscribers/{}/consent", uuid::Uuid::new_v4()),
+            &api_key,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn deleted_subscribers_are_kept_but_hidden_and_can_sign_up_again() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let created = create_subscriber(&app, &api_key).await;
+    let subscriber_path = format!("/subscribers/{}", created["id"].as_str().unwrap());
+
+    let response = app.api_delete(&subscriber_path, &api_key).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let list: serde_json::Value = app
+        .api_get("/subscribers", &api_key)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(list["subscribers"].as_array().unwrap().is_empty());
+    let saved = sqlx::query!("SELECT deleted_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.deleted_at.is_some());
+
+    let recreated = create_subscriber(&app, &api_key).await;
+    assert_ne!(recreated["id"], created["id"]);
+    let list: serde_json::Value = app
+        .api_get("/subscribers", &api_key)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(list["subscribers"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn creating_a_subscriber_with_invalid_data_returns_a_400() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": "", "email": "ursula_le_guin@gmail.com"}),
+            "empty name",
+        ),
+        (
+            serde_json::json!({"name": "Ursula", "email": "definitely-not-an-email"}),
+            "invalid email",
+        ),
+        (serde_json::json!({"name": "Ursula"}), "missing email"),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.api_post("/subscribers", &api_key, &body).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload was {}.",
+            description
+        );
+        assert_error_envelope(&response.json().await.unwrap(), "validation_error");
+    }
+}
+
+#[tokio::test]
+async fn every_invalid_field_of_a_subscriber_is_reported() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    let response = app
+        .api_post(
+            "/subscribers",
+            &api_key,
+            &serde_json::json!({"name": "", "email": "definitely-not-an-email"}),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let message = body["error"]["message"].as_str().unwrap();
+    assert!(message.contains("is not a valid subscriber name."));
+    assert!(message.contains("definitely-not-an-email is not a valid subscriber email."));
+}
+
+#[tokio::test]
+async fn subscribers_must_have_accepted_the_policy_when_it_is_required() {
+    let app = spawn_app_with(|c| {
+        c.subscribers.consent_policy.required = true;
+        c.subscribers.consent_policy.version = "2022-05".into();
+    })
+    .await;
+    let api_key = app.create_api_key().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = |accepted_policy: Option<&str>| {
+        serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "accepted_policy": accepted_policy
+        })
+    };
+
+    let response = app.api_post("/subscribers", &api_key, &body(None)).await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_error_envelope(&response.json().await.unwrap(), "validation_error");
+
+    let response = app
+        .api_post("/subscribers", &api_key, &body(Some("2022-05")))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let created: serde_json::Value = response.json().await.unwrap();
+    let export: serde_json::Value = app
+        .api_get(
+            &format!("/subscribers/{}/consent", created["id"].as_str().unwrap()),
+            &api_key,
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(export["records"][0]["policy_version"], "2022-05");
+}
+
+#[tokio::test]
+async fn creating_an_existing_subscriber_returns_a_409() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    create_subscriber(&app, &api_key).await;
+
+    let response = app
+        .api_post(
+            "/subscribers",
+            &api_key,
+            &serde_json::json!({
+                "name": "le guin",
+                "email": "ursula_le_guin@gmail.com"
+            }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+    assert_error_envelope(&response.json().await.unwrap(), "conflict");
+}
+
+#[tokio::test]
+async fn published_issues_report_their_delivery_status() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    create_subscriber(&app, &api_key).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request, 3, 1);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app
+        .api_post(
+            "/newsletters",
+            &api_key,
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "delivering");
+    assert_eq!(issue["pending_deliveries"], 1);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let issue_path = format!(
+        "/newsletters/{}",
+        issue["newsletter_issue_id"].as_str().unwrap()
+    );
+    let issue: serde_json::Value = app
+        .api_get(&issue_path, &api_key)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["status"], "delivered");
+    assert_eq!(issue["pending_deliveries"], 0);
+}
+
+#[tokio::test]
+async fn getting_an_unknown_issue_returns_a_404() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    let response = app
+        .api_get(&format!("/newsletters/{}", uuid::Uuid::new_v4()), &api_key)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+    assert_error_envelope(&response.json().await.unwrap(), "not_found");
+}
+
+#[tokio::test]
+async fn malformed_path_parameters_are_reported_with_the_error_envelope() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    let response = app.api_get("/newsletters/not-a-uuid", &api_key).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_error_envelope(&response.json().await.unwrap(), "validation_error");
+}
+
+#[tokio::test]
+async fn the_subscriber_list_is_paginated_oldest_first() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    for email in [
+        "first@example.com",
+        "second@example.com",
+        "third@example.com",
+    ] {
+        let record = ImportedSubscriber {
+            email: email.to_string(),
+            name: None,
+            status: ImportedStatus::Confirmed,
+            tags: vec![],
+        };
+        import_subscribers(
+            &app.db_pool,
+            TenantId::DEFAULT,
+            vec![record],
+            false,
+            &app.subscriber_settings,
+            None,
+        )
+        .await
+        .unwrap();
+    }
+
+    let first_page: serde_json::Value = app
+        .api_get("/subscribers?limit=2", &api_key)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let cursor = first_page["next_cursor"].as_str().unwrap();
+    let second_page: serde_json::Value = app
+        .api_get(&format!("/subscribers?limit=2&cursor={}", cursor), &api_key)
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    let emails: Vec<_> = first_page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .chain(second_page["subscribers"].as_array().unwrap())
+        .map(|s| s["email"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        emails,
+        vec![
+            "first@example.com",
+            "second@example.com",
+            "third@example.com"
+        ]
+    );
+    assert!(second_page["next_cursor"].is_null());
+}
+
+async fn import_confirmed_subscriber(app: &TestApp, email: &str, tags: &[&str]) {
+    let record = ImportedSubscriber {
+        email: email.into(),
+        name: None,
+        status: ImportedStatus::Confirmed,
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+    };
+    import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        vec![record],
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+}
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+#[tokio::test]
+async fn publishing_is_idempotent_with_an_idempotency_key_header() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    import_confirmed_subscriber(&app, "ursula@example.com", &[]).await;
+    let publish = || {
+        app.api_client
+            .post(format!("{}/api/v1/newsletters", &app.address))
+            .bearer_auth(&api_key)
+            .header("Idempotency-Key", "spring-edition")
+            .json(&newsletter_body())
+            .send()
+    };
+
+    let first = publish().await.unwrap();
+    assert_eq!(first.status().as_u16(), 202);
+    let first: serde_json::Value = first.json().await.unwrap();
+    let second = publish().await.unwrap();
+    assert_eq!(second.status().as_u16(), 202);
+    let second: serde_json::Value = second.json().await.unwrap();
+
+    assert_eq!(first, second);
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 1);
+}
+
+#[tokio::test]
+async fn an_invalid_idempotency_key_header_returns_a_400() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/api/v1/newsletters", &app.address))
+        .bearer_auth(&api_key)
+        .header("Idempotency-Key", "k".repeat(50))
+        .json(&newsletter_body())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_error_envelope(&response.json().await.unwrap(), "validation_error");
+}
+
+#[tokio::test]
+async fn publishing_beyond_the_daily_sending_quota_is_rejected() {
+    let app = spawn_app_with(|c| c.newsletters.max_emails_per_day = 1).await;
+    let api_key = app.create_api_key().await;
+    import_confirmed_subscriber(&app, "ursula@example.com", &[]).await;
+    import_confirmed_subscriber(&app, "ged@example.com", &[]).await;
+
+    let response = app
+        .api_post("/newsletters", &api_key, &newsletter_body())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_error_envelope(&body, "too_many_requests");
+    assert!(body["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("only 1 more emails can be sent today"));
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
+
+#[tokio::test]
+async fn a_segmented_issue_only_goes_to_the_tagged_subscribers() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    import_confirmed_subscriber(&app, "ursula@example.com", &["rust"]).await;
+    import_confirmed_subscriber(&app, "ged@example.com", &["go"]).await;
+    let mut body = newsletter_body();
+    body["segment"] = "rust".into();
+
+    let response = app.api_post("/newsletters", &api_key, &body).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["segment"], "rust");
+    assert_eq!(issue["pending_deliveries"], 1);
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, "ursula@example.com");
+}
+
+#[tokio::test]
+async fn a_response_too_large_to_be_saved_rolls_the_publication_back() {
+    let app = spawn_app_with(|c| c.idempotency.max_response_size_bytes = 16).await;
+    let api_key = app.create_api_key().await;
+    import_confirmed_subscriber(&app, "ursula@example.com", &[]).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/api/v1/newsletters", &app.address))
+        .bearer_auth(&api_key)
+        .header("Idempotency-Key", "spring-edition")
+        .json(&newsletter_body())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 500);
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
+
+#[tokio::test]
+async fn a_simulated_issue_renders_samples_but_queues_nothing() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    import_confirmed_subscriber(&app, "ursula@example.com", &["rust"]).await;
+    import_confirmed_subscriber(&app, "ged@example.com", &["rust"]).await;
+    import_confirmed_subscriber(&app, "tenar@example.com", &["go"]).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let mut body = newsletter_body();
+    body["text"] = "This went to {{ email }}.".into();
+    body["segment"] = "rust".into();
+    body["samples"] = 1.into();
+
+    let response = app.api_post("/newsletters/simulate", &api_key, &body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let simulation: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(simulation["recipients"], 2);
+    let samples = simulation["samples"].as_array().unwrap();
+    assert_eq!(samples.len(), 1);
+    let recipient = samples[0]["recipient"].as_str().unwrap();
+    assert!(["ursula@example.com", "ged@example.com"].contains(&recipient));
+    let text_content = samples[0]["text_content"].as_str().unwrap();
+    assert!(text_content.contains(&format!("This went to {}.", recipient)));
+    assert!(text_content.contains("/unsubscribe?subscription_token=SAMPLE"));
+    assert!(text_content.contains("/issues/SAMPLE"));
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn simulating_an_issue_beyond_the_daily_sending_quota_is_a_429() {
+    // Arrange
+    let app = spawn_app_with(|c| c.newsletters.max_emails_per_day = 1).await;
+    let api_key = app.create_api_key().await;
+    import_confirmed_subscriber(&app, "ursula@example.com", &[]).await;
+    import_confirmed_subscriber(&app, "ged@example.com", &[]).await;
+
+    // Act
+    let response = app
+        .api_post("/newsletters/simulate", &api_key, &newsletter_body())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_error_envelope(&body, "too_many_requests");
+    assert!(body["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("only 1 more emails can be sent today"));
+}
+
+#[tokio::test]
+async fn simulations_render_a_bounded_number_of_samples() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let mut body = newsletter_body();
+    body["samples"] = 1000.into();
+
+    let response = app.api_post("/newsletters/simulate", &api_key, &body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_error_envelope(&response.json().await.unwrap(), "validation_error");
+}
+
+#[tokio::test]
+async fn subscribers_are_masked_for_keys_of_admins_without_pii_access() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let created = create_subscriber(&app, &api_key).await;
+    app.revoke_pii_access().await;
+
+    // Act
+    let list: serde_json::Value = app
+        .api_get("/subscribers", &api_key)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let subscriber: serde_json::Value = app
+        .api_get(
+            &format!("/subscribers/{}", created["id"].as_str().unwrap()),
+            &api_key,
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(list["subscribers"][0]["email"], "u***@gmail.com");
+    assert_eq!(list["subscribers"][0]["name"], "l***");
+    assert_eq!(subscriber["email"], "u***@gmail.com");
+}
+
+#[tokio::test]
+async fn publishing_over_the_cap_of_the_tenant_is_a_429() {
+    // Arrange
+    let app = spawn_app_with(|c| c.concurrency_limits.publishes = 1).await;
+    let api_key = app.create_api_key().await;
+    let permit = app
+        .concurrency_limits
+        .try_acquire(TenantId::DEFAULT, HeavyRoute::Publish)
+        .unwrap();
+
+    // Act
+    let response = app
+        .api_post("/newsletters", &api_key, &newsletter_body())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    assert_error_envelope(&response.json().await.unwrap(), "too_many_requests");
+    drop(permit);
+    let response = app
+        .api_post("/newsletters", &api_key, &newsletter_body())
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn exporting_over_the_cap_of_the_tenant_is_a_409() {
+    // Arrange
+    let app = spawn_app_with(|c| c.concurrency_limits.exports = 1).await;
+    let api_key = app.create_api_key().await;
+    let response = app
+        .api_post("/newsletters", &api_key, &newsletter_body())
+        .await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let report_path = format!(
+        "/newsletters/{}/report",
+        issue["newsletter_issue_id"].as_str().unwrap()
+    );
+    let other_tenant = TenantId::from(uuid::Uuid::new_v4());
+    let _other_permit = app
+        .concurrency_limits
+        .try_acquire(other_tenant, HeavyRoute::Export)
+        .unwrap();
+    let response = app.api_get(&report_path, &api_key).await;
+    assert_eq!(response.status().as_u16(), 200);
+    // The permit is held until the body has been streamed.
+    response.text().await.unwrap();
+    let _permit = app
+        .concurrency_limits
+        .try_acquire(TenantId::DEFAULT, HeavyRoute::Export)
+        .unwrap();
+
+    // Act
+    let response = app.api_get(&report_path, &api_key).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    assert_error_envelope(&response.json().await.unwrap(), "conflict");
+}
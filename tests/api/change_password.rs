@@ -46,12 +46,11 @@ async fn new_password_fields_must_match() {
         }))
         .await;
 
-    assert_is_redirect_to(&response, "/admin/password");
-
-    let html_page = app.get_change_password_html().await;
+    assert_eq!(response.status().as_u16(), 400);
+    let html_page = response.text().await.unwrap();
     assert!(html_page.contains(
-        "<p><i>You entered two different new passwords - \
-        the field values must match.</i></p>"
+        "<p class=\"field-error\">You entered two different new passwords - \
+        the field values must match.</p>"
     ));
 }
 
@@ -76,10 +75,9 @@ async fn current_password_must_be_valid() {
         }))
         .await;
 
-    assert_is_redirect_to(&response, "/admin/password");
-
-    let html_page = app.get_change_password_html().await;
-    assert!(html_page.contains("<p><i>The current password is incorrect.</i></p>"));
+    assert_eq!(response.status().as_u16(), 400);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<p class="field-error">The current password is incorrect.</p>"#));
 }
 
 #[tokio::test]
@@ -89,7 +87,6 @@ async fn new_password_must_be_greater_than_12_characters() {
     let short_password: String = Uuid::new_v4()
         .to_string()
         .graphemes(true)
-        .into_iter()
         .take(12)
         .collect();
 
@@ -107,10 +104,113 @@ async fn new_password_must_be_greater_than_12_characters() {
         }))
         .await;
 
+    assert_eq!(response.status().as_u16(), 400);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page
+        .contains(r#"<p class="field-error">Passwords must be longer than 12 characters.</p>"#));
+}
+
+#[tokio::test]
+async fn new_password_must_mix_character_classes() {
+    let app = spawn_app().await;
+    let weak_password = "onlylowercaseletters";
+    app.do_login().await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": weak_password,
+            "new_password_check": weak_password,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Passwords must mix at least three of"));
+}
+
+#[tokio::test]
+async fn new_password_must_differ_from_the_current_one() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &app.test_user.password,
+            "new_password_check": &app.test_user.password,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("The new password must be different from the current one."));
+}
+
+#[tokio::test]
+async fn every_invalid_field_is_reported_at_once() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": Uuid::new_v4().to_string(),
+            "new_password": "short",
+            "new_password_check": "different",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("The current password is incorrect."));
+    assert!(html_page.contains("Passwords must be longer than 12 characters."));
+    assert!(html_page.contains("You entered two different new passwords"));
+}
+
+#[tokio::test]
+async fn changing_passwords_logs_out_the_other_sessions() {
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Another browser logs in first
+    let other_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let response = other_client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Change Password
+    app.do_login().await;
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
     assert_is_redirect_to(&response, "/admin/password");
 
-    let html_page = app.get_change_password_html().await;
-    assert!(html_page.contains("<p><i>Passwords must be longer than 12 characters.</i></p>"));
+    // The session that changed the password is still logged in...
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // ...the other one is not
+    let response = other_client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
 }
 
 #[tokio::test]
@@ -0,0 +1,213 @@
+use crate::helpers::{spawn_app, TestApp};
+use secrecy::Secret;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::chat_notifications::post_chat_notifications;
+use zero2prod::configuration::{ChatNotificationSettings, ChatService};
+use zero2prod::tenancy::{create_tenant, NewTenant, TenantId};
+use zero2prod::testing::IssueFixture;
+
+fn settings(webhook: &MockServer, error_spike_threshold: u64) -> ChatNotificationSettings {
+    ChatNotificationSettings {
+        service: ChatService::Slack,
+        webhook_url: Secret::new(format!("{}/webhook", webhook.uri())),
+        error_spike_threshold,
+        error_spike_window_minutes: 15,
+    }
+}
+
+/// The messages posted to `webhook_path`.
+async fn posted_messages(webhook: &MockServer, webhook_path: &str) -> Vec<String> {
+    webhook
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == webhook_path)
+        .map(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            body["text"].as_str().unwrap().to_owned()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn published_and_sent_issues_are_posted_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let webhook = MockServer::start().await;
+    Mock::given(path("/webhook"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&webhook)
+        .await;
+    let settings = settings(&webhook, 20);
+    let notifier = settings.notifier().unwrap();
+    app.publish_issue().await;
+
+    // Act
+    let posted = post_chat_notifications(&app.db_pool, &notifier, &settings)
+        .await
+        .unwrap();
+    let posted_again = post_chat_notifications(&app.db_pool, &notifier, &settings)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(posted, 2);
+    assert_eq!(posted_again, 0);
+    assert_eq!(
+        posted_messages(&webhook, "/webhook").await,
+        [
+            "\"Newsletter title\" was published.",
+            "\"Newsletter title\" finished sending: 0 delivered (100.0%), 0 failed, 0 skipped."
+        ]
+    );
+}
+
+async fn create_tenant_with_webhook(
+    app: &TestApp,
+    name: &str,
+    webhook_url: Option<String>,
+) -> TenantId {
+    let tenant_id = create_tenant(
+        &app.db_pool,
+        &NewTenant {
+            name: name.into(),
+            hostname: format!("{}.example.com", name),
+            sender_email: None,
+            public_base_url: None,
+            chat_webhook_url: None,
+        },
+    )
+    .await
+    .unwrap();
+    // Stored by hand: the mock webhook is not served over https.
+    sqlx::query!(
+        "UPDATE tenants SET chat_webhook_url = $1 WHERE tenant_id = $2",
+        webhook_url,
+        *tenant_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    tenant_id
+}
+
+#[tokio::test]
+async fn each_tenant_is_posted_to_its_own_channel() {
+    // Arrange
+    let app = spawn_app().await;
+    let webhook = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&webhook)
+        .await;
+    let settings = settings(&webhook, 20);
+    let notifier = settings.notifier().unwrap();
+    let with_channel = create_tenant_with_webhook(
+        &app,
+        "earthsea",
+        Some(format!("{}/earthsea", webhook.uri())),
+    )
+    .await;
+    let without_channel = create_tenant_with_webhook(&app, "hain", None).await;
+    IssueFixture::default()
+        .with_title("Earthsea news")
+        .for_tenant(with_channel)
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    IssueFixture::default()
+        .with_title("Hain news")
+        .for_tenant(without_channel)
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    app.publish_issue().await;
+
+    // Act
+    post_chat_notifications(&app.db_pool, &notifier, &settings)
+        .await
+        .unwrap();
+
+    // Assert
+    let default_messages = posted_messages(&webhook, "/webhook").await;
+    assert_eq!(default_messages.len(), 2);
+    assert!(default_messages
+        .iter()
+        .all(|m| m.contains("Newsletter title")));
+    let tenant_messages = posted_messages(&webhook, "/earthsea").await;
+    assert_eq!(tenant_messages.len(), 2);
+    assert!(tenant_messages.iter().all(|m| m.contains("Earthsea news")));
+    assert_eq!(webhook.received_requests().await.unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn events_are_posted_again_if_the_webhook_failed() {
+    // Arrange
+    let app = spawn_app().await;
+    let webhook = MockServer::start().await;
+    let settings = settings(&webhook, 20);
+    let notifier = settings.notifier().unwrap();
+    app.publish_issue().await;
+    Mock::given(path("/webhook"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&webhook)
+        .await;
+    Mock::given(path("/webhook"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&webhook)
+        .await;
+
+    // Act - the webhook fails once
+    let outcome = post_chat_notifications(&app.db_pool, &notifier, &settings).await;
+    assert!(outcome.is_err());
+    let posted = post_chat_notifications(&app.db_pool, &notifier, &settings)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(posted, 2);
+}
+
+#[tokio::test]
+async fn a_spike_of_failed_deliveries_raises_a_single_alert() {
+    // Arrange
+    let app = spawn_app().await;
+    app.import_confirmed_subscribers(&["ursula@example.com", "ged@example.com"])
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    let webhook = MockServer::start().await;
+    Mock::given(path("/webhook"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&webhook)
+        .await;
+    let settings = settings(&webhook, 2);
+    let notifier = settings.notifier().unwrap();
+    app.publish_issue().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act - running twice within the window
+    post_chat_notifications(&app.db_pool, &notifier, &settings)
+        .await
+        .unwrap();
+    post_chat_notifications(&app.db_pool, &notifier, &settings)
+        .await
+        .unwrap();
+
+    // Assert
+    let alerts: Vec<_> = posted_messages(&webhook, "/webhook")
+        .await
+        .into_iter()
+        .filter(|message| message.contains("deliveries failed in the last 15 minutes"))
+        .collect();
+    assert_eq!(alerts.len(), 1);
+    assert!(alerts[0].starts_with("2 deliveries failed"));
+}
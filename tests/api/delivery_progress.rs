@@ -0,0 +1,94 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use zero2prod::testing::{run_worker_once, FakeEmailSender, IssueFixture, SubscriberFixture};
+
+async fn get_progress(app: &TestApp, newsletter_issue_id: Uuid) -> reqwest::Response {
+    app.api_client
+        .get(format!(
+            "{}/admin/newsletters/{}/progress",
+            &app.address, newsletter_issue_id
+        ))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// The next chunk of the stream that is not a comment.
+async fn next_event(response: &mut reqwest::Response) -> String {
+    loop {
+        let chunk = response.chunk().await.unwrap().expect("The stream ended");
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        if !chunk.starts_with(':') {
+            return chunk;
+        }
+    }
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_follow_the_delivery_progress() {
+    let app = spawn_app().await;
+
+    let response = get_progress(&app, Uuid::new_v4()).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_progress_of_an_unknown_issue_is_a_404() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = get_progress(&app, Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_stream_ends_once_the_issue_is_delivered() {
+    let app = spawn_app().await;
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    app.do_login().await;
+
+    let response = get_progress(&app, issue_id).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "text/event-stream");
+    let body = response.text().await.unwrap();
+    assert_eq!(
+        body,
+        "event: progress\ndata: {\"delivered\":0,\"failed\":0,\"remaining\":0}\n\n\
+        event: done\ndata: {\"delivered\":0,\"failed\":0,\"remaining\":0}\n\n"
+    );
+}
+
+#[tokio::test]
+async fn progress_is_streamed_as_the_worker_delivers_the_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    app.do_login().await;
+    let mut response = get_progress(&app, issue_id).await;
+
+    // Act - Part 1 - Nothing delivered yet
+    let event = next_event(&mut response).await;
+    assert_eq!(
+        event,
+        "event: progress\ndata: {\"delivered\":0,\"failed\":0,\"remaining\":1}\n\n"
+    );
+
+    // Act - Part 2 - The worker goes through the queue
+    run_worker_once(&app.db_pool, &FakeEmailSender::default(), &app.address)
+        .await
+        .unwrap();
+    let event = next_event(&mut response).await;
+
+    // Assert
+    assert!(event
+        .starts_with("event: progress\ndata: {\"delivered\":1,\"failed\":0,\"remaining\":0}\n\n"));
+    assert!(event.contains("event: done\n"));
+    assert!(response.chunk().await.unwrap().is_none());
+}
@@ -0,0 +1,232 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::import::{import_subscribers, ImportedStatus, ImportedSubscriber};
+use zero2prod::tenancy::TenantId;
+
+/// Deliver an issue to two confirmed subscribers, one of whom opens it twice.
+async fn deliver_an_issue(app: &TestApp, api_key: &str) -> String {
+    let records = ["ursula@example.com", "ged@example.com"]
+        .into_iter()
+        .map(|email| ImportedSubscriber {
+            email: email.into(),
+            name: None,
+            status: ImportedStatus::Confirmed,
+            tags: vec![],
+        })
+        .collect();
+    import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        records,
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+    let response = app
+        .api_post(
+            "/newsletters",
+            api_key,
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let issue_id = issue["newsletter_issue_id"].as_str().unwrap().to_owned();
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    for _ in 0..2 {
+        let open = serde_json::json!({
+            "RecordType": "Open",
+            "Recipient": "ursula@example.com",
+            "Tag": issue_id,
+        });
+        let response = app
+            .post_signed_webhook(&Uuid::new_v4().to_string(), &open)
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    issue_id
+}
+
+#[tokio::test]
+async fn the_csv_report_lists_every_recipient_with_their_opens() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let issue_id = deliver_an_issue(&app, &api_key).await;
+    app.do_login().await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/newsletters/{}/report.csv",
+            &app.address, issue_id
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "subscriber_email,outcome,error,provider,attempted_at,opens,clicks"
+    );
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with("ged@example.com,delivered,,primary,"));
+    assert!(lines[1].ends_with(",0,0"));
+    assert!(lines[2].starts_with("ursula@example.com,delivered,,"));
+    assert!(lines[2].ends_with(",2,0"));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_a_report() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/newsletters/{}/report.csv",
+            &app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_report_of_an_unknown_issue_is_a_404() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    app.do_login().await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/newsletters/{}/report.csv",
+            &app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = app
+        .api_get(&format!("/newsletters/{}/report", Uuid::new_v4()), &api_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_json_report_is_available_through_the_api() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let issue_id = deliver_an_issue(&app, &api_key).await;
+
+    let response = app
+        .api_get(&format!("/newsletters/{}/report", issue_id), &api_key)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["newsletter_issue_id"], issue_id);
+    let recipients = report["recipients"].as_array().unwrap();
+    assert_eq!(recipients.len(), 2);
+    assert_eq!(recipients[1]["subscriber_email"], "ursula@example.com");
+    assert_eq!(recipients[1]["outcome"], "delivered");
+    assert_eq!(recipients[1]["opens"], 2);
+    assert!(recipients[1]["attempted_at"].is_string());
+}
+
+#[tokio::test]
+async fn a_report_larger_than_a_chunk_is_streamed_in_full() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let records = (0..2000)
+        .map(|i| ImportedSubscriber {
+            email: format!("subscriber-{:04}@example.com", i),
+            name: None,
+            status: ImportedStatus::Confirmed,
+            tags: vec![],
+        })
+        .collect();
+    import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        records,
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+    let response = app
+        .api_post(
+            "/newsletters",
+            &api_key,
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let issue_id = issue["newsletter_issue_id"].as_str().unwrap().to_owned();
+    app.do_login().await;
+
+    // Act
+    let json = app
+        .api_get(&format!("/newsletters/{}/report", issue_id), &api_key)
+        .await;
+    let json_status = json.status().as_u16();
+    let json_content_type = json.headers()["Content-Type"].clone();
+    // Each export holds a permit until its body has been streamed.
+    let report: serde_json::Value = json.json().await.unwrap();
+    let csv = app
+        .api_client
+        .get(format!(
+            "{}/admin/newsletters/{}/report.csv",
+            &app.address, issue_id
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(json_status, 200);
+    assert_eq!(json_content_type, "application/json");
+    let recipients = report["recipients"].as_array().unwrap();
+    assert_eq!(recipients.len(), 2000);
+    assert_eq!(
+        recipients[1999]["subscriber_email"],
+        "subscriber-1999@example.com"
+    );
+    assert_eq!(recipients[1999]["outcome"], "pending");
+    assert_eq!(csv.status().as_u16(), 200);
+    let csv = csv.text().await.unwrap();
+    assert_eq!(csv.lines().count(), 2001);
+    assert!(csv.ends_with("subscriber-1999@example.com,pending,,,,0,0\n"));
+}
@@ -0,0 +1,224 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::email_outbox::{deliver_outbox_emails, PostgresEmailOutbox};
+use zero2prod::services::{EmailOutbox, OutboxEmail};
+use zero2prod::tenancy::TenantId;
+
+async fn outbox_recipients(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT recipient FROM email_outbox ORDER BY enqueued_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.recipient)
+        .collect()
+}
+
+async fn enqueue_outbox_email(app: &TestApp, recipient: &str) {
+    PostgresEmailOutbox::new(&app.db_pool, TenantId::DEFAULT)
+        .enqueue(OutboxEmail {
+            sender: None,
+            recipient: recipient.into(),
+            subject: "Confirm your subscription".into(),
+            html_content: "<p>Welcome!</p>".into(),
+            text_content: "Welcome!".into(),
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn signups_are_accepted_when_the_email_provider_fails() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("may take a while to arrive"));
+    assert_eq!(outbox_recipients(&app).await, ["ursula_le_guin@gmail.com"]);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn the_provider_is_not_called_while_the_circuit_breaker_is_open() {
+    let app = spawn_app_with(|c| c.email_client.circuit_breaker_failure_threshold = 1).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for email in ["ursula%40example.com", "ged%40example.com"] {
+        let response = app
+            .post_subscriptions(format!("name=le%20guin&email={}", email))
+            .await;
+        assert_eq!(response.status().as_u16(), 202);
+    }
+
+    assert_eq!(
+        outbox_recipients(&app).await,
+        ["ursula@example.com", "ged@example.com"]
+    );
+}
+
+#[tokio::test]
+async fn browsers_are_told_the_confirmation_email_may_be_delayed() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscribe_form(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/subscribe/pending?delayed=true");
+    let html = app.get_subscribe_pending_html("?delayed=true").await;
+    assert!(html.contains("may take a while to arrive"));
+}
+
+#[tokio::test]
+async fn emails_of_the_outbox_are_sent_once_the_provider_is_back() {
+    let app = spawn_app().await;
+    let outage = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    drop(outage);
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let delivered = deliver_outbox_emails(&app.db_pool, &app.email_client)
+        .await
+        .unwrap();
+
+    assert_eq!(delivered, 1);
+    assert!(outbox_recipients(&app).await.is_empty());
+    let email_request = app.email_server.received_requests().await.unwrap().pop();
+    let links = app.get_confirmation_links(&email_request.unwrap(), 3, 1);
+    assert_eq!(links.html.path(), "/subscriptions/confirm");
+}
+
+#[tokio::test]
+async fn emails_stay_in_the_outbox_while_the_provider_fails() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    let outcome = deliver_outbox_emails(&app.db_pool, &app.email_client).await;
+
+    assert!(outcome.is_err());
+    assert_eq!(outbox_recipients(&app).await, ["ursula_le_guin@gmail.com"]);
+}
+
+#[tokio::test]
+async fn an_email_which_keeps_failing_does_not_block_the_others() {
+    // Arrange
+    let app = spawn_app().await;
+    enqueue_outbox_email(&app, "ursula@example.com").await;
+    enqueue_outbox_email(&app, "ged@example.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_string_contains("ursula@example.com"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_string_contains("ged@example.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let first_run = deliver_outbox_emails(&app.db_pool, &app.email_client).await;
+    let second_run = deliver_outbox_emails(&app.db_pool, &app.email_client).await;
+
+    // Assert
+    assert!(first_run.is_err());
+    assert_eq!(second_run.unwrap(), 1);
+    let failed = sqlx::query!(
+        "SELECT recipient, attempts, next_attempt_at > now() AS \"later!\" FROM email_outbox"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(failed.recipient, "ursula@example.com");
+    assert_eq!(failed.attempts, 1);
+    assert!(failed.later);
+}
+
+#[tokio::test]
+async fn an_email_is_dead_lettered_after_too_many_attempts() {
+    // Arrange
+    let app = spawn_app().await;
+    enqueue_outbox_email(&app, "ursula@example.com").await;
+    sqlx::query!("UPDATE email_outbox SET attempts = 7")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let outcome = deliver_outbox_emails(&app.db_pool, &app.email_client).await;
+    sqlx::query!("UPDATE email_outbox SET next_attempt_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let next_run = deliver_outbox_emails(&app.db_pool, &app.email_client).await;
+
+    // Assert
+    assert!(outcome.is_err());
+    assert_eq!(next_run.unwrap(), 0);
+    let dead_lettered =
+        sqlx::query!("SELECT attempts, last_error, dead_lettered_at FROM email_outbox")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(dead_lettered.attempts, 8);
+    assert!(dead_lettered.last_error.is_some());
+    assert!(dead_lettered.dead_lettered_at.is_some());
+}
@@ -0,0 +1,152 @@
+use crate::helpers::{spawn_app, spawn_app_with, EMBED_ORIGIN};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::SubscriptionStatus;
+
+#[tokio::test]
+async fn the_embed_script_points_to_this_instance() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_embed_script().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/javascript; charset=utf-8"
+    );
+    let script = response.text().await.unwrap();
+    assert!(script.contains("http://127.0.0.1/embed/subscriptions"));
+}
+
+#[tokio::test]
+async fn the_embed_script_only_asks_for_a_name_if_required() {
+    let app = spawn_app().await;
+    let script = app.get_embed_script().await.text().await.unwrap();
+    assert!(script.contains(r#"name="name""#));
+
+    let app = spawn_app_with(|c| c.subscribers.require_name = false).await;
+    let script = app.get_embed_script().await.text().await.unwrap();
+    assert!(!script.contains(r#"name="name""#));
+    assert!(!script.contains("form.elements.name"));
+}
+
+#[tokio::test]
+async fn preflight_requests_from_allowed_origins_are_accepted() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.preflight_embed_subscriptions(EMBED_ORIGIN).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Access-Control-Allow-Origin"],
+        EMBED_ORIGIN
+    );
+}
+
+#[tokio::test]
+async fn requests_from_other_origins_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let preflight = app
+        .preflight_embed_subscriptions("https://evil.example.com")
+        .await;
+    let response = app
+        .post_embed_subscriptions(
+            "https://evil.example.com",
+            &serde_json::json!({"name": "le guin", "email": "ursula_le_guin@gmail.com"}),
+        )
+        .await;
+
+    // Assert
+    assert!(preflight
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+    assert_eq!(response.status().as_u16(), 400);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn embedded_subscriptions_are_pending_and_get_a_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_embed_subscriptions(
+            EMBED_ORIGIN,
+            &serde_json::json!({"name": "le guin", "email": "ursula_le_guin@gmail.com"}),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Access-Control-Allow-Origin"],
+        EMBED_ORIGIN
+    );
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+    let saved =
+        sqlx::query!(r#"SELECT email, status AS "status: SubscriptionStatus" FROM subscriptions"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn invalid_embedded_subscriptions_are_rejected_with_a_json_error() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": "", "email": "ursula_le_guin@gmail.com"}),
+            "empty name",
+        ),
+        (
+            serde_json::json!({"name": "Ursula", "email": "definitely-not-an-email"}),
+            "invalid email",
+        ),
+        (serde_json::json!({"name": "Ursula"}), "missing email"),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_embed_subscriptions(EMBED_ORIGIN, &body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            description
+        );
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "validation_error");
+    }
+}
@@ -0,0 +1,103 @@
+use crate::helpers::{spawn_app, TestApp};
+
+const BROWSER_ACCEPT: &str = "text/html,application/xhtml+xml;q=0.9,*/*;q=0.8";
+
+async fn browse(app: &TestApp, path: &str, accept_language: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", app.address, path))
+        .header("Accept", BROWSER_ACCEPT)
+        .header("Accept-Language", accept_language)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn assert_html(response: &reqwest::Response, status: u16) {
+    assert_eq!(response.status().as_u16(), status);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+}
+
+#[tokio::test]
+async fn browsers_get_a_branded_page_for_an_unknown_path() {
+    let app = spawn_app().await;
+
+    let response = browse(&app, "/no/such/page", "en").await;
+
+    assert_html(&response, 404);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Page not found"));
+    assert!(html_page.contains(r#"<link rel="stylesheet" href="/static/theme.css">"#));
+}
+
+#[tokio::test]
+async fn error_pages_are_localized() {
+    let app = spawn_app().await;
+
+    let response = browse(&app, "/no/such/page", "fr-FR,fr;q=0.9").await;
+
+    assert_html(&response, 404);
+    assert!(response.text().await.unwrap().contains("Page introuvable"));
+}
+
+#[tokio::test]
+async fn browsers_get_the_message_of_a_client_error() {
+    let app = spawn_app().await;
+
+    let response = browse(
+        &app,
+        "/subscriptions/confirm?subscription_token=not-a-token",
+        "en",
+    )
+    .await;
+
+    assert_html(&response, 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("This request could not be served"));
+}
+
+#[tokio::test]
+async fn browsers_get_a_forbidden_page_for_an_unsigned_link() {
+    let app = spawn_app().await;
+
+    let response = browse(
+        &app,
+        "/subscriptions/stay?subscription_token=aaaaaaaaaaaaaaaaaaaaaaaaa",
+        "en",
+    )
+    .await;
+
+    assert_html(&response, 403);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("You cannot see this page"));
+}
+
+#[tokio::test]
+async fn json_clients_get_problem_details_for_an_unknown_path() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/no/such/page", app.address))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "not_found");
+    assert_eq!(problem["status"], 404);
+}
@@ -0,0 +1,257 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{EventPublisherKind, EventSettings};
+use zero2prod::events::{try_publish_events, EventPublisher, ExecutionOutcome};
+use zero2prod::import::{import_subscribers, ImportedStatus, ImportedSubscriber};
+use zero2prod::tenancy::TenantId;
+
+async fn recorded_events(app: &TestApp) -> Vec<serde_json::Value> {
+    sqlx::query!("SELECT payload FROM events ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.payload)
+        .collect()
+}
+
+async fn publish_issue(app: &TestApp) -> String {
+    let api_key = app.create_api_key().await;
+    let response = app
+        .api_post(
+            "/newsletters",
+            &api_key,
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    issue["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn signing_up_records_a_single_event() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act - signing up twice
+    for _ in 0..2 {
+        app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+            .await;
+    }
+
+    // Assert
+    let events = recorded_events(&app).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["type"], "subscription_requested");
+    assert_eq!(events[0]["email"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn confirming_a_subscriber_records_a_single_event() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request, 3, 1);
+
+    // Act - following the link twice, the second time finding it used
+    for expected_status in [200, 410] {
+        let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+        assert_eq!(response.status().as_u16(), expected_status);
+    }
+
+    // Assert
+    let events = recorded_events(&app).await;
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1]["type"], "subscriber_confirmed");
+    assert_eq!(events[1]["email"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn delivering_an_issue_records_its_publication_and_completion() {
+    // Arrange
+    let app = spawn_app().await;
+    let records = ["ursula@example.com", "ged@example.com"]
+        .into_iter()
+        .map(|email| ImportedSubscriber {
+            email: email.into(),
+            name: None,
+            status: ImportedStatus::Confirmed,
+            tags: vec![],
+        })
+        .collect();
+    import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        records,
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let issue_id = publish_issue(&app).await;
+    let events_before_delivery = recorded_events(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(events_before_delivery.len(), 1);
+    let events = recorded_events(&app).await;
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["type"], "issue_published");
+    assert_eq!(events[0]["newsletter_issue_id"], issue_id);
+    assert_eq!(events[0]["title"], "Newsletter title");
+    assert_eq!(events[1]["type"], "delivery_completed");
+    assert_eq!(events[1]["newsletter_issue_id"], issue_id);
+    assert_eq!(events[1]["delivered"], 2);
+    assert_eq!(events[1]["failed"], 0);
+}
+
+#[tokio::test]
+async fn a_failed_delivery_records_an_event() {
+    // Arrange
+    let app = spawn_app().await;
+    let records = vec![ImportedSubscriber {
+        email: "ursula@example.com".into(),
+        name: None,
+        status: ImportedStatus::Confirmed,
+        tags: vec![],
+    }];
+    import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        records,
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let issue_id = publish_issue(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let events = recorded_events(&app).await;
+    let types: Vec<_> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(
+        types,
+        ["issue_published", "delivery_failed", "delivery_completed"]
+    );
+    assert_eq!(events[1]["newsletter_issue_id"], issue_id);
+    assert_eq!(events[1]["subscriber_email"], "ursula@example.com");
+    assert_eq!(events[1]["outcome"], "failed");
+}
+
+#[tokio::test]
+async fn an_issue_without_subscribers_is_delivered_right_away() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    publish_issue(&app).await;
+
+    // Assert
+    let types: Vec<_> = recorded_events(&app)
+        .await
+        .into_iter()
+        .map(|e| e["type"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(types, ["issue_published", "delivery_completed"]);
+}
+
+#[tokio::test]
+async fn recorded_events_are_published_to_kafka_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let kafka_proxy = MockServer::start().await;
+    let publisher = EventPublisher::from_settings(&EventSettings {
+        publisher: EventPublisherKind::Kafka,
+        url: kafka_proxy.uri(),
+        topic: "newsletter".into(),
+    })
+    .unwrap()
+    .unwrap();
+    Mock::given(path("/topics/newsletter"))
+        .and(method("POST"))
+        .and(header("Content-Type", "application/vnd.kafka.json.v2+json"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&kafka_proxy)
+        .await;
+    publish_issue(&app).await;
+
+    // Act
+    let first = try_publish_events(&app.db_pool, &publisher).await.unwrap();
+    let second = try_publish_events(&app.db_pool, &publisher).await.unwrap();
+
+    // Assert
+    assert!(matches!(first, ExecutionOutcome::EventsPublished(2)));
+    assert!(matches!(second, ExecutionOutcome::EmptyQueue));
+    let request = &kafka_proxy.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let records = body["records"].as_array().unwrap();
+    assert_eq!(records[0]["value"]["type"], "issue_published");
+    assert_eq!(records[0]["key"], records[0]["value"]["event_id"]);
+}
+
+#[tokio::test]
+async fn events_stay_unpublished_if_kafka_rejects_them() {
+    // Arrange
+    let app = spawn_app().await;
+    let kafka_proxy = MockServer::start().await;
+    let publisher = EventPublisher::from_settings(&EventSettings {
+        publisher: EventPublisherKind::Kafka,
+        url: kafka_proxy.uri(),
+        topic: "newsletter".into(),
+    })
+    .unwrap()
+    .unwrap();
+    Mock::given(path("/topics/newsletter"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&kafka_proxy)
+        .await;
+    publish_issue(&app).await;
+
+    // Act
+    let outcome = try_publish_events(&app.db_pool, &publisher).await;
+
+    // Assert
+    assert!(outcome.is_err());
+    let unpublished =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM events WHERE published_at IS NULL"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(unpublished.count, 2);
+}
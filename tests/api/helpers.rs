@@ -1,26 +1,42 @@
+use actix_web::web;
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 use once_cell::sync::Lazy;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
 use wiremock::MockServer;
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::authentication::{set_operator, set_pii_access};
+use zero2prod::concurrency_limits::ConcurrencyLimits;
+use zero2prod::configuration::{
+    get_configuration, DatabaseSettings, LogFormat, LoggingSettings, Settings, SubscriberSettings,
+};
 use zero2prod::email_client::EmailClient;
-use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::import::{import_subscribers, ImportedStatus, ImportedSubscriber};
+use zero2prod::jobs::{self, try_execute_job, JobSettings};
+use zero2prod::signing::UrlSigner;
 use zero2prod::startup::{get_connection_pool, Application};
-use zero2prod::telemetry::{get_subscriber, init_subscriber};
-
-static TRACING: Lazy<()> = Lazy::new(|| {
-    let default_filter_level = "info".to_string();
+use zero2prod::telemetry::{get_subscriber, init_subscriber, LogHandle};
+use zero2prod::tenancy::TenantId;
+use zero2prod::testing::run_worker_once;
+use zero2prod::webhooks::sign_webhook;
+
+static TRACING: Lazy<LogHandle> = Lazy::new(|| {
+    let settings = LoggingSettings {
+        level: "info".to_string(),
+        format: LogFormat::Json,
+    };
     let subscriber_name = "test".to_string();
 
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let (subscriber, log_handle) = get_subscriber(subscriber_name, &settings, std::io::stdout);
         init_subscriber(subscriber);
+        log_handle
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let (subscriber, log_handle) = get_subscriber(subscriber_name, &settings, std::io::sink);
         init_subscriber(subscriber);
-    };
+        log_handle
+    }
 });
 
 pub struct ConfirmationLinks {
@@ -36,6 +52,13 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub webhook_signing_secret: Secret<String>,
+    /// Signs links as the application does.
+    pub url_signer: UrlSigner,
+    pub subscriber_settings: SubscriberSettings,
+    pub job_settings: JobSettings,
+    /// The caps the application serves heavy requests under, shared with it.
+    pub concurrency_limits: web::Data<ConcurrencyLimits>,
 }
 
 pub struct TestUser {
@@ -78,7 +101,7 @@ impl TestUser {
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -86,21 +109,262 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn get_unsubscribe(&self, subscription_token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/unsubscribe?subscription_token={}",
+                &self.address, subscription_token
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Through a signed link, as the unsubscribe page gives.
+    pub async fn get_preferences(&self, subscription_token: &str) -> reqwest::Response {
+        let path_and_query = self.url_signer.sign(
+            &format!("/preferences?subscription_token={}", subscription_token),
+            std::time::Duration::from_secs(60),
+        );
+        self.api_client
+            .get(format!("{}{}", &self.address, path_and_query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_preferences<Body>(&self, form: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/preferences", &self.address))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unsubscribe<Body>(&self, form: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/unsubscribe", &self.address))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_churn(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/churn", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_search(&self, q: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/search", &self.address))
+            .query(&[("q", q)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribe_form_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/subscribe", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// `query` is appended to the path as is, e.g. `?delayed=true`.
+    pub async fn get_subscribe_pending_html(&self, query: &str) -> String {
+        self.api_client
+            .get(format!("{}/subscribe/pending{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Submit the hosted subscribe form, as a browser would.
+    pub async fn post_subscribe_form<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Accept", "text/html,application/xhtml+xml;q=0.9,*/*;q=0.8")
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_embed_script(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/embed/subscribe.js", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_embed_subscriptions<Body>(
+        &self,
+        origin: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/embed/subscriptions", &self.address))
+            .header("Origin", origin)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn preflight_embed_subscriptions(&self, origin: &str) -> reqwest::Response {
+        self.api_client
+            .request(
+                reqwest::Method::OPTIONS,
+                format!("{}/embed/subscriptions", &self.address),
+            )
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "content-type")
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Send a webhook signed with the shared secret, as the email provider would.
+    pub async fn post_signed_webhook(
+        &self,
+        event_id: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        let body = serde_json::to_vec(body).unwrap();
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let signature = sign_webhook(&self.webhook_signing_secret, event_id, &timestamp, &body);
+        self.post_webhook(event_id, &timestamp, &signature, body)
+            .await
+    }
+
+    pub async fn post_webhook(
+        &self,
+        event_id: &str,
+        timestamp: &str,
+        signature: &str,
+        body: Vec<u8>,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/email_provider", &self.address))
+            .header("Content-Type", "application/json")
+            .header("X-Webhook-Id", event_id)
+            .header("X-Webhook-Timestamp", timestamp)
+            .header("X-Webhook-Signature", signature)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn post_newsletters<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
             .form(&body)
             .send()
             .await
             .expect("Failed to execute request")
     }
 
+    pub async fn post_autosave<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/autosave",
+                &self.address, draft_id
+            ))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_cancel_newsletter(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/cancel",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_newsletter_issue(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_retry_deliveries(
+        &self,
+        newsletter_issue_id: Uuid,
+        subscriber_emails: &[&str],
+    ) -> reqwest::Response {
+        let form: Vec<_> = subscriber_emails
+            .iter()
+            .map(|email| ("subscriber_email", *email))
+            .collect();
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/retry",
+                &self.address, newsletter_issue_id
+            ))
+            .form(&form)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_resume_deliveries(&self, newsletter_issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/resume",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn get_newsletters(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -110,9 +374,20 @@ impl TestApp {
         self.get_newsletters().await.text().await.unwrap()
     }
 
+    pub async fn get_newsletter_calendar(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/calendar{}",
+                &self.address, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -123,7 +398,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(&body)
             .send()
             .await
@@ -132,7 +407,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -142,7 +417,7 @@ impl TestApp {
     }
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -154,7 +429,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -168,13 +443,326 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_logging(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/logging", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_logging_html(&self) -> String {
+        self.get_logging().await.text().await.unwrap()
+    }
+
+    pub async fn post_logging<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/logging", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_notifications(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/notifications", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_notifications_html(&self) -> String {
+        self.get_notifications().await.text().await.unwrap()
+    }
+
+    pub async fn post_notifications(&self, fields: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/notifications", &self.address))
+            .form(fields)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_tools(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/tools", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_tools_html(&self) -> String {
+        self.get_tools().await.text().await.unwrap()
+    }
+
+    pub async fn post_tools<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/tools", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_read_only(&self, enabled: bool) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/read_only", &self.address))
+            .form(&[("enabled", enabled.to_string())])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_backups(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/backups", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_backups_html(&self) -> String {
+        self.get_backups().await.text().await.unwrap()
+    }
+
+    pub async fn post_backups(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/backups", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_redirects(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/redirects", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_redirects_html(&self) -> String {
+        self.get_redirects().await.text().await.unwrap()
+    }
+
+    pub async fn post_redirect(&self, from_path: &str, to_location: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/redirects", &self.address))
+            .form(&[("from_path", from_path), ("to_location", to_location)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_delete_redirect(&self, from_path: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/redirects/delete", &self.address))
+            .form(&[("from_path", from_path)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_waitlist_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/waitlist", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Promote the entry `email` joined the waitlist with.
+    pub async fn post_promote_waitlist_entry(&self, email: &str) -> reqwest::Response {
+        let entry = sqlx::query!("SELECT entry_id FROM waitlist WHERE email = $1", email)
+            .fetch_one(&self.db_pool)
+            .await
+            .expect("Failed to find the waitlist entry.");
+        self.api_client
+            .post(format!("{}/admin/waitlist/promote", &self.address))
+            .form(&[("entry_id", entry.entry_id.to_string())])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_setup_bundle(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/bundle", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_setup_bundle(&self, bundle: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/bundle", &self.address))
+            .form(&[("bundle", bundle)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_deliverability(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/deliverability", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_diagnostics(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/diagnostics", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_keys_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api_keys", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_api_keys<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/api_keys", &self.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_api_key<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/api_keys/revoke", &self.address))
             .form(&body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    /// Create an API key for the test user, bypassing the admin UI.
+    pub async fn create_api_key(&self) -> String {
+        zero2prod::authentication::create_api_key(self.test_user.user_id, "test", &self.db_pool)
+            .await
+            .expect("Failed to create an API key.")
+            .expose_secret()
+            .to_owned()
+    }
+
+    /// `GET` a public archive route, without an API key.
+    pub async fn get_archive(&self, path: &str, if_none_match: Option<&str>) -> reqwest::Response {
+        let mut request = self
+            .api_client
+            .get(format!("{}/api/v1/issues{}", &self.address, path));
+        if let Some(etag) = if_none_match {
+            request = request.header("If-None-Match", etag);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn api_get(&self, path: &str, api_key: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(api_key)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn api_post<Body>(&self, path: &str, api_key: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(api_key)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn api_delete(&self, path: &str, api_key: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(api_key)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Publish an issue to the confirmed subscribers through the API.
+    pub async fn publish_issue(&self) {
+        let api_key = self.create_api_key().await;
+        let response = self
+            .api_post(
+                "/newsletters",
+                &api_key,
+                &serde_json::json!({
+                    "title": "Newsletter title",
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }),
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 202);
+    }
+
+    /// Import confirmed subscribers of the default tenant, bypassing the confirmation
+    /// email.
+    pub async fn import_confirmed_subscribers(&self, emails: &[&str]) {
+        let records = emails
+            .iter()
+            .map(|email| ImportedSubscriber {
+                email: email.to_string(),
+                name: None,
+                status: ImportedStatus::Confirmed,
+                tags: vec![],
+            })
+            .collect();
+        import_subscribers(
+            &self.db_pool,
+            TenantId::DEFAULT,
+            records,
+            false,
+            &self.subscriber_settings,
+            None,
+        )
+        .await
+        .expect("Failed to import the subscribers.");
+    }
+
     pub async fn do_login(&self) {
         let login_body = serde_json::json!({
             "username": &self.test_user.username,
@@ -185,6 +773,30 @@ impl TestApp {
         assert_is_redirect_to(&response, "/admin/dashboard");
     }
 
+    /// The tools which work on the whole deployment are reserved to the operators.
+    pub async fn make_operator(&self) {
+        set_operator(
+            &self.db_pool,
+            TenantId::DEFAULT,
+            &self.test_user.username,
+            true,
+        )
+        .await
+        .expect("Failed to make the test user an operator.");
+    }
+
+    /// Have the test user see the personal data of subscribers masked.
+    pub async fn revoke_pii_access(&self) {
+        set_pii_access(
+            &self.db_pool,
+            TenantId::DEFAULT,
+            &self.test_user.username,
+            false,
+        )
+        .await
+        .expect("Failed to revoke the PII access of the test user.");
+    }
+
     pub fn get_confirmation_links(
         &self,
         email_request: &wiremock::Request,
@@ -206,16 +818,16 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap(), html_links);
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap(), text_links);
+        let html = get_link(body["HtmlBody"].as_str().unwrap(), html_links);
+        let plain_text = get_link(body["TextBody"].as_str().unwrap(), text_links);
 
         ConfirmationLinks { html, plain_text }
     }
 
-    pub async fn dispatch_all_pending_emails(&self) {
+    pub async fn run_all_pending_jobs(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
+            if let jobs::ExecutionOutcome::EmptyQueue =
+                try_execute_job(&self.db_pool, &self.job_settings)
                     .await
                     .unwrap()
             {
@@ -223,36 +835,63 @@ impl TestApp {
             }
         }
     }
+
+    pub async fn dispatch_all_pending_emails(&self) {
+        run_worker_once(&self.db_pool, &self.email_client, &self.address)
+            .await
+            .unwrap();
+    }
+}
+
+/// The only website allowed to use the embedded subscribe form in tests.
+pub const EMBED_ORIGIN: &str = "https://blog.example.com";
+
+pub fn log_handle() -> LogHandle {
+    Lazy::force(&TRACING).clone()
+}
+
+pub fn test_configuration(email_server: &MockServer) -> Settings {
+    let mut c = get_configuration().expect("Failed to read configuration.");
+    c.database.database_name = Uuid::new_v4().to_string();
+    c.application.port = 0;
+    c.email_client.base_url = email_server.uri();
+    c.application.embed_allowed_origins = vec![EMBED_ORIGIN.into()];
+    // Deliver right away, unless a test is about cancelling an issue.
+    c.newsletters.undo_window_seconds = 0;
+    // Tests running side by side can be slow to get a connection: only the tests about
+    // load shedding turn it on.
+    c.load_shedding.enabled = false;
+    c
 }
 
 pub async fn spawn_app() -> TestApp {
-    Lazy::force(&TRACING);
+    spawn_app_with(|_| {}).await
+}
 
+/// Spawn the application after tweaking the test configuration.
+pub async fn spawn_app_with(customise: impl FnOnce(&mut Settings)) -> TestApp {
     let email_server = MockServer::start().await;
 
-    let configuration = {
-        let mut c = get_configuration().expect("Failed to read configuration.");
-        c.database.database_name = Uuid::new_v4().to_string();
-        c.application.port = 0;
-        c.email_client.base_url = email_server.uri();
-        c
-    };
+    let mut configuration = test_configuration(&email_server);
+    customise(&mut configuration);
 
     configure_database(&configuration.database).await;
 
-    let application = Application::build(configuration.clone())
+    let application = Application::build(configuration.clone(), log_handle())
         .await
         .expect("Failed to build application");
 
     let application_port = application.port();
     let address = format!("http://127.0.0.1:{}", application_port);
-    let _ = tokio::spawn(application.run_until_stopped());
+    let concurrency_limits = application.concurrency_limits();
+    drop(tokio::spawn(application.run_until_stopped()));
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
         .cookie_store(true)
         .build()
         .unwrap();
 
+    let job_settings = JobSettings::from(&configuration);
     let test_app = TestApp {
         address,
         port: application_port,
@@ -261,13 +900,18 @@ pub async fn spawn_app() -> TestApp {
         test_user: TestUser::generate(),
         api_client: client,
         email_client: configuration.email_client.client(),
+        webhook_signing_secret: configuration.webhooks.signing_secret,
+        url_signer: UrlSigner::new(&configuration.signing),
+        job_settings,
+        subscriber_settings: configuration.subscribers,
+        concurrency_limits,
     };
 
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
 }
 
-async fn configure_database(config: &DatabaseSettings) -> PgPool {
+pub async fn configure_database(config: &DatabaseSettings) -> PgPool {
     let mut connection = PgConnection::connect_with(&config.without_db())
         .await
         .expect("Failed to connect to Postgres");
@@ -0,0 +1,312 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+use wiremock::matchers::{header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::EmailVerificationMethod;
+use zero2prod::domain::SubscriptionStatus;
+use zero2prod::import::{import_subscribers, ImportedStatus, ImportedSubscriber};
+use zero2prod::tenancy::TenantId;
+use zero2prod::testing::{run_worker_once, FakeEmailSender, IssueFixture, SubscriberFixture};
+
+fn subscriber(email: &str, status: ImportedStatus) -> ImportedSubscriber {
+    ImportedSubscriber {
+        email: email.into(),
+        name: Some("Le Guin".into()),
+        status,
+        tags: vec![],
+    }
+}
+
+#[tokio::test]
+async fn import_stores_confirmed_and_pending_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let records = vec![
+        subscriber("ursula@example.com", ImportedStatus::Confirmed),
+        subscriber("ged@example.com", ImportedStatus::PendingConfirmation),
+    ];
+
+    // Act
+    let report = import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        records,
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(report.imported_confirmed, 1);
+    assert_eq!(report.imported_pending, 1);
+    let saved = sqlx::query!(
+        r#"SELECT email, status AS "status: SubscriptionStatus" FROM subscriptions ORDER BY email"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].email, "ged@example.com");
+    assert_eq!(saved[0].status, SubscriptionStatus::PendingConfirmation);
+    assert_eq!(saved[1].status, SubscriptionStatus::Confirmed);
+}
+
+#[tokio::test]
+async fn import_skips_duplicates_excluded_and_existing_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        vec![subscriber("ursula@example.com", ImportedStatus::Confirmed)],
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+    let records = vec![
+        subscriber("ursula@example.com", ImportedStatus::Confirmed),
+        subscriber("ged@example.com", ImportedStatus::Confirmed),
+        subscriber("GED@example.com", ImportedStatus::Confirmed),
+        subscriber(
+            "tenar@example.com",
+            ImportedStatus::Excluded("unsubscribed".into()),
+        ),
+        subscriber("not-an-email", ImportedStatus::Confirmed),
+    ];
+
+    // Act
+    let report = import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        records,
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(report.imported_confirmed, 1);
+    assert_eq!(report.already_subscribed, 1);
+    assert_eq!(report.duplicates_in_export, 1);
+    assert_eq!(report.excluded.get("unsubscribed"), Some(&1));
+    assert_eq!(report.invalid.len(), 1);
+}
+
+#[tokio::test]
+async fn subscribers_are_recognised_whatever_the_case_of_their_address() {
+    // Arrange
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_email("Ursula@example.com")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let report = import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        vec![subscriber("ursula@example.com", ImportedStatus::Confirmed)],
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(report.imported_confirmed, 0);
+    assert_eq!(report.already_subscribed, 1);
+    let saved = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 1);
+}
+
+#[tokio::test]
+async fn dry_run_does_not_persist_anything() {
+    // Arrange
+    let app = spawn_app().await;
+    let records = vec![subscriber("ursula@example.com", ImportedStatus::Confirmed)];
+
+    // Act
+    let report = import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        records,
+        true,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(report.imported_confirmed, 1);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn imported_tags_are_stored() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut record = subscriber("ursula@example.com", ImportedStatus::Confirmed);
+    record.tags = vec!["vip".into(), "early adopter".into()];
+
+    // Act
+    import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        vec![record],
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    let tags = sqlx::query!("SELECT tag FROM subscriber_tags ORDER BY tag")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let tags: Vec<_> = tags.into_iter().map(|r| r.tag).collect();
+    assert_eq!(tags, vec!["early adopter", "vip"]);
+}
+
+#[tokio::test]
+async fn imported_addresses_found_undeliverable_are_never_sent_issues() {
+    // Arrange
+    let verification_server = MockServer::start().await;
+    let verification_url = format!("{}/verify", verification_server.uri());
+    let app = spawn_app_with(|c| {
+        let verification = &mut c.subscribers.import_verification;
+        verification.method = EmailVerificationMethod::Api;
+        verification.api_url = verification_url;
+        verification.api_key = secrecy::Secret::new("verification-key".into());
+    })
+    .await;
+    for (email, result) in [
+        ("ursula@example.com", "deliverable"),
+        ("ged@example.com", "undeliverable"),
+    ] {
+        Mock::given(method("GET"))
+            .and(path("/verify"))
+            .and(query_param("email", email))
+            .and(header("Authorization", "Bearer verification-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "result": result
+            })))
+            .expect(1)
+            .mount(&verification_server)
+            .await;
+    }
+    let records = vec![
+        subscriber("ursula@example.com", ImportedStatus::Confirmed),
+        subscriber("ged@example.com", ImportedStatus::Confirmed),
+    ];
+
+    // Act
+    let report = import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        records,
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+    IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let sender = FakeEmailSender::default();
+    run_worker_once(&app.db_pool, &sender, &app.address)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report.imported_confirmed, 2);
+    assert_eq!(report.verified.get("deliverable"), Some(&1));
+    assert_eq!(report.verified.get("undeliverable"), Some(&1));
+    let saved = sqlx::query!("SELECT email, verification FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved[0].verification.as_deref(), Some("undeliverable"));
+    assert_eq!(saved[1].verification.as_deref(), Some("deliverable"));
+    let recipients: Vec<_> = sender.sent().iter().map(|e| e.recipient.clone()).collect();
+    assert_eq!(recipients, vec!["ursula@example.com"]);
+}
+
+#[tokio::test]
+async fn dry_runs_and_known_addresses_are_not_verified() {
+    // Arrange
+    let verification_server = MockServer::start().await;
+    let verification_url = format!("{}/verify", verification_server.uri());
+    let app = spawn_app_with(|c| {
+        let verification = &mut c.subscribers.import_verification;
+        verification.method = EmailVerificationMethod::Api;
+        verification.api_url = verification_url;
+        verification.api_key = secrecy::Secret::new("verification-key".into());
+    })
+    .await;
+    Mock::given(method("GET"))
+        .and(path("/verify"))
+        .and(query_param("email", "ged@example.com"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "result": "deliverable"
+        })))
+        .expect(1)
+        .mount(&verification_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/verify"))
+        .and(query_param("email", "ursula@example.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&verification_server)
+        .await;
+
+    // Act
+    import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        vec![subscriber("ursula@example.com", ImportedStatus::Confirmed)],
+        true,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+    SubscriberFixture::confirmed()
+        .with_email("ursula@example.com")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    let report = import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        vec![
+            subscriber("ursula@example.com", ImportedStatus::Confirmed),
+            subscriber("ged@example.com", ImportedStatus::Confirmed),
+        ],
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(report.already_subscribed, 1);
+    assert_eq!(report.verified.get("deliverable"), Some(&1));
+}
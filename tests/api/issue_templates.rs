@@ -0,0 +1,222 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::testing::SubscriberFixture;
+
+async fn post_template(app: &TestApp) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/admin/newsletters/templates", app.address))
+        .form(&serde_json::json!({
+            "name": "Monthly digest",
+            "title": "{{ section: month }} digest",
+            "html": "<p>Hi {{ name | there }}</p><p>{{ section: intro }}</p>",
+            "text": "Hi {{ name | there }}\n\n{{ section: intro }}",
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_templates_html(app: &TestApp) -> String {
+    app.api_client
+        .get(format!("{}/admin/newsletters/templates", app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+fn issue_body(text: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "May digest",
+        "text": text,
+        "html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "confirmed": true,
+    })
+}
+
+#[tokio::test]
+async fn templates_list_the_fields_to_fill_in() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = post_template(&app).await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters/templates");
+    let html_page = app
+        .api_client
+        .get(format!("{}/admin/newsletters/templates", app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Added the template &quot;Monthly digest&quot;."));
+    assert!(html_page.contains("<td>month, intro</td>"));
+}
+
+#[tokio::test]
+async fn a_draft_can_start_from_a_template() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    post_template(&app).await;
+    let template_id = sqlx::query!("SELECT template_id FROM issue_templates")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .template_id;
+
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/newsletters/templates/{}/draft",
+            app.address, template_id
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(location.starts_with("/admin/newsletters?draft_id="));
+    let html_page = app
+        .api_client
+        .get(format!("{}{}", app.address, location))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("{{ section: month }} digest"));
+}
+
+#[tokio::test]
+async fn an_issue_with_sections_left_cannot_be_published() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app
+        .post_newsletters(&issue_body("Hi {{ name }}\n\n{{ section: intro }}"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Replace {{ section: intro }} before publishing"));
+}
+
+#[tokio::test]
+async fn merge_fields_are_filled_in_for_each_recipient() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_name("Ged")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.do_login().await;
+
+    let response = app.post_newsletters(&issue_body("Hi {{ name }}")).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.unwrap().body).unwrap();
+    assert!(body["TextBody"].as_str().unwrap().contains("Hi Ged"));
+}
+
+#[tokio::test]
+async fn names_need_a_fallback_if_a_recipient_has_none() {
+    let app = spawn_app().await;
+    let subscriber = SubscriberFixture::confirmed()
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "UPDATE subscriptions SET name = NULL WHERE id = $1",
+        subscriber.id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.do_login().await;
+
+    let response = app.post_newsletters(&issue_body("Hi {{ name }}")).await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("1 of the recipients have no name"));
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(issues, 0);
+}
+
+#[tokio::test]
+async fn the_templates_of_another_tenant_are_not_listed_nor_drafted_from() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_tenant = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO tenants (tenant_id, name) VALUES ($1, 'Other')",
+        other_tenant
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let template_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_templates (
+            template_id, tenant_id, name, title, text_content, html_content, created_at
+        )
+        VALUES ($1, $2, 'Monthly digest', 'Their digest', '', '', now())
+        "#,
+        template_id,
+        other_tenant
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.do_login().await;
+
+    // Act
+    let added = post_template(&app).await;
+    let added_page = get_templates_html(&app).await;
+    let drafted = app
+        .api_client
+        .post(format!(
+            "{}/admin/newsletters/templates/{}/draft",
+            app.address, template_id
+        ))
+        .send()
+        .await
+        .unwrap();
+    let drafted_page = get_templates_html(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&added, "/admin/newsletters/templates");
+    assert!(added_page.contains("Added the template &quot;Monthly digest&quot;."));
+    assert!(!added_page.contains("Their digest"));
+    assert_is_redirect_to(&drafted, "/admin/newsletters/templates");
+    assert!(drafted_page.contains("The template does not exist or has been removed."));
+}
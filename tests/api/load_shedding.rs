@@ -0,0 +1,64 @@
+use crate::helpers::spawn_app_with;
+use std::time::Duration;
+
+#[tokio::test]
+async fn the_archive_is_turned_away_while_the_database_is_saturated() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.load_shedding.enabled = true;
+        c.database.max_connections = 1;
+    })
+    .await;
+    // The only connection of the application waits on a table we keep locked.
+    let mut lock = app.db_pool.begin().await.unwrap();
+    sqlx::query!("LOCK TABLE newsletter_issues IN ACCESS EXCLUSIVE MODE")
+        .execute(&mut lock)
+        .await
+        .unwrap();
+    let blocked = tokio::spawn(
+        app.api_client
+            .get(format!("{}/issues", &app.address))
+            .send(),
+    );
+    // The pool of the test has a single connection too: the transaction holding the lock
+    // is the one watching for the request to block on it.
+    for _ in 0..100 {
+        let waiting = sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM pg_stat_activity
+            WHERE datname = current_database() AND wait_event_type = 'Lock'
+            "#
+        )
+        .fetch_one(&mut lock)
+        .await
+        .unwrap();
+        if waiting.count > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/sitemap.xml", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let health = app
+        .api_client
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    assert_eq!(response.headers()["Retry-After"], "30");
+    assert!(response.text().await.unwrap().contains("very busy"));
+    // Only the non-essential pages are turned away.
+    assert_eq!(health.status().as_u16(), 200);
+    lock.rollback().await.unwrap();
+    assert_eq!(blocked.await.unwrap().unwrap().status().as_u16(), 200);
+}
@@ -1,8 +1,51 @@
+mod admin_activity;
+mod admin_api_keys;
+mod admin_backups;
+mod admin_bundle;
 mod admin_dashboard;
+mod admin_deliverability;
+mod admin_diagnostics;
+mod admin_logging;
+mod admin_notifications;
+mod admin_preview;
+mod admin_queries;
+mod admin_search;
+mod admin_tools;
+mod api_issues;
+mod api_jobs;
+mod api_recent;
+mod api_v1;
 mod change_password;
+mod chat_notifications;
+mod delivery_progress;
+mod delivery_report;
+mod email_outbox;
+mod embed;
+mod error_pages;
+mod events;
 mod health_check;
 mod helpers;
+mod import;
+mod issue_templates;
+mod load_shedding;
 mod login;
+mod newsletter_drafts;
+mod newsletter_issue;
 mod newsletters;
+mod preferences;
+mod problem_details;
+mod public_archive;
+mod re_engagement;
+mod read_only;
+mod redirects;
+mod retention;
+mod scheduler;
+mod seed;
+mod startup_checks;
 mod subscriptions;
 mod subscriptions_confirm;
+mod tenancy;
+mod unsubscribe;
+mod waitlist;
+mod webhooks;
+mod weekly_report;
@@ -0,0 +1,625 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn get(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", app.address, path))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn post(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}{}", app.address, path))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// A draft saved twice, the text changing in between.
+async fn draft_saved_twice(app: &TestApp) -> Uuid {
+    let draft_id = Uuid::new_v4();
+    app.post_autosave(draft_id, &draft_body()).await;
+    let mut body = draft_body();
+    body["text"] = "Almost done".into();
+    app.post_autosave(draft_id, &body).await;
+    draft_id
+}
+
+/// A draft of another admin, whose last heartbeat was `heartbeat_age_seconds` ago.
+async fn draft_of_another_user(app: &TestApp, heartbeat_age_seconds: f64) -> Uuid {
+    let other_user_id = Uuid::new_v4();
+    let draft_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO users (user_id, username, password_hash) VALUES ($1, 'ged', 'not-a-hash')",
+        other_user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (
+            draft_id, user_id, title, text_content, html_content, saved_at, locked_by,
+            lock_heartbeat_at
+        )
+        VALUES ($1, $2, 'Theirs', '', '', now(), $2, now() - make_interval(secs => $3))
+        "#,
+        draft_id,
+        other_user_id,
+        heartbeat_age_seconds
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    draft_id
+}
+
+/// A draft of the admin of another tenant, saved once.
+async fn draft_of_another_tenant(app: &TestApp) -> Uuid {
+    let tenant_id = Uuid::new_v4();
+    let user_id = Uuid::new_v4();
+    let draft_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO tenants (tenant_id, name) VALUES ($1, 'Other')",
+        tenant_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, tenant_id)
+        VALUES ($1, 'ged', 'not-a-hash', $2)
+        "#,
+        user_id,
+        tenant_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (
+            draft_id, tenant_id, user_id, title, text_content, html_content, saved_at,
+            locked_by, lock_heartbeat_at
+        )
+        VALUES ($1, $2, $3, 'Theirs', '', '', now(), $3, now() - interval '1 hour')
+        "#,
+        draft_id,
+        tenant_id,
+        user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_draft_revisions (
+            draft_id, tenant_id, revision, title, text_content, html_content, saved_at
+        )
+        VALUES ($1, $2, 1, 'Theirs', '', '', now())
+        "#,
+        draft_id,
+        tenant_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    draft_id
+}
+
+async fn draft_title(app: &TestApp, draft_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT title FROM newsletter_drafts WHERE draft_id = $1",
+        draft_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .title
+}
+
+async fn revision_numbers(app: &TestApp, draft_id: Uuid) -> Vec<i32> {
+    sqlx::query!(
+        "SELECT revision FROM newsletter_draft_revisions WHERE draft_id = $1 ORDER BY revision",
+        draft_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.revision)
+    .collect()
+}
+
+fn draft_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Half-written issue",
+        "html": "<p>Work in progress</p>",
+        "text": "Work in progress",
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_autosave_a_draft() {
+    let app = spawn_app().await;
+
+    let response = app.post_autosave(Uuid::new_v4(), &draft_body()).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_autosaved_draft_is_restored_when_reopening_the_form() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let draft_id = Uuid::new_v4();
+
+    let response = app.post_autosave(draft_id, &draft_body()).await;
+    assert_eq!(response.status().as_u16(), 204);
+    // Later saves overwrite the content.
+    let mut body = draft_body();
+    body["text"] = "Almost done".into();
+    app.post_autosave(draft_id, &body).await;
+
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("Restored the draft autosaved at"));
+    assert!(html_page.contains(r#"value="Half-written issue""#));
+    assert!(html_page.contains(">Almost done</textarea>"));
+    assert!(html_page.contains(&format!(r#"name="draft_id" value="{}""#, draft_id)));
+}
+
+#[tokio::test]
+async fn publishing_a_draft_again_after_editing_it_is_not_a_retry() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let draft_id = Uuid::new_v4();
+    app.post_autosave(draft_id, &draft_body()).await;
+    let mut body = draft_body();
+    body["draft_id"] = draft_id.to_string().into();
+    body["idempotency_key"] = idempotency_key(&app.get_newsletters_html().await).into();
+    body["confirmed"] = true.into();
+    app.post_newsletters(&body).await;
+
+    // The page opened again to edit the draft further.
+    let html_page = app.get_newsletters_html().await;
+    body["title"] = "Second edition".into();
+    body["idempotency_key"] = idempotency_key(&html_page).into();
+    let response = app.post_newsletters(&body).await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert!(!html_page.contains(&format!(r#"name="idempotency_key" value="{}""#, draft_id)));
+    let titles: Vec<_> =
+        sqlx::query!("SELECT title FROM newsletter_issues ORDER BY published_at, title")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.title)
+            .collect();
+    assert_eq!(titles, ["Half-written issue", "Second edition"]);
+}
+
+/// The idempotency key the compose form was rendered with.
+fn idempotency_key(html_page: &str) -> String {
+    let (_, rest) = html_page
+        .split_once(r#"name="idempotency_key" value=""#)
+        .unwrap();
+    rest[..36].to_owned()
+}
+
+#[tokio::test]
+async fn a_published_draft_is_discarded() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let draft_id = Uuid::new_v4();
+    app.post_autosave(draft_id, &draft_body()).await;
+
+    let mut body = draft_body();
+    body["draft_id"] = draft_id.to_string().into();
+    body["idempotency_key"] = Uuid::new_v4().to_string().into();
+    body["confirmed"] = true.into();
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_newsletters_html().await;
+    assert!(!html_page.contains("Restored the draft"));
+    assert!(!html_page.contains(&draft_id.to_string()));
+}
+
+#[tokio::test]
+async fn a_draft_being_edited_by_another_user_cannot_be_overwritten() {
+    let app = spawn_app().await;
+    let draft_id = draft_of_another_user(&app, 0.0).await;
+    app.do_login().await;
+
+    let response = app.post_autosave(draft_id, &draft_body()).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "This draft is being edited by ged."
+    );
+    assert_eq!(draft_title(&app, draft_id).await, "Theirs");
+}
+
+#[tokio::test]
+async fn the_form_shows_who_is_editing_the_draft() {
+    let app = spawn_app().await;
+    let draft_id = draft_of_another_user(&app, 0.0).await;
+    app.do_login().await;
+
+    let html_page = get(&app, &format!("/admin/newsletters?draft_id={}", draft_id))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("This draft is being edited by ged."));
+
+    let heartbeat = post(&app, &format!("/admin/newsletters/{}/heartbeat", draft_id)).await;
+    assert_eq!(heartbeat.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn other_drafts_in_progress_are_listed() {
+    let app = spawn_app().await;
+    let draft_id = draft_of_another_user(&app, 0.0).await;
+    app.do_login().await;
+
+    let html_page = app.get_newsletters_html().await;
+
+    assert!(html_page.contains(&format!("/admin/newsletters?draft_id={}", draft_id)));
+    assert!(html_page.contains("being edited by ged"));
+}
+
+#[tokio::test]
+async fn the_drafts_of_another_tenant_cannot_be_opened_or_overwritten() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = draft_of_another_tenant(&app).await;
+    app.do_login().await;
+
+    // Act
+    let html_page = get(&app, &format!("/admin/newsletters?draft_id={}", draft_id))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let revisions = get(&app, &format!("/admin/newsletters/{}/revisions", draft_id)).await;
+    let restore = post(
+        &app,
+        &format!("/admin/newsletters/{}/revisions/1/restore", draft_id),
+    )
+    .await;
+    let autosave = app.post_autosave(draft_id, &draft_body()).await;
+
+    // Assert
+    assert!(!html_page.contains("Theirs"));
+    assert!(!html_page.contains(&draft_id.to_string()));
+    assert_is_redirect_to(&revisions, "/admin/newsletters");
+    assert_is_redirect_to(&restore, "/admin/newsletters");
+    assert_eq!(autosave.status().as_u16(), 403);
+    assert_eq!(draft_title(&app, draft_id).await, "Theirs");
+}
+
+#[tokio::test]
+async fn a_stale_lock_is_taken_over() {
+    let app = spawn_app().await;
+    let draft_id = draft_of_another_user(&app, 600.0).await;
+    app.do_login().await;
+
+    let heartbeat = post(&app, &format!("/admin/newsletters/{}/heartbeat", draft_id)).await;
+    assert_eq!(heartbeat.status().as_u16(), 204);
+    let response = app.post_autosave(draft_id, &draft_body()).await;
+
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(draft_title(&app, draft_id).await, "Half-written issue");
+}
+
+#[tokio::test]
+async fn a_draft_being_edited_by_another_user_cannot_be_published() {
+    let app = spawn_app().await;
+    let draft_id = draft_of_another_user(&app, 0.0).await;
+    app.do_login().await;
+
+    let mut body = draft_body();
+    body["draft_id"] = draft_id.to_string().into();
+    body["idempotency_key"] = Uuid::new_v4().to_string().into();
+    body["confirmed"] = true.into();
+    let response = app.post_newsletters(&body).await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("This draft is being edited by ged."));
+    assert_eq!(draft_title(&app, draft_id).await, "Theirs");
+}
+
+#[tokio::test]
+async fn every_save_is_kept_as_a_revision() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let draft_id = draft_saved_twice(&app).await;
+
+    let response = get(&app, &format!("/admin/newsletters/{}/revisions", draft_id)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert_eq!(
+        html_page
+            .matches("<button type=\"submit\">Restore</button>")
+            .count(),
+        2
+    );
+    assert!(html_page.contains(&format!(
+        "/admin/newsletters/{}/revisions/1?compare_to=2",
+        draft_id
+    )));
+}
+
+#[tokio::test]
+async fn saves_which_change_nothing_are_not_kept_as_revisions() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    let draft_id = draft_saved_twice(&app).await;
+    let mut body = draft_body();
+    body["text"] = "Almost done".into();
+
+    // Act
+    let response = app.post_autosave(draft_id, &body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(revision_numbers(&app, draft_id).await, [1, 2]);
+}
+
+#[tokio::test]
+async fn only_the_latest_revisions_are_kept() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    let draft_id = draft_saved_twice(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_draft_revisions (
+            draft_id, tenant_id, revision, title, text_content, html_content, saved_at
+        )
+        SELECT draft_id, tenant_id, n, title, n::text, html_content, saved_at
+        FROM newsletter_drafts, generate_series(3, 50) AS n
+        WHERE draft_id = $1
+        "#,
+        draft_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let mut body = draft_body();
+    body["text"] = "Done".into();
+
+    // Act
+    app.post_autosave(draft_id, &body).await;
+
+    // Assert
+    let revisions = revision_numbers(&app, draft_id).await;
+    assert_eq!(revisions.len(), 50);
+    assert_eq!(revisions.first(), Some(&2));
+    assert_eq!(revisions.last(), Some(&51));
+}
+
+#[tokio::test]
+async fn revisions_are_compared_side_by_side() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let draft_id = draft_saved_twice(&app).await;
+
+    let response = get(
+        &app,
+        &format!("/admin/newsletters/{}/revisions/1", draft_id),
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Revision 1 compared with the current draft"));
+    assert!(html_page.contains(
+        "<tr class=\"changed\">\n<td><pre>Work in progress</pre></td>\n<td><pre>Almost done</pre></td>"
+    ));
+    // The title did not change.
+    assert!(html_page.contains("No change."));
+}
+
+#[tokio::test]
+async fn an_older_revision_can_be_restored() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let draft_id = draft_saved_twice(&app).await;
+
+    let response = post(
+        &app,
+        &format!("/admin/newsletters/{}/revisions/1/restore", draft_id),
+    )
+    .await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("Restored revision 1."));
+    assert!(html_page.contains(">Work in progress</textarea>"));
+    // Restoring is a save of its own, which can be undone.
+    let revisions = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM newsletter_draft_revisions WHERE draft_id = $1",
+        draft_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(revisions.count, 3);
+}
+
+#[tokio::test]
+async fn the_revisions_of_a_published_draft_are_dropped() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let draft_id = draft_saved_twice(&app).await;
+    let mut body = draft_body();
+    body["draft_id"] = draft_id.to_string().into();
+    body["idempotency_key"] = Uuid::new_v4().to_string().into();
+    body["confirmed"] = true.into();
+    app.post_newsletters(&body).await;
+
+    let response = get(&app, &format!("/admin/newsletters/{}/revisions", draft_id)).await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let response = post(
+        &app,
+        &format!("/admin/newsletters/{}/revisions/1/restore", draft_id),
+    )
+    .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletters_html().await;
+    assert!(!html_page.contains("Restored the draft"));
+}
+
+#[tokio::test]
+async fn comments_are_shown_below_the_draft() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let draft_id = Uuid::new_v4();
+    app.post_autosave(draft_id, &draft_body()).await;
+
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/newsletters/{}/comments",
+            app.address, draft_id
+        ))
+        .form(&serde_json::json!({ "body": "The intro is too long" }))
+        .send()
+        .await
+        .unwrap();
+
+    let draft_page = format!("/admin/newsletters?draft_id={}", draft_id);
+    assert_is_redirect_to(&response, &draft_page);
+    let html_page = get(&app, &draft_page).await.text().await.unwrap();
+    assert!(html_page.contains("The intro is too long"));
+    assert!(html_page.contains(&format!("<strong>{}</strong>", app.test_user.username)));
+    assert!(html_page.contains("Resolve"));
+}
+
+#[tokio::test]
+async fn a_comment_can_be_resolved() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let draft_id = Uuid::new_v4();
+    app.post_autosave(draft_id, &draft_body()).await;
+    app.api_client
+        .post(format!(
+            "{}/admin/newsletters/{}/comments",
+            app.address, draft_id
+        ))
+        .form(&serde_json::json!({ "body": "The intro is too long" }))
+        .send()
+        .await
+        .unwrap();
+    let comment_id = sqlx::query!("SELECT comment_id FROM draft_comments")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .comment_id;
+
+    app.api_client
+        .post(format!(
+            "{}/admin/newsletters/{}/comments/{}/resolve",
+            app.address, draft_id, comment_id
+        ))
+        .form(&serde_json::json!({ "resolved": true }))
+        .send()
+        .await
+        .unwrap();
+
+    let html_page = get(&app, &format!("/admin/newsletters?draft_id={}", draft_id))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<s>The intro is too long</s>"));
+    assert!(html_page.contains("Reopen"));
+}
+
+#[tokio::test]
+async fn the_drafts_of_another_tenant_cannot_be_commented_or_resolved() {
+    // Arrange
+    let app = spawn_app().await;
+    let draft_id = draft_of_another_tenant(&app).await;
+    let comment_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO draft_comments (comment_id, draft_id, author_id, body, created_at)
+        SELECT $1, draft_id, user_id, 'Theirs to resolve', now()
+        FROM newsletter_drafts
+        WHERE draft_id = $2
+        "#,
+        comment_id,
+        draft_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.do_login().await;
+
+    // Act
+    let comment = app
+        .api_client
+        .post(format!(
+            "{}/admin/newsletters/{}/comments",
+            app.address, draft_id
+        ))
+        .form(&serde_json::json!({ "body": "The intro is too long" }))
+        .send()
+        .await
+        .unwrap();
+    let resolve = app
+        .api_client
+        .post(format!(
+            "{}/admin/newsletters/{}/comments/{}/resolve",
+            app.address, draft_id, comment_id
+        ))
+        .form(&serde_json::json!({ "resolved": true }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&comment, "/admin/newsletters");
+    assert_is_redirect_to(&resolve, "/admin/newsletters");
+    let comments = sqlx::query!("SELECT body, resolved_at FROM draft_comments")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0].body, "Theirs to resolve");
+    assert!(comments[0].resolved_at.is_none());
+}
+
+#[tokio::test]
+async fn a_draft_must_be_saved_before_it_can_be_commented() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/newsletters/{}/comments",
+            app.address,
+            Uuid::new_v4()
+        ))
+        .form(&serde_json::json!({ "body": "The intro is too long" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("This draft does not exist - or it was published."));
+}
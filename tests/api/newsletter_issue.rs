@@ -0,0 +1,170 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use zero2prod::testing::{run_worker_once, FakeEmailSender, IssueFixture, SubscriberFixture};
+
+const URSULA: &str = "ursula@example.com";
+const GED: &str = "ged@example.com";
+
+/// Publish an issue which fails to reach both of its recipients.
+async fn publish_failed_issue(app: &TestApp) -> Uuid {
+    for email in [URSULA, GED] {
+        SubscriberFixture::confirmed()
+            .with_email(email)
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    run_worker_once(
+        &app.db_pool,
+        &FakeEmailSender::failing_for(&[URSULA, GED]),
+        &app.address,
+    )
+    .await
+    .unwrap();
+    issue_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_an_issue() {
+    let app = spawn_app().await;
+
+    let response = app.get_newsletter_issue(Uuid::new_v4()).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_unknown_issue_is_a_404() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app.get_newsletter_issue(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_failed_recipients_of_an_issue_are_listed() {
+    let app = spawn_app().await;
+    let issue_id = publish_failed_issue(&app).await;
+    app.do_login().await;
+
+    let response = app.get_newsletter_issue(issue_id).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<li>Failed: 2</li>"));
+    for email in [URSULA, GED] {
+        assert!(html_page.contains(&format!(
+            r#"<input type="checkbox" name="subscriber_email" value="{}">"#,
+            email
+        )));
+    }
+    assert!(html_page.contains("The email provider rejected ged@example.com"));
+}
+
+#[tokio::test]
+async fn only_the_selected_recipients_are_sent_the_issue_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = publish_failed_issue(&app).await;
+    app.do_login().await;
+
+    // Act - Part 1 - Retry one of the recipients
+    let response = app.post_retry_deliveries(issue_id, &[URSULA]).await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    let html_page = app
+        .get_newsletter_issue(issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>The delivery to 1 recipient will be retried.</i></p>"));
+    assert!(html_page.contains("<li>Pending: 1</li>"));
+
+    // Act - Part 2 - The worker goes through the queue again
+    let email_sender = FakeEmailSender::default();
+    run_worker_once(&app.db_pool, &email_sender, &app.address)
+        .await
+        .unwrap();
+
+    // Assert
+    let recipients: Vec<_> = email_sender
+        .sent()
+        .iter()
+        .map(|e| e.recipient.clone())
+        .collect();
+    assert_eq!(recipients, [URSULA]);
+    let html_page = app
+        .get_newsletter_issue(issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<li>Delivered: 1</li>"));
+    assert!(html_page.contains("<li>Failed: 1</li>"));
+    assert!(!html_page.contains(&format!(r#"value="{}""#, URSULA)));
+}
+
+#[tokio::test]
+async fn recipients_who_unsubscribed_since_are_not_sent_the_issue_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = publish_failed_issue(&app).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' WHERE email = $1",
+        GED
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.do_login().await;
+
+    // Act
+    app.post_retry_deliveries(issue_id, &[URSULA, GED]).await;
+
+    // Assert
+    let html_page = app
+        .get_newsletter_issue(issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>The delivery to 1 recipient will be retried.</i></p>"));
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, URSULA);
+}
+
+#[tokio::test]
+async fn delivered_recipients_cannot_be_retried() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_email(URSULA)
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    run_worker_once(&app.db_pool, &FakeEmailSender::default(), &app.address)
+        .await
+        .unwrap();
+    app.do_login().await;
+
+    app.post_retry_deliveries(issue_id, &[URSULA]).await;
+
+    let html_page = app
+        .get_newsletter_issue(issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("None of the selected deliveries can be retried."));
+    let executed = run_worker_once(&app.db_pool, &FakeEmailSender::default(), &app.address)
+        .await
+        .unwrap();
+    assert_eq!(executed, 0);
+}
@@ -1,11 +1,24 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, spawn_app_with, test_configuration, ConfirmationLinks,
+    TestApp,
+};
 use fake::faker::internet::en::SafeEmail;
 use fake::faker::name::en::Name;
 use fake::Fake;
 use std::time::Duration;
 use uuid::Uuid;
 use wiremock::matchers::{any, method, path};
-use wiremock::{Mock, MockBuilder, ResponseTemplate};
+use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::email_failover::FailoverEmailSender;
+use zero2prod::i18n::Locale;
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome, DELIVERY_CHANNEL};
+use zero2prod::sending_quota::{SendingQuota, WarmUp};
+use zero2prod::services::{NewIssue, NewsletterService};
+use zero2prod::tenancy::{create_tenant, NewTenant, TenantId};
+use zero2prod::testing::{
+    email_footer, run_worker_once, FakeEmailSender, IssueFixture, SubscriberFixture,
+};
 
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
@@ -24,6 +37,7 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
         "text": "Newsletter body as plain text",
         "html": "<p>Newsletter body as HTML</p>",
         "idempotency_key": Uuid::new_v4().to_string(),
+        "confirmed": true,
     });
 
     app.do_login().await;
@@ -52,6 +66,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
         "text": "Newsletter body as plain text",
         "html": "<p>Newsletter body as HTML</p>",
         "idempotency_key": Uuid::new_v4().to_string(),
+        "confirmed": true,
     });
 
     app.do_login().await;
@@ -63,6 +78,742 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     // Mock verifies on Drop that we have sent the newsletter email
 }
 
+#[tokio::test]
+async fn publishing_an_issue_wakes_up_the_delivery_workers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let mut listener = sqlx::postgres::PgListener::connect_with(&app.db_pool)
+        .await
+        .unwrap();
+    listener.listen(DELIVERY_CHANNEL).await.unwrap();
+
+    // Act
+    app.do_login().await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter Title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "confirmed": true,
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    let notification = tokio::time::timeout(Duration::from_secs(5), listener.recv())
+        .await
+        .expect("The workers were not notified")
+        .unwrap();
+    assert_eq!(notification.channel(), DELIVERY_CHANNEL);
+}
+
+#[tokio::test]
+async fn issues_published_from_the_admin_panel_keep_their_tags() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let body = serde_json::json!({
+        "title": "Newsletter Title",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+        "tags": "Rust, releases, rust",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+
+    let confirmation = app.post_newsletters(&body).await.text().await.unwrap();
+    let mut body = body;
+    body["confirmed"] = true.into();
+    let response = app.post_newsletters(&body).await;
+
+    assert!(confirmation.contains("Tagged rust, releases."));
+    assert!(confirmation.contains(r#"name="tags" value="rust, releases""#));
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let tags = sqlx::query!("SELECT tag FROM newsletter_issue_tags ORDER BY tag")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let tags: Vec<_> = tags.into_iter().map(|r| r.tag).collect();
+    assert_eq!(tags, ["releases", "rust"]);
+}
+
+#[tokio::test]
+async fn issues_are_sent_to_confirmed_subscribers_tagged_with_the_issue_id() {
+    let app = spawn_app().await;
+    let confirmed = SubscriberFixture::confirmed()
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    SubscriberFixture::pending()
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    let issue_id = IssueFixture::default()
+        .with_title("Fixture issue")
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    let sender = FakeEmailSender::default();
+
+    let executed = run_worker_once(&app.db_pool, &sender, &app.address)
+        .await
+        .unwrap();
+
+    assert_eq!(executed, 1);
+    let sent = sender.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].recipient, confirmed.email);
+    assert_eq!(sent[0].subject, "Fixture issue");
+    assert_eq!(sent[0].tag, Some(issue_id.to_string()));
+}
+
+#[tokio::test]
+async fn the_worker_stops_sending_once_the_hourly_quota_is_reached() {
+    let app = spawn_app().await;
+    for email in ["ursula@example.com", "ged@example.com"] {
+        SubscriberFixture::confirmed()
+            .with_email(email)
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let sender = FakeEmailSender::default();
+    let quota = SendingQuota {
+        per_hour: Some(1),
+        ..SendingQuota::default()
+    };
+
+    let footer = email_footer();
+    let english = Locale::parse("en").unwrap();
+
+    let first = try_execute_task(
+        &app.db_pool,
+        &sender,
+        &app.address,
+        &quota,
+        &footer,
+        &english,
+        None,
+    )
+    .await
+    .unwrap();
+    let second = try_execute_task(
+        &app.db_pool,
+        &sender,
+        &app.address,
+        &quota,
+        &footer,
+        &english,
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert!(matches!(first, ExecutionOutcome::TaskCompleted));
+    assert!(matches!(second, ExecutionOutcome::QuotaExhausted));
+    assert_eq!(sender.sent().len(), 1);
+}
+
+#[tokio::test]
+async fn workers_running_side_by_side_do_not_overshoot_the_quota() {
+    let app = spawn_app().await;
+    for i in 0..5 {
+        SubscriberFixture::confirmed()
+            .with_email(&format!("subscriber{}@example.com", i))
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let sender = FakeEmailSender::default();
+    let quota = SendingQuota {
+        per_day: Some(2),
+        ..SendingQuota::default()
+    };
+    let footer = email_footer();
+    let english = Locale::parse("en").unwrap();
+
+    // Each worker holds a connection for its task, and takes another to count it.
+    let outcomes = futures_util::future::join_all((0..4).map(|_| {
+        try_execute_task(
+            &app.db_pool,
+            &sender,
+            &app.address,
+            &quota,
+            &footer,
+            &english,
+            None,
+        )
+    }))
+    .await;
+
+    assert!(outcomes.iter().all(|o| o.is_ok()));
+    assert_eq!(sender.sent().len(), 2);
+    let queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 3);
+}
+
+#[tokio::test]
+async fn the_worker_defers_the_deliveries_beyond_the_warm_up_cap_of_the_day() {
+    let app = spawn_app().await;
+    for email in ["ursula@example.com", "ged@example.com"] {
+        SubscriberFixture::confirmed()
+            .with_email(email)
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let sender = FakeEmailSender::default();
+    let quota = SendingQuota {
+        warm_up: Some(WarmUp {
+            start_date: chrono::Utc::now().date_naive(),
+            daily_caps: vec![1, 1000],
+        }),
+        ..SendingQuota::default()
+    };
+    let footer = email_footer();
+    let english = Locale::parse("en").unwrap();
+
+    let mut outcomes = Vec::new();
+    for _ in 0..2 {
+        let outcome = try_execute_task(
+            &app.db_pool,
+            &sender,
+            &app.address,
+            &quota,
+            &footer,
+            &english,
+            None,
+        )
+        .await
+        .unwrap();
+        outcomes.push(outcome);
+    }
+
+    assert!(matches!(outcomes[0], ExecutionOutcome::TaskCompleted));
+    assert!(matches!(outcomes[1], ExecutionOutcome::EmptyQueue));
+    assert_eq!(sender.sent().len(), 1);
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 1);
+}
+
+#[tokio::test]
+async fn each_sending_domain_is_warmed_up_on_its_own() {
+    let app = spawn_app().await;
+    let tenant_id = create_tenant(
+        &app.db_pool,
+        &NewTenant {
+            name: "Example News".into(),
+            hostname: "news.example.com".into(),
+            sender_email: Some(SubscriberEmail::parse("editor@news.example.com".into()).unwrap()),
+            public_base_url: None,
+            chat_webhook_url: None,
+        },
+    )
+    .await
+    .unwrap();
+    for (email, tenant_id) in [
+        ("ursula@example.com", TenantId::DEFAULT),
+        ("ged@example.com", TenantId::DEFAULT),
+        ("tenar@example.com", tenant_id),
+    ] {
+        SubscriberFixture::confirmed()
+            .with_email(email)
+            .for_tenant(tenant_id)
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    IssueFixture::default()
+        .for_tenant(tenant_id)
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    let sender = FakeEmailSender::default();
+    let quota = SendingQuota {
+        warm_up: Some(WarmUp {
+            start_date: chrono::Utc::now().date_naive(),
+            daily_caps: vec![1, 1000],
+        }),
+        sender_domain: "example.com".into(),
+        ..SendingQuota::default()
+    };
+    let footer = email_footer();
+    let english = Locale::parse("en").unwrap();
+
+    let mut completed = 0;
+    while let ExecutionOutcome::TaskCompleted = try_execute_task(
+        &app.db_pool,
+        &sender,
+        &app.address,
+        &quota,
+        &footer,
+        &english,
+        None,
+    )
+    .await
+    .unwrap()
+    {
+        completed += 1;
+    }
+
+    // One email from each sending domain, the cap of their first day.
+    assert_eq!(completed, 2);
+    let mut senders: Vec<_> = sqlx::query!("SELECT sender_domain FROM issue_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.sender_domain.unwrap())
+        .collect();
+    senders.sort();
+    assert_eq!(senders, ["example.com", "news.example.com"]);
+}
+
+#[tokio::test]
+async fn the_worker_throttles_the_deliveries_to_each_domain() {
+    let app = spawn_app().await;
+    for email in ["ursula@example.com", "ged@Example.com", "tenar@example.org"] {
+        SubscriberFixture::confirmed()
+            .with_email(email)
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let sender = FakeEmailSender::default();
+    let quota = SendingQuota {
+        per_domain_per_minute: vec![("example.com".into(), 1)],
+        ..SendingQuota::default()
+    };
+    let footer = email_footer();
+    let english = Locale::parse("en").unwrap();
+
+    let mut completed = 0;
+    while let ExecutionOutcome::TaskCompleted = try_execute_task(
+        &app.db_pool,
+        &sender,
+        &app.address,
+        &quota,
+        &footer,
+        &english,
+        None,
+    )
+    .await
+    .unwrap()
+    {
+        completed += 1;
+    }
+
+    assert_eq!(completed, 2);
+    assert!(sender
+        .sent()
+        .iter()
+        .any(|e| e.recipient == "tenar@example.org"));
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued
+        .subscriber_email
+        .to_lowercase()
+        .ends_with("@example.com"));
+}
+
+#[tokio::test]
+async fn issues_fail_over_to_the_fallback_provider_and_record_it() {
+    let app = spawn_app().await;
+    for email in ["ursula@example.com", "ged@example.com"] {
+        SubscriberFixture::confirmed()
+            .with_email(email)
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    // The primary provider is down: its breaker opens after the first failure.
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let fallback_server = MockServer::start().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&fallback_server)
+        .await;
+    let mut settings = test_configuration(&app.email_server).email_client;
+    settings.circuit_breaker_failure_threshold = 1;
+    settings.fallback_base_url = fallback_server.uri();
+    let sender = FailoverEmailSender::from_settings(&settings);
+
+    run_worker_once(&app.db_pool, &sender, &app.address)
+        .await
+        .unwrap();
+
+    let deliveries = sqlx::query!("SELECT outcome, provider FROM issue_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 2);
+    for delivery in deliveries {
+        assert_eq!(delivery.outcome, "delivered");
+        assert_eq!(delivery.provider.as_deref(), Some("fallback"));
+    }
+}
+
+#[tokio::test]
+async fn issues_close_with_the_sender_and_their_postal_address() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    IssueFixture::default()
+        .with_content("Hello", "<html><body><p>Hello</p></body></html>")
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    let sender = FakeEmailSender::default();
+
+    run_worker_once(&app.db_pool, &sender, &app.address)
+        .await
+        .unwrap();
+
+    let sent = sender.sent()[0].clone();
+    assert!(sent
+        .text_content
+        .ends_with("\n--\nZero To Production\n1 Main Street, Springfield"));
+    assert!(sent.html_content.ends_with(
+        "<p class=\"footer\">Zero To Production<br>1 Main Street, Springfield</p>\n</body></html>"
+    ));
+}
+
+#[tokio::test]
+async fn issues_are_framed_in_the_language_the_subscriber_chose() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_locale("fr")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let sender = FakeEmailSender::default();
+
+    run_worker_once(&app.db_pool, &sender, &app.address)
+        .await
+        .unwrap();
+
+    let sent = sender.sent()[0].clone();
+    assert!(sent
+        .text_content
+        .starts_with("Voir cet e-mail dans votre navigateur: "));
+    assert!(sent.html_content.contains(">Se désabonner</a>"));
+}
+
+#[tokio::test]
+async fn issues_link_to_their_page_in_the_public_archive() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    let issue_id = IssueFixture::default()
+        .with_title("Fixture issue")
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    let sender = FakeEmailSender::default();
+
+    run_worker_once(&app.db_pool, &sender, &app.address)
+        .await
+        .unwrap();
+
+    let url = format!("{}/issues/{}", app.address, issue_id);
+    let sent = sender.sent()[0].clone();
+    assert!(sent
+        .text_content
+        .starts_with(&format!("View this email in your browser: {}\n", url)));
+    assert!(sent.html_content.contains(&format!(
+        r#"<a href="{}">View this email in your browser</a>"#,
+        url
+    )));
+    let response = app.api_client.get(&url).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<h1>Fixture issue</h1>"));
+}
+
+#[tokio::test]
+async fn every_confirmed_subscriber_of_a_large_audience_is_queued_once() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT
+            gen_random_uuid(),
+            'subscriber' || i || '@example.com',
+            'Subscriber',
+            now(),
+            (CASE WHEN i % 10 = 0 THEN 'pending_confirmation' ELSE 'confirmed' END)::subscription_status
+        FROM generate_series(1, 2500) AS i
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.do_login().await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter Title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "confirmed": true,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let queue = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "total!", COUNT(DISTINCT subscriber_email) AS "distinct!"
+        FROM issue_delivery_queue
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(queue.total, 2250);
+    assert_eq!(queue.distinct, 2250);
+}
+
+#[tokio::test]
+async fn the_workers_deliver_an_issue_whose_publishing_stopped_before_queuing_it() {
+    // Arrange
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_email("ursula@example.com")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    // The issue is committed, but the request stops before its deliveries are queued.
+    let issue = NewIssue::parse(
+        "Newsletter Title".into(),
+        "Newsletter body as plain text".into(),
+        "<p>Newsletter body as HTML</p>".into(),
+    )
+    .unwrap();
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    NewsletterService::new(&mut transaction, TenantId::DEFAULT)
+        .publish(&issue)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+    let sender = FakeEmailSender::default();
+
+    // Act
+    let executed = run_worker_once(&app.db_pool, &sender, &app.address)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(executed, 1);
+    assert_eq!(sender.sent()[0].recipient, "ursula@example.com");
+}
+
+#[tokio::test]
+async fn an_unconfirmed_submission_shows_what_would_be_sent_without_publishing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.do_login().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Spring edition",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": idempotency_key,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<strong>1</strong> confirmed subscriber(s)"));
+    assert!(html_page.contains("less than a minute"));
+    assert!(html_page.contains(r#"srcdoc="&lt;p&gt;Newsletter body as HTML"#));
+    assert!(html_page.contains(&format!(
+        r#"name="idempotency_key" value="{}""#,
+        idempotency_key
+    )));
+    assert!(html_page.contains(r#"<input type="hidden" name="confirmed" value="true">"#));
+    // Text inputs would strip the line breaks of the content on the way back.
+    assert!(html_page.contains(r#"<input type="hidden" name="text""#));
+    let issues = sqlx::query!("SELECT COUNT(*) AS count FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, Some(0));
+}
+
+#[tokio::test]
+async fn the_confirmation_screen_previews_the_issue_at_several_widths() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app.post_newsletters(&preview_body()).await;
+
+    let html_page = response.text().await.unwrap();
+    for width in ["mobile", "email", "desktop"] {
+        assert!(html_page.contains(&format!(r#"<figure class="preview-frame {}">"#, width)));
+    }
+    assert_eq!(html_page.matches("<iframe").count(), 3);
+    assert!(html_page.contains(r#"<label for="preview-text">Plain text</label>"#));
+    assert!(html_page.contains("Newsletter body as plain text</pre>"));
+}
+
+#[tokio::test]
+async fn the_confirmation_screen_shows_a_sample_of_the_recipients() {
+    let app = spawn_app_with(|c| c.newsletters.recipients_sample_size = 2).await;
+    for email in ["ursula@example.com", "ged@example.com", "tenar@example.com"] {
+        SubscriberFixture::confirmed()
+            .with_email(email)
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    SubscriberFixture::pending()
+        .with_email("pending@example.com")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    app.do_login().await;
+
+    let html_page = app
+        .post_newsletters(&preview_body())
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(html_page.matches("@example.com</td>").count(), 2);
+    assert!(!html_page.contains("pending@example.com"));
+    assert!(!html_page.contains("***"));
+}
+
+#[tokio::test]
+async fn the_sample_of_recipients_is_masked_for_admins_without_pii_access() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_email("ursula@example.com")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "UPDATE users SET can_view_pii = false WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.do_login().await;
+
+    let html_page = app
+        .post_newsletters(&preview_body())
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains("<td>U***</td><td>u***@example.com</td>"));
+    assert!(!html_page.contains("ursula@example.com"));
+}
+
+#[tokio::test]
+async fn confirming_a_previewed_issue_publishes_it_once() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.do_login().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let mut body = serde_json::json!({
+        "title": "Spring edition",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_newsletters(&body).await;
+
+    body["confirmed"] = true.into();
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    // A second confirmation replays the saved response.
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn the_confirmation_screen_shows_the_spam_score_of_the_issue() {
+    let spam_checker = MockServer::start().await;
+    let app = spawn_app_with(|c| c.spam_check.base_url = spam_checker.uri()).await;
+    Mock::given(path("/filter"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": true,
+            "score": "2.5",
+            "rules": [{ "score": "2.5", "description": "Subject is all capitals" }]
+        })))
+        .expect(1)
+        .mount(&spam_checker)
+        .await;
+    app.do_login().await;
+
+    let response = app.post_newsletters(&preview_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Spam score: <strong>2.5</strong>"));
+    assert!(html_page.contains("<li>2.5: Subject is all capitals</li>"));
+    assert!(!html_page.contains("most spam filters would reject"));
+}
+
+#[tokio::test]
+async fn an_issue_can_still_be_confirmed_when_the_spam_check_fails() {
+    let spam_checker = MockServer::start().await;
+    let app = spawn_app_with(|c| c.spam_check.base_url = spam_checker.uri()).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&spam_checker)
+        .await;
+    app.do_login().await;
+
+    let response = app.post_newsletters(&preview_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("The issue could not be checked for spam"));
+    assert!(html_page.contains(r#"name="confirmed" value="true""#));
+}
+
 #[tokio::test]
 async fn newsletters_returns_400_for_invalid_data() {
     let app = spawn_app().await;
@@ -105,6 +856,7 @@ async fn you_must_be_logged_in_to_publish_a_newsletter() {
         "text": "Newsletter body as plain text",
         "html": "<p>Newsletter body as HTML</p>",
         "idempotency_key": Uuid::new_v4().to_string(),
+        "confirmed": true,
     });
 
     let response = app.post_newsletters(&newsletter_request_body).await;
@@ -113,6 +865,27 @@ async fn you_must_be_logged_in_to_publish_a_newsletter() {
     assert_is_redirect_to(&response, "/login");
 }
 
+#[tokio::test]
+async fn an_issue_beyond_the_daily_sending_quota_is_not_published() {
+    let app = spawn_app_with(|c| c.newsletters.max_emails_per_day = 1).await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.do_login().await;
+    let mut body = preview_body();
+    body["confirmed"] = true.into();
+
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("without exceeding the daily sending quota"));
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+}
+
 #[tokio::test]
 async fn you_must_be_logged_in_to_get_publish_newsletter_form() {
     let app = spawn_app().await;
@@ -142,6 +915,7 @@ async fn newsletter_creation_is_idempotent() {
         "text": "Newsletter body as plain text",
         "html": "<p>Newsletter body as HTML</p>",
         "idempotency_key": Uuid::new_v4().to_string(),
+        "confirmed": true,
     });
 
     let response = app.post_newsletters(&newsletter_request_body).await;
@@ -184,6 +958,7 @@ async fn concurrent_form_submission_is_handled_gracefully() {
         "text": "Newsletter body as plain text",
         "html": "<p>Newsletter body as HTML</p>",
         "idempotency_key": Uuid::new_v4().to_string(),
+        "confirmed": true,
     });
 
     let response1 = app.post_newsletters(&newsletter_request_body);
@@ -197,10 +972,241 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     app.dispatch_all_pending_emails().await;
 }
 
+#[tokio::test]
+async fn an_idempotency_key_can_be_reused_once_expired() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter Title",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "confirmed": true,
+    });
+    app.post_newsletters(&newsletter_request_body).await;
+    // Past the default TTL of a day.
+    sqlx::query!("UPDATE idempotency SET created_at = now() - interval '2 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_newsletters(&newsletter_request_body).await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 2);
+}
+
+#[tokio::test]
+async fn a_retry_gives_up_when_the_original_request_outlives_the_pending_lease() {
+    let app = spawn_app_with(|c| c.idempotency.pending_lease_timeout_milliseconds = 200).await;
+    app.do_login().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    // The original request, still in flight.
+    let mut original = app.db_pool.begin().await.unwrap();
+    sqlx::query!(
+        "INSERT INTO idempotency (user_id, idempotency_key, created_at) VALUES ($1, $2, now())",
+        app.test_user.user_id,
+        idempotency_key
+    )
+    .execute(&mut original)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter Title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": idempotency_key,
+            "confirmed": true,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+    original.rollback().await.unwrap();
+}
+
+#[tokio::test]
+async fn an_issue_can_be_cancelled_before_its_undo_window_closes() {
+    let app = spawn_app_with(|c| c.newsletters.undo_window_seconds = 120).await;
+    SubscriberFixture::confirmed()
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    app.do_login().await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Spring edition",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "confirmed": true,
+    }))
+    .await;
+
+    // Nothing goes out during the undo window.
+    let sender = FakeEmailSender::default();
+    assert_eq!(
+        run_worker_once(&app.db_pool, &sender, &app.address)
+            .await
+            .unwrap(),
+        0
+    );
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("Undo send"));
+
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let response = app.post_cancel_newsletter(issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("The newsletter issue has been cancelled - no email was sent."));
+    assert!(!html_page.contains("Undo send"));
+    let left = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM newsletter_issues) AS "issues!",
+            (SELECT COUNT(*) FROM issue_delivery_queue) AS "queued!"
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!((left.issues, left.queued), (0, 0));
+}
+
+#[tokio::test]
+async fn an_issue_cannot_be_cancelled_once_its_delivery_has_started() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let sender = FakeEmailSender::default();
+    run_worker_once(&app.db_pool, &sender, &app.address)
+        .await
+        .unwrap();
+    app.do_login().await;
+
+    let response = app.post_cancel_newsletter(issue_id).await;
+
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("The newsletter issue can no longer be cancelled"));
+    assert_eq!(sender.sent().len(), 1);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_newsletter_calendar() {
+    let app = spawn_app().await;
+
+    let response = app.get_newsletter_calendar("").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_calendar_shows_the_issues_of_the_month_and_flags_same_day_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    for title in ["Morning edition", "Evening edition"] {
+        IssueFixture::default()
+            .with_title(title)
+            .publish(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    app.do_login().await;
+
+    // Act
+    let response = app.get_newsletter_calendar("").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Morning edition"));
+    assert!(html_page.contains("Evening edition"));
+    assert!(html_page.contains("2 issues on the same day"));
+}
+
+#[tokio::test]
+async fn the_calendar_can_be_filtered_by_tag() {
+    // Arrange
+    let app = spawn_app().await;
+    IssueFixture::default()
+        .with_title("Rust edition")
+        .with_tags(&["rust"])
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    IssueFixture::default()
+        .with_title("Go edition")
+        .with_tags(&["go"])
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    app.do_login().await;
+
+    // Act
+    let response = app.get_newsletter_calendar("?tag=rust").await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Rust edition"));
+    assert!(!html_page.contains("Go edition"));
+    assert!(html_page.contains(r#"<option value="go">go</option>"#));
+    assert!(html_page.contains("&amp;tag=rust"));
+}
+
+#[tokio::test]
+async fn the_calendar_can_show_another_month() {
+    // Arrange
+    let app = spawn_app().await;
+    IssueFixture::default()
+        .with_title("Current edition")
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    app.do_login().await;
+
+    // Act
+    let response = app.get_newsletter_calendar("?month=2022-04").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("April 2022"));
+    assert!(html_page.contains("?month=2022-03"));
+    assert!(html_page.contains("?month=2022-05"));
+    assert!(!html_page.contains("Current edition"));
+}
+
+#[tokio::test]
+async fn an_invalid_calendar_month_is_rejected() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    for month in ["April", "262143-12"] {
+        let response = app
+            .get_newsletter_calendar(&format!("?month={}", month))
+            .await;
+
+        assert_eq!(response.status().as_u16(), 400, "{}", month);
+    }
+}
+
 async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     let name: String = Name().fake();
     let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
+    let body = serde_urlencoded::to_string(serde_json::json!({
         "name": name,
         "email": email
     }))
@@ -213,7 +1219,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .expect(1)
         .mount_as_scoped(&app.email_server)
         .await;
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
@@ -225,7 +1231,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .unwrap()
         .pop()
         .unwrap();
-    app.get_confirmation_links(&email_request, 3, 1)
+    app.get_confirmation_links(email_request, 3, 1)
 }
 
 async fn create_confirmed_subscriber(app: &TestApp) {
@@ -240,3 +1246,13 @@ async fn create_confirmed_subscriber(app: &TestApp) {
 fn when_sending_an_email() -> MockBuilder {
     Mock::given(path("/email")).and(method("POST"))
 }
+
+/// An issue submitted from the compose form, before confirmation.
+fn preview_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Spring edition",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
@@ -0,0 +1,150 @@
+use crate::helpers::{spawn_app, TestApp};
+use zero2prod::testing::{
+    run_worker_once, FakeEmailSender, IssueFixture, StoredSubscriber, SubscriberFixture,
+};
+
+async fn confirmed_subscriber(app: &TestApp, email: &str) -> StoredSubscriber {
+    SubscriberFixture::confirmed()
+        .with_email(email)
+        .store(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn set_timezone(app: &TestApp, subscriber: &StoredSubscriber, timezone: &str) {
+    let response = app
+        .post_preferences(&serde_json::json!({
+            "subscription_token": subscriber.subscription_token.as_ref(),
+            "timezone": timezone,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribers_can_set_their_timezone() {
+    let app = spawn_app().await;
+    let subscriber = confirmed_subscriber(&app, "ursula@example.com").await;
+
+    set_timezone(&app, &subscriber, "America/Los_Angeles").await;
+
+    let html_page = app
+        .get_preferences(subscriber.subscription_token.as_ref())
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(r#"<option value="America/Los_Angeles" selected>"#));
+    let saved = sqlx::query!("SELECT timezone FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.timezone.as_deref(), Some("America/Los_Angeles"));
+}
+
+#[tokio::test]
+async fn unknown_timezones_are_rejected() {
+    let app = spawn_app().await;
+    let subscriber = confirmed_subscriber(&app, "ursula@example.com").await;
+
+    let response = app
+        .post_preferences(&serde_json::json!({
+            "subscription_token": subscriber.subscription_token.as_ref(),
+            "timezone": "Earthsea/Havnor",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_unsubscribe_page_links_to_the_preferences_through_a_signed_url() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber = confirmed_subscriber(&app, "ursula@example.com").await;
+    let token = subscriber.subscription_token.as_ref();
+
+    // Act
+    let html_page = app.get_unsubscribe(token).await.text().await.unwrap();
+
+    // Assert
+    let link = html_page
+        .split(r#"<a href=""#)
+        .map(|s| {
+            s.split('"')
+                .next()
+                .unwrap()
+                .replace("&#x2f;", "/")
+                .replace("&amp;", "&")
+        })
+        .find(|href| href.starts_with("/preferences?"))
+        .unwrap();
+    assert!(link.contains("&signature="));
+    let response = reqwest::get(format!("{}{}", app.address, link))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let unsigned = app
+        .api_client
+        .get(format!(
+            "{}/preferences?subscription_token={}",
+            app.address, token
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(unsigned.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_preferences_page_needs_a_known_subscription_token() {
+    let app = spawn_app().await;
+
+    let response = app.get_preferences("a".repeat(25).as_str()).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn issues_sent_at_local_time_wait_for_the_send_time_of_each_recipient() {
+    let app = spawn_app().await;
+    let with_timezone = confirmed_subscriber(&app, "ursula@example.com").await;
+    confirmed_subscriber(&app, "ged@example.com").await;
+    set_timezone(&app, &with_timezone, "Asia/Tokyo").await;
+    // The current hour of the day in Tokyo has started: its next occurrence is tomorrow.
+    let hour: u8 = sqlx::query!(
+        r#"SELECT EXTRACT(hour FROM now() AT TIME ZONE 'Asia/Tokyo')::int AS "hour!""#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .hour as u8;
+    IssueFixture::default()
+        .sent_at_local_hour(hour)
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    let sender = FakeEmailSender::default();
+
+    run_worker_once(&app.db_pool, &sender, &app.address)
+        .await
+        .unwrap();
+
+    let recipients: Vec<_> = sender.sent().iter().map(|e| e.recipient.clone()).collect();
+    assert_eq!(recipients, ["ged@example.com"]);
+    let waiting = sqlx::query!(
+        r#"
+        SELECT
+            subscriber_email,
+            EXTRACT(hour FROM deliver_after AT TIME ZONE 'Asia/Tokyo')::int AS "hour!",
+            deliver_after > now() AS "later!"
+        FROM issue_delivery_queue
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(waiting.subscriber_email, "ursula@example.com");
+    assert_eq!(waiting.hour, hour as i32);
+    assert!(waiting.later);
+}
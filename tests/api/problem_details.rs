@@ -0,0 +1,103 @@
+use crate::helpers::spawn_app;
+
+async fn assert_problem(response: reqwest::Response, status: u16, code: &str) {
+    assert_eq!(response.status().as_u16(), status);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["status"], status);
+    assert_eq!(problem["code"], code);
+    assert!(problem["title"].is_string());
+    assert!(problem["detail"].is_string());
+}
+
+#[tokio::test]
+async fn subscribe_errors_are_problem_details_when_json_is_accepted() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept", "application/json")
+        .form(&[("name", ""), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_problem(response, 400, "invalid_subscriber").await;
+}
+
+#[tokio::test]
+async fn subscribe_errors_are_unchanged_for_other_clients() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_ne!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+}
+
+#[tokio::test]
+async fn confirmation_errors_are_problem_details_when_json_is_accepted() {
+    let app = spawn_app().await;
+
+    for (token, status, code) in [
+        ("not-a-token", 400, "invalid_subscription_token"),
+        (
+            "aaaaaaaaaaaaaaaaaaaaaaaaa",
+            401,
+            "unknown_subscription_token",
+        ),
+    ] {
+        let response = app
+            .api_client
+            .get(format!(
+                "{}/subscriptions/confirm?subscription_token={}",
+                &app.address, token
+            ))
+            .header("Accept", "application/problem+json")
+            .send()
+            .await
+            .unwrap();
+
+        assert_problem(response, status, code).await;
+    }
+}
+
+#[tokio::test]
+async fn api_errors_are_problem_details_when_json_is_accepted() {
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/api/v1/subscribers/{}",
+            &app.address,
+            uuid::Uuid::new_v4()
+        ))
+        .bearer_auth(&api_key)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_problem(response, 404, "not_found").await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/subscribers", &app.address))
+        .bearer_auth("not-a-real-key")
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_problem(response, 401, "unauthorized").await;
+}
@@ -0,0 +1,445 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+async fn publish_issue(app: &TestApp, api_key: &str, title: &str) -> String {
+    let response = app
+        .api_post(
+            "/newsletters",
+            api_key,
+            &serde_json::json!({
+                "title": title,
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    issue["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+async fn get(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", &app.address, path))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn published_issues_are_listed_and_rendered_on_the_public_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let issue_id = publish_issue(&app, &api_key, "Spring edition").await;
+
+    // Act
+    let archive = get(&app, "/issues").await;
+    let issue = get(&app, &format!("/issues/{}", issue_id)).await;
+
+    // Assert
+    assert_eq!(archive.status().as_u16(), 200);
+    let archive_html = archive.text().await.unwrap();
+    assert!(archive_html.contains(&format!(
+        r#"<a href="/issues/{}">Spring edition</a>"#,
+        issue_id
+    )));
+    assert_eq!(issue.status().as_u16(), 200);
+    assert!(issue
+        .text()
+        .await
+        .unwrap()
+        .contains("<p>Newsletter body as HTML</p>"));
+}
+
+#[tokio::test]
+async fn an_unknown_issue_is_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = get(&app, &format!("/issues/{}", uuid::Uuid::new_v4())).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn issues_within_their_undo_window_are_not_archived_yet() {
+    // Arrange
+    let app = spawn_app_with(|c| c.newsletters.undo_window_seconds = 120).await;
+    let api_key = app.create_api_key().await;
+    let issue_id = publish_issue(&app, &api_key, "Spring edition").await;
+
+    // Act
+    let archive = get(&app, "/issues").await;
+    let issue = get(&app, &format!("/issues/{}", issue_id)).await;
+    let api_issue = app.get_archive(&format!("/{}", issue_id), None).await;
+    let api_archive: serde_json::Value = app.get_archive("", None).await.json().await.unwrap();
+
+    // Assert
+    assert!(!archive.text().await.unwrap().contains("Spring edition"));
+    assert_eq!(issue.status().as_u16(), 404);
+    assert_eq!(api_issue.status().as_u16(), 404);
+    assert_eq!(api_archive["total_issues"], 0);
+}
+
+#[tokio::test]
+async fn the_sitemap_lists_the_public_pages_and_every_published_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let issue_id = publish_issue(&app, &api_key, "Spring edition").await;
+
+    // Act
+    let response = get(&app, "/sitemap.xml").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/xml; charset=utf-8"
+    );
+    let sitemap = response.text().await.unwrap();
+    assert!(sitemap.contains("<loc>http://127.0.0.1/</loc>"));
+    assert!(sitemap.contains("<loc>http://127.0.0.1/issues</loc>"));
+    assert!(sitemap.contains(&format!(
+        "<loc>http://127.0.0.1/issues/{}</loc><lastmod>",
+        issue_id
+    )));
+}
+
+#[tokio::test]
+async fn robots_txt_disallows_everything_when_indexing_is_disabled() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.allow_indexing = false).await;
+
+    // Act
+    let response = get(&app, "/robots.txt").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "User-agent: *\nDisallow: /\n"
+    );
+}
+
+#[tokio::test]
+async fn robots_txt_points_to_the_sitemap_when_indexing_is_allowed() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.allow_indexing = true).await;
+
+    // Act
+    let response = get(&app, "/robots.txt").await;
+
+    // Assert
+    let robots = response.text().await.unwrap();
+    assert!(robots.contains("Disallow: /admin/"));
+    assert!(robots.contains("Disallow: /api/"));
+    assert!(!robots.contains("Disallow: /\n"));
+    assert!(robots.contains("Sitemap: http://127.0.0.1/sitemap.xml"));
+}
+
+#[tokio::test]
+async fn the_archive_is_served_from_the_cache_until_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    publish_issue(&app, &api_key, "Spring edition").await;
+    get(&app, "/issues").await;
+    // Renamed behind the application's back: the cached page still shows the old title.
+    sqlx::query!("UPDATE newsletter_issues SET title = 'Renamed edition'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let cached_html = get(&app, "/issues").await.text().await.unwrap();
+
+    // Act
+    publish_issue(&app, &api_key, "Summer edition").await;
+
+    // Assert
+    assert!(cached_html.contains("Spring edition"));
+    let archive_html = get(&app, "/issues").await.text().await.unwrap();
+    assert!(archive_html.contains("Renamed edition"));
+    assert!(archive_html.contains("Summer edition"));
+}
+
+#[tokio::test]
+async fn the_cache_can_be_disabled() {
+    // Arrange
+    let app = spawn_app_with(|c| c.cache.ttl_seconds = 0).await;
+    let api_key = app.create_api_key().await;
+    publish_issue(&app, &api_key, "Spring edition").await;
+    get(&app, "/issues").await;
+
+    // Act
+    sqlx::query!("UPDATE newsletter_issues SET title = 'Renamed edition'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Assert
+    let archive_html = get(&app, "/issues").await.text().await.unwrap();
+    assert!(archive_html.contains("Renamed edition"));
+}
+
+#[tokio::test]
+async fn the_archive_links_to_older_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    for title in ["First edition", "Second edition"] {
+        publish_issue(&app, &api_key, title).await;
+    }
+
+    // Act
+    let first_page = get(&app, "/issues?limit=1").await.text().await.unwrap();
+    let older_link = first_page
+        .split(r#"<a href="/issues?cursor="#)
+        .nth(1)
+        .and_then(|s| s.split('"').next())
+        .expect("There is no link to older issues");
+    let second_page = get(&app, &format!("/issues?cursor={}", older_link))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(older_link.ends_with("&limit=1"));
+    assert!(first_page.contains("Second edition"));
+    assert!(!first_page.contains("First edition"));
+    assert!(second_page.contains("First edition"));
+    assert!(!second_page.contains("Second edition"));
+}
+
+#[tokio::test]
+async fn public_pages_use_the_configured_branding() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.branding.site_name = "Earthsea Weekly".into();
+        c.branding.logo_url = "https://example.com/logo.png".into();
+        c.branding.accent_color = "#aa3300".into();
+    })
+    .await;
+
+    // Act
+    let html = get(&app, "/issues").await.text().await.unwrap();
+
+    // Assert
+    assert!(html.contains(r#"<a href="/">Earthsea Weekly</a>"#));
+    assert!(html.contains(r#"<img src="https://example.com/logo.png" alt="">"#));
+    assert!(html.contains("--accent: #aa3300;"));
+    assert!(html.contains(r#"<link rel="stylesheet" href="/static/theme.css">"#));
+}
+
+#[tokio::test]
+async fn the_theme_stylesheet_follows_the_color_scheme_of_the_visitor() {
+    let app = spawn_app().await;
+
+    let response = get(&app, "/static/theme.css").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/css; charset=utf-8"
+    );
+    let css = response.text().await.unwrap();
+    assert!(css.contains("@media (prefers-color-scheme: dark)"));
+    assert_eq!(
+        get(&app, "/static/unknown.css").await.status().as_u16(),
+        404
+    );
+}
+
+async fn publish_issue_with(app: &TestApp, api_key: &str, body: serde_json::Value) {
+    let response = app.api_post("/newsletters", api_key, &body).await;
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn the_archive_can_be_searched_and_browsed_by_tag() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    for (title, text, tags) in [
+        ("Spring edition", "Planting the garden", vec![]),
+        ("Summer edition", "Watering the garden", vec!["rust"]),
+        ("Autumn edition", "Raking leaves", vec!["Rust", "leaves"]),
+    ] {
+        publish_issue_with(
+            &app,
+            &api_key,
+            serde_json::json!({
+                "title": title,
+                "text": text,
+                "html": format!("<p>{}</p>", text),
+                "tags": tags,
+            }),
+        )
+        .await;
+    }
+
+    // Act
+    let searched = get(&app, "/issues?q=Garden").await;
+    let searched_cache_control = searched.headers()["Cache-Control"].to_owned();
+    let searched = searched.text().await.unwrap();
+    let tagged = get(&app, "/issues/tags/rust").await.text().await.unwrap();
+    let both = get(&app, "/issues/tags/rust?q=garden")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(searched.contains("Spring edition"));
+    assert!(searched.contains("Summer edition"));
+    assert!(!searched.contains("Autumn edition"));
+    assert!(searched_cache_control.to_str().unwrap().contains("public"));
+    assert!(!tagged.contains("Spring edition"));
+    assert!(tagged.contains("Summer edition"));
+    assert!(tagged.contains("Autumn edition"));
+    assert!(tagged.contains("Issues tagged rust"));
+    assert!(tagged.contains(r#"<a href="/issues/tags/rust" aria-current="page">rust</a>"#));
+    assert!(tagged.contains(r#"<a href="/issues/tags/leaves">leaves</a>"#));
+    assert!(both.contains("Summer edition"));
+    assert!(!both.contains("Spring edition"));
+    assert!(!both.contains("Autumn edition"));
+}
+
+#[tokio::test]
+async fn the_link_to_older_issues_keeps_the_filters() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    for title in ["First garden", "Unrelated", "Second garden"] {
+        publish_issue(&app, &api_key, title).await;
+    }
+
+    // Act
+    let first_page = get(&app, "/issues?q=garden&limit=1")
+        .await
+        .text()
+        .await
+        .unwrap();
+    let older_link = first_page
+        .split(r#"<a href="/issues?cursor="#)
+        .nth(1)
+        .and_then(|s| s.split('"').next())
+        .expect("There is no link to older issues")
+        .replace("&amp;", "&");
+    let second_page = get(&app, &format!("/issues?cursor={}", older_link))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(older_link.ends_with("&limit=1&q=garden"));
+    assert!(first_page.contains("Second garden"));
+    assert!(second_page.contains("First garden"));
+    assert!(!second_page.contains("Unrelated"));
+}
+
+#[tokio::test]
+async fn each_tag_has_an_rss_feed_of_its_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    publish_issue(&app, &api_key, "Untagged edition").await;
+    for title in ["First <rust> edition", "Second rust edition"] {
+        publish_issue_with(
+            &app,
+            &api_key,
+            serde_json::json!({
+                "title": title,
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+                "tags": ["rust"],
+            }),
+        )
+        .await;
+    }
+
+    // Act
+    let response = get(&app, "/issues/tags/rust/feed.xml").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/rss+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("<link>http://127.0.0.1/issues/tags/rust</link>"));
+    assert!(feed.contains("<title>First &lt;rust&gt; edition</title>"));
+    assert!(feed.contains("<title>Second rust edition</title>"));
+    assert!(!feed.contains("Untagged edition"));
+    assert!(feed.find("Second rust edition") < feed.find("First &lt;rust&gt; edition"));
+    let sitemap = get(&app, "/sitemap.xml").await.text().await.unwrap();
+    assert!(sitemap.contains("<loc>http://127.0.0.1/issues/tags/rust</loc>"));
+}
+
+#[tokio::test]
+async fn invalid_tags_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+
+    // Act
+    let response = app
+        .api_post(
+            "/newsletters",
+            &api_key,
+            &serde_json::json!({
+                "title": "Newsletter title",
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+                "tags": ["rust weekly"],
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        get(&app, "/issues/tags/rust%20weekly")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+}
+
+#[tokio::test]
+async fn merge_fields_fall_back_on_the_public_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    let api_key = app.create_api_key().await;
+    let response = app
+        .api_post(
+            "/newsletters",
+            &api_key,
+            &serde_json::json!({
+                "title": "Spring edition for {{ name | you }}",
+                "text": "Hi {{ name | there }}",
+                "html": "<p>Hi {{ name | there }}, sent to {{ email }}. {{ x }}</p>",
+            }),
+        )
+        .await;
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let issue_id = issue["newsletter_issue_id"].as_str().unwrap();
+
+    // Act
+    let archive = get(&app, "/issues").await.text().await.unwrap();
+    let issue = get(&app, &format!("/issues/{}", issue_id))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(archive.contains("Spring edition for you"));
+    assert!(issue.contains("Spring edition for you"));
+    assert!(issue.contains("<p>Hi there, sent to . {{ x }}</p>"));
+}
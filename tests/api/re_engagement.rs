@@ -0,0 +1,210 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+use zero2prod::configuration::{get_configuration, ReEngagementSettings};
+use zero2prod::re_engagement::re_engage_inactive_subscribers;
+use zero2prod::signing::UrlSigner;
+use zero2prod::testing::{run_worker_once, FakeEmailSender, IssueFixture, SubscriberFixture};
+
+const URSULA: &str = "ursula@example.com";
+const GED: &str = "ged@earthsea.org";
+
+fn settings() -> ReEngagementSettings {
+    ReEngagementSettings {
+        inactive_issues: 2,
+        grace_period_days: 14,
+        subject: "Still there?".into(),
+        message: "Do you still want to hear from us?".into(),
+    }
+}
+
+/// Deliver `count` issues to every confirmed subscriber.
+async fn deliver_issues(app: &TestApp, count: usize) -> Vec<Uuid> {
+    let mut issue_ids = Vec::new();
+    for _ in 0..count {
+        issue_ids.push(IssueFixture::default().publish(&app.db_pool).await.unwrap());
+        run_worker_once(&app.db_pool, &FakeEmailSender::default(), &app.address)
+            .await
+            .unwrap();
+    }
+    issue_ids
+}
+
+async fn record_open(app: &TestApp, recipient: &str, issue_id: Uuid) {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_provider_events (event_id, record_type, recipient, payload, received_at)
+        VALUES ($1, 'Open', $2, jsonb_build_object('Tag', $3::text), now())
+        "#,
+        Uuid::new_v4().to_string(),
+        recipient,
+        issue_id.to_string()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn run_campaign(app: &TestApp, email_sender: &FakeEmailSender) -> u64 {
+    let signer = UrlSigner::new(&get_configuration().unwrap().signing);
+    re_engage_inactive_subscribers(
+        &app.db_pool,
+        email_sender,
+        &signer,
+        &app.address,
+        &settings(),
+        None,
+    )
+    .await
+    .unwrap()
+}
+
+async fn subscription_status(app: &TestApp, email: &str) -> (String, Option<String>) {
+    let r = sqlx::query!(
+        r#"SELECT status::text AS "status!", unsubscribe_reason FROM subscriptions WHERE email = $1"#,
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    (r.status, r.unsubscribe_reason)
+}
+
+#[tokio::test]
+async fn subscribers_who_opened_none_of_the_last_issues_are_asked_whether_to_stay() {
+    let app = spawn_app().await;
+    for email in [URSULA, GED] {
+        SubscriberFixture::confirmed()
+            .with_email(email)
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    let issue_ids = deliver_issues(&app, 2).await;
+    record_open(&app, GED, issue_ids[1]).await;
+    let email_sender = FakeEmailSender::default();
+
+    assert_eq!(run_campaign(&app, &email_sender).await, 1);
+
+    let sent = email_sender.sent().clone();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].recipient, URSULA);
+    assert_eq!(sent[0].subject, "Still there?");
+    assert!(sent[0].text_content.contains(&format!(
+        "{}/subscriptions/stay?subscription_token=",
+        app.address
+    )));
+    // They are only asked once.
+    assert_eq!(run_campaign(&app, &email_sender).await, 0);
+}
+
+#[tokio::test]
+async fn subscribers_with_fewer_issues_than_the_threshold_are_not_asked() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_email(URSULA)
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    deliver_issues(&app, 1).await;
+    let email_sender = FakeEmailSender::default();
+
+    assert_eq!(run_campaign(&app, &email_sender).await, 0);
+    assert!(email_sender.sent().is_empty());
+}
+
+#[tokio::test]
+async fn following_the_link_keeps_the_subscriber() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_email(URSULA)
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    deliver_issues(&app, 2).await;
+    let email_sender = FakeEmailSender::default();
+    run_campaign(&app, &email_sender).await;
+    let text_content = email_sender.sent()[0].text_content.clone();
+    let stay_link = text_content.rsplit(' ').next().unwrap();
+
+    let response = reqwest::get(stay_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The issues delivered before they answered no longer count.
+    assert_eq!(run_campaign(&app, &email_sender).await, 0);
+    assert_eq!(subscription_status(&app, URSULA).await.0, "confirmed");
+    assert_eq!(email_sender.sent().len(), 1);
+}
+
+#[tokio::test]
+async fn the_stay_link_must_be_signed() {
+    let app = spawn_app().await;
+    let subscriber = SubscriberFixture::confirmed()
+        .with_email(URSULA)
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/stay?subscription_token={}",
+        app.address,
+        subscriber.subscription_token.as_ref()
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn subscribers_who_did_not_answer_are_unsubscribed_after_the_grace_period() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_email(URSULA)
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    deliver_issues(&app, 2).await;
+    let email_sender = FakeEmailSender::default();
+    run_campaign(&app, &email_sender).await;
+
+    // Still within the grace period.
+    run_campaign(&app, &email_sender).await;
+    assert_eq!(subscription_status(&app, URSULA).await.0, "confirmed");
+
+    sqlx::query!("UPDATE subscriptions SET re_engagement_sent_at = now() - interval '15 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(run_campaign(&app, &email_sender).await, 1);
+
+    assert_eq!(
+        subscription_status(&app, URSULA).await,
+        ("unsubscribed".into(), Some("inactive".into()))
+    );
+}
+
+#[tokio::test]
+async fn subscribers_who_open_an_issue_during_the_grace_period_stay() {
+    // Arrange
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_email(URSULA)
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    let issue_ids = deliver_issues(&app, 2).await;
+    let email_sender = FakeEmailSender::default();
+    run_campaign(&app, &email_sender).await;
+    sqlx::query!("UPDATE subscriptions SET re_engagement_sent_at = now() - interval '15 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    record_open(&app, URSULA, issue_ids[0]).await;
+
+    // Act
+    run_campaign(&app, &email_sender).await;
+
+    // Assert
+    assert_eq!(subscription_status(&app, URSULA).await.0, "confirmed");
+    assert_eq!(email_sender.sent().len(), 1);
+}
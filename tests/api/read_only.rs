@@ -0,0 +1,108 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const SUBSCRIBER: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+async fn mock_confirmation_email(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_switch_read_only_mode() {
+    let app = spawn_app().await;
+    mock_confirmation_email(&app).await;
+
+    assert_is_redirect_to(&app.post_read_only(true).await, "/login");
+    assert_eq!(
+        app.post_subscriptions(SUBSCRIBER.into()).await.status(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn only_operators_can_switch_read_only_mode() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    mock_confirmation_email(&app).await;
+
+    // Act
+    let response = app.post_read_only(true).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        app.post_subscriptions(SUBSCRIBER.into()).await.status(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn writes_are_rejected_while_reads_are_served_in_read_only_mode() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.make_operator().await;
+
+    assert_is_redirect_to(&app.post_read_only(true).await, "/admin/tools");
+
+    let response = app.post_subscriptions(SUBSCRIBER.into()).await;
+    assert_eq!(response.status().as_u16(), 503);
+    assert!(response.text().await.unwrap().contains("read-only mode"));
+    for path in ["/health_check", "/issues", "/admin/dashboard"] {
+        let response = app
+            .api_client
+            .get(format!("{}{}", app.address, path))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200, "{}", path);
+    }
+    assert!(app.get_tools_html().await.contains("switched on by"));
+}
+
+#[tokio::test]
+async fn writes_are_accepted_again_once_read_only_mode_is_switched_off() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.make_operator().await;
+    app.post_read_only(true).await;
+    mock_confirmation_email(&app).await;
+
+    assert_is_redirect_to(&app.post_read_only(false).await, "/admin/tools");
+
+    assert_eq!(
+        app.post_subscriptions(SUBSCRIBER.into()).await.status(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn read_only_mode_set_in_the_configuration_cannot_be_switched_off() {
+    let app = spawn_app_with(|c| c.application.read_only = true).await;
+    app.do_login().await;
+    app.make_operator().await;
+
+    app.post_read_only(false).await;
+    assert!(app
+        .get_tools_html()
+        .await
+        .contains("Read-only mode is set in the configuration"));
+
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", app.address))
+        .header("Accept", "application/json")
+        .form(&[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 503);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "read_only");
+}
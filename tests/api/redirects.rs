@@ -0,0 +1,90 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn get(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", app.address, path))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_the_redirects() {
+    let app = spawn_app().await;
+
+    assert_is_redirect_to(&app.get_redirects().await, "/login");
+    assert_is_redirect_to(&app.post_redirect("/p/first", "/").await, "/login");
+    assert_is_redirect_to(&app.post_delete_redirect("/p/first").await, "/login");
+    assert_eq!(get(&app, "/p/first").await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn moved_paths_redirect_permanently_to_their_new_location() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    let response = app
+        .post_redirect("/p/first-issue", "https://example.com/issues/1")
+        .await;
+    assert_is_redirect_to(&response, "/admin/redirects");
+    let html_page = app.get_redirects_html().await;
+    assert!(html_page.contains("/p/first-issue now redirects to https://example.com/issues/1."));
+
+    let response = get(&app, "/p/first-issue").await;
+    assert_eq!(response.status().as_u16(), 301);
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/issues/1"
+    );
+    assert_eq!(get(&app, "/p/second-issue").await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn redirects_cannot_shadow_a_route() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    sqlx::query!(
+        "INSERT INTO redirects (tenant_id, from_path, to_location, created_at)
+        SELECT tenant_id, '/health_check', '/', now() FROM tenants"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(get(&app, "/health_check").await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn invalid_redirects_are_rejected() {
+    let app = spawn_app().await;
+    app.do_login().await;
+
+    for (from_path, to_location) in [
+        ("p/first", "/"),
+        ("/admin/dashboard", "/"),
+        ("/p/first", "javascript:alert(1)"),
+    ] {
+        let response = app.post_redirect(from_path, to_location).await;
+        assert_is_redirect_to(&response, "/admin/redirects");
+    }
+
+    let html_page = app.get_redirects_html().await;
+    assert!(html_page.contains("No redirect yet."));
+    assert_eq!(get(&app, "/p/first").await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn removed_redirects_are_no_longer_followed() {
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.post_redirect("/p/first", "/archive").await;
+
+    let response = app.post_delete_redirect("/p/first").await;
+    assert_is_redirect_to(&response, "/admin/redirects");
+    assert!(app
+        .get_redirects_html()
+        .await
+        .contains("/p/first no longer redirects."));
+
+    assert_eq!(get(&app, "/p/first").await.status().as_u16(), 404);
+}
@@ -0,0 +1,292 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+use zero2prod::configuration::RetentionSettings;
+use zero2prod::import::{import_subscribers, ImportedStatus, ImportedSubscriber};
+use zero2prod::retention::apply_retention_policy;
+use zero2prod::tenancy::TenantId;
+use zero2prod::testing::IssueFixture;
+
+fn policy() -> RetentionSettings {
+    RetentionSettings {
+        delivery_attempts_days: 30,
+        unsubscribed_subscribers_days: 30,
+        engagement_events_days: 30,
+        dry_run: false,
+    }
+}
+
+/// An unsubscribed subscriber who received an issue, and opened and bounced it, each of
+/// `days_ago`.
+async fn insert_history(app: &TestApp, issue_id: Uuid, email: &str, days_ago: i32) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (
+            id, email, name, subscribed_at, status, unsubscribed_at
+        )
+        VALUES (
+            $1, $2, 'Ursula', now() - make_interval(days => $3 + 10), 'unsubscribed',
+            now() - make_interval(days => $3)
+        )
+        "#,
+        Uuid::new_v4(),
+        email,
+        days_ago
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
+            newsletter_issue_id, subscriber_email, outcome, attempted_at
+        )
+        VALUES ($1, $2, 'delivered', now() - make_interval(days => $3))
+        "#,
+        issue_id,
+        email,
+        days_ago
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    for record_type in ["Open", "Bounce"] {
+        sqlx::query!(
+            r#"
+            INSERT INTO email_provider_events (
+                event_id, record_type, recipient, payload, received_at
+            )
+            VALUES ($1, $2, $3, '{}', now() - make_interval(days => $4))
+            "#,
+            Uuid::new_v4().to_string(),
+            record_type,
+            email,
+            days_ago
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn the_data_past_its_retention_is_deleted_or_anonymized() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    insert_history(&app, issue_id, "old@example.com", 40).await;
+    insert_history(&app, issue_id, "recent@example.com", 2).await;
+
+    // Act
+    let report = apply_retention_policy(&app.db_pool, &policy(), false)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report.deleted_delivery_attempts, 1);
+    assert_eq!(report.anonymized_subscribers, 1);
+    assert_eq!(report.deleted_engagement_events, 1);
+    let subscribers = sqlx::query!("SELECT email, name FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers[0].email.starts_with("anonymized+"));
+    assert_eq!(subscribers[0].name, None);
+    assert_eq!(subscribers[1].email, "recent@example.com");
+    let deliveries = sqlx::query!("SELECT subscriber_email FROM issue_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].subscriber_email, "recent@example.com");
+    let events = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM email_provider_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(events.n, 3);
+}
+
+#[tokio::test]
+async fn a_dry_run_reports_without_changing_anything() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    insert_history(&app, issue_id, "old@example.com", 40).await;
+
+    // Act
+    let report = apply_retention_policy(&app.db_pool, &policy(), true)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report.total(), 3);
+    let subscriber = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.email, "old@example.com");
+}
+
+#[tokio::test]
+async fn rules_set_to_zero_days_keep_the_data_forever() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    insert_history(&app, issue_id, "old@example.com", 400).await;
+    let policy = RetentionSettings {
+        delivery_attempts_days: 0,
+        unsubscribed_subscribers_days: 0,
+        ..policy()
+    };
+
+    // Act
+    let report = apply_retention_policy(&app.db_pool, &policy, false)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report.deleted_delivery_attempts, 0);
+    assert_eq!(report.anonymized_subscribers, 0);
+    assert_eq!(report.deleted_engagement_events, 1);
+}
+
+#[tokio::test]
+async fn the_address_of_anonymized_subscribers_is_scrubbed_everywhere() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (
+            id, email, name, subscribed_at, status, unsubscribed_at, unsubscribe_reason
+        )
+        VALUES (
+            $1, 'old@example.com', 'Ursula', now() - make_interval(days => 50),
+            'unsubscribed', now() - make_interval(days => 40), 'bounced'
+        )
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (
+            subscriber_id, action, ip_address, user_agent, recorded_at
+        )
+        VALUES ($1, 'signup', '127.0.0.1', 'Mozilla/5.0', now() - make_interval(days => 50))
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO events (event_id, event_type, payload, occurred_at)
+        VALUES ($1, 'subscriber_unsubscribed', $2, now() - make_interval(days => 40))
+        "#,
+        Uuid::new_v4(),
+        serde_json::json!({
+            "type": "subscriber_unsubscribed",
+            "subscriber_id": subscriber_id,
+            "email": "old@example.com",
+            "reason": "bounced",
+        })
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_provider_events (
+            event_id, record_type, recipient, payload, received_at
+        )
+        VALUES ($1, 'Bounce', 'Old@example.com', $2, now() - make_interval(days => 40))
+        "#,
+        Uuid::new_v4().to_string(),
+        serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "HardBounce",
+            "Email": "Old@example.com",
+            "Tag": issue_id.to_string(),
+            "Details": "smtp;550 5.1.1 The email account that you tried to reach does not exist",
+        })
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    apply_retention_policy(&app.db_pool, &policy(), false)
+        .await
+        .unwrap();
+
+    // Assert
+    let consent = sqlx::query!("SELECT ip_address, user_agent FROM consent_records")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(consent.ip_address, None);
+    assert_eq!(consent.user_agent, None);
+    let event =
+        sqlx::query!("SELECT payload FROM events WHERE event_type = 'subscriber_unsubscribed'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert!(event.payload["email"]
+        .as_str()
+        .unwrap()
+        .starts_with("anonymized+"));
+    let bounce = sqlx::query!("SELECT recipient, payload FROM email_provider_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(bounce.recipient.unwrap().starts_with("anonymized+"));
+    assert_eq!(
+        bounce.payload,
+        serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "HardBounce",
+            "Tag": issue_id.to_string(),
+        })
+    );
+}
+
+#[tokio::test]
+async fn anonymized_subscribers_who_bounced_are_not_imported_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    insert_history(&app, issue_id, "old@example.com", 40).await;
+    sqlx::query!("UPDATE subscriptions SET unsubscribe_reason = 'bounced'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    apply_retention_policy(&app.db_pool, &policy(), false)
+        .await
+        .unwrap();
+    let records = vec![ImportedSubscriber {
+        email: "OLD@example.com".into(),
+        name: None,
+        status: ImportedStatus::Confirmed,
+        tags: vec![],
+    }];
+
+    // Act
+    let report = import_subscribers(
+        &app.db_pool,
+        TenantId::DEFAULT,
+        records,
+        false,
+        &app.subscriber_settings,
+        None,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(report.imported_confirmed, 0);
+    assert_eq!(report.excluded.get("suppressed"), Some(&1));
+}
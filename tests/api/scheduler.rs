@@ -0,0 +1,217 @@
+use crate::helpers::{spawn_app, TestApp};
+use std::time::Duration;
+use uuid::Uuid;
+use zero2prod::configuration::get_configuration;
+use zero2prod::idempotency::delete_expired_keys;
+use zero2prod::pii::{encrypt_subscribers, PiiCipher};
+use zero2prod::repositories::{prune_pending_subscriptions, purge_deleted_subscribers};
+use zero2prod::scheduler::build_scheduler;
+
+async fn insert_subscriber(app: &TestApp, email: &str, status: &str, days_ago: i32) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'Subscriber', now() - make_interval(days => $3), $4::text::subscription_status)
+        "#,
+        subscriber_id,
+        email,
+        days_ago,
+        status
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)",
+        Uuid::new_v4().to_string(),
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+#[tokio::test]
+async fn only_the_expired_pending_subscriptions_are_pruned() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "expired@example.com", "pending_confirmation", 31).await;
+    insert_subscriber(&app, "recent@example.com", "pending_confirmation", 2).await;
+    insert_subscriber(&app, "confirmed@example.com", "confirmed", 90).await;
+
+    let pruned = prune_pending_subscriptions(&app.db_pool, Duration::from_secs(30 * 24 * 3600))
+        .await
+        .unwrap();
+
+    assert_eq!(pruned, 1);
+    let emails: Vec<String> = sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect();
+    assert_eq!(emails, ["confirmed@example.com", "recent@example.com"]);
+}
+
+#[tokio::test]
+async fn deleted_subscribers_are_purged_once_their_retention_elapses() {
+    let app = spawn_app().await;
+    for (email, days_ago) in [("old@example.com", 31), ("recent@example.com", 2)] {
+        let subscriber_id = insert_subscriber(&app, email, "confirmed", 90).await;
+        sqlx::query!(
+            "UPDATE subscriptions SET deleted_at = now() - make_interval(days => $2) WHERE id = $1",
+            subscriber_id,
+            days_ago
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    insert_subscriber(&app, "kept@example.com", "confirmed", 90).await;
+
+    let purged = purge_deleted_subscribers(&app.db_pool, Duration::from_secs(30 * 24 * 3600))
+        .await
+        .unwrap();
+
+    assert_eq!(purged, 1);
+    let emails: Vec<String> = sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect();
+    assert_eq!(emails, ["kept@example.com", "recent@example.com"]);
+}
+
+#[tokio::test]
+async fn only_the_expired_idempotency_keys_are_deleted() {
+    let app = spawn_app().await;
+    let configuration = get_configuration().unwrap();
+    for (key, hours_ago) in [("old", 48), ("fresh", 1)] {
+        sqlx::query!(
+            r#"
+            INSERT INTO idempotency (user_id, idempotency_key, created_at)
+            VALUES ($1, $2, now() - make_interval(hours => $3))
+            "#,
+            app.test_user.user_id,
+            key,
+            hours_ago
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    let deleted = delete_expired_keys(&app.db_pool, &configuration.idempotency)
+        .await
+        .unwrap();
+
+    assert_eq!(deleted, 1);
+    let keys = sqlx::query!("SELECT idempotency_key FROM idempotency")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].idempotency_key, "fresh");
+}
+
+#[tokio::test]
+async fn disabled_jobs_are_not_scheduled() {
+    let app = spawn_app().await;
+    let mut configuration = get_configuration().unwrap();
+    configuration.scheduler.prune_pending_subscriptions.enabled = false;
+
+    let scheduler = build_scheduler(app.db_pool.clone(), &configuration);
+
+    let jobs: Vec<_> = scheduler.jobs().map(|job| job.name()).collect();
+    assert_eq!(
+        jobs,
+        [
+            "purge_deleted_subscribers",
+            "clean_up_idempotency_keys",
+            "deliver_outbox_emails",
+            "send_weekly_reports"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn sealed_subscribers_keep_nothing_in_the_clear() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "Ursula@example.com", "confirmed", 0).await;
+    let key = |byte: u8| secrecy::Secret::new(base64::encode([byte; 32]));
+    let cipher = PiiCipher::new(&key(1), &key(2)).unwrap();
+
+    // Act
+    let encrypted = encrypt_subscribers(&app.db_pool, &cipher).await.unwrap();
+
+    // Assert
+    assert_eq!(encrypted, 1);
+    let saved = sqlx::query!(
+        "SELECT email, name, email_encrypted, name_encrypted, email_blind_index FROM subscriptions"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        saved.email,
+        format!("{}@example.com.encrypted.invalid", subscriber_id)
+    );
+    assert!(saved.name.is_none());
+    let email = cipher
+        .decrypt(subscriber_id, &saved.email_encrypted.unwrap())
+        .unwrap();
+    assert_eq!(email, "Ursula@example.com");
+    let name = cipher
+        .decrypt(subscriber_id, &saved.name_encrypted.unwrap())
+        .unwrap();
+    assert_eq!(name, "Subscriber");
+    assert_eq!(
+        saved.email_blind_index.unwrap(),
+        cipher.blind_index("ursula@example.com")
+    );
+    assert_eq!(encrypt_subscribers(&app.db_pool, &cipher).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn sealed_subscribers_are_sealed_again_once_their_name_is_set_in_the_clear() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ursula@example.com", "confirmed", 0).await;
+    let key = |byte: u8| secrecy::Secret::new(base64::encode([byte; 32]));
+    let cipher = PiiCipher::new(&key(1), &key(2)).unwrap();
+    encrypt_subscribers(&app.db_pool, &cipher).await.unwrap();
+
+    // Act
+    sqlx::query!("UPDATE subscriptions SET name = 'Le Guin'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let saved = sqlx::query!("SELECT email_encrypted, name_encrypted FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.email_encrypted.is_some());
+    assert!(saved.name_encrypted.is_none());
+    let encrypted = encrypt_subscribers(&app.db_pool, &cipher).await.unwrap();
+
+    // Assert
+    assert_eq!(encrypted, 1);
+    let saved = sqlx::query!("SELECT name, email_encrypted, name_encrypted FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.name.is_none());
+    let email = cipher
+        .decrypt(subscriber_id, &saved.email_encrypted.unwrap())
+        .unwrap();
+    assert_eq!(email, "ursula@example.com");
+    let name = cipher
+        .decrypt(subscriber_id, &saved.name_encrypted.unwrap())
+        .unwrap();
+    assert_eq!(name, "Le Guin");
+}
@@ -0,0 +1,25 @@
+use crate::helpers::spawn_app;
+use zero2prod::seed::seed;
+
+#[tokio::test]
+async fn seeding_signs_up_subscribers_and_queues_issues_for_the_confirmed_ones() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let report = seed(&app.db_pool, 20, 2).await.unwrap();
+
+    // Assert
+    let stored = report.confirmed_subscribers + report.pending_subscribers;
+    assert_eq!(stored + report.already_subscribed, 20);
+    let subscribers = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.count as usize, stored);
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count as usize, 2 * report.confirmed_subscribers);
+}
@@ -0,0 +1,283 @@
+use crate::helpers::{configure_database, log_handle, test_configuration};
+use secrecy::Secret;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::Settings;
+use zero2prod::startup::Application;
+
+async fn settings_with_startup_checks(email_server: &MockServer) -> Settings {
+    let mut configuration = test_configuration(email_server);
+    configuration.application.startup_checks = true;
+    configure_database(&configuration.database).await;
+    configuration
+}
+
+#[tokio::test]
+async fn startup_checks_pass_when_all_dependencies_are_healthy() {
+    let email_server = MockServer::start().await;
+    let configuration = settings_with_startup_checks(&email_server).await;
+
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&email_server)
+        .await;
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    assert!(outcome.is_ok());
+}
+
+#[tokio::test]
+async fn startup_checks_fail_when_the_email_provider_rejects_the_token() {
+    let email_server = MockServer::start().await;
+    let configuration = settings_with_startup_checks(&email_server).await;
+
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(1)
+        .mount(&email_server)
+        .await;
+
+    let error = Application::build(configuration, log_handle())
+        .await
+        .err()
+        .expect("Startup checks should have failed");
+
+    assert!(error.to_string().contains("email provider"));
+}
+
+#[tokio::test]
+async fn startup_checks_fail_when_migrations_are_pending() {
+    let email_server = MockServer::start().await;
+    let configuration = settings_with_startup_checks(&email_server).await;
+
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&email_server)
+        .await;
+    let pool = sqlx::PgPool::connect_with(configuration.database.with_db())
+        .await
+        .unwrap();
+    sqlx::query(
+        "DELETE FROM _sqlx_migrations WHERE version = (SELECT MAX(version) FROM _sqlx_migrations)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let error = Application::build(configuration, log_handle())
+        .await
+        .err()
+        .expect("Startup checks should have failed");
+
+    let error = error.to_string();
+    assert!(error.contains("postgres"));
+    assert!(error.contains("have not been applied"));
+}
+
+/// A migration of a later release, which this code does not know.
+async fn apply_newer_migration(configuration: &Settings, breaks_older_code: bool) {
+    let pool = sqlx::PgPool::connect_with(configuration.database.with_db())
+        .await
+        .unwrap();
+    sqlx::query(
+        r#"
+        INSERT INTO _sqlx_migrations
+            (version, description, success, checksum, execution_time)
+        VALUES (99990101000000, 'from a later release', true, '\x00', 0)
+        "#,
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    if breaks_older_code {
+        sqlx::query("SELECT breaks_older_code(99990101000000)")
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
+
+async fn mock_email_provider(email_server: &MockServer) {
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn the_application_starts_against_the_additive_migrations_of_a_later_release() {
+    let email_server = MockServer::start().await;
+    let configuration = settings_with_startup_checks(&email_server).await;
+    mock_email_provider(&email_server).await;
+    apply_newer_migration(&configuration, false).await;
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    assert!(outcome.is_ok());
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_against_a_migration_which_breaks_it() {
+    let email_server = MockServer::start().await;
+    let configuration = settings_with_startup_checks(&email_server).await;
+    mock_email_provider(&email_server).await;
+    apply_newer_migration(&configuration, true).await;
+
+    let error = Application::build(configuration, log_handle())
+        .await
+        .err()
+        .expect("Startup checks should have failed");
+
+    let error = error.to_string();
+    assert!(error.contains("postgres"));
+    assert!(error.contains("too recent for this code"), "{}", error);
+}
+
+#[tokio::test]
+async fn startup_checks_report_every_failing_dependency() {
+    let email_server = MockServer::start().await;
+    let mut configuration = settings_with_startup_checks(&email_server).await;
+    configuration.redis_uri = Secret::new("redis://127.0.0.1:1".to_string());
+
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&email_server)
+        .await;
+
+    let error = Application::build(configuration, log_handle())
+        .await
+        .err()
+        .expect("Startup checks should have failed");
+
+    let error = error.to_string();
+    assert!(error.contains("2 startup check(s) failed"));
+    assert!(error.contains("redis"));
+    assert!(error.contains("email provider"));
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_with_an_invalid_idempotency_configuration() {
+    let email_server = MockServer::start().await;
+    let mut configuration = test_configuration(&email_server);
+    configuration.idempotency.pending_lease_timeout_milliseconds =
+        configuration.idempotency.key_ttl_seconds * 1000;
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    let error = outcome.err().unwrap().to_string();
+    assert!(
+        error.contains("pending_lease_timeout_milliseconds"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_without_database_connections() {
+    let email_server = MockServer::start().await;
+    let mut configuration = test_configuration(&email_server);
+    configuration.database.max_connections = 0;
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    let error = outcome.err().unwrap().to_string();
+    assert!(error.contains("database.max_connections"), "{}", error);
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_with_a_zero_scheduler_interval() {
+    let email_server = MockServer::start().await;
+    let mut configuration = test_configuration(&email_server);
+    configuration
+        .scheduler
+        .clean_up_idempotency_keys
+        .interval_seconds = 0;
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    let error = outcome.err().unwrap().to_string();
+    assert!(
+        error.contains("scheduler.clean_up_idempotency_keys.interval_seconds"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_without_a_postal_address_in_the_footer() {
+    let email_server = MockServer::start().await;
+    let mut configuration = test_configuration(&email_server);
+    configuration.email_footer.postal_address = " ".into();
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    let error = outcome.err().unwrap().to_string();
+    assert!(error.contains("email_footer.postal_address"), "{}", error);
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_without_a_re_engagement_threshold() {
+    let email_server = MockServer::start().await;
+    let mut configuration = test_configuration(&email_server);
+    configuration.re_engagement.inactive_issues = 0;
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    let error = outcome.err().unwrap().to_string();
+    assert!(error.contains("re_engagement.inactive_issues"), "{}", error);
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_with_an_invalid_accent_color() {
+    let email_server = MockServer::start().await;
+    let mut configuration = test_configuration(&email_server);
+    configuration.branding.accent_color = "red; background: url(evil)".into();
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    let error = outcome.err().unwrap().to_string();
+    assert!(error.contains("branding.accent_color"), "{}", error);
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_with_an_invalid_public_base_url() {
+    let email_server = MockServer::start().await;
+    let mut configuration = test_configuration(&email_server);
+    configuration.application.public_base_url = "news.example.com".into();
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    let error = outcome.err().unwrap().to_string();
+    assert!(error.contains("application.public_base_url"), "{}", error);
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_without_a_url_signing_key() {
+    let email_server = MockServer::start().await;
+    let mut configuration = test_configuration(&email_server);
+    configuration.signing.keys.clear();
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    let error = outcome.err().unwrap().to_string();
+    assert!(error.contains("signing.keys"), "{}", error);
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_with_an_s3_bucket_but_no_credentials() {
+    let email_server = MockServer::start().await;
+    let mut configuration = test_configuration(&email_server);
+    configuration.backups.s3.bucket = "newsletter-backups".into();
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    let error = outcome.err().unwrap().to_string();
+    assert!(error.contains("backups.s3"), "{}", error);
+}
@@ -1,6 +1,8 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+use sha2::{Digest, Sha256};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::SubscriptionStatus;
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
@@ -33,14 +35,45 @@ async fn subscribe_persists_the_new_subscriber() {
 
     app.post_subscriptions(body.into()).await;
 
-    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
+    let saved = sqlx::query!(
+        r#"SELECT email, name, status AS "status: SubscriptionStatus" FROM subscriptions"#,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
+
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name.as_deref(), Some("le guin"));
+    assert_eq!(saved.status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn subscribe_stores_the_name_with_collapsed_whitespace() {
+    let app = spawn_app().await;
+    let body = "name=%20%20le%09%20guin%20&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
         .fetch_one(&app.db_pool)
         .await
         .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name.as_deref(), Some("le guin"));
+}
 
-    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
-    assert_eq!(saved.name, "le guin");
-    assert_eq!(saved.status, "pending_confirmation");
+#[tokio::test]
+async fn subscribe_rejects_names_longer_than_the_configured_maximum() {
+    let app = spawn_app_with(|c| c.subscribers.max_name_length = 5).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(400, response.status().as_u16());
 }
 
 #[tokio::test]
@@ -51,6 +84,14 @@ async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
         ("name=&email=ursula_le_guin%40gmail.com", "empty name"),
         ("name=Ursula&email=", "empty email"),
         ("name=Ursula&email=definitely-not-an-email", "invalid email"),
+        (
+            "name=Ursula%E2%80%AEniuG&email=ursula_le_guin%40gmail.com",
+            "bidi override in the name",
+        ),
+        (
+            "name=Ursula%07&email=ursula_le_guin%40gmail.com",
+            "control character in the name",
+        ),
     ];
 
     for (body, description) in test_cases {
@@ -87,6 +128,48 @@ async fn subscribe_return_a_400_when_data_is_missing() {
     }
 }
 
+#[tokio::test]
+async fn the_name_is_optional_when_names_are_not_required() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscribers.require_name = false).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name, None);
+    // The confirmation email greets them without a name.
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("Welcome. You&#x27;re almost there."));
+}
+
+#[tokio::test]
+async fn the_subscribe_form_does_not_ask_for_a_name_unless_required() {
+    let app = spawn_app_with(|c| c.subscribers.require_name = false).await;
+
+    let html_page = app.get_subscribe_form_html().await;
+
+    assert!(!html_page.contains(r#"name="name""#));
+    assert!(html_page.contains(r#"name="email""#));
+}
+
 #[tokio::test]
 async fn subscribe_sends_a_confirmation_email_for_valid_data() {
     let app = spawn_app().await;
@@ -119,7 +202,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
     app.post_subscriptions(body.into()).await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request, 3, 1);
+    let confirmation_links = app.get_confirmation_links(email_request, 3, 1);
 
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
     // Mock asserts on drop
@@ -184,8 +267,8 @@ async fn subscribe_twice_sends_same_confirmation_links() {
     let requests = &app.email_server.received_requests().await.unwrap();
     let email_request1 = &requests[0];
     let email_request2 = &requests[1];
-    let confirmation_links1 = app.get_confirmation_links(&email_request1, 3, 1);
-    let confirmation_links2 = app.get_confirmation_links(&email_request2, 3, 1);
+    let confirmation_links1 = app.get_confirmation_links(email_request1, 3, 1);
+    let confirmation_links2 = app.get_confirmation_links(email_request2, 3, 1);
 
     assert_eq!(confirmation_links1.html, confirmation_links2.html);
     assert_eq!(
@@ -195,12 +278,42 @@ async fn subscribe_twice_sends_same_confirmation_links() {
     // Mock asserts on drop
 }
 
+#[tokio::test]
+async fn concurrent_signups_for_the_same_email_settle_on_one_subscriber() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.local";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let responses =
+        futures_util::future::join_all((0..5).map(|_| app.post_subscriptions(body.into()))).await;
+
+    for response in responses {
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let saved = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM subscriptions) AS "subscribers!",
+            (SELECT COUNT(*) FROM subscription_tokens) AS "tokens!"
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.subscribers, 1);
+    assert_eq!(saved.tokens, 1);
+}
+
 #[tokio::test]
 async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.local";
 
-    sqlx::query!("ALTER TABLE subscriptions DROP COLUMN email")
+    sqlx::query!("ALTER TABLE subscriptions DROP COLUMN email CASCADE")
         .execute(&app.db_pool)
         .await
         .unwrap();
@@ -209,3 +322,428 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
 
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn the_confirmation_email_is_sent_in_the_language_of_the_visitor() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept-Language", "fr-CH, fr;q=0.9, en;q=0.8")
+        .form(&[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .starts_with("Merci pour votre inscription"));
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Bienvenue !");
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("Bienvenue, le guin. Vous y êtes presque."));
+    let confirmation_links = app.get_confirmation_links(email_request, 3, 1);
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+}
+
+#[tokio::test]
+async fn unsupported_languages_fall_back_to_the_default_locale() {
+    // Arrange
+    let app = spawn_app_with(|c| c.i18n.default_locale = "fr".into()).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept-Language", "de")
+        .form(&[("name", ""), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Veuillez vérifier votre nom et votre adresse e-mail."
+    );
+}
+
+#[tokio::test]
+async fn the_confirmation_email_is_sent_in_the_language_chosen_at_signup() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Accept-Language", "en")
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("locale", "fr"),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Bienvenue !");
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.locale.as_deref(), Some("fr"));
+}
+
+#[tokio::test]
+async fn subscribe_rejects_an_unsupported_locale() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("locale", "tlh"),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn the_subscribe_form_offers_the_supported_languages() {
+    let app = spawn_app().await;
+
+    let html_page = app
+        .api_client
+        .get(format!("{}/subscribe", &app.address))
+        .header("Accept-Language", "fr")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains(r#"<option value="en">English</option>"#));
+    assert!(html_page.contains(r#"<option value="fr" selected>Français</option>"#));
+}
+
+#[tokio::test]
+async fn the_subscribe_form_posts_to_the_subscriptions_endpoint() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_subscribe_form_html().await;
+
+    assert!(html_page.contains(r#"<form action="/subscriptions" method="post">"#));
+    assert!(html_page.contains(r#"name="name""#));
+    assert!(html_page.contains(r#"name="email""#));
+}
+
+#[tokio::test]
+async fn invalid_data_from_the_subscribe_form_is_reported_on_the_form() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscribe_form(&serde_json::json!({"name": "", "email": "ursula_le_guin@gmail.com"}))
+        .await;
+    assert_is_redirect_to(&response, "/subscribe");
+
+    let html_page = app.get_subscribe_form_html().await;
+    assert!(html_page.contains("Please enter your name"));
+    assert!(!html_page.contains("Please enter a valid email address."));
+
+    // The flash message is shown once.
+    let html_page = app.get_subscribe_form_html().await;
+    assert!(!html_page.contains("Please enter your name"));
+}
+
+#[tokio::test]
+async fn every_invalid_field_of_the_subscribe_form_is_reported() {
+    let app = spawn_app().await;
+
+    app.post_subscribe_form(&serde_json::json!({"name": "<Ursula>", "email": "ursula"}))
+        .await;
+
+    let html_page = app.get_subscribe_form_html().await;
+    assert!(html_page.contains("Please enter your name"));
+    assert!(html_page.contains("Please enter a valid email address."));
+}
+
+#[tokio::test]
+async fn a_subscription_from_the_subscribe_form_leads_to_the_pending_page() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscribe_form(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/subscribe/pending");
+
+    let html_page = app.get_subscribe_pending_html("").await;
+    assert!(html_page.contains("We have sent a confirmation link to u***@gmail.com."));
+    assert!(!html_page.contains("ursula_le_guin"));
+}
+
+#[tokio::test]
+async fn the_pending_page_does_not_show_text_from_its_url() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html_page = app
+        .get_subscribe_pending_html("?email=support%40evil.example%20-%20call%20us")
+        .await;
+
+    // Assert
+    assert!(html_page.contains("We have sent you a confirmation link."));
+    assert!(!html_page.contains("evil.example"));
+}
+
+#[tokio::test]
+async fn the_subscribe_form_asks_for_the_email_twice_if_configured() {
+    let app = spawn_app().await;
+    let html_page = app.get_subscribe_form_html().await;
+    assert!(!html_page.contains(r#"name="email_confirmation""#));
+
+    let app = spawn_app_with(|c| c.subscribers.confirm_email = true).await;
+    let html_page = app.get_subscribe_form_html().await;
+    assert!(html_page.contains(r#"name="email_confirmation""#));
+}
+
+#[tokio::test]
+async fn mismatched_email_addresses_are_reported_on_the_form() {
+    let app = spawn_app_with(|c| c.subscribers.confirm_email = true).await;
+
+    let response = app
+        .post_subscribe_form(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "email_confirmation": "ursula_le_guin@gmial.com"
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/subscribe");
+    let html_page = app.get_subscribe_form_html().await;
+    assert!(html_page.contains("The two email addresses do not match."));
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn matching_email_addresses_subscribe_regardless_of_case() {
+    let app = spawn_app_with(|c| c.subscribers.confirm_email = true).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscribe_form(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "email_confirmation": " Ursula_Le_Guin@gmail.com"
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/subscribe/pending");
+}
+
+#[tokio::test]
+async fn the_subscribe_form_asks_to_accept_the_policy_if_required() {
+    let app = spawn_app().await;
+    let html_page = app.get_subscribe_form_html().await;
+    assert!(!html_page.contains(r#"name="accepted_policy""#));
+
+    let app = spawn_app_with(|c| {
+        c.subscribers.consent_policy.required = true;
+        c.subscribers.consent_policy.version = "2022-05".into();
+    })
+    .await;
+    let html_page = app.get_subscribe_form_html().await;
+    assert!(html_page.contains(r#"name="accepted_policy" value="2022-05""#));
+}
+
+#[tokio::test]
+async fn signups_without_the_policy_accepted_are_rejected_when_it_is_required() {
+    let app = spawn_app_with(|c| {
+        c.subscribers.consent_policy.required = true;
+        c.subscribers.consent_policy.version = "2022-05".into();
+    })
+    .await;
+
+    for form in [
+        serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }),
+        serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "accepted_policy": "2022-01"
+        }),
+    ] {
+        let response = app.post_subscribe_form(&form).await;
+
+        assert_is_redirect_to(&response, "/subscribe");
+        let html_page = app.get_subscribe_form_html().await;
+        assert!(html_page.contains("Please accept the terms to subscribe."));
+    }
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn the_accepted_policy_version_is_recorded_with_the_signup() {
+    let app = spawn_app_with(|c| {
+        c.subscribers.consent_policy.required = true;
+        c.subscribers.consent_policy.version = "2022-05".into();
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscribe_form(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "accepted_policy": "2022-05"
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/subscribe/pending");
+    let saved = sqlx::query!("SELECT action, policy_version FROM consent_records")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.action, "signup");
+    assert_eq!(saved.policy_version.as_deref(), Some("2022-05"));
+}
+
+#[tokio::test]
+async fn the_signup_is_recorded_with_the_hash_of_the_form_the_subscriber_was_shown() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.subscribers.consent_policy.required = true;
+        c.subscribers.consent_policy.version = "2022-05".into();
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let html_page = app.get_subscribe_form_html().await;
+    let start = html_page.find("<form").unwrap();
+    let end = html_page.find("</form>").unwrap() + "</form>".len();
+    let form_hash = hex::encode(Sha256::digest(&html_page.as_bytes()[start..end]));
+
+    // Act
+    app.post_subscribe_form(&serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "accepted_policy": "2022-05"
+    }))
+    .await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT form_hash FROM consent_records")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.form_hash, Some(form_hash));
+}
+
+#[tokio::test]
+async fn a_forwarded_address_is_only_believed_from_a_trusted_proxy() {
+    for (trusted_proxies, ip_address) in [
+        (vec![], "127.0.0.1"),
+        (vec!["127.0.0.1".parse().unwrap()], "203.0.113.7"),
+    ] {
+        // Arrange
+        let app = spawn_app_with(|c| c.application.trusted_proxies = trusted_proxies).await;
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&app.email_server)
+            .await;
+
+        // Act
+        app.api_client
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Forwarded-For", "198.51.100.1, 203.0.113.7")
+            .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        let saved = sqlx::query!("SELECT ip_address FROM consent_records")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(saved.ip_address.as_deref(), Some(ip_address));
+    }
+}
@@ -1,7 +1,7 @@
 use crate::helpers::spawn_app;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
-use zero2prod::domain::SubscriptionToken;
+use zero2prod::domain::{SubscriptionStatus, SubscriptionToken};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -58,15 +58,17 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
 
     app.post_subscriptions(body.into()).await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request, 3, 1);
+    let confirmation_links = app.get_confirmation_links(email_request, 3, 1);
 
     let response = reqwest::get(confirmation_links.html).await.unwrap();
 
     assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<h1>Subscription confirmed</h1>"));
 }
 
 #[tokio::test]
-async fn the_link_returned_by_subscribe_returns_a_200_if_called_twice() {
+async fn the_link_returned_by_subscribe_only_works_once() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
@@ -79,11 +81,45 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called_twice() {
 
     app.post_subscriptions(body.into()).await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request, 3, 1);
+    let confirmation_links = app.get_confirmation_links(email_request, 3, 1);
 
-    reqwest::get(confirmation_links.html).await.unwrap();
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
     let response = reqwest::get(confirmation_links.plain_text).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response.text().await.unwrap().contains("Link already used"));
+}
+
+#[tokio::test]
+async fn subscribers_who_unsubscribed_can_confirm_again_after_signing_up() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request, 3, 1);
+    reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.post_subscriptions(body.into()).await;
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
     assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status::text FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status.as_deref(), Some("confirmed"));
 }
 
 #[tokio::test]
@@ -100,16 +136,18 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
 
     app.post_subscriptions(body.into()).await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request, 3, 1);
+    let confirmation_links = app.get_confirmation_links(email_request, 3, 1);
 
     reqwest::get(confirmation_links.html).await.unwrap();
 
-    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
-        .fetch_one(&app.db_pool)
-        .await
-        .expect("Failed to fetch saved subscription.");
+    let saved = sqlx::query!(
+        r#"SELECT email, name, status AS "status: SubscriptionStatus" FROM subscriptions"#,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
 
     assert_eq!(saved.email, "ursula_le_guin@gmail.com");
-    assert_eq!(saved.name, "le guin");
-    assert_eq!(saved.status, "confirmed")
+    assert_eq!(saved.name.as_deref(), Some("le guin"));
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed)
 }
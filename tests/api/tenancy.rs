@@ -0,0 +1,275 @@
+use crate::helpers::{
+    assert_is_redirect_to, configure_database, log_handle, spawn_app_with, test_configuration,
+    TestApp,
+};
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::authentication::create_user;
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::startup::Application;
+use zero2prod::tenancy::{create_tenant, NewTenant, TenantId};
+use zero2prod::testing::{IssueFixture, SubscriberFixture};
+
+const TENANT_HOST: &str = "news.example.com";
+const TENANT_SENDER: &str = "editor@news.example.com";
+
+async fn spawn_app_with_tenant() -> (TestApp, TenantId) {
+    let app = spawn_app_with(|c| c.tenancy.enabled = true).await;
+    let tenant_id = create_tenant(
+        &app.db_pool,
+        &NewTenant {
+            name: "Example News".into(),
+            hostname: TENANT_HOST.into(),
+            sender_email: Some(SubscriberEmail::parse(TENANT_SENDER.into()).unwrap()),
+            public_base_url: None,
+            chat_webhook_url: None,
+        },
+    )
+    .await
+    .unwrap();
+    (app, tenant_id)
+}
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn the_same_email_can_subscribe_to_each_tenant() {
+    let (app, tenant_id) = spawn_app_with_tenant().await;
+    mock_email_server(&app).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Host", TENANT_HOST)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let tenants: Vec<_> = sqlx::query!("SELECT tenant_id FROM subscriptions ORDER BY tenant_id")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| TenantId::from(r.tenant_id))
+        .collect();
+    assert_eq!(tenants, vec![TenantId::DEFAULT, tenant_id]);
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_sent_from_the_tenant_address() {
+    let (app, _) = spawn_app_with_tenant().await;
+    mock_email_server(&app).await;
+
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Host", TENANT_HOST)
+        .form(&[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["From"], TENANT_SENDER);
+}
+
+#[tokio::test]
+async fn issues_are_delivered_from_the_tenant_address_to_its_subscribers_only() {
+    let (app, tenant_id) = spawn_app_with_tenant().await;
+    mock_email_server(&app).await;
+    SubscriberFixture::confirmed()
+        .with_email("ursula@example.com")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    SubscriberFixture::confirmed()
+        .with_email("ged@earthsea.org")
+        .for_tenant(tenant_id)
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+
+    IssueFixture::default()
+        .for_tenant(tenant_id)
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["From"], TENANT_SENDER);
+    assert_eq!(body["To"], "ged@earthsea.org");
+}
+
+#[tokio::test]
+async fn admins_cannot_log_in_to_another_tenant() {
+    let (app, _) = spawn_app_with_tenant().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .header("Host", TENANT_HOST)
+        .form(&[
+            ("username", &app.test_user.username),
+            ("password", &app.test_user.password),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn each_tenant_names_its_own_admins() {
+    // Arrange
+    let (app, tenant_id) = spawn_app_with_tenant().await;
+    let password = Uuid::new_v4().to_string();
+    create_user(
+        &app.test_user.username,
+        Secret::new(password.clone()),
+        tenant_id,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .header("Host", TENANT_HOST)
+        .form(&[
+            ("username", &app.test_user.username),
+            ("password", &password),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_archive_lists_the_issues_of_the_tenant_only() {
+    let (app, tenant_id) = spawn_app_with_tenant().await;
+    IssueFixture::default()
+        .with_title("Default edition")
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+    IssueFixture::default()
+        .with_title("Tenant edition")
+        .for_tenant(tenant_id)
+        .publish(&app.db_pool)
+        .await
+        .unwrap();
+
+    let tenant_archive = app
+        .api_client
+        .get(format!("{}/api/v1/issues", &app.address))
+        .header("Host", TENANT_HOST)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let default_archive = app.get_archive("", None).await.text().await.unwrap();
+
+    assert!(tenant_archive.contains("Tenant edition"));
+    assert!(!tenant_archive.contains("Default edition"));
+    assert!(default_archive.contains("Default edition"));
+    assert!(!default_archive.contains("Tenant edition"));
+}
+
+#[tokio::test]
+async fn subscribers_are_sent_links_to_the_tenant_public_base_url() {
+    let (app, _) = spawn_app_with_tenant().await;
+    mock_email_server(&app).await;
+    create_tenant(
+        &app.db_pool,
+        &NewTenant {
+            name: "Brand".into(),
+            hostname: "admin.brand.example.org".into(),
+            sender_email: None,
+            public_base_url: Some("https://www.brand.example.org/news".into()),
+            chat_webhook_url: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    // Requests to the public domain are served for the tenant too.
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Host", "www.brand.example.org")
+        .form(&[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("https://www.brand.example.org/news/subscriptions/confirm?"));
+}
+
+#[tokio::test]
+async fn invalid_public_base_urls_are_rejected() {
+    let (app, _) = spawn_app_with_tenant().await;
+
+    for public_base_url in ["www.brand.example.org", "https://www.brand.example.org/"] {
+        let outcome = create_tenant(
+            &app.db_pool,
+            &NewTenant {
+                name: "Brand".into(),
+                hostname: "admin.brand.example.org".into(),
+                sender_email: None,
+                public_base_url: Some(public_base_url.into()),
+                chat_webhook_url: None,
+            },
+        )
+        .await;
+        assert!(outcome.is_err(), "{}", public_base_url);
+    }
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_with_an_invalid_tenant_public_base_url() {
+    let email_server = MockServer::start().await;
+    let mut configuration = test_configuration(&email_server);
+    configuration.tenancy.enabled = true;
+    let pool = configure_database(&configuration.database).await;
+    sqlx::query!(
+        "INSERT INTO tenants (tenant_id, name, public_base_url) VALUES ($1, 'Brand', 'brand')",
+        Uuid::new_v4()
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let outcome = Application::build(configuration, log_handle()).await;
+
+    let error = outcome.err().unwrap().to_string();
+    assert!(error.contains("tenant \"Brand\""), "{}", error);
+}
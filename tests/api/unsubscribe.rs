@@ -0,0 +1,218 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use zero2prod::domain::{SubscriptionStatus, SubscriptionToken};
+use zero2prod::testing::{
+    run_worker_once, FakeEmailSender, IssueFixture, StoredSubscriber, SubscriberFixture,
+};
+
+async fn confirmed_subscriber(app: &TestApp, email: &str) -> StoredSubscriber {
+    SubscriberFixture::confirmed()
+        .with_email(email)
+        .store(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn unsubscribe(app: &TestApp, subscriber: &StoredSubscriber, reason: &str) {
+    let response = app
+        .post_unsubscribe(&serde_json::json!({
+            "subscription_token": subscriber.subscription_token.as_ref(),
+            "reason": reason,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn issues_carry_the_unsubscribe_link_of_their_recipient() {
+    let app = spawn_app().await;
+    let subscriber = confirmed_subscriber(&app, "ursula@example.com").await;
+    IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let sender = FakeEmailSender::default();
+
+    run_worker_once(&app.db_pool, &sender, &app.address)
+        .await
+        .unwrap();
+
+    let link = format!(
+        "{}/unsubscribe?subscription_token={}",
+        app.address,
+        subscriber.subscription_token.as_ref()
+    );
+    let sent = sender.sent()[0].clone();
+    assert!(sent
+        .text_content
+        .contains(&format!("\n\nUnsubscribe: {}\n", link)));
+    assert!(sent
+        .html_content
+        .contains(&format!(r#"<a href="{}">Unsubscribe</a>"#, link)));
+}
+
+#[tokio::test]
+async fn the_unsubscribe_page_offers_an_optional_survey() {
+    let app = spawn_app().await;
+    let subscriber = confirmed_subscriber(&app, "ursula@example.com").await;
+
+    let response = app
+        .get_unsubscribe(subscriber.subscription_token.as_ref())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        r#"<input type="hidden" name="subscription_token" value="{}">"#,
+        subscriber.subscription_token.as_ref()
+    )));
+    for reason in ["too_frequent", "not_relevant", "other"] {
+        assert!(html_page.contains(&format!(
+            r#"<input type="radio" name="reason" value="{}">"#,
+            reason
+        )));
+    }
+    // Visiting the page alone does not unsubscribe.
+    let status =
+        sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .status;
+    assert_eq!(status, SubscriptionStatus::Confirmed);
+}
+
+#[tokio::test]
+async fn the_unsubscribe_page_rejects_invalid_tokens_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = app.get_unsubscribe("fake").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_no_longer_receive_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber = confirmed_subscriber(&app, "ursula@example.com").await;
+
+    // Act
+    unsubscribe(&app, &subscriber, "too_frequent").await;
+    IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let sender = FakeEmailSender::default();
+    run_worker_once(&app.db_pool, &sender, &app.address)
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(sender.sent().is_empty());
+    let saved =
+        sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus", unsubscribe_reason, unsubscribed_at FROM subscriptions"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Unsubscribed);
+    assert_eq!(saved.unsubscribe_reason.as_deref(), Some("too_frequent"));
+    assert!(saved.unsubscribed_at.is_some());
+}
+
+#[tokio::test]
+async fn deliveries_still_queued_for_an_unsubscribed_subscriber_are_dropped() {
+    let app = spawn_app().await;
+    let subscriber = confirmed_subscriber(&app, "ursula@example.com").await;
+    IssueFixture::default().publish(&app.db_pool).await.unwrap();
+
+    unsubscribe(&app, &subscriber, "").await;
+
+    let executed = run_worker_once(&app.db_pool, &FakeEmailSender::default(), &app.address)
+        .await
+        .unwrap();
+    assert_eq!(executed, 0);
+}
+
+#[tokio::test]
+async fn unsubscribing_with_an_unknown_token_is_a_401() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_unsubscribe(&serde_json::json!({
+            "subscription_token": SubscriptionToken::generate().as_ref(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn unsubscribing_with_an_unknown_reason_is_a_400() {
+    let app = spawn_app().await;
+    let subscriber = confirmed_subscriber(&app, "ursula@example.com").await;
+
+    let response = app
+        .post_unsubscribe(&serde_json::json!({
+            "subscription_token": subscriber.subscription_token.as_ref(),
+            "reason": "bored",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn an_old_confirmation_link_does_not_resubscribe() {
+    let app = spawn_app().await;
+    let subscriber = confirmed_subscriber(&app, "ursula@example.com").await;
+    unsubscribe(&app, &subscriber, "other").await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token={}",
+            app.address,
+            subscriber.subscription_token.as_ref()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Confirmation links are single-use: subscribing again is the way back.
+    assert_eq!(response.status().as_u16(), 410);
+    let saved = sqlx::query!(
+        r#"SELECT status AS "status: SubscriptionStatus", unsubscribe_reason FROM subscriptions"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Unsubscribed);
+    assert_eq!(saved.unsubscribe_reason.as_deref(), Some("other"));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_churn_report() {
+    let app = spawn_app().await;
+
+    let response = app.get_churn().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_churn_report_counts_unsubscribes_by_reason() {
+    let app = spawn_app().await;
+    for (email, reason) in [
+        ("ursula@example.com", "too_frequent"),
+        ("ged@example.com", "too_frequent"),
+        ("tenar@example.com", ""),
+    ] {
+        let subscriber = confirmed_subscriber(&app, email).await;
+        unsubscribe(&app, &subscriber, reason).await;
+    }
+    confirmed_subscriber(&app, "arha@example.com").await;
+    app.do_login().await;
+
+    let response = app.get_churn().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<td>Too frequent</td>\n<td>2</td>\n<td>2</td>"));
+    assert!(html_page.contains("<td>Not relevant</td>\n<td>0</td>\n<td>0</td>"));
+    assert!(html_page.contains("<td>No answer</td>\n<td>1</td>\n<td>1</td>"));
+    assert!(html_page.contains("<th>Total</th>\n<th>3</th>\n<th>3</th>"));
+}
@@ -0,0 +1,287 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app_with, TestApp, EMBED_ORIGIN};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::testing::SubscriberFixture;
+
+/// An app whose list is full with a single subscriber.
+async fn spawn_full_app() -> TestApp {
+    let app = spawn_app_with(|c| c.subscribers.max_subscribers = 1).await;
+    SubscriberFixture::confirmed()
+        .with_email("ged@example.com")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    app
+}
+
+async fn waitlisted_emails(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT email FROM waitlist ORDER BY joined_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect()
+}
+
+#[tokio::test]
+async fn signups_join_the_waitlist_once_the_list_is_full() {
+    let app = spawn_full_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("joined the waitlist"));
+    assert_eq!(waitlisted_emails(&app).await, ["ursula_le_guin@gmail.com"]);
+    let subscribers = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+}
+
+#[tokio::test]
+async fn browsers_are_sent_to_the_waitlist_page() {
+    let app = spawn_full_app().await;
+
+    let response = app
+        .post_subscribe_form(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/subscribe/waitlisted");
+    let html_page = app
+        .api_client
+        .get(format!("{}/subscribe/waitlisted", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The list is full"));
+}
+
+#[tokio::test]
+async fn known_subscribers_signing_up_again_are_not_waitlisted() {
+    let app = spawn_full_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=ged&email=ged%40example.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(waitlisted_emails(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_promote_a_waitlist_entry() {
+    let app = spawn_full_app().await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    let response = app
+        .post_promote_waitlist_entry("ursula_le_guin@gmail.com")
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(waitlisted_emails(&app).await, ["ursula_le_guin@gmail.com"]);
+}
+
+#[tokio::test]
+async fn promoted_entries_get_a_confirmation_link_despite_the_limit() {
+    let app = spawn_full_app().await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&locale=fr".into())
+        .await;
+    app.do_login().await;
+    assert!(app
+        .get_waitlist_html()
+        .await
+        .contains("ursula_le_guin@gmail.com"));
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_promote_waitlist_entry("ursula_le_guin@gmail.com")
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/waitlist");
+    let html_page = app.get_waitlist_html().await;
+    assert!(html_page.contains("ursula_le_guin@gmail.com has been sent a confirmation link."));
+    assert!(html_page.contains("Nobody is waiting."));
+    let saved = sqlx::query!(
+        "SELECT name, locale FROM subscriptions WHERE email = 'ursula_le_guin@gmail.com'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.name.as_deref(), Some("le guin"));
+    assert_eq!(saved.locale.as_deref(), Some("fr"));
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Bienvenue !");
+}
+
+#[tokio::test]
+async fn the_waitlist_is_masked_for_admins_without_pii_access() {
+    // Arrange
+    let app = spawn_full_app().await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.revoke_pii_access().await;
+    app.do_login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let html_page = app.get_waitlist_html().await;
+    let response = app
+        .post_promote_waitlist_entry("ursula_le_guin@gmail.com")
+        .await;
+
+    // Assert
+    assert!(html_page.contains("<td>u***@gmail.com</td>"));
+    assert!(!html_page.contains("ursula_le_guin@gmail.com"));
+    assert_is_redirect_to(&response, "/admin/waitlist");
+    let html_page = app.get_waitlist_html().await;
+    assert!(html_page.contains("u***@gmail.com has been sent a confirmation link."));
+    assert!(waitlisted_emails(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn every_way_in_joins_the_waitlist_once_the_list_is_full() {
+    // Arrange
+    let app = spawn_full_app().await;
+    let api_key = app.create_api_key().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let embedded = app
+        .post_embed_subscriptions(
+            EMBED_ORIGIN,
+            &serde_json::json!({"name": "le guin", "email": "ursula@example.com"}),
+        )
+        .await;
+    let created = app
+        .api_post(
+            "/subscribers",
+            &api_key,
+            &serde_json::json!({"name": "tenar", "email": "tenar@example.com"}),
+        )
+        .await;
+    app.import_confirmed_subscribers(&["ogion@example.com"])
+        .await;
+
+    // Assert
+    assert_eq!(embedded.status().as_u16(), 200);
+    let body: serde_json::Value = embedded.json().await.unwrap();
+    assert_eq!(body["status"], "waitlisted");
+    assert_eq!(created.status().as_u16(), 202);
+    assert_eq!(
+        waitlisted_emails(&app).await,
+        [
+            "ursula@example.com",
+            "tenar@example.com",
+            "ogion@example.com"
+        ]
+    );
+    let subscribers = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+}
+
+#[tokio::test]
+async fn concurrent_signups_cannot_all_take_the_last_spot() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscribers.max_subscribers = 1).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let (ursula, ged) = tokio::join!(
+        app.post_subscriptions("name=le%20guin&email=ursula%40example.com".into()),
+        app.post_subscriptions("name=ged&email=ged%40example.com".into())
+    );
+
+    // Assert
+    let mut statuses = [ursula.status().as_u16(), ged.status().as_u16()];
+    statuses.sort_unstable();
+    assert_eq!(statuses, [200, 202]);
+    assert_eq!(waitlisted_emails(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn sealed_signups_join_the_waitlist_under_a_pseudonym() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.subscribers.max_subscribers = 1;
+        c.pii_encryption.enabled = true;
+        c.pii_encryption.key = secrecy::Secret::new(base64::encode([1; 32]));
+        c.pii_encryption.blind_index_key = secrecy::Secret::new(base64::encode([2; 32]));
+    })
+    .await;
+    SubscriberFixture::confirmed()
+        .with_email("ged@example.com")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let first = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let again = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(again.status().as_u16(), 202);
+    let saved = sqlx::query!("SELECT email, name, name_encrypted FROM waitlist")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert!(saved[0].email.ends_with("@gmail.com.encrypted.invalid"));
+    assert!(saved[0].name.is_none());
+    assert!(saved[0].name_encrypted.is_some());
+}
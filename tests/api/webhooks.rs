@@ -0,0 +1,300 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+use uuid::Uuid;
+use zero2prod::i18n::Locale;
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::sending_quota::SendingQuota;
+use zero2prod::testing::{email_footer, FakeEmailSender, IssueFixture, SubscriberFixture};
+use zero2prod::webhooks::sign_webhook;
+
+fn bounce_event() -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "Email": "ursula_le_guin@gmail.com",
+        "Type": "HardBounce",
+    })
+}
+
+#[tokio::test]
+async fn signed_events_are_stored() {
+    let app = spawn_app().await;
+    let event_id = Uuid::new_v4().to_string();
+
+    let response = app.post_signed_webhook(&event_id, &bounce_event()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT event_id, record_type, recipient FROM email_provider_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.event_id, event_id);
+    assert_eq!(saved.record_type, "Bounce");
+    assert_eq!(saved.recipient.as_deref(), Some("ursula_le_guin@gmail.com"));
+}
+
+#[tokio::test]
+async fn replayed_events_are_rejected() {
+    let app = spawn_app().await;
+    let event_id = Uuid::new_v4().to_string();
+
+    let response = app.post_signed_webhook(&event_id, &bounce_event()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_signed_webhook(&event_id, &bounce_event()).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn events_with_an_invalid_signature_are_rejected() {
+    let app = spawn_app().await;
+    let event_id = Uuid::new_v4().to_string();
+    let body = serde_json::to_vec(&bounce_event()).unwrap();
+    let timestamp = chrono::Utc::now().timestamp().to_string();
+    let signature = sign_webhook(
+        &"not-the-secret".to_string().into(),
+        &event_id,
+        &timestamp,
+        &body,
+    );
+
+    let response = app
+        .post_webhook(&event_id, &timestamp, &signature, body)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT event_id FROM email_provider_events")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn stale_events_are_rejected() {
+    let app = spawn_app().await;
+    let event_id = Uuid::new_v4().to_string();
+    let body = serde_json::to_vec(&bounce_event()).unwrap();
+    let timestamp = (chrono::Utc::now().timestamp() - 3600).to_string();
+    let signature = sign_webhook(&app.webhook_signing_secret, &event_id, &timestamp, &body);
+
+    let response = app
+        .post_webhook(&event_id, &timestamp, &signature, body)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_event_id_swapped_under_a_valid_signature_is_rejected() {
+    let app = spawn_app().await;
+    let body = serde_json::to_vec(&bounce_event()).unwrap();
+    let timestamp = chrono::Utc::now().timestamp().to_string();
+    let signature = sign_webhook(
+        &app.webhook_signing_secret,
+        "an-event-id",
+        &timestamp,
+        &body,
+    );
+
+    let response = app
+        .post_webhook("another-event-id", &timestamp, &signature, body)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn events_without_signature_headers_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/webhooks/email_provider", &app.address))
+        .json(&bounce_event())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_rejected_signature_does_not_burn_the_event_id() {
+    let app = spawn_app().await;
+    let event_id = Uuid::new_v4().to_string();
+    let body = serde_json::to_vec(&bounce_event()).unwrap();
+    let timestamp = chrono::Utc::now().timestamp().to_string();
+
+    let response = app.post_webhook(&event_id, &timestamp, "00", body).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.post_signed_webhook(&event_id, &bounce_event()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn hard_bounces_unsubscribe_the_recipient() {
+    let app = spawn_app().await;
+    let subscriber = SubscriberFixture::confirmed()
+        .with_email("ursula_le_guin@gmail.com")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_signed_webhook(&Uuid::new_v4().to_string(), &bounce_event())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(
+        r#"SELECT status::text AS "status!", unsubscribe_reason FROM subscriptions WHERE id = $1"#,
+        subscriber.id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+    assert_eq!(saved.unsubscribe_reason.as_deref(), Some("bounced"));
+    let event = sqlx::query!("SELECT bounce_category FROM email_provider_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.bounce_category.as_deref(), Some("hard"));
+}
+
+#[tokio::test]
+async fn soft_bounces_are_retried_a_few_times() {
+    let app = spawn_app().await;
+    SubscriberFixture::confirmed()
+        .with_email("tenar@example.com")
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    sqlx::query!("DELETE FROM issue_delivery_queue")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let soft_bounce = serde_json::json!({
+        "RecordType": "Bounce",
+        "Email": "tenar@example.com",
+        "Type": "SoftBounce",
+        "Tag": issue_id.to_string(),
+    });
+
+    for retry in 1..=4 {
+        let response = app
+            .post_signed_webhook(&Uuid::new_v4().to_string(), &soft_bounce)
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+        let queued =
+            sqlx::query!(r#"SELECT deliver_after > now() AS "later!" FROM issue_delivery_queue"#)
+                .fetch_optional(&app.db_pool)
+                .await
+                .unwrap();
+        if retry <= 3 {
+            assert!(queued.unwrap().later);
+        } else {
+            assert!(queued.is_none());
+        }
+        sqlx::query!("DELETE FROM issue_delivery_queue")
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+    }
+}
+
+fn spam_complaint(recipient: &str, issue_id: Uuid) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "SpamComplaint",
+        "Email": recipient,
+        "Tag": issue_id.to_string(),
+    })
+}
+
+#[tokio::test]
+async fn spam_complaints_unsubscribe_the_recipient_and_are_recorded_against_the_issue() {
+    let app = spawn_app().await;
+    let subscriber = SubscriberFixture::confirmed()
+        .store(&app.db_pool)
+        .await
+        .unwrap();
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+
+    let response = app
+        .post_signed_webhook(
+            &Uuid::new_v4().to_string(),
+            &spam_complaint(&subscriber.email, issue_id),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(
+        r#"SELECT status::text AS "status!", unsubscribe_reason FROM subscriptions WHERE id = $1"#,
+        subscriber.id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+    assert_eq!(saved.unsubscribe_reason.as_deref(), Some("complained"));
+    let complaint = sqlx::query!("SELECT newsletter_issue_id FROM issue_complaints")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(complaint.newsletter_issue_id, issue_id);
+}
+
+#[tokio::test]
+async fn too_many_complaints_pause_the_delivery_until_an_admin_resumes_it() {
+    let app = spawn_app_with(|c| c.newsletters.complaint_rate_min_deliveries = 2).await;
+    for _ in 0..3 {
+        SubscriberFixture::confirmed()
+            .store(&app.db_pool)
+            .await
+            .unwrap();
+    }
+    let issue_id = IssueFixture::default().publish(&app.db_pool).await.unwrap();
+    let sender = FakeEmailSender::default();
+    let (quota, footer) = (SendingQuota::unlimited(), email_footer());
+    let english = Locale::parse("en").unwrap();
+    let execute_task = || {
+        try_execute_task(
+            &app.db_pool,
+            &sender,
+            &app.address,
+            &quota,
+            &footer,
+            &english,
+            None,
+        )
+    };
+    for _ in 0..2 {
+        execute_task().await.unwrap();
+    }
+    let complainer = sender.sent()[0].recipient.clone();
+
+    app.post_signed_webhook(
+        &Uuid::new_v4().to_string(),
+        &spam_complaint(&complainer, issue_id),
+    )
+    .await;
+
+    assert!(matches!(
+        execute_task().await.unwrap(),
+        ExecutionOutcome::EmptyQueue
+    ));
+    let event = sqlx::query!("SELECT event_type FROM events ORDER BY occurred_at DESC LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.event_type, "delivery_paused");
+
+    app.do_login().await;
+    let response = app.post_resume_deliveries(issue_id).await;
+
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    assert!(matches!(
+        execute_task().await.unwrap(),
+        ExecutionOutcome::TaskCompleted
+    ));
+}
@@ -0,0 +1,62 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::weekly_report::send_weekly_reports;
+
+/// The subject and the plain text of the emails waiting in the outbox, oldest first.
+async fn outbox_emails(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT subject, text_content FROM email_outbox ORDER BY enqueued_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.subject, r.text_content))
+        .collect()
+}
+
+#[tokio::test]
+async fn admins_who_chose_it_get_the_weekly_report_once_a_week() {
+    // Arrange
+    let app = spawn_app().await;
+    app.import_confirmed_subscribers(&["ursula@example.com", "ged@example.com"])
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.do_login().await;
+    app.post_notifications(&[("email", "admin@example.com"), ("event", "weekly_report")])
+        .await;
+    app.publish_issue().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act - running twice within the week
+    let reported = send_weekly_reports(&app.db_pool).await.unwrap();
+    let reported_again = send_weekly_reports(&app.db_pool).await.unwrap();
+
+    // Assert
+    assert_eq!(reported, 1);
+    assert_eq!(reported_again, 0);
+    let emails = outbox_emails(&app).await;
+    assert_eq!(emails.len(), 1);
+    let (subject, text) = &emails[0];
+    assert_eq!(subject, "Default: your weekly report");
+    assert!(text.contains("New subscribers: 2"));
+    assert!(text.contains("Issues sent: 1"));
+}
+
+#[tokio::test]
+async fn admins_who_did_not_choose_it_get_no_weekly_report() {
+    // Arrange
+    let app = spawn_app().await;
+    app.do_login().await;
+    app.post_notifications(&[("email", "admin@example.com"), ("event", "new_complaint")])
+        .await;
+
+    // Act
+    send_weekly_reports(&app.db_pool).await.unwrap();
+
+    // Assert
+    assert!(outbox_emails(&app).await.is_empty());
+}